@@ -4,15 +4,15 @@ use std::io;
 use std::process::ExitCode;
 
 use clap::Parser;
-use sqlarfs_cli::Cli;
+use sqlarfs_cli::{Cli, UserError};
 
 fn main() -> eyre::Result<ExitCode> {
     color_eyre::install()?;
 
     if let Err(err) = Cli::parse().dispatch(io::stdout()) {
-        if let Some(user_err) = err.downcast_ref::<sqlarfs::Error>() {
+        if let Some(user_err) = UserError::from_report(&err) {
             eprintln!("Error: {}", user_err);
-            return Ok(ExitCode::FAILURE);
+            return Ok(ExitCode::from(user_err.kind().exit_code()));
         }
 
         return Err(err);
@@ -1,12 +1,99 @@
 use std::path::PathBuf;
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// Read default settings from this config file.
+    ///
+    /// The default is `~/.config/sqlar/config.toml`. This can also be set with the SQLAR_CONFIG
+    /// environment variable.
+    #[arg(long, global = true, value_name = "PATH", conflicts_with = "no_config")]
+    pub config: Option<PathBuf>,
+
+    /// Ignore the config file and the SQLAR_* environment variables.
+    #[arg(long, global = true, default_value = "false")]
+    pub no_config: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProgressFormat {
+    /// Newline-delimited JSON objects printed to stderr.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionLevel {
+    /// Don't compress files.
+    None,
+
+    /// Compress files, optimizing for speed (default).
+    Fast,
+
+    /// Compress files, optimizing for size.
+    Best,
+}
+
+impl From<CompressionLevel> for sqlarfs::Compression {
+    fn from(level: CompressionLevel) -> Self {
+        match level {
+            CompressionLevel::None => sqlarfs::Compression::None,
+            CompressionLevel::Fast => sqlarfs::Compression::FAST,
+            CompressionLevel::Best => sqlarfs::Compression::BEST,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Newline {
+    /// Leave line endings as they are (default).
+    Preserve,
+
+    /// Convert CRLF line endings to LF.
+    Lf,
+
+    /// Convert LF line endings to CRLF.
+    Crlf,
+}
+
+impl From<Newline> for sqlarfs::NewlinePolicy {
+    fn from(newline: Newline) -> Self {
+        match newline {
+            Newline::Preserve => sqlarfs::NewlinePolicy::Preserve,
+            Newline::Lf => sqlarfs::NewlinePolicy::Lf,
+            Newline::Crlf => sqlarfs::NewlinePolicy::CrLf,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overwrite {
+    /// Fail when a file already exists (default).
+    Error,
+
+    /// Replace files that already exist.
+    Replace,
+
+    /// Skip files that already exist.
+    Skip,
+}
+
+impl From<Overwrite> for sqlarfs::OverwritePolicy {
+    fn from(overwrite: Overwrite) -> Self {
+        match overwrite {
+            Overwrite::Error => sqlarfs::OverwritePolicy::Error,
+            Overwrite::Replace => sqlarfs::OverwritePolicy::Replace,
+            Overwrite::Skip => sqlarfs::OverwritePolicy::Skip,
+        }
+    }
 }
 
 #[derive(Args, Debug, Clone)]
@@ -45,6 +132,54 @@ pub struct Create {
     /// Don't preserve file metadata.
     #[arg(long, default_value = "false", overrides_with = "_preserve")]
     pub no_preserve: bool,
+
+    /// Create a reproducible archive.
+    ///
+    /// Archiving the same files always produces a byte-for-byte identical archive. Files are added
+    /// in sorted order, every file gets the same mtime, and file permissions are normalized.
+    #[arg(long, default_value = "false")]
+    pub reproducible: bool,
+
+    /// The mtime to give every file in a reproducible archive, in seconds since the Unix epoch.
+    ///
+    /// The default is 0.
+    #[arg(long, value_name = "SECONDS", requires = "reproducible")]
+    pub epoch: Option<u64>,
+
+    /// Read the files to add from a manifest instead of the filesystem.
+    ///
+    /// Pass `-` to read the manifest from stdin. Each line of the manifest has the tab-separated
+    /// fields `PATH MODE MTIME SOURCE [SIZE]`, where MODE is an octal mode including the file type
+    /// bits (e.g. 100644), MTIME is in seconds since the Unix epoch, and SOURCE is the file to copy
+    /// the contents from, or the target of a symlink. If SOURCE is `-`, exactly SIZE bytes of file
+    /// contents follow the line inline. Missing parent directories are created automatically.
+    #[arg(long, value_name = "PATH", conflicts_with = "source")]
+    pub from_manifest: Option<PathBuf>,
+
+    /// Replace files that already exist in the archive.
+    ///
+    /// Directories that already exist are merged rather than replaced.
+    #[arg(long, default_value = "false", conflicts_with = "skip_existing")]
+    pub replace: bool,
+
+    /// Skip files that already exist in the archive.
+    #[arg(long, default_value = "false")]
+    pub skip_existing: bool,
+
+    /// How to compress the files being added.
+    #[arg(long, value_enum, value_name = "LEVEL")]
+    pub compression: Option<CompressionLevel>,
+
+    /// Leave out files inside the given directories that match this glob pattern.
+    ///
+    /// The pattern is matched against both the file name and the full path. This can be passed
+    /// multiple times.
+    #[arg(long, value_name = "PATTERN")]
+    pub exclude: Vec<String>,
+
+    /// Report progress as each file is copied, in the given format.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub progress: Option<ProgressFormat>,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -70,6 +205,23 @@ pub struct Extract {
     /// Don't extract the given directory recursively.
     #[arg(long, default_value = "false", overrides_with = "_recursive")]
     pub no_recursive: bool,
+
+    /// Report progress as each file is copied, in the given format.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub progress: Option<ProgressFormat>,
+
+    /// Check the extracted files against the archive once they're extracted.
+    ///
+    /// This re-reads each extracted regular file and compares its size and SHA-256 checksum to the
+    /// file in the archive, printing each file that doesn't match.
+    #[arg(long, default_value = "false", conflicts_with = "newline")]
+    pub verify: bool,
+
+    /// Convert the line endings in text files as they're extracted.
+    ///
+    /// Files that don't look like text are extracted unchanged.
+    #[arg(long, value_enum, value_name = "STYLE")]
+    pub newline: Option<Newline>,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -107,6 +259,31 @@ pub struct Archive {
     /// Don't preserve file metadata.
     #[arg(long, default_value = "false", overrides_with = "_preserve")]
     pub no_preserve: bool,
+
+    /// Replace files that already exist in the archive.
+    ///
+    /// Directories that already exist are merged rather than replaced.
+    #[arg(long, default_value = "false", conflicts_with = "skip_existing")]
+    pub replace: bool,
+
+    /// Skip files that already exist in the archive.
+    #[arg(long, default_value = "false")]
+    pub skip_existing: bool,
+
+    /// How to compress the files being added.
+    #[arg(long, value_enum, value_name = "LEVEL")]
+    pub compression: Option<CompressionLevel>,
+
+    /// Leave out files inside the given directories that match this glob pattern.
+    ///
+    /// The pattern is matched against both the file name and the full path. This can be passed
+    /// multiple times.
+    #[arg(long, value_name = "PATTERN")]
+    pub exclude: Vec<String>,
+
+    /// Report progress as each file is copied, in the given format.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub progress: Option<ProgressFormat>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
@@ -131,6 +308,16 @@ impl From<FileType> for sqlarfs::FileType {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ListSort {
+    /// Parents before their children.
+    #[default]
+    Depth,
+
+    /// By path, in natural order (`file2` before `file10`).
+    Name,
+}
+
 #[derive(Args, Debug, Clone)]
 pub struct List {
     /// Only return descendants of this directory.
@@ -151,8 +338,137 @@ pub struct List {
     /// Only return files of this type.
     #[arg(long, short, value_enum)]
     pub r#type: Option<FileType>,
+
+    /// How to sort the list of files.
+    #[arg(long, value_enum, default_value_t)]
+    pub sort: ListSort,
+
+    /// Print the start of each regular file after its path, separated by a tab.
+    ///
+    /// This prints up to this many bytes of each file, 64 by default. Invalid UTF-8 is replaced
+    /// and control characters are escaped, so each file stays on one line.
+    #[arg(
+        long,
+        value_name = "BYTES",
+        num_args = 0..=1,
+        default_missing_value = "64"
+    )]
+    pub preview: Option<u64>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct Tree {
+    /// The directory to print the tree of.
+    ///
+    /// By default, this prints the whole archive.
+    pub path: Option<PathBuf>,
+
+    /// The path of the SQLite archive.
+    #[arg(long, short)]
+    pub archive: PathBuf,
+
+    /// Print the size of each regular file in bytes.
+    #[arg(long, short, default_value = "false")]
+    pub size: bool,
+
+    /// Only descend this many levels deep.
+    #[arg(long, short, value_name = "LEVELS")]
+    pub depth: Option<usize>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct Grep {
+    /// The regular expression to search for.
+    pub pattern: String,
+
+    /// The file or directory in the archive to search.
+    ///
+    /// By default, this searches every file in the archive.
+    pub path: Option<PathBuf>,
+
+    /// The path of the SQLite archive.
+    #[arg(long, short)]
+    pub archive: PathBuf,
+
+    /// Match the pattern case-insensitively.
+    #[arg(long, short, default_value = "false")]
+    pub ignore_case: bool,
+
+    /// Only print the paths of the files that contain a match.
+    #[arg(long, short = 'l', default_value = "false")]
+    pub files_with_matches: bool,
+
+    /// Search files that look like binary files too.
+    #[arg(long, default_value = "false")]
+    pub text: bool,
 }
 
+#[derive(Args, Debug, Clone)]
+pub struct Sha256sum {
+    /// The files or directories in the archive to compute checksums for.
+    ///
+    /// Directories are searched recursively. By default, this computes checksums for every file in
+    /// the archive.
+    pub paths: Vec<PathBuf>,
+
+    /// The path of the SQLite archive.
+    #[arg(long, short)]
+    pub archive: PathBuf,
+
+    /// Verify the files in the archive against the checksums in this file.
+    ///
+    /// Pass `-` to read the checksums from stdin. This accepts the format that `sha256sum`
+    /// produces.
+    #[arg(long, short, value_name = "FILE", conflicts_with = "paths")]
+    pub check: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct Analyze {
+    /// The file or directory in the archive to analyze.
+    ///
+    /// By default, this analyzes every file in the archive.
+    pub path: Option<PathBuf>,
+
+    /// The path of the SQLite archive.
+    #[arg(long, short)]
+    pub archive: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum IndexFormat {
+    /// Comma-separated values, with a header row.
+    #[default]
+    Csv,
+
+    /// One JSON object per line.
+    #[value(name = "jsonl")]
+    JsonLines,
+}
+
+impl From<IndexFormat> for sqlarfs::IndexFormat {
+    fn from(format: IndexFormat) -> Self {
+        match format {
+            IndexFormat::Csv => sqlarfs::IndexFormat::Csv,
+            IndexFormat::JsonLines => sqlarfs::IndexFormat::JsonLines,
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct Index {
+    /// The path of the SQLite archive.
+    #[arg(long, short)]
+    pub archive: PathBuf,
+
+    /// The format to print the index in.
+    #[arg(long, short, value_enum, default_value_t)]
+    pub format: IndexFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct Config {}
+
 #[derive(Args, Debug, Clone)]
 pub struct Remove {
     /// The path of the file or directory to remove.
@@ -163,6 +479,59 @@ pub struct Remove {
     pub archive: PathBuf,
 }
 
+#[derive(Args, Debug, Clone)]
+pub struct Repair {
+    /// The path of the SQLite archive.
+    #[arg(long, short)]
+    pub archive: PathBuf,
+
+    /// Create directories that are missing but have files under them.
+    #[arg(long, default_value = "false")]
+    pub fix_dirs: bool,
+
+    /// Rename files whose paths have leading or trailing slashes, repeated slashes, or `.`
+    /// components.
+    #[arg(long, default_value = "false")]
+    pub fix_paths: bool,
+
+    /// Correct negative sizes and sizes that don't match the decompressed contents of files.
+    #[arg(long, default_value = "false")]
+    pub fix_sizes: bool,
+
+    /// Delete files whose parent directory is missing or isn't a directory.
+    ///
+    /// This happens after --fix-dirs, so only files whose parents couldn't be created are
+    /// deleted.
+    #[arg(long, default_value = "false")]
+    pub remove_orphans: bool,
+
+    /// Delete regular files whose contents can't be decompressed.
+    #[arg(long, default_value = "false")]
+    pub drop_corrupt: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct Bench {
+    /// The number of files to generate.
+    #[arg(long, default_value = "1000", value_name = "COUNT")]
+    pub files: u64,
+
+    /// The size of each generated file in bytes.
+    #[arg(long, default_value = "4096", value_name = "BYTES")]
+    pub size: usize,
+
+    /// How to compress the files when creating the archive.
+    #[arg(long, value_enum, value_name = "LEVEL", default_value = "fast")]
+    pub compression: CompressionLevel,
+
+    /// Generate the files and the archive in this directory.
+    ///
+    /// A new directory is created inside it and removed afterward. The default is the system
+    /// temporary directory.
+    #[arg(long, value_name = "PATH")]
+    pub dir: Option<PathBuf>,
+}
+
 #[derive(Subcommand, Debug, Clone)]
 pub enum Commands {
     /// Create a new SQLite archive from the given files.
@@ -183,7 +552,51 @@ pub enum Commands {
     #[command(visible_alias = "ls")]
     List(List),
 
+    /// Print the files in an archive as a tree.
+    Tree(Tree),
+
+    /// Search the contents of the files in an archive for a regular expression.
+    ///
+    /// This prints each matching line with its path and line number. Binary files are skipped
+    /// unless --text is passed.
+    Grep(Grep),
+
+    /// Print the SHA-256 checksums of the files in an archive.
+    ///
+    /// The output is in the same format as the `sha256sum` utility.
+    Sha256sum(Sha256sum),
+
+    /// Report how well the files in an archive compress, grouped by file extension.
+    ///
+    /// The ratio is the stored size as a percentage of the original size, so smaller is better.
+    Analyze(Analyze),
+
+    /// Print an index of every file in an archive and its metadata.
+    ///
+    /// This prints the path, file type, size, mode, mtime, and symlink target of each file. The
+    /// mode is an integer and the mtime is in seconds since the Unix epoch.
+    Index(Index),
+
+    /// Print the default settings read from the config file and environment.
+    ///
+    /// The output is in the format of the config file.
+    Config(Config),
+
     /// Remove a file or directory from an archive.
     #[command(visible_alias = "rm")]
     Remove(Remove),
+
+    /// Find and fix problems in an archive created by another tool.
+    ///
+    /// This prints how many of each kind of problem there were before and after the repair.
+    /// Without any flags, this only reports problems without changing the archive.
+    Repair(Repair),
+
+    /// Measure how fast archives can be created, listed, and extracted on this machine.
+    ///
+    /// This generates a tree of files, creates an archive from it, lists the archive, and
+    /// extracts it, printing the throughput of each step. Include the output when reporting a
+    /// performance problem.
+    #[command(hide = true)]
+    Bench(Bench),
 }
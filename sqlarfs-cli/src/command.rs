@@ -1,12 +1,105 @@
-use std::io::Write;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
-use sqlarfs::{ArchiveOptions, Connection, ExtractOptions, ListOptions};
+use clap::ValueEnum;
+use glob::Pattern;
+use regex::bytes::{Regex, RegexBuilder};
+use sqlarfs::{
+    ArchiveOptions, Connection, ExtractOptions, FileMetadata, ListEntry, ListOptions,
+    OverwritePolicy, RepairOptions,
+};
 
-use super::cli::{Archive, Cli, Commands, Create, Extract, List, Remove};
+use super::cli::{
+    Analyze, Archive, Bench, Cli, Commands, CompressionLevel, Config, Create, Extract, Grep, Index,
+    List, ListSort, Overwrite, ProgressFormat, Remove, Repair, Sha256sum, Tree,
+};
+use super::config::Settings;
+use super::manifest::{add_entry, Manifest};
+use super::progress::json_progress;
 
 const SQLAR_EXTENSION: &str = "sqlar";
 
+fn overwrite_policy(
+    replace: bool,
+    skip_existing: bool,
+    default: Option<Overwrite>,
+) -> OverwritePolicy {
+    if replace {
+        OverwritePolicy::Replace
+    } else if skip_existing {
+        OverwritePolicy::Skip
+    } else {
+        default.map_or(OverwritePolicy::Error, Into::into)
+    }
+}
+
+// The options for adding files to an archive that can come from either flags or the config file.
+fn add_options(
+    compression: Option<CompressionLevel>,
+    exclude: &[String],
+    settings: &Settings,
+) -> sqlarfs::Result<ArchiveOptions> {
+    let mut opts = ArchiveOptions::new();
+
+    if let Some(level) = compression.or(settings.compression) {
+        opts = opts.compression(level.into());
+    }
+
+    let patterns = settings
+        .exclude
+        .iter()
+        .chain(exclude)
+        .map(|pattern| {
+            Pattern::new(pattern).map_err(|err| sqlarfs::Error::InvalidArgs {
+                reason: format!("Invalid exclude pattern: {pattern}: {err}"),
+            })
+        })
+        .collect::<sqlarfs::Result<Vec<_>>>()?;
+
+    if !patterns.is_empty() {
+        opts = opts.exclude(move |path| {
+            let file_name = path.file_name().map(|name| name.to_string_lossy());
+
+            patterns.iter().any(|pattern| {
+                pattern.matches_path(path)
+                    || file_name
+                        .as_deref()
+                        .is_some_and(|name| pattern.matches(name))
+            })
+        });
+    }
+
+    Ok(opts)
+}
+
+fn archive_options_for(
+    opts: ArchiveOptions,
+    source: &Path,
+    progress: Option<ProgressFormat>,
+) -> ArchiveOptions {
+    match progress {
+        Some(ProgressFormat::Json) => opts.on_progress(json_progress(source)),
+        None => opts,
+    }
+}
+
+fn extract_options_for(
+    opts: ExtractOptions,
+    source: &Path,
+    progress: Option<ProgressFormat>,
+) -> ExtractOptions {
+    match progress {
+        Some(ProgressFormat::Json) => opts.on_progress(json_progress(source)),
+        None => opts,
+    }
+}
+
 fn file_name(path: &Path) -> Option<&Path> {
     path.file_name()
         .map(Path::new)
@@ -14,8 +107,14 @@ fn file_name(path: &Path) -> Option<&Path> {
 }
 
 impl Create {
-    pub fn run(&self) -> eyre::Result<()> {
-        let archive_filename = if self.source.is_empty() {
+    pub fn run(&self, settings: &Settings) -> eyre::Result<()> {
+        let archive_filename = if self.from_manifest.is_some() {
+            self.archive.clone().ok_or(sqlarfs::Error::InvalidArgs {
+                reason: String::from(
+                    "When reading files from a manifest, the archive path must be specified.",
+                ),
+            })?
+        } else if self.source.is_empty() {
             self.archive.clone().ok_or(sqlarfs::Error::InvalidArgs {
                 reason: String::from("When no files are being added to the archive, the archive path must be specified."),
             })?
@@ -38,14 +137,29 @@ impl Create {
             })?
         };
 
-        let mut conn = Connection::create_new(archive_filename)?;
+        let overwrite = overwrite_policy(self.replace, self.skip_existing, settings.overwrite);
+        let progress = self.progress.or(settings.progress);
+        let compression = self
+            .compression
+            .or(settings.compression)
+            .map(sqlarfs::Compression::from);
 
-        let opts = ArchiveOptions::new()
+        let opts = add_options(self.compression, &self.exclude, settings)?
             .follow_symlinks(self.follow)
             .recursive(!self.no_recursive)
             .preserve_metadata(!self.no_preserve)
+            .deterministic(self.reproducible)
+            .deterministic_mtime(UNIX_EPOCH + Duration::from_secs(self.epoch.unwrap_or(0)))
+            .overwrite(overwrite)
             .children(false);
 
+        // When an overwrite policy is given, add to the archive if it already exists.
+        let mut conn = if overwrite == OverwritePolicy::Error {
+            Connection::create_new(archive_filename)?
+        } else {
+            Connection::create(archive_filename)?
+        };
+
         conn.exec(|archive| {
             for source_path in &self.source {
                 let source_filename =
@@ -53,7 +167,23 @@ impl Create {
                         reason: String::from("The source path must have a filename."),
                     })?;
 
-                archive.archive_with(source_path, source_filename, &opts)?;
+                archive.archive_with(
+                    source_path,
+                    source_filename,
+                    &archive_options_for(opts.clone(), source_path, progress),
+                )?;
+            }
+
+            if let Some(manifest_path) = &self.from_manifest {
+                let reader: Box<dyn BufRead> = if manifest_path == Path::new("-") {
+                    Box::new(io::stdin().lock())
+                } else {
+                    Box::new(BufReader::new(fs::File::open(manifest_path)?))
+                };
+
+                for entry in Manifest::new(reader) {
+                    add_entry(archive, &entry?, overwrite, compression)?;
+                }
             }
 
             sqlarfs::Result::Ok(())
@@ -63,18 +193,79 @@ impl Create {
     }
 }
 
+// Compare the regular files extracted from `source` in the archive to `dest` in the filesystem
+// against the archive, printing each file that doesn't match.
+//
+// This returns the number of files that didn't match.
+fn verify_extracted(
+    archive: &mut sqlarfs::Archive,
+    source: &Path,
+    dest: &Path,
+    list_opts: Option<ListOptions>,
+    stdout: &mut impl Write,
+) -> eyre::Result<usize> {
+    let paths = if source != Path::new("") && archive.open(source)?.metadata()?.is_file() {
+        vec![source.to_owned()]
+    } else if let Some(opts) = list_opts {
+        archive
+            .list_with(&opts.file_type(sqlarfs::FileType::File).by_name_natural())?
+            .map(|entry| entry.map(ListEntry::into_path))
+            .collect::<sqlarfs::Result<Vec<_>>>()?
+    } else {
+        Vec::new()
+    };
+
+    let mut failed = 0;
+
+    for path in paths {
+        let dest_path = match path.strip_prefix(source) {
+            Ok(relative) if relative != Path::new("") => dest.join(relative),
+            _ => dest.to_owned(),
+        };
+
+        let mut file = archive.open(&path)?;
+
+        let expected_size = match file.metadata()? {
+            FileMetadata::File { size, .. } => size,
+            _ => continue,
+        };
+
+        let actual = fs::File::open(&dest_path).and_then(|dest_file| {
+            let actual_size = dest_file.metadata()?.len();
+            Ok((actual_size, sqlarfs::Digest::from_reader(dest_file)?))
+        });
+
+        let reason = match actual {
+            Ok((actual_size, _)) if actual_size != expected_size => "size does not match",
+            Ok((_, actual_digest)) if actual_digest != file.digest()? => "checksum does not match",
+            Ok(_) => continue,
+            Err(_) => "open or read",
+        };
+
+        writeln!(stdout, "{}: FAILED {reason}", dest_path.to_string_lossy())?;
+        failed += 1;
+    }
+
+    Ok(failed)
+}
+
 impl Extract {
-    pub fn run(&self) -> eyre::Result<()> {
-        let mut conn = Connection::open(&self.archive)?;
+    pub fn run(&self, settings: &Settings, mut stdout: impl Write) -> eyre::Result<()> {
+        let mut conn = Connection::open_readonly(&self.archive)?;
+        let progress = self.progress.or(settings.progress);
+        let newline_policy = self.newline.map(Into::into).unwrap_or_default();
 
         conn.exec(|archive| {
             if self.source.is_empty() {
+                let opts = ExtractOptions::new()
+                    .children(true)
+                    .recursive(!self.no_recursive)
+                    .newline_policy(newline_policy);
+
                 archive.extract_with(
                     "",
                     &self.dest,
-                    &ExtractOptions::new()
-                        .children(true)
-                        .recursive(!self.no_recursive),
+                    &extract_options_for(opts, Path::new(""), progress),
                 )?;
             }
 
@@ -86,30 +277,76 @@ impl Extract {
                     ),
                 })?;
 
+                let opts = ExtractOptions::new()
+                    .children(false)
+                    .recursive(!self.no_recursive)
+                    .newline_policy(newline_policy);
+
                 archive.extract_with(
                     path,
                     self.dest.join(file_name),
-                    &ExtractOptions::new()
-                        .children(false)
-                        .recursive(!self.no_recursive),
+                    &extract_options_for(opts, path, progress),
                 )?;
             }
 
             sqlarfs::Result::Ok(())
         })?;
 
+        if self.verify {
+            self.verify(&mut conn, &mut stdout)?;
+        }
+
+        Ok(())
+    }
+
+    fn verify(&self, conn: &mut Connection, stdout: &mut impl Write) -> eyre::Result<()> {
+        let failed = conn.exec(|archive| {
+            let mut failed = 0;
+
+            if self.source.is_empty() {
+                let opts = if self.no_recursive {
+                    ListOptions::new().children_of("")
+                } else {
+                    ListOptions::new()
+                };
+
+                failed += verify_extracted(archive, Path::new(""), &self.dest, Some(opts), stdout)?;
+            }
+
+            for path in &self.source {
+                // We already checked that every source path has a file name when extracting.
+                let dest = self.dest.join(path.file_name().unwrap_or_default());
+
+                let opts = (!self.no_recursive).then(|| ListOptions::new().descendants_of(path));
+
+                failed += verify_extracted(archive, path, &dest, opts, stdout)?;
+            }
+
+            eyre::Result::<_>::Ok(failed)
+        })?;
+
+        if failed > 0 {
+            return Err(ChecksumMismatch { failed }.into());
+        }
+
         Ok(())
     }
 }
 
 impl Archive {
-    pub fn run(&self) -> eyre::Result<()> {
+    pub fn run(&self, settings: &Settings) -> eyre::Result<()> {
         let mut conn = Connection::open(&self.archive)?;
+        let progress = self.progress.or(settings.progress);
 
-        let opts = ArchiveOptions::new()
+        let opts = add_options(self.compression, &self.exclude, settings)?
             .follow_symlinks(self.follow)
             .recursive(!self.no_recursive)
             .preserve_metadata(!self.no_preserve)
+            .overwrite(overwrite_policy(
+                self.replace,
+                self.skip_existing,
+                settings.overwrite,
+            ))
             .children(false);
 
         conn.exec(|archive| {
@@ -127,7 +364,11 @@ impl Archive {
                 }
             }
 
-            archive.archive_with(&self.source, dest_path, &opts)?;
+            archive.archive_with(
+                &self.source,
+                dest_path,
+                &archive_options_for(opts.clone(), &self.source, progress),
+            )?;
 
             sqlarfs::Result::Ok(())
         })?;
@@ -138,10 +379,12 @@ impl Archive {
 
 impl List {
     pub fn run(&self, mut stdout: impl Write) -> eyre::Result<()> {
-        let mut conn = Connection::open(&self.archive)?;
+        let mut conn = Connection::open_readonly(&self.archive)?;
 
-        // We always sort by depth.
-        let mut opts = ListOptions::new().by_depth();
+        let mut opts = match self.sort {
+            ListSort::Depth => ListOptions::new().by_depth(),
+            ListSort::Name => ListOptions::new().by_name_natural(),
+        };
 
         if self.children {
             opts = opts.children_of(self.parent.as_ref().unwrap_or(&PathBuf::from("")));
@@ -157,7 +400,219 @@ impl List {
 
         conn.exec(|archive| {
             for entry in archive.list_with(&opts)? {
-                writeln!(stdout, "{}", entry?.path().to_string_lossy())?;
+                let entry = entry?;
+                let path = entry.path().to_string_lossy();
+
+                match self.preview {
+                    Some(len) if entry.metadata().is_file() => {
+                        let head = archive.open(entry.path())?.head(len)?;
+                        let preview = String::from_utf8_lossy(&head);
+
+                        writeln!(stdout, "{path}\t{}", preview.escape_debug())?;
+                    }
+                    _ => writeln!(stdout, "{path}")?,
+                }
+            }
+
+            sqlarfs::Result::Ok(())
+        })?;
+
+        Ok(())
+    }
+}
+
+// Write the descendants of `dir` as the branches of a tree, with each line starting with `prefix`.
+fn write_tree(
+    stdout: &mut impl Write,
+    children: &HashMap<PathBuf, Vec<ListEntry>>,
+    dir: &Path,
+    prefix: &str,
+    show_size: bool,
+) -> io::Result<()> {
+    let entries = match children.get(dir) {
+        Some(entries) => entries,
+        None => return Ok(()),
+    };
+
+    for (i, entry) in entries.iter().enumerate() {
+        let (branch, indent) = if i == entries.len() - 1 {
+            ("`-- ", "    ")
+        } else {
+            ("|-- ", "|   ")
+        };
+
+        let name = entry
+            .path()
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+
+        match entry.metadata() {
+            FileMetadata::File { size, .. } if show_size => {
+                writeln!(stdout, "{prefix}{branch}[{size}] {name}")?
+            }
+            FileMetadata::Symlink { target, .. } => writeln!(
+                stdout,
+                "{prefix}{branch}{name} -> {}",
+                target.to_string_lossy()
+            )?,
+            _ => writeln!(stdout, "{prefix}{branch}{name}")?,
+        }
+
+        write_tree(
+            stdout,
+            children,
+            entry.path(),
+            &format!("{prefix}{indent}"),
+            show_size,
+        )?;
+    }
+
+    Ok(())
+}
+
+impl Tree {
+    pub fn run(&self, mut stdout: impl Write) -> eyre::Result<()> {
+        let mut conn = Connection::open_readonly(&self.archive)?;
+
+        let root = self.path.clone().unwrap_or_default();
+        let opts = ListOptions::new().descendants_of(&root).by_depth();
+
+        // Group the files by their parent directory.
+        let mut children: HashMap<PathBuf, Vec<ListEntry>> = HashMap::new();
+
+        conn.exec(|archive| {
+            if root != Path::new("") {
+                // Make sure the directory actually exists.
+                archive.open(&root)?.metadata()?;
+            }
+
+            for entry in archive.list_with(&opts)? {
+                let entry = entry?;
+
+                let depth = entry
+                    .path()
+                    .strip_prefix(&root)
+                    .map(|path| path.components().count())
+                    .unwrap_or_default();
+
+                // The list is sorted by depth, so every file after this one is too deep as well.
+                if self.depth.is_some_and(|max_depth| depth > max_depth) {
+                    break;
+                }
+
+                if let Some(parent) = entry.path().parent() {
+                    children.entry(parent.to_owned()).or_default().push(entry);
+                }
+            }
+
+            sqlarfs::Result::Ok(())
+        })?;
+
+        for entries in children.values_mut() {
+            entries.sort_by(|left, right| left.path().cmp(right.path()));
+        }
+
+        if root == Path::new("") {
+            writeln!(stdout, ".")?;
+        } else {
+            writeln!(stdout, "{}", root.to_string_lossy())?;
+        }
+
+        write_tree(&mut stdout, &children, &root, "", self.size)?;
+
+        Ok(())
+    }
+}
+
+// Print the lines in a file that match `regex`.
+fn grep_file(
+    stdout: &mut impl Write,
+    reader: impl Read,
+    path: &Path,
+    regex: &Regex,
+    files_with_matches: bool,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+
+    let display_path = path.to_string_lossy();
+    let mut line = Vec::new();
+    let mut line_num = 0;
+
+    loop {
+        line.clear();
+
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+
+        line_num += 1;
+
+        let contents = line
+            .strip_suffix(b"\n")
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .unwrap_or(&line);
+
+        if !regex.is_match(contents) {
+            continue;
+        }
+
+        if files_with_matches {
+            writeln!(stdout, "{display_path}")?;
+            return Ok(());
+        }
+
+        writeln!(
+            stdout,
+            "{display_path}:{line_num}:{}",
+            String::from_utf8_lossy(contents)
+        )?;
+    }
+}
+
+impl Grep {
+    pub fn run(&self, mut stdout: impl Write) -> eyre::Result<()> {
+        let mut conn = Connection::open_readonly(&self.archive)?;
+
+        let regex = RegexBuilder::new(&self.pattern)
+            .case_insensitive(self.ignore_case)
+            .build()
+            .map_err(|err| sqlarfs::Error::InvalidArgs {
+                reason: format!("Invalid regular expression: {err}"),
+            })?;
+
+        let root = self.path.clone().unwrap_or_default();
+
+        conn.exec(|archive| {
+            let paths = if root != Path::new("") && archive.open(&root)?.metadata()?.is_file() {
+                vec![root.clone()]
+            } else {
+                let opts = ListOptions::new()
+                    .descendants_of(&root)
+                    .file_type(sqlarfs::FileType::File)
+                    .by_name_natural();
+
+                archive
+                    .list_with(&opts)?
+                    .map(|entry| entry.map(ListEntry::into_path))
+                    .collect::<sqlarfs::Result<Vec<_>>>()?
+            };
+
+            for path in paths {
+                let mut file = archive.open(&path)?;
+
+                // Like `grep`, we skip binary files by default.
+                if !self.text && !file.is_probably_text()? {
+                    continue;
+                }
+
+                grep_file(
+                    &mut stdout,
+                    file.reader()?,
+                    &path,
+                    &regex,
+                    self.files_with_matches,
+                )?;
             }
 
             sqlarfs::Result::Ok(())
@@ -167,6 +622,138 @@ impl List {
     }
 }
 
+/// The error returned when files don't match their checksums.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    /// The number of files that didn't match.
+    pub failed: usize,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.failed == 1 {
+            write!(f, "1 file did not match its checksum.")
+        } else {
+            write!(f, "{} files did not match their checksums.", self.failed)
+        }
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+// Parse a line of the output of `sha256sum`, returning the checksum and the path.
+//
+// The path is separated from the checksum by a space and then either a space (text mode) or an
+// asterisk (binary mode), which are the same thing for our purposes.
+fn parse_checksum_line(line: &str) -> Option<(&str, &str)> {
+    let (checksum, rest) = line.split_once(' ')?;
+    let path = rest.strip_prefix(' ').or_else(|| rest.strip_prefix('*'))?;
+
+    if checksum.len() != 64 || !checksum.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    Some((checksum, path))
+}
+
+impl Sha256sum {
+    fn print(&self, archive: &mut sqlarfs::Archive, stdout: &mut impl Write) -> eyre::Result<()> {
+        let roots = if self.paths.is_empty() {
+            vec![PathBuf::new()]
+        } else {
+            self.paths.clone()
+        };
+
+        for root in roots {
+            let paths = if root != Path::new("") && archive.open(&root)?.metadata()?.is_file() {
+                vec![root]
+            } else {
+                let opts = ListOptions::new()
+                    .descendants_of(&root)
+                    .file_type(sqlarfs::FileType::File)
+                    .by_name_natural();
+
+                archive
+                    .list_with(&opts)?
+                    .map(|entry| entry.map(ListEntry::into_path))
+                    .collect::<sqlarfs::Result<Vec<_>>>()?
+            };
+
+            for path in paths {
+                let digest = archive.open(&path)?.digest()?;
+                writeln!(stdout, "{digest}  {}", path.to_string_lossy())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check(
+        &self,
+        archive: &mut sqlarfs::Archive,
+        checksum_path: &Path,
+        stdout: &mut impl Write,
+    ) -> eyre::Result<()> {
+        let reader: Box<dyn BufRead> = if checksum_path == Path::new("-") {
+            Box::new(io::stdin().lock())
+        } else {
+            Box::new(BufReader::new(fs::File::open(checksum_path)?))
+        };
+
+        let mut failed = 0;
+
+        for (line_num, line) in reader.lines().enumerate() {
+            let line = line?;
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let (expected, path) =
+                parse_checksum_line(&line).ok_or_else(|| sqlarfs::Error::InvalidArgs {
+                    reason: format!(
+                        "Line {} of the checksum file is not a valid checksum line.",
+                        line_num + 1
+                    ),
+                })?;
+
+            let actual = match archive.open(path)?.digest() {
+                Ok(digest) => digest,
+                Err(
+                    sqlarfs::Error::FileNotFound { .. } | sqlarfs::Error::NotARegularFile { .. },
+                ) => {
+                    writeln!(stdout, "{path}: FAILED open or read")?;
+                    failed += 1;
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            if actual.to_string().eq_ignore_ascii_case(expected) {
+                writeln!(stdout, "{path}: OK")?;
+            } else {
+                writeln!(stdout, "{path}: FAILED")?;
+                failed += 1;
+            }
+        }
+
+        if failed > 0 {
+            return Err(ChecksumMismatch { failed }.into());
+        }
+
+        Ok(())
+    }
+
+    pub fn run(&self, mut stdout: impl Write) -> eyre::Result<()> {
+        let mut conn = Connection::open_readonly(&self.archive)?;
+
+        conn.exec(|archive| match &self.check {
+            Some(checksum_path) => self.check(archive, checksum_path, &mut stdout),
+            None => self.print(archive, &mut stdout),
+        })
+    }
+}
+
 impl Remove {
     pub fn run(&self) -> eyre::Result<()> {
         let mut conn = Connection::open(&self.archive)?;
@@ -177,14 +764,297 @@ impl Remove {
     }
 }
 
+impl Repair {
+    pub fn run(&self, mut stdout: impl Write) -> eyre::Result<()> {
+        let mut conn = Connection::open(&self.archive)?;
+
+        let opts = RepairOptions::new()
+            .fix_dirs(self.fix_dirs)
+            .fix_paths(self.fix_paths)
+            .fix_sizes(self.fix_sizes)
+            .remove_orphans(self.remove_orphans)
+            .drop_corrupt(self.drop_corrupt);
+
+        let report = conn.exec(|archive| archive.repair(&opts))?;
+        let (before, after) = (report.before(), report.after());
+
+        writeln!(stdout, "{:<20} {:>8} {:>8}", "PROBLEM", "BEFORE", "AFTER")?;
+
+        for (name, before, after) in [
+            (
+                "missing directories",
+                before.missing_dirs(),
+                after.missing_dirs(),
+            ),
+            (
+                "non-dir parents",
+                before.non_dir_parents(),
+                after.non_dir_parents(),
+            ),
+            ("bad paths", before.bad_paths(), after.bad_paths()),
+            (
+                "duplicate paths",
+                before.duplicate_paths(),
+                after.duplicate_paths(),
+            ),
+            (
+                "negative sizes",
+                before.negative_sizes(),
+                after.negative_sizes(),
+            ),
+            (
+                "corrupt files",
+                before.corrupt_files(),
+                after.corrupt_files(),
+            ),
+        ] {
+            writeln!(
+                stdout,
+                "{:<20} {:>8} {:>8}",
+                name,
+                before.len(),
+                after.len()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Analyze {
+    pub fn run(&self, mut stdout: impl Write) -> eyre::Result<()> {
+        let mut conn = Connection::open_readonly(&self.archive)?;
+
+        let root = self.path.clone().unwrap_or_default();
+        let report = conn.exec(|archive| archive.compression_report(&root))?;
+
+        writeln!(
+            stdout,
+            "{:<12} {:>8} {:>14} {:>14} {:>14} {:>7}",
+            "EXTENSION", "FILES", "ORIGINAL", "STORED", "SAVED", "RATIO"
+        )?;
+
+        let rows = report
+            .extensions()
+            .iter()
+            .map(|entry| (entry.extension().unwrap_or("(none)"), entry.stats()))
+            .chain([("TOTAL", report.total())]);
+
+        for (name, stats) in rows {
+            writeln!(
+                stdout,
+                "{:<12} {:>8} {:>14} {:>14} {:>14} {:>6.1}%",
+                name,
+                stats.files(),
+                stats.original_size(),
+                stats.stored_size(),
+                stats.savings(),
+                stats.ratio() * 100.0,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Index {
+    pub fn run(&self, stdout: impl Write) -> eyre::Result<()> {
+        let mut conn = Connection::open_readonly(&self.archive)?;
+
+        conn.exec(|archive| archive.export_index(stdout, self.format.into()))?;
+
+        Ok(())
+    }
+}
+
+impl Config {
+    pub fn run(
+        &self,
+        settings: &Settings,
+        config_path: Option<&Path>,
+        mut stdout: impl Write,
+    ) -> eyre::Result<()> {
+        match config_path {
+            Some(path) => writeln!(stdout, "# Config file: {}", path.to_string_lossy())?,
+            None => writeln!(stdout, "# No config file was found.")?,
+        }
+
+        // Fill in the defaults so it's clear what will actually happen.
+        let effective = Settings {
+            compression: Some(settings.compression.unwrap_or(CompressionLevel::Fast)),
+            overwrite: Some(settings.overwrite.unwrap_or(Overwrite::Error)),
+            ..settings.clone()
+        };
+
+        write!(stdout, "{}", toml::to_string(&effective)?)?;
+
+        Ok(())
+    }
+}
+
+// How many of the files generated by `sqlar bench` go in each directory.
+const BENCH_FILES_PER_DIR: u64 = 100;
+
+// The contents of a file generated by `sqlar bench`. This is text that's different for each file,
+// so it compresses about as well as real text files do.
+fn bench_contents(index: u64, size: usize) -> Vec<u8> {
+    let mut contents = Vec::with_capacity(size);
+    let mut line = 0;
+
+    while contents.len() < size {
+        contents.extend_from_slice(
+            format!("file {index} line {line}: the quick brown fox jumps over the lazy dog\n")
+                .as_bytes(),
+        );
+        line += 1;
+    }
+
+    contents.truncate(size);
+    contents
+}
+
+fn write_bench_row(
+    stdout: &mut impl Write,
+    name: &str,
+    files: u64,
+    bytes: Option<u64>,
+    elapsed: Duration,
+) -> io::Result<()> {
+    let secs = elapsed.as_secs_f64();
+
+    let (bytes, mib_per_sec) = match bytes {
+        Some(bytes) => (
+            bytes.to_string(),
+            format!("{:.1}", bytes as f64 / (1024.0 * 1024.0) / secs),
+        ),
+        None => (String::from("-"), String::from("-")),
+    };
+
+    writeln!(
+        stdout,
+        "{:<10} {:>8} {:>12} {:>10.3} {:>10.1} {:>8}",
+        name,
+        files,
+        bytes,
+        secs,
+        files as f64 / secs,
+        mib_per_sec,
+    )
+}
+
+impl Bench {
+    pub fn run(&self, mut stdout: impl Write) -> eyre::Result<()> {
+        let parent = self.dir.clone().unwrap_or_else(std::env::temp_dir);
+        let root = parent.join(format!("sqlar-bench-{}", std::process::id()));
+
+        fs::create_dir(&root)?;
+
+        // Clean up even if the benchmark fails.
+        let result = self.run_in(&root, &mut stdout);
+        fs::remove_dir_all(&root)?;
+
+        result
+    }
+
+    fn run_in(&self, root: &Path, stdout: &mut impl Write) -> eyre::Result<()> {
+        let tree = root.join("tree");
+
+        for index in 0..self.files {
+            let dir = tree.join(format!("dir-{}", index / BENCH_FILES_PER_DIR));
+            fs::create_dir_all(&dir)?;
+            fs::write(
+                dir.join(format!("file-{index}.txt")),
+                bench_contents(index, self.size),
+            )?;
+        }
+
+        let bytes = self.files.saturating_mul(self.size as u64);
+        let archive_path = root.join("bench.sqlar");
+        let opts = ArchiveOptions::new().compression(self.compression.into());
+
+        let start = Instant::now();
+        let mut conn = Connection::create_new(&archive_path)?;
+        conn.exec(|archive| archive.archive_with(&tree, "tree", &opts))?;
+        let create_elapsed = start.elapsed();
+
+        let start = Instant::now();
+        let entries = conn.exec(|archive| {
+            let mut entries = 0;
+
+            for entry in archive.list()? {
+                entry?;
+                entries += 1;
+            }
+
+            sqlarfs::Result::Ok(entries)
+        })?;
+        let list_elapsed = start.elapsed();
+
+        let start = Instant::now();
+        conn.exec(|archive| {
+            archive.extract_with("tree", root.join("extracted"), &ExtractOptions::new())
+        })?;
+        let extract_elapsed = start.elapsed();
+
+        let compression = self
+            .compression
+            .to_possible_value()
+            .expect("Every compression level has a name. This is a bug.");
+
+        writeln!(
+            stdout,
+            "# sqlar {}: {} files of {} bytes, compression {}",
+            env!("CARGO_PKG_VERSION"),
+            self.files,
+            self.size,
+            compression.get_name(),
+        )?;
+        writeln!(
+            stdout,
+            "{:<10} {:>8} {:>12} {:>10} {:>10} {:>8}",
+            "OPERATION", "FILES", "BYTES", "SECONDS", "FILES/S", "MIB/S"
+        )?;
+        write_bench_row(stdout, "create", self.files, Some(bytes), create_elapsed)?;
+        write_bench_row(stdout, "list", entries, None, list_elapsed)?;
+        write_bench_row(stdout, "extract", self.files, Some(bytes), extract_elapsed)?;
+
+        Ok(())
+    }
+}
+
 impl Cli {
     pub fn dispatch(&self, stdout: impl Write) -> eyre::Result<()> {
+        self.dispatch_with_env(stdout, |name| env::var_os(name))
+    }
+
+    /// Run the command like [`Cli::dispatch`], but with a custom environment.
+    ///
+    /// `var` returns the value of the environment variable with the given name, if it's set. See
+    /// [`Settings::load_with_env`].
+    pub fn dispatch_with_env<F>(&self, stdout: impl Write, var: F) -> eyre::Result<()>
+    where
+        F: Fn(&str) -> Option<OsString>,
+    {
+        let (settings, config_path) = if self.no_config {
+            (Settings::default(), None)
+        } else {
+            Settings::load_with_env(self.config.as_deref(), var)?
+        };
+
         match &self.command {
-            Commands::Create(create) => create.run(),
-            Commands::Extract(extract) => extract.run(),
-            Commands::Archive(archive) => archive.run(),
+            Commands::Create(create) => create.run(&settings),
+            Commands::Extract(extract) => extract.run(&settings, stdout),
+            Commands::Archive(archive) => archive.run(&settings),
             Commands::List(list) => list.run(stdout),
+            Commands::Tree(tree) => tree.run(stdout),
+            Commands::Grep(grep) => grep.run(stdout),
+            Commands::Sha256sum(sha256sum) => sha256sum.run(stdout),
+            Commands::Analyze(analyze) => analyze.run(stdout),
+            Commands::Index(index) => index.run(stdout),
+            Commands::Config(config) => config.run(&settings, config_path.as_deref(), stdout),
             Commands::Remove(remove) => remove.run(),
+            Commands::Repair(repair) => repair.run(stdout),
+            Commands::Bench(bench) => bench.run(stdout),
         }
     }
 }
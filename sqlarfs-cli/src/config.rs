@@ -0,0 +1,257 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::cli::{CompressionLevel, Overwrite, ProgressFormat};
+
+// The environment variable that overrides the path of the config file.
+const CONFIG_PATH_ENV: &str = "SQLAR_CONFIG";
+
+const COMPRESSION_ENV: &str = "SQLAR_COMPRESSION";
+const EXCLUDE_ENV: &str = "SQLAR_EXCLUDE";
+const OVERWRITE_ENV: &str = "SQLAR_OVERWRITE";
+const PROGRESS_ENV: &str = "SQLAR_PROGRESS";
+
+fn invalid_config(reason: String) -> sqlarfs::Error {
+    sqlarfs::Error::InvalidArgs { reason }
+}
+
+fn parse_env_value<T: ValueEnum>(name: &str, value: &str) -> sqlarfs::Result<T> {
+    T::from_str(value, true).map_err(|_| {
+        invalid_config(format!(
+            "The environment variable {name} has an invalid value: {value}"
+        ))
+    })
+}
+
+/// Default settings for the CLI.
+///
+/// These are read from a TOML config file and then from `SQLAR_*` environment variables, which
+/// take precedence over the config file. Flags passed on the command line take precedence over
+/// both.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// The compression method to use when adding files to an archive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<CompressionLevel>,
+
+    /// Glob patterns for files to leave out when adding files to an archive.
+    pub exclude: Vec<String>,
+
+    /// What to do when adding a file that already exists in the archive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overwrite: Option<Overwrite>,
+
+    /// The format to report progress in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<ProgressFormat>,
+}
+
+impl Settings {
+    /// The path of the config file when one isn't specified.
+    ///
+    /// This is `$XDG_CONFIG_HOME/sqlar/config.toml` if `XDG_CONFIG_HOME` is set, and
+    /// `~/.config/sqlar/config.toml` otherwise. On Windows, it's `%APPDATA%\sqlar\config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        Self::default_path_with_env(&|name| env::var_os(name))
+    }
+
+    fn default_path_with_env(var: &dyn Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+        let config_dir = match var("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None if cfg!(windows) => PathBuf::from(var("APPDATA")?),
+            None => PathBuf::from(var("HOME")?).join(".config"),
+        };
+
+        Some(config_dir.join("sqlar").join("config.toml"))
+    }
+
+    /// Parse the contents of a config file.
+    pub fn parse(contents: &str) -> sqlarfs::Result<Self> {
+        toml::from_str(contents)
+            .map_err(|err| invalid_config(format!("The config file is invalid: {err}")))
+    }
+
+    // Read the config file at `path`. If it doesn't exist and `required` is `false`, this returns
+    // the default settings.
+    fn read(path: &Path, required: bool) -> eyre::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Self::parse(&contents)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !required => Ok(Self::default()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Err(invalid_config(format!(
+                "The config file does not exist: {}",
+                path.to_string_lossy()
+            ))
+            .into()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Override these settings with the values of environment variables.
+    ///
+    /// `var` returns the value of the environment variable with the given name, if it's set.
+    /// `SQLAR_EXCLUDE` is a list of patterns separated like `PATH`, which are added to the
+    /// patterns from the config file.
+    pub fn apply_env<F>(&mut self, var: F) -> sqlarfs::Result<()>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if let Some(value) = var(COMPRESSION_ENV) {
+            self.compression = Some(parse_env_value(COMPRESSION_ENV, &value)?);
+        }
+
+        if let Some(value) = var(EXCLUDE_ENV) {
+            self.exclude.extend(
+                env::split_paths(&value)
+                    .filter(|pattern| !pattern.as_os_str().is_empty())
+                    .map(|pattern| pattern.to_string_lossy().into_owned()),
+            );
+        }
+
+        if let Some(value) = var(OVERWRITE_ENV) {
+            self.overwrite = Some(parse_env_value(OVERWRITE_ENV, &value)?);
+        }
+
+        if let Some(value) = var(PROGRESS_ENV) {
+            self.progress = Some(parse_env_value(PROGRESS_ENV, &value)?);
+        }
+
+        Ok(())
+    }
+
+    /// Load the settings from the config file and the environment.
+    ///
+    /// If `path` is `None`, this uses the path in `SQLAR_CONFIG` or the default path, and it's not
+    /// an error for the config file not to exist. This returns the settings and the path of the
+    /// config file that was read, if any.
+    pub fn load(path: Option<&Path>) -> eyre::Result<(Self, Option<PathBuf>)> {
+        Self::load_with_env(path, |name| env::var_os(name))
+    }
+
+    /// Load the settings like [`Settings::load`], but with a custom environment.
+    ///
+    /// `var` returns the value of the environment variable with the given name, if it's set. This
+    /// is used to find the config file as well as to override its settings.
+    pub fn load_with_env<F>(path: Option<&Path>, var: F) -> eyre::Result<(Self, Option<PathBuf>)>
+    where
+        F: Fn(&str) -> Option<OsString>,
+    {
+        let (path, required) = match path {
+            Some(path) => (Some(path.to_owned()), true),
+            None => match var(CONFIG_PATH_ENV).filter(|path| !path.is_empty()) {
+                Some(path) => (Some(PathBuf::from(path)), true),
+                None => (Self::default_path_with_env(&var), false),
+            },
+        };
+
+        let mut settings = match &path {
+            Some(path) => Self::read(path, required)?,
+            None => Self::default(),
+        };
+
+        settings.apply_env(|name| var(name).and_then(|value| value.into_string().ok()))?;
+
+        let path = path.filter(|path| path.exists());
+
+        Ok((settings, path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use xpct::{be_err, be_ok, equal, expect};
+
+    fn apply_env(vars: &[(&str, &str)]) -> sqlarfs::Result<Settings> {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+
+        let mut settings = Settings {
+            exclude: vec![String::from("*.tmp")],
+            overwrite: Some(Overwrite::Skip),
+            ..Default::default()
+        };
+
+        settings.apply_env(|name| vars.get(name).cloned())?;
+
+        Ok(settings)
+    }
+
+    #[test]
+    fn parse_config_file() {
+        let contents = r#"
+            compression = "best"
+            exclude = ["*.tmp", ".git"]
+            overwrite = "replace"
+            progress = "json"
+        "#;
+
+        expect!(Settings::parse(contents))
+            .to(be_ok())
+            .to(equal(Settings {
+                compression: Some(CompressionLevel::Best),
+                exclude: vec![String::from("*.tmp"), String::from(".git")],
+                overwrite: Some(Overwrite::Replace),
+                progress: Some(ProgressFormat::Json),
+            }));
+    }
+
+    #[test]
+    fn parse_config_file_with_unknown_key_errors() {
+        expect!(Settings::parse("unknown = true")).to(be_err());
+    }
+
+    #[test]
+    fn parse_config_file_with_invalid_value_errors() {
+        expect!(Settings::parse(r#"overwrite = "sometimes""#)).to(be_err());
+    }
+
+    #[test]
+    fn env_vars_override_config_file() {
+        expect!(apply_env(&[
+            (COMPRESSION_ENV, "none"),
+            (OVERWRITE_ENV, "error"),
+            (PROGRESS_ENV, "json"),
+        ]))
+        .to(be_ok())
+        .to(equal(Settings {
+            compression: Some(CompressionLevel::None),
+            exclude: vec![String::from("*.tmp")],
+            overwrite: Some(Overwrite::Error),
+            progress: Some(ProgressFormat::Json),
+        }));
+    }
+
+    #[test]
+    fn env_var_excludes_are_added_to_config_file_excludes() {
+        let separator = if cfg!(windows) { ";" } else { ":" };
+
+        expect!(apply_env(&[(
+            EXCLUDE_ENV,
+            &format!(".git{separator}target")
+        )]))
+        .to(be_ok())
+        .map(|settings| settings.exclude)
+        .to(equal(vec![
+            String::from("*.tmp"),
+            String::from(".git"),
+            String::from("target"),
+        ]));
+    }
+
+    #[test]
+    fn invalid_env_var_errors() {
+        expect!(apply_env(&[(OVERWRITE_ENV, "sometimes")])).to(be_err());
+    }
+}
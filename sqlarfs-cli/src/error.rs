@@ -0,0 +1,225 @@
+use std::fmt;
+use std::io;
+
+use super::command::ChecksumMismatch;
+
+// Primary SQLite result codes. Extended result codes have the primary code in the low byte.
+const SQLITE_BUSY: i32 = 5;
+const SQLITE_LOCKED: i32 = 6;
+const SQLITE_CORRUPT: i32 = 11;
+const SQLITE_NOTADB: i32 = 26;
+
+/// The kind of failure the CLI exited with.
+///
+/// Each kind has a distinct exit code, so scripts can branch on the type of failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FailureKind {
+    /// Any failure that doesn't have a more specific kind.
+    General,
+
+    /// A file or archive was not found.
+    NotFound,
+
+    /// A file or archive already exists.
+    AlreadyExists,
+
+    /// The archive is corrupt or is not a SQLite database.
+    Corrupt,
+
+    /// The archive or a file in it is locked by another process.
+    Locked,
+
+    /// The command ran to completion, but some files failed, like files that didn't match their
+    /// checksums.
+    PartialFailure,
+}
+
+impl FailureKind {
+    /// The exit code for this kind of failure.
+    ///
+    /// Exit code 2 is reserved for invalid command-line arguments.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            FailureKind::General => 1,
+            FailureKind::NotFound => 3,
+            FailureKind::AlreadyExists => 4,
+            FailureKind::Corrupt => 5,
+            FailureKind::Locked => 6,
+            FailureKind::PartialFailure => 7,
+        }
+    }
+}
+
+impl From<&sqlarfs::Error> for FailureKind {
+    fn from(err: &sqlarfs::Error) -> Self {
+        match err {
+            sqlarfs::Error::FileNotFound { .. }
+            | sqlarfs::Error::NoParentDirectory { .. }
+            | sqlarfs::Error::CannotOpen => FailureKind::NotFound,
+            sqlarfs::Error::FileAlreadyExists { .. } | sqlarfs::Error::SqlarAlreadyExists => {
+                FailureKind::AlreadyExists
+            }
+            sqlarfs::Error::NotADatabase => FailureKind::Corrupt,
+            sqlarfs::Error::WouldBlock { .. } => FailureKind::Locked,
+            sqlarfs::Error::Sqlite { code } => match code.raw_code().map(|code| code & 0xff) {
+                Some(SQLITE_BUSY | SQLITE_LOCKED) => FailureKind::Locked,
+                Some(SQLITE_CORRUPT | SQLITE_NOTADB) => FailureKind::Corrupt,
+                _ => FailureKind::General,
+            },
+            sqlarfs::Error::Io { kind, .. } => match kind {
+                io::ErrorKind::NotFound => FailureKind::NotFound,
+                io::ErrorKind::AlreadyExists => FailureKind::AlreadyExists,
+                io::ErrorKind::WouldBlock => FailureKind::Locked,
+                _ => FailureKind::General,
+            },
+            _ => FailureKind::General,
+        }
+    }
+}
+
+/// An error that should be reported to the user as a short message and an exit code.
+///
+/// Errors that aren't user errors are bugs, and are reported with a full backtrace instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserError {
+    kind: FailureKind,
+    message: String,
+}
+
+impl fmt::Display for UserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for UserError {}
+
+impl UserError {
+    /// The kind of failure this is.
+    pub fn kind(&self) -> FailureKind {
+        self.kind
+    }
+
+    /// Return a [`UserError`] if the given error was caused by the user or their environment.
+    pub fn from_report(report: &eyre::Report) -> Option<Self> {
+        if let Some(err) = report.downcast_ref::<sqlarfs::Error>() {
+            return Some(Self {
+                kind: err.into(),
+                message: err.to_string(),
+            });
+        }
+
+        if let Some(err) = report.downcast_ref::<io::Error>() {
+            return Some(Self {
+                kind: (&sqlarfs::Error::Io {
+                    kind: err.kind(),
+                    code: err.raw_os_error(),
+                })
+                    .into(),
+                message: err.to_string(),
+            });
+        }
+
+        if let Some(err) = report.downcast_ref::<ChecksumMismatch>() {
+            return Some(Self {
+                kind: FailureKind::PartialFailure,
+                message: err.to_string(),
+            });
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use xpct::{be_none, be_some, equal, expect};
+
+    fn kind_of(report: eyre::Report) -> Option<FailureKind> {
+        UserError::from_report(&report).map(|err| err.kind())
+    }
+
+    #[test]
+    fn library_errors_map_to_failure_kinds() {
+        expect!(kind_of(
+            sqlarfs::Error::FileNotFound {
+                path: PathBuf::from("file")
+            }
+            .into()
+        ))
+        .to(equal(Some(FailureKind::NotFound)));
+
+        expect!(kind_of(sqlarfs::Error::SqlarAlreadyExists.into()))
+            .to(equal(Some(FailureKind::AlreadyExists)));
+
+        expect!(kind_of(sqlarfs::Error::NotADatabase.into())).to(equal(Some(FailureKind::Corrupt)));
+
+        expect!(kind_of(
+            sqlarfs::Error::WouldBlock {
+                path: PathBuf::from("file")
+            }
+            .into()
+        ))
+        .to(equal(Some(FailureKind::Locked)));
+
+        expect!(kind_of(sqlarfs::Error::FilesystemLoop.into()))
+            .to(equal(Some(FailureKind::General)));
+    }
+
+    #[test]
+    fn io_errors_map_to_failure_kinds() {
+        expect!(kind_of(io::Error::from(io::ErrorKind::NotFound).into()))
+            .to(equal(Some(FailureKind::NotFound)));
+
+        expect!(kind_of(
+            io::Error::from(io::ErrorKind::AlreadyExists).into()
+        ))
+        .to(equal(Some(FailureKind::AlreadyExists)));
+
+        expect!(kind_of(io::Error::from(io::ErrorKind::Other).into()))
+            .to(equal(Some(FailureKind::General)));
+    }
+
+    #[test]
+    fn checksum_mismatches_are_partial_failures() {
+        expect!(kind_of(ChecksumMismatch { failed: 1 }.into()))
+            .to(equal(Some(FailureKind::PartialFailure)));
+    }
+
+    #[test]
+    fn other_errors_are_not_user_errors() {
+        expect!(UserError::from_report(&eyre::eyre!("a bug"))).to(be_none());
+    }
+
+    #[test]
+    fn user_errors_keep_original_message() {
+        expect!(UserError::from_report(&sqlarfs::Error::CannotOpen.into()))
+            .to(be_some())
+            .map(|err| err.to_string())
+            .to(equal(sqlarfs::Error::CannotOpen.to_string()));
+    }
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let kinds = [
+            FailureKind::General,
+            FailureKind::NotFound,
+            FailureKind::AlreadyExists,
+            FailureKind::Corrupt,
+            FailureKind::Locked,
+            FailureKind::PartialFailure,
+        ];
+
+        let mut codes = kinds.map(FailureKind::exit_code).to_vec();
+        codes.sort();
+        codes.dedup();
+
+        expect!(codes.len()).to(equal(kinds.len()));
+        expect!(codes.contains(&2)).to(equal(false));
+    }
+}
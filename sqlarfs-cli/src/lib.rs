@@ -1,4 +1,14 @@
 mod cli;
 mod command;
+mod config;
+mod error;
+mod manifest;
+mod progress;
 
-pub use cli::{Archive, Cli, Commands, Create, Extract, List, Remove};
+pub use cli::{
+    Analyze, Archive, Cli, Commands, CompressionLevel, Config, Create, Extract, Grep, List,
+    Overwrite, ProgressFormat, Remove, Sha256sum, Tree,
+};
+pub use command::ChecksumMismatch;
+pub use config::Settings;
+pub use error::{FailureKind, UserError};
@@ -0,0 +1,193 @@
+// Parsing for the manifest format accepted by `sqlar create --from-manifest`.
+//
+// A manifest is a sequence of newline-delimited entries, each with tab-separated fields:
+//
+//     PATH<TAB>MODE<TAB>MTIME<TAB>SOURCE[<TAB>SIZE]
+//
+// - `PATH` is the path of the file in the archive.
+// - `MODE` is the file mode in octal, including the file type bits (e.g. `100644` for a regular
+//   file, `40755` for a directory, or `120777` for a symbolic link).
+// - `MTIME` is the file's mtime in seconds since the Unix epoch.
+// - `SOURCE` is the path of a file in the filesystem to copy the contents from for regular
+//   files, the target for symbolic links, and is ignored for directories. If it's `-` for a
+//   regular file, the contents are read inline: exactly `SIZE` bytes immediately following the
+//   newline at the end of the entry.
+//
+// Blank lines and lines starting with `#` are ignored.
+
+use std::fs;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sqlarfs::{Compression, FileMode, FileType, OverwritePolicy};
+
+const TYPE_MASK: u32 = 0o170000;
+const FILE_MODE: u32 = 0o100000;
+const DIR_MODE: u32 = 0o040000;
+const SYMLINK_MODE: u32 = 0o120000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Path(PathBuf),
+    Inline(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub kind: FileType,
+    pub mode: FileMode,
+    pub mtime: SystemTime,
+    pub source: Source,
+}
+
+fn invalid(line_num: usize, reason: &str) -> sqlarfs::Error {
+    sqlarfs::Error::InvalidArgs {
+        reason: format!("Invalid manifest entry on line {line_num}: {reason}"),
+    }
+}
+
+/// An iterator over the entries in a manifest.
+#[derive(Debug)]
+pub struct Manifest<R> {
+    reader: R,
+    line_num: usize,
+}
+
+impl<R: BufRead> Manifest<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line_num: 0,
+        }
+    }
+
+    fn parse_entry(&mut self, line: &str) -> sqlarfs::Result<Entry> {
+        let line_num = self.line_num;
+        let fields = line.split('\t').collect::<Vec<_>>();
+
+        let [path, mode, mtime, source, rest @ ..] = fields.as_slice() else {
+            return Err(invalid(
+                line_num,
+                "expected at least 4 tab-separated fields",
+            ));
+        };
+
+        let mode = u32::from_str_radix(mode, 8)
+            .map_err(|_| invalid(line_num, "the mode is not a valid octal number"))?;
+
+        let kind = match mode & TYPE_MASK {
+            FILE_MODE => FileType::File,
+            DIR_MODE => FileType::Dir,
+            SYMLINK_MODE => FileType::Symlink,
+            _ => return Err(invalid(line_num, "the mode has an unsupported file type")),
+        };
+
+        let mtime_secs = mtime
+            .parse::<u64>()
+            .map_err(|_| invalid(line_num, "the mtime is not a valid Unix timestamp"))?;
+
+        let source = match (kind, *source, rest) {
+            (FileType::File, "-", [size]) => {
+                let size = size
+                    .parse::<usize>()
+                    .map_err(|_| invalid(line_num, "the size is not a valid number"))?;
+
+                let mut data = vec![0u8; size];
+                self.reader.read_exact(&mut data)?;
+
+                Source::Inline(data)
+            }
+            (FileType::File, "-", _) => {
+                return Err(invalid(line_num, "inline file contents require a size"))
+            }
+            (_, source, _) => Source::Path(PathBuf::from(source)),
+        };
+
+        Ok(Entry {
+            path: PathBuf::from(path),
+            kind,
+            mode: FileMode::from_bits_truncate(mode & !TYPE_MASK),
+            mtime: UNIX_EPOCH + Duration::from_secs(mtime_secs),
+            source,
+        })
+    }
+}
+
+impl<R: BufRead> Iterator for Manifest<R> {
+    type Item = sqlarfs::Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut line = String::new();
+
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(err) => return Some(Err(err.into())),
+            }
+
+            self.line_num += 1;
+
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            return Some(self.parse_entry(line));
+        }
+    }
+}
+
+/// Add an entry from a manifest to the archive, creating any missing parent directories.
+pub fn add_entry(
+    archive: &mut sqlarfs::Archive,
+    entry: &Entry,
+    overwrite: OverwritePolicy,
+    compression: Option<Compression>,
+) -> sqlarfs::Result<()> {
+    if let Some(parent) = entry.path.parent() {
+        if parent != Path::new("") {
+            archive.open(parent)?.create_dir_all()?;
+        }
+    }
+
+    let mut file = archive.open(&entry.path)?;
+
+    if let Some(method) = compression {
+        file.set_compression(method);
+    }
+
+    if overwrite != OverwritePolicy::Error && file.exists()? {
+        if overwrite == OverwritePolicy::Skip {
+            return Ok(());
+        }
+
+        let is_dir = file.metadata()?.kind() == FileType::Dir;
+
+        if !(is_dir && entry.kind == FileType::Dir) {
+            file.delete()?;
+        }
+    }
+
+    match (&entry.kind, &entry.source) {
+        (FileType::File, Source::Inline(data)) => {
+            file.create_file()?;
+            file.write_bytes(data)?;
+        }
+        (FileType::File, Source::Path(path)) => {
+            file.create_file()?;
+            file.write_file(&mut fs::File::open(path)?)?;
+        }
+        (FileType::Dir, _) => file.create_dir_all()?,
+        (FileType::Symlink, Source::Path(target)) => file.create_symlink(target)?,
+        (FileType::Symlink, Source::Inline(_)) => unreachable!(),
+    }
+
+    file.set_mode(Some(entry.mode))?;
+    file.set_mtime(Some(entry.mtime))?;
+
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::path::Path;
+
+use sqlarfs::Progress;
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+
+    escaped.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                write!(escaped, "\\u{:04x}", u32::from(c)).expect("Writing to a string can't fail.")
+            }
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('"');
+
+    escaped
+}
+
+fn json_optional(value: Option<u64>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => String::from("null"),
+    }
+}
+
+// Format a progress event as a single line of JSON.
+//
+// `source` is the path passed on the command line that the file is being copied from, since
+// commands that accept more than one path report progress for each of them separately.
+fn json_line(source: &Path, progress: &Progress) -> String {
+    format!(
+        r#"{{"event":"progress","source":{},"path":{},"files_done":{},"files_total":{},"bytes_done":{},"bytes_total":{}}}"#,
+        json_string(&source.to_string_lossy()),
+        json_string(&progress.path().to_string_lossy()),
+        progress.files_done(),
+        json_optional(progress.files_total()),
+        progress.bytes_done(),
+        json_optional(progress.bytes_total()),
+    )
+}
+
+// Return a callback that prints progress events to stderr as newline-delimited JSON.
+pub fn json_progress(source: &Path) -> impl Fn(&Progress) + Send + Sync + 'static {
+    let source = source.to_owned();
+
+    move |progress| {
+        // Progress output is best-effort; a closed stderr shouldn't stop the operation.
+        let _ = writeln!(io::stderr().lock(), "{}", json_line(&source, progress));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    use sqlarfs::{Connection, ExtractOptions};
+    use xpct::{equal, expect};
+
+    #[test]
+    fn json_strings_are_escaped() {
+        expect!(json_string("a\"b\\c\nd\u{1}")).to(equal(r#""a\"b\\c\nd\u0001""#));
+    }
+
+    #[test]
+    fn progress_is_formatted_as_json() -> sqlarfs::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let lines_clone = Arc::clone(&lines);
+
+        let opts = ExtractOptions::new().on_progress(move |progress| {
+            lines_clone
+                .lock()
+                .unwrap()
+                .push(json_line(Path::new("src"), progress))
+        });
+
+        Connection::open_in_memory()?.exec(|archive| {
+            let mut file = archive.open("file")?;
+            file.create_file()?;
+            file.write_str("contents")?;
+            drop(file);
+
+            archive.extract_with("file", temp_dir.path().join("file"), &opts)
+        })?;
+
+        expect!(lines.lock().unwrap().clone()).to(equal(vec![String::from(
+            r#"{"event":"progress","source":"src","path":"file","files_done":1,"files_total":1,"bytes_done":8,"bytes_total":8}"#,
+        )]));
+
+        Ok(())
+    }
+}
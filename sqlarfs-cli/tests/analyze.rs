@@ -0,0 +1,101 @@
+mod common;
+
+use std::path::Path;
+
+use common::command;
+use sqlarfs::{Compression, Connection};
+use xpct::{be_err, be_ok, equal, expect};
+
+fn create_archive(path: &Path) -> sqlarfs::Result<()> {
+    let mut conn = Connection::create_new(path)?;
+
+    conn.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        for (path, contents) in [
+            ("dir/a.txt", "aaaa"),
+            ("dir/b.txt", "bbbbbb"),
+            ("dir/c.md", "cc"),
+            ("README", "readme"),
+        ] {
+            let mut file = archive.open(path)?;
+            file.create_file()?;
+            file.set_compression(Compression::None);
+            file.write_str(contents)?;
+        }
+
+        sqlarfs::Result::Ok(())
+    })
+}
+
+#[test]
+fn errors_when_archive_does_not_exist() -> eyre::Result<()> {
+    expect!(command(&["analyze", "--archive", "nonexistent.sqlar"])).to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn errors_when_path_does_not_exist() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "analyze",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "nonexistent",
+    ]))
+    .to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn analyzing_whole_archive() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    let expected = [
+        "EXTENSION       FILES       ORIGINAL         STORED          SAVED   RATIO",
+        "txt                 2             10             10              0  100.0%",
+        "(none)              1              6              6              0  100.0%",
+        "md                  1              2              2              0  100.0%",
+        "TOTAL               4             18             18              0  100.0%",
+    ];
+
+    expect!(command(&[
+        "analyze",
+        "--archive",
+        &archive_path.to_string_lossy(),
+    ]))
+    .to(be_ok())
+    .to(equal(expected.join("\n")));
+
+    Ok(())
+}
+
+#[test]
+fn analyzing_directory() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    let output = command(&[
+        "analyze",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "dir",
+    ])?;
+
+    expect!(output.lines().last()).to(equal(Some(
+        "TOTAL               3             12             12              0  100.0%",
+    )));
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use std::fs;
+
+use common::command;
+use xpct::{be_empty, be_ok, be_true, equal, expect};
+
+#[test]
+fn benchmarking_prints_each_operation() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let output = command(&[
+        "bench",
+        "--files",
+        "150",
+        "--size",
+        "100",
+        "--dir",
+        &temp_dir.path().to_string_lossy(),
+    ])?;
+
+    let lines = output.lines().collect::<Vec<_>>();
+
+    expect!(lines.len()).to(equal(5));
+    expect!(lines[0].ends_with("150 files of 100 bytes, compression fast")).to(be_true());
+    expect!(lines[1].split_whitespace().next()).to(equal(Some("OPERATION")));
+
+    let rows = lines[2..]
+        .iter()
+        .map(|line| {
+            let columns = line.split_whitespace().collect::<Vec<_>>();
+            (columns[0], columns[1], columns[2])
+        })
+        .collect::<Vec<_>>();
+
+    // The tree has a root directory and two directories under it.
+    expect!(rows).to(equal(vec![
+        ("create", "150", "15000"),
+        ("list", "153", "-"),
+        ("extract", "150", "15000"),
+    ]));
+
+    Ok(())
+}
+
+#[test]
+fn benchmarking_cleans_up_after_itself() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    expect!(command(&[
+        "bench",
+        "--files",
+        "10",
+        "--dir",
+        &temp_dir.path().to_string_lossy(),
+    ]))
+    .to(be_ok());
+
+    expect!(fs::read_dir(temp_dir.path())?.collect::<Vec<_>>()).to(be_empty());
+
+    Ok(())
+}
@@ -1,14 +1,38 @@
+#![allow(dead_code)]
+
+use std::ffi::OsString;
 use std::path::PathBuf;
 
 use clap::Parser;
 use sqlarfs_cli::Cli;
 
+// Run the CLI with the given arguments, ignoring the user's config file and environment.
 pub fn command(args: &[&str]) -> eyre::Result<String> {
+    let mut all_args = vec!["--no-config"];
+    all_args.extend_from_slice(args);
+
+    command_with_config(&all_args)
+}
+
+// Run the CLI with the given arguments, reading the config file and environment.
+//
+// This doesn't read the user's config file or environment. Use `command_with_env` to set the
+// environment variables the CLI sees.
+pub fn command_with_config(args: &[&str]) -> eyre::Result<String> {
+    command_with_env(args, &[])
+}
+
+// Run the CLI with the given arguments, as if `vars` were the only environment variables set.
+pub fn command_with_env(args: &[&str], vars: &[(&str, &str)]) -> eyre::Result<String> {
     let mut output = Vec::new();
     let mut all_args = vec!["sqlar"];
 
     all_args.extend_from_slice(args);
-    Cli::parse_from(all_args).dispatch(&mut output)?;
+    Cli::parse_from(all_args).dispatch_with_env(&mut output, |name| {
+        vars.iter()
+            .find(|(var_name, _)| *var_name == name)
+            .map(|(_, value)| OsString::from(value))
+    })?;
 
     Ok(String::from_utf8(output)?.trim().to_owned())
 }
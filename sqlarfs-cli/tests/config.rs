@@ -0,0 +1,252 @@
+mod common;
+
+use std::fs;
+
+use common::{command, command_with_config, command_with_env};
+use sqlarfs::{Connection, FileMetadata};
+use xpct::{be_err, be_false, be_ok, be_true, equal, expect, match_pattern, pattern};
+
+#[test]
+fn printing_settings_from_config_file() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let config_path = temp_dir.path().join("config.toml");
+
+    fs::write(
+        &config_path,
+        "compression = \"best\"\nexclude = [\"*.tmp\"]\nprogress = \"json\"\n",
+    )?;
+
+    expect!(command_with_config(&[
+        "config",
+        "--config",
+        &config_path.to_string_lossy()
+    ]))
+    .to(be_ok())
+    .to(equal(format!(
+        "# Config file: {}\ncompression = \"best\"\nexclude = [\"*.tmp\"]\noverwrite = \"error\"\nprogress = \"json\"",
+        config_path.to_string_lossy()
+    )));
+
+    Ok(())
+}
+
+#[test]
+fn printing_settings_without_config_file() -> eyre::Result<()> {
+    expect!(command(&["config"])).to(be_ok()).to(equal(
+        "# No config file was found.\ncompression = \"fast\"\nexclude = []\noverwrite = \"error\"",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn default_config_file_is_read_from_config_dir() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let config_path = temp_dir.path().join("sqlar").join("config.toml");
+
+    fs::create_dir(temp_dir.path().join("sqlar"))?;
+    fs::write(&config_path, "compression = \"none\"\n")?;
+
+    expect!(command_with_env(
+        &["config"],
+        &[("XDG_CONFIG_HOME", &temp_dir.path().to_string_lossy())]
+    ))
+    .to(be_ok())
+    .to(equal(format!(
+        "# Config file: {}\ncompression = \"none\"\nexclude = []\noverwrite = \"error\"",
+        config_path.to_string_lossy()
+    )));
+
+    Ok(())
+}
+
+#[test]
+fn config_file_is_read_from_env_var() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let config_path = temp_dir.path().join("config.toml");
+
+    fs::write(&config_path, "overwrite = \"skip\"\n")?;
+
+    expect!(command_with_env(
+        &["config"],
+        &[
+            ("SQLAR_CONFIG", &config_path.to_string_lossy()),
+            ("SQLAR_COMPRESSION", "best"),
+        ]
+    ))
+    .to(be_ok())
+    .to(equal(format!(
+        "# Config file: {}\ncompression = \"best\"\nexclude = []\noverwrite = \"skip\"",
+        config_path.to_string_lossy()
+    )));
+
+    Ok(())
+}
+
+#[test]
+fn config_file_that_does_not_exist_errors() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    expect!(command_with_config(&[
+        "config",
+        "--config",
+        &temp_dir.path().join("nonexistent.toml").to_string_lossy(),
+    ]))
+    .to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn invalid_config_file_errors() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let config_path = temp_dir.path().join("config.toml");
+
+    fs::write(&config_path, "unknown = true\n")?;
+
+    expect!(command_with_config(&[
+        "config",
+        "--config",
+        &config_path.to_string_lossy()
+    ]))
+    .to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn config_and_no_config_flags_conflict() -> eyre::Result<()> {
+    use clap::Parser;
+
+    expect!(sqlarfs_cli::Cli::try_parse_from([
+        "sqlar",
+        "--no-config",
+        "--config",
+        "config.toml",
+        "config",
+    ]))
+    .to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn creating_archive_uses_excludes_from_config_file() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let config_path = temp_dir.path().join("config.toml");
+    let archive_path = temp_dir.path().join("test.sqlar");
+    let source_dir = temp_dir.path().join("source");
+
+    fs::create_dir(&source_dir)?;
+    fs::write(source_dir.join("file"), "")?;
+    fs::write(source_dir.join("file.tmp"), "")?;
+    fs::write(source_dir.join("file.bak"), "")?;
+
+    fs::write(&config_path, "exclude = [\"*.tmp\"]\n")?;
+
+    command_with_config(&[
+        "create",
+        "--config",
+        &config_path.to_string_lossy(),
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--exclude",
+        "*.bak",
+        &source_dir.to_string_lossy(),
+    ])?;
+
+    let mut conn = Connection::open(&archive_path)?;
+
+    conn.exec(|archive| {
+        expect!(archive.open("source/file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        expect!(archive.open("source/file.tmp")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        expect!(archive.open("source/file.bak")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn flags_override_config_file() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let config_path = temp_dir.path().join("config.toml");
+    let archive_path = temp_dir.path().join("test.sqlar");
+    let source_file = temp_dir.path().join("file");
+
+    fs::write(&source_file, "a".repeat(1024))?;
+    fs::write(
+        &config_path,
+        "compression = \"best\"\noverwrite = \"skip\"\n",
+    )?;
+
+    Connection::create_new(&archive_path)?;
+
+    command_with_config(&[
+        "archive",
+        "--config",
+        &config_path.to_string_lossy(),
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--compression",
+        "none",
+        &source_file.to_string_lossy(),
+    ])?;
+
+    fs::write(&source_file, "new contents")?;
+
+    // The config file says to skip existing files, so this should be a no-op.
+    command_with_config(&[
+        "archive",
+        "--config",
+        &config_path.to_string_lossy(),
+        "--archive",
+        &archive_path.to_string_lossy(),
+        &source_file.to_string_lossy(),
+    ])?;
+
+    let mut conn = Connection::open(&archive_path)?;
+
+    conn.exec(|archive| {
+        let file = archive.open("file")?;
+
+        expect!(file.is_compressed()).to(be_ok()).to(be_false());
+        expect!(file.metadata())
+            .to(be_ok())
+            .to(match_pattern(pattern!(FileMetadata::File {
+                size: 1024,
+                ..
+            })));
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn invalid_exclude_pattern_errors() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    expect!(command(&[
+        "create",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--exclude",
+        "[",
+        &temp_dir.path().to_string_lossy(),
+    ]))
+    .to(be_err());
+
+    Ok(())
+}
@@ -2,13 +2,15 @@ mod common;
 
 use std::env;
 use std::fs;
+use std::io::Read;
+use std::time::{Duration, UNIX_EPOCH};
 
 use clap::Parser;
 use serial_test::serial;
-use sqlarfs::Connection;
+use sqlarfs::{Connection, FileMetadata, FileMode};
 use sqlarfs_cli::{Cli, Commands, Create};
 use xpct::be_empty;
-use xpct::{be_err, be_existing_file, expect, match_pattern, pattern};
+use xpct::{be_err, be_existing_file, be_ok, be_true, equal, expect, match_pattern, pattern};
 
 use common::{command, root_path};
 
@@ -262,3 +264,252 @@ fn archive_path_is_required_when_archiving_multiple_files() -> eyre::Result<()>
 
     Ok(())
 }
+
+#[test]
+fn reproducible_archives_are_identical() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let source_path = temp_dir.path().join("source");
+    fs::create_dir(&source_path)?;
+    fs::write(source_path.join("file"), "file contents")?;
+
+    let first_path = temp_dir.path().join("first.sqlar");
+    let second_path = temp_dir.path().join("second.sqlar");
+
+    for archive_path in [&first_path, &second_path] {
+        command(&[
+            "create",
+            "--reproducible",
+            "--epoch",
+            "1000000",
+            "--archive",
+            &archive_path.to_string_lossy(),
+            &source_path.to_string_lossy(),
+        ])?;
+    }
+
+    expect!(fs::read(&first_path)?).to(equal(fs::read(&second_path)?));
+
+    Ok(())
+}
+
+#[test]
+fn epoch_flag_requires_reproducible_flag() -> eyre::Result<()> {
+    expect!(Cli::try_parse_from([
+        "sqlar",
+        "create",
+        "--epoch",
+        "0",
+        "nonexistent"
+    ]))
+    .to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn create_archive_from_manifest() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    let source_path = temp_dir.path().join("source");
+    fs::write(&source_path, "from file")?;
+
+    let manifest_path = temp_dir.path().join("manifest");
+    let manifest = format!(
+        "# A comment.\n\
+        dir\t40755\t10\t-\n\
+        dir/file\t100644\t20\t{}\n\
+        dir/inline\t100600\t30\t-\t6\n\
+        inline\n\
+        nested/link\t120777\t40\ttarget\n",
+        source_path.to_string_lossy()
+    );
+    fs::write(&manifest_path, manifest)?;
+
+    command(&[
+        "create",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--from-manifest",
+        &manifest_path.to_string_lossy(),
+    ])?;
+
+    Connection::open(&archive_path)?.exec(|archive| {
+        let mut file = archive.open("dir/file")?;
+        let mut contents = String::new();
+        file.reader()?.read_to_string(&mut contents)?;
+        expect!(contents).to(equal("from file"));
+
+        expect!(file.metadata()?.mtime()).to(equal(Some(UNIX_EPOCH + Duration::from_secs(20))));
+
+        let mut inline = archive.open("dir/inline")?;
+        let mut contents = String::new();
+        inline.reader()?.read_to_string(&mut contents)?;
+        expect!(contents).to(equal("inline"));
+
+        expect!(inline.metadata()?.mode()).to(equal(Some(FileMode::OWNER_R | FileMode::OWNER_W)));
+
+        expect!(archive.open("nested/link")?.metadata())
+            .to(be_ok())
+            .to(match_pattern(pattern!(FileMetadata::Symlink { .. })));
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn creating_archive_from_invalid_manifest_errors() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    let manifest_path = temp_dir.path().join("manifest");
+    fs::write(&manifest_path, "file\tnot-a-mode\t0\t-\t0\n")?;
+
+    expect!(command(&[
+        "create",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--from-manifest",
+        &manifest_path.to_string_lossy(),
+    ]))
+    .to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn creating_with_replace_adds_to_existing_archive() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+    let source_dir = tempfile::tempdir()?;
+    let source_path = source_dir.path().join("file");
+    fs::write(&source_path, "new contents")?;
+
+    let mut conn = Connection::create_new(&archive_path)?;
+    conn.exec(|archive| {
+        archive.open("file")?.create_file()?;
+        archive.open("file")?.write_str("old contents")?;
+        archive.open("other")?.create_file()?;
+        sqlarfs::Result::Ok(())
+    })?;
+    drop(conn);
+
+    expect!(command(&[
+        "create",
+        "--replace",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        &source_path.to_string_lossy(),
+    ]))
+    .to(be_ok());
+
+    let mut conn = Connection::open(&archive_path)?;
+    conn.exec(|archive| {
+        let mut contents = String::new();
+        archive
+            .open("file")?
+            .reader()?
+            .read_to_string(&mut contents)?;
+        expect!(contents).to(equal("new contents"));
+
+        expect!(archive.open("other")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn creating_with_skip_existing_keeps_existing_files() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+    let source_dir = tempfile::tempdir()?;
+    let source_path = source_dir.path().join("file");
+    fs::write(&source_path, "new contents")?;
+
+    let mut conn = Connection::create_new(&archive_path)?;
+    conn.exec(|archive| {
+        archive.open("file")?.create_file()?;
+        archive.open("file")?.write_str("old contents")?;
+        sqlarfs::Result::Ok(())
+    })?;
+    drop(conn);
+
+    expect!(command(&[
+        "create",
+        "--skip-existing",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        &source_path.to_string_lossy(),
+    ]))
+    .to(be_ok());
+
+    let mut conn = Connection::open(&archive_path)?;
+    conn.exec(|archive| {
+        let mut contents = String::new();
+        archive
+            .open("file")?
+            .reader()?
+            .read_to_string(&mut contents)?;
+        expect!(contents).to(equal("old contents"));
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn replace_and_skip_existing_flags_conflict() -> eyre::Result<()> {
+    expect!(Cli::try_parse_from([
+        "sqlar",
+        "create",
+        "--replace",
+        "--skip-existing",
+        "--archive",
+        "nonexistent.sqlar",
+        "nonexistent",
+    ]))
+    .to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn creates_archive_with_json_progress() -> eyre::Result<()> {
+    let source_file = tempfile::NamedTempFile::new()?;
+    let archive_file = tempfile::NamedTempFile::new()?;
+
+    command(&[
+        "create",
+        "--archive",
+        &archive_file.path().to_string_lossy(),
+        "--progress",
+        "json",
+        &source_file.path().to_string_lossy(),
+    ])?;
+
+    expect!(archive_file.path()).to(be_existing_file());
+
+    Ok(())
+}
+
+#[test]
+fn progress_flag_rejects_unknown_formats() -> eyre::Result<()> {
+    expect!(Cli::try_parse_from([
+        "sqlar",
+        "create",
+        "--archive",
+        "test.sqlar",
+        "--progress",
+        "xml",
+    ]))
+    .to(be_err());
+
+    Ok(())
+}
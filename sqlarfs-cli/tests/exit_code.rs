@@ -0,0 +1,87 @@
+mod common;
+
+use std::fs;
+
+use common::command;
+use sqlarfs::Connection;
+use sqlarfs_cli::{FailureKind, UserError};
+use xpct::{be_some, equal, expect};
+
+fn failure_kind(result: eyre::Result<String>) -> Option<FailureKind> {
+    UserError::from_report(&result.err()?).map(|err| err.kind())
+}
+
+#[test]
+fn file_not_found_in_archive_is_not_found() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    Connection::create_new(&archive_path)?;
+
+    expect!(failure_kind(command(&[
+        "remove",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "nonexistent"
+    ])))
+    .to(be_some())
+    .to(equal(FailureKind::NotFound));
+
+    Ok(())
+}
+
+#[test]
+fn archive_that_is_not_a_database_is_corrupt() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    fs::write(&archive_path, "this is not a database".repeat(100))?;
+
+    expect!(failure_kind(command(&[
+        "list",
+        "--archive",
+        &archive_path.to_string_lossy(),
+    ])))
+    .to(be_some())
+    .to(equal(FailureKind::Corrupt));
+
+    Ok(())
+}
+
+#[test]
+fn checksum_mismatch_is_partial_failure() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+    let checksum_path = temp_dir.path().join("checksums");
+
+    Connection::create_new(&archive_path)?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("contents")
+    })?;
+
+    fs::write(&checksum_path, format!("{}  file\n", "0".repeat(64)))?;
+
+    let result = command(&[
+        "sha256sum",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--check",
+        &checksum_path.to_string_lossy(),
+    ]);
+
+    expect!(failure_kind(result))
+        .to(be_some())
+        .to(equal(FailureKind::PartialFailure));
+
+    Ok(())
+}
+
+#[test]
+fn exit_codes_match_failure_kinds() {
+    expect!(FailureKind::NotFound.exit_code()).to(equal(3));
+    expect!(FailureKind::AlreadyExists.exit_code()).to(equal(4));
+    expect!(FailureKind::Corrupt.exit_code()).to(equal(5));
+    expect!(FailureKind::Locked.exit_code()).to(equal(6));
+    expect!(FailureKind::PartialFailure.exit_code()).to(equal(7));
+}
@@ -1,6 +1,7 @@
 mod common;
 
 use std::env;
+use std::fs;
 use std::path::Path;
 
 use clap::Parser;
@@ -8,7 +9,8 @@ use serial_test::serial;
 use sqlarfs::Connection;
 use sqlarfs_cli::{Cli, Commands, Extract};
 use xpct::{
-    be_directory, be_err, be_existing_file, be_regular_file, expect, match_pattern, pattern,
+    be_directory, be_err, be_existing_file, be_ok, be_regular_file, equal, expect, match_pattern,
+    pattern,
 };
 
 use common::{command, root_path};
@@ -231,3 +233,167 @@ fn extract_errors_when_source_does_not_have_a_filename() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn extracts_with_json_progress() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    let mut conn = Connection::create_new(&archive_path)?;
+    conn.exec(|archive| archive.open("file")?.create_file())?;
+
+    command(&[
+        "extract",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--progress",
+        "json",
+        &temp_dir.path().to_string_lossy(),
+    ])?;
+
+    expect!(temp_dir.path().join("file")).to(be_regular_file());
+
+    Ok(())
+}
+
+#[test]
+fn verifying_extracted_files_that_match() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+    let dest_path = temp_dir.path().join("dest");
+
+    fs::create_dir(&dest_path)?;
+
+    let mut conn = Connection::create_new(&archive_path)?;
+    conn.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let mut file = archive.open("dir/file")?;
+        file.create_file()?;
+        file.write_str("contents")
+    })?;
+
+    expect!(command(&[
+        "extract",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--verify",
+        &dest_path.to_string_lossy(),
+    ]))
+    .to(be_ok())
+    .to(equal(String::new()));
+
+    expect!(dest_path.join("dir/file")).to(be_regular_file());
+
+    Ok(())
+}
+
+#[test]
+fn verifying_extracted_source_files_that_match() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    let mut conn = Connection::create_new(&archive_path)?;
+    conn.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let mut file = archive.open("dir/file")?;
+        file.create_file()?;
+        file.write_str("contents")?;
+
+        let mut file = archive.open("other")?;
+        file.create_file()?;
+        file.write_str("other contents")
+    })?;
+
+    expect!(command(&[
+        "extract",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--source",
+        "dir",
+        "--source",
+        "other",
+        "--verify",
+        &temp_dir.path().to_string_lossy(),
+    ]))
+    .to(be_ok());
+
+    Ok(())
+}
+
+#[test]
+fn verifying_corrupt_extracted_files_errors() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+    let dest_path = temp_dir.path().join("dest");
+
+    fs::create_dir(&dest_path)?;
+
+    let mut conn = Connection::create_new(&archive_path)?;
+    conn.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("a".repeat(1024))
+    })?;
+    drop(conn);
+
+    // Corrupt the archive so that the size it records doesn't match the contents of the file.
+    rusqlite::Connection::open(&archive_path)?
+        .execute("UPDATE sqlar SET sz = 2048 WHERE name = 'file'", ())?;
+
+    let mut output = Vec::new();
+    let err = Cli::parse_from([
+        "sqlar",
+        "--no-config",
+        "extract",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--verify",
+        &dest_path.to_string_lossy(),
+    ])
+    .dispatch(&mut output)
+    .unwrap_err();
+
+    expect!(err.downcast_ref::<sqlarfs_cli::ChecksumMismatch>())
+        .to(equal(Some(&sqlarfs_cli::ChecksumMismatch { failed: 1 })));
+
+    expect!(String::from_utf8(output)?.trim().to_owned()).to(equal(format!(
+        "{}: FAILED size does not match",
+        dest_path.join("file").to_string_lossy()
+    )));
+
+    Ok(())
+}
+
+#[test]
+fn extracting_with_newline_converts_text_files() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+    let dest_path = temp_dir.path().join("dest");
+
+    fs::create_dir(&dest_path)?;
+
+    let mut conn = Connection::create_new(&archive_path)?;
+    conn.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("one\ntwo\n")
+    })?;
+
+    expect!(command(&[
+        "extract",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--newline",
+        "crlf",
+        &dest_path.to_string_lossy(),
+    ]))
+    .to(be_ok());
+
+    expect!(fs::read_to_string(dest_path.join("file")))
+        .to(be_ok())
+        .to(equal("one\r\ntwo\r\n"));
+
+    Ok(())
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use std::path::Path;
+
+use common::command;
+use sqlarfs::Connection;
+use xpct::{be_err, be_ok, equal, expect};
+
+fn create_archive(path: &Path) -> sqlarfs::Result<()> {
+    let mut conn = Connection::create_new(path)?;
+
+    conn.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let mut file = archive.open("dir/file1")?;
+        file.create_file()?;
+        file.write_str("first line\nsecond Line\r\nthird line")?;
+
+        let mut file = archive.open("file2")?;
+        file.create_file()?;
+        file.write_str("no matches here\nanother line\n")?;
+
+        let mut file = archive.open("binary")?;
+        file.create_file()?;
+        file.write_bytes(b"\0binary line\n")?;
+
+        sqlarfs::Result::Ok(())
+    })
+}
+
+#[test]
+fn errors_when_archive_does_not_exist() -> eyre::Result<()> {
+    expect!(command(&["grep", "--archive", "nonexistent.sqlar", "line"])).to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn errors_when_pattern_is_invalid() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "grep",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "(",
+    ]))
+    .to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn searching_whole_archive() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "grep",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "^[a-z]+ line$",
+    ]))
+    .to(be_ok())
+    .to(equal(
+        [
+            "dir/file1:1:first line",
+            "dir/file1:3:third line",
+            "file2:2:another line",
+        ]
+        .join("\n"),
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn searching_specific_path() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "grep",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "line",
+        "file2",
+    ]))
+    .to(be_ok())
+    .to(equal("file2:2:another line"));
+
+    expect!(command(&[
+        "grep",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "line",
+        "dir",
+    ]))
+    .to(be_ok())
+    .to(equal(
+        ["dir/file1:1:first line", "dir/file1:3:third line"].join("\n"),
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn searching_case_insensitively() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "grep",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--ignore-case",
+        "SECOND LINE",
+    ]))
+    .to(be_ok())
+    .to(equal("dir/file1:2:second Line"));
+
+    Ok(())
+}
+
+#[test]
+fn printing_only_files_with_matches() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "grep",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--files-with-matches",
+        "line",
+    ]))
+    .to(be_ok())
+    .to(equal(["dir/file1", "file2"].join("\n")));
+
+    Ok(())
+}
+
+#[test]
+fn searching_binary_files_with_text_flag() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "grep",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--text",
+        "binary",
+    ]))
+    .to(be_ok())
+    .to(equal("binary:1:\0binary line"));
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use common::command;
+use sqlarfs::{Connection, FileMode};
+use xpct::{be_err, be_ok, equal, expect};
+
+fn create_archive(path: &Path) -> sqlarfs::Result<()> {
+    let mut conn = Connection::create_new(path)?;
+
+    conn.exec(|archive| {
+        let mut dir = archive.open("dir")?;
+        dir.create_dir()?;
+        dir.set_mode(Some(FileMode::from_bits_truncate(0o755)))?;
+        dir.set_mtime(Some(UNIX_EPOCH + Duration::from_secs(60)))?;
+
+        let mut file = archive.open("dir/file")?;
+        file.create_file()?;
+        file.write_str("contents")?;
+        file.set_mode(Some(FileMode::from_bits_truncate(0o644)))?;
+        file.set_mtime(None)?;
+
+        sqlarfs::Result::Ok(())
+    })
+}
+
+#[test]
+fn errors_when_archive_does_not_exist() -> eyre::Result<()> {
+    expect!(command(&["index", "--archive", "nonexistent.sqlar"])).to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn index_defaults_to_csv() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    let expected = [
+        "path,type,size,mode,mtime,target",
+        "dir,dir,,493,60,",
+        "dir/file,file,8,420,,",
+    ];
+
+    expect!(command(&[
+        "index",
+        "--archive",
+        &archive_path.to_string_lossy()
+    ]))
+    .to(be_ok())
+    .to(equal(expected.join("\n")));
+
+    Ok(())
+}
+
+#[test]
+fn index_as_json_lines() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    let expected = [
+        r#"{"path":"dir","type":"dir","size":null,"mode":493,"mtime":60,"target":null}"#,
+        r#"{"path":"dir/file","type":"file","size":8,"mode":420,"mtime":null,"target":null}"#,
+    ];
+
+    expect!(command(&[
+        "index",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--format",
+        "jsonl",
+    ]))
+    .to(be_ok())
+    .to(equal(expected.join("\n")));
+
+    Ok(())
+}
@@ -2,7 +2,7 @@ mod common;
 
 use common::command;
 use sqlarfs::Connection;
-use xpct::{be_err, be_ok, consist_of, expect};
+use xpct::{be_err, be_ok, consist_of, equal, expect};
 
 #[test]
 fn errors_when_archive_does_not_exist() -> eyre::Result<()> {
@@ -171,3 +171,71 @@ fn listing_files_by_type() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn listing_files_sorted_by_name() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    let mut conn = Connection::create_new(&archive_path)?;
+
+    conn.exec(|archive| {
+        archive.open("file10")?.create_file()?;
+        archive.open("file2")?.create_file()?;
+        archive.open("file1")?.create_file()?;
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    expect!(command(&[
+        "list",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--sort",
+        "name",
+    ]))
+    .to(be_ok())
+    .map(|output| output.split('\n').map(String::from).collect::<Vec<_>>())
+    .to(equal(vec![
+        String::from("file1"),
+        String::from("file2"),
+        String::from("file10"),
+    ]));
+
+    Ok(())
+}
+
+#[test]
+fn listing_files_with_preview() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    let mut conn = Connection::create_new(&archive_path)?;
+
+    conn.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("first line\nsecond line")?;
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    expect!(command(&[
+        "list",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--sort",
+        "name",
+        "--preview=13",
+    ]))
+    .to(be_ok())
+    .map(|output| output.split('\n').map(String::from).collect::<Vec<_>>())
+    .to(equal(vec![
+        String::from("dir"),
+        String::from("file\tfirst line\\nse"),
+    ]));
+
+    Ok(())
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use std::path::Path;
+
+use common::command;
+use sqlarfs::Connection;
+use xpct::{be_err, be_false, be_ok, be_true, equal, expect};
+
+// Create an archive with a file whose parent directory is missing and a file whose path has a
+// trailing slash, like an archive created by a buggy tool.
+fn create_archive(path: &Path) -> eyre::Result<()> {
+    Connection::create_new(path)?;
+
+    let raw_conn = rusqlite::Connection::open(path)?;
+
+    for name in ["dir/file", "other/"] {
+        raw_conn.execute(
+            "INSERT INTO sqlar (name, mode, sz, data) VALUES (?1, ?2, 0, zeroblob(0))",
+            (name, 0o100644),
+        )?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn errors_when_archive_does_not_exist() -> eyre::Result<()> {
+    expect!(command(&["repair", "--archive", "nonexistent.sqlar"])).to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn reporting_problems_without_fixing_them() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    let expected = [
+        "PROBLEM                BEFORE    AFTER",
+        "missing directories         1        1",
+        "non-dir parents             0        0",
+        "bad paths                   1        1",
+        "duplicate paths             0        0",
+        "negative sizes              0        0",
+        "corrupt files               0        0",
+    ];
+
+    expect!(command(&[
+        "repair",
+        "--archive",
+        &archive_path.to_string_lossy()
+    ]))
+    .to(be_ok())
+    .to(equal(expected.join("\n")));
+
+    Ok(())
+}
+
+#[test]
+fn fixing_dirs_and_paths() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    let expected = [
+        "PROBLEM                BEFORE    AFTER",
+        "missing directories         1        0",
+        "non-dir parents             0        0",
+        "bad paths                   1        0",
+        "duplicate paths             0        0",
+        "negative sizes              0        0",
+        "corrupt files               0        0",
+    ];
+
+    expect!(command(&[
+        "repair",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--fix-dirs",
+        "--fix-paths",
+    ]))
+    .to(be_ok())
+    .to(equal(expected.join("\n")));
+
+    let mut conn = Connection::open(&archive_path)?;
+
+    conn.exec(|archive| {
+        expect!(archive.open("dir")?.exists())
+            .to(be_ok())
+            .to(be_true());
+        expect!(archive.open("other")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn removing_orphans() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "repair",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--remove-orphans",
+    ]))
+    .to(be_ok());
+
+    let mut conn = Connection::open(&archive_path)?;
+
+    conn.exec(|archive| {
+        expect!(archive.open("dir/file")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    Ok(())
+}
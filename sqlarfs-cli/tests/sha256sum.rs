@@ -0,0 +1,172 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::command;
+use sqlarfs::Connection;
+use xpct::{be_err, be_ok, equal, expect};
+
+const CONTENTS1_SHA256: &str = "809da78733fb34d7548ff1a8abe962ec865f8db07820e00f7a61ba79e2b6ff9f";
+const CONTENTS2_SHA256: &str = "869ed4d9645d8f65f6650ff3e987e335183c02ebed99deccea2917c6fd7be006";
+
+fn create_archive(path: &Path) -> sqlarfs::Result<()> {
+    let mut conn = Connection::create_new(path)?;
+
+    conn.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let mut file = archive.open("dir/file1")?;
+        file.create_file()?;
+        file.write_str("contents1")?;
+
+        let mut file = archive.open("file2")?;
+        file.create_file()?;
+        file.write_str("contents2")?;
+
+        sqlarfs::Result::Ok(())
+    })
+}
+
+#[test]
+fn errors_when_archive_does_not_exist() -> eyre::Result<()> {
+    expect!(command(&["sha256sum", "--archive", "nonexistent.sqlar"])).to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn printing_checksums_of_whole_archive() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "sha256sum",
+        "--archive",
+        &archive_path.to_string_lossy(),
+    ]))
+    .to(be_ok())
+    .to(equal(format!(
+        "{CONTENTS1_SHA256}  dir/file1\n{CONTENTS2_SHA256}  file2"
+    )));
+
+    Ok(())
+}
+
+#[test]
+fn printing_checksums_of_specific_paths() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "sha256sum",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "file2",
+        "dir",
+    ]))
+    .to(be_ok())
+    .to(equal(format!(
+        "{CONTENTS2_SHA256}  file2\n{CONTENTS1_SHA256}  dir/file1"
+    )));
+
+    Ok(())
+}
+
+#[test]
+fn printing_checksums_of_nonexistent_path_errors() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "sha256sum",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "nonexistent",
+    ]))
+    .to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn checking_checksums_that_match() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+    let checksum_path = temp_dir.path().join("SHA256SUMS");
+
+    create_archive(&archive_path)?;
+
+    fs::write(
+        &checksum_path,
+        format!("{CONTENTS1_SHA256}  dir/file1\n{CONTENTS2_SHA256} *file2\n"),
+    )?;
+
+    expect!(command(&[
+        "sha256sum",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--check",
+        &checksum_path.to_string_lossy(),
+    ]))
+    .to(be_ok())
+    .to(equal("dir/file1: OK\nfile2: OK"));
+
+    Ok(())
+}
+
+#[test]
+fn checking_checksums_that_do_not_match_errors() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+    let checksum_path = temp_dir.path().join("SHA256SUMS");
+
+    create_archive(&archive_path)?;
+
+    fs::write(
+        &checksum_path,
+        format!("{CONTENTS2_SHA256}  dir/file1\n{CONTENTS1_SHA256}  nonexistent\n"),
+    )?;
+
+    let err = command(&[
+        "sha256sum",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--check",
+        &checksum_path.to_string_lossy(),
+    ])
+    .unwrap_err();
+
+    expect!(err.downcast_ref::<sqlarfs_cli::ChecksumMismatch>())
+        .to(equal(Some(&sqlarfs_cli::ChecksumMismatch { failed: 2 })));
+
+    Ok(())
+}
+
+#[test]
+fn checking_malformed_checksum_file_errors() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+    let checksum_path = temp_dir.path().join("SHA256SUMS");
+
+    create_archive(&archive_path)?;
+
+    fs::write(&checksum_path, "not a checksum line\n")?;
+
+    expect!(command(&[
+        "sha256sum",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--check",
+        &checksum_path.to_string_lossy(),
+    ]))
+    .to(be_err());
+
+    Ok(())
+}
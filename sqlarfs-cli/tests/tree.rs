@@ -0,0 +1,153 @@
+mod common;
+
+use std::path::Path;
+
+use common::command;
+use sqlarfs::Connection;
+use xpct::{be_err, be_ok, equal, expect};
+
+fn create_archive(path: &Path) -> sqlarfs::Result<()> {
+    let mut conn = Connection::create_new(path)?;
+
+    conn.exec(|archive| {
+        archive.open("dir1")?.create_dir()?;
+        archive.open("dir1/file1")?.create_file()?;
+        archive.open("dir1/dir2")?.create_dir()?;
+        archive.open("dir1/dir2/file2")?.create_file()?;
+        archive.open("file3")?.create_file()?;
+        archive.open("file3")?.write_str("contents")?;
+        archive.open("symlink")?.create_symlink("file3")?;
+
+        sqlarfs::Result::Ok(())
+    })
+}
+
+#[test]
+fn errors_when_archive_does_not_exist() -> eyre::Result<()> {
+    expect!(command(&["tree", "--archive", "nonexistent.sqlar"])).to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn errors_when_path_does_not_exist() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "tree",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "nonexistent",
+    ]))
+    .to(be_err());
+
+    Ok(())
+}
+
+#[test]
+fn printing_tree_of_whole_archive() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "tree",
+        "--archive",
+        &archive_path.to_string_lossy()
+    ]))
+    .to(be_ok())
+    .to(equal(
+        [
+            ".",
+            "|-- dir1",
+            "|   |-- dir2",
+            "|   |   `-- file2",
+            "|   `-- file1",
+            "|-- file3",
+            "`-- symlink -> file3",
+        ]
+        .join("\n"),
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn printing_tree_of_directory() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "tree",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "dir1",
+    ]))
+    .to(be_ok())
+    .to(equal(
+        ["dir1", "|-- dir2", "|   `-- file2", "`-- file1"].join("\n"),
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn printing_tree_with_depth_limit() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "tree",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--depth",
+        "1",
+    ]))
+    .to(be_ok())
+    .to(equal(
+        [".", "|-- dir1", "|-- file3", "`-- symlink -> file3"].join("\n"),
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn printing_tree_with_sizes() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("test.sqlar");
+
+    create_archive(&archive_path)?;
+
+    expect!(command(&[
+        "tree",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--size",
+        "dir1/dir2",
+    ]))
+    .to(be_ok())
+    .to(equal(["dir1/dir2", "`-- [0] file2"].join("\n")));
+
+    expect!(command(&[
+        "tree",
+        "--archive",
+        &archive_path.to_string_lossy(),
+        "--size",
+        "--depth",
+        "1",
+    ]))
+    .to(be_ok())
+    .to(equal(
+        [".", "|-- dir1", "|-- [8] file3", "`-- symlink -> file3"].join("\n"),
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,298 @@
+//! Benchmarks for archiving, extracting, reading, and listing files.
+//!
+//! Run these with `cargo bench -p sqlarfs`. Fixtures are generated each time the benchmarks run,
+//! so the listing benchmark takes a while to start.
+
+// `criterion_group!` generates a public function we can't document.
+#![allow(missing_docs)]
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rand::rngs::SmallRng;
+use rand::{RngCore, SeedableRng};
+use sqlarfs::{Compression, Connection};
+use tempfile::TempDir;
+
+const SMALL_FILE_COUNT: u64 = 1_000;
+const SMALL_FILE_SIZE: usize = 1024;
+const LARGE_FILE_SIZE: usize = 64 * 1024 * 1024;
+const LIST_ROW_COUNT: u64 = 1_000_000;
+const STREAM_SIZE: u64 = 64 * 1024 * 1024;
+
+// Random bytes don't compress, so this is half random and half zeroes to give compression
+// something to do.
+fn file_contents(rng: &mut SmallRng, size: usize) -> Vec<u8> {
+    let mut contents = vec![0u8; size];
+    rng.fill_bytes(&mut contents[..size / 2]);
+    contents
+}
+
+// Create a directory of small files on disk.
+fn small_files_dir() -> io::Result<TempDir> {
+    let temp_dir = tempfile::tempdir()?;
+    let mut rng = SmallRng::seed_from_u64(0);
+
+    for i in 0..SMALL_FILE_COUNT {
+        // Spread the files across a few directories so we're not only benchmarking a flat tree.
+        let dir = temp_dir.path().join(format!("dir{}", i % 10));
+        fs::create_dir_all(&dir)?;
+        fs::write(
+            dir.join(format!("file{i}")),
+            file_contents(&mut rng, SMALL_FILE_SIZE),
+        )?;
+    }
+
+    Ok(temp_dir)
+}
+
+// Create a single large file on disk.
+fn large_file() -> io::Result<tempfile::NamedTempFile> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    let mut rng = SmallRng::seed_from_u64(0);
+
+    // Write it in chunks so we don't need the whole file in memory.
+    for _ in 0..(LARGE_FILE_SIZE / SMALL_FILE_SIZE) {
+        file.write_all(&file_contents(&mut rng, SMALL_FILE_SIZE))?;
+    }
+
+    file.flush()?;
+
+    Ok(file)
+}
+
+// A reader that generates `len` bytes of compressible data without keeping it all in memory.
+struct CompressibleReader {
+    rng: SmallRng,
+    chunk: Vec<u8>,
+    pos: usize,
+    remaining: u64,
+}
+
+impl CompressibleReader {
+    fn new(len: u64) -> Self {
+        Self {
+            rng: SmallRng::seed_from_u64(0),
+            chunk: Vec::new(),
+            pos: 0,
+            remaining: len,
+        }
+    }
+}
+
+impl Read for CompressibleReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            let chunk_len = self.remaining.min(64 * 1024) as usize;
+            self.chunk = file_contents(&mut self.rng, chunk_len);
+            self.pos = 0;
+        }
+
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        self.remaining -= len as u64;
+
+        Ok(len)
+    }
+}
+
+// Create an archive on disk with `count` empty files in it.
+fn archive_with_rows(dir: &Path, count: u64) -> sqlarfs::Result<Connection> {
+    let mut conn = Connection::create_new(dir.join("list.sqlar"))?;
+
+    conn.exec(|archive| {
+        for i in 0..count {
+            archive.open(format!("file{i}"))?.create_file()?;
+        }
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    Ok(conn)
+}
+
+fn bench_archive_small_files(c: &mut Criterion) {
+    let source = small_files_dir().unwrap();
+
+    let mut group = c.benchmark_group("archive_small_files");
+    group.throughput(Throughput::Elements(SMALL_FILE_COUNT));
+
+    group.bench_function("archive_tree", |b| {
+        b.iter_batched(
+            || Connection::open_in_memory().unwrap(),
+            |mut conn| {
+                conn.exec(|archive| archive.archive(source.path(), "source"))
+                    .unwrap();
+                conn
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+}
+
+fn bench_extract_small_files(c: &mut Criterion) {
+    let source = small_files_dir().unwrap();
+    let mut conn = Connection::open_in_memory().unwrap();
+
+    conn.exec(|archive| archive.archive(source.path(), "source"))
+        .unwrap();
+
+    let mut group = c.benchmark_group("extract_small_files");
+    group.throughput(Throughput::Elements(SMALL_FILE_COUNT));
+
+    group.bench_function("extract_tree", |b| {
+        b.iter_batched(
+            || tempfile::tempdir().unwrap(),
+            |dest| {
+                conn.exec(|archive| archive.extract("source", dest.path().join("source")))
+                    .unwrap();
+                dest
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+}
+
+fn bench_large_file(c: &mut Criterion) {
+    let source = large_file().unwrap();
+
+    let mut group = c.benchmark_group("large_file");
+    group.throughput(Throughput::Bytes(LARGE_FILE_SIZE as u64));
+    group.sample_size(10);
+
+    let methods = [
+        ("stored", Some(Compression::None)),
+        // Whatever the default is for the enabled features.
+        ("default", None),
+    ];
+
+    for (name, method) in methods {
+        group.bench_with_input(BenchmarkId::new("write", name), &method, |b, method| {
+            b.iter_batched(
+                || Connection::open_in_memory().unwrap(),
+                |mut conn| {
+                    conn.exec(|archive| {
+                        let mut file = archive.open("file")?;
+                        file.create_file()?;
+
+                        if let Some(method) = method {
+                            file.set_compression(*method);
+                        }
+
+                        file.write_file(&mut fs::File::open(source.path())?)
+                    })
+                    .unwrap();
+                    conn
+                },
+                BatchSize::PerIteration,
+            )
+        });
+
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        conn.exec(|archive| {
+            let mut file = archive.open("file")?;
+            file.create_file()?;
+
+            if let Some(method) = method {
+                file.set_compression(method);
+            }
+
+            file.write_file(&mut fs::File::open(source.path())?)
+        })
+        .unwrap();
+
+        group.bench_function(BenchmarkId::new("read", name), |b| {
+            b.iter(|| {
+                conn.exec(|archive| {
+                    let mut file = archive.open("file")?;
+                    let mut reader = file.reader()?;
+                    let mut buf = vec![0u8; 64 * 1024];
+
+                    // Read into a fixed buffer so we're not benchmarking allocations.
+                    while reader.read(&mut buf)? > 0 {}
+
+                    sqlarfs::Result::Ok(())
+                })
+                .unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+// Stream a large amount of compressible data of an unknown size into a file using the default
+// compression for the enabled features. The archive is on disk so the compressed data doesn't all
+// have to fit in memory.
+fn bench_compressed_stream(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compressed_stream");
+    group.throughput(Throughput::Bytes(STREAM_SIZE));
+    group.sample_size(10);
+
+    group.bench_function("write_from", |b| {
+        b.iter_batched(
+            || {
+                let temp_dir = tempfile::tempdir().unwrap();
+                let conn = Connection::create_new(temp_dir.path().join("stream.sqlar")).unwrap();
+                (temp_dir, conn)
+            },
+            |(temp_dir, mut conn)| {
+                conn.exec(|archive| {
+                    let mut file = archive.open("file")?;
+                    file.create_file()?;
+                    file.write_from(&mut CompressibleReader::new(STREAM_SIZE))
+                })
+                .unwrap();
+                (temp_dir, conn)
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+}
+
+fn bench_list(c: &mut Criterion) {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut conn = archive_with_rows(temp_dir.path(), LIST_ROW_COUNT).unwrap();
+
+    let mut group = c.benchmark_group("list");
+    group.throughput(Throughput::Elements(LIST_ROW_COUNT));
+    group.sample_size(10);
+
+    group.bench_function("list", |b| {
+        b.iter(|| {
+            conn.exec(|archive| {
+                let mut count = 0u64;
+
+                for entry in archive.list()? {
+                    entry?;
+                    count += 1;
+                }
+
+                sqlarfs::Result::Ok(count)
+            })
+            .unwrap()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_archive_small_files,
+    bench_extract_small_files,
+    bench_large_file,
+    bench_compressed_stream,
+    bench_list
+);
+criterion_main!(benches);
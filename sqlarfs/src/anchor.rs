@@ -0,0 +1,304 @@
+use std::fs;
+use std::path::Path;
+#[cfg(unix)]
+use std::path::PathBuf;
+
+use super::metadata::FileMode;
+
+// Whether the file `name` in the directory `dir` is a symlink.
+#[cfg(unix)]
+fn is_symlink_at(dir: &std::os::fd::OwnedFd, name: &std::ffi::OsStr) -> bool {
+    use rustix::fs::{statat, AtFlags, FileType};
+
+    statat(dir, name, AtFlags::SYMLINK_NOFOLLOW)
+        .is_ok_and(|stat| FileType::from_raw_mode(stat.st_mode) == FileType::Symlink)
+}
+
+// A directory that files are created under relative to a file descriptor, using `openat(2)` and
+// friends.
+//
+// The directory is opened once, and each path under it is resolved one component at a time
+// without following symlinks. This means that swapping a directory for a symlink partway through
+// extracting can't redirect where files are written.
+#[derive(Debug)]
+pub(super) struct AnchoredDir {
+    // The path the directory was opened at. Paths passed to the methods on this type must start
+    // with it.
+    #[cfg(unix)]
+    path: PathBuf,
+    #[cfg(unix)]
+    fd: std::os::fd::OwnedFd,
+    #[cfg(not(unix))]
+    never: std::convert::Infallible,
+}
+
+#[cfg(unix)]
+impl AnchoredDir {
+    pub fn open(path: &Path) -> crate::Result<Self> {
+        use rustix::fs::{open, Mode, OFlags};
+
+        let dir = if path == Path::new("") {
+            Path::new(".")
+        } else {
+            path
+        };
+
+        let fd = open(
+            dir,
+            OFlags::DIRECTORY | OFlags::RDONLY | OFlags::CLOEXEC,
+            Mode::empty(),
+        )
+        .map_err(|err| match err {
+            rustix::io::Errno::NOENT => crate::Error::FileNotFound { path: path.into() },
+            rustix::io::Errno::NOTDIR => crate::Error::NotADirectory { path: path.into() },
+            _ => std::io::Error::from(err).into(),
+        })?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            fd,
+        })
+    }
+
+    fn map_err(err: rustix::io::Errno, path: &Path) -> crate::Error {
+        use rustix::io::Errno;
+
+        match err {
+            Errno::EXIST => crate::Error::FileAlreadyExists { path: path.into() },
+            Errno::NOENT | Errno::NOTDIR => crate::Error::NoParentDirectory { path: path.into() },
+            // We never follow symlinks, so this means something in the way is a symlink.
+            Errno::LOOP => crate::Error::PathEscapesRoot { path: path.into() },
+            _ => std::io::Error::from(err).into(),
+        }
+    }
+
+    // Open the parent directory of `path`, returning it along with the file name of `path`.
+    fn open_parent<'a>(
+        &self,
+        path: &'a Path,
+    ) -> crate::Result<(std::os::fd::OwnedFd, &'a std::ffi::OsStr)> {
+        use rustix::fs::{openat, Mode, OFlags};
+        use rustix::io::Errno;
+        use std::path::Component;
+
+        let escapes_root = || crate::Error::PathEscapesRoot { path: path.into() };
+
+        let mut components = path
+            .strip_prefix(&self.path)
+            .map_err(|_| escapes_root())?
+            .components();
+
+        let Some(Component::Normal(file_name)) = components.next_back() else {
+            return Err(escapes_root());
+        };
+
+        let mut dir = self.fd.try_clone()?;
+
+        for component in components {
+            let Component::Normal(name) = component else {
+                return Err(escapes_root());
+            };
+
+            dir = match openat(
+                &dir,
+                name,
+                OFlags::DIRECTORY | OFlags::NOFOLLOW | OFlags::RDONLY | OFlags::CLOEXEC,
+                Mode::empty(),
+            ) {
+                Ok(fd) => fd,
+                // Depending on the platform, opening a symlink with these flags fails with either
+                // `ELOOP` or `ENOTDIR`, the latter of which is ambiguous.
+                Err(Errno::NOTDIR) if is_symlink_at(&dir, name) => return Err(escapes_root()),
+                Err(err) => return Err(Self::map_err(err, path)),
+            };
+        }
+
+        Ok((dir, file_name))
+    }
+
+    // Create a new regular file at `path` and open it for writing.
+    pub fn create_file(&self, path: &Path) -> crate::Result<fs::File> {
+        use rustix::fs::{openat, Mode, OFlags};
+
+        let (parent, file_name) = self.open_parent(path)?;
+
+        let fd = openat(
+            &parent,
+            file_name,
+            OFlags::CREATE | OFlags::EXCL | OFlags::NOFOLLOW | OFlags::WRONLY | OFlags::CLOEXEC,
+            Mode::from_bits_truncate(0o666),
+        )
+        .map_err(|err| Self::map_err(err, path))?;
+
+        Ok(fs::File::from(fd))
+    }
+
+    // Create a new directory at `path`.
+    pub fn create_dir(&self, path: &Path) -> crate::Result<()> {
+        use rustix::fs::{mkdirat, Mode};
+
+        let (parent, file_name) = self.open_parent(path)?;
+
+        mkdirat(&parent, file_name, Mode::from_bits_truncate(0o777))
+            .map_err(|err| Self::map_err(err, path))?;
+
+        Ok(())
+    }
+
+    // Create a new symbolic link at `path` pointing to `target`.
+    pub fn create_symlink(&self, target: &Path, path: &Path) -> crate::Result<()> {
+        use rustix::fs::symlinkat;
+
+        let (parent, file_name) = self.open_parent(path)?;
+
+        symlinkat(target, &parent, file_name).map_err(|err| Self::map_err(err, path))?;
+
+        Ok(())
+    }
+
+    // Set the mode of the directory at `path`.
+    pub fn set_dir_mode(&self, path: &Path, mode: FileMode) -> crate::Result<()> {
+        use rustix::fs::{openat, Mode, OFlags};
+
+        let (parent, file_name) = self.open_parent(path)?;
+
+        let fd = openat(
+            &parent,
+            file_name,
+            OFlags::DIRECTORY | OFlags::NOFOLLOW | OFlags::RDONLY | OFlags::CLOEXEC,
+            Mode::empty(),
+        )
+        .map_err(|err| Self::map_err(err, path))?;
+
+        self.set_file_mode(&fs::File::from(fd), mode)
+    }
+
+    // Set the mode of a file we have open.
+    pub fn set_file_mode(&self, file: &fs::File, mode: FileMode) -> crate::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        file.set_permissions(fs::Permissions::from_mode(mode.bits()))?;
+
+        Ok(())
+    }
+}
+
+// This is only supported on Unix-like systems. `ExtractOptions::validate` rejects it everywhere
+// else, so none of these are ever called.
+#[cfg(not(unix))]
+impl AnchoredDir {
+    pub fn open(path: &Path) -> crate::Result<Self> {
+        Err(crate::Error::InvalidArgs {
+            reason: format!(
+                "Extracting relative to a directory file descriptor is only supported on Unix-like systems: {}",
+                path.display()
+            ),
+        })
+    }
+
+    pub fn create_file(&self, _path: &Path) -> crate::Result<fs::File> {
+        match self.never {}
+    }
+
+    pub fn create_dir(&self, _path: &Path) -> crate::Result<()> {
+        match self.never {}
+    }
+
+    pub fn create_symlink(&self, _target: &Path, _path: &Path) -> crate::Result<()> {
+        match self.never {}
+    }
+
+    pub fn set_dir_mode(&self, _path: &Path, _mode: FileMode) -> crate::Result<()> {
+        match self.never {}
+    }
+
+    pub fn set_file_mode(&self, _file: &fs::File, _mode: FileMode) -> crate::Result<()> {
+        match self.never {}
+    }
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::symlink;
+
+    use xpct::{be_err, be_ok, equal, expect};
+
+    #[test]
+    fn creating_file_through_swapped_symlink_errors() -> crate::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().join("root");
+        let outside = temp_dir.path().join("outside");
+
+        fs::create_dir(&root)?;
+        fs::create_dir(&outside)?;
+
+        let anchor = AnchoredDir::open(&root)?;
+
+        anchor.create_dir(&root.join("dir"))?;
+
+        // Swap the directory we just created for a symlink pointing outside of the root.
+        fs::remove_dir(root.join("dir"))?;
+        symlink(&outside, root.join("dir"))?;
+
+        expect!(anchor.create_file(&root.join("dir/file")))
+            .to(be_err())
+            .to(equal(crate::Error::PathEscapesRoot {
+                path: root.join("dir/file"),
+            }));
+
+        expect!(fs::read_dir(&outside)?.count()).to(equal(0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn creating_file_outside_of_root_errors() -> crate::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().join("root");
+
+        fs::create_dir(&root)?;
+
+        let anchor = AnchoredDir::open(&root)?;
+
+        expect!(anchor.create_file(&temp_dir.path().join("file")))
+            .to(be_err())
+            .to(equal(crate::Error::PathEscapesRoot {
+                path: temp_dir.path().join("file"),
+            }));
+
+        expect!(anchor.create_file(&root.join("../file")))
+            .to(be_err())
+            .to(equal(crate::Error::PathEscapesRoot {
+                path: root.join("../file"),
+            }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn creating_files_relative_to_root() -> crate::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path();
+
+        let anchor = AnchoredDir::open(root)?;
+
+        expect!(anchor.create_dir(&root.join("dir"))).to(be_ok());
+        expect!(anchor.create_file(&root.join("dir/file"))).to(be_ok());
+        expect!(anchor.create_symlink(Path::new("file"), &root.join("dir/symlink"))).to(be_ok());
+
+        expect!(fs::read_link(root.join("dir/symlink")))
+            .to(be_ok())
+            .to(equal(PathBuf::from("file")));
+
+        expect!(anchor.create_file(&root.join("dir/file")))
+            .to(be_err())
+            .to(equal(crate::Error::FileAlreadyExists {
+                path: root.join("dir/file"),
+            }));
+
+        Ok(())
+    }
+}
@@ -1,11 +1,35 @@
-use std::path::Path;
+use std::cell::Cell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::{ExtractOptions, FileMode};
+use crate::{Connection, ConnectionBuilder, ExtractOptions, FileMode};
 
+#[cfg(feature = "sqlcipher")]
+use super::builder::EncryptionKey;
+use super::clock::Clock;
+use super::digest::{Digest, DigestAlgorithm, DigestOptions};
+use super::external::ExternalLink;
 use super::file::File;
-use super::list::{ListEntries, ListOptions};
+use super::filter::Filter;
+use super::http::StaticResource;
+use super::import::ImportOptions;
+use super::index::IndexFormat;
+use super::inline::InlineStats;
+use super::list::{EntryId, ListCursor, ListEntries, ListEntry, ListOptions};
+use super::overlay::Overlay;
+use super::recompress::RecompressOptions;
+use super::rename::RenamePolicy;
+use super::repair::{ArchiveProblems, RepairOptions, RepairReport};
+use super::report::CompressionReport;
+use super::retention::RetentionPolicy;
+use super::settings::Settings;
 use super::store::Store;
+use super::stream::Compression;
 use super::tree::ArchiveOptions;
+use super::unicode::PathNormalization;
+use super::unnamed::{unused_path, UnnamedFile};
 
 /// A SQLite archive.
 ///
@@ -21,18 +45,50 @@ use super::tree::ArchiveOptions;
 /// All file paths in a SQLite archive are encoded using the database encoding; trying to use a
 /// path that is not valid Unicode will result in an error.
 ///
+/// Reads through an `Archive` always see the writes made earlier in the same transaction, even
+/// before it's committed. Other connections to the same database won't see those writes until the
+/// transaction commits. If you need to see the archive as it was before the current transaction,
+/// use [`Archive::snapshot_read`].
+///
 /// [`Connection::exec`]: crate::Connection::exec
 #[derive(Debug)]
 pub struct Archive<'conn> {
-    store: Store<'conn>,
+    pub(super) store: Store<'conn>,
     umask: FileMode,
+    source_date_epoch: bool,
+    compression: Compression,
+    update_mtime: bool,
+    pub(super) clock: Clock,
+    #[cfg(feature = "sqlcipher")]
+    pub(super) encryption_key: Option<EncryptionKey>,
+    lock_namespace: Arc<str>,
+    path_normalization: PathNormalization,
+    pub(super) filters: Vec<Filter>,
+    pub(super) inline_stats: Cell<InlineStats>,
 }
 
 impl<'conn> Archive<'conn> {
-    pub(super) fn new(tx: rusqlite::Transaction<'conn>) -> Self {
+    pub(super) fn new(
+        tx: rusqlite::Transaction<'conn>,
+        lock_namespace: Arc<str>,
+        table: Arc<str>,
+    ) -> Self {
         Self {
-            store: Store::new(tx),
+            store: Store::new(tx, table),
             umask: FileMode::OTHER_W,
+            source_date_epoch: false,
+            #[cfg(feature = "deflate")]
+            compression: Compression::FAST,
+            #[cfg(not(feature = "deflate"))]
+            compression: Compression::None,
+            update_mtime: false,
+            clock: Clock::default(),
+            #[cfg(feature = "sqlcipher")]
+            encryption_key: None,
+            lock_namespace,
+            path_normalization: PathNormalization::Preserve,
+            filters: Vec::new(),
+            inline_stats: Cell::new(InlineStats::default()),
         }
     }
 
@@ -50,64 +106,1182 @@ impl<'conn> Archive<'conn> {
     /// handle to a file that may or may not exist.
     ///
     /// See [`File::exists`] to check if the file actually exists in the archive.
-    pub fn open<'ar, P: AsRef<Path>>(&'ar mut self, path: P) -> crate::Result<File<'conn, 'ar>> {
-        // Opening a file must take a mutable receiver to ensure that the user can't get lwo
-        // handles to the same file. Otherwise they could do things like open the blob twice or
-        // edit the row while the blob is open.
-        File::new(path.as_ref(), &mut self.store, self.umask)
+    ///
+    /// You can hold handles to several files at once, like to copy the contents of one file into
+    /// another. However, there can only be one handle to a given file at a time; the handle must
+    /// be dropped before the file can be opened again.
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: The given `path` is empty or absolute.
+    /// - [`FileAlreadyOpen`]: There's already a handle to the file at `path`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut source = archive.open("source")?;
+    /// source.create_file()?;
+    /// source.write_str("Hello, world!")?;
+    ///
+    /// let mut dest = archive.open("dest")?;
+    /// dest.create_file()?;
+    /// dest.write_from(&mut source.reader()?)?;
+    ///
+    /// assert!(archive.open("dest").is_err());
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    /// [`FileAlreadyOpen`]: crate::Error::FileAlreadyOpen
+    pub fn open<'ar, P: AsRef<Path>>(&'ar self, path: P) -> crate::Result<File<'conn, 'ar>> {
+        let mut file = File::new(
+            &self.path_normalization.apply(path.as_ref()),
+            &self.store,
+            self.umask,
+            self.source_date_epoch,
+            Arc::clone(&self.lock_namespace),
+        )?;
+
+        file.set_compression(self.compression);
+        file.set_update_mtime(self.update_mtime);
+        file.set_clock(self.clock.clone());
+        file.set_path_normalization(self.path_normalization);
+
+        Ok(file)
+    }
+
+    /// Open a handle to the file with the given ID, or return `None` if there isn't one.
+    ///
+    /// IDs are returned by [`ListEntry::id`]. This is faster than [`Archive::open`] when you've
+    /// already listed the files, because the file is looked up by its ID instead of its path.
+    /// Unlike [`Archive::open`], this doesn't apply [`Archive::path_normalization`], because the
+    /// path comes from the archive.
+    ///
+    /// See [`EntryId`] for when an ID can be reused by another file.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileAlreadyOpen`]: There's already a handle to this file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// archive.open("file")?.create_file()?;
+    ///
+    /// for entry in archive.list()? {
+    ///     let file = archive.open_by_id(entry?.id())?.unwrap();
+    ///     assert_eq!(file.path(), std::path::Path::new("file"));
+    /// }
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileAlreadyOpen`]: crate::Error::FileAlreadyOpen
+    pub fn open_by_id<'ar>(&'ar self, id: EntryId) -> crate::Result<Option<File<'conn, 'ar>>> {
+        match self.store.path_by_rowid(id.0)? {
+            Some(path) => self.open_stored(Path::new(&path)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // Open a handle to the file at `path` without applying the path normalization form. This is
+    // for paths that come from the archive itself, which are already the way they're stored.
+    pub(super) fn open_stored<'ar>(&'ar self, path: &Path) -> crate::Result<File<'conn, 'ar>> {
+        let mut file = File::new(
+            path,
+            &self.store,
+            self.umask,
+            self.source_date_epoch,
+            Arc::clone(&self.lock_namespace),
+        )?;
+
+        file.set_compression(self.compression);
+        file.set_update_mtime(self.update_mtime);
+        file.set_clock(self.clock.clone());
+        file.set_path_normalization(self.path_normalization);
+
+        Ok(file)
+    }
+
+    // Open a file without claiming its path. This is for methods that only need a handle for the
+    // duration of the call to read the file, and shouldn't fail because the user has the same file
+    // open. Methods that change the file should use `open_unclaimed_mut` instead.
+    pub(super) fn open_unclaimed<'ar, P: AsRef<Path>>(
+        &'ar self,
+        path: P,
+    ) -> crate::Result<File<'conn, 'ar>> {
+        let mut file = File::new_unclaimed(
+            &self.path_normalization.apply(path.as_ref()),
+            &self.store,
+            self.umask,
+            self.source_date_epoch,
+            Arc::clone(&self.lock_namespace),
+        )?;
+
+        file.set_compression(self.compression);
+        file.set_update_mtime(self.update_mtime);
+        file.set_clock(self.clock.clone());
+        file.set_path_normalization(self.path_normalization);
+
+        Ok(file)
+    }
+
+    // Open a file that this call is going to create, change, or delete without claiming its path.
+    // Unlike `open_unclaimed`, this fails if the user has a handle to the file, so we don't change
+    // it out from under them.
+    pub(super) fn open_unclaimed_mut<'ar, P: AsRef<Path>>(
+        &'ar self,
+        path: P,
+    ) -> crate::Result<File<'conn, 'ar>> {
+        let file = self.open_unclaimed(path)?;
+        self.store.check_not_open(file.path().to_str())?;
+        Ok(file)
+    }
+
+    /// Create handles to the files at each of the given `paths`.
+    ///
+    /// This is the same as calling [`Archive::open`] for each path. The handles share this
+    /// archive's transaction and its cache of prepared statements, so opening and using many
+    /// files this way doesn't pay a setup cost for each one.
+    ///
+    /// Deleting or renaming a directory through one handle affects the files under it, so other
+    /// handles to those files will see that they no longer exist.
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: One of the paths is empty or absolute.
+    /// - [`FileAlreadyOpen`]: The same path was passed more than once, or there's already a
+    ///   handle to one of the files.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// for mut file in archive.open_many(["first", "second", "third"])? {
+    ///     file.create_file()?;
+    /// }
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    /// [`FileAlreadyOpen`]: crate::Error::FileAlreadyOpen
+    pub fn open_many<'ar, I, P>(&'ar self, paths: I) -> crate::Result<Vec<File<'conn, 'ar>>>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        paths.into_iter().map(|path| self.open(path)).collect()
+    }
+
+    /// Create a new regular file that doesn't have a path yet.
+    ///
+    /// The file becomes visible at a path once you call [`UnnamedFile::persist`]. If the
+    /// [`UnnamedFile`] is dropped without being persisted, it's deleted. This is the archive
+    /// equivalent of writing to a temporary file and then renaming it into place.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let mut archive = tx.archive_mut();
+    /// let mut file = archive.create_unnamed()?;
+    /// file.write_str("Hello, world!")?;
+    /// file.persist("file")?;
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    pub fn create_unnamed(&self) -> crate::Result<UnnamedFile<'conn, '_>> {
+        let path = unused_path(&self.store)?;
+        let path_normalization = self.path_normalization;
+        let mut file = self.open(path)?;
+        file.create_file()?;
+
+        Ok(UnnamedFile::new(file, path_normalization))
     }
 
     /// Return an iterator over the files in this archive.
     ///
     /// This is the same as [`Archive::list_with`], but using the default options.
-    pub fn list(&mut self) -> crate::Result<ListEntries> {
+    pub fn list(&self) -> crate::Result<ListEntries<'_>> {
         self.store.list_files(&ListOptions::new())
     }
 
-    /// Return an iterator over the files in this archive.
+    /// Return an iterator over the files in this archive.
+    ///
+    /// This accepts a [`ListOptions`] to sort and filter the results.
+    ///
+    /// This returns an error if mutually exclusive options were specified together in
+    /// [`ListOptions`].
+    ///
+    /// The returned iterator reads from the archive lazily, so it's unspecified whether changes
+    /// you make to the archive while iterating over it show up in the results. If you need to do
+    /// that, use [`Archive::list_cursor`].
+    ///
+    /// # Examples
+    ///
+    /// List the regular files that are descendants of `parent/dir` in descending order by size.
+    ///
+    /// ```
+    /// # use sqlarfs::{ListOptions, Connection, FileMetadata};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let mut archive = tx.archive_mut();
+    /// let opts = ListOptions::new().by_size().desc().descendants_of("parent/dir");
+    ///
+    /// for result in archive.list_with(&opts)? {
+    ///     let entry = result?;
+    ///     let path = entry.path();
+    ///
+    ///     if let FileMetadata::File { size, .. } = entry.metadata() {
+    ///         println!("{}: {}", path.to_string_lossy(), size);
+    ///     }
+    /// }
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    pub fn list_with(&self, opts: &ListOptions) -> crate::Result<ListEntries<'_>> {
+        if opts.is_invalid {
+            return Err(crate::Error::InvalidArgs {
+                reason: String::from(
+                    "Mutually exclusive options where used together in `ListOptions`.",
+                ),
+            });
+        }
+
+        self.store.list_files(opts)
+    }
+
+    /// Return a cursor over the files in this archive that tolerates modifying the archive while
+    /// iterating.
+    ///
+    /// The [`ListEntries`] returned by [`Archive::list_with`] reads from the archive lazily, so
+    /// it's unspecified whether it sees changes made while iterating. This instead takes a
+    /// snapshot of the matching files up front and returns a [`ListCursor`], which you can advance
+    /// with [`ListCursor::next`] in between modifying the archive. See [`ListCursor`] for what
+    /// this guarantees.
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: Mutually exclusive options were specified together in [`ListOptions`].
+    ///
+    /// # Examples
+    ///
+    /// Delete every empty regular file in the archive.
+    ///
+    /// ```
+    /// # use sqlarfs::{Connection, FileMetadata, FileType, ListOptions};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let mut archive = tx.archive_mut();
+    /// let opts = ListOptions::new().file_type(FileType::File);
+    /// let mut cursor = archive.list_cursor(&opts)?;
+    ///
+    /// while let Some(entry) = cursor.next(archive) {
+    ///     let entry = entry?;
+    ///
+    ///     if let FileMetadata::File { size: 0, .. } = entry.metadata() {
+    ///         archive.open(entry.path())?.delete()?;
+    ///     }
+    /// }
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    pub fn list_cursor(&self, opts: &ListOptions) -> crate::Result<ListCursor> {
+        let paths = self
+            .list_with(opts)?
+            .map(|entry| entry.map(ListEntry::into_path))
+            .collect::<crate::Result<Vec<_>>>()?;
+
+        Ok(ListCursor::new(paths))
+    }
+
+    /// Write an index of every file in this archive and its metadata to `writer`.
+    ///
+    /// This writes one entry per file, with its path, file type, size, mode, mtime, and symlink
+    /// target, in the given [`IndexFormat`]. Parents are written before their children. This is
+    /// useful for taking an inventory of a large archive to analyze with other tools.
+    ///
+    /// The size is the uncompressed size in bytes, the mode is the file mode as an integer, and
+    /// the mtime is the number of seconds since the Unix epoch.
+    ///
+    /// This returns the number of entries that were written.
+    ///
+    /// # Errors
+    ///
+    /// - [`Io`]: There was an error writing to `writer`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{Connection, IndexFormat};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let mut archive = tx.archive_mut();
+    /// archive.open("dir")?.create_dir()?;
+    ///
+    /// let mut index = Vec::new();
+    /// archive.export_index(&mut index, IndexFormat::JsonLines)?;
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`Io`]: crate::Error::Io
+    pub fn export_index<W: Write>(&self, mut writer: W, format: IndexFormat) -> crate::Result<u64> {
+        self.write_index(&mut writer, format)
+    }
+
+    /// Delete all the files in this archive that match the given [`ListOptions`].
+    ///
+    /// This accepts the same filters as [`Archive::list_with`] and deletes every matching file in
+    /// a single statement. If a directory matches, all its descendants are deleted as well. Sort
+    /// options like [`ListOptions::by_size`] can't be used, since they would change which files
+    /// match.
+    ///
+    /// This returns the total number of files that were deleted, including descendants of
+    /// matching directories.
+    ///
+    /// If you don't specify any filters, this deletes every file in the archive.
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: Mutually exclusive options were specified together in [`ListOptions`],
+    ///   a sort option was specified, or one of the given mtimes is before the Unix epoch.
+    /// - [`FilePinned`]: One of the files that would be deleted is pinned with [`File::pin`], or
+    ///   is a directory that contains a pinned file. Nothing is deleted.
+    /// - [`FileInUse`]: One of the files that would be deleted has an open [`File`] handle. Nothing
+    ///   is deleted.
+    ///
+    /// # Examples
+    ///
+    /// Delete all the `.tmp` files in the archive.
+    ///
+    /// ```
+    /// # use sqlarfs::{ListOptions, Connection};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let mut archive = tx.archive_mut();
+    /// let num_deleted = archive.delete_matching(&ListOptions::new().glob("*.tmp"))?;
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    /// [`FilePinned`]: crate::Error::FilePinned
+    /// [`FileInUse`]: crate::Error::FileInUse
+    pub fn delete_matching(&self, opts: &ListOptions) -> crate::Result<u64> {
+        if opts.is_invalid {
+            return Err(crate::Error::InvalidArgs {
+                reason: String::from(
+                    "Mutually exclusive options where used together in `ListOptions`.",
+                ),
+            });
+        }
+
+        if opts.sort.is_some() || opts.direction.is_some() {
+            return Err(crate::Error::InvalidArgs {
+                reason: String::from("Sort options can't be used when deleting files."),
+            });
+        }
+
+        self.store.exec(|store| store.delete_files(opts))
+    }
+
+    /// Delete old files from this archive according to a [`RetentionPolicy`].
+    ///
+    /// This is meant for archives that accumulate files over time, like logs or snapshots, where
+    /// only recent files need to be kept. All the files are deleted in a single statement.
+    ///
+    /// Only files of the policy's [`RetentionPolicy::file_type`] are pruned, which is regular files
+    /// by default. This means directories and symbolic links are never pruned unless you ask for
+    /// them. When pruning directories, all their descendants are deleted as well.
+    ///
+    /// Files that are pinned with [`File::pin`] are kept, and so are directories that contain
+    /// pinned files, unless you pass [`RetentionPolicy::force`]. Pinned files still count toward
+    /// [`RetentionPolicy::keep_newest`].
+    ///
+    /// This returns the total number of files that were deleted, including descendants of pruned
+    /// directories.
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: The cutoff passed to [`RetentionPolicy::older_than`] is before the Unix
+    ///   epoch.
+    /// - [`FileInUse`]: One of the files that would be deleted has an open [`File`] handle. Nothing
+    ///   is deleted.
+    ///
+    /// # Examples
+    ///
+    /// Keep only the 10 newest files in the `logs` directory.
+    ///
+    /// ```
+    /// # use sqlarfs::{RetentionPolicy, Connection};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let mut archive = tx.archive_mut();
+    /// let policy = RetentionPolicy::new().keep_newest(10).descendants_of("logs");
+    /// let num_deleted = archive.prune(&policy)?;
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    /// [`FileInUse`]: crate::Error::FileInUse
+    pub fn prune(&self, policy: &RetentionPolicy) -> crate::Result<u64> {
+        self.store.exec(|store| store.prune_files(policy))
+    }
+
+    /// Rename the file at `from` to `to`.
+    ///
+    /// This is the same as [`Archive::rename_with`], but using [`RenamePolicy::Error`].
+    pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> crate::Result<()> {
+        self.rename_with(from, to, RenamePolicy::Error)
+    }
+
+    /// Rename the file at `from` to `to`, using `policy` to decide what to do if `to` already
+    /// exists.
+    ///
+    /// If `from` is a directory, all its descendants are moved along with it. The whole rename
+    /// happens atomically.
+    ///
+    /// You cannot move a directory into one of its own descendants, or onto one of its own
+    /// ancestors.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: There is no file at `from`.
+    /// - [`NoParentDirectory`]: The parent directory of `to` does not exist.
+    /// - [`FileAlreadyExists`]: `policy` is [`RenamePolicy::Error`] and there is already a file
+    ///   at `to`.
+    /// - [`NameTooLong`]: The new path of `from` or one of its descendants would be longer than
+    ///   [`Archive::max_name_len`].
+    /// - [`FilePinned`]: `policy` is [`RenamePolicy::Overwrite`] or [`RenamePolicy::Merge`] and
+    ///   a file that would be overwritten is pinned with [`File::pin`].
+    /// - [`FileInUse`]: `from`, `to`, or one of their descendants has an open [`File`] handle.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{Connection, RenamePolicy};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let mut archive = tx.archive_mut();
+    /// archive.open("a")?.create_dir()?;
+    /// archive.open("a/file")?.create_file()?;
+    /// archive.open("b")?.create_dir()?;
+    ///
+    /// archive.rename_with("a", "b", RenamePolicy::Merge)?;
+    ///
+    /// assert!(archive.open("b/file")?.exists()?);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NoParentDirectory`]: crate::Error::NoParentDirectory
+    /// [`FileAlreadyExists`]: crate::Error::FileAlreadyExists
+    /// [`NameTooLong`]: crate::Error::NameTooLong
+    /// [`FilePinned`]: crate::Error::FilePinned
+    /// [`FileInUse`]: crate::Error::FileInUse
+    pub fn rename_with<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        from: P,
+        to: Q,
+        policy: RenamePolicy,
+    ) -> crate::Result<()> {
+        self.rename_tree(
+            &self.path_normalization.apply(from.as_ref()),
+            &self.path_normalization.apply(to.as_ref()),
+            policy,
+        )
+    }
+
+    /// Copy the file at `from` to `to`.
+    ///
+    /// If `from` is a directory, all its descendants are copied along with it. The copies keep
+    /// the mode and mtime of the originals, along with their metadata set with
+    /// [`File::set_meta`] and their external links. Files aren't pinned just because the
+    /// original was. The contents of each file are copied within the database as they're stored,
+    /// so they're never decompressed or read into memory. The whole copy happens atomically.
+    ///
+    /// You cannot copy a directory into one of its own descendants.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: There is no file at `from`.
+    /// - [`NoParentDirectory`]: The parent directory of `to` does not exist.
+    /// - [`FileAlreadyExists`]: There is already a file at `to`.
+    /// - [`NameTooLong`]: The path of the copy of `from` or one of its descendants would be
+    ///   longer than [`Archive::max_name_len`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// archive.open("original")?.create_dir()?;
+    /// archive.open("original/file")?.create_file()?;
+    ///
+    /// archive.copy("original", "duplicate")?;
+    ///
+    /// assert!(archive.open("original/file")?.exists()?);
+    /// assert!(archive.open("duplicate/file")?.exists()?);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`File::set_meta`]: crate::File::set_meta
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NoParentDirectory`]: crate::Error::NoParentDirectory
+    /// [`FileAlreadyExists`]: crate::Error::FileAlreadyExists
+    /// [`NameTooLong`]: crate::Error::NameTooLong
+    pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> crate::Result<()> {
+        self.copy_tree(
+            &self.path_normalization.apply(from.as_ref()),
+            &self.path_normalization.apply(to.as_ref()),
+        )
+    }
+
+    /// Copy the file at `from` in this archive to `to` in the archive `dest`.
+    ///
+    /// This is like [`Archive::copy`], except that the copy is made in another archive, such as
+    /// one in a different database. If `from` is a directory, all its descendants are copied
+    /// along with it. The copies keep the mode and mtime of the originals, along with their
+    /// metadata set with [`File::set_meta`]. The contents of each file are copied as they're
+    /// stored, so compressed files stay compressed, and they're streamed from one archive to the
+    /// other without being read into memory. Any shared compression dictionaries in this archive
+    /// are copied along with them. The copy is made atomically in `dest`.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: There is no file at `from`.
+    /// - [`NoParentDirectory`]: The parent directory of `to` does not exist in `dest`.
+    /// - [`FileAlreadyExists`]: There is already a file at `to` in `dest`.
+    /// - [`NameTooLong`]: The path of the copy of `from` or one of its descendants would be
+    ///   longer than [`Archive::max_name_len`] for `dest`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// let mut src_conn = Connection::open_in_memory()?;
+    /// let mut dest_conn = Connection::open_in_memory()?;
+    ///
+    /// let mut src_tx = src_conn.transaction()?;
+    /// let mut dest_tx = dest_conn.transaction()?;
+    ///
+    /// let src = src_tx.archive_mut();
+    /// src.open("dir")?.create_dir()?;
+    /// src.open("dir/file")?.create_file()?;
+    ///
+    /// src.copy_to(dest_tx.archive_mut(), "dir", "copied")?;
+    ///
+    /// assert!(dest_tx.archive_mut().open("copied/file")?.exists()?);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`File::set_meta`]: crate::File::set_meta
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NoParentDirectory`]: crate::Error::NoParentDirectory
+    /// [`FileAlreadyExists`]: crate::Error::FileAlreadyExists
+    /// [`NameTooLong`]: crate::Error::NameTooLong
+    pub fn copy_to<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        dest: &mut Archive,
+        from: P,
+        to: Q,
+    ) -> crate::Result<()> {
+        self.copy_tree_to(
+            dest,
+            &self.path_normalization.apply(from.as_ref()),
+            &dest.path_normalization.apply(to.as_ref()),
+        )
+    }
+
+    /// Copy the contents of the regular file at `from` into the regular file at `to`.
+    ///
+    /// The data is copied as it's stored, so if `from` is compressed, `to` will be compressed the
+    /// same way. The data is streamed from one file to the other, so this doesn't read the whole
+    /// file into memory, and it works even when the `deflate` Cargo feature is disabled. Use
+    /// [`Archive::copy_data_with`] to compress the data differently.
+    ///
+    /// The file at `to` must already exist. Its contents are overwritten, but its metadata is left
+    /// alone.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: There is no file at `from` or `to`.
+    /// - [`NotARegularFile`]: The file at `from` or `to` is a directory or a symbolic link.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut original = archive.open("original")?;
+    /// original.create_file()?;
+    /// original.write_str("Hello, world!")?;
+    ///
+    /// archive.open("duplicate")?.create_file()?;
+    /// archive.copy_data("original", "duplicate")?;
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    pub fn copy_data<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> crate::Result<()> {
+        self.copy_file_data(
+            &self.path_normalization.apply(from.as_ref()),
+            &self.path_normalization.apply(to.as_ref()),
+            None,
+        )
+    }
+
+    /// Copy the contents of the regular file at `from` into the regular file at `to`, compressing
+    /// them with `method`.
+    ///
+    /// This is like [`Archive::copy_data`], except that the data is decompressed and then
+    /// compressed again, the same as [`File::write_file`] with [`File::set_compression`]. If
+    /// `method` is [`Compression::None`], the data is streamed and isn't read into memory. If
+    /// `from` and `to` are the same file, this changes how that file is compressed.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: There is no file at `from` or `to`.
+    /// - [`NotARegularFile`]: The file at `from` or `to` is a directory or a symbolic link.
+    /// - [`CompressionNotSupported`]: The file at `from` is compressed, but the `deflate` Cargo
+    ///   feature is disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{Compression, Connection};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut original = archive.open("original")?;
+    /// original.create_file()?;
+    /// original.write_str("Hello, world!")?;
+    ///
+    /// archive.open("duplicate")?.create_file()?;
+    /// archive.copy_data_with("original", "duplicate", Compression::None)?;
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`File::write_file`]: crate::File::write_file
+    /// [`File::set_compression`]: crate::File::set_compression
+    /// [`Compression::None`]: crate::Compression::None
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    pub fn copy_data_with<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        from: P,
+        to: Q,
+        method: Compression,
+    ) -> crate::Result<()> {
+        self.copy_file_data(
+            &self.path_normalization.apply(from.as_ref()),
+            &self.path_normalization.apply(to.as_ref()),
+            Some(method),
+        )
+    }
+
+    /// Change how every regular file in the archive is compressed.
+    ///
+    /// This is the same as [`Archive::recompress_with`] with the default [`RecompressOptions`].
+    pub fn recompress(&self, method: Compression) -> crate::Result<()> {
+        self.recompress_with(method, &RecompressOptions::new())
+    }
+
+    /// Change how every regular file in the archive is compressed, using the given `opts`.
+    ///
+    /// Each regular file is decompressed and then compressed again with `method`, the same as
+    /// [`Archive::copy_data_with`]. Only the contents of the files change; their metadata is left
+    /// alone. You can use this to compress an archive that was created with compression disabled,
+    /// or to decompress one so that it can be read without the `deflate` Cargo feature. The
+    /// archive default set with [`Archive::set_compression`] isn't changed.
+    ///
+    /// All the files are recompressed atomically.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileAlreadyOpen`]: There's a handle open to one of the files.
+    /// - [`CompressionNotSupported`]: One of the files is compressed, but the Cargo feature for
+    ///   its compression method is disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{Compression, Connection, RecompressOptions};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let opts = RecompressOptions::new().on_progress(|progress| {
+    ///     println!("Recompressed {}", progress.path().display());
+    /// });
+    ///
+    /// archive.recompress_with(Compression::BEST, &opts)?;
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileAlreadyOpen`]: crate::Error::FileAlreadyOpen
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    pub fn recompress_with(
+        &self,
+        method: Compression,
+        opts: &RecompressOptions,
+    ) -> crate::Result<()> {
+        self.recompress_files(method, opts)
+    }
+
+    /// Train a shared Zstandard dictionary on the small text files in the archive.
+    ///
+    /// Small files don't contain enough data on their own for the compressor to find much
+    /// redundancy, but many similar small files, like JSON records or HTML pages, share a lot of
+    /// it between them. This trains a dictionary on the regular files in the archive that are
+    /// text and no larger than 64 KiB, and stores it in the `sqlar_zstd_dicts` table. From then
+    /// on, small text files written with [`Compression::Zstd`] are compressed with that
+    /// dictionary, which can make them much smaller. Files written with [`File::writer`] aren't
+    /// compressed with the dictionary.
+    ///
+    /// Files that are already in the archive aren't changed. Use [`Archive::recompress`] to
+    /// compress them with the new dictionary.
+    ///
+    /// Calling this again trains a new dictionary, which replaces the old one for new files.
+    /// Old dictionaries are kept, and files are always read with the dictionary they were
+    /// compressed with. This happens transparently, but it means other tools can't read files
+    /// compressed with a dictionary. This requires the `zstd` Cargo feature.
+    ///
+    /// # Errors
+    ///
+    /// - [`CompressionNotSupported`]: One of the files is compressed, but the Cargo feature for
+    ///   its compression method is disabled.
+    /// - [`Io`]: There aren't enough small text files in the archive to train a dictionary.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{Compression, Connection};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// for i in 0..200 {
+    ///     let mut file = archive.open(format!("user-{i}.json"))?;
+    ///     file.create_file()?;
+    ///     file.write_str(format!(r#"{{"id": {i}, "name": "user {i}", "active": true}}"#))?;
+    /// }
+    ///
+    /// archive.train_dictionary()?;
+    /// archive.recompress(Compression::Zstd { level: 3 })?;
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`Compression::Zstd`]: crate::Compression::Zstd
+    /// [`File::writer`]: crate::File::writer
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    /// [`Io`]: crate::Error::Io
+    #[cfg(feature = "zstd")]
+    pub fn train_dictionary(&self) -> crate::Result<()> {
+        self.train_zstd_dictionary()
+    }
+
+    /// Store the contents of identical files only once.
+    ///
+    /// This finds regular files in the archive with the same contents and moves their contents
+    /// into a shared `sqlar_dedup` table, so each distinct file is only stored once. The files
+    /// can still be read, copied, and renamed as normal through this library. Writing to one of
+    /// them gives it its own copy of its contents again, and contents that aren't shared by any
+    /// file anymore are deleted. Files whose contents aren't the same as any other file's are left
+    /// as they are. To keep files from being duplicated as they're archived, use
+    /// [`ArchiveOptions::deduplicate`].
+    ///
+    /// Files are compared by their contents as they're stored, so identical files that were
+    /// compressed differently aren't deduplicated. You can use [`Archive::recompress`] first to
+    /// compress every file the same way.
+    ///
+    /// This returns the number of files whose contents were already stored for another file.
+    ///
+    /// This is an extension to the sqlar format. Other tools can see the deduplicated files, but
+    /// can't read their contents.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// for path in ["a", "b"] {
+    ///     let mut file = archive.open(path)?;
+    ///     file.create_file()?;
+    ///     file.write_str("Hello, world!")?;
+    /// }
+    ///
+    /// assert_eq!(archive.deduplicate()?, 1);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    pub fn deduplicate(&self) -> crate::Result<u64> {
+        self.dedup_files()
+    }
+
+    /// Set whether to record the time this archive was last modified.
+    ///
+    /// When this is enabled, any change to the files in the archive or their metadata, including
+    /// pinning them with [`File::pin`], updates the time returned by [`Archive::last_modified`]
+    /// and increments the counter returned by [`Archive::generation`]. This is stored in the
+    /// archive and tracked with SQLite triggers, so it stays enabled for future connections and
+    /// picks up changes made by other programs. This is useful for invalidating caches of the
+    /// archive's contents.
+    ///
+    /// Disabling this removes the tracking from the archive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let mut archive = tx.archive_mut();
+    /// archive.set_track_modified(true)?;
+    /// archive.open("file")?.create_file()?;
+    ///
+    /// assert!(archive.last_modified()?.is_some());
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    pub fn set_track_modified(&self, track: bool) -> crate::Result<()> {
+        self.store.exec(|store| {
+            if track {
+                store.enable_modified_tracking()
+            } else {
+                store.disable_modified_tracking()
+            }
+        })
+    }
+
+    /// The time this archive was last modified.
+    ///
+    /// This returns `None` unless tracking has been enabled with
+    /// [`Archive::set_track_modified`]. When tracking is first enabled, this is the time it was
+    /// enabled. This has millisecond precision.
+    pub fn last_modified(&self) -> crate::Result<Option<SystemTime>> {
+        Ok(self
+            .store
+            .last_modified()?
+            .map(|millis| UNIX_EPOCH + Duration::from_millis(millis)))
+    }
+
+    /// A counter that increases every time this archive is modified.
+    ///
+    /// This returns `None` unless tracking has been enabled with
+    /// [`Archive::set_track_modified`]. When tracking is first enabled, this is `0`.
+    ///
+    /// Unlike [`Archive::last_modified`], this is guaranteed to change when the archive changes,
+    /// even if the system clock goes backwards or two changes happen within the same millisecond.
+    /// A single operation may increase it by more than one, so don't use it to count changes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let mut archive = tx.archive_mut();
+    /// archive.set_track_modified(true)?;
+    /// let generation = archive.generation()?;
+    ///
+    /// archive.open("file")?.create_file()?;
+    ///
+    /// assert_ne!(archive.generation()?, generation);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    pub fn generation(&self) -> crate::Result<Option<u64>> {
+        self.store.generation()
+    }
+
+    /// Compute a digest of the contents of this archive.
+    ///
+    /// This is the same as [`Archive::content_digest_with`], but using the default options.
+    pub fn content_digest(&self) -> crate::Result<Digest> {
+        self.content_digest_with(&DigestOptions::new())
+    }
+
+    /// Compute a digest of the contents of this archive.
+    ///
+    /// This is a SHA-256 hash over the path, type, mode, mtime, and uncompressed contents of every
+    /// file in the archive, in sorted order. Two archives with the same files have the same digest
+    /// regardless of the order the files were added in, which files are compressed, or how the
+    /// underlying SQLite database is laid out on disk.
+    ///
+    /// You can use [`DigestOptions`] to leave file modes and mtimes out of the digest.
+    ///
+    /// # Errors
+    ///
+    /// - [`CompressionNotSupported`]: A file is compressed, but the `deflate` Cargo feature is
+    ///   disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{Connection, DigestOptions};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let mut archive = tx.archive_mut();
+    /// let opts = DigestOptions::new().include_mtime(false);
+    /// let digest = archive.content_digest_with(&opts)?;
+    ///
+    /// println!("{digest}");
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    pub fn content_digest_with(&self, opts: &DigestOptions) -> crate::Result<Digest> {
+        self.digest_archive(opts)
+    }
+
+    /// Compute the digest of every regular file in the archive and store it in the archive.
+    ///
+    /// The digests are stored in a `sqlar_checksums` table, which is created if it doesn't exist.
+    /// Any digests stored previously are replaced. You can check the files against their stored
+    /// digests later with [`Archive::verify_checksums`].
+    ///
+    /// A file's stored digest is removed when the file is deleted and follows it when it's
+    /// renamed, but it isn't updated when the file is written to.
+    ///
+    /// This returns the number of files whose digests were stored.
+    ///
+    /// # Errors
+    ///
+    /// - [`CompressionNotSupported`]: One of the files is compressed, but the Cargo feature for
+    ///   its compression method is disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{Connection, DigestAlgorithm};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut file = archive.open("file")?;
+    /// file.create_file()?;
+    /// file.write_str("Hello, world!")?;
+    ///
+    /// archive.store_checksums(DigestAlgorithm::Sha256)?;
+    ///
+    /// assert!(archive.verify_checksums()?.is_empty());
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    pub fn store_checksums(&self, algorithm: DigestAlgorithm) -> crate::Result<u64> {
+        self.compute_checksums(algorithm)
+    }
+
+    /// Check the contents of the files in the archive against their stored digests.
+    ///
+    /// This checks every file with a digest stored by [`Archive::store_checksums`] and returns the
+    /// paths of the files whose contents don't match, sorted by path. Files that can't be
+    /// decompressed because their contents are corrupt don't match. Files without a stored digest
+    /// are skipped. The files are streamed, so they're never extracted or read into memory all at
+    /// once.
+    ///
+    /// # Errors
+    ///
+    /// - [`CompressionNotSupported`]: One of the files is compressed, but the Cargo feature for
+    ///   its compression method is disabled.
+    /// - [`DigestNotSupported`]: One of the digests was computed with a hash algorithm this build
+    ///   of sqlarfs doesn't support.
+    ///
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    /// [`DigestNotSupported`]: crate::Error::DigestNotSupported
+    pub fn verify_checksums(&self) -> crate::Result<Vec<PathBuf>> {
+        self.check_checksums()
+    }
+
+    /// Report how well the files at `path` compress, grouped by file extension.
+    ///
+    /// If `path` is a directory, this reports on all the regular files in that directory tree. If
+    /// `path` is empty, this reports on every regular file in the archive. You can use this to
+    /// decide which types of files aren't worth compressing.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: There is no file at `path`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let mut archive = tx.archive_mut();
+    /// let report = archive.compression_report("")?;
+    ///
+    /// for entry in report.extensions() {
+    ///     println!(
+    ///         "{}: {:.0}%",
+    ///         entry.extension().unwrap_or("(none)"),
+    ///         entry.stats().ratio() * 100.0,
+    ///     );
+    /// }
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    pub fn compression_report<P: AsRef<Path>>(&self, path: P) -> crate::Result<CompressionReport> {
+        let path = self.path_normalization.apply(path.as_ref());
+        self.report_compression(&path)
+    }
+
+    /// Check that the archive is valid, without changing anything.
+    ///
+    /// Archives created by this library are always valid, but archives created by other tools
+    /// may not be. This looks for:
+    ///
+    /// - Directories that don't exist but have files under them.
+    /// - Files that have files under them but aren't directories.
+    /// - Files whose paths aren't in the form this library expects, like paths with trailing
+    ///   slashes or `..` components. These files can be listed, but not opened.
+    /// - Files whose paths are the same as another file's once they're in that form.
+    /// - Files with a negative size that aren't symbolic links.
+    /// - Regular files whose contents can't be decompressed.
+    ///
+    /// This reads the contents of every compressed file in the archive. Use [`Archive::repair`]
+    /// to fix some of these problems.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// archive.open("dir")?.create_dir()?;
+    /// archive.open("dir/file")?.create_file()?;
+    ///
+    /// assert!(archive.check()?.is_empty());
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    pub fn check(&self) -> crate::Result<ArchiveProblems> {
+        self.find_problems()
+    }
+
+    /// Find and optionally fix problems in an archive created by another tool.
+    ///
+    /// This looks for the same problems as [`Archive::check`]. The [`RepairOptions`] determine
+    /// which of them are fixed. The returned [`RepairReport`] lists the problems that were found
+    /// before and after the repair. Passing the default options only reports the problems without
+    /// changing anything.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{Connection, RepairOptions};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let opts = RepairOptions::new().fix_dirs(true).fix_paths(true);
+    /// let report = archive.repair(&opts)?;
+    ///
+    /// assert!(report.after().is_empty());
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    pub fn repair(&self, opts: &RepairOptions) -> crate::Result<RepairReport> {
+        self.repair_problems(opts)
+    }
+
+    /// Find the symbolic links in this archive whose targets don't exist.
+    ///
+    /// Each link's target is resolved relative to the directory containing the link, following
+    /// any other symbolic links along the way. A link is broken if its target doesn't exist in
+    /// the archive, a component of its target is a regular file, or it's part of a cycle of
+    /// symbolic links.
+    ///
+    /// Links whose targets point outside of the archive, because they're absolute paths or they
+    /// have more `..` components than the link has parent directories, can't be resolved without
+    /// knowing where the archive will be extracted, so they're never reported. Use
+    /// [`ExtractOptions::skip_broken_symlinks`] to check those against the filesystem when
+    /// extracting.
+    ///
+    /// This returns the paths of the broken links, sorted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::path::PathBuf;
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let mut archive = tx.archive_mut();
+    /// archive.open("file")?.create_file()?;
+    /// archive.open("good")?.create_symlink("file")?;
+    /// archive.open("broken")?.create_symlink("missing")?;
+    ///
+    /// assert_eq!(archive.find_broken_symlinks()?, vec![PathBuf::from("broken")]);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`ExtractOptions::skip_broken_symlinks`]: crate::ExtractOptions::skip_broken_symlinks
+    pub fn find_broken_symlinks(&self) -> crate::Result<Vec<PathBuf>> {
+        self.find_broken_symlinks_impl()
+    }
+
+    /// Decide which file to serve for a request to a static website backed by this archive.
     ///
-    /// This accepts a [`ListOptions`] to sort and filter the results.
+    /// `request_path` is the decoded path from the request URL, like `/docs/` or
+    /// `/assets/style.css`, and `accept_encoding` is the value of the `Accept-Encoding` header, if
+    /// there was one. This resolves the request the way most static file servers do:
     ///
-    /// This returns an error if mutually exclusive options were specified together in
-    /// [`ListOptions`].
+    /// - A request for a directory without a trailing slash is redirected to add one.
+    /// - A request for a directory with a trailing slash serves its `index.html`.
+    /// - If the client accepts it, a precompressed sibling file (`.br` for Brotli or `.gz` for
+    ///   gzip) is served in place of the requested file. Brotli is preferred over gzip.
     ///
-    /// # Examples
+    /// Paths containing `..` segments are never resolved.
     ///
-    /// List the regular files that are descendants of `parent/dir` in descending order by size.
+    /// # Examples
     ///
     /// ```
-    /// # use sqlarfs::{ListOptions, Connection, FileMetadata};
+    /// # use std::path::PathBuf;
+    /// # use sqlarfs::{Connection, StaticResource};
     /// # let mut connection = Connection::open_in_memory()?;
     /// # let mut tx = connection.transaction()?;
     /// # let mut archive = tx.archive_mut();
-    /// let opts = ListOptions::new().by_size().desc().descendants_of("parent/dir");
+    /// archive.open("docs")?.create_dir()?;
+    /// archive.open("docs/index.html")?.create_file()?;
     ///
-    /// for result in archive.list_with(&opts)? {
-    ///     let entry = result?;
-    ///     let path = entry.path();
+    /// assert_eq!(
+    ///     archive.resolve_static("/docs", None)?,
+    ///     StaticResource::Redirect { location: String::from("/docs/") },
+    /// );
     ///
-    ///     if let FileMetadata::File { size, .. } = entry.metadata() {
-    ///         println!("{}: {}", path.to_string_lossy(), size);
-    ///     }
-    /// }
+    /// assert_eq!(
+    ///     archive.resolve_static("/docs/", None)?,
+    ///     StaticResource::File { path: PathBuf::from("docs/index.html"), encoding: None },
+    /// );
     /// # sqlarfs::Result::Ok(())
     /// ```
-    pub fn list_with(&mut self, opts: &ListOptions) -> crate::Result<ListEntries> {
-        if opts.is_invalid {
-            return Err(crate::Error::InvalidArgs {
-                reason: String::from(
-                    "Mutually exclusive options where used together in `ListOptions`.",
-                ),
-            });
-        }
-
-        self.store.list_files(opts)
+    pub fn resolve_static(
+        &self,
+        request_path: &str,
+        accept_encoding: Option<&str>,
+    ) -> crate::Result<StaticResource> {
+        self.resolve_static_path(request_path, accept_encoding)
     }
 
     /// Copy the filesystem directory tree at `from` into the archive at `to`.
     ///
     /// This is the same as [`Archive::archive_with`], but using the default options.
-    pub fn archive<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, from: P, to: Q) -> crate::Result<()> {
+    pub fn archive<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> crate::Result<()> {
         self.archive_with(from, to, &Default::default())
     }
 
@@ -132,7 +1306,7 @@ impl<'conn> Archive<'conn> {
     /// [`NotADirectory`]: crate::Error::NotADirectory
     /// [`FileAlreadyExists`]: crate::Error::FileAlreadyExists
     pub fn archive_with<P: AsRef<Path>, Q: AsRef<Path>>(
-        &mut self,
+        &self,
         from: P,
         to: Q,
         opts: &ArchiveOptions,
@@ -145,13 +1319,93 @@ impl<'conn> Archive<'conn> {
             &super::mode::UnixModeAdapter,
             #[cfg(windows)]
             &super::mode::WindowsModeAdapter,
-        )
+            None,
+        )?;
+
+        Ok(())
+    }
+
+    /// Return how many small files have been written with a single insert in this transaction.
+    ///
+    /// See [`InlineStats`] and [`ArchiveOptions::inline_threshold`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs;
+    /// # use sqlarfs::Connection;
+    /// # let temp_dir = tempfile::tempdir()?;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// fs::write(temp_dir.path().join("file"), "contents")?;
+    ///
+    /// archive.archive(temp_dir.path(), "dir")?;
+    ///
+    /// assert_eq!(archive.inline_stats().files(), 1);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    pub fn inline_stats(&self) -> InlineStats {
+        self.inline_stats.get()
+    }
+
+    /// Import the rows returned by a query against another SQLite database as files.
+    ///
+    /// This runs `query` against the SQLite database at `database`, which is opened read-only,
+    /// and creates a regular file in the archive for each row it returns. Use [`ImportOptions`] to
+    /// choose which columns hold the path, contents, mtime, and mode of each file. Parent
+    /// directories are created as needed.
+    ///
+    /// This returns the number of files that were imported.
+    ///
+    /// If this returns an error, the rows before the one that failed have already been imported.
+    /// Return the error from the transaction to roll them back.
+    ///
+    /// # Errors
+    ///
+    /// - [`CannotOpen`]: The database at `database` could not be opened.
+    /// - [`InvalidArgs`]: The query does not return one of the columns in `opts`, or one of those
+    ///   columns holds a value of the wrong type.
+    /// - [`FileAlreadyExists`]: One of the rows would overwrite an existing file in the archive.
+    /// - [`NotADirectory`]: The parent of one of the rows' paths is not a directory.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use sqlarfs::{Connection, ImportOptions};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let mut archive = tx.archive_mut();
+    /// let opts = ImportOptions::new()
+    ///     .path_column("filename")
+    ///     .contents_column("body")
+    ///     .mtime_column("uploaded_at");
+    ///
+    /// let num_imported = archive.import_query(
+    ///     "attachments.db",
+    ///     "SELECT filename, body, uploaded_at FROM attachments",
+    ///     &opts,
+    /// )?;
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`CannotOpen`]: crate::Error::CannotOpen
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    /// [`FileAlreadyExists`]: crate::Error::FileAlreadyExists
+    /// [`NotADirectory`]: crate::Error::NotADirectory
+    pub fn import_query<P: AsRef<Path>>(
+        &self,
+        database: P,
+        query: &str,
+        opts: &ImportOptions,
+    ) -> crate::Result<u64> {
+        self.import_rows(database.as_ref(), query, opts)
     }
 
     /// Copy the directory tree in the archive at `from` into the filesystem at `to`.
     ///
     /// This is the same as [`Archive::extract_with`], but using the default options.
-    pub fn extract<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, from: P, to: Q) -> crate::Result<()> {
+    pub fn extract<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> crate::Result<()> {
         self.extract_with(from, to, &Default::default())
     }
 
@@ -170,13 +1424,27 @@ impl<'conn> Archive<'conn> {
     ///   but is not a directory.
     /// - [`FileAlreadyExists`]: One of the files in `from` would overwrite an existing file in the
     ///   filesystem.
+    /// - [`UnsupportedMetadata`]: [`ExtractOptions::metadata_fallback`] was
+    ///   [`MetadataFallback::Error`] and the filesystem at `to` can't store the metadata of the
+    ///   files being extracted.
+    /// - [`PathEscapesRoot`]: [`ExtractOptions::secure`] was `true` and one of the files would be
+    ///   extracted outside of the destination directory.
+    /// - [`PathEscapesRoot`]: [`ExtractOptions::anchor_root`] was `true` and one of the files
+    ///   would be extracted through a symbolic link.
+    /// - [`NameTooLong`]: [`ExtractOptions::long_names`] was [`LongNamePolicy::Error`] and one of
+    ///   the files has a name that's too long for the filesystem.
     ///
     /// [`FileNotFound`]: crate::Error::FileNotFound
     /// [`NoParentDirectory`]: crate::Error::NoParentDirectory
     /// [`NotADirectory`]: crate::Error::NotADirectory
     /// [`FileAlreadyExists`]: crate::Error::FileAlreadyExists
+    /// [`UnsupportedMetadata`]: crate::Error::UnsupportedMetadata
+    /// [`MetadataFallback::Error`]: crate::MetadataFallback::Error
+    /// [`PathEscapesRoot`]: crate::Error::PathEscapesRoot
+    /// [`NameTooLong`]: crate::Error::NameTooLong
+    /// [`LongNamePolicy::Error`]: crate::LongNamePolicy::Error
     pub fn extract_with<P: AsRef<Path>, Q: AsRef<Path>>(
-        &mut self,
+        &self,
         from: P,
         to: Q,
         opts: &ExtractOptions,
@@ -192,6 +1460,16 @@ impl<'conn> Archive<'conn> {
         )
     }
 
+    /// Whether this archive was opened with [`Connection::open_readonly`].
+    ///
+    /// Every operation that would write to a read-only archive fails with [`ReadOnly`].
+    ///
+    /// [`Connection::open_readonly`]: crate::Connection::open_readonly
+    /// [`ReadOnly`]: crate::Error::ReadOnly
+    pub fn is_read_only(&self) -> crate::Result<bool> {
+        self.store.is_read_only()
+    }
+
     /// The current umask for newly created files and directories.
     pub fn umask(&self) -> FileMode {
         self.umask
@@ -218,4 +1496,472 @@ impl<'conn> Archive<'conn> {
     pub fn set_umask(&mut self, mode: FileMode) {
         self.umask = mode;
     }
+
+    /// Whether newly created files honor the `SOURCE_DATE_EPOCH` environment variable.
+    ///
+    /// See [`Archive::set_source_date_epoch`].
+    pub fn source_date_epoch(&self) -> bool {
+        self.source_date_epoch
+    }
+
+    /// Set whether newly created files honor the `SOURCE_DATE_EPOCH` environment variable.
+    ///
+    /// If this is `true` and [`SOURCE_DATE_EPOCH`] is set, files created with
+    /// [`File::create_file`], [`File::create_dir`], [`File::create_dir_all`], and
+    /// [`File::create_symlink`] get an mtime no later than that timestamp. Creating a file returns
+    /// an error if the environment variable is set but isn't a valid Unix timestamp.
+    ///
+    /// To clamp mtimes when archiving files from the filesystem, see
+    /// [`ArchiveOptions::source_date_epoch`].
+    ///
+    /// The default is `false`.
+    ///
+    /// [`SOURCE_DATE_EPOCH`]: https://reproducible-builds.org/specs/source-date-epoch/
+    pub fn set_source_date_epoch(&mut self, honor: bool) {
+        self.source_date_epoch = honor;
+    }
+
+    /// The compression method given to files opened from this archive.
+    ///
+    /// See [`Archive::set_compression`].
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Set the compression method given to files opened from this archive.
+    ///
+    /// This only sets the initial value of [`File::compression`] for handles returned by
+    /// [`Archive::open`] after this is called. You can still change it for each file with
+    /// [`File::set_compression`].
+    ///
+    /// The default is [`Compression::FAST`] when the `deflate` Cargo feature is enabled and
+    /// [`Compression::None`] otherwise.
+    pub fn set_compression(&mut self, method: Compression) {
+        self.compression = method;
+    }
+
+    /// Whether writing to a file updates its mtime.
+    ///
+    /// See [`Archive::set_update_mtime`].
+    pub fn update_mtime(&self) -> bool {
+        self.update_mtime
+    }
+
+    /// Set whether writing to a file updates its mtime.
+    ///
+    /// If this is `true`, replacing the contents of a file through a handle returned by
+    /// [`Archive::open`] sets its mtime to the current time, the same way creating a file does.
+    /// This honors [`Archive::set_source_date_epoch`]. Changing a file's metadata does not count
+    /// as writing to it.
+    ///
+    /// The default is `false`.
+    pub fn set_update_mtime(&mut self, update: bool) {
+        self.update_mtime = update;
+    }
+
+    /// Use this function to get the current time instead of the system clock.
+    ///
+    /// The current time is what new files and directories get as their mtime, including small
+    /// files inserted by [`Archive::archive_with`] when [`ArchiveOptions::preserve_metadata`] is
+    /// disabled, and what [`Archive::set_update_mtime`] sets it to. Returning a fixed time makes
+    /// the archive reproducible and time-based tests reliable. This still honors
+    /// [`Archive::set_source_date_epoch`].
+    ///
+    /// Like the other settings on an [`Archive`], this only lasts for the current transaction.
+    ///
+    /// By default, the system clock is used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::{Duration, UNIX_EPOCH};
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let epoch = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    ///
+    /// archive.set_clock(move || epoch);
+    ///
+    /// let mut file = archive.open("file")?;
+    /// file.create_file()?;
+    ///
+    /// assert_eq!(file.metadata()?.mtime(), Some(epoch));
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`ArchiveOptions::preserve_metadata`]: crate::ArchiveOptions::preserve_metadata
+    pub fn set_clock<F>(&mut self, clock: F)
+    where
+        F: Fn() -> SystemTime + Send + Sync + 'static,
+    {
+        self.clock = Clock::new(clock);
+    }
+
+    /// Go back to using the system clock to get the current time.
+    ///
+    /// See [`Archive::set_clock`].
+    pub fn reset_clock(&mut self) {
+        self.clock = Clock::default();
+    }
+
+    /// The maximum length of the path of a new file, in bytes.
+    ///
+    /// See [`Archive::set_max_name_len`].
+    pub fn max_name_len(&self) -> usize {
+        self.store.max_name_len()
+    }
+
+    /// Set the maximum length of the path of a new file, in bytes.
+    ///
+    /// Creating or renaming a file so that its path is longer than this returns
+    /// [`Error::NameTooLong`]. This catches paths that would fail in confusing ways once they're
+    /// extracted or read by other tools. Files that are already in the archive can still be read,
+    /// no matter how long their paths are.
+    ///
+    /// To handle long file names when extracting files, see [`ExtractOptions::long_names`].
+    ///
+    /// The default is 4096 bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{Connection, Error};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// archive.set_max_name_len(8);
+    ///
+    /// assert!(matches!(
+    ///     archive.open("long-file-name")?.create_file(),
+    ///     Err(Error::NameTooLong { .. }),
+    /// ));
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`Error::NameTooLong`]: crate::Error::NameTooLong
+    /// [`ExtractOptions::long_names`]: crate::ExtractOptions::long_names
+    pub fn set_max_name_len(&mut self, len: usize) {
+        self.store.set_max_name_len(len);
+    }
+
+    /// Execute the given function with the given [`Settings`] overriding this archive's defaults.
+    ///
+    /// The settings apply to any files opened within `f`. Once `f` returns, the previous settings
+    /// are restored, whether or not it succeeded.
+    ///
+    /// This runs `f` within a savepoint. If `f` returns `Err`, any changes it made to the archive
+    /// are rolled back, but the enclosing transaction is left open.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{Connection, FileMode, Settings};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let settings = Settings::new().umask(FileMode::GROUP_W | FileMode::OTHER_W);
+    ///
+    /// archive.with_settings(&settings, |archive| {
+    ///     assert_eq!(archive.umask(), FileMode::GROUP_W | FileMode::OTHER_W);
+    ///     archive.open("file")?.create_file()
+    /// })?;
+    ///
+    /// assert_eq!(archive.umask(), FileMode::OTHER_W);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    pub fn with_settings<T, E, F>(&mut self, settings: &Settings, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut Archive<'conn>) -> Result<T, E>,
+        E: From<crate::Error>,
+    {
+        let previous = Settings {
+            umask: Some(self.umask),
+            compression: Some(self.compression),
+            update_mtime: Some(self.update_mtime),
+        };
+
+        self.apply_settings(settings);
+
+        if let Err(err) = self.store.begin_savepoint() {
+            self.apply_settings(&previous);
+            return Err(err.into());
+        }
+
+        let result = f(self);
+
+        let finished = match result {
+            Ok(_) => self.store.release_savepoint(),
+            Err(_) => self.store.rollback_savepoint(),
+        };
+
+        self.apply_settings(&previous);
+
+        finished?;
+
+        result
+    }
+
+    fn apply_settings(&mut self, settings: &Settings) {
+        if let Some(umask) = settings.umask {
+            self.umask = umask;
+        }
+
+        if let Some(compression) = settings.compression {
+            self.compression = compression;
+        }
+
+        if let Some(update_mtime) = settings.update_mtime {
+            self.update_mtime = update_mtime;
+        }
+    }
+
+    /// How paths passed to this archive are normalized.
+    ///
+    /// See [`Archive::set_path_normalization`].
+    pub fn path_normalization(&self) -> PathNormalization {
+        self.path_normalization
+    }
+
+    /// Set how paths passed to this archive are normalized.
+    ///
+    /// This applies to the paths passed to [`Archive::open`] and [`Archive::rename_with`], which
+    /// includes the files created by [`Archive::archive_with`]. Files that are already in the
+    /// archive keep their names, so once this is set to [`PathNormalization::Nfc`], any existing
+    /// files whose names aren't in NFC can only be listed, not opened. You can use
+    /// [`Archive::normalization_conflicts`] to find them.
+    ///
+    /// The default is [`PathNormalization::Preserve`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{Connection, PathNormalization};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// archive.set_path_normalization(PathNormalization::Nfc);
+    ///
+    /// // This is "café" with a combining accent.
+    /// archive.open("cafe\u{301}")?.create_file()?;
+    ///
+    /// assert!(archive.open("caf\u{e9}")?.exists()?);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    pub fn set_path_normalization(&mut self, normalization: PathNormalization) {
+        self.path_normalization = normalization;
+    }
+
+    /// Register a [`Filter`] to transform the contents of files as they're archived and extracted.
+    ///
+    /// The filter applies to files archived with [`Archive::archive_with`] and extracted with
+    /// [`Archive::extract_with`] for as long as this `Archive` exists; it doesn't apply to files
+    /// written or read directly through a [`File`]. When more than one filter matches a file,
+    /// their archive transforms are applied in the order they were registered, and their extract
+    /// transforms are applied in the reverse order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{Connection, Filter};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// archive.register_filter(
+    ///     Filter::new(|path| path.extension().is_some_and(|ext| ext == "txt"))
+    ///         .on_archive(|contents| Ok(contents.to_ascii_uppercase())),
+    /// );
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    pub fn register_filter(&mut self, filter: Filter) {
+        self.filters.push(filter);
+    }
+
+    /// Find files whose paths are different but are the same once normalized to NFC.
+    ///
+    /// This returns a list of groups of paths, where each group contains two or more paths that
+    /// are equivalent under Unicode normalization. Archives with files like these will have files
+    /// that overwrite each other when extracted on filesystems that normalize file names, like
+    /// APFS on macOS.
+    ///
+    /// The groups and the paths within them are in sorted order.
+    pub fn normalization_conflicts(&self) -> crate::Result<Vec<Vec<PathBuf>>> {
+        self.find_normalization_conflicts()
+    }
+
+    /// Open a read-only connection to the archive that an [`ExternalLink`] points into.
+    ///
+    /// If the path of the other archive is relative, it's resolved relative to the directory
+    /// containing this archive. For in-memory and temporary databases, it's resolved relative to
+    /// the current working directory.
+    ///
+    /// You can open [`ExternalLink::target`] in the returned connection to read the linked file.
+    ///
+    /// # Errors
+    ///
+    /// - [`CannotOpen`]: The other archive does not exist.
+    /// - [`NotADatabase`]: The other archive is not a SQLite database.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let temp_dir = tempfile::tempdir()?;
+    /// let mut base = Connection::create_new(temp_dir.path().join("base.sqlar"))?;
+    ///
+    /// base.exec(|archive| {
+    ///     let mut file = archive.open("file")?;
+    ///     file.create_file()?;
+    ///     file.write_str("Hello, world!")
+    /// })?;
+    ///
+    /// let mut overlay = Connection::create_new(temp_dir.path().join("overlay.sqlar"))?;
+    ///
+    /// overlay.exec(|archive| {
+    ///     archive.open("link")?.create_external_link("base.sqlar", "file")?;
+    ///
+    ///     let link = archive.open("link")?.external_link()?.unwrap();
+    ///     let mut base = archive.open_external(&link)?;
+    ///
+    ///     base.exec(|base_archive| {
+    ///         let mut contents = String::new();
+    ///         std::io::Read::read_to_string(
+    ///             &mut base_archive.open(link.target())?.reader()?,
+    ///             &mut contents,
+    ///         )?;
+    ///
+    ///         assert_eq!(contents, "Hello, world!");
+    ///
+    ///         sqlarfs::Result::Ok(())
+    ///     })
+    /// })?;
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`CannotOpen`]: crate::Error::CannotOpen
+    /// [`NotADatabase`]: crate::Error::NotADatabase
+    pub fn open_external(&self, link: &ExternalLink) -> crate::Result<Connection> {
+        self.connect_external(link)
+    }
+
+    /// Create a read-only view of this archive layered on top of the `lower` archive.
+    ///
+    /// Files in this archive shadow files at the same path in `lower`. You can add more layers
+    /// below these two with [`Overlay::layer`]. The archives can come from different
+    /// connections. See [`Overlay`] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// # use sqlarfs::Connection;
+    /// let mut base_conn = Connection::open_in_memory()?;
+    /// let mut patch_conn = Connection::open_in_memory()?;
+    ///
+    /// let mut base_tx = base_conn.transaction()?;
+    /// let mut patch_tx = patch_conn.transaction()?;
+    ///
+    /// let base = base_tx.archive_mut();
+    /// let mut file = base.open("file")?;
+    /// file.create_file()?;
+    /// file.write_str("old")?;
+    /// base.open("other")?.create_file()?;
+    ///
+    /// let patch = patch_tx.archive_mut();
+    /// let mut file = patch.open("file")?;
+    /// file.create_file()?;
+    /// file.write_str("new")?;
+    ///
+    /// let overlay = patch.overlay(base);
+    ///
+    /// let mut contents = String::new();
+    /// overlay.reader("file")?.read_to_string(&mut contents)?;
+    ///
+    /// assert_eq!(contents, "new");
+    /// assert!(overlay.exists("other")?);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    pub fn overlay<'a, 'lower>(&'a self, lower: &'a Archive<'lower>) -> Overlay<'a> {
+        Overlay::new(self, lower)
+    }
+
+    /// Execute the given function against a read-only snapshot of the archive.
+    ///
+    /// This opens a separate read-only connection to the same database and calls the given
+    /// function with the [`Archive`] for a transaction on that connection. Because the current
+    /// transaction hasn't been committed yet, the snapshot doesn't see any of its writes; it sees
+    /// the archive as of the last commit. The snapshot stays consistent for the duration of the
+    /// function, so you can use this to compare the state of the archive before and after the
+    /// changes made in the current transaction.
+    ///
+    /// The snapshot is rolled back when the function returns. Any [`umask`] and
+    /// [`path_normalization`] set on this archive also apply to the snapshot.
+    ///
+    /// SQLite only allows reading from a database while another connection is writing to it
+    /// until the writer needs to spill its changes to disk. If the database isn't in WAL mode
+    /// and the current transaction has made a lot of changes, opening the snapshot may fail with
+    /// a [`Sqlite`] error because the database is busy.
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: This is an in-memory or temporary database, which can't be opened by a
+    ///   second connection.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let temp_dir = tempfile::tempdir()?;
+    /// # let db_path = temp_dir.path().join("test.sqlar");
+    /// let mut connection = Connection::create_new(db_path)?;
+    ///
+    /// connection.exec(|archive| {
+    ///     archive.open("file")?.create_file()?;
+    ///
+    ///     let existed_before = archive.snapshot_read(|snapshot| snapshot.open("file")?.exists())?;
+    ///
+    ///     assert!(!existed_before);
+    ///
+    ///     sqlarfs::Result::Ok(())
+    /// })?;
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`umask`]: Archive::umask
+    /// [`path_normalization`]: Archive::path_normalization
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    /// [`Sqlite`]: crate::Error::Sqlite
+    pub fn snapshot_read<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut Archive) -> Result<T, E>,
+        E: From<crate::Error>,
+    {
+        let db_path = self
+            .store
+            .db_path()
+            .ok_or_else(|| crate::Error::InvalidArgs {
+                reason: String::from(
+                    "Cannot take a snapshot of an in-memory or temporary database.",
+                ),
+            })?;
+
+        let builder = ConnectionBuilder::new().table_name(self.store.table());
+
+        #[cfg(feature = "sqlcipher")]
+        let builder = builder.with_encryption_key(self.encryption_key.clone());
+
+        let mut conn = builder.open_readonly(db_path)?;
+        let mut tx = conn.transaction()?;
+
+        let snapshot = tx.archive_mut();
+        snapshot.umask = self.umask;
+        snapshot.source_date_epoch = self.source_date_epoch;
+        snapshot.path_normalization = self.path_normalization;
+
+        let result = f(snapshot);
+
+        tx.rollback()?;
+
+        result
+    }
 }
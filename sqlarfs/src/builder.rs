@@ -0,0 +1,784 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "sqlcipher")]
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::OpenFlags;
+
+use super::archive::Archive;
+use super::store::{check_is_archive, validate_table_name, DEFAULT_TABLE};
+use super::transaction::Connection;
+
+// The range of page sizes SQLite supports. Page sizes must also be a power of two.
+const MIN_PAGE_SIZE: u32 = 512;
+const MAX_PAGE_SIZE: u32 = 65536;
+
+// Create an empty file next to `path` to build a new archive in, returning its path.
+//
+// The file has to be in the same directory as `path` so that it can be renamed into place
+// atomically. We create it here, rather than letting SQLite create it, so that two callers can't
+// end up building their archives in the same file.
+fn create_temp_file(path: &Path) -> crate::Result<PathBuf> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let file_name = path.file_name().ok_or_else(|| crate::Error::InvalidArgs {
+        reason: format!("This path does not have a file name: {}", path.display()),
+    })?;
+
+    let parent = match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    };
+
+    loop {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or_default();
+
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(format!(
+            ".{}-{}-{}.tmp",
+            process::id(),
+            nanos,
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let temp_path = parent.join(temp_name);
+
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(_) => return Ok(temp_path),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(crate::Error::NoParentDirectory { path: path.into() })
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// How SQLite reclaims space in the database file when files are deleted from the archive.
+///
+/// See the [SQLite docs](https://www.sqlite.org/pragma.html#pragma_auto_vacuum) for more
+/// information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AutoVacuum {
+    /// Free space is reused for new data, but the database file never shrinks.
+    ///
+    /// This is the SQLite default.
+    #[default]
+    None,
+
+    /// The database file is truncated to remove free space every time a transaction commits.
+    Full,
+
+    /// Free space is tracked, but the database file only shrinks when you call
+    /// [`Connection::incremental_vacuum`].
+    Incremental,
+}
+
+impl AutoVacuum {
+    fn pragma_value(self) -> &'static str {
+        match self {
+            AutoVacuum::None => "NONE",
+            AutoVacuum::Full => "FULL",
+            AutoVacuum::Incremental => "INCREMENTAL",
+        }
+    }
+}
+
+/// How SQLite keeps the database consistent while a transaction is being written.
+///
+/// See the [SQLite docs](https://www.sqlite.org/pragma.html#pragma_journal_mode) for more
+/// information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum JournalMode {
+    /// A rollback journal is created for each transaction and deleted when it commits.
+    ///
+    /// This is the SQLite default.
+    #[default]
+    Delete,
+
+    /// Like [`JournalMode::Delete`], but the journal is truncated instead of deleted.
+    Truncate,
+
+    /// Like [`JournalMode::Delete`], but the journal's header is overwritten instead of the file
+    /// being deleted.
+    Persist,
+
+    /// The rollback journal is kept in memory.
+    ///
+    /// If the process crashes in the middle of a transaction, the database may be corrupted.
+    Memory,
+
+    /// Changes are appended to a write-ahead log, which lets readers keep reading while a
+    /// transaction is being written.
+    ///
+    /// This is often faster, but the database can't be opened on read-only media unless the
+    /// write-ahead log has been checkpointed and removed. This mode persists; once a database is
+    /// in WAL mode, it stays that way until it's changed back.
+    Wal,
+
+    /// There is no rollback journal.
+    ///
+    /// If the process crashes in the middle of a transaction, the database may be corrupted, and
+    /// rolling back a transaction has undefined results.
+    Off,
+}
+
+impl JournalMode {
+    fn pragma_value(self) -> &'static str {
+        match self {
+            JournalMode::Delete => "DELETE",
+            JournalMode::Truncate => "TRUNCATE",
+            JournalMode::Persist => "PERSIST",
+            JournalMode::Memory => "MEMORY",
+            JournalMode::Wal => "WAL",
+            JournalMode::Off => "OFF",
+        }
+    }
+}
+
+/// How often SQLite waits for writes to reach the disk.
+///
+/// Waiting less often is faster but makes it more likely that a power loss or OS crash loses the
+/// most recent transactions or corrupts the database.
+///
+/// See the [SQLite docs](https://www.sqlite.org/pragma.html#pragma_synchronous) for more
+/// information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Synchronous {
+    /// Never wait for writes to reach the disk.
+    Off,
+
+    /// Wait at the most critical moments.
+    ///
+    /// With [`JournalMode::Wal`], this is safe from corruption, but transactions that were
+    /// committed just before a power loss may be rolled back.
+    Normal,
+
+    /// Wait at every critical moment, so that a power loss can't corrupt the database.
+    ///
+    /// This is the SQLite default.
+    #[default]
+    Full,
+
+    /// Like [`Synchronous::Full`], but also wait for the directory containing the rollback
+    /// journal, so that committed transactions are durable after a power loss.
+    Extra,
+}
+
+impl Synchronous {
+    fn pragma_value(self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        }
+    }
+}
+
+// The key SQLCipher uses to encrypt the database.
+//
+// This is a separate type so that we don't print the key in `Debug` output.
+#[cfg(feature = "sqlcipher")]
+#[derive(Clone, PartialEq, Eq)]
+pub(super) struct EncryptionKey(Arc<str>);
+
+#[cfg(feature = "sqlcipher")]
+impl EncryptionKey {
+    pub(super) fn new(key: &str) -> Self {
+        Self(Arc::from(key))
+    }
+
+    pub(super) fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(feature = "sqlcipher")]
+impl std::fmt::Debug for EncryptionKey {
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+/// A builder for opening a [`Connection`] with custom options.
+///
+/// Options that affect the layout of the database file, like [`ConnectionBuilder::page_size`] and
+/// [`ConnectionBuilder::auto_vacuum`], can only be set when the archive is first created. They're
+/// ignored when opening an archive that already exists. Tuning options like
+/// [`ConnectionBuilder::busy_timeout`] and [`ConnectionBuilder::cache_size`] apply to the
+/// connection being opened, and need to be set again each time the archive is opened.
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// # use sqlarfs::{AutoVacuum, ConnectionBuilder, JournalMode, Synchronous};
+/// let mut connection = ConnectionBuilder::new()
+///     .page_size(65536)
+///     .auto_vacuum(AutoVacuum::Full)
+///     .journal_mode(JournalMode::Wal)
+///     .synchronous(Synchronous::Normal)
+///     .busy_timeout(Duration::from_secs(5))
+///     .open_in_memory()?;
+/// # sqlarfs::Result::Ok(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionBuilder {
+    page_size: Option<u32>,
+    auto_vacuum: Option<AutoVacuum>,
+    soft_heap_limit: Option<u64>,
+    busy_timeout: Option<Duration>,
+    journal_mode: Option<JournalMode>,
+    synchronous: Option<Synchronous>,
+    cache_size: Option<u64>,
+    mmap_size: Option<u64>,
+    table: Option<String>,
+    #[cfg(feature = "sqlcipher")]
+    key: Option<EncryptionKey>,
+    require_sqlar_table: bool,
+}
+
+impl ConnectionBuilder {
+    /// Create a new [`ConnectionBuilder`] with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The page size of the database in bytes.
+    ///
+    /// This must be a power of two between 512 and 65536. Larger pages can make archives of large
+    /// files smaller and faster to read.
+    ///
+    /// By default, this is the SQLite default, which is currently 4096.
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// How SQLite reclaims space when files are deleted from the archive.
+    ///
+    /// The default is [`AutoVacuum::None`].
+    pub fn auto_vacuum(mut self, auto_vacuum: AutoVacuum) -> Self {
+        self.auto_vacuum = Some(auto_vacuum);
+        self
+    }
+
+    /// Set a soft limit on the amount of heap memory SQLite allocates, in bytes.
+    ///
+    /// When SQLite is using more memory than this, it tries to free memory, mostly by shrinking
+    /// its page caches, before allocating more. This is a soft limit; SQLite will still exceed it
+    /// rather than fail. A limit of `0` means no limit.
+    ///
+    /// This is a process-wide setting that applies to every SQLite connection in the process, not
+    /// just the one being opened, and it stays in effect after the connection is closed. You can
+    /// check the current limit with [`Connection::memory_stats`].
+    ///
+    /// By default, the limit is left as it is, which is no limit unless it's been set elsewhere.
+    ///
+    /// See the [SQLite docs](https://www.sqlite.org/c3ref/hard_heap_limit64.html) for more
+    /// information.
+    pub fn soft_heap_limit(mut self, bytes: u64) -> Self {
+        self.soft_heap_limit = Some(bytes);
+        self
+    }
+
+    /// How long to wait for another connection to release its lock on the database.
+    ///
+    /// When another connection is writing to the database, operations on this connection retry
+    /// until this much time has passed, and then fail with a [`Sqlite`] error that
+    /// [`Error::is_retryable`]. A timeout of zero means operations fail right away.
+    ///
+    /// The default is 5 seconds.
+    ///
+    /// [`Sqlite`]: crate::Error::Sqlite
+    /// [`Error::is_retryable`]: crate::Error::is_retryable
+    pub fn busy_timeout(mut self, timeout: Duration) -> Self {
+        self.busy_timeout = Some(timeout);
+        self
+    }
+
+    /// How SQLite keeps the database consistent while a transaction is being written.
+    ///
+    /// Some journal modes, like [`JournalMode::Wal`], are stored in the database and stay in
+    /// effect for every connection after this one. This is ignored by
+    /// [`ConnectionBuilder::open_readonly`], which can't change the database, and by
+    /// [`ConnectionBuilder::open_in_memory`] for modes that only make sense on disk.
+    ///
+    /// By default, the journal mode is left as it is, which is [`JournalMode::Delete`] for new
+    /// archives.
+    pub fn journal_mode(mut self, mode: JournalMode) -> Self {
+        self.journal_mode = Some(mode);
+        self
+    }
+
+    /// How often SQLite waits for writes to reach the disk.
+    ///
+    /// The default is [`Synchronous::Full`].
+    pub fn synchronous(mut self, synchronous: Synchronous) -> Self {
+        self.synchronous = Some(synchronous);
+        self
+    }
+
+    /// The most memory to use for caching database pages, in bytes.
+    ///
+    /// A bigger cache can make reading and writing large archives faster. This is rounded down
+    /// to the nearest KiB.
+    ///
+    /// By default, this is the SQLite default, which is currently 2 MiB.
+    ///
+    /// See the [SQLite docs](https://www.sqlite.org/pragma.html#pragma_cache_size) for more
+    /// information.
+    pub fn cache_size(mut self, bytes: u64) -> Self {
+        self.cache_size = Some(bytes);
+        self
+    }
+
+    /// The most of the database file to map into memory, in bytes.
+    ///
+    /// Memory-mapped I/O can make reading large archives faster. SQLite caps this at a limit set
+    /// when it's compiled. A size of `0` disables memory-mapped I/O.
+    ///
+    /// By default, memory-mapped I/O is disabled.
+    ///
+    /// See the [SQLite docs](https://www.sqlite.org/mmap.html) for more information.
+    pub fn mmap_size(mut self, bytes: u64) -> Self {
+        self.mmap_size = Some(bytes);
+        self
+    }
+
+    /// The name of the table to store files in.
+    ///
+    /// This lets one database hold several archives, each in its own table, like an application
+    /// database that keeps separate archives for different users. The tables sqlarfs uses to store
+    /// extra data about files are named after this table, so they don't collide either. Every
+    /// option that refers to the `sqlar` table, like [`ConnectionBuilder::create_sqlar_table`],
+    /// refers to this table instead.
+    ///
+    /// The name must only contain ASCII letters, digits, and underscores, it must not start with a
+    /// digit or `sqlite_`, and it must not be an SQL keyword like `order`. Avoid names that start
+    /// with the name of another archive table followed by an underscore, like `photos_meta`
+    /// alongside `photos`, since those are used for its extra data.
+    ///
+    /// Other sqlar tools only look for a table named `sqlar`. To move an existing archive into a
+    /// different table, use [`Connection::rename_table`].
+    ///
+    /// The default is `sqlar`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::ConnectionBuilder;
+    /// let mut connection = ConnectionBuilder::new()
+    ///     .table_name("photos")
+    ///     .open_in_memory()?;
+    ///
+    /// assert_eq!(connection.table_name(), "photos");
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    pub fn table_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.table = Some(name.into());
+        self
+    }
+
+    /// The key to encrypt the database with, using [SQLCipher](https://www.zetetic.net/sqlcipher/).
+    ///
+    /// When creating an archive, this encrypts the whole database with `key`. When opening an
+    /// existing archive, `key` must be the key it was encrypted with. SQLCipher derives the
+    /// encryption key from this passphrase. To pass a raw 256-bit key instead, format it as a
+    /// blob literal in hex, like `x'2DD29CA851E7B56E4697B0E1F08507293D761A05CE4D1B628663F411A8086D99'`.
+    ///
+    /// SQLCipher can't tell a wrong key apart from a file that isn't a database at all, so opening
+    /// an archive with the wrong key, or an encrypted archive without one, returns
+    /// [`NotADatabase`]. Other sqlar tools can't read encrypted archives. To change the key, use
+    /// [`Connection::rekey`].
+    ///
+    /// This is ignored by [`ConnectionBuilder::open_in_memory`].
+    ///
+    /// By default, the database is not encrypted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::ConnectionBuilder;
+    /// # let temp_dir = tempfile::tempdir()?;
+    /// # let db_path = temp_dir.path().join("test.sqlar");
+    /// let mut connection = ConnectionBuilder::new()
+    ///     .encryption_key("correct horse battery staple")
+    ///     .create_new(db_path)?;
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`NotADatabase`]: crate::Error::NotADatabase
+    #[cfg(feature = "sqlcipher")]
+    pub fn encryption_key(mut self, key: &str) -> Self {
+        self.key = Some(EncryptionKey::new(key));
+        self
+    }
+
+    // This is for opening another connection to a database with the same key.
+    #[cfg(feature = "sqlcipher")]
+    pub(super) fn with_encryption_key(mut self, key: Option<EncryptionKey>) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Create the `sqlar` table when opening a database that doesn't have one.
+    ///
+    /// By default, [`ConnectionBuilder::open`] and [`ConnectionBuilder::open_readonly`] create
+    /// the `sqlar` table if it doesn't already exist, which turns any SQLite database into a
+    /// SQLite archive. If this is `false`, they return [`NotAnArchive`] instead, so you can't
+    /// accidentally modify a database that isn't a SQLite archive. Transactions on the connection
+    /// also return [`NotAnArchive`] if the `sqlar` table is dropped after the connection is opened.
+    ///
+    /// This doesn't affect [`ConnectionBuilder::create`], [`ConnectionBuilder::create_new`], or
+    /// [`ConnectionBuilder::open_in_memory`], which always create the table.
+    ///
+    /// The default is `true`.
+    ///
+    /// [`NotAnArchive`]: crate::Error::NotAnArchive
+    pub fn create_sqlar_table(mut self, create: bool) -> Self {
+        self.require_sqlar_table = !create;
+        self
+    }
+
+    fn validate(&self) -> crate::Result<()> {
+        validate_table_name(self.table())?;
+
+        if let Some(page_size) = self.page_size {
+            if !page_size.is_power_of_two() || !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size)
+            {
+                return Err(crate::Error::InvalidArgs {
+                    reason: format!(
+                        "The page size must be a power of two between {MIN_PAGE_SIZE} and {MAX_PAGE_SIZE}, but it was {page_size}."
+                    ),
+                });
+            }
+        }
+
+        if let Some(limit) = self.soft_heap_limit {
+            if i64::try_from(limit).is_err() {
+                return Err(crate::Error::InvalidArgs {
+                    reason: format!(
+                        "The soft heap limit must be at most {}, but it was {limit}.",
+                        i64::MAX
+                    ),
+                });
+            }
+        }
+
+        if let Some(size) = self.mmap_size {
+            if i64::try_from(size).is_err() {
+                return Err(crate::Error::InvalidArgs {
+                    reason: format!(
+                        "The mmap size must be at most {}, but it was {size}.",
+                        i64::MAX
+                    ),
+                });
+            }
+        }
+
+        // SQLite takes the busy timeout in milliseconds as a C `int`.
+        if let Some(timeout) = self.busy_timeout {
+            if i32::try_from(timeout.as_millis()).is_err() {
+                return Err(crate::Error::InvalidArgs {
+                    reason: format!(
+                        "The busy timeout must be at most {} milliseconds, but it was {}.",
+                        i32::MAX,
+                        timeout.as_millis()
+                    ),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn table(&self) -> &str {
+        self.table.as_deref().unwrap_or(DEFAULT_TABLE)
+    }
+
+    // Open the database at `path`, unlocking it with the encryption key if there is one. The key
+    // must be set before anything else reads from the database.
+    fn open_with_flags(
+        &self,
+        path: &Path,
+        flags: OpenFlags,
+    ) -> crate::Result<rusqlite::Connection> {
+        let conn = rusqlite::Connection::open_with_flags(path, flags)?;
+
+        #[cfg(feature = "sqlcipher")]
+        if let Some(key) = &self.key {
+            conn.pragma_update(None, "key", key.as_str())?;
+        }
+
+        Ok(conn)
+    }
+
+    // Apply the options that can change on every connection, as opposed to the ones that can only
+    // be set when the database is created.
+    fn configure(&self, conn: &rusqlite::Connection) -> crate::Result<()> {
+        if let Some(limit) = self.soft_heap_limit {
+            // We already checked that this fits in an `i64` in `validate`.
+            conn.pragma_update(None, "soft_heap_limit", limit as i64)?;
+        }
+
+        if let Some(timeout) = self.busy_timeout {
+            conn.busy_timeout(timeout)?;
+        }
+
+        if let Some(synchronous) = self.synchronous {
+            conn.pragma_update(None, "synchronous", synchronous.pragma_value())?;
+        }
+
+        if let Some(bytes) = self.cache_size {
+            // A negative cache size is in KiB rather than pages. Anything that doesn't fit is far
+            // bigger than any cache could be, so we just use the largest size we can.
+            let kib = i64::try_from(bytes / 1024).unwrap_or(i64::MAX);
+            conn.pragma_update(None, "cache_size", -kib)?;
+        }
+
+        if let Some(size) = self.mmap_size {
+            // We already checked that this fits in an `i64` in `validate`.
+            conn.pragma_update(None, "mmap_size", size as i64)?;
+        }
+
+        Ok(())
+    }
+
+    // Set the journal mode, which needs to be able to write to the database.
+    fn set_journal_mode(&self, conn: &rusqlite::Connection) -> crate::Result<()> {
+        if let Some(mode) = self.journal_mode {
+            // This returns the new journal mode, which is the old one if it couldn't be changed,
+            // like when asking for WAL mode in an in-memory database. That isn't an error.
+            conn.pragma_update_and_check(None, "journal_mode", mode.pragma_value(), |_| Ok(()))?;
+        }
+
+        Ok(())
+    }
+
+    // If we've been told not to create the `sqlar` table when opening a database, check that it's
+    // already there.
+    fn check_sqlar_table(&self, conn: &rusqlite::Connection) -> crate::Result<()> {
+        if self.require_sqlar_table {
+            check_is_archive(conn, self.table())?;
+        }
+
+        Ok(())
+    }
+
+    fn connect(
+        &self,
+        conn: rusqlite::Connection,
+        fail_if_exists: bool,
+    ) -> crate::Result<Connection> {
+        // These must be set before any tables are created, which is when SQLite commits to a
+        // page size and vacuum mode for the database. In an existing database, they're no-ops.
+        if let Some(page_size) = self.page_size {
+            conn.pragma_update(None, "page_size", page_size)?;
+        }
+
+        if let Some(auto_vacuum) = self.auto_vacuum {
+            conn.pragma_update(None, "auto_vacuum", auto_vacuum.pragma_value())?;
+        }
+
+        self.configure(&conn)?;
+        self.set_journal_mode(&conn)?;
+
+        let mut conn = Connection::new(conn, self.table())?;
+
+        #[cfg(feature = "sqlcipher")]
+        conn.set_encryption_key(self.key.clone());
+
+        conn.exec(|archive| archive.init(fail_if_exists))?;
+        conn.set_require_sqlar_table(self.require_sqlar_table);
+
+        Ok(conn)
+    }
+
+    /// Open a connection to the SQLite archive at `path`.
+    ///
+    /// See [`Connection::open`].
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: One of the options is invalid.
+    /// - [`CannotOpen`]: The database could not be opened because it does not exist.
+    /// - [`NotADatabase`]: The file at `path` is not a SQLite database.
+    /// - [`NotAnArchive`]: The database doesn't have a `sqlar` table, and
+    ///   [`ConnectionBuilder::create_sqlar_table`] is `false`.
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    /// [`CannotOpen`]: crate::Error::CannotOpen
+    /// [`NotADatabase`]: crate::Error::NotADatabase
+    /// [`NotAnArchive`]: crate::Error::NotAnArchive
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> crate::Result<Connection> {
+        self.validate()?;
+
+        // SQLITE_OPEN_NO_MUTEX is the default in rusqlite. Its docs explain why.
+        let flags = OpenFlags::SQLITE_OPEN_NO_MUTEX | OpenFlags::SQLITE_OPEN_READ_WRITE;
+
+        let conn = self.open_with_flags(path.as_ref(), flags)?;
+
+        self.check_sqlar_table(&conn)?;
+
+        self.connect(conn, false)
+    }
+
+    /// Create or open the SQLite archive at `path`.
+    ///
+    /// See [`Connection::create`].
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: One of the options is invalid.
+    /// - [`NotADatabase`]: The file at `path` exists but is not a SQLite database.
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    /// [`NotADatabase`]: crate::Error::NotADatabase
+    pub fn create<P: AsRef<Path>>(&self, path: P) -> crate::Result<Connection> {
+        self.validate()?;
+
+        // SQLITE_OPEN_NO_MUTEX is the default in rusqlite. Its docs explain why.
+        let flags = OpenFlags::SQLITE_OPEN_NO_MUTEX
+            | OpenFlags::SQLITE_OPEN_READ_WRITE
+            | OpenFlags::SQLITE_OPEN_CREATE;
+
+        self.connect(self.open_with_flags(path.as_ref(), flags)?, false)
+    }
+
+    /// Create a new SQLite archive at `path`.
+    ///
+    /// See [`Connection::create_new`].
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: One of the options is invalid.
+    /// - [`SqlarAlreadyExists`]: A SQLite archive already exists at `path`.
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    /// [`SqlarAlreadyExists`]: crate::Error::SqlarAlreadyExists
+    pub fn create_new<P: AsRef<Path>>(&self, path: P) -> crate::Result<Connection> {
+        self.validate()?;
+
+        // SQLITE_OPEN_NO_MUTEX is the default in rusqlite. Its docs explain why.
+        let flags = OpenFlags::SQLITE_OPEN_NO_MUTEX
+            | OpenFlags::SQLITE_OPEN_READ_WRITE
+            | OpenFlags::SQLITE_OPEN_CREATE;
+
+        self.connect(self.open_with_flags(path.as_ref(), flags)?, true)
+    }
+
+    /// Open a read-only connection to the SQLite archive at `path`.
+    ///
+    /// See [`Connection::open_readonly`]. Options that only apply when creating an archive,
+    /// including [`ConnectionBuilder::create_sqlar_table`], are ignored.
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: One of the options is invalid.
+    /// - [`CannotOpen`]: The database could not be opened because it does not exist.
+    /// - [`NotADatabase`]: The file at `path` is not a SQLite database.
+    /// - [`NotAnArchive`]: The database doesn't have a `sqlar` table.
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    /// [`CannotOpen`]: crate::Error::CannotOpen
+    /// [`NotADatabase`]: crate::Error::NotADatabase
+    /// [`NotAnArchive`]: crate::Error::NotAnArchive
+    pub fn open_readonly<P: AsRef<Path>>(&self, path: P) -> crate::Result<Connection> {
+        self.validate()?;
+
+        // SQLITE_OPEN_NO_MUTEX is the default in rusqlite. Its docs explain why.
+        let flags = OpenFlags::SQLITE_OPEN_NO_MUTEX | OpenFlags::SQLITE_OPEN_READ_ONLY;
+
+        let conn = self.open_with_flags(path.as_ref(), flags)?;
+
+        // We can't create the `sqlar` table in a read-only database, so it has to be there
+        // already.
+        check_is_archive(&conn, self.table())?;
+
+        self.configure(&conn)?;
+
+        let mut conn = Connection::new(conn, self.table())?;
+
+        #[cfg(feature = "sqlcipher")]
+        conn.set_encryption_key(self.key.clone());
+
+        conn.set_require_sqlar_table(self.require_sqlar_table);
+
+        Ok(conn)
+    }
+
+    /// Create a new in-memory SQLite archive.
+    ///
+    /// See [`Connection::open_in_memory`].
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: One of the options is invalid.
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    pub fn open_in_memory(&self) -> crate::Result<Connection> {
+        self.validate()?;
+
+        self.connect(rusqlite::Connection::open_in_memory()?, true)
+    }
+
+    /// Build a new SQLite archive in a temporary file and then move it to `path`.
+    ///
+    /// See [`Connection::create_atomically`].
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: One of the options is invalid, or `path` doesn't have a file name.
+    /// - [`NoParentDirectory`]: The parent directory of `path` does not exist.
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    /// [`NoParentDirectory`]: crate::Error::NoParentDirectory
+    pub fn create_atomically<P, T, E, F>(&self, path: P, f: F) -> Result<T, E>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&mut Archive) -> Result<T, E>,
+        E: From<crate::Error>,
+    {
+        self.validate()?;
+
+        let path = path.as_ref();
+        let temp_path = create_temp_file(path)?;
+
+        let result = self
+            .create_new(&temp_path)
+            .map_err(E::from)
+            .and_then(|mut conn| {
+                let value = conn.exec(f)?;
+
+                // Make sure the database is closed before we move it.
+                drop(conn);
+
+                fs::rename(&temp_path, path).map_err(crate::Error::from)?;
+
+                Ok(value)
+            });
+
+        if result.is_err() {
+            // If this fails, the temporary file is left behind. There's nothing more we can do
+            // about that here.
+            let _ = fs::remove_file(&temp_path);
+        }
+
+        result
+    }
+}
@@ -0,0 +1,358 @@
+//! An index of the files in many archives.
+//!
+//! A [`Catalog`] is a separate SQLite database that records the path, type, size, and SHA-256
+//! digest of every file in each archive you add to it. You can then ask which archives contain a
+//! given path, or which files in which archives have given contents, without opening each archive.
+//!
+//! ```
+//! # use sqlarfs::Connection;
+//! use sqlarfs::catalog::Catalog;
+//! # let temp_dir = tempfile::tempdir()?;
+//! # let archive_path = temp_dir.path().join("backup.sqlar");
+//!
+//! let mut conn = Connection::create_new(&archive_path)?;
+//! conn.exec(|archive| {
+//!     let mut file = archive.open("notes.txt")?;
+//!     file.create_file()?;
+//!     file.write_str("Hello, world!")
+//! })?;
+//!
+//! let mut catalog = Catalog::open_in_memory()?;
+//! catalog.add(&archive_path)?;
+//!
+//! let matches = catalog.find_path("notes.txt")?;
+//!
+//! assert_eq!(matches.len(), 1);
+//! assert_eq!(matches[0].archive(), archive_path);
+//! # sqlarfs::Result::Ok(())
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use rusqlite::OptionalExtension;
+
+use super::digest::Digest;
+use super::file::normalize_path;
+use super::metadata::{FileMetadata, FileType};
+use super::transaction::Connection;
+
+/// A database that indexes the files in many archives.
+///
+/// Archives are identified by the path you pass to [`Catalog::add`]. The catalog only reflects
+/// the contents of each archive as of the last time it was added; to pick up changes, add it
+/// again.
+///
+/// See the [module docs](crate::catalog) for an example.
+#[derive(Debug)]
+pub struct Catalog {
+    conn: rusqlite::Connection,
+}
+
+/// A file in an archive that matched a query against a [`Catalog`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogEntry {
+    archive: PathBuf,
+    path: PathBuf,
+    kind: FileType,
+    size: Option<u64>,
+    digest: Option<Digest>,
+}
+
+impl CatalogEntry {
+    /// The path of the archive that contains this file.
+    pub fn archive(&self) -> &Path {
+        &self.archive
+    }
+
+    /// The path of the file in the archive.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The type of the file.
+    pub fn kind(&self) -> FileType {
+        self.kind
+    }
+
+    /// The uncompressed size of the file, or `None` if it's not a regular file.
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// The SHA-256 digest of the contents of the file, or `None` if it's not a regular file.
+    ///
+    /// This is the same digest returned by [`File::digest`].
+    ///
+    /// [`File::digest`]: crate::File::digest
+    pub fn digest(&self) -> Option<Digest> {
+        self.digest
+    }
+}
+
+fn file_type_name(kind: FileType) -> &'static str {
+    match kind {
+        FileType::File => "file",
+        FileType::Dir => "dir",
+        FileType::Symlink => "symlink",
+    }
+}
+
+fn file_type_from_name(name: &str) -> rusqlite::Result<FileType> {
+    match name {
+        "file" => Ok(FileType::File),
+        "dir" => Ok(FileType::Dir),
+        "symlink" => Ok(FileType::Symlink),
+        _ => Err(rusqlite::Error::InvalidColumnType(
+            2,
+            String::from("kind"),
+            rusqlite::types::Type::Text,
+        )),
+    }
+}
+
+fn entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<CatalogEntry> {
+    let archive: String = row.get(0)?;
+    let path: String = row.get(1)?;
+    let kind: String = row.get(2)?;
+    let size: Option<u64> = row.get(3)?;
+    let digest: Option<[u8; 32]> = row.get(4)?;
+
+    Ok(CatalogEntry {
+        archive: archive.into(),
+        path: path.into(),
+        kind: file_type_from_name(&kind)?,
+        size,
+        digest: digest.map(Digest::from_bytes),
+    })
+}
+
+// A file read out of an archive, ready to be inserted into the catalog.
+struct IndexedFile {
+    path: String,
+    kind: FileType,
+    size: Option<u64>,
+    digest: Option<Digest>,
+}
+
+fn index_archive(conn: &mut Connection) -> crate::Result<Vec<IndexedFile>> {
+    conn.exec(|archive| {
+        // We can't read the contents of files while we're still iterating over the list.
+        let entries = archive.list()?.collect::<crate::Result<Vec<_>>>()?;
+
+        let mut files = Vec::with_capacity(entries.len());
+
+        for entry in entries {
+            let path = entry.path().to_string_lossy().into_owned();
+
+            let (size, digest) = match entry.metadata() {
+                FileMetadata::File { size, .. } => {
+                    (Some(*size), Some(archive.open(&path)?.digest()?))
+                }
+                _ => (None, None),
+            };
+
+            files.push(IndexedFile {
+                path,
+                kind: entry.metadata().kind(),
+                size,
+                digest,
+            });
+        }
+
+        Ok(files)
+    })
+}
+
+impl Catalog {
+    fn new(conn: rusqlite::Connection) -> crate::Result<Self> {
+        // Entries are deleted along with their archive.
+        conn.pragma_update(None, "foreign_keys", true)?;
+
+        conn.execute_batch(
+            "
+            CREATE TABLE IF NOT EXISTS sqlarfs_catalog_archives (
+                id INTEGER PRIMARY KEY,
+                path TEXT NOT NULL UNIQUE
+            );
+
+            CREATE TABLE IF NOT EXISTS sqlarfs_catalog_entries (
+                archive INTEGER NOT NULL
+                    REFERENCES sqlarfs_catalog_archives (id) ON DELETE CASCADE,
+                name TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                sz INTEGER,
+                sha256 BLOB,
+                PRIMARY KEY (archive, name)
+            );
+
+            CREATE INDEX IF NOT EXISTS sqlarfs_catalog_entries_by_name
+                ON sqlarfs_catalog_entries (name);
+
+            CREATE INDEX IF NOT EXISTS sqlarfs_catalog_entries_by_sha256
+                ON sqlarfs_catalog_entries (sha256);
+            ",
+        )?;
+
+        Ok(Self { conn })
+    }
+
+    /// Create or open the catalog database at `path`.
+    ///
+    /// # Errors
+    ///
+    /// - [`NotADatabase`]: The file at `path` exists but is not a SQLite database.
+    ///
+    /// [`NotADatabase`]: crate::Error::NotADatabase
+    pub fn open<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
+        Self::new(rusqlite::Connection::open(path)?)
+    }
+
+    /// Create a new in-memory catalog.
+    pub fn open_in_memory() -> crate::Result<Self> {
+        Self::new(rusqlite::Connection::open_in_memory()?)
+    }
+
+    /// Index the files in the archive at `archive_path`.
+    ///
+    /// If the archive is already in the catalog, this replaces its entries with the current
+    /// contents of the archive. The archive is opened read-only.
+    ///
+    /// # Errors
+    ///
+    /// - [`CannotOpen`]: The archive could not be opened because it does not exist.
+    /// - [`NotADatabase`]: The file at `archive_path` is not a SQLite database.
+    /// - [`CompressionNotSupported`]: A file in the archive is compressed, but the `deflate`
+    ///   Cargo feature is disabled.
+    ///
+    /// [`CannotOpen`]: crate::Error::CannotOpen
+    /// [`NotADatabase`]: crate::Error::NotADatabase
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    pub fn add<P: AsRef<Path>>(&mut self, archive_path: P) -> crate::Result<()> {
+        let archive_path = archive_path.as_ref();
+        let files = index_archive(&mut Connection::open_readonly(archive_path)?)?;
+
+        let tx = self.conn.transaction()?;
+
+        tx.execute(
+            "DELETE FROM sqlarfs_catalog_archives WHERE path = ?1",
+            (archive_path.to_string_lossy(),),
+        )?;
+
+        tx.execute(
+            "INSERT INTO sqlarfs_catalog_archives (path) VALUES (?1)",
+            (archive_path.to_string_lossy(),),
+        )?;
+
+        let archive_id = tx.last_insert_rowid();
+
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO sqlarfs_catalog_entries (archive, name, kind, sz, sha256) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+
+            for file in &files {
+                stmt.execute((
+                    archive_id,
+                    &file.path,
+                    file_type_name(file.kind),
+                    file.size,
+                    file.digest
+                        .as_ref()
+                        .map(|digest| digest.as_bytes().as_slice()),
+                ))?;
+            }
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Remove the archive at `archive_path` from the catalog.
+    ///
+    /// This returns `false` if the archive wasn't in the catalog. This doesn't touch the archive
+    /// itself.
+    pub fn remove<P: AsRef<Path>>(&mut self, archive_path: P) -> crate::Result<bool> {
+        let deleted = self.conn.execute(
+            "DELETE FROM sqlarfs_catalog_archives WHERE path = ?1",
+            (archive_path.as_ref().to_string_lossy(),),
+        )?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Return the paths of the archives in the catalog, sorted by path.
+    pub fn archives(&self) -> crate::Result<Vec<PathBuf>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT path FROM sqlarfs_catalog_archives ORDER BY path")?;
+
+        let paths = stmt
+            .query_map((), |row| row.get::<_, String>(0))?
+            .map(|path| path.map(PathBuf::from))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(paths)
+    }
+
+    /// Return whether the archive at `archive_path` is in the catalog.
+    pub fn contains<P: AsRef<Path>>(&self, archive_path: P) -> crate::Result<bool> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT 1 FROM sqlarfs_catalog_archives WHERE path = ?1",
+                (archive_path.as_ref().to_string_lossy(),),
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+
+    fn find(
+        &self,
+        condition: &str,
+        param: &dyn rusqlite::ToSql,
+    ) -> crate::Result<Vec<CatalogEntry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "
+            SELECT archives.path, entries.name, entries.kind, entries.sz, entries.sha256
+            FROM sqlarfs_catalog_entries AS entries
+            JOIN sqlarfs_catalog_archives AS archives ON archives.id = entries.archive
+            WHERE {condition}
+            ORDER BY archives.path, entries.name
+            "
+        ))?;
+
+        let entries = stmt
+            .query_map([param], entry_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(entries)
+    }
+
+    /// Return the files at `path` in every archive in the catalog.
+    ///
+    /// The results are sorted by archive path.
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: The given `path` is empty or absolute.
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    pub fn find_path<P: AsRef<Path>>(&self, path: P) -> crate::Result<Vec<CatalogEntry>> {
+        let path = normalize_path(path.as_ref())?;
+
+        self.find("entries.name = ?1", &path)
+    }
+
+    /// Return the regular files whose contents have the given `digest` in every archive in the
+    /// catalog.
+    ///
+    /// You can get the digest of a file with [`File::digest`] or [`Digest::from_reader`]. The
+    /// results are sorted by archive path and then by path in the archive.
+    ///
+    /// [`File::digest`]: crate::File::digest
+    pub fn find_digest(&self, digest: &Digest) -> crate::Result<Vec<CatalogEntry>> {
+        self.find("entries.sha256 = ?1", &digest.as_bytes().as_slice())
+    }
+}
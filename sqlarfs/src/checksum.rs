@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+
+use super::archive::Archive;
+use super::digest::{digest_stream_with, DigestAlgorithm};
+use super::stream::FileReader;
+use super::util::u64_from_usize;
+
+impl<'conn> Archive<'conn> {
+    pub(super) fn compute_checksums(&self, algorithm: DigestAlgorithm) -> crate::Result<u64> {
+        self.store.exec(|store| {
+            let files = store.file_sizes(None)?;
+
+            for (path, _) in &files {
+                let mut reader = FileReader::new(store.open_blob(path, true)?)?;
+                let digest = digest_stream_with(&mut reader, algorithm)?;
+
+                // Close the blob handle before we touch the database again.
+                drop(reader);
+
+                store.set_checksum(path, algorithm.name(), digest.as_bytes())?;
+            }
+
+            Ok(u64_from_usize(files.len()))
+        })
+    }
+
+    pub(super) fn check_checksums(&self) -> crate::Result<Vec<PathBuf>> {
+        let mut mismatched = Vec::new();
+
+        for (path, algorithm, expected) in self.store.checksums()? {
+            let algorithm = DigestAlgorithm::from_name(&algorithm)?;
+            let mut reader = FileReader::new(self.store.open_blob(&path, true)?)?;
+
+            let matches = match digest_stream_with(&mut reader, algorithm) {
+                Ok(digest) => digest.as_bytes().as_slice() == expected.as_slice(),
+                // If the file can't be decompressed, its contents are corrupt.
+                Err(err) => match crate::Error::from(err) {
+                    crate::Error::Io { .. } => false,
+                    err => return Err(err),
+                },
+            };
+
+            if !matches {
+                mismatched.push(PathBuf::from(path));
+            }
+        }
+
+        Ok(mismatched)
+    }
+}
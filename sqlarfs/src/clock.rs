@@ -0,0 +1,40 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+type ClockFn = dyn Fn() -> SystemTime + Send + Sync;
+
+// Where the current time comes from when giving files an mtime. See `Archive::set_clock`.
+#[derive(Clone, Default)]
+pub(super) struct Clock {
+    now: Option<Arc<ClockFn>>,
+}
+
+impl fmt::Debug for Clock {
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Clock")
+            .field("now", &self.now.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+impl Clock {
+    pub fn new<F>(now: F) -> Self
+    where
+        F: Fn() -> SystemTime + Send + Sync + 'static,
+    {
+        Self {
+            now: Some(Arc::new(now)),
+        }
+    }
+
+    // The current time according to this clock, which is the system time unless it's been
+    // overridden.
+    pub fn now(&self) -> SystemTime {
+        match &self.now {
+            Some(now) => now(),
+            None => SystemTime::now(),
+        }
+    }
+}
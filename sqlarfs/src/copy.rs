@@ -0,0 +1,123 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::archive::Archive;
+use super::file::normalize_path;
+use super::rename::{check_dest, check_tree_dest};
+use super::store::RawData;
+use super::stream::{Compression, FileReader};
+
+impl<'conn> Archive<'conn> {
+    // Copy the contents of the regular file at `from` into the regular file at `to`. If
+    // `compression` is `None`, the contents are copied as they're stored.
+    pub(super) fn copy_file_data(
+        &self,
+        from: &Path,
+        to: &Path,
+        compression: Option<Compression>,
+    ) -> crate::Result<()> {
+        let from = normalize_path(from)?;
+        let to = normalize_path(to)?;
+
+        self.store.exec(|store| {
+            for path in [&from, &to] {
+                if !store.read_metadata(path)?.is_file() {
+                    return Err(crate::Error::NotARegularFile {
+                        path: PathBuf::from(path),
+                    });
+                }
+            }
+
+            let mut dest = self.open_unclaimed_mut(&to)?;
+
+            let Some(method) = compression else {
+                // Copying the stored bytes of a file onto itself would overwrite them before we
+                // read them, and it wouldn't change anything anyways.
+                if from != to {
+                    store.copy_blob(&from, &to)?;
+                    dest.touch_after_write(store)?;
+                }
+
+                return Ok(());
+            };
+
+            let mut reader = FileReader::new(store.open_blob(&from, true)?)?;
+            dest.set_compression(method);
+
+            if from == to {
+                // We can't read from the file while we're overwriting it, so we write the new
+                // contents somewhere else first.
+                dest.replace_contents_atomic(&mut reader)
+            } else {
+                let size = store.blob_size(&from)?.original;
+                dest.write_stream(&mut reader, Some(size))
+            }
+        })
+    }
+
+    pub(super) fn copy_tree(&self, from: &Path, to: &Path) -> crate::Result<()> {
+        let from = normalize_path(from)?;
+        let to = normalize_path(to)?;
+
+        self.store.exec(|store| {
+            // Make sure the source exists before doing anything else.
+            store.read_metadata(&from)?;
+
+            if check_tree_dest(store, &from, &to)? {
+                return Err(crate::Error::FileAlreadyExists {
+                    path: PathBuf::from(&to),
+                });
+            }
+
+            store.copy_files(&from, &to)?;
+
+            Ok(())
+        })
+    }
+
+    // Copy the file at `from` and all its descendants in this archive to `to` in `dest`.
+    pub(super) fn copy_tree_to(&self, dest: &Archive, from: &Path, to: &Path) -> crate::Result<()> {
+        let from = normalize_path(from)?;
+        let to = normalize_path(to)?;
+
+        self.store.exec(|src_store| {
+            let files = src_store.raw_files(&from)?;
+
+            dest.store.exec(|dest_store| {
+                // The source and destination are in different archives, so they can't overlap.
+                if check_dest(dest_store, &to)? {
+                    return Err(crate::Error::FileAlreadyExists {
+                        path: PathBuf::from(&to),
+                    });
+                }
+
+                // Files compressed with a shared dictionary can't be read without it.
+                #[cfg(feature = "zstd")]
+                for (id, dictionary) in src_store.dictionaries()? {
+                    dest_store.insert_dictionary(id, &dictionary, false)?;
+                }
+
+                for file in &files {
+                    let dest_path = format!("{}{}", to, &file.name[from.len()..]);
+
+                    dest_store.insert_raw_file(&dest_path, file)?;
+
+                    if let RawData::Blob(len) = file.data {
+                        if len > 0 {
+                            let mut source = src_store.open_blob(&file.name, true)?.into_blob();
+                            let mut dest = dest_store.open_blob(&dest_path, false)?.into_blob();
+
+                            io::copy(&mut source, &mut dest)?;
+                        }
+                    }
+
+                    for (key, value) in src_store.list_meta(&file.name)? {
+                        dest_store.set_meta(&dest_path, &key, &value)?;
+                    }
+                }
+
+                Ok(())
+            })
+        })
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+
+use super::archive::Archive;
+use super::store::Store;
+
+impl<'conn> Archive<'conn> {
+    pub(super) fn dedup_files(&self) -> crate::Result<u64> {
+        self.store.exec(|store| {
+            let mut paths_by_hash = HashMap::<Vec<u8>, Vec<String>>::new();
+
+            for (path, _) in store.file_sizes(None)? {
+                if let Some(hash) = store.dedup_hash(&path)? {
+                    paths_by_hash.entry(hash).or_default().push(path);
+                }
+            }
+
+            let mut num_shared = 0;
+
+            // Contents that only one file has stay where they are, so other tools can still read
+            // them.
+            for (hash, paths) in paths_by_hash {
+                if paths.len() < 2 && !store.has_dedup_contents(&hash)? {
+                    // Remember the hash so files added later can find this one without reading
+                    // it again.
+                    for path in paths {
+                        store.set_dedup_hash(&path, &hash)?;
+                    }
+
+                    continue;
+                }
+
+                for path in paths {
+                    if store.dedup_file(&path, &hash)? {
+                        num_shared += 1;
+                    }
+                }
+            }
+
+            Ok(num_shared)
+        })
+    }
+}
+
+// Share the contents of the file at `path` with the other files that have the same contents, if
+// there are any. Only the files whose hashes were recorded by `Store::set_dedup_hash` are
+// compared, so this reads only the file at `path`. See `Archive::deduplicate`.
+//
+// This returns `true` if another file already had the same contents.
+pub(super) fn dedup_file(store: &Store, path: &str) -> crate::Result<bool> {
+    store.exec(|store| {
+        let Some(hash) = store.dedup_hash(path)? else {
+            return Ok(false);
+        };
+
+        if store.has_dedup_contents(&hash)? {
+            return store.dedup_file(path, &hash);
+        }
+
+        if let Some(other) = store.find_dedup_hash(&hash, path)? {
+            store.dedup_file(&other, &hash)?;
+            return store.dedup_file(path, &hash);
+        }
+
+        // Remember the hash so files added later with the same contents can find this one
+        // without reading it again.
+        store.set_dedup_hash(path, &hash)?;
+
+        Ok(false)
+    })
+}
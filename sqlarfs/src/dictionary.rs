@@ -0,0 +1,94 @@
+use std::io::{self, Read};
+use std::num::NonZeroU32;
+
+use rusqlite::blob::Blob;
+use zstd::zstd_safe;
+
+use super::archive::Archive;
+use super::store::Store;
+use super::stream::FileReader;
+use super::util::{looks_like_text, u64_from_usize};
+
+// Files larger than this are neither used to train the dictionary nor compressed with it. Large
+// files have enough redundancy of their own that a dictionary doesn't help much.
+const DICTIONARY_MAX_FILE_SIZE: u64 = 64 * 1024;
+
+// The maximum size of a trained dictionary. This is the default used by the `zstd` CLI.
+const DICTIONARY_MAX_SIZE: usize = 110 * 1024;
+
+// The most sample data we'll read into memory to train a dictionary. Zstandard recommends about
+// 100 times as much sample data as the size of the dictionary.
+const MAX_SAMPLE_BYTES: u64 = 100 * DICTIONARY_MAX_SIZE as u64;
+
+// The largest a Zstandard frame header can be.
+const MAX_FRAME_HEADER_SIZE: usize = 18;
+
+// Whether `bytes` are small text, which is what the dictionary is for.
+fn is_small_text(bytes: &[u8]) -> bool {
+    u64_from_usize(bytes.len()) <= DICTIONARY_MAX_FILE_SIZE && looks_like_text(bytes)
+}
+
+// Compress `bytes` with Zstandard, using the archive's dictionary if it has one and `bytes` are
+// small text.
+pub(super) fn zstd_compress(store: &Store, bytes: &[u8], level: i32) -> crate::Result<Vec<u8>> {
+    if is_small_text(bytes) {
+        if let Some(dictionary) = store.active_dictionary()? {
+            let mut compressor = zstd::bulk::Compressor::with_dictionary(level, &dictionary)?;
+            return Ok(compressor.compress(bytes)?);
+        }
+    }
+
+    Ok(zstd::bulk::compress(bytes, level)?)
+}
+
+// The ID of the dictionary that the data in `blob` was compressed with, or `None` if it wasn't
+// compressed with a dictionary or wasn't compressed with Zstandard.
+pub(super) fn frame_dictionary_id(blob: &Blob<'_>) -> crate::Result<Option<u32>> {
+    let mut header = [0u8; MAX_FRAME_HEADER_SIZE];
+    let header_len = header.len().min(blob.len());
+
+    blob.read_at_exact(&mut header[..header_len], 0)?;
+
+    Ok(zstd_safe::get_dict_id_from_frame(&header[..header_len]).map(NonZeroU32::get))
+}
+
+impl<'conn> Archive<'conn> {
+    pub(super) fn train_zstd_dictionary(&self) -> crate::Result<()> {
+        self.store.exec(|store| {
+            let mut samples = Vec::new();
+            let mut sample_bytes = 0;
+
+            for (path, size) in store.file_sizes(None)? {
+                if size.original == 0 || size.original > DICTIONARY_MAX_FILE_SIZE {
+                    continue;
+                }
+
+                if sample_bytes + size.original > MAX_SAMPLE_BYTES {
+                    break;
+                }
+
+                let mut contents = Vec::new();
+                FileReader::new(store.open_blob(&path, true)?)?.read_to_end(&mut contents)?;
+
+                if is_small_text(&contents) {
+                    sample_bytes += size.original;
+                    samples.push(contents);
+                }
+            }
+
+            let dictionary = zstd::dict::from_samples(&samples, DICTIONARY_MAX_SIZE)?;
+
+            // Frames only record which dictionary they were compressed with if the dictionary has
+            // an ID, and we need that to find the dictionary again when reading them. Trained
+            // dictionaries always have one.
+            let id = zstd_safe::get_dict_id_from_dict(&dictionary).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "The trained dictionary does not have an ID.",
+                )
+            })?;
+
+            store.insert_dictionary(id.get(), &dictionary, true)
+        })
+    }
+}
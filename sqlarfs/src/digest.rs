@@ -0,0 +1,309 @@
+use std::fmt;
+use std::io;
+use std::time::UNIX_EPOCH;
+
+use sha2::{Digest as _, Sha256};
+
+use super::archive::Archive;
+use super::list::{ListOptions, ListSort};
+use super::metadata::FileMetadata;
+use super::util::u64_from_usize;
+
+/// A hash algorithm for computing the [`Digest`] of a file.
+///
+/// This is used with [`File::digest_with`] and [`Archive::store_checksums`].
+///
+/// [`File::digest_with`]: crate::File::digest_with
+/// [`Archive::store_checksums`]: crate::Archive::store_checksums
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum DigestAlgorithm {
+    /// SHA-256.
+    #[default]
+    Sha256,
+
+    /// BLAKE3, which is much faster than SHA-256.
+    ///
+    /// This requires the `blake3` Cargo feature.
+    #[cfg(feature = "blake3")]
+    Blake3,
+}
+
+impl DigestAlgorithm {
+    // The name of the algorithm as it's stored in the archive.
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            #[cfg(feature = "blake3")]
+            Self::Blake3 => "blake3",
+        }
+    }
+
+    pub(super) fn from_name(name: &str) -> crate::Result<Self> {
+        match name {
+            "sha256" => Ok(Self::Sha256),
+            #[cfg(feature = "blake3")]
+            "blake3" => Ok(Self::Blake3),
+            _ => Err(crate::Error::DigestNotSupported {
+                algorithm: name.to_owned(),
+            }),
+        }
+    }
+}
+
+/// A cryptographic digest of the contents of an archive or a file.
+///
+/// This is returned by [`Archive::content_digest`] and [`File::digest`]. You can format it as a hex
+/// string with its [`Display`] implementation.
+///
+/// You can compute the digest of a file outside of the archive with [`Digest::from_reader`] to
+/// compare it against the digest of a file in the archive.
+///
+/// Two digests are only equal if they were computed with the same [`DigestAlgorithm`].
+///
+/// [`Archive::content_digest`]: crate::Archive::content_digest
+/// [`File::digest`]: crate::File::digest
+/// [`Display`]: std::fmt::Display
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Digest {
+    algorithm: DigestAlgorithm,
+    bytes: [u8; 32],
+}
+
+impl Digest {
+    pub(super) fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self {
+            algorithm: DigestAlgorithm::Sha256,
+            bytes,
+        }
+    }
+
+    /// The raw bytes of the digest.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.bytes
+    }
+
+    /// The hash algorithm this digest was computed with.
+    pub fn algorithm(&self) -> DigestAlgorithm {
+        self.algorithm
+    }
+
+    /// Compute the SHA-256 digest of everything read from `reader`.
+    ///
+    /// This produces the same digest as [`File::digest`] for the same contents.
+    ///
+    /// # Errors
+    ///
+    /// This returns any error returned by the `reader`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Digest;
+    /// let digest = Digest::from_reader("Hello, world!".as_bytes())?;
+    ///
+    /// assert_eq!(
+    ///     digest.to_string(),
+    ///     "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3",
+    /// );
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`File::digest`]: crate::File::digest
+    pub fn from_reader<R: io::Read>(mut reader: R) -> io::Result<Self> {
+        digest_stream(&mut reader)
+    }
+
+    /// Compute the digest of everything read from `reader` using the given hash `algorithm`.
+    ///
+    /// This produces the same digest as [`File::digest_with`] for the same contents.
+    ///
+    /// # Errors
+    ///
+    /// This returns any error returned by the `reader`.
+    ///
+    /// [`File::digest_with`]: crate::File::digest_with
+    pub fn from_reader_with<R: io::Read>(
+        mut reader: R,
+        algorithm: DigestAlgorithm,
+    ) -> io::Result<Self> {
+        digest_stream_with(&mut reader, algorithm)
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.bytes {
+            write!(f, "{byte:02x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Options for computing a digest of the contents of an archive.
+///
+/// This is used with [`Archive::content_digest_with`].
+///
+/// [`Archive::content_digest_with`]: crate::Archive::content_digest_with
+#[derive(Debug, Clone)]
+pub struct DigestOptions {
+    include_mode: bool,
+    include_mtime: bool,
+}
+
+impl Default for DigestOptions {
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DigestOptions {
+    /// Create a new [`DigestOptions`] with default settings.
+    pub fn new() -> Self {
+        Self {
+            include_mode: true,
+            include_mtime: true,
+        }
+    }
+
+    /// Include file modes in the digest.
+    ///
+    /// The default is `true`.
+    pub fn include_mode(mut self, include: bool) -> Self {
+        self.include_mode = include;
+        self
+    }
+
+    /// Include file mtimes in the digest.
+    ///
+    /// The default is `true`.
+    pub fn include_mtime(mut self, include: bool) -> Self {
+        self.include_mtime = include;
+        self
+    }
+}
+
+// Every variable-length field is prefixed with its length so that different sequences of files
+// can't produce the same encoding.
+fn hash_bytes(hasher: &mut Sha256, bytes: &[u8]) {
+    hasher.update(u64_from_usize(bytes.len()).to_le_bytes());
+    hasher.update(bytes);
+}
+
+// Optional fields are prefixed with a byte indicating whether they're present.
+fn hash_optional(hasher: &mut Sha256, value: Option<&[u8]>) {
+    match value {
+        Some(bytes) => {
+            hasher.update([1]);
+            hasher.update(bytes);
+        }
+        None => hasher.update([0]),
+    }
+}
+
+// Compute the SHA-256 of the contents of a stream.
+pub(super) fn digest_stream<R: ?Sized + io::Read>(reader: &mut R) -> io::Result<Digest> {
+    digest_stream_with(reader, DigestAlgorithm::Sha256)
+}
+
+// Compute the digest of the contents of a stream using `algorithm`.
+pub(super) fn digest_stream_with<R: ?Sized + io::Read>(
+    reader: &mut R,
+    algorithm: DigestAlgorithm,
+) -> io::Result<Digest> {
+    let bytes = match algorithm {
+        DigestAlgorithm::Sha256 => {
+            let mut hasher = Sha256::new();
+            io::copy(reader, &mut HashWriter(&mut hasher))?;
+            hasher.finalize().into()
+        }
+        #[cfg(feature = "blake3")]
+        DigestAlgorithm::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            io::copy(reader, &mut hasher)?;
+            hasher.finalize().into()
+        }
+    };
+
+    Ok(Digest { algorithm, bytes })
+}
+
+impl<'conn> Archive<'conn> {
+    pub(super) fn digest_archive(&self, opts: &DigestOptions) -> crate::Result<Digest> {
+        let list_opts = ListOptions {
+            sort: Some(ListSort::Name),
+            ..ListOptions::new()
+        };
+
+        // We can't read the contents of files while we're still iterating over the list.
+        let entries = self
+            .list_with(&list_opts)?
+            .collect::<crate::Result<Vec<_>>>()?;
+
+        let mut hasher = Sha256::new();
+
+        for entry in entries {
+            let path = entry.path().to_string_lossy().into_owned();
+            let metadata = entry.metadata();
+
+            hash_bytes(&mut hasher, path.as_bytes());
+
+            let type_tag: u8 = match metadata {
+                FileMetadata::File { .. } => 0,
+                FileMetadata::Dir { .. } => 1,
+                FileMetadata::Symlink { .. } => 2,
+            };
+
+            hasher.update([type_tag]);
+
+            if opts.include_mode {
+                let mode = metadata.mode().map(|mode| mode.bits().to_le_bytes());
+                hash_optional(&mut hasher, mode.as_ref().map(|mode| mode.as_slice()));
+            }
+
+            if opts.include_mtime {
+                let mtime = metadata
+                    .mtime()
+                    .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+                    .map(|duration| duration.as_secs().to_le_bytes());
+                hash_optional(&mut hasher, mtime.as_ref().map(|mtime| mtime.as_slice()));
+            }
+
+            match metadata {
+                FileMetadata::File { size, .. } => {
+                    // Hash the uncompressed contents so that the digest doesn't depend on how the
+                    // file was compressed.
+                    hasher.update(size.to_le_bytes());
+
+                    let mut file = self.open_unclaimed(&path)?;
+                    let mut reader = file.reader()?;
+
+                    io::copy(&mut reader, &mut HashWriter(&mut hasher))?;
+                }
+                FileMetadata::Symlink { target, .. } => {
+                    hash_bytes(&mut hasher, target.to_string_lossy().as_bytes());
+                }
+                FileMetadata::Dir { .. } => {}
+            }
+        }
+
+        Ok(Digest::from_bytes(hasher.finalize().into()))
+    }
+}
+
+struct HashWriter<'a>(&'a mut Sha256);
+
+impl<'a> io::Write for HashWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use std::io::{self, Read};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::file::File;
+
+// Errors from serializing or deserializing a value are reported as `InvalidData` I/O errors.
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+impl<'conn, 'ar> File<'conn, 'ar> {
+    // Read the entire decompressed contents of the file into memory.
+    fn read_to_vec(&mut self) -> crate::Result<Vec<u8>> {
+        let mut contents = Vec::new();
+        self.reader()?.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    /// Overwrite the file with `value` serialized as JSON.
+    ///
+    /// This truncates the file and writes the entire serialized value to it, the same as
+    /// [`File::write_bytes`]. This requires the `json` Cargo feature.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    /// - [`Io`]: `value` can't be serialized as JSON. The error kind is
+    ///   [`InvalidData`](std::io::ErrorKind::InvalidData).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut file = archive.open("settings.json")?;
+    /// file.create_file()?;
+    ///
+    /// let settings = HashMap::from([("theme", "dark")]);
+    /// file.write_json(&settings)?;
+    ///
+    /// let actual: HashMap<String, String> = file.read_json()?;
+    /// assert_eq!(actual["theme"], "dark");
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    /// [`Io`]: crate::Error::Io
+    #[cfg(feature = "json")]
+    pub fn write_json<T: ?Sized + Serialize>(&mut self, value: &T) -> crate::Result<()> {
+        let bytes = serde_json::to_vec(value).map_err(invalid_data)?;
+        self.write_bytes(&bytes)
+    }
+
+    /// Read the contents of the file as JSON and deserialize them.
+    ///
+    /// This reads the whole file into memory. This requires the `json` Cargo feature.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    /// - [`CompressionNotSupported`]: This file is compressed, but the `deflate` Cargo feature is
+    ///   disabled.
+    /// - [`Io`]: The file isn't valid JSON or doesn't match the type `T`. The error kind is
+    ///   [`InvalidData`](std::io::ErrorKind::InvalidData).
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    /// [`Io`]: crate::Error::Io
+    #[cfg(feature = "json")]
+    pub fn read_json<T: DeserializeOwned>(&mut self) -> crate::Result<T> {
+        let contents = self.read_to_vec()?;
+        Ok(serde_json::from_slice(&contents).map_err(invalid_data)?)
+    }
+
+    /// Overwrite the file with `value` serialized as MessagePack.
+    ///
+    /// Structs are serialized as maps with their field names, so fields can be added and
+    /// reordered without breaking existing files. This truncates the file and writes the entire
+    /// serialized value to it, the same as [`File::write_bytes`]. This requires the `msgpack`
+    /// Cargo feature.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    /// - [`Io`]: `value` can't be serialized as MessagePack. The error kind is
+    ///   [`InvalidData`](std::io::ErrorKind::InvalidData).
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    /// [`Io`]: crate::Error::Io
+    #[cfg(feature = "msgpack")]
+    pub fn write_msgpack<T: ?Sized + Serialize>(&mut self, value: &T) -> crate::Result<()> {
+        let bytes = rmp_serde::to_vec_named(value).map_err(invalid_data)?;
+        self.write_bytes(&bytes)
+    }
+
+    /// Read the contents of the file as MessagePack and deserialize them.
+    ///
+    /// This reads the whole file into memory. This requires the `msgpack` Cargo feature.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    /// - [`CompressionNotSupported`]: This file is compressed, but the `deflate` Cargo feature is
+    ///   disabled.
+    /// - [`Io`]: The file isn't valid MessagePack or doesn't match the type `T`. The error kind is
+    ///   [`InvalidData`](std::io::ErrorKind::InvalidData).
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    /// [`Io`]: crate::Error::Io
+    #[cfg(feature = "msgpack")]
+    pub fn read_msgpack<T: DeserializeOwned>(&mut self) -> crate::Result<T> {
+        let contents = self.read_to_vec()?;
+        Ok(rmp_serde::from_slice(&contents).map_err(invalid_data)?)
+    }
+}
@@ -1,7 +1,9 @@
 use std::fmt;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::result;
+use std::slice;
+use std::vec;
 use thiserror::Error;
 
 /// An opaque type representing a SQLite error code.
@@ -33,6 +35,24 @@ impl SqliteErrorCode {
     }
 }
 
+/// A broad category of [`Error`](enum@Error), for deciding how to recover from it.
+///
+/// See [`Error::category`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCategory {
+    /// The operation failed because of contention with another connection or process, and may
+    /// succeed if it's tried again later.
+    Retryable,
+
+    /// The operation conflicts with something that already exists, and won't succeed until that's
+    /// resolved.
+    Conflict,
+
+    /// The operation will fail the same way no matter how many times it's tried.
+    Permanent,
+}
+
 /// The error type for sqlarfs.
 ///
 /// This type can be converted [`From`] an [`std::io::Error`]. If the value the [`std::io::Error`]
@@ -101,6 +121,16 @@ pub enum Error {
     #[error("Attempted to read a compressed file, but sqlarfs was compiled without compression support.")]
     CompressionNotSupported,
 
+    /// Attempted to verify a checksum computed with a hash algorithm that this build of sqlarfs
+    /// doesn't support, like BLAKE3 when the `blake3` Cargo feature is disabled.
+    #[error(
+        "Attempted to verify a checksum computed with an unsupported hash algorithm: {algorithm}"
+    )]
+    DigestNotSupported {
+        /// The name of the hash algorithm.
+        algorithm: String,
+    },
+
     /// Attempted to write more data to the SQLite archive than its maximum blob size will allow.
     #[error(
         "Attempted to write more data to the SQLite archive than its maximum blob size will allow."
@@ -123,6 +153,75 @@ pub enum Error {
     #[error("Attempted to create a new SQLite archive, but one already exists.")]
     SqlarAlreadyExists,
 
+    /// Attempted to use a SQLite database that is not a SQLite archive, because it doesn't have a
+    /// `sqlar` table.
+    #[error(
+        "This SQLite database is not a SQLite archive because it has no `sqlar` table. {}",
+        describe_tables(tables)
+    )]
+    NotAnArchive {
+        /// The names of the tables the database does have, to help tell what it is.
+        tables: Vec<String>,
+    },
+
+    /// The filesystem doesn't support the file permissions or symbolic links being extracted.
+    #[error("The filesystem does not support the file permissions or symbolic links being extracted: {path}")]
+    UnsupportedMetadata {
+        /// The path of the directory being extracted into.
+        path: PathBuf,
+    },
+
+    /// A file is locked, and taking out the lock would have blocked.
+    #[error("This file is locked: {path}")]
+    WouldBlock {
+        /// The path of the file that is locked.
+        path: PathBuf,
+    },
+
+    /// Attempted to delete a file that is pinned.
+    #[error("This file is pinned and cannot be deleted: {path}")]
+    FilePinned {
+        /// The path of the file that is pinned.
+        path: PathBuf,
+    },
+
+    /// Extracting a file would have written outside of the directory being extracted into.
+    #[error("Extracting this file would have written outside of the directory being extracted into: {path}")]
+    PathEscapesRoot {
+        /// The path the file would have been extracted to.
+        path: PathBuf,
+    },
+
+    /// A file name was too long.
+    ///
+    /// This is returned when creating a file whose path is longer than the archive allows, or when
+    /// extracting a file whose name is longer than the filesystem allows.
+    #[error("This file name is too long: {path}")]
+    NameTooLong {
+        /// The path of the file whose name is too long.
+        path: PathBuf,
+    },
+
+    /// Attempted to open a file that already has an open handle in this transaction.
+    #[error("This file is already open: {path}")]
+    FileAlreadyOpen {
+        /// The path of the file that is already open.
+        path: PathBuf,
+    },
+
+    /// Attempted to change or delete a file that has an open handle in this transaction.
+    ///
+    /// Operations that change many files at once, like [`Archive::delete_matching`], return this
+    /// rather than changing a file out from under a [`File`] that's still in use.
+    ///
+    /// [`Archive::delete_matching`]: crate::Archive::delete_matching
+    /// [`File`]: crate::File
+    #[error("This file is in use: {path}")]
+    FileInUse {
+        /// The path of the file that is in use.
+        path: PathBuf,
+    },
+
     /// There was an error from the underlying SQLite database.
     #[error("There was an error from the underlying SQLite database: {code}")]
     Sqlite {
@@ -139,6 +238,228 @@ pub enum Error {
         /// The raw OS error code, if there is one.
         code: Option<i32>,
     },
+
+    /// Some files failed while archiving or extracting a tree of files with `keep_going` enabled.
+    ///
+    /// See [`ArchiveOptions::keep_going`] and [`ExtractOptions::keep_going`].
+    ///
+    /// [`ArchiveOptions::keep_going`]: crate::ArchiveOptions::keep_going
+    /// [`ExtractOptions::keep_going`]: crate::ExtractOptions::keep_going
+    #[error("{errors}")]
+    Multiple {
+        /// The error for each file that failed.
+        errors: ErrorBundle,
+    },
+}
+
+/// The errors for each file that failed in an operation that kept going after the first failure.
+///
+/// This is returned in [`Error::Multiple`]. It always holds at least one error, in the order the
+/// files failed. Iterating over it yields the path of each file that failed along with its error.
+///
+/// # Examples
+///
+/// ```
+/// # use sqlarfs::{ArchiveOptions, Connection, Error};
+/// let mut conn = Connection::open_in_memory()?;
+///
+/// conn.exec(|archive| {
+///     let opts = ArchiveOptions::new().keep_going(true);
+///
+///     if let Err(Error::Multiple { errors }) = archive.archive_with("src", "src", &opts) {
+///         for (path, err) in &errors {
+///             eprintln!("{}: {err}", path.display());
+///         }
+///     }
+///
+///     sqlarfs::Result::Ok(())
+/// })?;
+/// # sqlarfs::Result::Ok(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorBundle {
+    errors: Vec<(PathBuf, Error)>,
+}
+
+impl ErrorBundle {
+    pub(super) fn new(errors: Vec<(PathBuf, Error)>) -> Self {
+        Self { errors }
+    }
+
+    /// The number of files that failed.
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Whether there are no errors.
+    ///
+    /// This is never `true` for an [`ErrorBundle`] returned by sqlarfs.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Iterate over the path of each file that failed along with its error.
+    pub fn iter(&self) -> slice::Iter<'_, (PathBuf, Error)> {
+        self.errors.iter()
+    }
+}
+
+impl fmt::Display for ErrorBundle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let noun = if self.errors.len() == 1 {
+            "file"
+        } else {
+            "files"
+        };
+
+        write!(f, "{} {noun} failed.", self.errors.len())?;
+
+        if let Some((path, err)) = self.errors.first() {
+            write!(f, " The first was {}: {err}", path.display())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ErrorBundle {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.errors
+            .first()
+            .map(|(_, err)| err as &(dyn std::error::Error + 'static))
+    }
+}
+
+impl IntoIterator for ErrorBundle {
+    type Item = (PathBuf, Error);
+    type IntoIter = vec::IntoIter<(PathBuf, Error)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ErrorBundle {
+    type Item = &'a (PathBuf, Error);
+    type IntoIter = slice::Iter<'a, (PathBuf, Error)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.iter()
+    }
+}
+
+// Describe the tables in a database that isn't a SQLite archive, which helps users figure out if
+// they passed the wrong file.
+fn describe_tables(tables: &[String]) -> String {
+    if tables.is_empty() {
+        String::from("The database is empty.")
+    } else {
+        format!("The database has these tables: {}", tables.join(", "))
+    }
+}
+
+impl Error {
+    /// The [`ErrorCategory`] this error falls into.
+    ///
+    /// This is meant for code that wraps sqlarfs and needs to decide whether to retry an
+    /// operation without matching on every error variant.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::path::PathBuf;
+    /// use sqlarfs::{Error, ErrorCategory};
+    ///
+    /// let err = Error::FileAlreadyExists { path: PathBuf::from("file") };
+    ///
+    /// assert_eq!(err.category(), ErrorCategory::Conflict);
+    /// assert!(!err.is_retryable());
+    /// ```
+    pub fn category(&self) -> ErrorCategory {
+        // Don't use a default match arm here, for the same reason as the conversion into
+        // `io::Error`.
+        match self {
+            Error::WouldBlock { .. } => ErrorCategory::Retryable,
+            Error::FileAlreadyExists { .. } => ErrorCategory::Conflict,
+            Error::SqlarAlreadyExists => ErrorCategory::Conflict,
+            Error::FileAlreadyOpen { .. } => ErrorCategory::Conflict,
+            Error::FileInUse { .. } => ErrorCategory::Conflict,
+            Error::InvalidArgs { .. } => ErrorCategory::Permanent,
+            Error::FileNotFound { .. } => ErrorCategory::Permanent,
+            Error::NoParentDirectory { .. } => ErrorCategory::Permanent,
+            Error::NotARegularFile { .. } => ErrorCategory::Permanent,
+            Error::NotADirectory { .. } => ErrorCategory::Permanent,
+            Error::FilesystemLoop => ErrorCategory::Permanent,
+            Error::CompressionNotSupported => ErrorCategory::Permanent,
+            Error::DigestNotSupported { .. } => ErrorCategory::Permanent,
+            Error::FileTooBig => ErrorCategory::Permanent,
+            Error::ReadOnly => ErrorCategory::Permanent,
+            Error::CannotOpen => ErrorCategory::Permanent,
+            Error::NotADatabase => ErrorCategory::Permanent,
+            Error::NotAnArchive { .. } => ErrorCategory::Permanent,
+            Error::UnsupportedMetadata { .. } => ErrorCategory::Permanent,
+            Error::FilePinned { .. } => ErrorCategory::Permanent,
+            Error::PathEscapesRoot { .. } => ErrorCategory::Permanent,
+            Error::NameTooLong { .. } => ErrorCategory::Permanent,
+            Error::Sqlite { code } => match code.inner.map(|err| err.code) {
+                Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked) => {
+                    ErrorCategory::Retryable
+                }
+                _ => ErrorCategory::Permanent,
+            },
+            Error::Io { kind, .. } => match kind {
+                io::ErrorKind::WouldBlock
+                | io::ErrorKind::Interrupted
+                | io::ErrorKind::TimedOut => ErrorCategory::Retryable,
+                io::ErrorKind::AlreadyExists => ErrorCategory::Conflict,
+                _ => ErrorCategory::Permanent,
+            },
+            // The bundle only falls into a category if every error in it does.
+            Error::Multiple { errors } => {
+                let mut categories = errors.iter().map(|(_, err)| err.category());
+
+                match categories.next() {
+                    Some(first) if categories.all(|category| category == first) => first,
+                    _ => ErrorCategory::Permanent,
+                }
+            }
+        }
+    }
+
+    // Whether this error means the database itself is unusable, as opposed to a problem with a
+    // single file. Operations that keep going after a file fails still stop for these.
+    pub(super) fn is_database_error(&self) -> bool {
+        matches!(
+            self,
+            Error::Sqlite { .. } | Error::ReadOnly | Error::CannotOpen | Error::NotADatabase
+        )
+    }
+
+    /// Whether the operation may succeed if it's tried again later.
+    ///
+    /// This is the case when the database is busy or locked by another connection, or when a file
+    /// lock couldn't be taken without blocking.
+    ///
+    /// See [`Error::category`].
+    pub fn is_retryable(&self) -> bool {
+        self.category() == ErrorCategory::Retryable
+    }
+
+    /// Whether the operation conflicts with something that already exists, like a file at the
+    /// same path.
+    ///
+    /// See [`Error::category`].
+    pub fn is_conflict(&self) -> bool {
+        self.category() == ErrorCategory::Conflict
+    }
+
+    /// Whether the operation will fail the same way no matter how many times it's tried, like when
+    /// the arguments are invalid or the database is corrupt.
+    ///
+    /// See [`Error::category`].
+    pub fn is_permanent(&self) -> bool {
+        self.category() == ErrorCategory::Permanent
+    }
 }
 
 impl From<io::Error> for Error {
@@ -173,13 +494,24 @@ impl From<Error> for io::Error {
             // When it's stable, we can use `std::io::ErrorKind::FilesystemLoop`.
             Error::FilesystemLoop => io::ErrorKind::Other,
             Error::CompressionNotSupported => io::ErrorKind::Other,
+            Error::DigestNotSupported { .. } => io::ErrorKind::Unsupported,
             Error::FileTooBig => io::ErrorKind::Other,
             Error::ReadOnly => io::ErrorKind::Other,
             Error::CannotOpen => io::ErrorKind::Other,
             Error::NotADatabase => io::ErrorKind::Other,
             Error::SqlarAlreadyExists => io::ErrorKind::AlreadyExists,
+            Error::NotAnArchive { .. } => io::ErrorKind::Other,
+            Error::UnsupportedMetadata { .. } => io::ErrorKind::Unsupported,
+            Error::WouldBlock { .. } => io::ErrorKind::WouldBlock,
+            Error::FilePinned { .. } => io::ErrorKind::PermissionDenied,
+            Error::PathEscapesRoot { .. } => io::ErrorKind::PermissionDenied,
+            // When it's stable, we can use `std::io::ErrorKind::InvalidFilename`.
+            Error::NameTooLong { .. } => io::ErrorKind::InvalidInput,
+            Error::FileAlreadyOpen { .. } => io::ErrorKind::Other,
+            Error::FileInUse { .. } => io::ErrorKind::Other,
             Error::Sqlite { .. } => io::ErrorKind::Other,
             Error::Io { kind, .. } => kind,
+            Error::Multiple { .. } => io::ErrorKind::Other,
         };
 
         io::Error::new(kind, err)
@@ -218,9 +550,48 @@ impl From<rusqlite::Error> for Error {
 /// The result type for sqlarfs.
 pub type Result<T> = result::Result<T, Error>;
 
+// Collects the errors for files that failed in an operation that keeps going after a failure.
+#[derive(Debug, Default)]
+pub(super) struct ErrorCollector {
+    keep_going: bool,
+    errors: Vec<(PathBuf, Error)>,
+}
+
+impl ErrorCollector {
+    pub fn new(keep_going: bool) -> Self {
+        Self {
+            keep_going,
+            errors: Vec::new(),
+        }
+    }
+
+    // Record `result` for the file at `path`, returning the error if the operation should stop.
+    pub fn collect<T>(&mut self, path: &Path, result: Result<T>) -> Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if self.keep_going && !err.is_database_error() => {
+                self.errors.push((path.to_owned(), err));
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    // Return an `Error::Multiple` if any files failed.
+    pub fn finish(self) -> Result<()> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Multiple {
+                errors: ErrorBundle::new(self.errors),
+            })
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use xpct::{be_ok, be_some, equal, expect, match_pattern, pattern};
+    use xpct::{be_false, be_ok, be_some, be_true, equal, expect, match_pattern, pattern};
 
     use super::*;
 
@@ -278,6 +649,55 @@ mod tests {
         expect!(unwrapped_error).to(match_pattern(pattern!(Error::InvalidArgs { .. })));
     }
 
+    #[test]
+    fn busy_database_is_retryable() {
+        let err: Error = rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error {
+                code: rusqlite::ErrorCode::DatabaseBusy,
+                extended_code: rusqlite::ffi::SQLITE_BUSY,
+            },
+            None,
+        )
+        .into();
+
+        expect!(err.category()).to(equal(ErrorCategory::Retryable));
+        expect!(err.is_retryable()).to(be_true());
+    }
+
+    #[test]
+    fn corrupt_database_is_permanent() {
+        let err: Error = rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error {
+                code: rusqlite::ErrorCode::DatabaseCorrupt,
+                extended_code: rusqlite::ffi::SQLITE_CORRUPT,
+            },
+            None,
+        )
+        .into();
+
+        expect!(err.is_permanent()).to(be_true());
+        expect!(err.is_retryable()).to(be_false());
+    }
+
+    #[test]
+    fn file_already_exists_is_conflict() {
+        let err = Error::FileAlreadyExists {
+            path: PathBuf::new(),
+        };
+
+        expect!(err.is_conflict()).to(be_true());
+        expect!(err.is_permanent()).to(be_false());
+    }
+
+    #[test]
+    fn io_error_is_classified_by_kind() {
+        let interrupted: Error = io::Error::from(io::ErrorKind::Interrupted).into();
+        let denied: Error = io::Error::from(io::ErrorKind::PermissionDenied).into();
+
+        expect!(interrupted.category()).to(equal(ErrorCategory::Retryable));
+        expect!(denied.category()).to(equal(ErrorCategory::Permanent));
+    }
+
     #[test]
     fn convert_from_rusqlite_error() {
         let rusqlite_err = rusqlite::Error::SqliteFailure(
@@ -292,4 +712,46 @@ mod tests {
 
         expect!(err).to(equal(Error::ReadOnly));
     }
+
+    #[test]
+    fn error_bundle_describes_the_first_error() {
+        let bundle = ErrorBundle::new(vec![
+            (PathBuf::from("a"), Error::FilesystemLoop),
+            (PathBuf::from("b"), Error::FilesystemLoop),
+        ]);
+
+        expect!(bundle.to_string()).to(equal(format!(
+            "2 files failed. The first was a: {}",
+            Error::FilesystemLoop
+        )));
+    }
+
+    #[test]
+    fn error_bundle_has_a_category_only_if_every_error_does() {
+        let conflicts = Error::Multiple {
+            errors: ErrorBundle::new(vec![
+                (
+                    PathBuf::from("a"),
+                    Error::FileAlreadyExists { path: "a".into() },
+                ),
+                (
+                    PathBuf::from("b"),
+                    Error::FileAlreadyExists { path: "b".into() },
+                ),
+            ]),
+        };
+
+        let mixed = Error::Multiple {
+            errors: ErrorBundle::new(vec![
+                (
+                    PathBuf::from("a"),
+                    Error::FileAlreadyExists { path: "a".into() },
+                ),
+                (PathBuf::from("b"), Error::FilesystemLoop),
+            ]),
+        };
+
+        expect!(conflicts.category()).to(equal(ErrorCategory::Conflict));
+        expect!(mixed.category()).to(equal(ErrorCategory::Permanent));
+    }
 }
@@ -0,0 +1,149 @@
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
+
+fn push_escaped_str(escaped: &mut String, s: &str) {
+    for c in s.chars() {
+        if c == '%' {
+            escaped.push_str("%25");
+        } else {
+            escaped.push(c);
+        }
+    }
+}
+
+// Escape a file name that isn't valid Unicode so it can be stored in an archive, returning `None`
+// if it's already valid Unicode.
+//
+// Bytes that aren't part of a valid UTF-8 sequence are percent-encoded, as are any literal percent
+// signs, so two different file names never escape to the same string.
+pub fn escape_file_name(name: &OsStr) -> Option<String> {
+    if name.to_str().is_some() {
+        return None;
+    }
+
+    let mut bytes = name.as_encoded_bytes();
+    let mut escaped = String::with_capacity(bytes.len());
+
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                push_escaped_str(&mut escaped, valid);
+                break;
+            }
+            Err(err) => {
+                let (valid, rest) = bytes.split_at(err.valid_up_to());
+
+                push_escaped_str(
+                    &mut escaped,
+                    std::str::from_utf8(valid)
+                        .expect("We already checked that this is valid UTF-8. This is a bug."),
+                );
+
+                let invalid_len = err.error_len().unwrap_or(rest.len());
+
+                for byte in &rest[..invalid_len] {
+                    write!(escaped, "%{byte:02X}").expect("Writing to a string never fails.");
+                }
+
+                bytes = &rest[invalid_len..];
+            }
+        }
+    }
+
+    Some(escaped)
+}
+
+// Escape each component of `path` that isn't valid Unicode, returning `None` if the whole path is
+// already valid Unicode.
+pub fn escape_path(path: &Path) -> Option<PathBuf> {
+    if path.to_str().is_some() {
+        return None;
+    }
+
+    Some(
+        path.components()
+            .map(|component| match component {
+                Component::Normal(name) => match escape_file_name(name) {
+                    Some(escaped) => OsString::from(escaped),
+                    None => name.to_owned(),
+                },
+                other => other.as_os_str().to_owned(),
+            })
+            .collect(),
+    )
+}
+
+// Return the original file name that `escaped` was escaped from, given the raw bytes of that file
+// name, or `None` if it can't be restored on this platform.
+//
+// If the file was renamed after it was archived, `escaped` won't match `raw` anymore, in which
+// case this also returns `None`.
+#[cfg(unix)]
+pub fn restore_file_name(escaped: &str, raw: &[u8]) -> Option<OsString> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let name = OsStr::from_bytes(raw);
+
+    if escape_file_name(name).as_deref() == Some(escaped) {
+        Some(name.to_owned())
+    } else {
+        None
+    }
+}
+
+// We can't safely turn the raw bytes back into an `OsStr` on other platforms.
+#[cfg(not(unix))]
+pub fn restore_file_name(_escaped: &str, _raw: &[u8]) -> Option<OsString> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use xpct::{be_none, equal, expect};
+
+    #[test]
+    fn escaping_valid_file_name_returns_none() {
+        expect!(escape_file_name(OsStr::new("100%"))).to(be_none());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn escaping_invalid_file_name_escapes_bytes_and_percent_signs() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = OsStr::from_bytes(b"caf\xe9 100%");
+
+        expect!(escape_file_name(name)).to(equal(Some(String::from("caf%E9 100%25"))));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn escaping_path_only_escapes_invalid_components() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"100%/\xff/file"));
+
+        expect!(escape_path(path)).to(equal(Some(PathBuf::from("100%/%FF/file"))));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn restoring_file_name_round_trips() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let raw = b"\xff%";
+        let escaped = escape_file_name(OsStr::from_bytes(raw)).unwrap();
+
+        expect!(restore_file_name(&escaped, raw))
+            .to(equal(Some(OsStr::from_bytes(raw).to_owned())));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn restoring_renamed_file_name_returns_none() {
+        expect!(restore_file_name("renamed", b"\xff")).to(be_none());
+    }
+}
@@ -0,0 +1,49 @@
+use std::path::{Path, PathBuf};
+
+use super::archive::Archive;
+use super::transaction::Connection;
+
+/// A reference to a file in another SQLite archive.
+///
+/// External links let one archive refer to files in another archive without copying their
+/// contents, so you can compose a small archive on top of a larger base archive. You can create
+/// one with [`File::create_external_link`] and read it back with [`File::external_link`].
+///
+/// [`File::create_external_link`]: crate::File::create_external_link
+/// [`File::external_link`]: crate::File::external_link
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalLink {
+    pub(super) archive: PathBuf,
+    pub(super) target: PathBuf,
+}
+
+impl ExternalLink {
+    /// The path of the SQLite archive this link points into.
+    ///
+    /// If this is a relative path, it's relative to the directory containing the archive the link
+    /// is stored in.
+    pub fn archive(&self) -> &Path {
+        &self.archive
+    }
+
+    /// The path of the file this link points to within the other archive.
+    pub fn target(&self) -> &Path {
+        &self.target
+    }
+}
+
+impl<'conn> Archive<'conn> {
+    pub(super) fn connect_external(&self, link: &ExternalLink) -> crate::Result<Connection> {
+        let base_dir = self
+            .store
+            .db_path()
+            .and_then(|db_path| Path::new(db_path).parent());
+
+        let archive_path = match base_dir {
+            Some(base_dir) if link.archive.is_relative() => base_dir.join(&link.archive),
+            _ => link.archive.clone(),
+        };
+
+        Connection::open_readonly(archive_path)
+    }
+}
@@ -1,23 +1,132 @@
+use std::borrow::Cow;
 use std::fs;
 use std::io::{self, Read, Write};
+use std::mem;
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 #[cfg(feature = "deflate")]
-use flate2::write::ZlibEncoder;
-
+use flate2::write::{ZlibDecoder, ZlibEncoder};
+
+use super::clock::Clock;
+use super::dedup::dedup_file;
+#[cfg(feature = "zstd")]
+use super::dictionary::zstd_compress;
+use super::digest::{digest_stream, digest_stream_with, Digest, DigestAlgorithm};
+use super::external::ExternalLink;
+use super::list::EntryId;
+use super::lock::{self, FileLock};
 use super::metadata::{mode_from_umask, FileMetadata, FileMode, FileType};
-use super::store::Store;
+use super::rename::{rename_paths, RenamePolicy};
+use super::store::{NewFile, Store};
 use super::stream::{Compression, FileReader};
-use super::util::u64_from_usize;
+use super::unicode::PathNormalization;
+use super::util::{clamp_to_source_date_epoch, looks_like_text, u64_from_usize, TEXT_SNIFF_LEN};
+use super::writer::FileWriter;
 
+// How much compressed output we keep in memory when compressing a stream before we start staging
+// it in the database.
 #[cfg(feature = "deflate")]
-const COPY_BUF_SIZE: usize = 1024 * 8;
+const COMPRESS_MEMORY_BUDGET: usize = 1024 * 1024 * 4;
+
+// The size of the chunks we stage file contents in when writing a stream of an unknown size.
+pub(super) const SPOOL_CHUNK_SIZE: usize = 1024 * 64;
 
 fn unwrap_path_parent(path: &Path) -> &Path {
     path.parent().expect("The given file path is an absolute path, but we should have already checked for this when opening the file handle. This is a bug.")
 }
 
+// Fill `buf` from `reader`, stopping early only at EOF. This returns the number of bytes read.
+fn read_chunk<R: ?Sized + Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(bytes_read) => filled += bytes_read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(filled)
+}
+
+// The sqlar spec requires that we only use the compressed data if it's smaller than the
+// uncompressed data.
+#[cfg(any(feature = "deflate", feature = "zstd"))]
+fn smaller_of(compressed_bytes: Vec<u8>, bytes: &[u8]) -> Cow<'_, [u8]> {
+    if compressed_bytes.len() < bytes.len() {
+        Cow::Owned(compressed_bytes)
+    } else {
+        Cow::Borrowed(bytes)
+    }
+}
+
+// Compress `bytes` with `method`, returning them as they should be stored.
+#[cfg_attr(not(feature = "zstd"), allow(unused_variables))]
+pub(super) fn compress_bytes<'a>(
+    store: &Store,
+    method: Compression,
+    bytes: &'a [u8],
+) -> crate::Result<Cow<'a, [u8]>> {
+    match method {
+        Compression::None => Ok(Cow::Borrowed(bytes)),
+        #[cfg(feature = "deflate")]
+        Compression::Deflate { level } => {
+            let mut encoder = ZlibEncoder::new(
+                Vec::with_capacity(bytes.len()),
+                flate2::Compression::new(level),
+            );
+            encoder.write_all(bytes)?;
+
+            Ok(smaller_of(encoder.finish()?, bytes))
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd { level } => Ok(smaller_of(zstd_compress(store, bytes, level)?, bytes)),
+    }
+}
+
+// Validate a path passed in by the user and normalize it to the form used in the `sqlar` table.
+pub(super) fn normalize_path(path: &Path) -> crate::Result<String> {
+    if path == Path::new("") {
+        return Err(crate::Error::InvalidArgs {
+            reason: format!("This path is empty: {}", path.to_string_lossy()),
+        });
+    }
+
+    if path.is_absolute() {
+        return Err(crate::Error::InvalidArgs {
+            reason: format!("This path is an absolute path, but SQLite archives only support relative paths: {}", path.to_string_lossy())
+        });
+    }
+
+    let normalized_path = match path.as_os_str().to_str() {
+        // SQLite archives created by the reference implementation don't have trailing slashes
+        // in directory paths, so we normalize paths coming in by stripping trailing path
+        // separators.
+        Some(utf8_str) => utf8_str
+            .trim_end_matches(std::path::MAIN_SEPARATOR)
+            .to_owned(),
+        None => {
+            return Err(crate::Error::InvalidArgs {
+                reason: format!("This path is not valid Unicode: {}", path.to_string_lossy()),
+            })
+        }
+    };
+
+    // SQLite archives created by the reference implementation normalize paths to always use
+    // forward slashes as the path separator.
+    let normalized_path = if cfg!(windows) {
+        normalized_path.replace(std::path::MAIN_SEPARATOR, "/")
+    } else {
+        normalized_path
+    };
+
+    Ok(normalized_path)
+}
+
 /// A file in a SQLite archive.
 ///
 /// A [`File`] is a handle to a regular file, directory, or symbolic link that may or may not exist
@@ -30,17 +139,18 @@ fn unwrap_path_parent(path: &Path) -> &Path {
 /// You can read from the beginning of a file, but cannot seek through it. You can truncate and
 /// overwrite the file's contents, but cannot append to it.
 ///
-/// Writing to a file does not automatically update its [`FileMetadata::File::mtime`].
+/// Writing to a file does not automatically update its [`FileMetadata::File::mtime`] unless
+/// [`Archive::set_update_mtime`] is enabled.
 ///
 /// Attempting to read from or write to a directory or symbolic link will return an error.
 ///
 /// # Compression
 ///
 /// Writes to a [`File`] can optionally be compressed with DEFLATE. You can change the compression
-/// method (compressed or uncompressed) via [`File::set_compression`]. The default is to compress
-/// writes if and only if the `deflate` Cargo feature is enabled. You can read compressed files
-/// regardless of the selected compression method, but doing so will return an error if the
-/// `deflate` feature is disabled.
+/// method (compressed or uncompressed) via [`File::set_compression`]. The default comes from
+/// [`Archive::set_compression`], which is to compress writes if and only if the `deflate` Cargo
+/// feature is enabled. You can read compressed files regardless of the selected compression
+/// method, but doing so will return an error if the `deflate` feature is disabled.
 ///
 /// Consider disabling compression if you know you're going to be writing a lot of incompressible
 /// data, such as files that are already compressed (e.g. photos and videos).
@@ -48,6 +158,8 @@ fn unwrap_path_parent(path: &Path) -> &Path {
 /// [`Read`]: std::io::Read
 /// [`Write`]: std::io::Write
 /// [`Seek`]: std::io::Seek
+/// [`Archive::set_compression`]: crate::Archive::set_compression
+/// [`Archive::set_update_mtime`]: crate::Archive::set_update_mtime
 #[derive(Debug)]
 pub struct File<'conn, 'ar> {
     // We store this internally as a string because the contract of this type requires the path to
@@ -55,62 +167,165 @@ pub struct File<'conn, 'ar> {
     path: String,
     compression: Compression,
     umask: FileMode,
-    store: &'ar mut Store<'conn>,
+    source_date_epoch: bool,
+    lock_namespace: Arc<str>,
+    update_mtime: bool,
+    clock: Clock,
+    path_normalization: PathNormalization,
+    // Whether this handle has claimed its path, so that no other handle can be opened to the same
+    // file until it's dropped.
+    claimed: bool,
+    store: &'ar Store<'conn>,
 }
 
 impl<'conn, 'ar> File<'conn, 'ar> {
     pub(super) fn new(
         path: &Path,
-        store: &'ar mut Store<'conn>,
+        store: &'ar Store<'conn>,
         umask: FileMode,
+        source_date_epoch: bool,
+        lock_namespace: Arc<str>,
     ) -> crate::Result<Self> {
-        if path == Path::new("") {
-            return Err(crate::Error::InvalidArgs {
-                reason: format!("This path is empty: {}", path.to_string_lossy()),
-            });
-        }
-
-        if path.is_absolute() {
-            return Err(crate::Error::InvalidArgs {
-                reason: format!("This path is an absolute path, but SQLite archives only support relative paths: {}", path.to_string_lossy())
-            });
-        }
+        let mut file = Self::new_unclaimed(path, store, umask, source_date_epoch, lock_namespace)?;
 
-        let normalized_path = match path.as_os_str().to_str() {
-            // SQLite archives created by the reference implementation don't have trailing slashes
-            // in directory paths, so we normalize paths coming in by stripping trailing path
-            // separators.
-            Some(utf8_str) => utf8_str
-                .trim_end_matches(std::path::MAIN_SEPARATOR)
-                .to_owned(),
-            None => {
-                return Err(crate::Error::InvalidArgs {
-                    reason: format!("This path is not valid Unicode: {}", path.to_string_lossy()),
-                })
-            }
-        };
+        store.claim_path(&file.path)?;
+        file.claimed = true;
 
-        // SQLite archives created by the reference implementation normalize paths to always use
-        // forward slashes as the path separator.
-        let normalized_path = if cfg!(windows) {
-            normalized_path.replace(std::path::MAIN_SEPARATOR, "/")
-        } else {
-            normalized_path
-        };
+        Ok(file)
+    }
 
+    // Create a handle without claiming its path. This is for handles that only live for the
+    // duration of a call into the archive. The user can hold a handle to the same file during the
+    // call, so callers that change the file must check `Store::check_not_open` first.
+    pub(super) fn new_unclaimed(
+        path: &Path,
+        store: &'ar Store<'conn>,
+        umask: FileMode,
+        source_date_epoch: bool,
+        lock_namespace: Arc<str>,
+    ) -> crate::Result<Self> {
         Ok(Self {
-            path: normalized_path,
+            path: normalize_path(path)?,
             store,
             #[cfg(feature = "deflate")]
             compression: Compression::FAST,
-            // Because getting a file handle requires a mutable receiver, we don't have to worry
-            // about keeping this in sync with `Archive::umask`.
+            // Because changing `Archive::umask` requires a mutable receiver, it can't change while
+            // this handle exists, so we don't have to worry about keeping this in sync with it.
             umask,
+            source_date_epoch,
+            lock_namespace,
+            update_mtime: false,
+            clock: Clock::default(),
+            path_normalization: PathNormalization::Preserve,
+            claimed: false,
             #[cfg(not(feature = "deflate"))]
             compression: Compression::None,
         })
     }
 
+    // The mtime to give newly created files.
+    pub(super) fn initial_mtime(&self) -> crate::Result<SystemTime> {
+        let now = self.clock.now();
+
+        if self.source_date_epoch {
+            clamp_to_source_date_epoch(now)
+        } else {
+            Ok(now)
+        }
+    }
+
+    pub(super) fn set_update_mtime(&mut self, update: bool) {
+        self.update_mtime = update;
+    }
+
+    // Because changing `Archive::set_clock` requires a mutable receiver, it can't change while
+    // this handle exists.
+    pub(super) fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    // Because changing `Archive::path_normalization` requires a mutable receiver, it can't change
+    // while this handle exists.
+    pub(super) fn set_path_normalization(&mut self, normalization: PathNormalization) {
+        self.path_normalization = normalization;
+    }
+
+    // Set the mtime of this file to now if writing to it should do that. This is called after
+    // writing to the file.
+    pub(super) fn touch_after_write(&self, store: &Store) -> crate::Result<()> {
+        if self.update_mtime {
+            store.set_mtime(&self.path, Some(self.initial_mtime()?))?;
+        }
+
+        Ok(())
+    }
+
+    // Prepare the row for this file as a new regular file holding `contents`, without touching
+    // the database. Inserting the row is the same as creating the file, setting its mode and
+    // mtime, and then writing `contents` to it.
+    pub(super) fn prepare_new_file(
+        &self,
+        mode: FileMode,
+        mtime: Option<SystemTime>,
+        contents: &[u8],
+    ) -> crate::Result<NewFile> {
+        self.validate_can_be_created()?;
+
+        let mtime = if self.update_mtime {
+            Some(self.initial_mtime()?)
+        } else {
+            mtime
+        };
+
+        Ok(NewFile {
+            path: self.path.clone(),
+            mode,
+            mtime,
+            size: u64_from_usize(contents.len()),
+            data: compress_bytes(self.store, self.compression, contents)?.into_owned(),
+        })
+    }
+
+    // Record the raw bytes of the file name this file was escaped from.
+    pub(super) fn set_raw_name(&self, raw: &[u8]) -> crate::Result<()> {
+        self.store.set_raw_name(&self.path, raw)
+    }
+
+    // Share the contents of this file with any other files that have the same contents. See
+    // `Archive::deduplicate`.
+    pub(super) fn deduplicate(&self) -> crate::Result<bool> {
+        dedup_file(self.store, &self.path)
+    }
+
+    pub(super) fn store(&self) -> &'ar Store<'conn> {
+        self.store
+    }
+
+    // Replace the contents of the file with the data in `spool`, which is `spooled_len` bytes long
+    // and `original_len` bytes long once decompressed.
+    pub(super) fn write_spool(
+        &mut self,
+        spool: u64,
+        spooled_len: u64,
+        original_len: u64,
+    ) -> crate::Result<()> {
+        self.validate_is_writable()?;
+
+        self.store.exec(|store| {
+            store.allocate_blob(&self.path, spooled_len)?;
+            let mut blob = store.open_blob(&self.path, false)?.into_blob();
+
+            store.read_spool(spool, |data| Ok(blob.write_all(data)?))?;
+
+            // Close the blob handle before we touch the database again.
+            drop(blob);
+
+            store.set_size(&self.path, original_len)?;
+
+            self.touch_after_write(store)
+        })
+    }
+
     fn validate_is_writable(&self) -> crate::Result<()> {
         if self.store.read_metadata(&self.path)?.is_file() {
             Ok(())
@@ -162,6 +377,46 @@ impl<'conn, 'ar> File<'conn, 'ar> {
         }
     }
 
+    // Move this file to `path`, which must not already exist. This is how unnamed files are linked
+    // into the archive.
+    pub(super) fn link_at(&mut self, path: &Path) -> crate::Result<()> {
+        let new_path = normalize_path(path)?;
+
+        // We need to hold both paths until we know which one this handle ends up at.
+        if self.claimed {
+            self.store.claim_path(&new_path)?;
+        }
+
+        let old_path = mem::replace(&mut self.path, new_path);
+
+        let result = self.validate_can_be_created().and_then(|()| {
+            match self.store.read_metadata(&self.path) {
+                Ok(_) => Err(crate::Error::FileAlreadyExists {
+                    path: PathBuf::from(&self.path),
+                }),
+                Err(crate::Error::FileNotFound { .. }) => {
+                    self.store.rename_files(&old_path, &self.path)?;
+                    Ok(())
+                }
+                Err(err) => Err(err),
+            }
+        });
+
+        match result {
+            Ok(()) if self.claimed => self.store.release_path(&old_path),
+            Ok(()) => {}
+            Err(_) => {
+                if self.claimed {
+                    self.store.release_path(&self.path);
+                }
+
+                self.path = old_path;
+            }
+        }
+
+        result
+    }
+
     //
     // Some operations, like setting the mode and mtime, don't strictly need to take a mutable
     // receiver. We make them take a mutable receiver anyways because:
@@ -207,8 +462,11 @@ impl<'conn, 'ar> File<'conn, 'ar> {
     ///
     /// - [`FileAlreadyExists`]: This file already exists in the archive.
     /// - [`NoParentDirectory`]: This file's parent directory does not exist or is not a directory.
+    /// - [`NameTooLong`]: This file's path is longer than [`Archive::max_name_len`].
     ///
     /// [`FileAlreadyExists`]: crate::Error::FileAlreadyExists
+    /// [`NameTooLong`]: crate::Error::NameTooLong
+    /// [`Archive::max_name_len`]: crate::Archive::max_name_len
     /// [`NoParentDirectory`]: crate::Error::NoParentDirectory
     pub fn create_file(&mut self) -> crate::Result<()> {
         self.validate_can_be_created()?;
@@ -217,7 +475,7 @@ impl<'conn, 'ar> File<'conn, 'ar> {
             &self.path,
             FileType::File,
             mode_from_umask(FileType::File, self.umask),
-            Some(SystemTime::now()),
+            Some(self.initial_mtime()?),
             None,
         )
     }
@@ -237,8 +495,11 @@ impl<'conn, 'ar> File<'conn, 'ar> {
     ///
     /// - [`FileAlreadyExists`]: This file already exists in the archive.
     /// - [`NoParentDirectory`]: This file's parent directory does not exist or is not a directory.
+    /// - [`NameTooLong`]: This file's path is longer than [`Archive::max_name_len`].
     ///
     /// [`FileAlreadyExists`]: crate::Error::FileAlreadyExists
+    /// [`NameTooLong`]: crate::Error::NameTooLong
+    /// [`Archive::max_name_len`]: crate::Archive::max_name_len
     /// [`NoParentDirectory`]: crate::Error::NoParentDirectory
     pub fn create_dir(&mut self) -> crate::Result<()> {
         self.validate_can_be_created()?;
@@ -247,7 +508,7 @@ impl<'conn, 'ar> File<'conn, 'ar> {
             &self.path,
             FileType::Dir,
             mode_from_umask(FileType::Dir, self.umask),
-            Some(SystemTime::now()),
+            Some(self.initial_mtime()?),
             None,
         )
     }
@@ -310,7 +571,7 @@ impl<'conn, 'ar> File<'conn, 'ar> {
         let path = PathBuf::from(&self.path);
         let mode = mode_from_umask(FileType::Dir, self.umask);
         // Each parent directory should have the same mtime.
-        let mtime = SystemTime::now();
+        let mtime = self.initial_mtime()?;
 
         let mut parents = Vec::new();
         let mut parent = path.as_path();
@@ -356,6 +617,7 @@ impl<'conn, 'ar> File<'conn, 'ar> {
     ///
     /// - [`FileAlreadyExists`]: This file already exists in the archive.
     /// - [`NoParentDirectory`]: This file's parent directory does not exist or is not a directory.
+    /// - [`NameTooLong`]: This file's path is longer than [`Archive::max_name_len`].
     ///
     /// # Examples
     ///
@@ -378,6 +640,8 @@ impl<'conn, 'ar> File<'conn, 'ar> {
     /// ```
     ///
     /// [`FileAlreadyExists`]: crate::Error::FileAlreadyExists
+    /// [`NameTooLong`]: crate::Error::NameTooLong
+    /// [`Archive::max_name_len`]: crate::Archive::max_name_len
     /// [`NoParentDirectory`]: crate::Error::NoParentDirectory
     pub fn create_symlink<P: AsRef<Path>>(&mut self, target: P) -> crate::Result<()> {
         let target_path = target.as_ref();
@@ -405,11 +669,92 @@ impl<'conn, 'ar> File<'conn, 'ar> {
             &self.path,
             FileType::Symlink,
             mode_from_umask(FileType::Symlink, self.umask),
-            Some(SystemTime::now()),
+            Some(self.initial_mtime()?),
             Some(normalized_target.as_str()),
         )
     }
 
+    /// Create a link to a file in another SQLite archive.
+    ///
+    /// This creates a file at this path that refers to the file at `target` in the archive at
+    /// `archive`, without copying its contents. If `archive` is a relative path, it's resolved
+    /// relative to the directory containing this archive when the link is followed. The other
+    /// archive doesn't need to exist when the link is created.
+    ///
+    /// The link is stored as an empty regular file, and the reference to the other archive is
+    /// stored in a separate table in the database. Other tools that read SQLite archives will see
+    /// an empty file. Use [`File::external_link`] to read the link back and
+    /// [`Archive::open_external`] to follow it.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileAlreadyExists`]: This file already exists in the archive.
+    /// - [`NoParentDirectory`]: This file's parent directory does not exist or is not a directory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut file = archive.open("file")?;
+    /// file.create_external_link("base.sqlar", "dir/file")?;
+    ///
+    /// let link = file.external_link()?.unwrap();
+    ///
+    /// assert_eq!(link.archive(), std::path::Path::new("base.sqlar"));
+    /// assert_eq!(link.target(), std::path::Path::new("dir/file"));
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`Archive::open_external`]: crate::Archive::open_external
+    /// [`FileAlreadyExists`]: crate::Error::FileAlreadyExists
+    /// [`NoParentDirectory`]: crate::Error::NoParentDirectory
+    pub fn create_external_link<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        archive: P,
+        target: Q,
+    ) -> crate::Result<()> {
+        let archive_path = archive.as_ref();
+
+        if archive_path == Path::new("") {
+            return Err(crate::Error::InvalidArgs {
+                reason: String::from("The given archive path is empty."),
+            });
+        }
+
+        let archive_str = archive_path
+            .to_str()
+            .ok_or_else(|| crate::Error::InvalidArgs {
+                reason: String::from("The given archive path is not valid Unicode."),
+            })?;
+
+        let normalized_target = normalize_path(target.as_ref())?;
+
+        self.create_file()?;
+
+        self.store
+            .set_external_link(&self.path, archive_str, &normalized_target)
+    }
+
+    /// The file in another archive that this file links to, or `None` if this isn't an external
+    /// link.
+    ///
+    /// See [`File::create_external_link`].
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    pub fn external_link(&self) -> crate::Result<Option<ExternalLink>> {
+        // Make sure the file exists.
+        self.store.read_metadata(&self.path)?;
+
+        self.store.get_external_link(&self.path)
+    }
+
     /// Delete the file from the archive.
     ///
     /// This does not consume its receiver and does not invalidate the file handle; you can still
@@ -420,6 +765,7 @@ impl<'conn, 'ar> File<'conn, 'ar> {
     /// # Errors
     ///
     /// - [`FileNotFound`]: This file does not exist.
+    /// - [`FilePinned`]: This file or one of its descendants is pinned. See [`File::pin`].
     ///
     /// # Examples
     ///
@@ -439,59 +785,113 @@ impl<'conn, 'ar> File<'conn, 'ar> {
     /// ```
     ///
     /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`FilePinned`]: crate::Error::FilePinned
     pub fn delete(&mut self) -> crate::Result<()> {
-        self.store.delete_file(&self.path)
+        self.store.delete_file(&self.path, false)
     }
 
-    /// The file metadata.
+    /// Delete the file from the archive, even if it's pinned.
+    ///
+    /// This is the same as [`File::delete`], except it also deletes files that are pinned.
     ///
     /// # Errors
     ///
     /// - [`FileNotFound`]: This file does not exist.
     ///
     /// [`FileNotFound`]: crate::Error::FileNotFound
-    pub fn metadata(&self) -> crate::Result<FileMetadata> {
-        self.store.read_metadata(&self.path)
+    pub fn force_delete(&mut self) -> crate::Result<()> {
+        self.store.delete_file(&self.path, true)
     }
 
-    /// Set the file mode.
+    /// Move this file to `path`, and point this handle at its new path.
     ///
-    /// The file mode is nullable, so it's possible to set this to `None`.
+    /// If this file is a directory, all its descendants are moved along with it. This only
+    /// rewrites the paths of the files in the archive, so their contents aren't copied. The whole
+    /// rename happens atomically.
     ///
-    /// Attempting to set the mode of a symlink is a no-op; Symlinks always have `777` permissions.
+    /// This is the same as [`Archive::rename`], except that this handle follows the file to its
+    /// new path.
     ///
     /// # Errors
     ///
     /// - [`FileNotFound`]: This file does not exist.
+    /// - [`NoParentDirectory`]: The parent directory of `path` does not exist.
+    /// - [`FileAlreadyExists`]: There is already a file at `path`.
+    /// - [`FileAlreadyOpen`]: There is already another open handle to the file at `path`.
+    /// - [`NameTooLong`]: The new path of this file or one of its descendants would be longer
+    ///   than [`Archive::max_name_len`].
     ///
     /// # Examples
     ///
     /// ```
-    /// # use sqlarfs::{Connection, FileMode};
+    /// # use sqlarfs::Connection;
     /// # let mut connection = Connection::open_in_memory()?;
     /// # let mut tx = connection.transaction()?;
     /// # let archive = tx.archive_mut();
-    /// let mode = FileMode::OWNER_R | FileMode::OWNER_W | FileMode::GROUP_R | FileMode::GROUP_W;
-    ///
-    /// let mut file = archive.open("file")?;
+    /// let mut file = archive.open("old")?;
     /// file.create_file()?;
-    /// file.set_mode(Some(mode))?;
     ///
-    /// assert_eq!(file.metadata()?.mode(), Some(mode));
+    /// file.rename_to("new")?;
+    ///
+    /// assert_eq!(file.path(), std::path::Path::new("new"));
+    /// assert!(!archive.open("old")?.exists()?);
     /// # sqlarfs::Result::Ok(())
     /// ```
     ///
+    /// [`Archive::rename`]: crate::Archive::rename
+    /// [`Archive::max_name_len`]: crate::Archive::max_name_len
     /// [`FileNotFound`]: crate::Error::FileNotFound
-    pub fn set_mode(&mut self, mode: Option<FileMode>) -> crate::Result<()> {
-        self.store.set_mode(&self.path, mode)
+    /// [`NoParentDirectory`]: crate::Error::NoParentDirectory
+    /// [`FileAlreadyExists`]: crate::Error::FileAlreadyExists
+    /// [`FileAlreadyOpen`]: crate::Error::FileAlreadyOpen
+    /// [`NameTooLong`]: crate::Error::NameTooLong
+    pub fn rename_to<P: AsRef<Path>>(&mut self, path: P) -> crate::Result<()> {
+        let new_path = normalize_path(&self.path_normalization.apply(path.as_ref()))?;
+
+        if new_path == self.path {
+            // Make sure the file exists.
+            self.store.read_metadata(&self.path)?;
+
+            return Ok(());
+        }
+
+        // Claim the new path first so no other handle can be opened to it while we hold both.
+        if self.claimed {
+            self.store.claim_path(&new_path)?;
+        }
+
+        match rename_paths(self.store, &self.path, &new_path, RenamePolicy::Error) {
+            Ok(()) => {
+                let old_path = mem::replace(&mut self.path, new_path);
+
+                if self.claimed {
+                    self.store.release_path(&old_path);
+                }
+
+                Ok(())
+            }
+            Err(err) => {
+                if self.claimed {
+                    self.store.release_path(&new_path);
+                }
+
+                Err(err)
+            }
+        }
     }
 
-    /// Set the time the file was last modified.
+    /// Pin this file so it can't be deleted.
     ///
-    /// The file mtime is nullable, so it's possible to set this to `None`.
+    /// Pinned files are protected from [`File::delete`], [`Archive::delete_matching`], and
+    /// [`Archive::prune`], as are any directories that contain them. This is useful for archives
+    /// that have a retention policy applied automatically but also contain files that must be
+    /// kept. You can still delete a pinned file with [`File::force_delete`].
     ///
-    /// The mtime in a SQLite archive only has a precision of 1 second, so this rounds down to the
-    /// nearest whole second.
+    /// Pinning a file that's already pinned does nothing. A pin follows the file when it's
+    /// renamed.
+    ///
+    /// Pins are stored in a separate table in the database, so other tools that read SQLite
+    /// archives will ignore them.
     ///
     /// # Errors
     ///
@@ -500,94 +900,73 @@ impl<'conn, 'ar> File<'conn, 'ar> {
     /// # Examples
     ///
     /// ```
-    /// # use std::time::{UNIX_EPOCH, SystemTime, Duration};
-    /// # use sqlarfs::Connection;
+    /// # use sqlarfs::{Connection, Error};
     /// # let mut connection = Connection::open_in_memory()?;
     /// # let mut tx = connection.transaction()?;
     /// # let archive = tx.archive_mut();
-    /// let mtime = SystemTime::now();
     /// let mut file = archive.open("file")?;
     /// file.create_file()?;
-    /// file.set_mtime(Some(mtime))?;
+    /// file.pin()?;
     ///
-    /// // The mtime in a SQLite archive only has a precision of 1 second.
-    /// let truncated_mtime = UNIX_EPOCH + Duration::from_secs(mtime.duration_since(UNIX_EPOCH).unwrap().as_secs());
+    /// assert!(matches!(file.delete(), Err(Error::FilePinned { .. })));
     ///
-    /// assert_eq!(file.metadata()?.mtime(), Some(truncated_mtime));
+    /// file.unpin()?;
+    /// file.delete()?;
     /// # sqlarfs::Result::Ok(())
     /// ```
     ///
+    /// [`Archive::delete_matching`]: crate::Archive::delete_matching
+    /// [`Archive::prune`]: crate::Archive::prune
     /// [`FileNotFound`]: crate::Error::FileNotFound
-    pub fn set_mtime(&mut self, mtime: Option<SystemTime>) -> crate::Result<()> {
-        self.store.set_mtime(&self.path, mtime)
+    pub fn pin(&mut self) -> crate::Result<()> {
+        self.store.pin(&self.path)
     }
 
-    /// Whether the file is empty.
+    /// Unpin this file so it can be deleted again.
+    ///
+    /// This returns `true` if the file was pinned.
+    ///
+    /// See [`File::pin`].
     ///
     /// # Errors
     ///
     /// - [`FileNotFound`]: This file does not exist.
-    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
     ///
     /// [`FileNotFound`]: crate::Error::FileNotFound
-    /// [`NotARegularFile`]: crate::Error::NotARegularFile
-    pub fn is_empty(&self) -> crate::Result<bool> {
-        self.validate_is_readable()?;
+    pub fn unpin(&mut self) -> crate::Result<bool> {
+        // Make sure the file exists.
+        self.store.read_metadata(&self.path)?;
 
-        match self.metadata()? {
-            FileMetadata::File { size, .. } => Ok(size == 0),
-            _ => unreachable!("By this point, we should have already checked that the file is a regular file. This is a bug."),
-        }
+        self.store.unpin(&self.path)
     }
 
-    /// Whether the contents of this file are compressed.
+    /// Whether this file is pinned.
     ///
-    /// Even if compression is enabled via [`File::set_compression`], a file may not be compressed
-    /// if it's incompressible or if compressing it would *increase* its size.
+    /// See [`File::pin`].
     ///
     /// # Errors
     ///
     /// - [`FileNotFound`]: This file does not exist.
-    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// # use sqlarfs::{Connection, Compression};
-    /// # let mut connection = Connection::open_in_memory()?;
-    /// # let mut tx = connection.transaction()?;
-    /// # let archive = tx.archive_mut();
-    /// let compressible_data = " ".repeat(32);
-    ///
-    /// let mut file = archive.open("file")?;
-    /// file.create_file()?;
-    ///
-    /// file.set_compression(Compression::None);
-    /// file.write_str(&compressible_data)?;
-    ///
-    /// assert!(!file.is_compressed()?);
-    ///
-    /// file.set_compression(Compression::BEST);
-    /// file.write_str(&compressible_data)?;
-    ///
-    /// assert!(file.is_compressed()?);
-    /// # sqlarfs::Result::Ok(())
-    /// ```
     ///
     /// [`FileNotFound`]: crate::Error::FileNotFound
-    /// [`NotARegularFile`]: crate::Error::NotARegularFile
-    pub fn is_compressed(&self) -> crate::Result<bool> {
-        self.validate_is_readable()?;
+    pub fn is_pinned(&self) -> crate::Result<bool> {
+        // Make sure the file exists.
+        self.store.read_metadata(&self.path)?;
 
-        Ok(self.store.blob_size(&self.path)?.is_compressed())
+        self.store.is_pinned(&self.path)
     }
 
-    /// Truncate the file to zero bytes.
+    /// Store a blob derived from this file, like a thumbnail, preview, or transcode.
+    ///
+    /// Derived blobs are stored in a side table in the archive under a `kind` of your choosing,
+    /// like `"thumb256"`, and replace any blob of the same kind already stored for this file. They
+    /// follow the file when it's renamed and are deleted along with it, but they aren't updated
+    /// when the contents of the file change; that's up to you.
     ///
     /// # Errors
     ///
     /// - [`FileNotFound`]: This file does not exist.
-    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    /// - [`InvalidArgs`]: `kind` is empty.
     ///
     /// # Examples
     ///
@@ -596,28 +975,401 @@ impl<'conn, 'ar> File<'conn, 'ar> {
     /// # let mut connection = Connection::open_in_memory()?;
     /// # let mut tx = connection.transaction()?;
     /// # let archive = tx.archive_mut();
-    /// let mut file = archive.open("file")?;
+    /// let mut file = archive.open("photo.jpg")?;
     /// file.create_file()?;
-    /// file.write_str("Hello, world!")?;
+    /// file.set_derived("thumb256", b"thumbnail")?;
     ///
-    /// assert!(!file.is_empty()?);
-    ///
-    /// file.truncate()?;
-    ///
-    /// assert!(file.is_empty()?);
+    /// assert_eq!(file.derived("thumb256")?, Some(b"thumbnail".to_vec()));
     /// # sqlarfs::Result::Ok(())
     /// ```
     ///
     /// [`FileNotFound`]: crate::Error::FileNotFound
-    /// [`NotARegularFile`]: crate::Error::NotARegularFile
-    pub fn truncate(&mut self) -> crate::Result<()> {
-        self.validate_is_writable()?;
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    pub fn set_derived(&mut self, kind: &str, bytes: &[u8]) -> crate::Result<()> {
+        if kind.is_empty() {
+            return Err(crate::Error::InvalidArgs {
+                reason: String::from("The kind of derived blob is empty."),
+            });
+        }
 
-        self.store.exec(|store| {
-            store.allocate_blob(&self.path, 0)?;
-            store.set_size(&self.path, 0)?;
+        self.store.set_derived(&self.path, kind, bytes)
+    }
 
-            Ok(())
+    /// The blob of the given `kind` derived from this file, or `None` if there isn't one.
+    ///
+    /// See [`File::set_derived`].
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    pub fn derived(&self, kind: &str) -> crate::Result<Option<Vec<u8>>> {
+        // Make sure the file exists.
+        self.store.read_metadata(&self.path)?;
+
+        self.store.get_derived(&self.path, kind)
+    }
+
+    /// Remove the blob of the given `kind` derived from this file.
+    ///
+    /// This returns `true` if there was a blob to remove.
+    ///
+    /// See [`File::set_derived`].
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    pub fn remove_derived(&mut self, kind: &str) -> crate::Result<bool> {
+        // Make sure the file exists.
+        self.store.read_metadata(&self.path)?;
+
+        self.store.remove_derived(&self.path, kind)
+    }
+
+    /// The file metadata.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    pub fn metadata(&self) -> crate::Result<FileMetadata> {
+        self.store.read_metadata(&self.path)
+    }
+
+    /// The ID of this file.
+    ///
+    /// This is the same as [`ListEntry::id`]. You can keep it to open this file again later with
+    /// [`Archive::open_by_id`], even if it's renamed in the meantime.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut file = archive.open("file")?;
+    /// file.create_file()?;
+    ///
+    /// let id = file.id()?;
+    /// file.rename_to("renamed")?;
+    /// drop(file);
+    ///
+    /// let file = archive.open_by_id(id)?.unwrap();
+    /// assert_eq!(file.path(), std::path::Path::new("renamed"));
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`ListEntry::id`]: crate::ListEntry::id
+    /// [`Archive::open_by_id`]: crate::Archive::open_by_id
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    pub fn id(&self) -> crate::Result<EntryId> {
+        let (rowid, _) = self.store.read_entry(&self.path)?;
+        Ok(EntryId(rowid))
+    }
+
+    /// Set the file mode.
+    ///
+    /// The file mode is nullable, so it's possible to set this to `None`.
+    ///
+    /// Attempting to set the mode of a symlink is a no-op; Symlinks always have `777` permissions.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{Connection, FileMode};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mode = FileMode::OWNER_R | FileMode::OWNER_W | FileMode::GROUP_R | FileMode::GROUP_W;
+    ///
+    /// let mut file = archive.open("file")?;
+    /// file.create_file()?;
+    /// file.set_mode(Some(mode))?;
+    ///
+    /// assert_eq!(file.metadata()?.mode(), Some(mode));
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    pub fn set_mode(&mut self, mode: Option<FileMode>) -> crate::Result<()> {
+        self.store.set_mode(&self.path, mode)
+    }
+
+    /// Set the time the file was last modified.
+    ///
+    /// The file mtime is nullable, so it's possible to set this to `None`.
+    ///
+    /// The mtime in a SQLite archive only has a precision of 1 second, so this rounds down to the
+    /// nearest whole second.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::{UNIX_EPOCH, SystemTime, Duration};
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mtime = SystemTime::now();
+    /// let mut file = archive.open("file")?;
+    /// file.create_file()?;
+    /// file.set_mtime(Some(mtime))?;
+    ///
+    /// // The mtime in a SQLite archive only has a precision of 1 second.
+    /// let truncated_mtime = UNIX_EPOCH + Duration::from_secs(mtime.duration_since(UNIX_EPOCH).unwrap().as_secs());
+    ///
+    /// assert_eq!(file.metadata()?.mtime(), Some(truncated_mtime));
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    pub fn set_mtime(&mut self, mtime: Option<SystemTime>) -> crate::Result<()> {
+        self.store.set_mtime(&self.path, mtime)
+    }
+
+    /// Attach a piece of user-defined metadata to this file.
+    ///
+    /// This stores an arbitrary key-value pair alongside the file, overwriting any existing value
+    /// for `key`. This is meant for small pieces of application-specific data, like where a file
+    /// was downloaded from. The metadata follows the file when it's renamed and is deleted along
+    /// with the file.
+    ///
+    /// This metadata is stored in a separate table in the database, so other tools that read
+    /// SQLite archives will ignore it.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut file = archive.open("file")?;
+    /// file.create_file()?;
+    /// file.set_meta("origin-url", "https://example.com/file")?;
+    ///
+    /// assert_eq!(file.meta("origin-url")?.as_deref(), Some("https://example.com/file"));
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    pub fn set_meta(&mut self, key: &str, value: &str) -> crate::Result<()> {
+        self.store.set_meta(&self.path, key, value)
+    }
+
+    /// Get the user-defined metadata for `key`, or `None` if it isn't set.
+    ///
+    /// See [`File::set_meta`].
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    pub fn meta(&self, key: &str) -> crate::Result<Option<String>> {
+        // Make sure the file exists.
+        self.store.read_metadata(&self.path)?;
+
+        self.store.get_meta(&self.path, key)
+    }
+
+    /// Delete the user-defined metadata for `key`.
+    ///
+    /// This returns `true` if there was a value to delete.
+    ///
+    /// See [`File::set_meta`].
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    pub fn delete_meta(&mut self, key: &str) -> crate::Result<bool> {
+        // Make sure the file exists.
+        self.store.read_metadata(&self.path)?;
+
+        self.store.delete_meta(&self.path, key)
+    }
+
+    /// Take out an exclusive lock on this file, waiting until it's available.
+    ///
+    /// This lock is advisory; it doesn't stop anyone from reading or writing the file. It's meant
+    /// to keep threads that share an archive from interleaving their writes to the same file.
+    /// Locks are shared by every [`Connection`] to the same database in this process, and are
+    /// released when the returned [`FileLock`] is dropped.
+    ///
+    /// The lock is held independently of the current transaction, so it can be held across
+    /// transactions. Be careful not to wait on a lock while holding a write transaction that the
+    /// current holder of the lock is waiting on, or you'll deadlock. Consider using
+    /// [`File::try_lock_exclusive`] or [`File::lock_exclusive_timeout`] instead.
+    ///
+    /// The file does not need to exist to be locked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut file = archive.open("file")?;
+    /// file.create_file()?;
+    ///
+    /// let lock = file.lock_exclusive()?;
+    /// file.truncate()?;
+    /// file.write_str("hello world")?;
+    /// drop(lock);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`Connection`]: crate::Connection
+    pub fn lock_exclusive(&self) -> crate::Result<FileLock> {
+        lock::acquire(&self.lock_namespace, &self.path, None)
+    }
+
+    /// Take out an exclusive lock on this file, or fail if it's already locked.
+    ///
+    /// See [`File::lock_exclusive`].
+    ///
+    /// # Errors
+    ///
+    /// - [`WouldBlock`]: This file is already locked.
+    ///
+    /// [`WouldBlock`]: crate::Error::WouldBlock
+    pub fn try_lock_exclusive(&self) -> crate::Result<FileLock> {
+        lock::acquire(&self.lock_namespace, &self.path, Some(Duration::ZERO))
+    }
+
+    /// Take out an exclusive lock on this file, waiting up to `timeout` for it to be available.
+    ///
+    /// See [`File::lock_exclusive`].
+    ///
+    /// # Errors
+    ///
+    /// - [`WouldBlock`]: This file was still locked after waiting for `timeout`.
+    ///
+    /// [`WouldBlock`]: crate::Error::WouldBlock
+    pub fn lock_exclusive_timeout(&self, timeout: Duration) -> crate::Result<FileLock> {
+        lock::acquire(&self.lock_namespace, &self.path, Some(timeout))
+    }
+
+    /// Whether the file is empty.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    pub fn is_empty(&self) -> crate::Result<bool> {
+        self.validate_is_readable()?;
+
+        match self.metadata()? {
+            FileMetadata::File { size, .. } => Ok(size == 0),
+            _ => unreachable!("By this point, we should have already checked that the file is a regular file. This is a bug."),
+        }
+    }
+
+    /// Whether the contents of this file are compressed.
+    ///
+    /// Even if compression is enabled via [`File::set_compression`], a file may not be compressed
+    /// if it's incompressible or if compressing it would *increase* its size.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{Connection, Compression};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let compressible_data = " ".repeat(32);
+    ///
+    /// let mut file = archive.open("file")?;
+    /// file.create_file()?;
+    ///
+    /// file.set_compression(Compression::None);
+    /// file.write_str(&compressible_data)?;
+    ///
+    /// assert!(!file.is_compressed()?);
+    ///
+    /// file.set_compression(Compression::BEST);
+    /// file.write_str(&compressible_data)?;
+    ///
+    /// assert!(file.is_compressed()?);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    pub fn is_compressed(&self) -> crate::Result<bool> {
+        self.validate_is_readable()?;
+
+        Ok(self.store.blob_size(&self.path)?.is_compressed())
+    }
+
+    /// Truncate the file to zero bytes.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut file = archive.open("file")?;
+    /// file.create_file()?;
+    /// file.write_str("Hello, world!")?;
+    ///
+    /// assert!(!file.is_empty()?);
+    ///
+    /// file.truncate()?;
+    ///
+    /// assert!(file.is_empty()?);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    pub fn truncate(&mut self) -> crate::Result<()> {
+        self.validate_is_writable()?;
+
+        self.store.exec(|store| {
+            store.allocate_blob(&self.path, 0)?;
+            store.set_size(&self.path, 0)?;
+
+            self.touch_after_write(store)
         })
     }
 
@@ -668,7 +1420,164 @@ impl<'conn, 'ar> File<'conn, 'ar> {
         FileReader::new(self.store.open_blob(&self.path, true)?)
     }
 
-    fn write_stream<R>(&mut self, reader: &mut R, size_hint: Option<u64>) -> crate::Result<()>
+    /// Compute the SHA-256 digest of the contents of this file.
+    ///
+    /// The digest is of the uncompressed contents, so it's the same whether or not the file is
+    /// compressed, and it matches what `sha256sum` would print for the file once extracted.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`CompressionNotSupported`]: This file is compressed, but the `deflate` Cargo feature is
+    ///   disabled.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut file = archive.open("file")?;
+    /// file.create_file()?;
+    /// file.write_str("Hello, world!")?;
+    ///
+    /// assert_eq!(
+    ///     file.digest()?.to_string(),
+    ///     "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3",
+    /// );
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    pub fn digest(&mut self) -> crate::Result<Digest> {
+        Ok(digest_stream(&mut self.reader()?)?)
+    }
+
+    /// Compute the digest of the contents of this file using the given hash `algorithm`.
+    ///
+    /// This is like [`File::digest`], but lets you pick the hash algorithm. The contents are
+    /// streamed through the hash function, so the file isn't read into memory.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`CompressionNotSupported`]: This file is compressed, but the `deflate` Cargo feature is
+    ///   disabled.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{Connection, DigestAlgorithm};
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut file = archive.open("file")?;
+    /// file.create_file()?;
+    /// file.write_str("Hello, world!")?;
+    ///
+    /// let digest = file.digest_with(DigestAlgorithm::Sha256)?;
+    ///
+    /// assert_eq!(digest, file.digest()?);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    pub fn digest_with(&mut self, algorithm: DigestAlgorithm) -> crate::Result<Digest> {
+        Ok(digest_stream_with(&mut self.reader()?, algorithm)?)
+    }
+
+    /// Read up to the first `len` bytes of the file.
+    ///
+    /// If the file is compressed, this only decompresses as much of it as it needs to, so it's
+    /// cheap to call on large files. This returns fewer than `len` bytes if the file is shorter
+    /// than that.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`CompressionNotSupported`]: This file is compressed, but the `deflate` Cargo feature is
+    ///   disabled.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut file = archive.open("file")?;
+    /// file.create_file()?;
+    /// file.write_str("Hello, world!")?;
+    ///
+    /// assert_eq!(file.head(5)?, b"Hello");
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    pub fn head(&mut self, len: u64) -> crate::Result<Vec<u8>> {
+        let reader = self.reader()?;
+
+        let mut buf = Vec::with_capacity(usize::try_from(len.min(reader.len())).unwrap_or(0));
+        reader.take(len).read_to_end(&mut buf)?;
+
+        Ok(buf)
+    }
+
+    /// Guess whether this file contains text rather than binary data.
+    ///
+    /// This looks at the first few kilobytes of the file, and considers it text if they're valid
+    /// UTF-8 and don't contain any NUL bytes. Empty files are considered text. Like any heuristic,
+    /// this can be wrong, especially for files in other text encodings like UTF-16.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`CompressionNotSupported`]: This file is compressed, but the `deflate` Cargo feature is
+    ///   disabled.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut text = archive.open("text")?;
+    /// text.create_file()?;
+    /// text.write_str("Hello, world!")?;
+    ///
+    /// let mut binary = archive.open("binary")?;
+    /// binary.create_file()?;
+    /// binary.write_bytes(&[0x7f, b'E', b'L', b'F', 0x02, 0x01, 0x01, 0x00])?;
+    ///
+    /// assert!(text.is_probably_text()?);
+    /// assert!(!binary.is_probably_text()?);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    pub fn is_probably_text(&mut self) -> crate::Result<bool> {
+        Ok(looks_like_text(&self.head(u64_from_usize(TEXT_SNIFF_LEN))?))
+    }
+
+    pub(super) fn write_stream<R>(
+        &mut self,
+        reader: &mut R,
+        size_hint: Option<u64>,
+    ) -> crate::Result<()>
     where
         R: ?Sized + Read,
     {
@@ -687,139 +1596,160 @@ impl<'conn, 'ar> File<'conn, 'ar> {
                         io::copy(reader, &mut blob)?
                     }
                     None => {
-                        // We do not have the length of the input stream, so we need to write it to
-                        // an in-memory buffer to find out how large of a blob to allocate in the
-                        // database.
-
-                        let mut buf = Vec::new();
-                        reader.read_to_end(&mut buf)?;
+                        // We do not have the length of the input stream, so we need to find out
+                        // how large of a blob to allocate in the database before we can write it.
+                        //
+                        // Rather than buffering the whole stream in memory, we write it in chunks
+                        // to a temporary table, which SQLite spills to disk as it grows. Once we
+                        // know the total size, we allocate the blob and copy the chunks into it.
+                        // This means the data is written twice, but memory usage stays constant
+                        // no matter how large the stream is. Callers that know the size up front
+                        // should pass it so we can skip the spool and write the blob directly.
+
+                        let spool = store.create_spool()?;
+
+                        let mut chunk = vec![0u8; SPOOL_CHUNK_SIZE];
+                        let mut total_len = 0;
+
+                        loop {
+                            let chunk_len = read_chunk(reader, &mut chunk)?;
+
+                            if chunk_len == 0 {
+                                break;
+                            }
+
+                            store.append_spool(spool, &chunk[..chunk_len])?;
+                            total_len += u64_from_usize(chunk_len);
+                        }
 
-                        store.allocate_blob(&self.path, u64_from_usize(buf.len()))?;
+                        store.allocate_blob(&self.path, total_len)?;
                         let mut blob = store.open_blob(&self.path, false)?.into_blob();
 
-                        blob.write_all(&buf)?;
+                        store.read_spool(spool, |data| Ok(blob.write_all(data)?))?;
+
+                        // Close the blob handle before we touch the database again.
+                        drop(blob);
 
-                        u64_from_usize(buf.len())
+                        store.clear_spool(spool)?;
+
+                        total_len
                     }
                 },
 
                 #[cfg(feature = "deflate")]
                 Compression::Deflate { level } => {
                     // We have no way of knowing the compressed size of the data until we actually
-                    // compress it, so we need to write it to an in-memory buffer to find out how
-                    // large of a blob to allocate in the database.
-                    //
-                    // Additionally, we need to know whether the compressed data is smaller than
-                    // the uncompressed data or not, but we want to avoid keeping both the full
-                    // uncompressed data and the full compressed data in memory, because the
-                    // `reader` could potentially return a large amount of data.
+                    // compress it, so we compress the whole stream before allocating the blob.
                     //
-                    // This implementation tries to strike a balance between minimizing the amount
-                    // of data we're keeping in memory and avoiding the need to do extra work
-                    // compressing data multiple times.
+                    // The compressed output is kept in memory until it grows past
+                    // `COMPRESS_MEMORY_BUDGET`, after which it's moved in chunks to a temporary
+                    // table, which SQLite spills to disk as it grows. This means each byte of the
+                    // input is only compressed once, and memory usage stays bounded no matter how
+                    // large the stream is.
                     //
-                    // The worst-case scenario is that we find out the input is compressible only
-                    // after we've compressed a lot of it, after which we end up compressing it
-                    // again.
-                    //
-                    // However, if the input is compressible, we'll probably figure that out pretty
-                    // quickly. As files get larger, the probability that they can't be compressed
-                    // *at all* decreases.
-                    //
-                    // We're also relying on the user to disable compression if they know they're
-                    // going to be writing a lot of data that's mostly incompressible (e.g. photos
-                    // and videos that are already compressed).
-
-                    let compression_level = flate2::Compression::new(level);
-
-                    let allocation_size = match size_hint {
-                        Some(len) => Some(len.try_into().map_err(|_| crate::Error::FileTooBig)?),
-                        None => None,
-                    };
-
-                    let mut uncompressed_buf = if let Some(capacity) = allocation_size {
-                        Vec::with_capacity(capacity)
-                    } else {
-                        Vec::new()
-                    };
+                    // We don't keep a copy of the uncompressed input. If it turns out the data
+                    // isn't compressible, we get it back by decompressing what we wrote, which is
+                    // cheap for data that deflate couldn't shrink.
 
-                    let mut copy_buf = vec![0u8; COPY_BUF_SIZE];
+                    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::new(level));
+                    let mut spool = None;
+                    let mut spooled_len = 0;
+                    let mut original_size = 0;
 
-                    // This encoder doesn't write the compressed data anywhere; we're only using it
-                    // to determine the compressed size of the data.
-                    let mut test_encoder = ZlibEncoder::new(io::sink(), compression_level);
+                    let mut chunk = vec![0u8; SPOOL_CHUNK_SIZE];
 
-                    let mut is_compressible = false;
-
-                    // We need to keep track of the total uncompressed size of the input, because
-                    // the uncompressed size of the file goes in the database.
-                    let mut bytes_read_so_far = 0;
-
-                    // Determine whether this file is compressible by writing the data to the
-                    // encoder until it says the output size is smaller than the input size.
                     loop {
-                        let bytes_read = reader.read(&mut copy_buf)?;
-                        bytes_read_so_far += u64_from_usize(bytes_read);
+                        let chunk_len = read_chunk(reader, &mut chunk)?;
 
-                        if bytes_read == 0 {
+                        if chunk_len == 0 {
                             break;
                         }
 
-                        uncompressed_buf.extend_from_slice(&copy_buf[..bytes_read]);
+                        encoder.write_all(&chunk[..chunk_len])?;
+                        original_size += u64_from_usize(chunk_len);
 
-                        test_encoder.write_all(&copy_buf[..bytes_read])?;
+                        if encoder.get_ref().len() >= COMPRESS_MEMORY_BUDGET {
+                            let spool = match spool {
+                                Some(spool) => spool,
+                                None => *spool.insert(store.create_spool()?),
+                            };
 
-                        // Flush the encoder's internal buffer to ensure we get an accurate count
-                        // of the total number of bytes input and output.
-                        test_encoder.flush()?;
+                            let compressed = encoder.get_mut();
 
-                        if test_encoder.total_out() < test_encoder.total_in() {
-                            is_compressible = true;
-                            break;
+                            for compressed_chunk in compressed.chunks(SPOOL_CHUNK_SIZE) {
+                                store.append_spool(spool, compressed_chunk)?;
+                            }
+
+                            spooled_len += u64_from_usize(compressed.len());
+                            compressed.clear();
                         }
                     }
 
-                    let bytes_to_write = if is_compressible {
-                        // Now that we know the file is compressible, and we have the full contents
-                        // of the `reader` in memory, we can compress it and keep the result to
-                        // write to the blob.
+                    let tail = encoder.finish()?;
+                    let compressed_size = spooled_len + u64_from_usize(tail.len());
 
-                        let compressed_buf = if let Some(capacity) = allocation_size {
-                            Vec::with_capacity(capacity)
-                        } else {
-                            Vec::new()
-                        };
+                    if compressed_size < original_size {
+                        store.allocate_blob(&self.path, compressed_size)?;
+                        let mut blob = store.open_blob(&self.path, false)?.into_blob();
 
-                        let mut encoder = ZlibEncoder::new(compressed_buf, compression_level);
+                        if let Some(spool) = spool {
+                            store.read_spool(spool, |data| Ok(blob.write_all(data)?))?;
+                        }
 
-                        // Copy the data we've read from the `reader` so far into the encoder.
-                        encoder.write_all(&uncompressed_buf)?;
+                        blob.write_all(&tail)?;
+                    } else {
+                        // The sqlar spec requires that we store the data uncompressed when
+                        // compressing it doesn't make it smaller.
+                        store.allocate_blob(&self.path, original_size)?;
+                        let blob = store.open_blob(&self.path, false)?.into_blob();
+                        let mut decoder = ZlibDecoder::new(blob);
+
+                        if let Some(spool) = spool {
+                            store.read_spool(spool, |data| Ok(decoder.write_all(data)?))?;
+                        }
 
-                        // Drop the uncompressed data to free that memory; we don't need it
-                        // anymore.
-                        drop(uncompressed_buf);
+                        decoder.write_all(&tail)?;
+                        decoder.finish()?;
+                    }
 
-                        // Copy the rest of the data—the data we have not read yet—into the
-                        // encoder.
-                        bytes_read_so_far += io::copy(reader, &mut encoder)?;
+                    if let Some(spool) = spool {
+                        store.clear_spool(spool)?;
+                    }
 
-                        encoder.finish()?
-                    } else {
-                        uncompressed_buf
+                    original_size
+                }
+
+                #[cfg(feature = "zstd")]
+                Compression::Zstd { level } => {
+                    // Unlike with DEFLATE, we don't try to find out whether the data is
+                    // compressible as we read it. We read the whole input into memory and then
+                    // compress it all at once.
+                    let mut uncompressed_buf = match size_hint {
+                        Some(len) => Vec::with_capacity(
+                            len.try_into().map_err(|_| crate::Error::FileTooBig)?,
+                        ),
+                        None => Vec::new(),
                     };
 
-                    store.allocate_blob(&self.path, u64_from_usize(bytes_to_write.len()))?;
-                    let mut target_blob = store.open_blob(&self.path, false)?.into_blob();
+                    reader.read_to_end(&mut uncompressed_buf)?;
 
-                    target_blob.write_all(&bytes_to_write)?;
+                    let compressed_buf = zstd_compress(store, &uncompressed_buf, level)?;
 
-                    bytes_read_so_far
+                    // Only use the compressed data if it's smaller than the uncompressed data. The
+                    // sqlar spec requires this.
+                    if compressed_buf.len() < uncompressed_buf.len() {
+                        store.store_blob(&self.path, &compressed_buf)?;
+                    } else {
+                        store.store_blob(&self.path, &uncompressed_buf)?;
+                    }
+
+                    u64_from_usize(uncompressed_buf.len())
                 }
             };
 
             store.set_size(&self.path, original_size)?;
 
-            Ok(())
+            self.touch_after_write(store)
         })
     }
 
@@ -827,6 +1757,12 @@ impl<'conn, 'ar> File<'conn, 'ar> {
     ///
     /// This truncates the file and copies the entire `reader` into it.
     ///
+    /// Because the size of `reader` isn't known ahead of time, the data is first staged in a
+    /// temporary table in the database and then copied into the file. This keeps memory usage
+    /// constant regardless of the size of the input, at the cost of writing the data twice. If you
+    /// know the size of the input, [`File::write_file`] and [`File::write_bytes`] avoid this extra
+    /// copy when compression is disabled.
+    ///
     /// # Errors
     ///
     /// - [`FileNotFound`]: This file does not exist.
@@ -841,6 +1777,53 @@ impl<'conn, 'ar> File<'conn, 'ar> {
         self.write_stream(reader, None)
     }
 
+    /// Return a writer for streaming data into the file.
+    ///
+    /// This is like [`File::write_from`], except that rather than passing a reader, you write data
+    /// to the returned [`FileWriter`] as it's produced. The file is truncated and its contents are
+    /// replaced with the data written to the writer once it's finished. Like [`File::write_from`],
+    /// the data is staged in the database as it's written, so memory usage stays constant
+    /// regardless of how much data is written.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::io::{Read, Write};
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut file = archive.open("file")?;
+    /// file.create_file()?;
+    ///
+    /// let mut writer = file.writer()?;
+    ///
+    /// for line in ["one", "two", "three"] {
+    ///     writeln!(writer, "{line}")?;
+    /// }
+    ///
+    /// writer.finish()?;
+    ///
+    /// let mut contents = String::new();
+    /// file.reader()?.read_to_string(&mut contents)?;
+    ///
+    /// assert_eq!(contents, "one\ntwo\nthree\n");
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    pub fn writer(&mut self) -> crate::Result<FileWriter<'_, 'conn, 'ar>> {
+        self.validate_is_writable()?;
+
+        FileWriter::new(self)
+    }
+
     /// Overwrite the file with the given bytes.
     ///
     /// This truncates the file and writes all of the given bytes to it.
@@ -856,32 +1839,11 @@ impl<'conn, 'ar> File<'conn, 'ar> {
         self.validate_is_writable()?;
 
         self.store.exec(|store| {
-            match self.compression {
-                Compression::None => {
-                    store.store_blob(&self.path, bytes)?;
-                }
-                #[cfg(feature = "deflate")]
-                Compression::Deflate { level } => {
-                    let mut encoder = ZlibEncoder::new(
-                        Vec::with_capacity(bytes.len()),
-                        flate2::Compression::new(level),
-                    );
-                    encoder.write_all(bytes)?;
-                    let compressed_bytes = encoder.finish()?;
-
-                    // Only use the compressed data if it's smaller than the uncompressed data. The
-                    // sqlar spec requires this.
-                    if compressed_bytes.len() < bytes.len() {
-                        store.store_blob(&self.path, &compressed_bytes)?;
-                    } else {
-                        store.store_blob(&self.path, bytes)?;
-                    }
-                }
-            };
+            store.store_blob(&self.path, &compress_bytes(store, self.compression, bytes)?)?;
 
             store.set_size(&self.path, u64_from_usize(bytes.len()))?;
 
-            Ok(())
+            self.touch_after_write(store)
         })
     }
 
@@ -919,6 +1881,30 @@ impl<'conn, 'ar> File<'conn, 'ar> {
         self.write_stream(file, Some(metadata.len()))
     }
 
+    /// Replace the contents of the file with the contents of `reader`, all at once.
+    ///
+    /// This is the same as [`File::write_from`], but it guarantees that the file isn't touched
+    /// until the new contents have been completely written. If reading from `reader` fails, the
+    /// file keeps its old contents. The file's metadata is left alone, except that its mtime is
+    /// updated if [`Archive::set_update_mtime`] is enabled.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    ///
+    /// [`Archive::set_update_mtime`]: crate::Archive::set_update_mtime
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    pub fn replace_contents_atomic<R>(&mut self, reader: &mut R) -> crate::Result<()>
+    where
+        R: ?Sized + Read,
+    {
+        // Writes run in a savepoint, so if reading from `reader` fails partway through, the
+        // truncated file is rolled back along with everything else.
+        self.write_from(reader)
+    }
+
     /// The current compression method used when writing to the file.
     pub fn compression(&self) -> Compression {
         self.compression
@@ -983,3 +1969,11 @@ impl<'conn, 'ar> File<'conn, 'ar> {
         self.umask = mode;
     }
 }
+
+impl<'conn, 'ar> Drop for File<'conn, 'ar> {
+    fn drop(&mut self) {
+        if self.claimed {
+            self.store.release_path(&self.path);
+        }
+    }
+}
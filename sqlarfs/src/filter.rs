@@ -0,0 +1,140 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::archive::Archive;
+
+type PathMatcher = dyn Fn(&Path) -> bool + Send + Sync;
+
+type Transform = dyn Fn(&[u8]) -> io::Result<Vec<u8>> + Send + Sync;
+
+/// A transformation applied to the contents of files as they're archived and extracted.
+///
+/// A filter applies to the regular files whose path in the archive matches its predicate. You can
+/// register a filter with [`Archive::register_filter`], after which it applies to files archived
+/// with [`Archive::archive_with`] and extracted with [`Archive::extract_with`].
+///
+/// Filters are for cases like normalizing line endings or stripping metadata from images. The
+/// transform set with [`Filter::on_archive`] is applied to the contents of each file before it's
+/// written to the archive, and the transform set with [`Filter::on_extract`] is applied to the
+/// contents of each file before it's written to the filesystem. Each transform is passed the
+/// entire contents of the file, so files that match a filter are read into memory.
+///
+/// # Examples
+///
+/// Store text files with Unix line endings, and extract them with Windows line endings.
+///
+/// ```
+/// # use sqlarfs::Filter;
+/// let filter = Filter::new(|path| path.extension().is_some_and(|ext| ext == "txt"))
+///     .on_archive(|contents| {
+///         Ok(String::from_utf8_lossy(contents).replace("\r\n", "\n").into_bytes())
+///     })
+///     .on_extract(|contents| {
+///         Ok(String::from_utf8_lossy(contents).replace('\n', "\r\n").into_bytes())
+///     });
+/// ```
+///
+/// [`Archive::register_filter`]: crate::Archive::register_filter
+/// [`Archive::archive_with`]: crate::Archive::archive_with
+/// [`Archive::extract_with`]: crate::Archive::extract_with
+#[derive(Clone)]
+pub struct Filter {
+    matches: Arc<PathMatcher>,
+    on_archive: Option<Arc<Transform>>,
+    on_extract: Option<Arc<Transform>>,
+}
+
+impl fmt::Debug for Filter {
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Filter")
+            .field("matches", &"..")
+            .field("on_archive", &self.on_archive.as_ref().map(|_| ".."))
+            .field("on_extract", &self.on_extract.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+impl Filter {
+    /// Create a new [`Filter`] that applies to files whose path in the archive matches the given
+    /// predicate.
+    ///
+    /// The filter doesn't change anything until you set a transform with [`Filter::on_archive`]
+    /// or [`Filter::on_extract`].
+    pub fn new<F>(matches: F) -> Self
+    where
+        F: Fn(&Path) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            matches: Arc::new(matches),
+            on_archive: None,
+            on_extract: None,
+        }
+    }
+
+    /// Transform the contents of matching files as they're archived.
+    ///
+    /// If this returns an error, archiving stops and returns it.
+    ///
+    /// By default, the contents are archived unchanged.
+    pub fn on_archive<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(&[u8]) -> io::Result<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.on_archive = Some(Arc::new(transform));
+        self
+    }
+
+    /// Transform the contents of matching files as they're extracted.
+    ///
+    /// This is typically the inverse of the transform passed to [`Filter::on_archive`]. If this
+    /// returns an error, extracting stops and returns it.
+    ///
+    /// By default, the contents are extracted unchanged.
+    pub fn on_extract<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(&[u8]) -> io::Result<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.on_extract = Some(Arc::new(transform));
+        self
+    }
+}
+
+// Apply the archive transforms of `filters` to `contents`, in the order they were registered.
+pub(super) fn apply_on_archive(filters: &[Filter], mut contents: Vec<u8>) -> io::Result<Vec<u8>> {
+    for transform in filters
+        .iter()
+        .filter_map(|filter| filter.on_archive.as_ref())
+    {
+        contents = transform(&contents)?;
+    }
+
+    Ok(contents)
+}
+
+// Apply the extract transforms of `filters` to `contents`, in the reverse of the order they were
+// registered, so they undo the archive transforms.
+pub(super) fn apply_on_extract(filters: &[Filter], mut contents: Vec<u8>) -> io::Result<Vec<u8>> {
+    for transform in filters
+        .iter()
+        .rev()
+        .filter_map(|filter| filter.on_extract.as_ref())
+    {
+        contents = transform(&contents)?;
+    }
+
+    Ok(contents)
+}
+
+impl<'conn> Archive<'conn> {
+    // Return the registered filters that apply to the file at `path` in the archive.
+    pub(super) fn matching_filters(&self, path: &Path) -> Vec<Filter> {
+        self.filters
+            .iter()
+            .filter(|filter| (filter.matches)(path))
+            .cloned()
+            .collect()
+    }
+}
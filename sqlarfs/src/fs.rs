@@ -0,0 +1,197 @@
+//! Functions for reading and writing files in an archive, modeled after [`std::fs`].
+//!
+//! These are shortcuts for common operations on an [`Archive`] that take the same arguments as
+//! their counterparts in [`std::fs`], plus the archive to operate on. They're meant to make it
+//! easy to port code that works with the real filesystem to work with an archive instead. For
+//! anything they don't cover, use [`Archive::open`] and the methods on [`File`].
+//!
+//! Unlike their counterparts in [`std::fs`], these don't follow symbolic links.
+//!
+//! ```
+//! # use sqlarfs::Connection;
+//! use sqlarfs::fs;
+//!
+//! let mut conn = Connection::open_in_memory()?;
+//!
+//! conn.exec(|archive| {
+//!     fs::create_dir_all(archive, "path/to")?;
+//!     fs::write(archive, "path/to/file", "Hello, world!")?;
+//!
+//!     assert_eq!(fs::read(archive, "path/to/file")?, b"Hello, world!");
+//!
+//!     for entry in fs::read_dir(archive, "path/to")? {
+//!         println!("{}", entry?.path().display());
+//!     }
+//!
+//!     fs::remove_file(archive, "path/to/file")
+//! })?;
+//! # sqlarfs::Result::Ok(())
+//! ```
+//!
+//! [`File`]: crate::File
+
+use std::io::Read;
+use std::path::Path;
+
+use super::archive::Archive;
+use super::list::{ListEntries, ListOptions};
+use super::metadata::FileMetadata;
+
+/// Read the entire contents of a file into a bytes vector.
+///
+/// This is the archive equivalent of [`std::fs::read`].
+///
+/// # Errors
+///
+/// - [`FileNotFound`]: The file does not exist.
+/// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+/// - [`CompressionNotSupported`]: The file is compressed, but the `deflate` Cargo feature is
+///   disabled.
+///
+/// [`FileNotFound`]: crate::Error::FileNotFound
+/// [`NotARegularFile`]: crate::Error::NotARegularFile
+/// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+pub fn read<P: AsRef<Path>>(archive: &Archive, path: P) -> crate::Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    archive.open(path)?.reader()?.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// Read the entire contents of a file into a string.
+///
+/// This is the archive equivalent of [`std::fs::read_to_string`].
+///
+/// # Errors
+///
+/// This returns the same errors as [`read`], plus:
+///
+/// - [`Io`]: The contents of the file are not valid UTF-8.
+///
+/// [`Io`]: crate::Error::Io
+pub fn read_to_string<P: AsRef<Path>>(archive: &Archive, path: P) -> crate::Result<String> {
+    let mut contents = String::new();
+    archive
+        .open(path)?
+        .reader()?
+        .read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+/// Write a slice as the entire contents of a file.
+///
+/// This creates the file if it doesn't exist and replaces its contents if it does. This is the
+/// archive equivalent of [`std::fs::write`].
+///
+/// New files get their mode from [`Archive::umask`] and are compressed with
+/// [`Archive::compression`].
+///
+/// # Errors
+///
+/// - [`NoParentDirectory`]: The file doesn't exist and its parent directory does not exist or is
+///   not a directory.
+/// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+/// - [`NameTooLong`]: The file's path is longer than [`Archive::max_name_len`].
+///
+/// [`NoParentDirectory`]: crate::Error::NoParentDirectory
+/// [`NotARegularFile`]: crate::Error::NotARegularFile
+/// [`NameTooLong`]: crate::Error::NameTooLong
+pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(
+    archive: &Archive,
+    path: P,
+    contents: C,
+) -> crate::Result<()> {
+    archive.store.exec(|_| {
+        let mut file = archive.open(path)?;
+
+        if !file.exists()? {
+            file.create_file()?;
+        }
+
+        file.write_bytes(contents.as_ref())
+    })
+}
+
+/// Return an iterator over the entries in a directory.
+///
+/// This only returns the immediate children of the directory, not all of its descendants. Pass an
+/// empty path to list the files in the root of the archive. This is the archive equivalent of
+/// [`std::fs::read_dir`].
+///
+/// To filter or sort the entries, use [`Archive::list_with`] with [`ListOptions::children_of`].
+///
+/// # Errors
+///
+/// - [`FileNotFound`]: The directory does not exist.
+/// - [`NotADirectory`]: The file is not a directory.
+///
+/// [`FileNotFound`]: crate::Error::FileNotFound
+/// [`NotADirectory`]: crate::Error::NotADirectory
+pub fn read_dir<'ar, P: AsRef<Path>>(
+    archive: &'ar Archive,
+    path: P,
+) -> crate::Result<ListEntries<'ar>> {
+    let path = path.as_ref();
+
+    if path != Path::new("") && !metadata(archive, path)?.is_dir() {
+        return Err(crate::Error::NotADirectory { path: path.into() });
+    }
+
+    archive.list_with(&ListOptions::new().children_of(path))
+}
+
+/// Recursively create a directory and all of its missing parent directories.
+///
+/// This does nothing if the directory already exists. This is the archive equivalent of
+/// [`std::fs::create_dir_all`].
+///
+/// # Errors
+///
+/// - [`FileAlreadyExists`]: The file already exists and is not a directory.
+/// - [`NoParentDirectory`]: One of the file's ancestors is not a directory.
+///
+/// [`FileAlreadyExists`]: crate::Error::FileAlreadyExists
+/// [`NoParentDirectory`]: crate::Error::NoParentDirectory
+pub fn create_dir_all<P: AsRef<Path>>(archive: &Archive, path: P) -> crate::Result<()> {
+    archive.open(path)?.create_dir_all()
+}
+
+/// Return the metadata of a file.
+///
+/// This is the archive equivalent of [`std::fs::symlink_metadata`], since it doesn't follow
+/// symbolic links.
+///
+/// # Errors
+///
+/// - [`FileNotFound`]: The file does not exist.
+///
+/// [`FileNotFound`]: crate::Error::FileNotFound
+pub fn metadata<P: AsRef<Path>>(archive: &Archive, path: P) -> crate::Result<FileMetadata> {
+    archive.open(path)?.metadata()
+}
+
+/// Remove a regular file or symbolic link.
+///
+/// This is the archive equivalent of [`std::fs::remove_file`]. To remove a directory and
+/// everything in it, use [`File::delete`].
+///
+/// # Errors
+///
+/// - [`FileNotFound`]: The file does not exist.
+/// - [`NotARegularFile`]: The file is a directory.
+/// - [`FilePinned`]: The file is pinned. See [`File::pin`].
+///
+/// [`File::delete`]: crate::File::delete
+/// [`File::pin`]: crate::File::pin
+/// [`FileNotFound`]: crate::Error::FileNotFound
+/// [`NotARegularFile`]: crate::Error::NotARegularFile
+/// [`FilePinned`]: crate::Error::FilePinned
+pub fn remove_file<P: AsRef<Path>>(archive: &Archive, path: P) -> crate::Result<()> {
+    let path = path.as_ref();
+    let mut file = archive.open(path)?;
+
+    if file.metadata()?.is_dir() {
+        return Err(crate::Error::NotARegularFile { path: path.into() });
+    }
+
+    file.delete()
+}
@@ -0,0 +1,448 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::archive::Archive;
+use super::file::File;
+use super::metadata::FileMetadata;
+use super::stream::FileReader;
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// Convert a number of days since the Unix epoch into a (year, month, day) civil date. This is
+// Howard Hinnant's `civil_from_days` algorithm: http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    (year, month, day)
+}
+
+// Format a timestamp as an HTTP date (RFC 9110 `IMF-fixdate`), like
+// `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn http_date(time: SystemTime) -> Option<String> {
+    let secs = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+
+    let days = secs / 86_400;
+    let secs_of_day = secs % 86_400;
+
+    let (year, month, day) = civil_from_days(days);
+    let weekday = WEEKDAYS[usize::try_from(days % 7).ok()?];
+    let month_name = MONTHS[usize::try_from(month - 1).ok()?];
+
+    Some(format!(
+        "{weekday}, {day:02} {month_name} {year:04} {:02}:{:02}:{:02} GMT",
+        secs_of_day / 3600,
+        (secs_of_day % 3600) / 60,
+        secs_of_day % 60,
+    ))
+}
+
+// Compare two entity tags using the weak comparison function from RFC 9110.
+fn etags_match(header: &str, etag: &str) -> bool {
+    let strip_weak = |tag: &str| tag.trim().trim_start_matches("W/").to_owned();
+    let etag = strip_weak(etag);
+
+    header
+        .split(',')
+        .any(|candidate| candidate.trim() == "*" || strip_weak(candidate) == etag)
+}
+
+impl FileMetadata {
+    /// A weak HTTP entity tag for this file.
+    ///
+    /// This is derived from the file's size and mtime, so it changes whenever either one does.
+    /// Because the mtime only has a precision of 1 second and isn't updated automatically when
+    /// writing to a file, this is a weak validator: a file whose contents are replaced with the
+    /// same number of bytes can keep the same tag. It's cheap to compute, but to detect every
+    /// change to the contents, use [`File::etag`] instead. That's the tag
+    /// [`File::read_if_none_match`] compares against.
+    ///
+    /// This returns `None` if this isn't a regular file or it doesn't have an mtime.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::{Duration, UNIX_EPOCH};
+    /// # use sqlarfs::FileMetadata;
+    /// let metadata = FileMetadata::File {
+    ///     mode: None,
+    ///     mtime: Some(UNIX_EPOCH + Duration::from_secs(1)),
+    ///     size: 16,
+    /// };
+    ///
+    /// assert_eq!(metadata.etag().as_deref(), Some("W/\"10-1\""));
+    /// ```
+    pub fn etag(&self) -> Option<String> {
+        match self {
+            Self::File {
+                mtime: Some(mtime),
+                size,
+                ..
+            } => {
+                let mtime_secs = mtime.duration_since(UNIX_EPOCH).ok()?.as_secs();
+                Some(format!("W/\"{size:x}-{mtime_secs:x}\""))
+            }
+            _ => None,
+        }
+    }
+
+    /// The file's mtime formatted as an HTTP date, for use in a `Last-Modified` header.
+    ///
+    /// This returns `None` if the file doesn't have an mtime.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::{Duration, UNIX_EPOCH};
+    /// # use sqlarfs::FileMetadata;
+    /// let metadata = FileMetadata::Dir {
+    ///     mode: None,
+    ///     mtime: Some(UNIX_EPOCH + Duration::from_secs(784_111_777)),
+    /// };
+    ///
+    /// assert_eq!(metadata.last_modified().as_deref(), Some("Sun, 06 Nov 1994 08:49:37 GMT"));
+    /// ```
+    pub fn last_modified(&self) -> Option<String> {
+        http_date(self.mtime()?)
+    }
+}
+
+/// The result of a conditional read of a [`File`].
+///
+/// This is returned by [`File::read_if_none_match`] and [`File::read_if_modified_since`].
+#[derive(Debug)]
+pub enum ConditionalRead<'conn> {
+    /// The file hasn't changed, so there's nothing to read.
+    ///
+    /// In an HTTP server, this corresponds to a `304 Not Modified` response.
+    NotModified,
+
+    /// The file has changed, and this is a reader for its contents.
+    Modified(FileReader<'conn>),
+}
+
+impl<'conn, 'ar> File<'conn, 'ar> {
+    /// A strong HTTP entity tag for this file.
+    ///
+    /// This is derived from the [`File::digest`] of the file's contents, so it changes whenever
+    /// the contents do, regardless of the file's size or mtime. Computing it reads the whole file.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    /// - [`CompressionNotSupported`]: This file is compressed, but the `deflate` Cargo feature is
+    ///   disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let mut connection = Connection::open_in_memory()?;
+    /// # let mut tx = connection.transaction()?;
+    /// # let archive = tx.archive_mut();
+    /// let mut file = archive.open("file")?;
+    /// file.create_file()?;
+    /// file.write_str("Hello, world!")?;
+    ///
+    /// assert_eq!(
+    ///     file.etag()?,
+    ///     "\"315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3\"",
+    /// );
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    pub fn etag(&mut self) -> crate::Result<String> {
+        Ok(format!("\"{}\"", self.digest()?))
+    }
+
+    /// Read this file only if its [`File::etag`] doesn't match `etag`.
+    ///
+    /// This implements the semantics of the HTTP `If-None-Match` header, so `etag` can be a
+    /// comma-separated list of entity tags, or `*` to match any file. Entity tags are compared
+    /// using weak comparison.
+    ///
+    /// Because this compares against a digest of the file's contents, it reads the whole file
+    /// even when it isn't modified.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    /// - [`CompressionNotSupported`]: This file is compressed, but the `deflate` Cargo feature is
+    ///   disabled.
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    pub fn read_if_none_match(&mut self, etag: &str) -> crate::Result<ConditionalRead<'_>> {
+        if etags_match(etag, &self.etag()?) {
+            return Ok(ConditionalRead::NotModified);
+        }
+
+        Ok(ConditionalRead::Modified(self.reader()?))
+    }
+
+    /// Read this file only if it was modified after `since`.
+    ///
+    /// This implements the semantics of the HTTP `If-Modified-Since` header. Because HTTP dates
+    /// have a precision of 1 second, `since` is rounded down to the nearest whole second.
+    ///
+    /// If the file doesn't have an mtime, it is always read.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    /// - [`CompressionNotSupported`]: This file is compressed, but the `deflate` Cargo feature is
+    ///   disabled.
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    pub fn read_if_modified_since(
+        &mut self,
+        since: SystemTime,
+    ) -> crate::Result<ConditionalRead<'_>> {
+        let metadata = self.metadata()?;
+
+        let since_secs = since
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        let mtime_secs = metadata
+            .mtime()
+            .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs());
+
+        if let Some(mtime_secs) = mtime_secs {
+            if mtime_secs <= since_secs {
+                return Ok(ConditionalRead::NotModified);
+            }
+        }
+
+        Ok(ConditionalRead::Modified(self.reader()?))
+    }
+}
+
+/// A `Content-Encoding` for a precompressed file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ContentEncoding {
+    /// Brotli, stored in a sibling file with a `.br` extension.
+    Brotli,
+
+    /// Gzip, stored in a sibling file with a `.gz` extension.
+    Gzip,
+}
+
+impl ContentEncoding {
+    /// The name of this encoding as used in the `Content-Encoding` header.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gzip",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gz",
+        }
+    }
+}
+
+/// How to respond to a request for a file in a static site.
+///
+/// This is returned by [`Archive::resolve_static`].
+///
+/// [`Archive::resolve_static`]: crate::Archive::resolve_static
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StaticResource {
+    /// Serve the file at `path`.
+    File {
+        /// The path of the file in the archive to serve.
+        path: PathBuf,
+
+        /// The content encoding of the file at `path`, if it's a precompressed variant.
+        encoding: Option<ContentEncoding>,
+    },
+
+    /// Redirect the client to `location`.
+    ///
+    /// This is returned when a directory is requested without a trailing slash, so that relative
+    /// links in its index page resolve correctly.
+    Redirect {
+        /// The path to redirect to.
+        location: String,
+    },
+
+    /// There is nothing to serve at this path.
+    NotFound,
+}
+
+// Return whether `encoding` is acceptable according to an `Accept-Encoding` header.
+fn accepts_encoding(header: &str, encoding: ContentEncoding) -> bool {
+    header.split(',').any(|item| {
+        let mut parts = item.split(';');
+        let coding = parts.next().unwrap_or_default().trim();
+
+        let is_rejected = parts.any(|param| {
+            let param = param.trim();
+            param
+                .strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .is_some_and(|q| q <= 0.0)
+        });
+
+        !is_rejected && (coding.eq_ignore_ascii_case(encoding.as_str()) || coding == "*")
+    })
+}
+
+const INDEX_FILE: &str = "index.html";
+
+impl<'conn> Archive<'conn> {
+    fn is_regular_file(&self, path: &Path) -> crate::Result<bool> {
+        match self.open_unclaimed(path)?.metadata() {
+            Ok(metadata) => Ok(metadata.is_file()),
+            Err(crate::Error::FileNotFound { .. }) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub(super) fn resolve_static_path(
+        &self,
+        request_path: &str,
+        accept_encoding: Option<&str>,
+    ) -> crate::Result<StaticResource> {
+        let relative_path = request_path.trim_start_matches('/');
+
+        if relative_path.split('/').any(|segment| segment == "..") {
+            return Ok(StaticResource::NotFound);
+        }
+
+        let has_trailing_slash = relative_path.is_empty() || relative_path.ends_with('/');
+
+        let file_path = if relative_path.trim_end_matches('/').is_empty() {
+            PathBuf::from(INDEX_FILE)
+        } else {
+            let path = PathBuf::from(relative_path);
+
+            let metadata = match self.open_unclaimed(&path)?.metadata() {
+                Ok(metadata) => metadata,
+                Err(crate::Error::FileNotFound { .. }) => return Ok(StaticResource::NotFound),
+                Err(err) => return Err(err),
+            };
+
+            if metadata.is_dir() {
+                if !has_trailing_slash {
+                    return Ok(StaticResource::Redirect {
+                        location: format!("{request_path}/"),
+                    });
+                }
+
+                path.join(INDEX_FILE)
+            } else if metadata.is_file() && !has_trailing_slash {
+                path
+            } else {
+                return Ok(StaticResource::NotFound);
+            }
+        };
+
+        if !self.is_regular_file(&file_path)? {
+            return Ok(StaticResource::NotFound);
+        }
+
+        if let Some(header) = accept_encoding {
+            for encoding in [ContentEncoding::Brotli, ContentEncoding::Gzip] {
+                if !accepts_encoding(header, encoding) {
+                    continue;
+                }
+
+                let mut variant_path = file_path.clone().into_os_string();
+                variant_path.push(".");
+                variant_path.push(encoding.extension());
+                let variant_path = PathBuf::from(variant_path);
+
+                if self.is_regular_file(&variant_path)? {
+                    return Ok(StaticResource::File {
+                        path: variant_path,
+                        encoding: Some(encoding),
+                    });
+                }
+            }
+        }
+
+        Ok(StaticResource::File {
+            path: file_path,
+            encoding: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use xpct::{be_false, be_some, be_true, equal, expect};
+
+    use super::*;
+
+    #[test]
+    fn format_http_date() {
+        let time = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        expect!(http_date(time))
+            .to(be_some())
+            .to(equal("Sun, 06 Nov 1994 08:49:37 GMT"));
+    }
+
+    #[test]
+    fn format_http_date_at_epoch() {
+        expect!(http_date(UNIX_EPOCH))
+            .to(be_some())
+            .to(equal("Thu, 01 Jan 1970 00:00:00 GMT"));
+    }
+
+    #[test]
+    fn format_http_date_on_leap_day() {
+        // 2024-02-29T12:00:00Z
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_208_000);
+        expect!(http_date(time))
+            .to(be_some())
+            .to(equal("Thu, 29 Feb 2024 12:00:00 GMT"));
+    }
+
+    #[test]
+    fn etags_match_weakly() {
+        expect!(etags_match("\"1-2\"", "W/\"1-2\"")).to(be_true());
+        expect!(etags_match("\"0-0\", W/\"1-2\"", "W/\"1-2\"")).to(be_true());
+        expect!(etags_match("*", "W/\"1-2\"")).to(be_true());
+        expect!(etags_match("W/\"1-3\"", "W/\"1-2\"")).to(be_false());
+    }
+
+    #[test]
+    fn parse_accept_encoding() {
+        expect!(accepts_encoding("gzip, br", ContentEncoding::Brotli)).to(be_true());
+        expect!(accepts_encoding("gzip;q=0.5", ContentEncoding::Gzip)).to(be_true());
+        expect!(accepts_encoding("*", ContentEncoding::Gzip)).to(be_true());
+        expect!(accepts_encoding("br;q=0, gzip", ContentEncoding::Brotli)).to(be_false());
+        expect!(accepts_encoding("identity", ContentEncoding::Gzip)).to(be_false());
+    }
+}
@@ -0,0 +1,197 @@
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use rusqlite::types::ValueRef;
+use rusqlite::OpenFlags;
+
+use super::archive::Archive;
+use super::metadata::FileMode;
+
+/// Options for importing rows from another SQLite database into an [`Archive`].
+///
+/// This is used with [`Archive::import_query`]. It maps the columns returned by the query to the
+/// properties of the files that are created in the archive.
+///
+/// [`Archive`]: crate::Archive
+/// [`Archive::import_query`]: crate::Archive::import_query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportOptions {
+    path_column: String,
+    contents_column: String,
+    mtime_column: Option<String>,
+    mode_column: Option<String>,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            path_column: String::from("path"),
+            contents_column: String::from("contents"),
+            mtime_column: None,
+            mode_column: None,
+        }
+    }
+}
+
+impl ImportOptions {
+    /// Create a new [`ImportOptions`] with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The column holding the path of each file in the archive.
+    ///
+    /// The default is `path`.
+    pub fn path_column(mut self, column: &str) -> Self {
+        self.path_column = column.to_owned();
+        self
+    }
+
+    /// The column holding the contents of each file.
+    ///
+    /// The contents can be a BLOB or TEXT value. If it's NULL, the file is empty.
+    ///
+    /// The default is `contents`.
+    pub fn contents_column(mut self, column: &str) -> Self {
+        self.contents_column = column.to_owned();
+        self
+    }
+
+    /// The column holding the mtime of each file, as an integer number of seconds since the Unix
+    /// epoch.
+    ///
+    /// If the value is NULL, the file has no mtime. By default, files get the current time as
+    /// their mtime, like when they're created with [`File::create_file`].
+    ///
+    /// [`File::create_file`]: crate::File::create_file
+    pub fn mtime_column(mut self, column: &str) -> Self {
+        self.mtime_column = Some(column.to_owned());
+        self
+    }
+
+    /// The column holding the Unix file mode of each file, as an integer.
+    ///
+    /// If the value is NULL, the file has no mode. By default, files get their mode from the
+    /// archive's umask.
+    pub fn mode_column(mut self, column: &str) -> Self {
+        self.mode_column = Some(column.to_owned());
+        self
+    }
+}
+
+fn column_index(stmt: &rusqlite::Statement, column: &str) -> crate::Result<usize> {
+    stmt.column_index(column)
+        .map_err(|_| crate::Error::InvalidArgs {
+            reason: format!("The query does not return a column named `{column}`."),
+        })
+}
+
+fn invalid_value(column: &str, expected: &str) -> crate::Error {
+    crate::Error::InvalidArgs {
+        reason: format!("The column `{column}` must be {expected}."),
+    }
+}
+
+fn optional_integer(
+    row: &rusqlite::Row,
+    index: Option<usize>,
+    column: &str,
+) -> crate::Result<Option<i64>> {
+    let Some(index) = index else {
+        return Ok(None);
+    };
+
+    match row.get_ref(index)? {
+        ValueRef::Null => Ok(None),
+        ValueRef::Integer(value) => Ok(Some(value)),
+        _ => Err(invalid_value(column, "an integer or NULL")),
+    }
+}
+
+impl<'conn> Archive<'conn> {
+    pub(super) fn import_rows(
+        &self,
+        database: &Path,
+        query: &str,
+        opts: &ImportOptions,
+    ) -> crate::Result<u64> {
+        // We can't `ATTACH` the database, because SQLite doesn't allow that inside a transaction,
+        // so we read it through a separate connection.
+        let source = rusqlite::Connection::open_with_flags(
+            database,
+            OpenFlags::SQLITE_OPEN_NO_MUTEX | OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )?;
+
+        let mut stmt = source.prepare(query)?;
+
+        let path_index = column_index(&stmt, &opts.path_column)?;
+        let contents_index = column_index(&stmt, &opts.contents_column)?;
+        let mtime_index = opts
+            .mtime_column
+            .as_deref()
+            .map(|column| column_index(&stmt, column))
+            .transpose()?;
+        let mode_index = opts
+            .mode_column
+            .as_deref()
+            .map(|column| column_index(&stmt, column))
+            .transpose()?;
+
+        let mut rows = stmt.query(())?;
+        let mut num_imported = 0;
+
+        while let Some(row) = rows.next()? {
+            let path = match row.get_ref(path_index)? {
+                ValueRef::Text(path) => String::from_utf8_lossy(path).into_owned(),
+                _ => return Err(invalid_value(&opts.path_column, "TEXT")),
+            };
+
+            let contents = match row.get_ref(contents_index)? {
+                ValueRef::Blob(bytes) | ValueRef::Text(bytes) => bytes,
+                ValueRef::Null => &[],
+                _ => {
+                    return Err(invalid_value(
+                        &opts.contents_column,
+                        "a BLOB, TEXT, or NULL",
+                    ))
+                }
+            };
+
+            let mtime = optional_integer(
+                row,
+                mtime_index,
+                opts.mtime_column.as_deref().unwrap_or_default(),
+            )?;
+
+            let mode = optional_integer(
+                row,
+                mode_index,
+                opts.mode_column.as_deref().unwrap_or_default(),
+            )?;
+
+            if let Some(parent) = Path::new(&path).parent() {
+                if parent != Path::new("") {
+                    self.open_unclaimed_mut(parent)?.create_dir_all()?;
+                }
+            }
+
+            let mut file = self.open_unclaimed_mut(&path)?;
+            file.create_file()?;
+            file.write_bytes(contents)?;
+
+            if mtime_index.is_some() {
+                file.set_mtime(
+                    mtime.map(|secs| UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64)),
+                )?;
+            }
+
+            if mode_index.is_some() {
+                file.set_mode(mode.map(|mode| FileMode::from_bits_truncate(mode as u32)))?;
+            }
+
+            num_imported += 1;
+        }
+
+        Ok(num_imported)
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt::Write as _;
+use std::io::Write;
+use std::time::UNIX_EPOCH;
+
+use super::archive::Archive;
+use super::list::ListEntry;
+use super::metadata::{FileMetadata, FileType};
+
+/// The format to write an archive index in.
+///
+/// This is used with [`Archive::export_index`].
+///
+/// [`Archive::export_index`]: crate::Archive::export_index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum IndexFormat {
+    /// Comma-separated values, with a header row.
+    ///
+    /// Fields are quoted as described in [RFC 4180](https://www.rfc-editor.org/rfc/rfc4180).
+    /// Fields that don't apply to an entry are left empty.
+    Csv,
+
+    /// One JSON object per line.
+    ///
+    /// Fields that don't apply to an entry are `null`.
+    JsonLines,
+}
+
+// The columns of the index, in order.
+const COLUMNS: [&str; 6] = ["path", "type", "size", "mode", "mtime", "target"];
+
+// A single value in the index.
+enum Field {
+    Null,
+    Integer(u64),
+    Text(String),
+}
+
+fn file_type_name(kind: FileType) -> &'static str {
+    match kind {
+        FileType::File => "file",
+        FileType::Dir => "dir",
+        FileType::Symlink => "symlink",
+    }
+}
+
+fn entry_fields(entry: &ListEntry) -> [Field; 6] {
+    let metadata = entry.metadata();
+
+    let size = match metadata {
+        FileMetadata::File { size, .. } => Field::Integer(*size),
+        _ => Field::Null,
+    };
+
+    let target = match metadata {
+        FileMetadata::Symlink { target, .. } => Field::Text(target.to_string_lossy().into_owned()),
+        _ => Field::Null,
+    };
+
+    let mode = match metadata.mode() {
+        Some(mode) => Field::Integer(u64::from(mode.bits())),
+        None => Field::Null,
+    };
+
+    let mtime = match metadata
+        .mtime()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+    {
+        Some(duration) => Field::Integer(duration.as_secs()),
+        None => Field::Null,
+    };
+
+    [
+        Field::Text(entry.path().to_string_lossy().into_owned()),
+        Field::Text(file_type_name(metadata.kind()).to_owned()),
+        size,
+        mode,
+        mtime,
+        target,
+    ]
+}
+
+fn csv_value(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+fn csv_row(fields: &[Field]) -> String {
+    fields
+        .iter()
+        .map(|field| match field {
+            Field::Null => String::new(),
+            Field::Integer(value) => value.to_string(),
+            Field::Text(value) => csv_value(value),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+
+    escaped.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                // Writing to a `String` can't fail.
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('"');
+
+    escaped
+}
+
+fn json_object(fields: &[Field]) -> String {
+    let members = COLUMNS
+        .iter()
+        .zip(fields)
+        .map(|(name, field)| {
+            let value = match field {
+                Field::Null => String::from("null"),
+                Field::Integer(value) => value.to_string(),
+                Field::Text(value) => json_string(value),
+            };
+
+            format!("{}:{}", json_string(name), value)
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+
+    format!("{{{members}}}")
+}
+
+impl<'conn> Archive<'conn> {
+    pub(super) fn write_index(
+        &self,
+        writer: &mut dyn Write,
+        format: IndexFormat,
+    ) -> crate::Result<u64> {
+        if format == IndexFormat::Csv {
+            writeln!(writer, "{}", COLUMNS.join(","))?;
+        }
+
+        let mut num_entries = 0;
+
+        for entry in self.list()? {
+            let fields = entry_fields(&entry?);
+
+            let line = match format {
+                IndexFormat::Csv => csv_row(&fields),
+                IndexFormat::JsonLines => json_object(&fields),
+            };
+
+            writeln!(writer, "{line}")?;
+
+            num_entries += 1;
+        }
+
+        writer.flush()?;
+
+        Ok(num_entries)
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use super::archive::Archive;
+use super::store::NewFile;
+use super::util::u64_from_usize;
+
+/// Counters for how often small files were written with a single insert.
+///
+/// When archiving a directory, regular files no larger than
+/// [`ArchiveOptions::inline_threshold`] are read into memory and inserted along with their
+/// metadata and contents, several at a time, rather than being created and then written to. This
+/// makes archiving trees with many tiny files much faster. Use these counters to check whether
+/// that's happening.
+///
+/// This is returned by [`Archive::inline_stats`]. The counters start at zero for each transaction.
+///
+/// [`ArchiveOptions::inline_threshold`]: crate::ArchiveOptions::inline_threshold
+/// [`Archive::inline_stats`]: crate::Archive::inline_stats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct InlineStats {
+    files: u64,
+    batches: u64,
+}
+
+impl InlineStats {
+    /// The number of files that were inserted along with their contents.
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    /// The number of statements those files were inserted with.
+    ///
+    /// Each statement inserts up to [`ArchiveOptions::batch_size`] files.
+    ///
+    /// [`ArchiveOptions::batch_size`]: crate::ArchiveOptions::batch_size
+    pub fn batches(&self) -> u64 {
+        self.batches
+    }
+}
+
+// The small files that are waiting to be inserted while archiving a directory.
+#[derive(Debug)]
+pub(super) struct InlineBatch {
+    files: Vec<NewFile>,
+    // The paths of `files`, so we can tell when something needs to see one of them.
+    paths: HashSet<String>,
+    batch_size: usize,
+}
+
+impl InlineBatch {
+    pub fn new(batch_size: usize) -> Self {
+        Self {
+            files: Vec::new(),
+            paths: HashSet::new(),
+            batch_size,
+        }
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        path.to_str().is_some_and(|path| self.paths.contains(path))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl<'conn> Archive<'conn> {
+    // Add `file` to the batch, inserting the batch if it's full.
+    pub(super) fn push_inline(&self, batch: &mut InlineBatch, file: NewFile) -> crate::Result<()> {
+        batch.paths.insert(file.path.clone());
+        batch.files.push(file);
+
+        if batch.files.len() >= batch.batch_size {
+            self.flush_inline(batch)?;
+        }
+
+        Ok(())
+    }
+
+    // Insert every file in the batch. This must be called before anything that needs to see
+    // those files in the archive.
+    pub(super) fn flush_inline(&self, batch: &mut InlineBatch) -> crate::Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        self.store.insert_files(&batch.files)?;
+
+        let mut stats = self.inline_stats.get();
+        stats.files += u64_from_usize(batch.files.len());
+        stats.batches += 1;
+        self.inline_stats.set(stats);
+
+        batch.files.clear();
+        batch.paths.clear();
+
+        Ok(())
+    }
+}
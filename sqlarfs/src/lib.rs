@@ -43,23 +43,85 @@
 // This requires the nightly toolchain.
 #![cfg_attr(coverage_nightly, feature(coverage_attribute))]
 
+mod anchor;
 mod archive;
+mod builder;
+pub mod catalog;
+mod checksum;
+mod clock;
+mod copy;
+mod dedup;
+#[cfg(feature = "zstd")]
+mod dictionary;
+mod digest;
+#[cfg(any(feature = "json", feature = "msgpack"))]
+mod document;
 mod error;
+mod escape;
+mod external;
 mod file;
+mod filter;
+pub mod fs;
+mod http;
+mod import;
+mod index;
+mod inline;
 mod list;
+mod lock;
+mod memory;
 mod metadata;
 mod mode;
+mod newline;
+mod overlay;
+mod progress;
+mod recompress;
+mod rename;
+mod repair;
+mod report;
+mod retention;
+mod settings;
+mod single;
 mod store;
 mod stream;
+mod symlink;
+mod template;
 mod transaction;
 mod tree;
+mod unicode;
+mod unnamed;
 mod util;
+mod writer;
 
 pub use archive::Archive;
-pub use error::{Error, Result, SqliteErrorCode};
+pub use builder::{AutoVacuum, ConnectionBuilder, JournalMode, Synchronous};
+pub use digest::{Digest, DigestAlgorithm, DigestOptions};
+pub use error::{Error, ErrorBundle, ErrorCategory, Result, SqliteErrorCode};
+pub use external::ExternalLink;
 pub use file::File;
-pub use list::{ListEntries, ListEntry, ListOptions};
+pub use filter::Filter;
+pub use http::{ConditionalRead, ContentEncoding, StaticResource};
+pub use import::ImportOptions;
+pub use index::IndexFormat;
+pub use inline::InlineStats;
+pub use list::{EntryId, ListCursor, ListEntries, ListEntry, ListOptions};
+pub use lock::FileLock;
+pub use memory::MemoryStats;
 pub use metadata::{FileMetadata, FileMode, FileType};
+pub use overlay::Overlay;
+pub use progress::Progress;
+pub use recompress::RecompressOptions;
+pub use rename::RenamePolicy;
+pub use repair::{ArchiveProblems, RepairOptions, RepairReport};
+pub use report::{CompressionReport, CompressionStats, ExtensionStats};
+pub use retention::RetentionPolicy;
+pub use settings::Settings;
+pub use single::SINGLE_ENTRY;
 pub use stream::{Compression, FileReader};
 pub use transaction::{Connection, Transaction, TransactionBehavior};
-pub use tree::{ArchiveOptions, ExtractOptions};
+pub use tree::{
+    AppleMetadata, ArchiveOptions, ConflictAction, ExtractOptions, LongNamePolicy,
+    MetadataFallback, NewlinePolicy, OverwritePolicy, WindowsSymlinkPolicy,
+};
+pub use unicode::PathNormalization;
+pub use unnamed::UnnamedFile;
+pub use writer::FileWriter;
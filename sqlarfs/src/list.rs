@@ -1,6 +1,8 @@
 use std::fmt;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
+use super::archive::Archive;
 use super::metadata::{FileMetadata, FileType};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -8,6 +10,9 @@ pub enum ListSort {
     Size,
     Mtime,
     Depth,
+    NameNatural,
+    // This isn't exposed in the public API. It's used internally when we need a stable order.
+    Name,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -20,8 +25,9 @@ pub enum SortDirection {
 ///
 /// This is used with [`Archive::list_with`].
 ///
-/// Unless you specify a sort criteria with [`ListOptions::by_mtime`] or [`ListOptions::by_size`],
-/// the order of the returned files is unspecified.
+/// Unless you specify a sort criteria with [`ListOptions::by_depth`], [`ListOptions::by_mtime`],
+/// [`ListOptions::by_size`], or [`ListOptions::by_name_natural`], the order of the returned files
+/// is unspecified.
 ///
 /// [`Archive::list_with`]: crate::Archive::list_with
 #[derive(Debug, Clone)]
@@ -31,6 +37,10 @@ pub struct ListOptions {
     pub(super) ancestor: Option<PathBuf>,
     pub(super) parent: Option<PathBuf>,
     pub(super) file_type: Option<FileType>,
+    pub(super) glob: Option<String>,
+    pub(super) modified_before: Option<SystemTime>,
+    pub(super) modified_after: Option<SystemTime>,
+    pub(super) empty_dirs: bool,
     pub(super) is_invalid: bool,
 }
 
@@ -50,6 +60,10 @@ impl ListOptions {
             ancestor: None,
             parent: None,
             file_type: None,
+            glob: None,
+            modified_before: None,
+            modified_after: None,
+            empty_dirs: false,
             is_invalid: false,
         }
     }
@@ -110,12 +124,55 @@ impl ListOptions {
         self
     }
 
+    /// Only return files whose path matches the given glob `pattern`.
+    ///
+    /// This uses SQLite's `GLOB` operator, so matching is case-sensitive and `*` can match across
+    /// path separators. For example, `*.tmp` matches both `foo.tmp` and `dir/foo.tmp`.
+    pub fn glob(mut self, pattern: &str) -> Self {
+        self.glob = Some(pattern.to_owned());
+
+        self
+    }
+
+    /// Only return files that were last modified before `mtime`.
+    ///
+    /// Files that don't have an mtime are never returned.
+    pub fn modified_before(mut self, mtime: SystemTime) -> Self {
+        self.modified_before = Some(mtime);
+
+        self
+    }
+
+    /// Only return files that were last modified at or after `mtime`.
+    ///
+    /// Files that don't have an mtime are never returned.
+    pub fn modified_after(mut self, mtime: SystemTime) -> Self {
+        self.modified_after = Some(mtime);
+
+        self
+    }
+
+    /// Only return directories that have no children.
+    ///
+    /// This is mutually exclusive with [`ListOptions::by_size`].
+    pub fn empty_dirs(mut self) -> Self {
+        if self.sort == Some(ListSort::Size) {
+            self.is_invalid = true;
+            return self;
+        }
+
+        self.empty_dirs = true;
+
+        self
+    }
+
     /// Sort by depth in the directory tree.
     ///
     /// This ensures parents always come before their children (or children before their parents in
     /// descending mode).
     ///
-    /// This is mutually exclusive with [`ListOptions::by_mtime`] and [`ListOptions::by_size`].
+    /// This is mutually exclusive with [`ListOptions::by_mtime`], [`ListOptions::by_size`], and
+    /// [`ListOptions::by_name_natural`].
     pub fn by_depth(mut self) -> Self {
         if self.sort.is_some() {
             self.is_invalid = true;
@@ -129,7 +186,8 @@ impl ListOptions {
 
     /// Sort by last modification time.
     ///
-    /// This is mutually exclusive with [`ListOptions::by_depth`] and [`ListOptions::by_size`].
+    /// This is mutually exclusive with [`ListOptions::by_depth`], [`ListOptions::by_size`], and
+    /// [`ListOptions::by_name_natural`].
     pub fn by_mtime(mut self) -> Self {
         if self.sort.is_some() {
             self.is_invalid = true;
@@ -146,10 +204,11 @@ impl ListOptions {
     /// If this is specified, then the list will only contain regular files, skipping directories
     /// and symbolic links.
     ///
-    /// This is mutually exclusive with [`ListOptions::by_depth`], [`ListOptions::by_mtime`] and
-    /// [`ListOptions::file_type`].
+    /// This is mutually exclusive with [`ListOptions::by_depth`], [`ListOptions::by_mtime`],
+    /// [`ListOptions::by_name_natural`], [`ListOptions::file_type`], and
+    /// [`ListOptions::empty_dirs`].
     pub fn by_size(mut self) -> Self {
-        if self.sort.is_some() || self.file_type.is_some() {
+        if self.sort.is_some() || self.file_type.is_some() || self.empty_dirs {
             self.is_invalid = true;
             return self;
         }
@@ -159,6 +218,25 @@ impl ListOptions {
         self
     }
 
+    /// Sort by file path in natural order.
+    ///
+    /// Natural order ignores case and compares runs of digits by their numeric value, so `file2`
+    /// comes before `file10`. Path separators sort before any other character, so the contents of
+    /// a directory are always listed together.
+    ///
+    /// This is mutually exclusive with [`ListOptions::by_depth`], [`ListOptions::by_mtime`], and
+    /// [`ListOptions::by_size`].
+    pub fn by_name_natural(mut self) -> Self {
+        if self.sort.is_some() {
+            self.is_invalid = true;
+            return self;
+        }
+
+        self.sort = Some(ListSort::NameNatural);
+
+        self
+    }
+
     /// Sort in ascending order (the default).
     ///
     /// This is mutually exclusive with [`ListOptions::desc`].
@@ -188,6 +266,21 @@ impl ListOptions {
     }
 }
 
+/// An opaque identifier for a file in an archive.
+///
+/// This is returned by [`ListEntry::id`] and [`File::id`], and you can pass it to
+/// [`Archive::open_by_id`] to open the file again without looking it up by its path. This makes it
+/// useful for caching references to files, like inode numbers in a filesystem.
+///
+/// An ID stays the same while the file exists, even if it's renamed. However, once a file is
+/// deleted, a new file may be given its ID, and IDs may change when the database is vacuumed, so
+/// don't store them in the archive or anywhere else that outlives the connection.
+///
+/// [`File::id`]: crate::File::id
+/// [`Archive::open_by_id`]: crate::Archive::open_by_id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntryId(pub(super) i64);
+
 /// An entry when iterating over a list of files.
 ///
 /// You can use [`Archive::list`] and [`Archive::list_with`] to iterate over the files in an
@@ -197,11 +290,19 @@ impl ListOptions {
 /// [`Archive::list_with`]: crate::Archive::list_with
 #[derive(Debug)]
 pub struct ListEntry {
+    pub(super) id: EntryId,
     pub(super) path: PathBuf,
     pub(super) metadata: FileMetadata,
 }
 
 impl ListEntry {
+    /// An opaque identifier for this file.
+    ///
+    /// See [`EntryId`].
+    pub fn id(&self) -> EntryId {
+        self.id
+    }
+
     /// The file path.
     pub fn path(&self) -> &Path {
         &self.path
@@ -288,3 +389,66 @@ impl<'conn> Iterator for ListEntries<'conn> {
             .map(|item| item.map_err(crate::Error::from))
     }
 }
+
+/// A cursor over a snapshot of the files in an archive, which can be advanced while the archive is
+/// being modified.
+///
+/// This is returned by [`Archive::list_cursor`]. Unlike [`ListEntries`], it doesn't read from the
+/// archive lazily, so you can create, delete, and rename files between calls to
+/// [`ListCursor::next`].
+///
+/// When the cursor is created, it takes a snapshot of the paths of the matching files, in the
+/// order given by the [`ListOptions`]. Each call to [`ListCursor::next`] reads the current
+/// metadata of the next file in the snapshot. This guarantees that:
+///
+/// - Each file is returned at most once.
+/// - Files created after the cursor was created are never returned.
+/// - Files that were deleted or renamed after the cursor was created are skipped.
+///
+/// The snapshot holds every matching path in memory.
+///
+/// [`Archive::list_cursor`]: crate::Archive::list_cursor
+#[derive(Debug)]
+pub struct ListCursor {
+    paths: std::vec::IntoIter<PathBuf>,
+}
+
+impl ListCursor {
+    pub(super) fn new(paths: Vec<PathBuf>) -> Self {
+        Self {
+            paths: paths.into_iter(),
+        }
+    }
+
+    /// Return the next file in the snapshot that still exists in `archive`.
+    ///
+    /// This returns `None` once every file in the snapshot has been returned or skipped.
+    pub fn next(&mut self, archive: &Archive) -> Option<crate::Result<ListEntry>> {
+        for path in self.paths.by_ref() {
+            let path_str = path
+                .to_str()
+                .expect("A path read from the archive was not valid Unicode. This is a bug.");
+
+            match archive.store.read_entry(path_str) {
+                Ok((id, metadata)) => {
+                    return Some(Ok(ListEntry {
+                        id: EntryId(id),
+                        path,
+                        metadata,
+                    }))
+                }
+                Err(crate::Error::FileNotFound { .. }) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+
+        None
+    }
+
+    /// The number of files left in the snapshot.
+    ///
+    /// This includes files that have since been deleted or renamed, which will be skipped.
+    pub fn remaining(&self) -> usize {
+        self.paths.len()
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+// Locks are identified by the database they belong to and the path of the file within it.
+type LockKey = (Arc<str>, String);
+
+// The set of file locks currently held in this process, across all connections.
+#[derive(Debug, Default)]
+struct LockTable {
+    held: Mutex<HashSet<LockKey>>,
+    released: Condvar,
+}
+
+impl LockTable {
+    fn get() -> &'static Self {
+        static TABLE: OnceLock<LockTable> = OnceLock::new();
+        TABLE.get_or_init(LockTable::default)
+    }
+
+    fn held(&self) -> MutexGuard<'_, HashSet<LockKey>> {
+        // A panic while holding this mutex can't leave the set in an inconsistent state.
+        self.held.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// Get the namespace that locks taken out through this connection belong to.
+//
+// Connections to the same database file share a namespace, so that locks taken out through one
+// connection are respected by the others. In-memory and temporary databases can't be shared
+// between connections, so they each get a namespace of their own.
+pub fn lock_namespace(conn: &rusqlite::Connection) -> Arc<str> {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+    match conn.path() {
+        Some(path) if !path.is_empty() => Arc::from(path),
+        _ => Arc::from(format!(
+            "\0memory:{}",
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        )),
+    }
+}
+
+// Acquire the lock on `path`.
+//
+// If `timeout` is `None`, this waits indefinitely.
+pub fn acquire(
+    namespace: &Arc<str>,
+    path: &str,
+    timeout: Option<Duration>,
+) -> crate::Result<FileLock> {
+    let table = LockTable::get();
+    let key = (Arc::clone(namespace), path.to_owned());
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    let mut held = table.held();
+
+    while held.contains(&key) {
+        held = match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+
+                if now >= deadline {
+                    return Err(crate::Error::WouldBlock {
+                        path: PathBuf::from(path),
+                    });
+                }
+
+                table
+                    .released
+                    .wait_timeout(held, deadline - now)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0
+            }
+            None => table
+                .released
+                .wait(held)
+                .unwrap_or_else(PoisonError::into_inner),
+        };
+    }
+
+    held.insert(key.clone());
+
+    Ok(FileLock { key })
+}
+
+/// An exclusive lock on a file in a SQLite archive.
+///
+/// This is returned by [`File::lock_exclusive`] and its related methods. The lock is released when
+/// this value is dropped.
+///
+/// [`File::lock_exclusive`]: crate::File::lock_exclusive
+#[derive(Debug)]
+#[must_use = "The lock is released as soon as this value is dropped."]
+pub struct FileLock {
+    key: LockKey,
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let table = LockTable::get();
+        table.held().remove(&self.key);
+        table.released.notify_all();
+    }
+}
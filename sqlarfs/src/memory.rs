@@ -0,0 +1,62 @@
+/// Statistics about how much memory SQLite may use.
+///
+/// This is returned by [`Connection::memory_stats`].
+///
+/// [`Connection::memory_stats`]: crate::Connection::memory_stats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MemoryStats {
+    cache_size: u64,
+    soft_heap_limit: Option<u64>,
+    hard_heap_limit: Option<u64>,
+}
+
+impl MemoryStats {
+    /// The maximum size of this connection's page cache in bytes.
+    ///
+    /// This is how much memory SQLite will use to cache pages of the database before it starts
+    /// evicting them.
+    pub fn cache_size(&self) -> u64 {
+        self.cache_size
+    }
+
+    /// The process-wide soft limit on SQLite heap memory in bytes, if there is one.
+    ///
+    /// See [`ConnectionBuilder::soft_heap_limit`].
+    ///
+    /// [`ConnectionBuilder::soft_heap_limit`]: crate::ConnectionBuilder::soft_heap_limit
+    pub fn soft_heap_limit(&self) -> Option<u64> {
+        self.soft_heap_limit
+    }
+
+    /// The process-wide hard limit on SQLite heap memory in bytes, if there is one.
+    ///
+    /// When SQLite would exceed this limit, allocations fail instead.
+    pub fn hard_heap_limit(&self) -> Option<u64> {
+        self.hard_heap_limit
+    }
+}
+
+// SQLite uses 0 to mean there's no limit.
+fn heap_limit(conn: &rusqlite::Connection, pragma: &str) -> crate::Result<Option<u64>> {
+    let limit: i64 = conn.pragma_query_value(None, pragma, |row| row.get(0))?;
+
+    Ok(u64::try_from(limit).ok().filter(|&limit| limit > 0))
+}
+
+pub(super) fn read_memory_stats(conn: &rusqlite::Connection) -> crate::Result<MemoryStats> {
+    let cache_size: i64 = conn.pragma_query_value(None, "cache_size", |row| row.get(0))?;
+
+    // A positive cache size is a number of pages, and a negative one is a number of KiB.
+    let cache_size = if cache_size >= 0 {
+        let page_size: u64 = conn.pragma_query_value(None, "page_size", |row| row.get(0))?;
+        cache_size.unsigned_abs() * page_size
+    } else {
+        cache_size.unsigned_abs() * 1024
+    };
+
+    Ok(MemoryStats {
+        cache_size,
+        soft_heap_limit: heap_limit(conn, "soft_heap_limit")?,
+        hard_heap_limit: heap_limit(conn, "hard_heap_limit")?,
+    })
+}
@@ -1,5 +1,8 @@
 use std::fs;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use super::metadata::FileMode;
 
@@ -77,6 +80,127 @@ impl WriteMode for WindowsModeAdapter {
     }
 }
 
+// Which kinds of file metadata a filesystem can store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub permissions: bool,
+    pub symlinks: bool,
+}
+
+impl Capabilities {
+    // We assume everything is supported unless we've probed the filesystem and found otherwise.
+    pub const ALL: Self = Self {
+        permissions: true,
+        symlinks: true,
+    };
+}
+
+// A file we create while probing the filesystem, which is removed when it's dropped.
+struct ProbeFile {
+    path: PathBuf,
+}
+
+impl ProbeFile {
+    fn path_in(dir: &Path, kind: &str) -> PathBuf {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        dir.join(format!(
+            ".sqlarfs-probe-{}-{}-{}",
+            kind,
+            process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ))
+    }
+}
+
+impl Drop for ProbeFile {
+    #[allow(clippy::permissions_set_readonly_false)]
+    fn drop(&mut self) {
+        // Windows won't let us delete a read-only file. We're cleaning up, so we ignore errors.
+        if let Ok(metadata) = fs::symlink_metadata(&self.path) {
+            let mut permissions = metadata.permissions();
+
+            if !metadata.is_symlink() && permissions.readonly() {
+                permissions.set_readonly(false);
+                let _ = fs::set_permissions(&self.path, permissions);
+            }
+        }
+
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn probe_permissions<T: WriteMode>(dir: &Path, mode_adapter: &T) -> crate::Result<bool> {
+    let probe = ProbeFile {
+        path: ProbeFile::path_in(dir, "mode"),
+    };
+
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe.path)?;
+
+    let readonly_mode = FileMode::OWNER_R | FileMode::GROUP_R | FileMode::OTHER_R;
+    let writable_mode = readonly_mode | FileMode::OWNER_W;
+
+    // Filesystems that don't support permissions might either return an error or silently ignore
+    // the change, so we check both.
+    for (mode, readonly) in [(readonly_mode, true), (writable_mode, false)] {
+        if mode_adapter.write_mode(&probe.path, mode).is_err() {
+            return Ok(false);
+        }
+
+        if fs::metadata(&probe.path)?.permissions().readonly() != readonly {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+fn probe_symlinks(dir: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        let probe = ProbeFile {
+            path: ProbeFile::path_in(dir, "link"),
+        };
+
+        std::os::unix::fs::symlink("target", &probe.path).is_ok()
+    }
+
+    // We don't extract symbolic links on non-Unix-like systems.
+    #[cfg(not(unix))]
+    {
+        let _ = dir;
+        false
+    }
+}
+
+// Find out which kinds of file metadata the filesystem containing `dir` can store, by creating
+// and then removing some files in it.
+pub fn probe_capabilities<T: WriteMode>(
+    dir: &Path,
+    mode_adapter: &T,
+) -> crate::Result<Capabilities> {
+    let permissions = match probe_permissions(dir, mode_adapter) {
+        Ok(permissions) => permissions,
+        Err(crate::Error::Io {
+            kind: io::ErrorKind::NotFound,
+            ..
+        }) => {
+            return Err(crate::Error::FileNotFound {
+                path: dir.to_owned(),
+            })
+        }
+        Err(err) => return Err(err),
+    };
+
+    Ok(Capabilities {
+        permissions,
+        symlinks: probe_symlinks(dir),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -217,4 +341,42 @@ mod tests {
 
         Ok(())
     }
+
+    // A mode adapter for a filesystem that doesn't support permissions.
+    struct UnsupportedModeAdapter;
+
+    impl WriteMode for UnsupportedModeAdapter {
+        fn write_mode(&self, _path: &Path, _mode: FileMode) -> crate::Result<()> {
+            Err(io::Error::from(io::ErrorKind::PermissionDenied).into())
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn probing_capabilities_detects_supported_metadata() -> crate::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+
+        expect!(probe_capabilities(temp_dir.path(), &UnixModeAdapter))
+            .to(be_ok())
+            .to(equal(Capabilities::ALL));
+
+        // Make sure we cleaned up after ourselves.
+        expect!(fs::read_dir(temp_dir.path())?.count()).to(equal(0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn probing_capabilities_detects_unsupported_permissions() -> crate::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+
+        expect!(probe_capabilities(temp_dir.path(), &UnsupportedModeAdapter))
+            .to(be_ok())
+            .map(|caps| caps.permissions)
+            .to(be_false());
+
+        expect!(fs::read_dir(temp_dir.path())?.count()).to(equal(0));
+
+        Ok(())
+    }
 }
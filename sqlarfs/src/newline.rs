@@ -0,0 +1,136 @@
+use std::io::{self, Write};
+
+use super::tree::NewlinePolicy;
+
+// Converts the line endings in a stream of bytes according to a `NewlinePolicy`.
+//
+// Input is passed in chunks, and a CRLF can be split across chunks.
+#[derive(Debug)]
+pub struct NewlineConverter {
+    policy: NewlinePolicy,
+    // Whether the last byte of the previous chunk was a CR.
+    last_was_cr: bool,
+}
+
+impl NewlineConverter {
+    pub fn new(policy: NewlinePolicy) -> Self {
+        Self {
+            policy,
+            last_was_cr: false,
+        }
+    }
+
+    pub fn write<W: ?Sized + Write>(&mut self, input: &[u8], out: &mut W) -> io::Result<()> {
+        match self.policy {
+            NewlinePolicy::Preserve => out.write_all(input),
+            NewlinePolicy::Lf => self.write_lf(input, out),
+            NewlinePolicy::CrLf => self.write_crlf(input, out),
+        }
+    }
+
+    // Write a CR that was held back at the end of the last chunk.
+    pub fn finish<W: ?Sized + Write>(&mut self, out: &mut W) -> io::Result<()> {
+        if self.policy == NewlinePolicy::Lf && self.last_was_cr {
+            out.write_all(b"\r")?;
+        }
+
+        self.last_was_cr = false;
+
+        Ok(())
+    }
+
+    fn write_lf<W: ?Sized + Write>(&mut self, input: &[u8], out: &mut W) -> io::Result<()> {
+        if input.is_empty() {
+            return Ok(());
+        }
+
+        let mut converted = Vec::with_capacity(input.len() + 1);
+
+        // A CR at the end of the last chunk was held back in case this chunk starts with a LF.
+        if self.last_was_cr && input.first() != Some(&b'\n') {
+            converted.push(b'\r');
+        }
+
+        let mut bytes = input.iter().peekable();
+
+        while let Some(&byte) = bytes.next() {
+            match (byte, bytes.peek()) {
+                (b'\r', Some(b'\n')) => {}
+                (b'\r', None) => {}
+                _ => converted.push(byte),
+            }
+        }
+
+        self.last_was_cr = input.last() == Some(&b'\r');
+
+        out.write_all(&converted)
+    }
+
+    fn write_crlf<W: ?Sized + Write>(&mut self, input: &[u8], out: &mut W) -> io::Result<()> {
+        let mut converted = Vec::with_capacity(input.len() + input.len() / 8);
+        let mut prev_was_cr = self.last_was_cr;
+
+        for &byte in input {
+            if byte == b'\n' && !prev_was_cr {
+                converted.push(b'\r');
+            }
+
+            converted.push(byte);
+            prev_was_cr = byte == b'\r';
+        }
+
+        self.last_was_cr = prev_was_cr;
+
+        out.write_all(&converted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use xpct::{equal, expect};
+
+    fn convert_chunks(policy: NewlinePolicy, chunks: &[&str]) -> String {
+        let mut converter = NewlineConverter::new(policy);
+        let mut out = Vec::new();
+
+        for chunk in chunks {
+            converter.write(chunk.as_bytes(), &mut out).unwrap();
+        }
+
+        converter.finish(&mut out).unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn preserve_leaves_line_endings_alone() {
+        expect!(convert_chunks(NewlinePolicy::Preserve, &["a\r\nb\nc\r"])).to(equal("a\r\nb\nc\r"));
+    }
+
+    #[test]
+    fn lf_converts_crlf() {
+        expect!(convert_chunks(NewlinePolicy::Lf, &["a\r\nb\nc\r\n"])).to(equal("a\nb\nc\n"));
+    }
+
+    #[test]
+    fn lf_leaves_lone_cr_alone() {
+        expect!(convert_chunks(NewlinePolicy::Lf, &["a\rb\r"])).to(equal("a\rb\r"));
+    }
+
+    #[test]
+    fn lf_converts_crlf_split_across_chunks() {
+        expect!(convert_chunks(NewlinePolicy::Lf, &["a\r", "\nb\r", "c"])).to(equal("a\nb\rc"));
+    }
+
+    #[test]
+    fn crlf_converts_lf() {
+        expect!(convert_chunks(NewlinePolicy::CrLf, &["a\nb\r\nc"])).to(equal("a\r\nb\r\nc"));
+    }
+
+    #[test]
+    fn crlf_does_not_double_crlf_split_across_chunks() {
+        expect!(convert_chunks(NewlinePolicy::CrLf, &["a\r", "\nb\n"])).to(equal("a\r\nb\r\n"));
+    }
+}
@@ -0,0 +1,319 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::Path;
+
+use super::archive::Archive;
+use super::file::normalize_path;
+use super::list::{ListEntry, ListOptions, ListSort, SortDirection};
+use super::metadata::FileMetadata;
+use super::stream::FileReader;
+use super::tree::{ConflictAction, ExtractOptions};
+use super::util::natural_cmp;
+
+// The operations an overlay needs from each of its layers.
+//
+// This is a trait so that one overlay can hold archives from different connections, which borrow
+// their connections for different lifetimes.
+trait Layer {
+    fn metadata(&self, path: &str) -> crate::Result<Option<FileMetadata>>;
+
+    fn reader(&self, path: &str) -> crate::Result<FileReader<'_>>;
+
+    fn list(&self, opts: &ListOptions) -> crate::Result<Vec<ListEntry>>;
+
+    fn extract(&self, from: &Path, to: &Path, opts: &ExtractOptions) -> crate::Result<()>;
+}
+
+impl<'conn> Layer for Archive<'conn> {
+    fn metadata(&self, path: &str) -> crate::Result<Option<FileMetadata>> {
+        match self.store.read_metadata(path) {
+            Ok(metadata) => Ok(Some(metadata)),
+            Err(crate::Error::FileNotFound { .. }) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn reader(&self, path: &str) -> crate::Result<FileReader<'_>> {
+        FileReader::new(self.store.open_blob(path, true)?)
+    }
+
+    fn list(&self, opts: &ListOptions) -> crate::Result<Vec<ListEntry>> {
+        self.list_with(opts)?.collect()
+    }
+
+    fn extract(&self, from: &Path, to: &Path, opts: &ExtractOptions) -> crate::Result<()> {
+        self.extract_with(from, to, opts)
+    }
+}
+
+/// A read-only view of several archives layered on top of each other.
+///
+/// Files in upper layers shadow files at the same path in lower layers. A regular file or symbolic
+/// link in an upper layer also hides everything under a directory at the same path in a lower
+/// layer, while directories that exist in more than one layer are merged. This lets you apply a
+/// small "patch" archive over a large base archive at read time without copying the base.
+///
+/// You can create an overlay with [`Archive::overlay`].
+///
+/// [`Archive::overlay`]: crate::Archive::overlay
+pub struct Overlay<'a> {
+    // Ordered from the topmost layer to the bottommost layer.
+    layers: Vec<&'a dyn Layer>,
+}
+
+impl<'a> fmt::Debug for Overlay<'a> {
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Overlay")
+            .field("layers", &self.layers.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> Overlay<'a> {
+    pub(super) fn new<'upper, 'lower>(
+        upper: &'a Archive<'upper>,
+        lower: &'a Archive<'lower>,
+    ) -> Self {
+        Self {
+            layers: vec![upper, lower],
+        }
+    }
+
+    /// Add another layer below all the existing layers.
+    pub fn layer<'conn>(mut self, lower: &'a Archive<'conn>) -> Self {
+        self.layers.push(lower);
+        self
+    }
+
+    // Whether the file at `path` in the layer at `index` is replaced by a file at the same path in
+    // one of the layers above it or is hidden by one of the layers above it.
+    fn is_shadowed(&self, index: usize, path: &Path) -> crate::Result<bool> {
+        for upper in &self.layers[..index] {
+            if upper.metadata(&normalize_path(path)?)?.is_some() {
+                return Ok(true);
+            }
+        }
+
+        self.is_hidden(index, path)
+    }
+
+    // Whether the file at `path` in the layer at `index` is hidden because one of its ancestors is
+    // a regular file or symbolic link in one of the layers above it.
+    fn is_hidden(&self, index: usize, path: &Path) -> crate::Result<bool> {
+        for upper in &self.layers[..index] {
+            for ancestor in path.ancestors().skip(1) {
+                if ancestor == Path::new("") {
+                    break;
+                }
+
+                if let Some(metadata) = upper.metadata(&normalize_path(ancestor)?)? {
+                    if !metadata.is_dir() {
+                        return Ok(true);
+                    }
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    // Find the topmost layer that has a visible file at `path`.
+    fn find(&self, path: &Path) -> crate::Result<Option<(usize, FileMetadata)>> {
+        let normalized_path = normalize_path(path)?;
+
+        for (index, layer) in self.layers.iter().enumerate() {
+            if let Some(metadata) = layer.metadata(&normalized_path)? {
+                return Ok(if self.is_shadowed(index, path)? {
+                    None
+                } else {
+                    Some((index, metadata))
+                });
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Return whether the file at `path` exists in any layer of the overlay.
+    pub fn exists<P: AsRef<Path>>(&self, path: P) -> crate::Result<bool> {
+        Ok(self.find(path.as_ref())?.is_some())
+    }
+
+    /// The metadata of the file at `path` in the topmost layer it's visible in.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist in any layer, or it's hidden by a file in an
+    ///   upper layer.
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    pub fn metadata<P: AsRef<Path>>(&self, path: P) -> crate::Result<FileMetadata> {
+        let path = path.as_ref();
+
+        match self.find(path)? {
+            Some((_, metadata)) => Ok(metadata),
+            None => Err(crate::Error::FileNotFound { path: path.into() }),
+        }
+    }
+
+    /// Get a reader for the contents of the file at `path` in the topmost layer it's visible in.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: This file does not exist in any layer, or it's hidden by a file in an
+    ///   upper layer.
+    /// - [`CompressionNotSupported`]: This file is compressed, but the `deflate` Cargo feature is
+    ///   disabled.
+    /// - [`NotARegularFile`]: The file is a directory or a symbolic link.
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`CompressionNotSupported`]: crate::Error::CompressionNotSupported
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    pub fn reader<P: AsRef<Path>>(&self, path: P) -> crate::Result<FileReader<'_>> {
+        let path = path.as_ref();
+
+        match self.find(path)? {
+            Some((index, FileMetadata::File { .. })) => {
+                self.layers[index].reader(&normalize_path(path)?)
+            }
+            Some(_) => Err(crate::Error::NotARegularFile { path: path.into() }),
+            None => Err(crate::Error::FileNotFound { path: path.into() }),
+        }
+    }
+
+    /// Return a list of the visible files in all the layers of the overlay.
+    ///
+    /// This is the same as [`Overlay::list_with`], but using the default options.
+    pub fn list(&mut self) -> crate::Result<Vec<ListEntry>> {
+        self.list_with(&ListOptions::new())
+    }
+
+    /// Return a list of the visible files in all the layers of the overlay, filtered and sorted
+    /// with the given [`ListOptions`].
+    ///
+    /// Each file is only listed once, with the metadata from the topmost layer it's visible in.
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: Mutually exclusive options were specified together in [`ListOptions`].
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    pub fn list_with(&mut self, opts: &ListOptions) -> crate::Result<Vec<ListEntry>> {
+        let mut entries = Vec::new();
+
+        for index in 0..self.layers.len() {
+            for entry in self.layers[index].list(opts)? {
+                if !self.is_shadowed(index, &entry.path)? {
+                    entries.push(entry);
+                }
+            }
+        }
+
+        if let Some(sort) = opts.sort {
+            entries.sort_by(|left, right| {
+                let ordering = compare_entries(sort, left, right);
+
+                match opts.direction {
+                    Some(SortDirection::Desc) => ordering.reverse(),
+                    Some(SortDirection::Asc) | None => ordering,
+                }
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// Copy the directory tree at `from` in the overlay into the filesystem at `to`.
+    ///
+    /// This is the same as [`Overlay::extract_with`], but using the default options.
+    pub fn extract<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, from: P, to: Q) -> crate::Result<()> {
+        self.extract_with(from, to, &Default::default())
+    }
+
+    /// Copy the directory tree at `from` in the overlay into the filesystem at `to`.
+    ///
+    /// This works like [`Archive::extract_with`], except the files in all the layers are
+    /// extracted, with files in upper layers taking the place of files in lower layers.
+    ///
+    /// The layers are extracted from the bottom up, so [`ExtractOptions::on_conflict`] only
+    /// applies to the files in the bottommost layer that contains `from`. Files from the layers
+    /// above it always replace whatever is already at the destination.
+    ///
+    /// # Errors
+    ///
+    /// This returns the same errors as [`Archive::extract_with`].
+    ///
+    /// [`Archive::extract_with`]: crate::Archive::extract_with
+    pub fn extract_with<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        from: P,
+        to: Q,
+        opts: &ExtractOptions,
+    ) -> crate::Result<()> {
+        let from = from.as_ref();
+        let to = to.as_ref();
+
+        let from_is_root = from == Path::new("");
+        let mut extracted_any = false;
+
+        for index in (0..self.layers.len()).rev() {
+            if !from_is_root {
+                let exists = self.layers[index]
+                    .metadata(&normalize_path(from)?)?
+                    .is_some();
+
+                // Files at the same path in upper layers will replace this one as they're
+                // extracted, but we still need to extract it in case it's a directory that gets
+                // merged with theirs.
+                if !exists || self.is_hidden(index, from)? {
+                    continue;
+                }
+            }
+
+            if extracted_any {
+                let overwrite_opts = opts
+                    .clone()
+                    .on_conflict(|_, _, _| ConflictAction::Overwrite);
+
+                self.layers[index].extract(from, to, &overwrite_opts)?;
+            } else {
+                self.layers[index].extract(from, to, opts)?;
+            }
+
+            extracted_any = true;
+        }
+
+        if !extracted_any {
+            return Err(crate::Error::FileNotFound { path: from.into() });
+        }
+
+        Ok(())
+    }
+}
+
+fn compare_entries(sort: ListSort, left: &ListEntry, right: &ListEntry) -> Ordering {
+    let left_path = left.path.to_string_lossy();
+    let right_path = right.path.to_string_lossy();
+
+    let ordering = match sort {
+        ListSort::Size => file_size(&left.metadata).cmp(&file_size(&right.metadata)),
+        ListSort::Mtime => left.metadata.mtime().cmp(&right.metadata.mtime()),
+        ListSort::Depth => left
+            .path
+            .components()
+            .count()
+            .cmp(&right.path.components().count()),
+        ListSort::NameNatural => natural_cmp(&left_path, &right_path),
+        ListSort::Name => Ordering::Equal,
+    };
+
+    ordering.then_with(|| left_path.cmp(&right_path))
+}
+
+fn file_size(metadata: &FileMetadata) -> u64 {
+    match metadata {
+        FileMetadata::File { size, .. } => *size,
+        _ => 0,
+    }
+}
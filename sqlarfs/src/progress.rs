@@ -0,0 +1,114 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A report of how far along archiving, extracting, or recompressing files is.
+///
+/// This is passed to the callbacks given to [`ArchiveOptions::on_progress`],
+/// [`ExtractOptions::on_progress`], and [`RecompressOptions::on_progress`].
+///
+/// [`ArchiveOptions::on_progress`]: crate::ArchiveOptions::on_progress
+/// [`ExtractOptions::on_progress`]: crate::ExtractOptions::on_progress
+/// [`RecompressOptions::on_progress`]: crate::RecompressOptions::on_progress
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    path: PathBuf,
+    files_done: u64,
+    files_total: Option<u64>,
+    bytes_done: u64,
+    bytes_total: Option<u64>,
+}
+
+impl Progress {
+    /// The path of the file that was just archived or extracted.
+    ///
+    /// When archiving, this is the path of the file in the archive. When extracting, this is the
+    /// path of the file in the archive it was extracted from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of files that have been archived or extracted so far, including this one.
+    ///
+    /// Files that were skipped count as done.
+    pub fn files_done(&self) -> u64 {
+        self.files_done
+    }
+
+    /// The total number of files that will be archived or extracted, if known.
+    ///
+    /// This is known when extracting, but not when archiving, because we don't walk the directory
+    /// tree ahead of time.
+    pub fn files_total(&self) -> Option<u64> {
+        self.files_total
+    }
+
+    /// The number of bytes of file contents that have been archived or extracted so far.
+    pub fn bytes_done(&self) -> u64 {
+        self.bytes_done
+    }
+
+    /// The total number of bytes of file contents that will be archived or extracted, if known.
+    ///
+    /// Like [`Progress::files_total`], this is only known when extracting.
+    pub fn bytes_total(&self) -> Option<u64> {
+        self.bytes_total
+    }
+}
+
+pub(super) type ProgressCallback = dyn Fn(&Progress) + Send + Sync;
+
+// Counts the files processed by a single call to `Archive::archive_tree`, `Archive::extract_tree`,
+// or `Archive::recompress_with` and passes the running totals to the user's callback.
+pub(super) struct ProgressTracker {
+    callback: Option<Arc<ProgressCallback>>,
+    files_done: u64,
+    files_total: Option<u64>,
+    bytes_done: u64,
+    bytes_total: Option<u64>,
+}
+
+impl fmt::Debug for ProgressTracker {
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressTracker")
+            .field("callback", &self.callback.as_ref().map(|_| ".."))
+            .field("files_done", &self.files_done)
+            .field("files_total", &self.files_total)
+            .field("bytes_done", &self.bytes_done)
+            .field("bytes_total", &self.bytes_total)
+            .finish()
+    }
+}
+
+impl ProgressTracker {
+    pub fn new(
+        callback: Option<Arc<ProgressCallback>>,
+        files_total: Option<u64>,
+        bytes_total: Option<u64>,
+    ) -> Self {
+        Self {
+            callback,
+            files_done: 0,
+            files_total,
+            bytes_done: 0,
+            bytes_total,
+        }
+    }
+
+    // Record that the file at `path`, which has `bytes` bytes of contents, is done.
+    pub fn file_done(&mut self, path: &Path, bytes: u64) {
+        self.files_done += 1;
+        self.bytes_done += bytes;
+
+        if let Some(callback) = &self.callback {
+            callback(&Progress {
+                path: path.to_owned(),
+                files_done: self.files_done,
+                files_total: self.files_total,
+                bytes_done: self.bytes_done,
+                bytes_total: self.bytes_total,
+            });
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::archive::Archive;
+use super::progress::{Progress, ProgressCallback, ProgressTracker};
+use super::stream::{Compression, FileReader};
+use super::util::u64_from_usize;
+
+/// Options for changing how the files in an archive are compressed.
+///
+/// This is used with [`Archive::recompress_with`].
+///
+/// [`Archive::recompress_with`]: crate::Archive::recompress_with
+#[derive(Clone)]
+pub struct RecompressOptions {
+    on_progress: Option<Arc<ProgressCallback>>,
+}
+
+impl fmt::Debug for RecompressOptions {
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecompressOptions")
+            .field("on_progress", &self.on_progress.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+impl Default for RecompressOptions {
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RecompressOptions {
+    /// Create a new [`RecompressOptions`] with default settings.
+    pub fn new() -> Self {
+        Self { on_progress: None }
+    }
+
+    /// Call this function after each file is recompressed.
+    ///
+    /// The callback is passed a [`Progress`] with the path of the file, the number of files and
+    /// bytes recompressed so far, and the total number of files and bytes being recompressed.
+    /// Bytes are counted by the uncompressed size of the files.
+    ///
+    /// By default, progress isn't reported.
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        self.on_progress = Some(Arc::new(callback));
+        self
+    }
+}
+
+impl<'conn> Archive<'conn> {
+    pub(super) fn recompress_files(
+        &self,
+        method: Compression,
+        opts: &RecompressOptions,
+    ) -> crate::Result<()> {
+        self.store.exec(|store| {
+            let files = store.file_sizes(None)?;
+
+            let mut progress = ProgressTracker::new(
+                opts.on_progress.clone(),
+                Some(u64_from_usize(files.len())),
+                Some(files.iter().map(|(_, size)| size.original).sum()),
+            );
+
+            for (path, size) in files {
+                // Claim the file so we don't pull its contents out from under an open handle.
+                let mut file = self.open_stored(Path::new(&path))?;
+
+                // There's nothing to decompress if the file is already stored uncompressed.
+                let already_uncompressed = size.original == size.actual;
+
+                if !(method == Compression::None && already_uncompressed) {
+                    let mut reader = FileReader::new(store.open_blob(&path, true)?)?;
+
+                    file.set_compression(method);
+                    file.set_update_mtime(false);
+
+                    // We can't read from the file while we're overwriting it, so the new contents
+                    // are written somewhere else first.
+                    file.replace_contents_atomic(&mut reader)?;
+                }
+
+                progress.file_done(Path::new(&path), size.original);
+            }
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,135 @@
+use std::path::{Path, PathBuf};
+
+use super::archive::Archive;
+use super::file::normalize_path;
+use super::store::Store;
+
+/// What to do when renaming a file would overwrite an existing file.
+///
+/// This is used with [`Archive::rename_with`].
+///
+/// [`Archive::rename_with`]: crate::Archive::rename_with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum RenamePolicy {
+    /// Return an error if the destination already exists.
+    #[default]
+    Error,
+
+    /// Merge the source directory into the destination directory.
+    ///
+    /// Directories that exist in both trees are merged recursively. Any other file in the
+    /// destination tree that has the same path as a file in the source tree is overwritten. If
+    /// a file in the source tree overwrites a directory, that directory's descendants are deleted.
+    ///
+    /// Like with [`RenamePolicy::Overwrite`], this returns an error if a pinned file would be
+    /// overwritten. See [`File::pin`].
+    ///
+    /// [`File::pin`]: crate::File::pin
+    Merge,
+
+    /// Delete the destination and all its descendants before renaming.
+    Overwrite,
+}
+
+impl<'conn> Archive<'conn> {
+    pub(super) fn rename_tree(
+        &self,
+        from: &Path,
+        to: &Path,
+        policy: RenamePolicy,
+    ) -> crate::Result<()> {
+        let from = normalize_path(from)?;
+        let to = normalize_path(to)?;
+
+        // Renaming moves the descendants of the source and can replace the destination and its
+        // descendants, so none of them can be open.
+        self.store.check_tree_not_open(&from)?;
+        self.store.check_tree_not_open(&to)?;
+
+        rename_paths(&self.store, &from, &to, policy)
+    }
+}
+
+// Rename the file at `from` to `to` in a single savepoint, moving its descendants along with it.
+// Both paths must already be normalized.
+pub(super) fn rename_paths(
+    store: &Store,
+    from: &str,
+    to: &str,
+    policy: RenamePolicy,
+) -> crate::Result<()> {
+    store.exec(|store| {
+        // Make sure the source exists before doing anything else.
+        store.read_metadata(from)?;
+
+        if from == to {
+            return Ok(());
+        }
+
+        let dest_exists = check_tree_dest(store, from, to)?;
+
+        if dest_exists {
+            match policy {
+                RenamePolicy::Error => {
+                    return Err(crate::Error::FileAlreadyExists {
+                        path: PathBuf::from(to),
+                    })
+                }
+                RenamePolicy::Overwrite => store.delete_file(to, false)?,
+                RenamePolicy::Merge => {
+                    store.delete_merge_conflicts(from, to)?;
+                    store.delete_merged_dirs(from, to)?;
+                }
+            }
+        }
+
+        // If the source was a directory that got merged into an existing directory, there may
+        // be nothing left to rename.
+        match store.rename_files(from, to) {
+            Ok(_) | Err(crate::Error::FileNotFound { .. }) => Ok(()),
+            Err(err) => Err(err),
+        }
+    })
+}
+
+// Check that the tree at `from` can be moved or copied to `to`, returning whether there's already
+// a file at `to`.
+pub(super) fn check_tree_dest(store: &Store, from: &str, to: &str) -> crate::Result<bool> {
+    if Path::new(to).starts_with(from) || Path::new(from).starts_with(to) {
+        return Err(crate::Error::InvalidArgs {
+            reason: format!(
+                "Cannot move or copy a file to its own ancestor or descendant: {from} -> {to}"
+            ),
+        });
+    }
+
+    check_dest(store, to)
+}
+
+// Check that the parent directory of `to` exists, returning whether there's already a file at
+// `to`.
+pub(super) fn check_dest(store: &Store, to: &str) -> crate::Result<bool> {
+    let parent_path = Path::new(to)
+        .parent()
+        .and_then(Path::to_str)
+        .unwrap_or_default();
+
+    if !parent_path.is_empty() {
+        match store.read_metadata(parent_path) {
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(_) | Err(crate::Error::FileNotFound { .. }) => {
+                return Err(crate::Error::NoParentDirectory {
+                    path: PathBuf::from(to),
+                })
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    match store.read_metadata(to) {
+        Ok(_) => Ok(true),
+        Err(crate::Error::FileNotFound { .. }) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
@@ -0,0 +1,451 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io;
+use std::path::PathBuf;
+
+use super::archive::Archive;
+use super::store::{BlobSize, Store};
+use super::stream::FileReader;
+use super::util::u64_from_usize;
+
+/// Options for repairing an archive with [`Archive::repair`].
+///
+/// By default, nothing is repaired, and [`Archive::repair`] only reports the problems it finds.
+///
+/// [`Archive::repair`]: crate::Archive::repair
+#[derive(Debug, Clone)]
+pub struct RepairOptions {
+    fix_dirs: bool,
+    fix_paths: bool,
+    fix_sizes: bool,
+    remove_orphans: bool,
+    drop_corrupt: bool,
+}
+
+impl Default for RepairOptions {
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RepairOptions {
+    /// Create a new [`RepairOptions`] with default settings.
+    pub fn new() -> Self {
+        Self {
+            fix_dirs: false,
+            fix_paths: false,
+            fix_sizes: false,
+            remove_orphans: false,
+            drop_corrupt: false,
+        }
+    }
+
+    /// Create the missing parent directories of files in the archive.
+    ///
+    /// The new directories get their mode from [`Archive::umask`] and an mtime of now. A missing
+    /// directory can't be created if one of its ancestors is not a directory.
+    ///
+    /// The default is `false`.
+    ///
+    /// [`Archive::umask`]: crate::Archive::umask
+    pub fn fix_dirs(mut self, fix: bool) -> Self {
+        self.fix_dirs = fix;
+        self
+    }
+
+    /// Rename files whose paths aren't in the form this library expects.
+    ///
+    /// This strips leading and trailing slashes, repeated slashes, and `.` components. Paths with
+    /// `..` components are left alone, as are paths that would be renamed onto a file that already
+    /// exists.
+    ///
+    /// The default is `false`.
+    pub fn fix_paths(mut self, fix: bool) -> Self {
+        self.fix_paths = fix;
+        self
+    }
+
+    /// Correct the sizes of files that are wrong.
+    ///
+    /// Directories with a negative size get a size of zero. Regular files with a negative size,
+    /// and compressed regular files whose contents decompress to a different size than the
+    /// archive says, get the size of their decompressed contents. If the contents of a regular
+    /// file with a negative size can't be decompressed, they're assumed to be uncompressed. Files
+    /// whose contents are stored as text rather than a blob are left alone, because that's how
+    /// symbolic links are stored.
+    ///
+    /// This happens before [`RepairOptions::drop_corrupt`], so files whose sizes are fixed aren't
+    /// deleted.
+    ///
+    /// The default is `false`.
+    pub fn fix_sizes(mut self, fix: bool) -> Self {
+        self.fix_sizes = fix;
+        self
+    }
+
+    /// Delete files whose parent directory is missing or isn't a directory.
+    ///
+    /// Such files can't be reached by walking the archive from the root. This happens after
+    /// [`RepairOptions::fix_dirs`], so only files whose parents couldn't be created are deleted.
+    ///
+    /// The default is `false`.
+    pub fn remove_orphans(mut self, remove: bool) -> Self {
+        self.remove_orphans = remove;
+        self
+    }
+
+    /// Delete regular files whose contents can't be decompressed.
+    ///
+    /// The default is `false`.
+    pub fn drop_corrupt(mut self, drop: bool) -> Self {
+        self.drop_corrupt = drop;
+        self
+    }
+}
+
+/// The problems found in an archive.
+///
+/// This is returned by [`Archive::check`] and is part of a [`RepairReport`]. Each list of paths is
+/// in sorted order.
+///
+/// [`Archive::check`]: crate::Archive::check
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArchiveProblems {
+    missing_dirs: Vec<PathBuf>,
+    non_dir_parents: Vec<PathBuf>,
+    bad_paths: Vec<PathBuf>,
+    duplicate_paths: Vec<PathBuf>,
+    negative_sizes: Vec<PathBuf>,
+    corrupt_files: Vec<PathBuf>,
+}
+
+impl ArchiveProblems {
+    /// The directories that don't exist in the archive but have files under them.
+    ///
+    /// See [`RepairOptions::fix_dirs`].
+    pub fn missing_dirs(&self) -> &[PathBuf] {
+        &self.missing_dirs
+    }
+
+    /// The files that have files under them, but aren't directories.
+    ///
+    /// The files under them can't be reached by walking the archive from the root.
+    pub fn non_dir_parents(&self) -> &[PathBuf] {
+        &self.non_dir_parents
+    }
+
+    /// The files whose paths aren't in the form this library expects.
+    ///
+    /// This includes paths with leading or trailing slashes and `..` components. These files can
+    /// be listed, but not opened. See [`RepairOptions::fix_paths`].
+    pub fn bad_paths(&self) -> &[PathBuf] {
+        &self.bad_paths
+    }
+
+    /// The files whose paths are the same as another file's once they're put in the form this
+    /// library expects, like `dir` and `dir/`.
+    ///
+    /// Every file in each group of duplicates is listed. [`RepairOptions::fix_paths`] can't rename
+    /// these files, because they would be renamed onto each other.
+    pub fn duplicate_paths(&self) -> &[PathBuf] {
+        &self.duplicate_paths
+    }
+
+    /// The files that have a negative size but aren't symbolic links.
+    ///
+    /// The sqlar format only uses a negative size to mark symbolic links.
+    pub fn negative_sizes(&self) -> &[PathBuf] {
+        &self.negative_sizes
+    }
+
+    /// The regular files whose contents can't be decompressed.
+    ///
+    /// Compressed files can't be checked when the `deflate` Cargo feature is disabled. See
+    /// [`RepairOptions::drop_corrupt`].
+    pub fn corrupt_files(&self) -> &[PathBuf] {
+        &self.corrupt_files
+    }
+
+    /// Whether no problems were found.
+    pub fn is_empty(&self) -> bool {
+        self.missing_dirs.is_empty()
+            && self.non_dir_parents.is_empty()
+            && self.bad_paths.is_empty()
+            && self.duplicate_paths.is_empty()
+            && self.negative_sizes.is_empty()
+            && self.corrupt_files.is_empty()
+    }
+}
+
+/// A report of the problems in an archive before and after repairing it.
+///
+/// This is returned by [`Archive::repair`].
+///
+/// [`Archive::repair`]: crate::Archive::repair
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    before: ArchiveProblems,
+    after: ArchiveProblems,
+}
+
+impl RepairReport {
+    /// The problems found in the archive before it was repaired.
+    pub fn before(&self) -> &ArchiveProblems {
+        &self.before
+    }
+
+    /// The problems left in the archive after it was repaired.
+    pub fn after(&self) -> &ArchiveProblems {
+        &self.after
+    }
+}
+
+// Return the form of `name` that this library expects, or `None` if there isn't one.
+fn normal_form(name: &str) -> Option<String> {
+    let mut components = Vec::new();
+
+    for component in name.split('/') {
+        match component {
+            "" | "." => continue,
+            ".." => return None,
+            _ => components.push(component),
+        }
+    }
+
+    if components.is_empty() {
+        None
+    } else {
+        Some(components.join("/"))
+    }
+}
+
+// The ancestors of `name`, from the closest to the farthest, not including `name` itself.
+fn ancestors(name: &str) -> impl Iterator<Item = &str> {
+    let mut current = name;
+
+    std::iter::from_fn(move || {
+        let (parent, _) = current.rsplit_once('/')?;
+        current = parent;
+        Some(parent)
+    })
+}
+
+fn is_corrupt(store: &Store, name: &str, size: &BlobSize) -> crate::Result<bool> {
+    // There's no way to tell if an uncompressed file is corrupt.
+    if !size.is_compressed() {
+        return Ok(false);
+    }
+
+    let mut reader = match FileReader::new(store.open_blob(name, true)?) {
+        Ok(reader) => reader,
+        // We can't check compressed files without the `deflate` feature.
+        Err(crate::Error::CompressionNotSupported) => return Ok(false),
+        Err(err) => return Err(err),
+    };
+
+    match io::copy(&mut reader, &mut io::sink()) {
+        Ok(len) => Ok(len != size.original),
+        Err(err) => match crate::Error::from(err) {
+            crate::Error::Io { .. } => Ok(true),
+            err => Err(err),
+        },
+    }
+}
+
+// The size of the contents of the file at `name` once they're decompressed, regardless of what
+// its `sz` column says, or `None` if they can't be decompressed.
+fn decompressed_size(store: &Store, name: &str) -> crate::Result<Option<u64>> {
+    let mut reader = match FileReader::new(store.open_blob_compressed(name)?) {
+        Ok(reader) => reader,
+        Err(crate::Error::CompressionNotSupported) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    match io::copy(&mut reader, &mut io::sink()) {
+        Ok(len) => Ok(Some(len)),
+        Err(err) => match crate::Error::from(err) {
+            crate::Error::Io { .. } => Ok(None),
+            err => Err(err),
+        },
+    }
+}
+
+// The files in normal form that have an ancestor that's missing or isn't a directory.
+fn find_orphans(store: &Store) -> crate::Result<Vec<String>> {
+    let dirs = store.dir_names()?.into_iter().collect::<HashSet<_>>();
+
+    Ok(store
+        .file_names()?
+        .into_iter()
+        .filter(|name| {
+            normal_form(name).as_deref() == Some(name.as_str())
+                && ancestors(name).any(|ancestor| !dirs.contains(ancestor))
+        })
+        .collect())
+}
+
+impl<'conn> Archive<'conn> {
+    pub(super) fn find_problems(&self) -> crate::Result<ArchiveProblems> {
+        let names = self.store.file_names()?;
+        let existing = names.iter().map(String::as_str).collect::<HashSet<_>>();
+        let dirs = self.store.dir_names()?.into_iter().collect::<HashSet<_>>();
+
+        let mut bad_paths = Vec::new();
+        let mut missing_dirs = BTreeSet::new();
+        let mut non_dir_parents = BTreeSet::new();
+        let mut by_normal_form = HashMap::<_, Vec<_>>::new();
+
+        for name in &names {
+            let normalized = normal_form(name);
+
+            if let Some(normalized) = &normalized {
+                by_normal_form
+                    .entry(normalized.clone())
+                    .or_default()
+                    .push(name.as_str());
+            }
+
+            if normalized.as_deref() != Some(name.as_str()) {
+                bad_paths.push(PathBuf::from(name));
+                continue;
+            }
+
+            for ancestor in ancestors(name) {
+                if !existing.contains(ancestor) {
+                    missing_dirs.insert(ancestor.to_owned());
+                } else if !dirs.contains(ancestor) {
+                    non_dir_parents.insert(ancestor.to_owned());
+                }
+            }
+        }
+
+        let duplicate_paths = by_normal_form
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .flatten()
+            .collect::<BTreeSet<_>>();
+
+        let mut corrupt_files = Vec::new();
+
+        for (name, size) in self.store.file_sizes(None)? {
+            if is_corrupt(&self.store, &name, &size)? {
+                corrupt_files.push(PathBuf::from(name));
+            }
+        }
+
+        Ok(ArchiveProblems {
+            missing_dirs: missing_dirs.into_iter().map(PathBuf::from).collect(),
+            non_dir_parents: non_dir_parents.into_iter().map(PathBuf::from).collect(),
+            bad_paths,
+            duplicate_paths: duplicate_paths.into_iter().map(PathBuf::from).collect(),
+            negative_sizes: self
+                .store
+                .negative_size_names()?
+                .into_iter()
+                .map(PathBuf::from)
+                .collect(),
+            corrupt_files,
+        })
+    }
+
+    pub(super) fn repair_problems(&self, opts: &RepairOptions) -> crate::Result<RepairReport> {
+        self.store.exec(|store| {
+            let before = self.find_problems()?;
+
+            if opts.fix_sizes {
+                let dirs = store.dir_names()?.into_iter().collect::<HashSet<_>>();
+
+                for path in &before.negative_sizes {
+                    let name = path.to_string_lossy();
+
+                    // We can't read the metadata of these files, because their size is invalid.
+                    let size = if dirs.contains(name.as_ref()) {
+                        0
+                    } else if !store.has_blob_data(&name)? {
+                        // Contents that are text rather than a blob are how symlinks are stored,
+                        // so we don't know whether the size or the mode is wrong.
+                        continue;
+                    } else {
+                        match decompressed_size(store, &name)? {
+                            Some(size) => size,
+                            None => {
+                                u64_from_usize(store.open_blob_compressed(&name)?.into_blob().len())
+                            }
+                        }
+                    };
+
+                    store.set_size(&name, size)?;
+                }
+
+                for path in &before.corrupt_files {
+                    let name = path.to_string_lossy();
+
+                    if let Some(size) = decompressed_size(store, &name)? {
+                        store.set_size(&name, size)?;
+                    }
+                }
+            }
+
+            // Fixing sizes can make corrupt files readable, so we need to look again.
+            let corrupt_files = if opts.drop_corrupt && opts.fix_sizes {
+                self.find_problems()?.corrupt_files
+            } else {
+                before.corrupt_files.clone()
+            };
+
+            if opts.drop_corrupt {
+                for path in &corrupt_files {
+                    store.delete_entry(&path.to_string_lossy())?;
+                }
+            }
+
+            if opts.fix_paths {
+                for path in &before.bad_paths {
+                    let name = path.to_string_lossy();
+
+                    // Corrupt files with bad paths may have already been deleted.
+                    if opts.drop_corrupt && corrupt_files.contains(path) {
+                        continue;
+                    }
+
+                    let Some(normalized) = normal_form(&name) else {
+                        continue;
+                    };
+
+                    match store.rename_entry(&name, &normalized) {
+                        Ok(()) | Err(crate::Error::FileAlreadyExists { .. }) => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+            }
+
+            if opts.fix_dirs {
+                // Fixing paths can leave new directories missing, so we need to look again.
+                let missing_dirs = if opts.fix_paths {
+                    self.find_problems()?.missing_dirs
+                } else {
+                    before.missing_dirs.clone()
+                };
+
+                // Parents sort before their children, so each directory's parent is created
+                // before it is.
+                for path in &missing_dirs {
+                    match self.open_unclaimed_mut(path)?.create_dir() {
+                        Ok(()) | Err(crate::Error::NoParentDirectory { .. }) => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+            }
+
+            if opts.remove_orphans {
+                for name in find_orphans(store)? {
+                    store.delete_entry(&name)?;
+                }
+            }
+
+            let after = self.find_problems()?;
+
+            Ok(RepairReport { before, after })
+        })
+    }
+}
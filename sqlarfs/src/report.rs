@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::archive::Archive;
+use super::file::normalize_path;
+
+/// Aggregate compression statistics for a set of files.
+///
+/// This is part of a [`CompressionReport`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CompressionStats {
+    files: u64,
+    compressed_files: u64,
+    original_size: u64,
+    stored_size: u64,
+}
+
+impl CompressionStats {
+    fn add(&mut self, original_size: u64, stored_size: u64) {
+        self.files += 1;
+        self.original_size += original_size;
+        self.stored_size += stored_size;
+
+        if original_size != stored_size {
+            self.compressed_files += 1;
+        }
+    }
+
+    /// The number of regular files.
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    /// The number of regular files that are stored compressed.
+    ///
+    /// Files are stored uncompressed when compression is disabled or when compressing them
+    /// wouldn't make them any smaller.
+    pub fn compressed_files(&self) -> u64 {
+        self.compressed_files
+    }
+
+    /// The total uncompressed size of the files in bytes.
+    pub fn original_size(&self) -> u64 {
+        self.original_size
+    }
+
+    /// The total size of the files as they're stored in the archive in bytes.
+    pub fn stored_size(&self) -> u64 {
+        self.stored_size
+    }
+
+    /// The stored size of the files as a fraction of their original size.
+    ///
+    /// Smaller is better; `0.25` means the files take up a quarter of their original size. If
+    /// the files are all empty, this is `1.0`.
+    pub fn ratio(&self) -> f64 {
+        if self.original_size == 0 {
+            1.0
+        } else {
+            self.stored_size as f64 / self.original_size as f64
+        }
+    }
+
+    /// The number of bytes saved by compressing the files.
+    pub fn savings(&self) -> u64 {
+        self.original_size.saturating_sub(self.stored_size)
+    }
+}
+
+/// Compression statistics for the files with a given file extension.
+///
+/// This is part of a [`CompressionReport`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExtensionStats {
+    extension: Option<String>,
+    stats: CompressionStats,
+}
+
+impl ExtensionStats {
+    /// The file extension, without the leading `.`, in lowercase.
+    ///
+    /// This is `None` for files without an extension.
+    pub fn extension(&self) -> Option<&str> {
+        self.extension.as_deref()
+    }
+
+    /// The statistics for files with this extension.
+    pub fn stats(&self) -> &CompressionStats {
+        &self.stats
+    }
+}
+
+/// A report of how well the files in an archive compress, grouped by file extension.
+///
+/// This is returned by [`Archive::compression_report`]. Only regular files are counted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompressionReport {
+    extensions: Vec<ExtensionStats>,
+    total: CompressionStats,
+}
+
+impl CompressionReport {
+    /// The statistics for each file extension.
+    ///
+    /// These are sorted by their original size, largest first.
+    pub fn extensions(&self) -> &[ExtensionStats] {
+        &self.extensions
+    }
+
+    /// The statistics for all the files in the report.
+    pub fn total(&self) -> &CompressionStats {
+        &self.total
+    }
+}
+
+impl<'conn> Archive<'conn> {
+    pub(super) fn report_compression(&self, path: &Path) -> crate::Result<CompressionReport> {
+        let ancestor = if path.as_os_str().is_empty() {
+            None
+        } else {
+            let path = normalize_path(path)?;
+
+            // Make sure the file exists.
+            self.store.read_metadata(&path)?;
+
+            Some(path)
+        };
+
+        let mut by_extension = HashMap::<Option<String>, CompressionStats>::new();
+        let mut total = CompressionStats::default();
+
+        for (name, size) in self.store.file_sizes(ancestor.as_deref())? {
+            let extension = Path::new(&name)
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase());
+
+            by_extension
+                .entry(extension)
+                .or_default()
+                .add(size.original, size.actual);
+
+            total.add(size.original, size.actual);
+        }
+
+        let mut extensions = by_extension
+            .into_iter()
+            .map(|(extension, stats)| ExtensionStats { extension, stats })
+            .collect::<Vec<_>>();
+
+        extensions.sort_by(|a, b| {
+            b.stats
+                .original_size
+                .cmp(&a.stats.original_size)
+                .then_with(|| a.extension.cmp(&b.extension))
+        });
+
+        Ok(CompressionReport { extensions, total })
+    }
+}
@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::metadata::FileType;
+
+/// A policy for deleting old files from an archive.
+///
+/// This is used with [`Archive::prune`].
+///
+/// A policy is made up of one or more rules:
+///
+/// - [`RetentionPolicy::keep_newest`] keeps the newest N files in each directory.
+/// - [`RetentionPolicy::older_than`] deletes files last modified before a cutoff.
+///
+/// If both rules are set, a file is only deleted if it is older than the cutoff *and* is not one
+/// of the newest N files in its directory. A policy with no rules deletes nothing.
+///
+/// Files that don't have an mtime are never deleted, and they don't count toward the newest N
+/// files in a directory. Pinned files are never deleted unless you use [`RetentionPolicy::force`],
+/// but they do count toward the newest N files in a directory.
+///
+/// [`Archive::prune`]: crate::Archive::prune
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub(super) keep_newest: Option<u64>,
+    pub(super) older_than: Option<SystemTime>,
+    pub(super) ancestor: Option<PathBuf>,
+    pub(super) file_type: FileType,
+    pub(super) force: bool,
+}
+
+impl Default for RetentionPolicy {
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetentionPolicy {
+    /// Create a new [`RetentionPolicy`] with no rules.
+    pub fn new() -> Self {
+        Self {
+            keep_newest: None,
+            older_than: None,
+            ancestor: None,
+            file_type: FileType::File,
+            force: false,
+        }
+    }
+
+    /// Keep only the `count` most recently modified files in each directory.
+    pub fn keep_newest(mut self, count: u64) -> Self {
+        self.keep_newest = Some(count);
+        self
+    }
+
+    /// Delete files that were last modified before `cutoff`.
+    pub fn older_than(mut self, cutoff: SystemTime) -> Self {
+        self.older_than = Some(cutoff);
+        self
+    }
+
+    /// Only consider files that are descendants of the given `directory`.
+    ///
+    /// By default, all files in the archive are considered.
+    pub fn descendants_of<P: AsRef<Path>>(mut self, directory: P) -> Self {
+        self.ancestor = Some(directory.as_ref().to_path_buf());
+        self
+    }
+
+    /// Only consider files of this [`FileType`].
+    ///
+    /// If this is [`FileType::Dir`], deleting a directory deletes all its descendants as well.
+    ///
+    /// The default is [`FileType::File`].
+    pub fn file_type(mut self, file_type: FileType) -> Self {
+        self.file_type = file_type;
+        self
+    }
+
+    /// Delete files even if they're pinned.
+    ///
+    /// By default, files that are pinned with [`File::pin`] are never deleted, and neither are
+    /// directories that contain pinned files.
+    ///
+    /// [`File::pin`]: crate::File::pin
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+}
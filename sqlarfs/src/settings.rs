@@ -0,0 +1,62 @@
+use super::metadata::FileMode;
+use super::stream::Compression;
+
+/// Archive-level defaults to override for the duration of [`Archive::with_settings`].
+///
+/// Any setting you don't set here keeps its current value.
+///
+/// [`Archive::with_settings`]: crate::Archive::with_settings
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub(super) umask: Option<FileMode>,
+    pub(super) compression: Option<Compression>,
+    pub(super) update_mtime: Option<bool>,
+}
+
+impl Default for Settings {
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Settings {
+    /// Create a new [`Settings`] that doesn't override anything.
+    pub fn new() -> Self {
+        Self {
+            umask: None,
+            compression: None,
+            update_mtime: None,
+        }
+    }
+
+    /// Override the umask for newly created files and directories.
+    ///
+    /// See [`Archive::set_umask`].
+    ///
+    /// [`Archive::set_umask`]: crate::Archive::set_umask
+    pub fn umask(mut self, mode: FileMode) -> Self {
+        self.umask = Some(mode);
+        self
+    }
+
+    /// Override the compression method used when writing to files.
+    ///
+    /// See [`Archive::set_compression`].
+    ///
+    /// [`Archive::set_compression`]: crate::Archive::set_compression
+    pub fn compression(mut self, method: Compression) -> Self {
+        self.compression = Some(method);
+        self
+    }
+
+    /// Override whether writing to a file updates its mtime.
+    ///
+    /// See [`Archive::set_update_mtime`].
+    ///
+    /// [`Archive::set_update_mtime`]: crate::Archive::set_update_mtime
+    pub fn update_mtime(mut self, update: bool) -> Self {
+        self.update_mtime = Some(update);
+        self
+    }
+}
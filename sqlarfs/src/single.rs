@@ -0,0 +1,60 @@
+use std::io::Read;
+
+use super::archive::Archive;
+
+/// The path of the entry used by [`Connection::store_single`] and [`Connection::load_single`].
+///
+/// [`Connection::store_single`]: crate::Connection::store_single
+/// [`Connection::load_single`]: crate::Connection::load_single
+pub const SINGLE_ENTRY: &str = "main";
+
+impl<'conn> Archive<'conn> {
+    pub(super) fn store_single_entry(&self, reader: &mut dyn Read) -> crate::Result<()> {
+        let mut file = self.open(SINGLE_ENTRY)?;
+
+        if file.exists()? {
+            file.set_mtime(Some(self.clock.now()))?;
+        } else {
+            file.create_file()?;
+        }
+
+        file.write_from(reader)
+    }
+
+    // Find the entry to load as the contents of a single-document archive: the entry at
+    // `SINGLE_ENTRY` if there is one, or otherwise the only file in the archive.
+    fn find_single_entry(&self) -> crate::Result<String> {
+        if self.open(SINGLE_ENTRY)?.exists()? {
+            return Ok(SINGLE_ENTRY.to_owned());
+        }
+
+        let mut entries = self.list()?;
+
+        let only_entry = match (entries.next(), entries.next()) {
+            (Some(entry), None) => entry?,
+            (None, _) => {
+                return Err(crate::Error::FileNotFound {
+                    path: SINGLE_ENTRY.into(),
+                })
+            }
+            (Some(_), Some(_)) => {
+                return Err(crate::Error::InvalidArgs {
+                    reason: format!(
+                        "The archive contains more than one file and none of them are at `{SINGLE_ENTRY}`."
+                    ),
+                })
+            }
+        };
+
+        Ok(only_entry.path().to_string_lossy().into_owned())
+    }
+
+    pub(super) fn load_single_entry(&self) -> crate::Result<Vec<u8>> {
+        let path = self.find_single_entry()?;
+
+        let mut contents = Vec::new();
+        self.open(path)?.reader()?.read_to_end(&mut contents)?;
+
+        Ok(contents)
+    }
+}
@@ -1,25 +1,72 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::{self, Duration, SystemTime, UNIX_EPOCH};
 
 use rusqlite::blob::Blob;
-use rusqlite::{OptionalExtension, Savepoint};
+use rusqlite::OptionalExtension;
 
 use crate::list::SortDirection;
 use crate::metadata::SYMLINK_MODE;
 
-use super::list::{ListEntries, ListEntry, ListMapFunc, ListOptions, ListSort};
+#[cfg(feature = "zstd")]
+use super::dictionary::frame_dictionary_id;
+use super::digest::digest_stream;
+use super::external::ExternalLink;
+use super::list::{EntryId, ListEntries, ListEntry, ListMapFunc, ListOptions, ListSort};
 use super::metadata::{FileMetadata, FileMode, FileType, DIR_MODE, FILE_MODE, TYPE_MASK};
+use super::retention::RetentionPolicy;
 use super::util::u64_from_usize;
 
-#[derive(Debug)]
-enum InnerTransaction<'conn> {
-    Transaction(rusqlite::Transaction<'conn>),
-    Savepoint(rusqlite::Savepoint<'conn>),
+// A savepoint that's rolled back when it's dropped unless it's been released.
+//
+// Unlike `rusqlite::Savepoint`, this only needs a shared reference to the connection, so that
+// several `File` handles can share one `Store`.
+struct SavepointGuard<'a> {
+    conn: &'a rusqlite::Connection,
+    released: bool,
+}
+
+impl<'a> SavepointGuard<'a> {
+    // SQLite allows nesting savepoints with the same name; each `RELEASE` or `ROLLBACK TO` applies
+    // to the most recent one.
+    fn new(conn: &'a rusqlite::Connection) -> crate::Result<Self> {
+        conn.execute_batch("SAVEPOINT sqlarfs_exec")?;
+
+        Ok(Self {
+            conn,
+            released: false,
+        })
+    }
+
+    fn release(mut self) -> crate::Result<()> {
+        self.released = true;
+        self.conn.execute_batch("RELEASE sqlarfs_exec")?;
+        Ok(())
+    }
+}
+
+impl<'a> Drop for SavepointGuard<'a> {
+    fn drop(&mut self) {
+        if !self.released {
+            // There's nothing we can do about an error here, and the enclosing transaction will
+            // be rolled back if it's not committed anyways.
+            let _ = self
+                .conn
+                .execute_batch("ROLLBACK TO sqlarfs_exec; RELEASE sqlarfs_exec");
+        }
+    }
 }
 
 pub struct FileBlob<'conn> {
     blob: Blob<'conn>,
     original_size: u64,
+    // The shared dictionary the contents were compressed with, if any.
+    #[cfg(feature = "zstd")]
+    dictionary: Option<Vec<u8>>,
 }
 
 impl<'conn> FileBlob<'conn> {
@@ -27,9 +74,49 @@ impl<'conn> FileBlob<'conn> {
         u64_from_usize(self.blob.len()) != self.original_size
     }
 
+    pub fn original_size(&self) -> u64 {
+        self.original_size
+    }
+
     pub fn into_blob(self) -> Blob<'conn> {
         self.blob
     }
+
+    #[cfg(feature = "zstd")]
+    pub fn into_blob_and_dictionary(self) -> (Blob<'conn>, Option<Vec<u8>>) {
+        (self.blob, self.dictionary)
+    }
+}
+
+// A regular file to insert along with its contents.
+#[derive(Debug)]
+pub struct NewFile {
+    pub path: String,
+    pub mode: FileMode,
+    pub mtime: Option<SystemTime>,
+    // The uncompressed size of the contents.
+    pub size: u64,
+    // The contents, as they should be stored.
+    pub data: Vec<u8>,
+}
+
+// A row of the `sqlar` table, exactly as it's stored.
+#[derive(Debug)]
+pub struct RawFile {
+    pub name: String,
+    pub mode: Option<i64>,
+    pub mtime: Option<i64>,
+    pub size: i64,
+    pub data: RawData,
+}
+
+#[derive(Debug)]
+pub enum RawData {
+    // The `data` column of a directory or symlink.
+    Value(rusqlite::types::Value),
+
+    // The length of the stored contents of a regular file.
+    Blob(u64),
 }
 
 #[derive(Debug)]
@@ -47,85 +134,610 @@ impl BlobSize {
     }
 }
 
+// The `WHERE` clause used to filter files according to a `ListOptions`. It expects the archive
+// table, `sqlar`, to be aliased as `s` and takes the parameters returned by `list_filter_params`.
+fn list_filter(sqlar: &Ident) -> String {
+    format!(
+        "
+            iif(?1 IS NULL OR ?1 = '', true, s.name GLOB ?1 || '/?*')
+            AND iif(?3 IS NULL, true, (s.mode & ?2) = ?3)
+            AND iif(?4 IS NULL, true, (s.mode & ?2) = ?4)
+            AND CASE
+                WHEN ?5 IS NULL THEN true
+                WHEN ?5 = '' THEN NOT s.name GLOB '*/*'
+                ELSE s.name GLOB ?5 || '/?*' AND NOT s.name GLOB ?5 || '/?*/*'
+            END
+            AND iif(?6 IS NULL, true, s.name GLOB ?6)
+            AND iif(?7 IS NULL, true, s.mtime < ?7)
+            AND iif(?8 IS NULL, true, s.mtime >= ?8)
+            AND iif(
+                ?9 IS NULL,
+                true,
+                (s.mode & ?2) = ?9 AND NOT EXISTS (SELECT 1 FROM {sqlar} AS c WHERE c.name GLOB s.name || '/?*')
+            )
+        "
+    )
+}
+
+fn unix_secs(time: SystemTime) -> crate::Result<u64> {
+    Ok(time
+        .duration_since(time::UNIX_EPOCH)
+        .map_err(|err| crate::Error::InvalidArgs {
+            reason: err.to_string(),
+        })?
+        .as_secs())
+}
+
+fn list_filter_params(opts: &ListOptions) -> crate::Result<Vec<Box<dyn rusqlite::ToSql>>> {
+    Ok(vec![
+        Box::new(opts.ancestor.as_ref().map(|ancestor| {
+            ancestor
+                .to_string_lossy()
+                .trim_end_matches(std::path::MAIN_SEPARATOR)
+                .to_string()
+        })),
+        Box::new(TYPE_MASK),
+        Box::new(if let Some(ListSort::Size) = opts.sort {
+            Some(FILE_MODE)
+        } else {
+            None
+        }),
+        Box::new(match opts.file_type {
+            Some(FileType::File) => Some(FILE_MODE),
+            Some(FileType::Dir) => Some(DIR_MODE),
+            Some(FileType::Symlink) => Some(SYMLINK_MODE),
+            None => None,
+        }),
+        Box::new(opts.parent.as_ref().map(|parent| {
+            parent
+                .to_string_lossy()
+                .trim_end_matches(std::path::MAIN_SEPARATOR)
+                .to_string()
+        })),
+        Box::new(opts.glob.clone()),
+        Box::new(opts.modified_before.map(unix_secs).transpose()?),
+        Box::new(opts.modified_after.map(unix_secs).transpose()?),
+        Box::new(if opts.empty_dirs {
+            Some(DIR_MODE)
+        } else {
+            None
+        }),
+    ])
+}
+
+// A SQL expression for the current time in milliseconds since the Unix epoch. This works in
+// versions of SQLite that don't support `unixepoch('subsec')`.
+const NOW_MILLIS: &str = "CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)";
+
+// Return whether the database has a table named `table`.
+pub fn table_exists(conn: &rusqlite::Connection, table: &str) -> crate::Result<bool> {
+    Ok(conn
+        .prepare_cached("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1")?
+        .query_row((table,), |_| Ok(()))
+        .optional()?
+        .is_some())
+}
+
+// Return the names of the tables in the database, excluding SQLite's internal tables.
+pub fn list_tables(conn: &rusqlite::Connection) -> crate::Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' ORDER BY name",
+    )?;
+
+    let tables = stmt
+        .query_map((), |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+
+    Ok(tables)
+}
+
+// Return an error if the database doesn't have an archive table named `table`.
+pub fn check_is_archive(conn: &rusqlite::Connection, table: &str) -> crate::Result<()> {
+    if table_exists(conn, table)? {
+        return Ok(());
+    }
+
+    Err(crate::Error::NotAnArchive {
+        tables: list_tables(conn)?,
+    })
+}
+
+// The name of the archive table, unless the connection was opened with a different one.
+pub const DEFAULT_TABLE: &str = "sqlar";
+
+// The keywords SQLite reserves. See <https://www.sqlite.org/lang_keywords.html>.
+const SQLITE_KEYWORDS: &[&str] = &[
+    "ABORT",
+    "ACTION",
+    "ADD",
+    "AFTER",
+    "ALL",
+    "ALTER",
+    "ALWAYS",
+    "ANALYZE",
+    "AND",
+    "AS",
+    "ASC",
+    "ATTACH",
+    "AUTOINCREMENT",
+    "BEFORE",
+    "BEGIN",
+    "BETWEEN",
+    "BY",
+    "CASCADE",
+    "CASE",
+    "CAST",
+    "CHECK",
+    "COLLATE",
+    "COLUMN",
+    "COMMIT",
+    "CONFLICT",
+    "CONSTRAINT",
+    "CREATE",
+    "CROSS",
+    "CURRENT",
+    "CURRENT_DATE",
+    "CURRENT_TIME",
+    "CURRENT_TIMESTAMP",
+    "DATABASE",
+    "DEFAULT",
+    "DEFERRABLE",
+    "DEFERRED",
+    "DELETE",
+    "DESC",
+    "DETACH",
+    "DISTINCT",
+    "DO",
+    "DROP",
+    "EACH",
+    "ELSE",
+    "END",
+    "ESCAPE",
+    "EXCEPT",
+    "EXCLUDE",
+    "EXCLUSIVE",
+    "EXISTS",
+    "EXPLAIN",
+    "FAIL",
+    "FILTER",
+    "FIRST",
+    "FOLLOWING",
+    "FOR",
+    "FOREIGN",
+    "FROM",
+    "FULL",
+    "GENERATED",
+    "GLOB",
+    "GROUP",
+    "GROUPS",
+    "HAVING",
+    "IF",
+    "IGNORE",
+    "IMMEDIATE",
+    "IN",
+    "INDEX",
+    "INDEXED",
+    "INITIALLY",
+    "INNER",
+    "INSERT",
+    "INSTEAD",
+    "INTERSECT",
+    "INTO",
+    "IS",
+    "ISNULL",
+    "JOIN",
+    "KEY",
+    "LAST",
+    "LEFT",
+    "LIKE",
+    "LIMIT",
+    "MATCH",
+    "MATERIALIZED",
+    "NATURAL",
+    "NO",
+    "NOT",
+    "NOTHING",
+    "NOTNULL",
+    "NULL",
+    "NULLS",
+    "OF",
+    "OFFSET",
+    "ON",
+    "OR",
+    "ORDER",
+    "OTHERS",
+    "OUTER",
+    "OVER",
+    "PARTITION",
+    "PLAN",
+    "PRAGMA",
+    "PRECEDING",
+    "PRIMARY",
+    "QUERY",
+    "RAISE",
+    "RANGE",
+    "RECURSIVE",
+    "REFERENCES",
+    "REGEXP",
+    "REINDEX",
+    "RELEASE",
+    "RENAME",
+    "REPLACE",
+    "RESTRICT",
+    "RETURNING",
+    "RIGHT",
+    "ROLLBACK",
+    "ROW",
+    "ROWS",
+    "SAVEPOINT",
+    "SELECT",
+    "SET",
+    "TABLE",
+    "TEMP",
+    "TEMPORARY",
+    "THEN",
+    "TIES",
+    "TO",
+    "TRANSACTION",
+    "TRIGGER",
+    "UNBOUNDED",
+    "UNION",
+    "UNIQUE",
+    "UPDATE",
+    "USING",
+    "VACUUM",
+    "VALUES",
+    "VIEW",
+    "VIRTUAL",
+    "WHEN",
+    "WHERE",
+    "WINDOW",
+    "WITH",
+    "WITHOUT",
+];
+
+// Return an error if `name` can't be used as the name of an archive table.
+//
+// We always quote the name in SQL, but we only allow plain identifiers that aren't keywords so
+// that the archive is still easy to query from other tools.
+pub fn validate_table_name(name: &str) -> crate::Result<()> {
+    let mut chars = name.chars();
+
+    let is_identifier = chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_');
+
+    let is_keyword = SQLITE_KEYWORDS
+        .iter()
+        .any(|keyword| keyword.eq_ignore_ascii_case(name));
+
+    if !is_identifier || is_keyword || name.to_ascii_lowercase().starts_with("sqlite_") {
+        return Err(crate::Error::InvalidArgs {
+            reason: format!("The archive table name must only contain ASCII letters, digits, and underscores, must not start with a digit or `sqlite_`, and must not be an SQL keyword, but it was `{name}`."),
+        });
+    }
+
+    Ok(())
+}
+
+// The name of a table, index, or trigger, which is quoted when it's formatted into SQL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ident(String);
+
+impl Ident {
+    // The unquoted name, for places that take a name rather than SQL.
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Ident {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\"", self.0.replace('"', "\"\""))
+    }
+}
+
+// The archive table and the side tables that store extra data about its files.
+//
+// Each archive table has its own side tables, named after it. The fields are named after the
+// tables of an archive table with the default name.
+#[derive(Debug, Clone)]
+pub struct Tables {
+    pub sqlar: Ident,
+    pub sqlar_meta: Ident,
+    pub sqlar_zstd_dicts: Ident,
+    pub sqlar_raw_names: Ident,
+    pub sqlar_pins: Ident,
+    pub sqlar_external: Ident,
+    pub sqlar_checksums: Ident,
+    pub sqlar_dedup: Ident,
+    pub sqlar_dedup_refs: Ident,
+    pub sqlar_dedup_hashes: Ident,
+    pub sqlar_derived: Ident,
+    pub sqlar_jobs: Ident,
+    pub sqlar_modified: Ident,
+}
+
+impl Tables {
+    pub fn new(table: &str) -> Self {
+        let side_table = |suffix: &str| Ident(format!("{table}_{suffix}"));
+
+        Self {
+            sqlar: Ident(table.to_owned()),
+            sqlar_meta: side_table("meta"),
+            sqlar_zstd_dicts: side_table("zstd_dicts"),
+            sqlar_raw_names: side_table("raw_names"),
+            sqlar_pins: side_table("pins"),
+            sqlar_external: side_table("external"),
+            sqlar_checksums: side_table("checksums"),
+            sqlar_dedup: side_table("dedup"),
+            sqlar_dedup_refs: side_table("dedup_refs"),
+            sqlar_dedup_hashes: side_table("dedup_hashes"),
+            sqlar_derived: side_table("derived"),
+            sqlar_jobs: side_table("jobs"),
+            sqlar_modified: side_table("modified"),
+        }
+    }
+
+    // The name of an index or trigger that belongs to this archive table.
+    pub fn object(&self, suffix: &str) -> Ident {
+        Ident(format!("{}_{suffix}", self.sqlar.name()))
+    }
+
+    // Every table, including the archive table itself.
+    pub fn all(&self) -> [&Ident; 13] {
+        // This is destructured so that adding a table without listing it here is a compile error.
+        let Self {
+            sqlar,
+            sqlar_meta,
+            sqlar_zstd_dicts,
+            sqlar_raw_names,
+            sqlar_pins,
+            sqlar_external,
+            sqlar_checksums,
+            sqlar_dedup,
+            sqlar_dedup_refs,
+            sqlar_dedup_hashes,
+            sqlar_derived,
+            sqlar_jobs,
+            sqlar_modified,
+        } = self;
+
+        [
+            sqlar,
+            sqlar_meta,
+            sqlar_zstd_dicts,
+            sqlar_raw_names,
+            sqlar_pins,
+            sqlar_external,
+            sqlar_checksums,
+            sqlar_dedup,
+            sqlar_dedup_refs,
+            sqlar_dedup_hashes,
+            sqlar_derived,
+            sqlar_jobs,
+            sqlar_modified,
+        ]
+    }
+}
+
+// The default maximum length of a path, in bytes. This is `PATH_MAX` on Linux.
+pub const DEFAULT_MAX_NAME_LEN: usize = 4096;
+
 // Methods on this type map 1:1 to SQL queries. rusqlite errors are handled and converted to
 // sqlarfs errors.
 #[derive(Debug)]
 pub struct Store<'conn> {
-    inner: InnerTransaction<'conn>,
+    tx: rusqlite::Transaction<'conn>,
+    // The archive table and its side tables.
+    tables: Tables,
+    // The paths of the files that currently have a `File` handle.
+    open_files: RefCell<HashSet<String>>,
+    // The ID of the next spool to create.
+    next_spool: Cell<u64>,
+    // The maximum length of a path, in bytes, for new files.
+    max_name_len: Cell<usize>,
 }
 
 impl<'conn> Store<'conn> {
-    pub fn new(tx: rusqlite::Transaction<'conn>) -> Self {
+    pub fn new(tx: rusqlite::Transaction<'conn>, table: Arc<str>) -> Self {
         Self {
-            inner: InnerTransaction::Transaction(tx),
+            tx,
+            tables: Tables::new(&table),
+            open_files: RefCell::new(HashSet::new()),
+            next_spool: Cell::new(0),
+            max_name_len: Cell::new(DEFAULT_MAX_NAME_LEN),
         }
     }
 
-    pub fn into_tx(self) -> rusqlite::Transaction<'conn> {
-        match self.inner {
-            InnerTransaction::Transaction(tx) => tx,
-            // This will only ever be the case in the middle of a [`Store::exec`] block, where it's
-            // not possible call this method.
-            InnerTransaction::Savepoint(_) => unreachable!(),
+    pub fn is_read_only(&self) -> crate::Result<bool> {
+        Ok(self.tx().is_readonly(rusqlite::DatabaseName::Main)?)
+    }
+
+    pub fn max_name_len(&self) -> usize {
+        self.max_name_len.get()
+    }
+
+    pub fn set_max_name_len(&self, len: usize) {
+        self.max_name_len.set(len);
+    }
+
+    fn check_name_len(&self, path: &str, len: usize) -> crate::Result<()> {
+        if len > self.max_name_len.get() {
+            return Err(crate::Error::NameTooLong { path: path.into() });
         }
+
+        Ok(())
     }
 
-    fn tx(&self) -> &rusqlite::Connection {
-        match &self.inner {
-            InnerTransaction::Transaction(transaction) => transaction,
-            InnerTransaction::Savepoint(savepoint) => savepoint,
+    // Record that there's a handle to the file at `path`, failing if there already is one.
+    //
+    // Two handles to the same file could do things like edit the row while the other has the blob
+    // open, so we only allow one at a time.
+    pub fn claim_path(&self, path: &str) -> crate::Result<()> {
+        if self.open_files.borrow_mut().insert(path.to_owned()) {
+            Ok(())
+        } else {
+            Err(crate::Error::FileAlreadyOpen { path: path.into() })
         }
     }
 
-    fn savepoint(&mut self) -> crate::Result<Savepoint> {
-        Ok(match &mut self.inner {
-            InnerTransaction::Transaction(transaction) => transaction.savepoint()?,
-            InnerTransaction::Savepoint(savepoint) => savepoint.savepoint()?,
-        })
+    // Record that the handle to the file at `path` has been dropped.
+    pub fn release_path(&self, path: &str) {
+        self.open_files.borrow_mut().remove(path);
+    }
+
+    // Return an error if there's a handle to any of the files at `paths`.
+    //
+    // Operations that change files without opening them, like the bulk operations on `Archive`,
+    // need to check this so they don't change a file out from under a handle the user is holding.
+    pub fn check_not_open<'a, I>(&self, paths: I) -> crate::Result<()>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let open_files = self.open_files.borrow();
+
+        if open_files.is_empty() {
+            return Ok(());
+        }
+
+        match paths.into_iter().find(|path| open_files.contains(*path)) {
+            Some(path) => Err(crate::Error::FileInUse { path: path.into() }),
+            None => Ok(()),
+        }
+    }
+
+    // Return an error if there's a handle to the file at `path` or any of its descendants.
+    pub fn check_tree_not_open(&self, path: &str) -> crate::Result<()> {
+        let open_files = self.open_files.borrow();
+
+        let in_use = open_files.iter().find(|open_path| {
+            path.is_empty()
+                || *open_path == path
+                || open_path
+                    .strip_prefix(path)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        });
+
+        match in_use {
+            Some(open_path) => Err(crate::Error::FileInUse {
+                path: open_path.into(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // The path of the database file, or `None` if it's an in-memory or temporary database.
+    pub fn db_path(&self) -> Option<&str> {
+        self.tx().path().filter(|path| !path.is_empty())
+    }
+
+    // The name of the archive table.
+    pub fn table(&self) -> &str {
+        self.tables.sqlar.name()
+    }
+
+    // Rename the archive table to `name`, along with its side tables, indexes, and triggers.
+    pub fn rename_table(&mut self, name: &str) -> crate::Result<()> {
+        validate_table_name(name)?;
+
+        check_is_archive(self.tx(), self.table())?;
+
+        if table_exists(self.tx(), name)? {
+            return Err(crate::Error::SqlarAlreadyExists);
+        }
+
+        let renamed = Tables::new(name);
+
+        let has_dedup = self.table_exists(&self.tables.sqlar_dedup_refs)?;
+        let has_modified = self.table_exists(&self.tables.sqlar_modified)?;
+
+        // Indexes and triggers can't be renamed, and they're named after the archive table, so we
+        // drop them here and create them again from their definitions once the tables are renamed.
+        if has_dedup {
+            self.drop_dedup_objects()?;
+        }
+
+        if has_modified {
+            self.drop_modified_triggers()?;
+        }
+
+        // Renaming a table also updates the foreign keys that reference it.
+        for (table, new_table) in self.tables.all().into_iter().zip(renamed.all()) {
+            if self.table_exists(table)? {
+                self.tx()
+                    .execute_batch(&format!("ALTER TABLE {table} RENAME TO {new_table}"))?;
+            }
+        }
+
+        self.tables = renamed;
+
+        if has_dedup {
+            self.create_dedup_tables()?;
+        }
+
+        if has_modified {
+            self.create_modified_triggers()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn into_tx(self) -> rusqlite::Transaction<'conn> {
+        self.tx
+    }
+
+    fn tx(&self) -> &rusqlite::Connection {
+        &self.tx
     }
 
     // Execute the given function inside of a savepoint.
     //
     // Operations that perform multiple writes to the database should wrap them with this method to
     // ensure atomicity and consistency.
-    pub fn exec<T, F>(&mut self, f: F) -> crate::Result<T>
+    pub fn exec<T, F>(&self, f: F) -> crate::Result<T>
     where
-        F: FnOnce(&mut Store) -> crate::Result<T>,
+        F: FnOnce(&Store) -> crate::Result<T>,
     {
-        let savepoint = self.savepoint()?;
+        let savepoint = SavepointGuard::new(self.tx())?;
 
-        let mut store = Store {
-            inner: InnerTransaction::Savepoint(savepoint),
-        };
+        let result = f(self)?;
 
-        let result = f(&mut store)?;
+        savepoint.release()?;
 
-        let savepoint = match store.inner {
-            InnerTransaction::Savepoint(savepoint) => savepoint,
-            InnerTransaction::Transaction(_) => unreachable!(),
-        };
+        Ok(result)
+    }
+
+    // Start a savepoint that must be ended with `release_savepoint` or `rollback_savepoint`.
+    //
+    // This is for when the caller needs to borrow the archive mutably while the savepoint is open,
+    // so they can't use `exec`.
+    pub fn begin_savepoint(&self) -> crate::Result<()> {
+        self.tx().execute_batch("SAVEPOINT sqlarfs_exec")?;
+        Ok(())
+    }
 
-        savepoint.commit()?;
+    pub fn release_savepoint(&self) -> crate::Result<()> {
+        self.tx().execute_batch("RELEASE sqlarfs_exec")?;
+        Ok(())
+    }
 
-        Ok(result)
+    pub fn rollback_savepoint(&self) -> crate::Result<()> {
+        self.tx()
+            .execute_batch("ROLLBACK TO sqlarfs_exec; RELEASE sqlarfs_exec")?;
+        Ok(())
     }
 
     pub fn create_table(&self, fail_if_exists: bool) -> crate::Result<()> {
+        let sqlar = &self.tables.sqlar;
+        let if_not_exists = if fail_if_exists { "" } else { "IF NOT EXISTS" };
+
         self.tx()
             .execute(
-                if fail_if_exists {
-                    "
-                CREATE TABLE sqlar(
-                    name TEXT PRIMARY KEY,
-                    mode INT,
-                    mtime INT,
-                    sz INT,
-                    data BLOB
-                );
-                "
-                } else {
+                &format!(
                     "
-                CREATE TABLE IF NOT EXISTS sqlar(
+                CREATE TABLE {if_not_exists} {sqlar}(
                     name TEXT PRIMARY KEY,
                     mode INT,
                     mtime INT,
@@ -133,7 +745,7 @@ impl<'conn> Store<'conn> {
                     data BLOB
                 );
                 "
-                },
+                ),
                 (),
             )
             .map_err(|err| {
@@ -160,10 +772,14 @@ impl<'conn> Store<'conn> {
         mtime: Option<SystemTime>,
         symlink_target: Option<&str>,
     ) -> crate::Result<()> {
+        let sqlar = &self.tables.sqlar;
+
         if symlink_target.is_some() && kind != FileType::Symlink {
             panic!("Tried to create a non-symlink with a symlink target. This is a bug.");
         }
 
+        self.check_name_len(path, path.len())?;
+
         let unix_mtime = mtime
             .map(|mtime| -> crate::Result<_> {
                 Ok(mtime
@@ -199,10 +815,12 @@ impl<'conn> Store<'conn> {
             )),
         };
 
-        let result = self.tx().execute(
-            "INSERT INTO sqlar (name, mode, mtime, sz, data) VALUES (?1, ?2, ?3, ?4, ?5)",
-            (path, mode_bits, unix_mtime, initial_size, initial_data),
-        );
+        let result = self
+            .tx()
+            .prepare_cached(&format!(
+                "INSERT INTO {sqlar} (name, mode, mtime, sz, data) VALUES (?1, ?2, ?3, ?4, ?5)"
+            ))?
+            .execute((path, mode_bits, unix_mtime, initial_size, initial_data));
 
         match result {
             Ok(_) => Ok(()),
@@ -215,49 +833,78 @@ impl<'conn> Store<'conn> {
         }
     }
 
-    pub fn delete_file(&self, path: &str) -> crate::Result<()> {
-        // Deleting files must be recursive so that the archive doesn't end up with orphan files.
-        let num_updated = self.tx().execute(
-            "DELETE FROM sqlar WHERE name = ?1 OR name GLOB ?1 || '/?*'",
-            (path,),
-        )?;
+    // Insert the regular files in `files` along with their contents in a single statement.
+    pub fn insert_files(&self, files: &[NewFile]) -> crate::Result<()> {
+        let sqlar = &self.tables.sqlar;
 
-        if num_updated == 0 {
-            return Err(crate::Error::FileNotFound { path: path.into() });
+        let mut mode_bits = Vec::with_capacity(files.len());
+        let mut mtime_secs = Vec::with_capacity(files.len());
+
+        for file in files {
+            self.check_name_len(&file.path, file.path.len())?;
+            mode_bits.push(file.mode.to_file_mode());
+            mtime_secs.push(file.mtime.map(unix_secs).transpose()?);
         }
 
-        Ok(())
-    }
+        let mut params: Vec<&dyn rusqlite::ToSql> = Vec::with_capacity(files.len() * 5);
 
-    pub fn open_blob(&self, path: &str, read_only: bool) -> crate::Result<FileBlob> {
-        let row = self
+        for (i, file) in files.iter().enumerate() {
+            params.extend([
+                &file.path as &dyn rusqlite::ToSql,
+                &mode_bits[i],
+                &mtime_secs[i],
+                &file.size,
+                &file.data,
+            ]);
+        }
+
+        let values = vec!["(?, ?, ?, ?, ?)"; files.len()].join(", ");
+
+        let result = self
             .tx()
-            .query_row(
-                "SELECT rowid, sz FROM sqlar WHERE name = ?1;",
-                (path,),
-                |row| Ok((row.get(0)?, row.get(1)?)),
-            )
-            .optional()?;
+            .prepare_cached(&format!(
+                "INSERT INTO {sqlar} (name, mode, mtime, sz, data) VALUES {values}"
+            ))?
+            .execute(params.as_slice());
 
-        match row {
-            Some((row_id, original_size)) => Ok(FileBlob {
-                blob: self.tx().blob_open(
-                    rusqlite::DatabaseName::Main,
-                    "sqlar",
-                    "data",
-                    row_id,
-                    read_only,
-                )?,
-                original_size,
-            }),
-            None => Err(crate::Error::FileNotFound { path: path.into() }),
+        match result {
+            Ok(_) => Ok(()),
+            Err(err)
+                if err.sqlite_error_code() == Some(rusqlite::ErrorCode::ConstraintViolation) =>
+            {
+                if let [file] = files {
+                    return Err(crate::Error::FileAlreadyExists {
+                        path: file.path.as_str().into(),
+                    });
+                }
+
+                // The error doesn't tell us which file already exists, but the statement didn't
+                // insert any of them, so we insert them one at a time to find out.
+                for file in files {
+                    self.insert_files(std::slice::from_ref(file))?;
+                }
+
+                Ok(())
+            }
+            Err(err) => Err(err.into()),
         }
     }
 
-    pub fn allocate_blob(&self, path: &str, len: u64) -> crate::Result<()> {
+    pub fn delete_file(&self, path: &str, force: bool) -> crate::Result<()> {
+        let sqlar = &self.tables.sqlar;
+
+        if !force {
+            if let Some(pinned) = self.find_pinned(path)? {
+                return Err(crate::Error::FilePinned {
+                    path: pinned.into(),
+                });
+            }
+        }
+
+        // Deleting files must be recursive so that the archive doesn't end up with orphan files.
         let num_updated = self.tx().execute(
-            "UPDATE sqlar SET data = zeroblob(?1) WHERE name = ?2",
-            (len, path),
+            &format!("DELETE FROM {sqlar} WHERE name = ?1 OR name GLOB ?1 || '/?*'"),
+            (path,),
         )?;
 
         if num_updated == 0 {
@@ -267,108 +914,1851 @@ impl<'conn> Store<'conn> {
         Ok(())
     }
 
-    pub fn store_blob(&self, path: &str, bytes: &[u8]) -> crate::Result<()> {
-        let num_updated = self
-            .tx()
-            .execute("UPDATE sqlar SET data = ?1 WHERE name = ?2", (bytes, path))?;
-
-        if num_updated == 0 {
-            return Err(crate::Error::FileNotFound { path: path.into() });
-        }
+    pub fn delete_files(&self, opts: &ListOptions) -> crate::Result<u64> {
+        let Tables {
+            sqlar, sqlar_pins, ..
+        } = &self.tables;
 
-        Ok(())
-    }
+        let params = list_filter_params(opts)?;
+        let params = params.iter().map(AsRef::as_ref).collect::<Vec<_>>();
+        let list_filter = list_filter(sqlar);
 
-    pub fn read_metadata(&self, path: &str) -> crate::Result<FileMetadata> {
-        self.tx()
-            .query_row(
-                "
+        let matched = format!(
+            "
+            WITH matched AS (
                 SELECT
-                    mode,
-                    mtime,
-                    sz,
-                    iif(sz < 0, data, NULL) AS target,
-                    data IS NULL AS is_dir
+                    s.name
                 FROM
-                    sqlar
+                    {sqlar} AS s
                 WHERE
-                    name = ?1;
-                ",
-                (path,),
-                |row| {
-                    let mode = row.get::<_, Option<u32>>(0)?.map(FileMode::from_mode);
-                    let mtime = row
-                        .get::<_, Option<u64>>(1)?
-                        .map(|mtime_secs| UNIX_EPOCH + Duration::from_secs(mtime_secs));
-                    let size: i64 = row.get(2)?;
-                    // When the `data` column contains a symlink target, its type is `TEXT`, not
-                    // `BLOB`. Remember that columns in SQLite are dynamically typed.
-                    let symlink_target: Option<String> = row.get(3)?;
-                    let is_dir: bool = row.get(4)?;
-
-                    // We ignore the file mode in the database when determining the file type.
-                    Ok(if let Some(target) = symlink_target {
-                        FileMetadata::Symlink {
-                            mtime,
-                            target: PathBuf::from(target),
-                        }
-                    } else if is_dir {
-                        FileMetadata::Dir { mode, mtime }
-                    } else {
-                        FileMetadata::File {
-                            mode,
-                            mtime,
-                            size: size.try_into().expect("The file size in the database was negative, but we should have already checked for this. This is a bug."),
-                        }
-                    })
-                },
+                    {list_filter}
             )
-            .optional()?
-            .ok_or(crate::Error::FileNotFound { path: path.into() })
-    }
+            "
+        );
 
-    pub fn set_mode(&self, path: &str, mode: Option<FileMode>) -> crate::Result<()> {
-        // If the file is a symlink, this is a no-op. Symlinks always have 777 permissions.
-        let num_updated = self.tx().execute(
-            "UPDATE sqlar SET mode = iif(mode & ?1 = ?2, mode, mode & ?1 | ?3) WHERE name = ?4",
-            (TYPE_MASK, SYMLINK_MODE, mode.map(|mode| mode.bits()), path),
-        )?;
+        // Like with `Store::delete_file`, pinned files can't be deleted, and neither can
+        // directories that contain pinned files.
+        if self.table_exists(sqlar_pins)? {
+            let pinned = self
+                .tx()
+                .query_row(
+                    &format!(
+                        "
+                        {matched}
+                        SELECT
+                            p.name
+                        FROM
+                            {sqlar_pins} AS p
+                            JOIN matched AS m ON p.name = m.name OR p.name GLOB m.name || '/?*'
+                        ORDER BY
+                            p.name
+                        LIMIT 1
+                        "
+                    ),
+                    params.as_slice(),
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()?;
 
-        if num_updated == 0 {
-            return Err(crate::Error::FileNotFound { path: path.into() });
+            if let Some(path) = pinned {
+                return Err(crate::Error::FilePinned { path: path.into() });
+            }
         }
 
-        Ok(())
-    }
+        // Like with `Store::delete_file`, we need to delete the descendants of any directories
+        // that match so that the archive doesn't end up with orphan files.
+        let mut stmt = self.tx().prepare(&format!(
+            "
+            {matched}
+            DELETE FROM
+                {sqlar}
+            WHERE
+                name IN (SELECT name FROM matched)
+                OR EXISTS (
+                    SELECT 1 FROM matched AS m WHERE {sqlar}.name GLOB m.name || '/?*'
+                )
+            RETURNING
+                name
+            "
+        ))?;
 
-    pub fn set_mtime(&self, path: &str, mtime: Option<SystemTime>) -> crate::Result<()> {
-        let mtime_secs = mtime
-            .map(|mtime| -> crate::Result<_> {
-                Ok(mtime
-                    .duration_since(time::UNIX_EPOCH)
-                    .map_err(|err| crate::Error::InvalidArgs {
-                        reason: err.to_string(),
-                    })?
-                    .as_secs())
-            })
-            .transpose()?;
+        let deleted = stmt
+            .query_map(params.as_slice(), |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
 
-        let num_updated = self.tx().execute(
-            "UPDATE sqlar SET mtime = ?1 WHERE name = ?2",
-            (mtime_secs, path),
-        )?;
+        // The caller rolls back the deletion if any of these files are open.
+        self.check_not_open(deleted.iter().map(String::as_str))?;
 
-        if num_updated == 0 {
-            return Err(crate::Error::FileNotFound { path: path.into() });
-        }
+        Ok(u64_from_usize(deleted.len()))
+    }
+
+    pub fn prune_files(&self, policy: &RetentionPolicy) -> crate::Result<u64> {
+        let sqlar = &self.tables.sqlar;
+
+        let ancestor = policy.ancestor.as_ref().map(|ancestor| {
+            ancestor
+                .to_string_lossy()
+                .trim_end_matches(std::path::MAIN_SEPARATOR)
+                .to_string()
+        });
+
+        let type_mode = match policy.file_type {
+            FileType::File => FILE_MODE,
+            FileType::Dir => DIR_MODE,
+            FileType::Symlink => SYMLINK_MODE,
+        };
+
+        let older_than = policy.older_than.map(unix_secs).transpose()?;
+
+        let pin_filter = if policy.force {
+            String::new()
+        } else {
+            self.pin_filter("candidates.name")?
+        };
+
+        // The parent directory of each file is computed by trimming everything after the last
+        // path separator. Files are ranked by mtime within their parent directory so we can keep
+        // the newest N in each.
+        let mut stmt = self.tx().prepare(&format!(
+            "
+            WITH candidates AS (
+                SELECT
+                    name,
+                    mtime,
+                    row_number() OVER (
+                        PARTITION BY rtrim(name, replace(name, '/', ''))
+                        ORDER BY mtime DESC, name DESC
+                    ) AS recency
+                FROM
+                    {sqlar}
+                WHERE
+                    mtime IS NOT NULL
+                    AND (mode & ?1) = ?2
+                    AND iif(?3 IS NULL OR ?3 = '', true, name GLOB ?3 || '/?*')
+            ),
+            pruned AS (
+                SELECT
+                    name
+                FROM
+                    candidates
+                WHERE
+                    (?4 IS NOT NULL OR ?5 IS NOT NULL)
+                    AND iif(?4 IS NULL, true, recency > ?4)
+                    AND iif(?5 IS NULL, true, mtime < ?5)
+                    {pin_filter}
+            )
+            DELETE FROM
+                {sqlar}
+            WHERE
+                name IN (SELECT name FROM pruned)
+                OR EXISTS (
+                    SELECT 1 FROM pruned AS p WHERE {sqlar}.name GLOB p.name || '/?*'
+                )
+            RETURNING
+                name
+            "
+        ))?;
+
+        let deleted = stmt
+            .query_map(
+                (
+                    TYPE_MASK,
+                    type_mode,
+                    ancestor,
+                    policy.keep_newest,
+                    older_than,
+                ),
+                |row| row.get(0),
+            )?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+
+        // The caller rolls back the deletion if any of these files are open.
+        self.check_not_open(deleted.iter().map(String::as_str))?;
+
+        Ok(u64_from_usize(deleted.len()))
+    }
+
+    pub fn rename_files(&self, from: &str, to: &str) -> crate::Result<u64> {
+        let sqlar = &self.tables.sqlar;
+
+        // Renaming a directory changes the paths of all its descendants, so we need to check the
+        // longest one.
+        let longest_len: Option<usize> = self.tx().query_row(
+            &format!("SELECT max(length(CAST(name AS BLOB))) FROM {sqlar} WHERE name = ?1 OR name GLOB ?1 || '/?*'"),
+            (from,),
+            |row| row.get(0),
+        )?;
+
+        if let Some(longest_len) = longest_len {
+            self.check_name_len(to, longest_len - from.len() + to.len())?;
+        }
+
+        let num_updated = self.tx().execute(
+            &format!(
+                "
+            UPDATE
+                {sqlar}
+            SET
+                name = ?2 || substr(name, length(?1) + 1)
+            WHERE
+                name = ?1 OR name GLOB ?1 || '/?*'
+            "
+            ),
+            (from, to),
+        )?;
+
+        if num_updated == 0 {
+            return Err(crate::Error::FileNotFound { path: from.into() });
+        }
+
+        Ok(u64_from_usize(num_updated))
+    }
+
+    // Copy the file at `from` and all its descendants to `to`, along with their user-defined
+    // metadata and external links. The file data is copied within the database.
+    pub fn copy_files(&self, from: &str, to: &str) -> crate::Result<u64> {
+        let Tables {
+            sqlar,
+            sqlar_meta,
+            sqlar_external,
+            sqlar_checksums,
+            sqlar_dedup_refs,
+            ..
+        } = &self.tables;
+
+        let longest_len: Option<usize> = self.tx().query_row(
+            &format!("SELECT max(length(CAST(name AS BLOB))) FROM {sqlar} WHERE name = ?1 OR name GLOB ?1 || '/?*'"),
+            (from,),
+            |row| row.get(0),
+        )?;
+
+        if let Some(longest_len) = longest_len {
+            self.check_name_len(to, longest_len - from.len() + to.len())?;
+        }
+
+        let num_inserted = self.tx().execute(
+            &format!(
+                "
+            INSERT INTO {sqlar} (name, mode, mtime, sz, data)
+            SELECT
+                ?2 || substr(name, length(?1) + 1), mode, mtime, sz, data
+            FROM
+                {sqlar}
+            WHERE
+                name = ?1 OR name GLOB ?1 || '/?*'
+            "
+            ),
+            (from, to),
+        )?;
+
+        if num_inserted == 0 {
+            return Err(crate::Error::FileNotFound { path: from.into() });
+        }
+
+        if self.table_exists(sqlar_meta)? {
+            self.tx().execute(
+                &format!(
+                    "
+                INSERT INTO {sqlar_meta} (name, key, value)
+                SELECT
+                    ?2 || substr(name, length(?1) + 1), key, value
+                FROM
+                    {sqlar_meta}
+                WHERE
+                    name = ?1 OR name GLOB ?1 || '/?*'
+                "
+                ),
+                (from, to),
+            )?;
+        }
+
+        if self.table_exists(sqlar_external)? {
+            self.tx().execute(
+                &format!(
+                    "
+                INSERT INTO {sqlar_external} (name, archive, target)
+                SELECT
+                    ?2 || substr(name, length(?1) + 1), archive, target
+                FROM
+                    {sqlar_external}
+                WHERE
+                    name = ?1 OR name GLOB ?1 || '/?*'
+                "
+                ),
+                (from, to),
+            )?;
+        }
+
+        if self.table_exists(sqlar_dedup_refs)? {
+            self.tx().execute(
+                &format!(
+                    "
+                INSERT INTO {sqlar_dedup_refs} (name, hash)
+                SELECT
+                    ?2 || substr(name, length(?1) + 1), hash
+                FROM
+                    {sqlar_dedup_refs}
+                WHERE
+                    name = ?1 OR name GLOB ?1 || '/?*'
+                "
+                ),
+                (from, to),
+            )?;
+        }
+
+        if self.table_exists(sqlar_checksums)? {
+            self.tx().execute(
+                &format!(
+                    "
+                INSERT INTO {sqlar_checksums} (name, algorithm, digest)
+                SELECT
+                    ?2 || substr(name, length(?1) + 1), algorithm, digest
+                FROM
+                    {sqlar_checksums}
+                WHERE
+                    name = ?1 OR name GLOB ?1 || '/?*'
+                "
+                ),
+                (from, to),
+            )?;
+        }
+
+        Ok(u64_from_usize(num_inserted))
+    }
+
+    // Return the rows of the file at `path` and all its descendants, sorted by path so parents
+    // come before their children. The contents of regular files are left out so they can be
+    // streamed separately.
+    pub fn raw_files(&self, path: &str) -> crate::Result<Vec<RawFile>> {
+        let sqlar = &self.tables.sqlar;
+
+        let (source, data) = self.stored_data_source()?;
+
+        let mut stmt = self.tx().prepare(&format!(
+            "
+            SELECT
+                {sqlar}.name,
+                {sqlar}.mode,
+                {sqlar}.mtime,
+                {sqlar}.sz,
+                iif(typeof({data}) = 'blob', NULL, {data}),
+                iif(typeof({data}) = 'blob', length({data}), NULL)
+            FROM
+                {source}
+            WHERE
+                {sqlar}.name = ?1 OR {sqlar}.name GLOB ?1 || '/?*'
+            ORDER BY
+                {sqlar}.name
+            "
+        ))?;
+
+        let rows = stmt.query_map((path,), |row| {
+            let blob_len: Option<u64> = row.get(5)?;
+
+            Ok(RawFile {
+                name: row.get(0)?,
+                mode: row.get(1)?,
+                mtime: row.get(2)?,
+                size: row.get(3)?,
+                data: match blob_len {
+                    Some(len) => RawData::Blob(len),
+                    None => RawData::Value(row.get(4)?),
+                },
+            })
+        })?;
+
+        let files = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+
+        if files.is_empty() {
+            return Err(crate::Error::FileNotFound { path: path.into() });
+        }
+
+        Ok(files)
+    }
+
+    // Insert a row for `file` at `path`. If it's a regular file, its contents are zeroed and need
+    // to be written separately.
+    pub fn insert_raw_file(&self, path: &str, file: &RawFile) -> crate::Result<()> {
+        let sqlar = &self.tables.sqlar;
+
+        self.check_name_len(path, path.len())?;
+
+        let result = match &file.data {
+            RawData::Blob(len) => self
+                .tx()
+                .prepare_cached(
+                    &format!("INSERT INTO {sqlar} (name, mode, mtime, sz, data) VALUES (?1, ?2, ?3, ?4, zeroblob(?5))"),
+                )?
+                .execute((path, file.mode, file.mtime, file.size, len)),
+            RawData::Value(value) => self
+                .tx()
+                .prepare_cached(
+                    &format!("INSERT INTO {sqlar} (name, mode, mtime, sz, data) VALUES (?1, ?2, ?3, ?4, ?5)"),
+                )?
+                .execute((path, file.mode, file.mtime, file.size, value)),
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(err)
+                if err.sqlite_error_code() == Some(rusqlite::ErrorCode::ConstraintViolation) =>
+            {
+                Err(crate::Error::FileAlreadyExists { path: path.into() })
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    // Return every key-value pair of user-defined metadata for the file at `path`, sorted by key.
+    pub fn list_meta(&self, path: &str) -> crate::Result<Vec<(String, String)>> {
+        let sqlar_meta = &self.tables.sqlar_meta;
+
+        if !self.table_exists(sqlar_meta)? {
+            return Ok(Vec::new());
+        }
+
+        let mut stmt = self.tx().prepare_cached(&format!(
+            "SELECT key, value FROM {sqlar_meta} WHERE name = ?1 ORDER BY key"
+        ))?;
+
+        let pairs = stmt
+            .query_map((path,), |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(pairs)
+    }
+
+    // Delete the files (and their descendants) that would be overwritten by merging the tree at
+    // `from` into the tree at `to`. Directories that exist in both trees are left alone.
+    pub fn delete_merge_conflicts(&self, from: &str, to: &str) -> crate::Result<()> {
+        let Tables {
+            sqlar, sqlar_pins, ..
+        } = &self.tables;
+
+        let conflicts = format!(
+            "
+            WITH conflicts AS (
+                SELECT
+                    d.name
+                FROM
+                    {sqlar} AS s
+                JOIN
+                    {sqlar} AS d ON d.name = ?2 || substr(s.name, length(?1) + 1)
+                WHERE
+                    (s.name = ?1 OR s.name GLOB ?1 || '/?*')
+                    AND NOT ((s.mode & ?3) = ?4 AND (d.mode & ?3) = ?4)
+            )
+            "
+        );
+
+        let params = (from, to, TYPE_MASK, DIR_MODE);
+
+        // Like with `Store::delete_file`, pinned files can't be overwritten, and neither can
+        // directories that contain pinned files.
+        if self.table_exists(sqlar_pins)? {
+            let pinned = self
+                .tx()
+                .query_row(
+                    &format!(
+                        "
+                        {conflicts}
+                        SELECT
+                            p.name
+                        FROM
+                            {sqlar_pins} AS p
+                            JOIN conflicts AS c ON p.name = c.name OR p.name GLOB c.name || '/?*'
+                        ORDER BY
+                            p.name
+                        LIMIT 1
+                        "
+                    ),
+                    params,
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()?;
+
+            if let Some(path) = pinned {
+                return Err(crate::Error::FilePinned { path: path.into() });
+            }
+        }
+
+        self.tx().execute(
+            &format!(
+                "
+            {conflicts}
+            DELETE FROM
+                {sqlar}
+            WHERE
+                name IN (SELECT name FROM conflicts)
+                OR EXISTS (
+                    SELECT 1 FROM conflicts AS c WHERE {sqlar}.name GLOB c.name || '/?*'
+                )
+            "
+            ),
+            params,
+        )?;
+
+        Ok(())
+    }
+
+    // Delete the directories in the tree at `from` that already exist in the tree at `to`, without
+    // deleting their descendants.
+    pub fn delete_merged_dirs(&self, from: &str, to: &str) -> crate::Result<()> {
+        let sqlar = &self.tables.sqlar;
+
+        self.tx().execute(
+            &format!(
+                "
+            DELETE FROM
+                {sqlar}
+            WHERE
+                (name = ?1 OR name GLOB ?1 || '/?*')
+                AND (mode & ?3) = ?4
+                AND EXISTS (
+                    SELECT
+                        1
+                    FROM
+                        {sqlar} AS d
+                    WHERE
+                        d.name = ?2 || substr({sqlar}.name, length(?1) + 1)
+                        AND (d.mode & ?3) = ?4
+                )
+            "
+            ),
+            (from, to, TYPE_MASK, DIR_MODE),
+        )?;
+
+        Ok(())
+    }
+
+    fn table_exists(&self, table: &Ident) -> crate::Result<bool> {
+        table_exists(self.tx(), table.name())
+    }
+
+    // This table is created lazily so that archives which don't use this feature are left
+    // untouched.
+    fn create_meta_table(&self) -> crate::Result<()> {
+        let Tables {
+            sqlar, sqlar_meta, ..
+        } = &self.tables;
+
+        self.tx().execute(
+            &format!(
+                "
+            CREATE TABLE IF NOT EXISTS {sqlar_meta}(
+                name TEXT NOT NULL REFERENCES {sqlar}(name) ON DELETE CASCADE ON UPDATE CASCADE,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (name, key)
+            );
+            "
+            ),
+            (),
+        )?;
+
+        Ok(())
+    }
+
+    pub fn set_meta(&self, path: &str, key: &str, value: &str) -> crate::Result<()> {
+        let sqlar_meta = &self.tables.sqlar_meta;
+
+        self.create_meta_table()?;
+
+        self.tx()
+            .execute(
+                &format!(
+                    "
+                INSERT INTO {sqlar_meta} (name, key, value)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT (name, key) DO UPDATE SET value = excluded.value
+                "
+                ),
+                (path, key, value),
+            )
+            .map_err(|err| match err.sqlite_error_code() {
+                Some(rusqlite::ErrorCode::ConstraintViolation) => {
+                    crate::Error::FileNotFound { path: path.into() }
+                }
+                _ => err.into(),
+            })?;
+
+        Ok(())
+    }
+
+    pub fn get_meta(&self, path: &str, key: &str) -> crate::Result<Option<String>> {
+        let sqlar_meta = &self.tables.sqlar_meta;
+
+        if !self.table_exists(sqlar_meta)? {
+            return Ok(None);
+        }
+
+        Ok(self
+            .tx()
+            .query_row(
+                &format!("SELECT value FROM {sqlar_meta} WHERE name = ?1 AND key = ?2"),
+                (path, key),
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub fn delete_meta(&self, path: &str, key: &str) -> crate::Result<bool> {
+        let sqlar_meta = &self.tables.sqlar_meta;
+
+        if !self.table_exists(sqlar_meta)? {
+            return Ok(false);
+        }
+
+        let num_deleted = self.tx().execute(
+            &format!("DELETE FROM {sqlar_meta} WHERE name = ?1 AND key = ?2"),
+            (path, key),
+        )?;
+
+        Ok(num_deleted > 0)
+    }
+
+    // This table is created lazily so that archives which don't use this feature are left
+    // untouched. It stores the Zstandard dictionaries that files in the archive were compressed
+    // with, keyed by the dictionary ID that's recorded in each compressed frame. Only the active
+    // dictionary is used to compress new files, but older ones are kept so that files compressed
+    // with them can still be read.
+    #[cfg(feature = "zstd")]
+    fn create_dictionary_table(&self) -> crate::Result<()> {
+        let sqlar_zstd_dicts = &self.tables.sqlar_zstd_dicts;
+
+        self.tx().execute(
+            &format!(
+                "
+            CREATE TABLE IF NOT EXISTS {sqlar_zstd_dicts}(
+                id INTEGER PRIMARY KEY NOT NULL,
+                dict BLOB NOT NULL,
+                active INTEGER NOT NULL
+            );
+            "
+            ),
+            (),
+        )?;
+
+        Ok(())
+    }
+
+    // Store a dictionary. If `active` is true, it replaces the active dictionary. Otherwise, it's
+    // only stored if there isn't already a dictionary with the same ID.
+    #[cfg(feature = "zstd")]
+    pub fn insert_dictionary(&self, id: u32, dict: &[u8], active: bool) -> crate::Result<()> {
+        let sqlar_zstd_dicts = &self.tables.sqlar_zstd_dicts;
+
+        self.create_dictionary_table()?;
+
+        if active {
+            self.tx()
+                .execute(&format!("UPDATE {sqlar_zstd_dicts} SET active = false"), ())?;
+        }
+
+        self.tx().execute(
+            &format!(
+                "
+            INSERT INTO {sqlar_zstd_dicts} (id, dict, active)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT (id) DO UPDATE SET active = excluded.active OR active
+            "
+            ),
+            (id, dict, active),
+        )?;
+
+        Ok(())
+    }
+
+    // The dictionary to compress new files with, if one has been trained.
+    #[cfg(feature = "zstd")]
+    pub fn active_dictionary(&self) -> crate::Result<Option<Vec<u8>>> {
+        let sqlar_zstd_dicts = &self.tables.sqlar_zstd_dicts;
+
+        if !self.table_exists(sqlar_zstd_dicts)? {
+            return Ok(None);
+        }
+
+        Ok(self
+            .tx()
+            .prepare_cached(&format!("SELECT dict FROM {sqlar_zstd_dicts} WHERE active"))?
+            .query_row((), |row| row.get(0))
+            .optional()?)
+    }
+
+    #[cfg(feature = "zstd")]
+    pub fn dictionary(&self, id: u32) -> crate::Result<Option<Vec<u8>>> {
+        let sqlar_zstd_dicts = &self.tables.sqlar_zstd_dicts;
+
+        if !self.table_exists(sqlar_zstd_dicts)? {
+            return Ok(None);
+        }
+
+        Ok(self
+            .tx()
+            .prepare_cached(&format!(
+                "SELECT dict FROM {sqlar_zstd_dicts} WHERE id = ?1"
+            ))?
+            .query_row((id,), |row| row.get(0))
+            .optional()?)
+    }
+
+    // Return every stored dictionary along with its ID.
+    #[cfg(feature = "zstd")]
+    pub fn dictionaries(&self) -> crate::Result<Vec<(u32, Vec<u8>)>> {
+        let sqlar_zstd_dicts = &self.tables.sqlar_zstd_dicts;
+
+        if !self.table_exists(sqlar_zstd_dicts)? {
+            return Ok(Vec::new());
+        }
+
+        let mut stmt = self.tx().prepare_cached(&format!(
+            "SELECT id, dict FROM {sqlar_zstd_dicts} ORDER BY id"
+        ))?;
+
+        let dicts = stmt
+            .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(dicts)
+    }
+
+    // This table is created lazily so that archives which don't use this feature are left
+    // untouched. It stores the raw bytes of file names that weren't valid Unicode, keyed by their
+    // escaped name.
+    fn create_raw_name_table(&self) -> crate::Result<()> {
+        let Tables {
+            sqlar,
+            sqlar_raw_names,
+            ..
+        } = &self.tables;
+
+        self.tx().execute(
+            &format!("
+            CREATE TABLE IF NOT EXISTS {sqlar_raw_names}(
+                name TEXT PRIMARY KEY NOT NULL REFERENCES {sqlar}(name) ON DELETE CASCADE ON UPDATE CASCADE,
+                raw BLOB NOT NULL
+            );
+            "),
+            (),
+        )?;
+
+        Ok(())
+    }
+
+    pub fn set_raw_name(&self, path: &str, raw: &[u8]) -> crate::Result<()> {
+        let sqlar_raw_names = &self.tables.sqlar_raw_names;
+
+        self.create_raw_name_table()?;
+
+        self.tx()
+            .execute(
+                &format!(
+                    "
+                INSERT INTO {sqlar_raw_names} (name, raw)
+                VALUES (?1, ?2)
+                ON CONFLICT (name) DO UPDATE SET raw = excluded.raw
+                "
+                ),
+                (path, raw),
+            )
+            .map_err(|err| match err.sqlite_error_code() {
+                Some(rusqlite::ErrorCode::ConstraintViolation) => {
+                    crate::Error::FileNotFound { path: path.into() }
+                }
+                _ => err.into(),
+            })?;
+
+        Ok(())
+    }
+
+    pub fn has_raw_names(&self) -> crate::Result<bool> {
+        let sqlar_raw_names = &self.tables.sqlar_raw_names;
+
+        self.table_exists(sqlar_raw_names)
+    }
+
+    pub fn raw_name(&self, path: &str) -> crate::Result<Option<Vec<u8>>> {
+        let sqlar_raw_names = &self.tables.sqlar_raw_names;
+
+        if !self.has_raw_names()? {
+            return Ok(None);
+        }
+
+        Ok(self
+            .tx()
+            .prepare_cached(&format!(
+                "SELECT raw FROM {sqlar_raw_names} WHERE name = ?1"
+            ))?
+            .query_row((path,), |row| row.get(0))
+            .optional()?)
+    }
+
+    // This table is created lazily so that archives which don't use this feature are left
+    // untouched.
+    fn create_pin_table(&self) -> crate::Result<()> {
+        let Tables {
+            sqlar, sqlar_pins, ..
+        } = &self.tables;
+
+        self.tx().execute(
+            &format!("
+            CREATE TABLE IF NOT EXISTS {sqlar_pins}(
+                name TEXT PRIMARY KEY NOT NULL REFERENCES {sqlar}(name) ON DELETE CASCADE ON UPDATE CASCADE
+            );
+            "),
+            (),
+        )?;
+
+        Ok(())
+    }
+
+    pub fn pin(&self, path: &str) -> crate::Result<()> {
+        let sqlar_pins = &self.tables.sqlar_pins;
+
+        self.create_pin_table()?;
+
+        self.tx()
+            .execute(
+                &format!(
+                    "INSERT INTO {sqlar_pins} (name) VALUES (?1) ON CONFLICT (name) DO NOTHING"
+                ),
+                (path,),
+            )
+            .map_err(|err| match err.sqlite_error_code() {
+                Some(rusqlite::ErrorCode::ConstraintViolation) => {
+                    crate::Error::FileNotFound { path: path.into() }
+                }
+                _ => err.into(),
+            })?;
+
+        Ok(())
+    }
+
+    pub fn unpin(&self, path: &str) -> crate::Result<bool> {
+        let sqlar_pins = &self.tables.sqlar_pins;
+
+        if !self.table_exists(sqlar_pins)? {
+            return Ok(false);
+        }
+
+        let num_deleted = self.tx().execute(
+            &format!("DELETE FROM {sqlar_pins} WHERE name = ?1"),
+            (path,),
+        )?;
+
+        Ok(num_deleted > 0)
+    }
+
+    pub fn is_pinned(&self, path: &str) -> crate::Result<bool> {
+        let sqlar_pins = &self.tables.sqlar_pins;
+
+        if !self.table_exists(sqlar_pins)? {
+            return Ok(false);
+        }
+
+        Ok(self
+            .tx()
+            .query_row(
+                &format!("SELECT 1 FROM {sqlar_pins} WHERE name = ?1"),
+                (path,),
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+
+    // Find a pinned file at `path` or among its descendants.
+    fn find_pinned(&self, path: &str) -> crate::Result<Option<String>> {
+        let sqlar_pins = &self.tables.sqlar_pins;
+
+        if !self.table_exists(sqlar_pins)? {
+            return Ok(None);
+        }
+
+        Ok(self
+            .tx()
+            .query_row(
+                &format!("SELECT name FROM {sqlar_pins} WHERE name = ?1 OR name GLOB ?1 || '/?*' ORDER BY name LIMIT 1"),
+                (path,),
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    // A condition to add to a `WHERE` clause to exclude files that are pinned or that have pinned
+    // descendants. `name_column` is the column holding the path of the file.
+    fn pin_filter(&self, name_column: &str) -> crate::Result<String> {
+        let sqlar_pins = &self.tables.sqlar_pins;
+
+        if !self.table_exists(sqlar_pins)? {
+            return Ok(String::new());
+        }
+
+        Ok(format!(
+            "AND NOT EXISTS (
+                SELECT 1 FROM {sqlar_pins} AS pin
+                WHERE pin.name = {name_column} OR pin.name GLOB {name_column} || '/?*'
+            )"
+        ))
+    }
+
+    // This table is created lazily so that archives which don't use this feature are left
+    // untouched.
+    fn create_external_table(&self) -> crate::Result<()> {
+        let Tables {
+            sqlar,
+            sqlar_external,
+            ..
+        } = &self.tables;
+
+        self.tx().execute(
+            &format!("
+            CREATE TABLE IF NOT EXISTS {sqlar_external}(
+                name TEXT PRIMARY KEY NOT NULL REFERENCES {sqlar}(name) ON DELETE CASCADE ON UPDATE CASCADE,
+                archive TEXT NOT NULL,
+                target TEXT NOT NULL
+            );
+            "),
+            (),
+        )?;
+
+        Ok(())
+    }
+
+    pub fn set_external_link(&self, path: &str, archive: &str, target: &str) -> crate::Result<()> {
+        let sqlar_external = &self.tables.sqlar_external;
+
+        self.create_external_table()?;
+
+        self.tx().execute(
+                &format!("
+                INSERT INTO {sqlar_external} (name, archive, target)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT (name) DO UPDATE SET archive = excluded.archive, target = excluded.target
+                "),
+                (path, archive, target),
+            )
+            .map_err(|err| match err.sqlite_error_code() {
+                Some(rusqlite::ErrorCode::ConstraintViolation) => {
+                    crate::Error::FileNotFound { path: path.into() }
+                }
+                _ => err.into(),
+            })?;
+
+        Ok(())
+    }
+
+    pub fn get_external_link(&self, path: &str) -> crate::Result<Option<ExternalLink>> {
+        let sqlar_external = &self.tables.sqlar_external;
+
+        if !self.table_exists(sqlar_external)? {
+            return Ok(None);
+        }
+
+        Ok(self
+            .tx()
+            .query_row(
+                &format!("SELECT archive, target FROM {sqlar_external} WHERE name = ?1"),
+                (path,),
+                |row| {
+                    Ok(ExternalLink {
+                        archive: PathBuf::from(row.get::<_, String>(0)?),
+                        target: PathBuf::from(row.get::<_, String>(1)?),
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    // This table is created lazily so that archives which don't use this feature are left
+    // untouched.
+    fn create_checksums_table(&self) -> crate::Result<()> {
+        let Tables {
+            sqlar,
+            sqlar_checksums,
+            ..
+        } = &self.tables;
+
+        self.tx().execute(
+            &format!("
+            CREATE TABLE IF NOT EXISTS {sqlar_checksums}(
+                name TEXT PRIMARY KEY NOT NULL REFERENCES {sqlar}(name) ON DELETE CASCADE ON UPDATE CASCADE,
+                algorithm TEXT NOT NULL,
+                digest BLOB NOT NULL
+            );
+            "),
+            (),
+        )?;
+
+        Ok(())
+    }
+
+    pub fn set_checksum(&self, path: &str, algorithm: &str, digest: &[u8]) -> crate::Result<()> {
+        let sqlar_checksums = &self.tables.sqlar_checksums;
+
+        self.create_checksums_table()?;
+
+        self.tx().prepare_cached(
+                &format!("
+                INSERT INTO {sqlar_checksums} (name, algorithm, digest)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT (name) DO UPDATE SET algorithm = excluded.algorithm, digest = excluded.digest
+                "),
+            )?
+            .execute((path, algorithm, digest))
+            .map_err(|err| match err.sqlite_error_code() {
+                Some(rusqlite::ErrorCode::ConstraintViolation) => {
+                    crate::Error::FileNotFound { path: path.into() }
+                }
+                _ => err.into(),
+            })?;
+
+        Ok(())
+    }
+
+    // Return the path, hash algorithm, and digest of every file that has a stored checksum, sorted
+    // by path.
+    pub fn checksums(&self) -> crate::Result<Vec<(String, String, Vec<u8>)>> {
+        let sqlar_checksums = &self.tables.sqlar_checksums;
+
+        if !self.table_exists(sqlar_checksums)? {
+            return Ok(Vec::new());
+        }
+
+        let mut stmt = self.tx().prepare(&format!(
+            "SELECT name, algorithm, digest FROM {sqlar_checksums} ORDER BY name"
+        ))?;
+
+        let rows = stmt
+            .query_map((), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(rows)
+    }
+
+    // These tables are created lazily so that archives which don't use this feature are left
+    // untouched.
+    fn create_dedup_tables(&self) -> crate::Result<()> {
+        let Tables {
+            sqlar,
+            sqlar_dedup,
+            sqlar_dedup_refs,
+            sqlar_dedup_hashes,
+            ..
+        } = &self.tables;
+        let refs_hash_index = self.tables.object("dedup_refs_hash");
+        let hashes_hash_index = self.tables.object("dedup_hashes_hash");
+        let after_write_trigger = self.tables.object("dedup_after_write");
+        let after_delete_trigger = self.tables.object("dedup_refs_after_delete");
+
+        // `sqlar_dedup_hashes` remembers the hashes of files whose contents aren't shared yet, so
+        // finding a file with the same contents doesn't mean reading every candidate again.
+        //
+        // The triggers keep the references and hashes in sync with the files, even when the files
+        // are changed by other tools. Writing to a file replaces its `data`, so its contents are no
+        // longer shared and its hash is out of date, and contents that aren't shared by any file
+        // are deleted.
+        self.tx().execute_batch(
+            &format!("
+            CREATE TABLE IF NOT EXISTS {sqlar_dedup}(
+                hash BLOB PRIMARY KEY NOT NULL,
+                data BLOB NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS {sqlar_dedup_refs}(
+                name TEXT PRIMARY KEY NOT NULL REFERENCES {sqlar}(name) ON DELETE CASCADE ON UPDATE CASCADE,
+                hash BLOB NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS {refs_hash_index} ON {sqlar_dedup_refs}(hash);
+
+            CREATE TABLE IF NOT EXISTS {sqlar_dedup_hashes}(
+                name TEXT PRIMARY KEY NOT NULL REFERENCES {sqlar}(name) ON DELETE CASCADE ON UPDATE CASCADE,
+                hash BLOB NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS {hashes_hash_index} ON {sqlar_dedup_hashes}(hash);
+
+            CREATE TRIGGER IF NOT EXISTS {after_write_trigger}
+            AFTER UPDATE OF data ON {sqlar}
+            BEGIN
+                DELETE FROM {sqlar_dedup_refs} WHERE name = new.name;
+                DELETE FROM {sqlar_dedup_hashes} WHERE name = new.name;
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS {after_delete_trigger}
+            AFTER DELETE ON {sqlar_dedup_refs}
+            WHEN NOT EXISTS (SELECT 1 FROM {sqlar_dedup_refs} WHERE hash = old.hash)
+            BEGIN
+                DELETE FROM {sqlar_dedup} WHERE hash = old.hash;
+            END;
+            "),
+        )?;
+
+        Ok(())
+    }
+
+    fn drop_dedup_objects(&self) -> crate::Result<()> {
+        let refs_hash_index = self.tables.object("dedup_refs_hash");
+        let hashes_hash_index = self.tables.object("dedup_hashes_hash");
+        let after_write_trigger = self.tables.object("dedup_after_write");
+        let after_delete_trigger = self.tables.object("dedup_refs_after_delete");
+
+        self.tx().execute_batch(&format!(
+            "
+            DROP INDEX IF EXISTS {refs_hash_index};
+            DROP INDEX IF EXISTS {hashes_hash_index};
+            DROP TRIGGER IF EXISTS {after_write_trigger};
+            DROP TRIGGER IF EXISTS {after_delete_trigger};
+            "
+        ))?;
+
+        Ok(())
+    }
+
+    // The rowid in `sqlar_dedup` of the contents of the file at `path`, or `None` if its contents
+    // aren't deduplicated.
+    fn dedup_rowid(&self, path: &str) -> crate::Result<Option<i64>> {
+        let Tables {
+            sqlar_dedup,
+            sqlar_dedup_refs,
+            ..
+        } = &self.tables;
+
+        if !self.table_exists(sqlar_dedup_refs)? {
+            return Ok(None);
+        }
+
+        Ok(self
+            .tx()
+            .prepare_cached(&format!(
+                "
+                SELECT
+                    d.rowid
+                FROM
+                    {sqlar_dedup_refs} AS r
+                    JOIN {sqlar_dedup} AS d ON d.hash = r.hash
+                WHERE
+                    r.name = ?1
+                "
+            ))?
+            .query_row((path,), |row| row.get(0))
+            .optional()?)
+    }
+
+    // The hash of the stored contents of the regular file at `path`, which identifies them in
+    // `sqlar_dedup`. The contents are compared as they're stored, so this doesn't decompress
+    // anything.
+    //
+    // This returns `None` if the file is empty or its contents are already deduplicated, since
+    // there's no space to save by deduplicating them. If the hash was recorded with
+    // `Store::set_dedup_hash`, this doesn't read the file.
+    pub fn dedup_hash(&self, path: &str) -> crate::Result<Option<Vec<u8>>> {
+        let sqlar_dedup_hashes = &self.tables.sqlar_dedup_hashes;
+
+        let size = self.blob_size(path)?;
+
+        if size.actual == 0 || self.dedup_rowid(path)?.is_some() {
+            return Ok(None);
+        }
+
+        if self.table_exists(sqlar_dedup_hashes)? {
+            let hash = self
+                .tx()
+                .prepare_cached(&format!(
+                    "SELECT hash FROM {sqlar_dedup_hashes} WHERE name = ?1"
+                ))?
+                .query_row((path,), |row| row.get(0))
+                .optional()?;
+
+            if hash.is_some() {
+                return Ok(hash);
+            }
+        }
+
+        let digest = digest_stream(&mut self.open_blob(path, true)?.into_blob())?;
+
+        Ok(Some(digest.as_bytes().to_vec()))
+    }
+
+    // Return whether `sqlar_dedup` already has contents with the given hash.
+    pub fn has_dedup_contents(&self, hash: &[u8]) -> crate::Result<bool> {
+        let sqlar_dedup = &self.tables.sqlar_dedup;
+
+        if !self.table_exists(sqlar_dedup)? {
+            return Ok(false);
+        }
+
+        Ok(self
+            .tx()
+            .prepare_cached(&format!("SELECT 1 FROM {sqlar_dedup} WHERE hash = ?1"))?
+            .query_row((hash,), |_| Ok(()))
+            .optional()?
+            .is_some())
+    }
+
+    // Record the hash of the contents of the file at `path`, which aren't shared yet, so other
+    // files with the same contents can find it with `Store::find_dedup_hash`. The hash is
+    // forgotten when the file is written to.
+    pub fn set_dedup_hash(&self, path: &str, hash: &[u8]) -> crate::Result<()> {
+        let sqlar_dedup_hashes = &self.tables.sqlar_dedup_hashes;
+
+        self.create_dedup_tables()?;
+
+        self.tx().execute(
+            &format!("INSERT INTO {sqlar_dedup_hashes} (name, hash) VALUES (?1, ?2) ON CONFLICT (name) DO UPDATE SET hash = excluded.hash"),
+            (path, hash),
+        )?;
+
+        Ok(())
+    }
+
+    // Return a file other than `path` whose contents aren't shared yet and have the given hash,
+    // if one was recorded with `Store::set_dedup_hash`.
+    pub fn find_dedup_hash(&self, hash: &[u8], path: &str) -> crate::Result<Option<String>> {
+        let sqlar_dedup_hashes = &self.tables.sqlar_dedup_hashes;
+
+        if !self.table_exists(sqlar_dedup_hashes)? {
+            return Ok(None);
+        }
+
+        Ok(self
+            .tx()
+            .prepare_cached(&format!(
+                "SELECT name FROM {sqlar_dedup_hashes} WHERE hash = ?1 AND name != ?2 ORDER BY name LIMIT 1"
+            ))?
+            .query_row((hash, path), |row| row.get(0))
+            .optional()?)
+    }
+
+    // Move the contents of the regular file at `path`, which have the given hash, into
+    // `sqlar_dedup`, where they can be shared with other files with the same contents.
+    //
+    // This returns `true` if another file already had the same contents.
+    pub fn dedup_file(&self, path: &str, hash: &[u8]) -> crate::Result<bool> {
+        let Tables {
+            sqlar,
+            sqlar_dedup,
+            sqlar_dedup_refs,
+            ..
+        } = &self.tables;
+
+        self.create_dedup_tables()?;
+
+        let num_inserted = self.tx().execute(
+            &format!("INSERT OR IGNORE INTO {sqlar_dedup} (hash, data) SELECT ?2, data FROM {sqlar} WHERE name = ?1"),
+            (path, hash),
+        )?;
+
+        self.tx().execute(
+            &format!("UPDATE {sqlar} SET data = zeroblob(0) WHERE name = ?1"),
+            (path,),
+        )?;
+
+        self.tx().execute(
+            &format!("INSERT INTO {sqlar_dedup_refs} (name, hash) VALUES (?1, ?2)"),
+            (path, hash),
+        )?;
+
+        Ok(num_inserted == 0)
+    }
+
+    // The tables to read the contents of files from and the expression for their stored
+    // contents. This follows the references to deduplicated contents, if there are any.
+    fn stored_data_source(&self) -> crate::Result<(String, String)> {
+        let Tables {
+            sqlar,
+            sqlar_dedup,
+            sqlar_dedup_refs,
+            ..
+        } = &self.tables;
+
+        Ok(if self.table_exists(sqlar_dedup_refs)? {
+            (
+                format!("{sqlar} LEFT JOIN {sqlar_dedup_refs} AS r USING (name) LEFT JOIN {sqlar_dedup} AS d ON d.hash = r.hash"),
+                format!("coalesce(d.data, {sqlar}.data)"),
+            )
+        } else {
+            (sqlar.to_string(), format!("{sqlar}.data"))
+        })
+    }
+
+    // This table is created lazily so that archives which don't use this feature are left
+    // untouched.
+    fn create_derived_table(&self) -> crate::Result<()> {
+        let Tables {
+            sqlar,
+            sqlar_derived,
+            ..
+        } = &self.tables;
+
+        self.tx().execute(
+            &format!(
+                "
+            CREATE TABLE IF NOT EXISTS {sqlar_derived}(
+                name TEXT NOT NULL REFERENCES {sqlar}(name) ON DELETE CASCADE ON UPDATE CASCADE,
+                kind TEXT NOT NULL,
+                data BLOB NOT NULL,
+                PRIMARY KEY (name, kind)
+            );
+            "
+            ),
+            (),
+        )?;
+
+        Ok(())
+    }
+
+    pub fn set_derived(&self, path: &str, kind: &str, data: &[u8]) -> crate::Result<()> {
+        let sqlar_derived = &self.tables.sqlar_derived;
+
+        self.create_derived_table()?;
+
+        self.tx().execute(
+                &format!("INSERT INTO {sqlar_derived} (name, kind, data) VALUES (?1, ?2, ?3) ON CONFLICT (name, kind) DO UPDATE SET data = excluded.data"),
+                (path, kind, data),
+            )
+            .map_err(|err| match err.sqlite_error_code() {
+                Some(rusqlite::ErrorCode::ConstraintViolation) => {
+                    crate::Error::FileNotFound { path: path.into() }
+                }
+                _ => err.into(),
+            })?;
+
+        Ok(())
+    }
+
+    pub fn get_derived(&self, path: &str, kind: &str) -> crate::Result<Option<Vec<u8>>> {
+        let sqlar_derived = &self.tables.sqlar_derived;
+
+        if !self.table_exists(sqlar_derived)? {
+            return Ok(None);
+        }
+
+        Ok(self
+            .tx()
+            .query_row(
+                &format!("SELECT data FROM {sqlar_derived} WHERE name = ?1 AND kind = ?2"),
+                (path, kind),
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub fn remove_derived(&self, path: &str, kind: &str) -> crate::Result<bool> {
+        let sqlar_derived = &self.tables.sqlar_derived;
+
+        if !self.table_exists(sqlar_derived)? {
+            return Ok(false);
+        }
+
+        let num_deleted = self.tx().execute(
+            &format!("DELETE FROM {sqlar_derived} WHERE name = ?1 AND kind = ?2"),
+            (path, kind),
+        )?;
+
+        Ok(num_deleted > 0)
+    }
+
+    // This table is created lazily so that archives which don't use this feature are left
+    // untouched. Each row is a file in the archive that a resumable archive job has started
+    // (`done = 0`) or finished (`done = 1`) archiving.
+    fn create_job_table(&self) -> crate::Result<()> {
+        let sqlar_jobs = &self.tables.sqlar_jobs;
+
+        self.tx().execute(
+            &format!(
+                "
+            CREATE TABLE IF NOT EXISTS {sqlar_jobs}(
+                job TEXT NOT NULL,
+                name TEXT NOT NULL,
+                done INTEGER NOT NULL,
+                PRIMARY KEY (job, name)
+            );
+            "
+            ),
+            (),
+        )?;
+
+        Ok(())
+    }
+
+    // Return `None` if the job hasn't started archiving the file at `path`, or whether it's
+    // finished archiving it.
+    pub fn job_status(&self, job: &str, path: &str) -> crate::Result<Option<bool>> {
+        let sqlar_jobs = &self.tables.sqlar_jobs;
+
+        if !self.table_exists(sqlar_jobs)? {
+            return Ok(None);
+        }
+
+        Ok(self
+            .tx()
+            .query_row(
+                &format!("SELECT done FROM {sqlar_jobs} WHERE job = ?1 AND name = ?2"),
+                (job, path),
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub fn set_job_status(&self, job: &str, path: &str, done: bool) -> crate::Result<()> {
+        let sqlar_jobs = &self.tables.sqlar_jobs;
+
+        self.create_job_table()?;
+
+        self.tx().execute(
+            &format!("INSERT INTO {sqlar_jobs} (job, name, done) VALUES (?1, ?2, ?3) ON CONFLICT (job, name) DO UPDATE SET done = excluded.done"),
+            (job, path, done),
+        )?;
+
+        Ok(())
+    }
+
+    pub fn delete_job(&self, job: &str) -> crate::Result<()> {
+        let sqlar_jobs = &self.tables.sqlar_jobs;
+
+        if !self.table_exists(sqlar_jobs)? {
+            return Ok(());
+        }
+
+        self.tx()
+            .execute(&format!("DELETE FROM {sqlar_jobs} WHERE job = ?1"), (job,))?;
+
+        Ok(())
+    }
+
+    pub fn enable_modified_tracking(&self) -> crate::Result<()> {
+        let sqlar_modified = &self.tables.sqlar_modified;
+
+        self.tx().execute_batch(&format!(
+            "
+            CREATE TABLE IF NOT EXISTS {sqlar_modified}(
+                id INTEGER PRIMARY KEY CHECK (id = 0),
+                mtime INTEGER NOT NULL,
+                generation INTEGER NOT NULL DEFAULT 0
+            );
+
+            INSERT OR IGNORE INTO {sqlar_modified} (id, mtime) VALUES (0, {NOW_MILLIS});
+            "
+        ))?;
+
+        self.create_modified_triggers()
+    }
+
+    // These are triggers rather than something we do in Rust so that changes made by other tools,
+    // like the `sqlite3` CLI, are tracked as well.
+    fn create_modified_triggers(&self) -> crate::Result<()> {
+        let sqlar_modified = &self.tables.sqlar_modified;
+
+        // The side tables that hold file metadata are normally created lazily, but the triggers
+        // on them need them to exist.
+        self.create_meta_table()?;
+        self.create_pin_table()?;
+        self.create_external_table()?;
+        self.create_raw_name_table()?;
+
+        let mut sql = String::new();
+
+        for (table, event, trigger) in self.modified_triggers() {
+            sql.push_str(&format!(
+                "
+                DROP TRIGGER IF EXISTS {trigger};
+                CREATE TRIGGER {trigger}
+                AFTER {event} ON {table}
+                BEGIN
+                    UPDATE {sqlar_modified}
+                    SET mtime = {NOW_MILLIS}, generation = generation + 1;
+                END;
+                "
+            ));
+        }
+
+        self.tx().execute_batch(&sql)?;
+
+        Ok(())
+    }
+
+    pub fn disable_modified_tracking(&self) -> crate::Result<()> {
+        let sqlar_modified = &self.tables.sqlar_modified;
+
+        self.drop_modified_triggers()?;
+
+        self.tx()
+            .execute_batch(&format!("DROP TABLE IF EXISTS {sqlar_modified};"))?;
+
+        Ok(())
+    }
+
+    fn drop_modified_triggers(&self) -> crate::Result<()> {
+        let mut sql = String::new();
+
+        for (_, _, trigger) in self.modified_triggers() {
+            sql.push_str(&format!("DROP TRIGGER IF EXISTS {trigger};\n"));
+        }
+
+        self.tx().execute_batch(&sql)?;
+
+        Ok(())
+    }
+
+    // Return the time the archive was last modified in milliseconds since the Unix epoch, or
+    // `None` if modifications aren't being tracked.
+    pub fn last_modified(&self) -> crate::Result<Option<u64>> {
+        let sqlar_modified = &self.tables.sqlar_modified;
+
+        if !self.table_exists(sqlar_modified)? {
+            return Ok(None);
+        }
+
+        Ok(self
+            .tx()
+            .query_row(
+                &format!("SELECT mtime FROM {sqlar_modified} WHERE id = 0"),
+                (),
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    // Return the number of changes made to the archive since modifications started being tracked,
+    // or `None` if they aren't being tracked.
+    pub fn generation(&self) -> crate::Result<Option<u64>> {
+        let sqlar_modified = &self.tables.sqlar_modified;
+
+        if !self.table_exists(sqlar_modified)? {
+            return Ok(None);
+        }
+
+        Ok(self
+            .tx()
+            .query_row(
+                &format!("SELECT generation FROM {sqlar_modified} WHERE id = 0"),
+                (),
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    // The triggers that track when the archive was modified, along with the table and event each
+    // one is for.
+    fn modified_triggers(&self) -> Vec<(&Ident, &'static str, Ident)> {
+        let Tables {
+            sqlar,
+            sqlar_meta,
+            sqlar_pins,
+            sqlar_external,
+            sqlar_raw_names,
+            ..
+        } = &self.tables;
+
+        [
+            sqlar,
+            sqlar_meta,
+            sqlar_pins,
+            sqlar_external,
+            sqlar_raw_names,
+        ]
+        .into_iter()
+        .flat_map(|table| {
+            ["INSERT", "UPDATE", "DELETE"]
+                .into_iter()
+                .map(move |event| {
+                    let trigger = Ident(format!("{}_modified_after_{event}", table.name()));
+                    (table, event, trigger)
+                })
+        })
+        .collect()
+    }
+
+    // The spool is a temporary table we use to stage file contents of an unknown size so we can
+    // find out how large of a blob to allocate without holding the whole file in memory. Temporary
+    // tables are private to this connection and are spilled to disk as they grow.
+    //
+    // This creates a new, empty spool and returns its ID. There can be more than one spool at a
+    // time, so that more than one file can be written to at a time.
+    pub fn create_spool(&self) -> crate::Result<u64> {
+        self.tx().execute_batch(
+            "
+            CREATE TEMP TABLE IF NOT EXISTS sqlar_spool(
+                seq INTEGER PRIMARY KEY,
+                spool INTEGER NOT NULL,
+                data BLOB NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS temp.sqlar_spool_spool ON sqlar_spool (spool, seq);
+            ",
+        )?;
+
+        let spool = self.next_spool.get();
+        self.next_spool.set(spool + 1);
+
+        Ok(spool)
+    }
+
+    pub fn append_spool(&self, spool: u64, data: &[u8]) -> crate::Result<()> {
+        self.tx()
+            .prepare_cached("INSERT INTO temp.sqlar_spool (spool, data) VALUES (?1, ?2)")?
+            .execute((spool, data))?;
+
+        Ok(())
+    }
+
+    // Call `f` with each chunk in the spool, in the order they were appended.
+    pub fn read_spool<F>(&self, spool: u64, mut f: F) -> crate::Result<()>
+    where
+        F: FnMut(&[u8]) -> crate::Result<()>,
+    {
+        let mut stmt = self
+            .tx()
+            .prepare("SELECT data FROM temp.sqlar_spool WHERE spool = ?1 ORDER BY seq")?;
+        let mut rows = stmt.query((spool,))?;
+
+        while let Some(row) = rows.next()? {
+            f(row.get_ref(0)?.as_blob().map_err(rusqlite::Error::from)?)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn clear_spool(&self, spool: u64) -> crate::Result<()> {
+        self.tx()
+            .prepare_cached("DELETE FROM temp.sqlar_spool WHERE spool = ?1")?
+            .execute((spool,))?;
+
+        Ok(())
+    }
+
+    pub fn open_blob(&self, path: &str, read_only: bool) -> crate::Result<FileBlob<'_>> {
+        let sqlar = &self.tables.sqlar;
+
+        let row = self
+            .tx()
+            .prepare_cached(&format!("SELECT rowid, sz FROM {sqlar} WHERE name = ?1;"))?
+            .query_row((path,), |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()?;
+
+        let Some((row_id, original_size)) = row else {
+            return Err(crate::Error::FileNotFound { path: path.into() });
+        };
+
+        self.open_blob_at(path, row_id, original_size, read_only)
+    }
+
+    // Open the contents of the file at `path` for reading as if they were compressed, ignoring its
+    // `sz` column, which may be wrong. This is for finding the real size of a file's contents by
+    // decompressing them. The original size of the returned blob is meaningless.
+    pub fn open_blob_compressed(&self, path: &str) -> crate::Result<FileBlob<'_>> {
+        let sqlar = &self.tables.sqlar;
+
+        let row_id = self
+            .tx()
+            .prepare_cached(&format!("SELECT rowid FROM {sqlar} WHERE name = ?1;"))?
+            .query_row((path,), |row| row.get(0))
+            .optional()?;
+
+        let Some(row_id) = row_id else {
+            return Err(crate::Error::FileNotFound { path: path.into() });
+        };
+
+        // No blob can be this long, so the contents always look compressed.
+        self.open_blob_at(path, row_id, u64::MAX, true)
+    }
+
+    fn open_blob_at(
+        &self,
+        path: &str,
+        row_id: i64,
+        original_size: u64,
+        read_only: bool,
+    ) -> crate::Result<FileBlob<'_>> {
+        let Tables {
+            sqlar, sqlar_dedup, ..
+        } = &self.tables;
+
+        // Deduplicated contents are shared with other files, so they're never written to in
+        // place. Writing to a file allocates a new blob for it first, which stops it from sharing
+        // its contents.
+        let (table, row_id) = match self.dedup_rowid(path)? {
+            Some(dedup_row_id) if read_only => (sqlar_dedup, dedup_row_id),
+            _ => (sqlar, row_id),
+        };
+
+        let blob = self.tx().blob_open(
+            rusqlite::DatabaseName::Main,
+            table.name(),
+            "data",
+            row_id,
+            read_only,
+        )?;
+
+        // We only need the dictionary to read the file.
+        #[cfg(feature = "zstd")]
+        let dictionary = if read_only && u64_from_usize(blob.len()) != original_size {
+            match frame_dictionary_id(&blob)? {
+                Some(id) => self.dictionary(id)?,
+                None => None,
+            }
+        } else {
+            None
+        };
+
+        Ok(FileBlob {
+            blob,
+            original_size,
+            #[cfg(feature = "zstd")]
+            dictionary,
+        })
+    }
+
+    pub fn allocate_blob(&self, path: &str, len: u64) -> crate::Result<()> {
+        let sqlar = &self.tables.sqlar;
+
+        let num_updated = self
+            .tx()
+            .prepare_cached(&format!(
+                "UPDATE {sqlar} SET data = zeroblob(?1) WHERE name = ?2"
+            ))?
+            .execute((len, path))?;
+
+        if num_updated == 0 {
+            return Err(crate::Error::FileNotFound { path: path.into() });
+        }
+
+        Ok(())
+    }
+
+    pub fn store_blob(&self, path: &str, bytes: &[u8]) -> crate::Result<()> {
+        let sqlar = &self.tables.sqlar;
+
+        let num_updated = self
+            .tx()
+            .prepare_cached(&format!("UPDATE {sqlar} SET data = ?1 WHERE name = ?2"))?
+            .execute((bytes, path))?;
+
+        if num_updated == 0 {
+            return Err(crate::Error::FileNotFound { path: path.into() });
+        }
+
+        Ok(())
+    }
+
+    // Copy the contents of the file at `from` into the file at `to` as they're stored, without
+    // decompressing them. This streams the data between the two blobs, so it doesn't read the
+    // whole file into memory. The metadata of `to` is left alone.
+    pub fn copy_blob(&self, from: &str, to: &str) -> crate::Result<()> {
+        let size = self.blob_size(from)?;
+
+        self.allocate_blob(to, size.actual)?;
+        self.set_size(to, size.original)?;
+
+        let mut source = self.open_blob(from, true)?.into_blob();
+        let mut dest = self.open_blob(to, false)?.into_blob();
+
+        io::copy(&mut source, &mut dest)?;
+
+        Ok(())
+    }
+
+    pub fn read_metadata(&self, path: &str) -> crate::Result<FileMetadata> {
+        self.read_entry(path).map(|(_, metadata)| metadata)
+    }
+
+    // Read the metadata of the file at `path` along with its rowid.
+    pub fn read_entry(&self, path: &str) -> crate::Result<(i64, FileMetadata)> {
+        let sqlar = &self.tables.sqlar;
+
+        let mut stmt = self.tx().prepare_cached(&format!(
+            "
+            SELECT
+                mode,
+                mtime,
+                sz,
+                iif(sz < 0, data, NULL) AS target,
+                data IS NULL AS is_dir,
+                rowid
+            FROM
+                {sqlar}
+            WHERE
+                name = ?1;
+            "
+        ))?;
+
+        stmt.query_row((path,), |row| {
+            let mode = row.get::<_, Option<u32>>(0)?.map(FileMode::from_mode);
+            let mtime = row
+                .get::<_, Option<u64>>(1)?
+                .map(|mtime_secs| UNIX_EPOCH + Duration::from_secs(mtime_secs));
+            let size: i64 = row.get(2)?;
+            // When the `data` column contains a symlink target, its type is `TEXT`, not
+            // `BLOB`. Remember that columns in SQLite are dynamically typed.
+            let symlink_target: Option<String> = row.get(3)?;
+            let is_dir: bool = row.get(4)?;
+            let rowid: i64 = row.get(5)?;
+
+            // We ignore the file mode in the database when determining the file type.
+            let metadata = if let Some(target) = symlink_target {
+                FileMetadata::Symlink {
+                    mtime,
+                    target: PathBuf::from(target),
+                }
+            } else if is_dir {
+                FileMetadata::Dir { mode, mtime }
+            } else {
+                FileMetadata::File {
+                    mode,
+                    mtime,
+                    size: size.try_into().expect("The file size in the database was negative, but we should have already checked for this. This is a bug."),
+                }
+            };
+
+            Ok((rowid, metadata))
+        })
+        .optional()?
+        .ok_or(crate::Error::FileNotFound { path: path.into() })
+    }
+
+    pub fn set_mode(&self, path: &str, mode: Option<FileMode>) -> crate::Result<()> {
+        let sqlar = &self.tables.sqlar;
+
+        // If the file is a symlink, this is a no-op. Symlinks always have 777 permissions.
+        let num_updated = self
+            .tx()
+            .prepare_cached(
+                &format!("UPDATE {sqlar} SET mode = iif(mode & ?1 = ?2, mode, mode & ?1 | ?3) WHERE name = ?4"),
+            )?
+            .execute((TYPE_MASK, SYMLINK_MODE, mode.map(|mode| mode.bits()), path))?;
+
+        if num_updated == 0 {
+            return Err(crate::Error::FileNotFound { path: path.into() });
+        }
+
+        Ok(())
+    }
+
+    pub fn set_mtime(&self, path: &str, mtime: Option<SystemTime>) -> crate::Result<()> {
+        let sqlar = &self.tables.sqlar;
+
+        let mtime_secs = mtime.map(unix_secs).transpose()?;
+
+        let num_updated = self
+            .tx()
+            .prepare_cached(&format!("UPDATE {sqlar} SET mtime = ?1 WHERE name = ?2"))?
+            .execute((mtime_secs, path))?;
+
+        if num_updated == 0 {
+            return Err(crate::Error::FileNotFound { path: path.into() });
+        }
 
         Ok(())
     }
 
     pub fn set_size(&self, path: &str, size: u64) -> crate::Result<()> {
+        let sqlar = &self.tables.sqlar;
+
         let num_updated = self
             .tx()
-            .execute("UPDATE sqlar SET sz = ?1 WHERE name = ?2", (size, path))?;
+            .prepare_cached(&format!("UPDATE {sqlar} SET sz = ?1 WHERE name = ?2"))?
+            .execute((size, path))?;
 
         if num_updated == 0 {
             return Err(crate::Error::FileNotFound { path: path.into() });
@@ -378,26 +2768,190 @@ impl<'conn> Store<'conn> {
     }
 
     pub fn blob_size(&self, path: &str) -> crate::Result<BlobSize> {
+        let sqlar = &self.tables.sqlar;
+
+        let (source, data) = self.stored_data_source()?;
+
+        self.tx()
+            .prepare_cached(&format!(
+                "SELECT {sqlar}.sz, length({data}) FROM {source} WHERE {sqlar}.name = ?1;"
+            ))?
+            .query_row((path,), |row| {
+                Ok(BlobSize {
+                    original: row.get(0)?,
+                    actual: row.get(1)?,
+                })
+            })
+            .optional()?
+            .ok_or(crate::Error::FileNotFound { path: path.into() })
+    }
+
+    // Return the path and size of every regular file at `ancestor` or among its descendants, or
+    // every regular file in the archive if `ancestor` is `None`. Files with a negative size are
+    // skipped; see `Archive::check`.
+    pub fn file_sizes(&self, ancestor: Option<&str>) -> crate::Result<Vec<(String, BlobSize)>> {
+        let sqlar = &self.tables.sqlar;
+
+        let (source, data) = self.stored_data_source()?;
+
+        let mut stmt = self.tx().prepare(&format!(
+            "
+            SELECT
+                {sqlar}.name,
+                {sqlar}.sz,
+                coalesce(length({data}), 0)
+            FROM
+                {source}
+            WHERE
+                ({sqlar}.mode & ?1) = ?2
+                AND {sqlar}.sz >= 0
+                AND iif(?3 IS NULL, true, {sqlar}.name = ?3 OR {sqlar}.name GLOB ?3 || '/?*')
+            ORDER BY
+                {sqlar}.name
+            "
+        ))?;
+
+        let rows = stmt.query_map((TYPE_MASK, FILE_MODE, ancestor), |row| {
+            Ok((
+                row.get(0)?,
+                BlobSize {
+                    original: row.get(1)?,
+                    actual: row.get(2)?,
+                },
+            ))
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    // Return the path of every file in the archive, sorted by path. Unlike `list_files`, this
+    // returns the paths exactly as they're stored, even if they aren't valid.
+    pub fn file_names(&self) -> crate::Result<Vec<String>> {
+        let sqlar = &self.tables.sqlar;
+
+        let mut stmt = self
+            .tx()
+            .prepare(&format!("SELECT name FROM {sqlar} ORDER BY name"))?;
+
+        let names = stmt
+            .query_map((), |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(names)
+    }
+
+    pub fn dir_names(&self) -> crate::Result<Vec<String>> {
+        let sqlar = &self.tables.sqlar;
+
+        let mut stmt = self.tx().prepare(&format!(
+            "SELECT name FROM {sqlar} WHERE data IS NULL ORDER BY name"
+        ))?;
+
+        let names = stmt
+            .query_map((), |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(names)
+    }
+
+    // Whether the `data` column of the file at `path` is a blob, as opposed to text or NULL.
+    pub fn has_blob_data(&self, path: &str) -> crate::Result<bool> {
+        let sqlar = &self.tables.sqlar;
+
         self.tx()
             .query_row(
-                "SELECT sz, length(data) FROM sqlar WHERE name = ?1;",
+                &format!("SELECT typeof(data) = 'blob' FROM {sqlar} WHERE name = ?1"),
                 (path,),
-                |row| {
-                    Ok(BlobSize {
-                        original: row.get(0)?,
-                        actual: row.get(1)?,
-                    })
-                },
+                |row| row.get(0),
             )
             .optional()?
-            .ok_or(crate::Error::FileNotFound { path: path.into() })
+            .ok_or_else(|| crate::Error::FileNotFound { path: path.into() })
+    }
+
+    // The names of the files that have a negative size but aren't symlinks, either because their
+    // mode says they're some other kind of file or because they have no data, which makes them a
+    // directory.
+    pub fn negative_size_names(&self) -> crate::Result<Vec<String>> {
+        let sqlar = &self.tables.sqlar;
+
+        let mut stmt = self.tx().prepare(&format!(
+            "
+            SELECT
+                name
+            FROM
+                {sqlar}
+            WHERE
+                sz < 0
+                AND (data IS NULL OR (mode IS NOT NULL AND (mode & ?1) != ?2))
+            ORDER BY
+                name
+            "
+        ))?;
+
+        let names = stmt
+            .query_map((TYPE_MASK, SYMLINK_MODE), |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(names)
+    }
+
+    // The path of the file with the given rowid, or `None` if there isn't one.
+    pub fn path_by_rowid(&self, rowid: i64) -> crate::Result<Option<String>> {
+        let sqlar = &self.tables.sqlar;
+
+        Ok(self
+            .tx()
+            .prepare_cached(&format!("SELECT name FROM {sqlar} WHERE rowid = ?1"))?
+            .query_row((rowid,), |row| row.get(0))
+            .optional()?)
+    }
+
+    // Rename a single file without touching the files under it. This is for fixing paths that
+    // aren't in the normal form, which the files under them may not share.
+    pub fn rename_entry(&self, from: &str, to: &str) -> crate::Result<()> {
+        let sqlar = &self.tables.sqlar;
+
+        let result = self.tx().execute(
+            &format!("UPDATE {sqlar} SET name = ?2 WHERE name = ?1"),
+            (from, to),
+        );
+
+        match result {
+            Ok(0) => Err(crate::Error::FileNotFound { path: from.into() }),
+            Ok(_) => Ok(()),
+            Err(err)
+                if err.sqlite_error_code() == Some(rusqlite::ErrorCode::ConstraintViolation) =>
+            {
+                Err(crate::Error::FileAlreadyExists { path: to.into() })
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    // Delete a single file without deleting the files under it.
+    pub fn delete_entry(&self, path: &str) -> crate::Result<()> {
+        let sqlar = &self.tables.sqlar;
+
+        let num_deleted = self
+            .tx()
+            .execute(&format!("DELETE FROM {sqlar} WHERE name = ?1"), (path,))?;
+
+        if num_deleted == 0 {
+            return Err(crate::Error::FileNotFound { path: path.into() });
+        }
+
+        Ok(())
     }
 
-    pub fn list_files(&self, opts: &ListOptions) -> crate::Result<ListEntries> {
+    pub fn list_files(&self, opts: &ListOptions) -> crate::Result<ListEntries<'_>> {
+        let sqlar = &self.tables.sqlar;
+
         let order_column = match opts.sort {
             Some(ListSort::Size) => "s.sz",
             Some(ListSort::Mtime) => "s.mtime",
             Some(ListSort::Depth) => "p.segments",
+            Some(ListSort::Name) => "s.name",
+            Some(ListSort::NameNatural) => "s.name COLLATE sqlarfs_natural",
             // The contract of `Archive::list` and `Archive::list_with` is that default sort order
             // is unspecified.
             None => "s.rowid",
@@ -408,6 +2962,8 @@ impl<'conn> Store<'conn> {
             Some(SortDirection::Desc) => "DESC",
         };
 
+        let list_filter = list_filter(sqlar);
+
         let stmt = self.tx().prepare(&format!(
             "
             WITH path_segments AS (
@@ -415,7 +2971,7 @@ impl<'conn> Store<'conn> {
                     name,
                     length(name) - length(replace(name, '/', '')) AS segments
                 FROM
-                    sqlar
+                    {sqlar}
             )
             SELECT
                 s.name,
@@ -423,51 +2979,20 @@ impl<'conn> Store<'conn> {
                 s.mtime,
                 s.sz,
                 iif(s.sz = -1, s.data, NULL) AS target,
-                s.data IS NULL AS is_dir
+                s.data IS NULL AS is_dir,
+                s.rowid
             FROM
-                sqlar AS s
+                {sqlar} AS s
             JOIN
                 path_segments AS p ON s.name = p.name
             WHERE
-                iif(?1 IS NULL OR ?1 = '', true, s.name GLOB ?1 || '/?*')
-                AND iif(?3 IS NULL, true, (s.mode & ?2) = ?3)
-                AND iif(?4 IS NULL, true, (s.mode & ?2) = ?4)
-                AND CASE
-                    WHEN ?5 IS NULL THEN true
-                    WHEN ?5 = '' THEN NOT s.name GLOB '*/*'
-                    ELSE s.name GLOB ?5 || '/?*' AND NOT s.name GLOB ?5 || '/?*/*'
-                END
+                {list_filter}
             ORDER BY
                 {order_column} {direction}
         "
         ))?;
 
-        let params: Vec<Box<dyn rusqlite::ToSql>> = vec![
-            Box::new(opts.ancestor.as_ref().map(|ancestor| {
-                ancestor
-                    .to_string_lossy()
-                    .trim_end_matches(std::path::MAIN_SEPARATOR)
-                    .to_string()
-            })),
-            Box::new(TYPE_MASK),
-            Box::new(if let Some(ListSort::Size) = opts.sort {
-                Some(FILE_MODE)
-            } else {
-                None
-            }),
-            Box::new(match opts.file_type {
-                Some(FileType::File) => Some(FILE_MODE),
-                Some(FileType::Dir) => Some(DIR_MODE),
-                Some(FileType::Symlink) => Some(SYMLINK_MODE),
-                None => None,
-            }),
-            Box::new(opts.parent.as_ref().map(|parent| {
-                parent
-                    .to_string_lossy()
-                    .trim_end_matches(std::path::MAIN_SEPARATOR)
-                    .to_string()
-            })),
-        ];
+        let params = list_filter_params(opts)?;
 
         let map_func: ListMapFunc = Box::new(|row| {
             let mode = row.get::<_, Option<u32>>(1)?.map(FileMode::from_mode);
@@ -496,6 +3021,7 @@ impl<'conn> Store<'conn> {
             };
 
             Ok(ListEntry {
+                id: EntryId(row.get(6)?),
                 path: PathBuf::from(row.get::<_, String>(0)?),
                 metadata,
             })
@@ -1,16 +1,34 @@
 use std::fmt;
+#[cfg(any(feature = "deflate", feature = "zstd"))]
+use std::io::Write;
 use std::io::{self, Read};
 
 #[cfg(feature = "deflate")]
 use flate2::read::ZlibDecoder;
+#[cfg(feature = "deflate")]
+use flate2::write::ZlibEncoder;
 use rusqlite::blob::Blob;
 
 use super::store::FileBlob;
+use super::util::u64_from_usize;
+
+// The most we'll preallocate when reading a compressed file to the end. The uncompressed size of a
+// compressed file comes from the `sz` column, which we can't verify until we've decompressed it,
+// so we don't want a corrupt archive to be able to make us allocate an arbitrary amount of memory.
+#[cfg(any(feature = "deflate", feature = "zstd"))]
+const MAX_COMPRESSED_PREALLOC: u64 = 64 * 1024 * 1024;
+
+// The magic number at the start of every Zstandard frame, which is how we tell a file compressed
+// with Zstandard apart from one compressed with DEFLATE. This can never be the start of a zlib
+// stream, because its first two bytes aren't a valid zlib header.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
 
 /// The compression method to use when writing to a [`File`].
 ///
 /// [`File`]: crate::File
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 #[non_exhaustive]
 pub enum Compression {
     /// Do not compress writes.
@@ -25,6 +43,25 @@ pub enum Compression {
         /// compression."
         level: u32,
     },
+
+    /// Compress writes using the Zstandard algorithm.
+    ///
+    /// This isn't part of the sqlar format, which only supports DEFLATE, so other tools won't be
+    /// able to read files compressed this way. Files compressed with Zstandard are recognized by
+    /// the Zstandard magic number at the start of their data, which can never be the start of
+    /// data compressed with DEFLATE. Like with DEFLATE, the compressed data is only stored if it's
+    /// smaller than the uncompressed data.
+    ///
+    /// Reading these files requires the `zstd` Cargo feature.
+    #[cfg(feature = "zstd")]
+    Zstd {
+        /// The compression level to use.
+        ///
+        /// This value is on a scale of 1-22, where higher levels mean better compression. Passing
+        /// 0 uses the default level, and negative values enable faster levels with less
+        /// compression.
+        level: i32,
+    },
 }
 
 impl Compression {
@@ -37,9 +74,107 @@ impl Compression {
     pub const BEST: Self = Self::Deflate { level: 9 };
 }
 
+// A streaming encoder for one of the compression methods, which writes its output into a buffer.
+#[cfg(any(feature = "deflate", feature = "zstd"))]
+pub(super) enum Encoder {
+    #[cfg(feature = "deflate")]
+    Deflate(ZlibEncoder<Vec<u8>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+}
+
+#[cfg(any(feature = "deflate", feature = "zstd"))]
+impl fmt::Debug for Encoder {
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "deflate")]
+            Self::Deflate(_) => f.debug_tuple("Deflate").finish(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(_) => f.debug_tuple("Zstd").finish(),
+        }
+    }
+}
+
+#[cfg(any(feature = "deflate", feature = "zstd"))]
+impl Encoder {
+    // Create an encoder for `method`, or return `None` if it doesn't compress anything.
+    pub fn new(method: Compression) -> io::Result<Option<Self>> {
+        Ok(match method {
+            Compression::None => None,
+            #[cfg(feature = "deflate")]
+            Compression::Deflate { level } => Some(Self::Deflate(ZlibEncoder::new(
+                Vec::new(),
+                flate2::Compression::new(level),
+            ))),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level } => Some(Self::Zstd(zstd::stream::write::Encoder::new(
+                Vec::new(),
+                level,
+            )?)),
+        })
+    }
+
+    // The compressed data that's been written so far.
+    pub fn output(&mut self) -> &mut Vec<u8> {
+        match self {
+            #[cfg(feature = "deflate")]
+            Self::Deflate(encoder) => encoder.get_mut(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.get_mut(),
+        }
+    }
+
+    // Write the rest of the compressed data to the output.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "deflate")]
+            Self::Deflate(encoder) => encoder.try_finish(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.do_finish(),
+        }
+    }
+}
+
+#[cfg(any(feature = "deflate", feature = "zstd"))]
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "deflate")]
+            Self::Deflate(encoder) => encoder.write(buf),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "deflate")]
+            Self::Deflate(encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+// Whether the data in `blob` was compressed with Zstandard rather than DEFLATE.
+fn is_zstd(blob: &Blob<'_>) -> crate::Result<bool> {
+    let mut magic = [0u8; ZSTD_MAGIC.len()];
+
+    if blob.len() < magic.len() {
+        return Ok(false);
+    }
+
+    blob.read_at_exact(&mut magic, 0)?;
+
+    Ok(magic == ZSTD_MAGIC)
+}
+
 enum InnerReader<'conn> {
     #[cfg(feature = "deflate")]
     Compressed(ZlibDecoder<Blob<'conn>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::read::Decoder<'static, io::BufReader<Blob<'conn>>>),
     Uncompressed(Blob<'conn>),
 }
 
@@ -49,6 +184,8 @@ impl<'conn> fmt::Debug for InnerReader<'conn> {
         match self {
             #[cfg(feature = "deflate")]
             Self::Compressed(_) => f.debug_tuple("Compressed").finish(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(_) => f.debug_tuple("Zstd").finish(),
             Self::Uncompressed(_) => f.debug_tuple("Uncompressed").finish(),
         }
     }
@@ -59,6 +196,8 @@ impl<'conn> Read for InnerReader<'conn> {
         match self {
             #[cfg(feature = "deflate")]
             InnerReader::Compressed(reader) => reader.read(buf),
+            #[cfg(feature = "zstd")]
+            InnerReader::Zstd(reader) => reader.read(buf),
             InnerReader::Uncompressed(reader) => reader.read(buf),
         }
     }
@@ -73,14 +212,45 @@ impl<'conn> Read for InnerReader<'conn> {
 #[derive(Debug)]
 pub struct FileReader<'conn> {
     inner: InnerReader<'conn>,
+    len: u64,
+    pos: u64,
 }
 
 impl<'conn> FileReader<'conn> {
     pub(super) fn new(blob: FileBlob<'conn>) -> crate::Result<Self> {
+        let len = blob.original_size();
+
         if blob.is_compressed() {
+            #[cfg(feature = "zstd")]
+            let (blob, dictionary) = blob.into_blob_and_dictionary();
+            #[cfg(not(feature = "zstd"))]
+            let blob = blob.into_blob();
+
+            if is_zstd(&blob)? {
+                // Files compressed with the archive's shared dictionary need it to be read. See
+                // `Archive::train_dictionary`.
+                #[cfg(feature = "zstd")]
+                return Ok(Self {
+                    inner: InnerReader::Zstd(match dictionary {
+                        Some(dictionary) => zstd::stream::read::Decoder::with_dictionary(
+                            io::BufReader::new(blob),
+                            &dictionary,
+                        )?,
+                        None => zstd::stream::read::Decoder::new(blob)?,
+                    }),
+                    len,
+                    pos: 0,
+                });
+
+                #[cfg(not(feature = "zstd"))]
+                return Err(crate::Error::CompressionNotSupported);
+            }
+
             #[cfg(feature = "deflate")]
             return Ok(Self {
-                inner: InnerReader::Compressed(ZlibDecoder::new(blob.into_blob())),
+                inner: InnerReader::Compressed(ZlibDecoder::new(blob)),
+                len,
+                pos: 0,
             });
 
             #[cfg(not(feature = "deflate"))]
@@ -89,12 +259,73 @@ impl<'conn> FileReader<'conn> {
 
         Ok(Self {
             inner: InnerReader::Uncompressed(blob.into_blob()),
+            len,
+            pos: 0,
         })
     }
+
+    /// The uncompressed size of the file in bytes.
+    ///
+    /// This is the total size of the file, regardless of how much of it has already been read.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The bounds on the number of bytes left to read.
+    ///
+    /// Like [`Iterator::size_hint`], this returns a lower bound and an optional upper bound. Since
+    /// the uncompressed size of the file is stored in the archive, both bounds are the number of
+    /// bytes in the file that haven't been read yet.
+    pub fn size_hint(&self) -> (u64, Option<u64>) {
+        let remaining = self.len.saturating_sub(self.pos);
+
+        (remaining, Some(remaining))
+    }
+
+    // How much space to reserve in a buffer before reading the rest of the file into it.
+    fn prealloc_len(&self) -> usize {
+        let (remaining, _) = self.size_hint();
+
+        let capped = match self.inner {
+            #[cfg(feature = "deflate")]
+            InnerReader::Compressed(_) => remaining.min(MAX_COMPRESSED_PREALLOC),
+            #[cfg(feature = "zstd")]
+            InnerReader::Zstd(_) => remaining.min(MAX_COMPRESSED_PREALLOC),
+            InnerReader::Uncompressed(_) => remaining,
+        };
+
+        usize::try_from(capped).unwrap_or(usize::MAX)
+    }
 }
 
 impl<'conn> Read for FileReader<'conn> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.inner.read(buf)
+        let bytes_read = self.inner.read(buf)?;
+        self.pos += u64_from_usize(bytes_read);
+
+        Ok(bytes_read)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        buf.reserve(self.prealloc_len());
+
+        let bytes_read = self.inner.read_to_end(buf)?;
+        self.pos += u64_from_usize(bytes_read);
+
+        Ok(bytes_read)
+    }
+
+    fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
+        buf.reserve(self.prealloc_len());
+
+        let bytes_read = self.inner.read_to_string(buf)?;
+        self.pos += u64_from_usize(bytes_read);
+
+        Ok(bytes_read)
     }
 }
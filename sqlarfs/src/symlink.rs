@@ -0,0 +1,156 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use super::archive::Archive;
+use super::list::ListOptions;
+use super::metadata::{FileMetadata, FileType};
+
+// The most symbolic links we'll follow when resolving a path before giving up, which is the same
+// as `MAXSYMLINKS` on Linux.
+const MAX_SYMLINK_DEPTH: u32 = 40;
+
+// Where the target of a symbolic link resolves to within an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Resolved {
+    // The target exists in the archive at this path.
+    Path(Vec<String>),
+
+    // The target doesn't exist in the archive.
+    Missing,
+
+    // The target is outside of the archive, because it's an absolute path or it has too many
+    // `..` components.
+    Outside,
+}
+
+impl<'conn> Archive<'conn> {
+    // Resolve `target` relative to the directory `base`, following any symbolic links along the
+    // way.
+    fn resolve_symlink(
+        &self,
+        base: Vec<String>,
+        target: &Path,
+        depth: u32,
+    ) -> crate::Result<Resolved> {
+        let mut path = base;
+        let mut is_dir = true;
+
+        for component in target.components() {
+            let name = match component {
+                Component::CurDir => continue,
+                Component::ParentDir => {
+                    if !is_dir {
+                        return Ok(Resolved::Missing);
+                    }
+
+                    if path.pop().is_none() {
+                        return Ok(Resolved::Outside);
+                    }
+
+                    continue;
+                }
+                Component::Normal(name) => name,
+                Component::RootDir | Component::Prefix(_) => return Ok(Resolved::Outside),
+            };
+
+            // You can't look up a file inside of something that isn't a directory.
+            if !is_dir {
+                return Ok(Resolved::Missing);
+            }
+
+            let Some(name) = name.to_str() else {
+                return Ok(Resolved::Missing);
+            };
+
+            path.push(name.to_owned());
+
+            match self.store.read_metadata(&path.join("/")) {
+                Ok(FileMetadata::Dir { .. }) => {}
+                Ok(FileMetadata::File { .. }) => is_dir = false,
+                Ok(FileMetadata::Symlink { target, .. }) => {
+                    if depth >= MAX_SYMLINK_DEPTH {
+                        return Ok(Resolved::Missing);
+                    }
+
+                    path.pop();
+
+                    match self.resolve_symlink(path, &target, depth + 1)? {
+                        Resolved::Path(resolved) => {
+                            is_dir = self.store.read_metadata(&resolved.join("/")).map_or(
+                                // The root of the archive is a directory.
+                                resolved.is_empty(),
+                                |metadata| metadata.is_dir(),
+                            );
+                            path = resolved;
+                        }
+                        other => return Ok(other),
+                    }
+                }
+                Err(crate::Error::FileNotFound { .. }) => return Ok(Resolved::Missing),
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(Resolved::Path(path))
+    }
+
+    // Resolve the target of the symbolic link at `link_path` within the archive.
+    pub(super) fn resolve_link_target(
+        &self,
+        link_path: &Path,
+        target: &Path,
+    ) -> crate::Result<Resolved> {
+        let base = link_path
+            .parent()
+            .into_iter()
+            .flat_map(Path::components)
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+
+        self.resolve_symlink(base, target, 0)
+    }
+
+    pub(super) fn find_broken_symlinks_impl(&self) -> crate::Result<Vec<PathBuf>> {
+        let links = self
+            .list_with(&ListOptions::new().file_type(FileType::Symlink))?
+            .collect::<crate::Result<Vec<_>>>()?;
+
+        let mut broken = Vec::new();
+
+        for link in links {
+            let FileMetadata::Symlink { target, .. } = link.metadata() else {
+                continue;
+            };
+
+            if self.resolve_link_target(link.path(), target)? == Resolved::Missing {
+                broken.push(link.into_path());
+            }
+        }
+
+        broken.sort();
+
+        Ok(broken)
+    }
+
+    // Whether the symbolic link at `link_path` in the archive, which is being extracted to
+    // `dest_path`, is broken. Targets outside of the archive are checked in the filesystem.
+    pub(super) fn is_broken_symlink(
+        &self,
+        link_path: &Path,
+        target: &Path,
+        dest_path: &Path,
+    ) -> crate::Result<bool> {
+        Ok(match self.resolve_link_target(link_path, target)? {
+            Resolved::Path(_) => false,
+            Resolved::Missing => true,
+            Resolved::Outside => {
+                let dest_target = match dest_path.parent() {
+                    Some(parent) => parent.join(target),
+                    None => target.to_owned(),
+                };
+
+                fs::metadata(dest_target).is_err()
+            }
+        })
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::str;
+
+// The longest variable name we'll look for between `{{` and `}}`. Anything longer is copied
+// through as-is, so a stray `{{` in a large file doesn't make us buffer the rest of it.
+const MAX_NAME_LEN: usize = 256;
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// Replaces `{{name}}` tokens in a stream of bytes with the values of the given variables.
+//
+// Input is passed in chunks, and tokens can be split across chunks. Tokens naming variables that
+// aren't in the map are left untouched.
+#[derive(Debug)]
+pub struct Substituter<'a> {
+    vars: &'a HashMap<String, String>,
+    // Input we can't write yet because it might be the start of a token.
+    pending: Vec<u8>,
+}
+
+impl<'a> Substituter<'a> {
+    pub fn new(vars: &'a HashMap<String, String>) -> Self {
+        Self {
+            vars,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn write<W: Write>(&mut self, input: &[u8], out: &mut W) -> io::Result<()> {
+        self.pending.extend_from_slice(input);
+
+        let consumed = self.substitute(out)?;
+        self.pending.drain(..consumed);
+
+        Ok(())
+    }
+
+    // Write any input that was held back waiting for the end of a token.
+    pub fn finish<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        out.write_all(&self.pending)?;
+        self.pending.clear();
+
+        Ok(())
+    }
+
+    // Write as much of the pending input as we can, returning the number of bytes written.
+    fn substitute<W: Write>(&self, out: &mut W) -> io::Result<usize> {
+        let buf = self.pending.as_slice();
+        let mut pos = 0;
+
+        loop {
+            let token_start = match find(&buf[pos..], b"{{") {
+                Some(offset) => pos + offset,
+                None => {
+                    // Hold back a trailing `{` in case it's the start of a token.
+                    let end = if buf[pos..].ends_with(b"{") {
+                        buf.len() - 1
+                    } else {
+                        buf.len()
+                    };
+
+                    out.write_all(&buf[pos..end])?;
+
+                    return Ok(end);
+                }
+            };
+
+            out.write_all(&buf[pos..token_start])?;
+
+            let name_start = token_start + 2;
+
+            match find(&buf[name_start..], b"}}") {
+                Some(name_len) if name_len <= MAX_NAME_LEN => {
+                    let token_end = name_start + name_len + 2;
+                    let name = &buf[name_start..name_start + name_len];
+
+                    match str::from_utf8(name)
+                        .ok()
+                        .and_then(|name| self.vars.get(name.trim()))
+                    {
+                        Some(value) => out.write_all(value.as_bytes())?,
+                        None => out.write_all(&buf[token_start..token_end])?,
+                    }
+
+                    pos = token_end;
+                }
+                // The closing `}}` might be in the next chunk.
+                None if buf.len() - name_start <= MAX_NAME_LEN + 1 => return Ok(token_start),
+                // This is too long to be a token.
+                _ => {
+                    out.write_all(b"{{")?;
+                    pos = name_start;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use xpct::{equal, expect};
+
+    fn vars() -> HashMap<String, String> {
+        HashMap::from([
+            (String::from("name"), String::from("sqlarfs")),
+            (String::from("version"), String::from("1.0.0")),
+        ])
+    }
+
+    fn substitute_chunks(chunks: &[&str]) -> String {
+        let vars = vars();
+        let mut substituter = Substituter::new(&vars);
+        let mut out = Vec::new();
+
+        for chunk in chunks {
+            substituter.write(chunk.as_bytes(), &mut out).unwrap();
+        }
+
+        substituter.finish(&mut out).unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn substitutes_known_variables() {
+        expect!(substitute_chunks(&[
+            "name = \"{{name}}\"\nversion = \"{{ version }}\""
+        ]))
+        .to(equal("name = \"sqlarfs\"\nversion = \"1.0.0\""));
+    }
+
+    #[test]
+    fn leaves_unknown_variables_alone() {
+        expect!(substitute_chunks(&["{{unknown}} {{name}}"])).to(equal("{{unknown}} sqlarfs"));
+    }
+
+    #[test]
+    fn substitutes_tokens_split_across_chunks() {
+        expect!(substitute_chunks(&["a{", "{na", "me}", "}b"])).to(equal("asqlarfsb"));
+    }
+
+    #[test]
+    fn passes_through_unclosed_tokens() {
+        expect!(substitute_chunks(&["a {{name", " b {"])).to(equal("a {{name b {"));
+    }
+
+    #[test]
+    fn passes_through_tokens_that_are_too_long() {
+        let long_token = format!("{{{{{}}}}}", "x".repeat(MAX_NAME_LEN + 1));
+
+        expect!(substitute_chunks(&[&long_token, "{{name}}"]))
+            .to(equal(format!("{long_token}sqlarfs")));
+    }
+}
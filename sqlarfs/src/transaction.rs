@@ -1,6 +1,16 @@
+use std::io::Read;
 use std::path::Path;
+use std::sync::Arc;
 
 use super::archive::Archive;
+use super::builder::ConnectionBuilder;
+#[cfg(feature = "sqlcipher")]
+use super::builder::EncryptionKey;
+use super::lock::lock_namespace;
+use super::memory::{read_memory_stats, MemoryStats};
+use super::store::{check_is_archive, table_exists, Store, DEFAULT_TABLE};
+use super::tree::ArchiveOptions;
+use super::util::natural_cmp;
 
 /// The behavior of a SQLite transaction.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -28,6 +38,18 @@ impl TransactionBehavior {
     }
 }
 
+// The namespace for file locks on the archive in `table`. Files in different archive tables are
+// different files, even when they're in the same database.
+fn table_lock_namespace(conn: &rusqlite::Connection, table: &str) -> Arc<str> {
+    let namespace = lock_namespace(conn);
+
+    if table == DEFAULT_TABLE {
+        namespace
+    } else {
+        Arc::from(format!("{namespace}\0{table}"))
+    }
+}
+
 /// A connection to a SQLite database.
 ///
 /// All operations on an [`Archive`] must happen within the context of a [`Transaction`]. You can
@@ -41,14 +63,50 @@ impl TransactionBehavior {
 /// - [`Connection::create_new`]
 /// - [`Connection::open_readonly`]
 /// - [`Connection::open_in_memory`]
+/// - [`Connection::create_atomically`]
+///
+/// To open a connection with custom options, use a [`ConnectionBuilder`].
 #[derive(Debug)]
 pub struct Connection {
     conn: rusqlite::Connection,
+    lock_namespace: Arc<str>,
+    table: Arc<str>,
+    #[cfg(feature = "sqlcipher")]
+    key: Option<EncryptionKey>,
+    require_sqlar_table: bool,
 }
 
 impl Connection {
-    pub(super) fn new(conn: rusqlite::Connection) -> Self {
-        Self { conn }
+    pub(super) fn new(conn: rusqlite::Connection, table: &str) -> crate::Result<Self> {
+        // Side tables that store extra data about files reference the `sqlar` table, and we rely
+        // on foreign key cascades to keep them in sync when files are renamed or deleted.
+        conn.pragma_update(None, "foreign_keys", true)?;
+
+        // This is used for `ListOptions::by_name_natural`.
+        conn.create_collation("sqlarfs_natural", natural_cmp)?;
+
+        let lock_namespace = table_lock_namespace(&conn, table);
+
+        Ok(Self {
+            conn,
+            lock_namespace,
+            table: Arc::from(table),
+            #[cfg(feature = "sqlcipher")]
+            key: None,
+            require_sqlar_table: false,
+        })
+    }
+
+    // Remember the key the database was unlocked with, so we can open more connections to it.
+    #[cfg(feature = "sqlcipher")]
+    pub(super) fn set_encryption_key(&mut self, key: Option<EncryptionKey>) {
+        self.key = key;
+    }
+
+    // Make starting a transaction fail if the `sqlar` table has been dropped. This is set once the
+    // table has been created or checked.
+    pub(super) fn set_require_sqlar_table(&mut self, require: bool) {
+        self.require_sqlar_table = require;
     }
 
     /// Open a connection to the SQLite archive at `path`.
@@ -63,16 +121,7 @@ impl Connection {
     /// [`CannotOpen`]: crate::Error::CannotOpen
     /// [`NotADatabase`]: crate::Error::NotADatabase
     pub fn open<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
-        use rusqlite::OpenFlags;
-
-        // SQLITE_OPEN_NO_MUTEX is the default in rusqlite. Its docs explain why.
-        let flags = OpenFlags::SQLITE_OPEN_NO_MUTEX | OpenFlags::SQLITE_OPEN_READ_WRITE;
-
-        let mut conn = Connection::new(rusqlite::Connection::open_with_flags(path, flags)?);
-
-        conn.exec(|archive| archive.init(false))?;
-
-        Ok(conn)
+        ConnectionBuilder::new().open(path)
     }
 
     /// Create or open the SQLite archive at `path`.
@@ -85,18 +134,7 @@ impl Connection {
     ///
     /// [`NotADatabase`]: crate::Error::NotADatabase
     pub fn create<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
-        use rusqlite::OpenFlags;
-
-        // SQLITE_OPEN_NO_MUTEX is the default in rusqlite. Its docs explain why.
-        let flags = OpenFlags::SQLITE_OPEN_NO_MUTEX
-            | OpenFlags::SQLITE_OPEN_READ_WRITE
-            | OpenFlags::SQLITE_OPEN_CREATE;
-
-        let mut conn = Connection::new(rusqlite::Connection::open_with_flags(path, flags)?);
-
-        conn.exec(|archive| archive.init(false))?;
-
-        Ok(conn)
+        ConnectionBuilder::new().create(path)
     }
 
     /// Create a new SQLite archive at `path`.
@@ -109,66 +147,397 @@ impl Connection {
     ///
     /// [`SqlarAlreadyExists`]: crate::Error::SqlarAlreadyExists
     pub fn create_new<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
-        use rusqlite::OpenFlags;
-
-        // SQLITE_OPEN_NO_MUTEX is the default in rusqlite. Its docs explain why.
-        let flags = OpenFlags::SQLITE_OPEN_NO_MUTEX
-            | OpenFlags::SQLITE_OPEN_READ_WRITE
-            | OpenFlags::SQLITE_OPEN_CREATE;
-
-        let mut conn = Connection::new(rusqlite::Connection::open_with_flags(path, flags)?);
-
-        conn.exec(|archive| archive.init(true))?;
-
-        Ok(conn)
+        ConnectionBuilder::new().create_new(path)
     }
 
     /// Open a read-only connection to the SQLite archive at `path`.
     ///
-    /// This does not create a new SQLite archive if one does not already exist.
+    /// The database is opened with `SQLITE_OPEN_READONLY`, and nothing is ever written to it, so
+    /// this is safe to use on read-only media. Unlike [`Connection::open`], this never creates the
+    /// `sqlar` table. Any operation that would write to the archive fails with [`ReadOnly`]. See
+    /// [`Archive::is_read_only`].
     ///
     /// # Errors
     ///
     /// - [`CannotOpen`]: The database could not be opened because it does not exist.
     /// - [`NotADatabase`]: The file at `path` is not a SQLite database.
+    /// - [`NotAnArchive`]: The database doesn't have a `sqlar` table.
     ///
+    /// [`ReadOnly`]: crate::Error::ReadOnly
+    /// [`Archive::is_read_only`]: crate::Archive::is_read_only
     /// [`CannotOpen`]: crate::Error::CannotOpen
     /// [`NotADatabase`]: crate::Error::NotADatabase
+    /// [`NotAnArchive`]: crate::Error::NotAnArchive
     pub fn open_readonly<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
-        use rusqlite::OpenFlags;
+        ConnectionBuilder::new().open_readonly(path)
+    }
 
-        // SQLITE_OPEN_NO_MUTEX is the default in rusqlite. Its docs explain why.
-        let flags = OpenFlags::SQLITE_OPEN_NO_MUTEX | OpenFlags::SQLITE_OPEN_READ_ONLY;
+    /// Create a new in-memory SQLite archive.
+    pub fn open_in_memory() -> crate::Result<Self> {
+        ConnectionBuilder::new().open_in_memory()
+    }
 
-        let mut conn = Connection::new(rusqlite::Connection::open_with_flags(path, flags)?);
+    /// Build a new SQLite archive and then move it to `path` all at once.
+    ///
+    /// The archive is created in a temporary file in the same directory as `path`, and `f` is
+    /// called within a transaction to fill it in. Once `f` returns successfully and the transaction
+    /// is committed, the temporary file is renamed to `path`, replacing any file that's already
+    /// there. Because the rename is atomic, anyone watching `path` will only ever see the old file
+    /// or the finished archive, never one that's partially built.
+    ///
+    /// If `f` returns an error, the temporary file is deleted and `path` is left alone.
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: The given `path` doesn't have a file name.
+    /// - [`NoParentDirectory`]: The parent directory of `path` does not exist.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// # let temp_dir = tempfile::tempdir()?;
+    /// let path = temp_dir.path().join("archive.sqlar");
+    ///
+    /// Connection::create_atomically(&path, |archive| {
+    ///     let mut file = archive.open("file")?;
+    ///     file.create_file()?;
+    ///     file.write_str("Hello, world!")
+    /// })?;
+    ///
+    /// assert!(path.exists());
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    /// [`NoParentDirectory`]: crate::Error::NoParentDirectory
+    pub fn create_atomically<P, T, E, F>(path: P, f: F) -> Result<T, E>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&mut Archive) -> Result<T, E>,
+        E: From<crate::Error>,
+    {
+        ConnectionBuilder::new().create_atomically(path, f)
+    }
 
-        conn.exec(|archive| archive.init(false))?;
+    /// Shrink the database file by removing up to `pages` pages of free space.
+    ///
+    /// If `pages` is `None`, this removes all the free space. This only does anything if the
+    /// archive was created with [`AutoVacuum::Incremental`].
+    ///
+    /// [`AutoVacuum::Incremental`]: crate::AutoVacuum::Incremental
+    pub fn incremental_vacuum(&mut self, pages: Option<u32>) -> crate::Result<()> {
+        match pages {
+            Some(pages) => self
+                .conn
+                .execute_batch(&format!("PRAGMA incremental_vacuum({pages});"))?,
+            None => self.conn.execute_batch("PRAGMA incremental_vacuum;")?,
+        }
 
-        Ok(conn)
+        Ok(())
     }
 
-    /// Create a new in-memory SQLite archive.
-    pub fn open_in_memory() -> crate::Result<Self> {
-        let mut conn = Self::new(rusqlite::Connection::open_in_memory()?);
+    /// Return statistics about how much memory SQLite may use.
+    ///
+    /// This reports the size of this connection's page cache and the process-wide heap limits.
+    /// It doesn't report how much memory SQLite is currently using, because SQLite only exposes
+    /// that through its C API.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::ConnectionBuilder;
+    /// let connection = ConnectionBuilder::new()
+    ///     .soft_heap_limit(64 * 1024 * 1024)
+    ///     .open_in_memory()?;
+    ///
+    /// let stats = connection.memory_stats()?;
+    ///
+    /// assert_eq!(stats.soft_heap_limit(), Some(64 * 1024 * 1024));
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    pub fn memory_stats(&self) -> crate::Result<MemoryStats> {
+        read_memory_stats(&self.conn)
+    }
 
-        conn.exec(|archive| archive.init(true))?;
+    /// Copy the filesystem directory tree at `from` into the archive at `to`, committing after
+    /// every `files_per_commit` files.
+    ///
+    /// This is the same as [`Archive::archive_with`] with [`ArchiveOptions::resumable`] set, except
+    /// that the work is split across several transactions. If archiving is interrupted, whether
+    /// by an error, a crash, or the process being killed, the files archived before the last
+    /// commit stay in the archive, and calling this again with the same arguments picks up where
+    /// it left off.
+    ///
+    /// Progress passed to [`ArchiveOptions::on_progress`] is counted separately for each
+    /// transaction.
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: `files_per_commit` was `0`.
+    ///
+    /// This can also return any of the errors returned by [`Archive::archive_with`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use sqlarfs::{ArchiveOptions, Connection};
+    /// let mut connection = Connection::open("backup.sqlar")?;
+    ///
+    /// connection.archive_resumable("/home/user", "user", &ArchiveOptions::new(), 1000)?;
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    pub fn archive_resumable<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        from: P,
+        to: Q,
+        opts: &ArchiveOptions,
+        files_per_commit: u64,
+    ) -> crate::Result<()> {
+        if files_per_commit == 0 {
+            return Err(crate::Error::InvalidArgs {
+                reason: String::from("The number of files per commit must be greater than zero."),
+            });
+        }
 
-        Ok(conn)
+        let opts = opts.clone().resumable(true);
+
+        loop {
+            let is_done = self.exec(|archive| {
+                archive.archive_tree(
+                    from.as_ref(),
+                    to.as_ref(),
+                    &opts,
+                    #[cfg(unix)]
+                    &super::mode::UnixModeAdapter,
+                    #[cfg(windows)]
+                    &super::mode::WindowsModeAdapter,
+                    Some(files_per_commit),
+                )
+            })?;
+
+            if is_done {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Store the contents of `reader` as the only document in this archive.
+    ///
+    /// This is for using an archive as a compressed container for a single document. The contents
+    /// are written to the regular file at [`SINGLE_ENTRY`], which is created if it doesn't exist
+    /// and replaced if it does, and its mtime is set to now. Any other files in the archive are
+    /// left alone, so you can store metadata alongside the document. Read the document back with
+    /// [`Connection::load_single`].
+    ///
+    /// # Errors
+    ///
+    /// - [`NotARegularFile`]: The file at [`SINGLE_ENTRY`] is a directory or a symbolic link.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// let mut connection = Connection::open_in_memory()?;
+    ///
+    /// connection.store_single("Hello, world!".as_bytes())?;
+    ///
+    /// assert_eq!(connection.load_single()?, b"Hello, world!");
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`SINGLE_ENTRY`]: crate::SINGLE_ENTRY
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    pub fn store_single<R: Read>(&mut self, mut reader: R) -> crate::Result<()> {
+        self.exec(|archive| archive.store_single_entry(&mut reader))
+    }
+
+    /// Read the contents of the only document in this archive.
+    ///
+    /// This reads the file at [`SINGLE_ENTRY`], as written by [`Connection::store_single`]. If
+    /// there is no file at that path but the archive contains exactly one file, that file is read
+    /// instead, so this works with single-file archives created by other tools.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: The archive is empty.
+    /// - [`InvalidArgs`]: There is no file at [`SINGLE_ENTRY`] and the archive contains more than
+    ///   one file.
+    /// - [`NotARegularFile`]: The file being read is a directory or a symbolic link.
+    ///
+    /// [`SINGLE_ENTRY`]: crate::SINGLE_ENTRY
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    /// [`NotARegularFile`]: crate::Error::NotARegularFile
+    pub fn load_single(&mut self) -> crate::Result<Vec<u8>> {
+        self.exec(|archive| archive.load_single_entry())
+    }
+
+    /// Return whether this database is a SQLite archive, meaning it has a `sqlar` table.
+    ///
+    /// Every connection has a `sqlar` table when it's opened, but the table can be dropped
+    /// afterward, like by another connection to the same database.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// let connection = Connection::open_in_memory()?;
+    ///
+    /// assert!(connection.is_archive()?);
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    pub fn is_archive(&self) -> crate::Result<bool> {
+        table_exists(&self.conn, &self.table)
+    }
+
+    /// The name of the table this connection stores files in.
+    ///
+    /// This is `sqlar` unless the connection was opened with [`ConnectionBuilder::table_name`].
+    pub fn table_name(&self) -> &str {
+        &self.table
+    }
+
+    /// Rename the table this connection stores files in to `name`.
+    ///
+    /// This renames the tables sqlarfs uses to store extra data about files along with it, all in
+    /// one transaction. Afterward, this connection uses the new name. This is for migrating an
+    /// existing archive into a database that holds several archives, or back again; see
+    /// [`ConnectionBuilder::table_name`].
+    ///
+    /// Other connections to the same database need to be reopened with the new name.
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: `name` is not a valid table name.
+    /// - [`NotAnArchive`]: The archive table doesn't exist.
+    /// - [`SqlarAlreadyExists`]: A table named `name` already exists.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::Connection;
+    /// let mut connection = Connection::open_in_memory()?;
+    ///
+    /// connection.rename_table("photos")?;
+    ///
+    /// assert_eq!(connection.table_name(), "photos");
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    /// [`NotAnArchive`]: crate::Error::NotAnArchive
+    /// [`SqlarAlreadyExists`]: crate::Error::SqlarAlreadyExists
+    pub fn rename_table(&mut self, name: &str) -> crate::Result<()> {
+        let mut store = Store::new(self.conn.transaction()?, Arc::clone(&self.table));
+        store.rename_table(name)?;
+        store.into_tx().commit()?;
+
+        self.table = Arc::from(name);
+        self.lock_namespace = table_lock_namespace(&self.conn, name);
+
+        Ok(())
+    }
+
+    /// Change the key the database is encrypted with.
+    ///
+    /// This re-encrypts the whole database with `key`, so it may take a while for a large
+    /// archive. Afterward, the archive can only be opened with the new key. The database must
+    /// already be encrypted; see [`ConnectionBuilder::encryption_key`].
+    ///
+    /// Other connections to the same database need to be reopened with the new key.
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: This connection was not opened with an encryption key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::ConnectionBuilder;
+    /// # let temp_dir = tempfile::tempdir()?;
+    /// # let db_path = temp_dir.path().join("test.sqlar");
+    /// let mut connection = ConnectionBuilder::new()
+    ///     .encryption_key("old key")
+    ///     .create_new(&db_path)?;
+    ///
+    /// connection.rekey("new key")?;
+    /// drop(connection);
+    ///
+    /// let connection = ConnectionBuilder::new()
+    ///     .encryption_key("new key")
+    ///     .open(&db_path)?;
+    /// # sqlarfs::Result::Ok(())
+    /// ```
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    #[cfg(feature = "sqlcipher")]
+    pub fn rekey(&mut self, key: &str) -> crate::Result<()> {
+        if self.key.is_none() {
+            return Err(crate::Error::InvalidArgs {
+                reason: String::from(
+                    "Cannot change the encryption key of a database that isn't encrypted.",
+                ),
+            });
+        }
+
+        self.conn.pragma_update(None, "rekey", key)?;
+        self.key = Some(EncryptionKey::new(key));
+
+        Ok(())
     }
 
     /// Start a new transaction.
+    ///
+    /// # Errors
+    ///
+    /// - [`NotAnArchive`]: The connection was opened with
+    ///   [`ConnectionBuilder::create_sqlar_table`] set to `false`, and the database no longer has
+    ///   a `sqlar` table.
+    ///
+    /// [`NotAnArchive`]: crate::Error::NotAnArchive
     pub fn transaction(&mut self) -> crate::Result<Transaction> {
-        Ok(Transaction::new(self.conn.transaction()?))
+        if self.require_sqlar_table {
+            check_is_archive(&self.conn, &self.table)?;
+        }
+
+        let tx = Transaction::new(
+            self.conn.transaction()?,
+            Arc::clone(&self.lock_namespace),
+            Arc::clone(&self.table),
+        );
+
+        #[cfg(feature = "sqlcipher")]
+        let tx = tx.with_encryption_key(self.key.clone());
+
+        Ok(tx)
     }
 
     /// Start a new transaction with the given [`TransactionBehavior`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Connection::transaction`].
     pub fn transaction_with(
         &mut self,
         behavior: TransactionBehavior,
     ) -> crate::Result<Transaction> {
-        Ok(Transaction::new(
+        if self.require_sqlar_table {
+            check_is_archive(&self.conn, &self.table)?;
+        }
+
+        let tx = Transaction::new(
             self.conn.transaction_with_behavior(behavior.inner())?,
-        ))
+            Arc::clone(&self.lock_namespace),
+            Arc::clone(&self.table),
+        );
+
+        #[cfg(feature = "sqlcipher")]
+        let tx = tx.with_encryption_key(self.key.clone());
+
+        Ok(tx)
     }
 
     /// Execute the given function within a new transaction.
@@ -207,8 +576,7 @@ impl Connection {
 /// let mut tx = connection.transaction()?;
 /// let archive = tx.archive_mut();
 ///
-/// let mut file = archive.open("file")?;
-/// file.create_file()?;
+/// archive.open("file")?.create_file()?;
 ///
 /// tx.commit()?;
 /// # sqlarfs::Result::Ok(())
@@ -220,12 +588,22 @@ pub struct Transaction<'conn> {
 }
 
 impl<'conn> Transaction<'conn> {
-    pub(super) fn new(tx: rusqlite::Transaction<'conn>) -> Self {
+    pub(super) fn new(
+        tx: rusqlite::Transaction<'conn>,
+        lock_namespace: Arc<str>,
+        table: Arc<str>,
+    ) -> Self {
         Self {
-            archive: Archive::new(tx),
+            archive: Archive::new(tx, lock_namespace, table),
         }
     }
 
+    #[cfg(feature = "sqlcipher")]
+    fn with_encryption_key(mut self, key: Option<EncryptionKey>) -> Self {
+        self.archive.encryption_key = key;
+        self
+    }
+
     /// Execute the given function within this transaction.
     ///
     /// This calls the given function, passing the [`Archive`] holding this transaction. If the
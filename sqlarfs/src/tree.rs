@@ -1,26 +1,156 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::fs;
-use std::io;
-use std::path::{Path, PathBuf};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::FileMetadata;
+use crate::{FileMetadata, FileMode};
 
+use super::anchor::AnchoredDir;
 use super::archive::Archive;
-use super::list::ListOptions;
-use super::metadata::FileType;
-use super::mode::{ReadMode, WriteMode};
+use super::digest::Digest;
+use super::error::ErrorCollector;
+use super::escape::{escape_path, restore_file_name};
+use super::filter::{apply_on_archive, apply_on_extract};
+use super::inline::InlineBatch;
+use super::list::{ListEntry, ListOptions};
+use super::metadata::{mode_from_umask, FileType};
+use super::mode::{probe_capabilities, Capabilities, ReadMode, WriteMode};
+use super::newline::NewlineConverter;
+use super::progress::{Progress, ProgressCallback, ProgressTracker};
+use super::stream::Compression;
+#[cfg(not(unix))]
+use super::symlink::Resolved;
+use super::template::Substituter;
+use super::util::{
+    clamp_to_source_date_epoch, long_path, looks_like_text, u64_from_usize, TEXT_SNIFF_LEN,
+};
+
+// The largest buffer we'll use to copy file contents out of the archive when extracting.
+const EXTRACT_BUF_SIZE: usize = 1024 * 256;
+
+/// What to do when archiving a file that already exists in the archive.
+///
+/// This is used with [`ArchiveOptions::overwrite`].
+///
+/// When the file being archived and the file in the archive are both directories, the directory
+/// in the archive is kept and the contents of the source directory are merged into it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum OverwritePolicy {
+    /// Return an error.
+    #[default]
+    Error,
+
+    /// Replace the file in the archive with the file being archived.
+    ///
+    /// For directories that are merged, this updates the metadata of the directory in the
+    /// archive.
+    Replace,
+
+    /// Leave the file in the archive alone and skip archiving the file.
+    ///
+    /// For directories that are merged, this leaves the metadata of the directory in the archive
+    /// alone.
+    Skip,
+}
+
+/// What to do with macOS Finder metadata when archiving files.
+///
+/// When files are copied from macOS to a filesystem that doesn't support extended attributes,
+/// macOS stores their extended attributes and resource forks in [AppleDouble] files alongside
+/// them, named `._` followed by the name of the original file. The Finder also leaves `.DS_Store`
+/// files in directories it has opened.
+///
+/// This is used with [`ArchiveOptions::apple_metadata`].
+///
+/// [AppleDouble]: https://en.wikipedia.org/wiki/AppleSingle_and_AppleDouble_formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum AppleMetadata {
+    /// Archive AppleDouble and `.DS_Store` files like any other file.
+    #[default]
+    Keep,
+
+    /// Leave AppleDouble and `.DS_Store` files out of the archive.
+    Skip,
+}
 
 /// Options for archiving files in the filesystem to an [`Archive`].
 ///
 /// This is used with [`Archive::archive_with`].
 ///
+/// With the `serde` feature enabled, this can be loaded from a config file. Missing fields get
+/// their default values. Callbacks like [`ArchiveOptions::exclude`],
+/// [`ArchiveOptions::map_path`], and [`ArchiveOptions::on_progress`] can't be loaded this way and
+/// are left unset.
+///
 /// [`Archive`]: crate::Archive
 /// [`Archive::archive_with`]: crate::Archive::archive_with
-#[derive(Debug, Clone)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct ArchiveOptions {
     follow_symlinks: bool,
     children: bool,
     recursive: bool,
     preserve_metadata: bool,
+    deterministic: bool,
+    deterministic_mtime: SystemTime,
+    source_date_epoch: bool,
+    overwrite: OverwritePolicy,
+    store_empty_dirs: bool,
+    prefix: Option<PathBuf>,
+    apple_metadata: AppleMetadata,
+    escape_names: bool,
+    compression: Option<Compression>,
+    resumable: bool,
+    deduplicate: bool,
+    inline_threshold: u64,
+    batch_size: usize,
+    keep_going: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    exclude: Option<Arc<ExcludeFilter>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    map_path: Option<Arc<PathMapper>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    on_progress: Option<Arc<ProgressCallback>>,
+}
+
+impl fmt::Debug for ArchiveOptions {
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArchiveOptions")
+            .field("follow_symlinks", &self.follow_symlinks)
+            .field("children", &self.children)
+            .field("recursive", &self.recursive)
+            .field("preserve_metadata", &self.preserve_metadata)
+            .field("deterministic", &self.deterministic)
+            .field("deterministic_mtime", &self.deterministic_mtime)
+            .field("source_date_epoch", &self.source_date_epoch)
+            .field("overwrite", &self.overwrite)
+            .field("store_empty_dirs", &self.store_empty_dirs)
+            .field("prefix", &self.prefix)
+            .field("apple_metadata", &self.apple_metadata)
+            .field("escape_names", &self.escape_names)
+            .field("compression", &self.compression)
+            .field("resumable", &self.resumable)
+            .field("deduplicate", &self.deduplicate)
+            .field("inline_threshold", &self.inline_threshold)
+            .field("batch_size", &self.batch_size)
+            .field("keep_going", &self.keep_going)
+            .field("exclude", &self.exclude.as_ref().map(|_| ".."))
+            .field("map_path", &self.map_path.as_ref().map(|_| ".."))
+            .field("on_progress", &self.on_progress.as_ref().map(|_| ".."))
+            .finish()
+    }
 }
 
 impl Default for ArchiveOptions {
@@ -37,6 +167,23 @@ impl ArchiveOptions {
             children: false,
             recursive: true,
             preserve_metadata: true,
+            deterministic: false,
+            deterministic_mtime: UNIX_EPOCH,
+            source_date_epoch: false,
+            overwrite: OverwritePolicy::Error,
+            store_empty_dirs: true,
+            prefix: None,
+            apple_metadata: AppleMetadata::Keep,
+            escape_names: false,
+            compression: None,
+            resumable: false,
+            deduplicate: false,
+            inline_threshold: 4 * 1024,
+            batch_size: 100,
+            keep_going: false,
+            exclude: None,
+            map_path: None,
+            on_progress: None,
         }
     }
 
@@ -81,18 +228,521 @@ impl ArchiveOptions {
         self.preserve_metadata = preserve;
         self
     }
+
+    /// Archive files deterministically.
+    ///
+    /// If this is `true`, archiving the same directory tree into an empty archive always produces
+    /// a byte-for-byte identical archive, regardless of when it was archived or who owns the
+    /// files. This is useful for build caching and verifying build artifacts. Specifically:
+    ///
+    /// - Files are added to the archive in sorted order.
+    /// - Every file gets the same mtime, which you can set with
+    ///   [`ArchiveOptions::deterministic_mtime`].
+    /// - Directories and executable files get `755` permissions, and all other regular files get
+    ///   `644` permissions.
+    ///
+    /// This overrides [`ArchiveOptions::preserve_metadata`].
+    ///
+    /// The default is `false`.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// The mtime to give every file when [`ArchiveOptions::deterministic`] is `true`.
+    ///
+    /// The default is the Unix epoch.
+    pub fn deterministic_mtime(mut self, mtime: SystemTime) -> Self {
+        self.deterministic_mtime = mtime;
+        self
+    }
+
+    /// Honor the `SOURCE_DATE_EPOCH` environment variable.
+    ///
+    /// If this is `true` and [`SOURCE_DATE_EPOCH`] is set, any file whose mtime would be later
+    /// than that timestamp gets that timestamp as its mtime instead. Archiving returns an error if
+    /// the environment variable is set but isn't a valid Unix timestamp.
+    ///
+    /// The default is `false`.
+    ///
+    /// [`SOURCE_DATE_EPOCH`]: https://reproducible-builds.org/specs/source-date-epoch/
+    pub fn source_date_epoch(mut self, honor: bool) -> Self {
+        self.source_date_epoch = honor;
+        self
+    }
+
+    /// What to do when a file being archived already exists in the archive.
+    ///
+    /// The default is [`OverwritePolicy::Error`].
+    pub fn overwrite(mut self, policy: OverwritePolicy) -> Self {
+        self.overwrite = policy;
+        self
+    }
+
+    /// Archive directories that don't contain any files.
+    ///
+    /// If this is `false`, directories in the source tree that end up with no children in the
+    /// archive are left out. Directories that already existed in the archive are never removed.
+    /// This has no effect if [`ArchiveOptions::recursive`] is `false`.
+    ///
+    /// The default is `true`.
+    pub fn store_empty_dirs(mut self, store: bool) -> Self {
+        self.store_empty_dirs = store;
+        self
+    }
+
+    /// Put the archived files under this directory in the archive.
+    ///
+    /// The prefix is prepended to the destination path, so archiving `src` to `dest` with the
+    /// prefix `backups/2024-06-01` puts it at `backups/2024-06-01/dest`. Any directories in the
+    /// prefix that don't already exist in the archive are created.
+    ///
+    /// The default is no prefix.
+    pub fn prefix<P: AsRef<Path>>(mut self, prefix: P) -> Self {
+        self.prefix = Some(prefix.as_ref().to_path_buf());
+        self
+    }
+
+    /// What to do with macOS Finder metadata files found while archiving a directory.
+    ///
+    /// This only applies to files found inside the source directory; the source file itself is
+    /// always archived.
+    ///
+    /// The default is [`AppleMetadata::Keep`].
+    pub fn apple_metadata(mut self, policy: AppleMetadata) -> Self {
+        self.apple_metadata = policy;
+        self
+    }
+
+    /// Archive files whose names aren't valid Unicode instead of returning an error.
+    ///
+    /// SQLite archives can only store paths that are valid Unicode. If this is `true`, any part of
+    /// a file name that isn't valid UTF-8 is percent-encoded, along with any literal `%`
+    /// characters in that file name. For example, a file named `caf\xE9` is archived as `caf%E9`.
+    /// File names that are valid Unicode are archived as-is.
+    ///
+    /// The original bytes of each escaped file name are stored in a separate table in the
+    /// database, and on Unix-like systems, [`Archive::extract_with`] uses them to restore the
+    /// original file name. Other tools that read SQLite archives will see the escaped name. If the
+    /// file is renamed in the archive, it's extracted under its new name.
+    ///
+    /// If this is `false`, archiving a file whose name isn't valid Unicode returns an
+    /// [`Error::InvalidArgs`].
+    ///
+    /// The default is `false`.
+    ///
+    /// [`Archive::extract_with`]: crate::Archive::extract_with
+    /// [`Error::InvalidArgs`]: crate::Error::InvalidArgs
+    pub fn escape_names(mut self, escape: bool) -> Self {
+        self.escape_names = escape;
+        self
+    }
+
+    /// The compression method to use for the files being archived.
+    ///
+    /// The default is the same as the default for [`File::set_compression`].
+    ///
+    /// [`File::set_compression`]: crate::File::set_compression
+    pub fn compression(mut self, method: Compression) -> Self {
+        self.compression = Some(method);
+        self
+    }
+
+    /// Record which files have been archived so an interrupted run can pick up where it left off.
+    ///
+    /// If this is `true`, each file is recorded in a `sqlar_jobs` table in the archive as it's
+    /// archived. If archiving the same source to the same destination is interrupted and then
+    /// started again, files that were already archived are skipped rather than causing an error,
+    /// and directories that were only partially archived are finished. The records are deleted
+    /// once archiving completes.
+    ///
+    /// The records are only durable once the transaction they were written in is committed, so
+    /// this is most useful with [`Connection::archive_resumable`], which commits periodically.
+    ///
+    /// The default is `false`.
+    ///
+    /// [`Connection::archive_resumable`]: crate::Connection::archive_resumable
+    pub fn resumable(mut self, resumable: bool) -> Self {
+        self.resumable = resumable;
+        self
+    }
+
+    /// Store the contents of identical files only once.
+    ///
+    /// If this is `true`, each regular file is deduplicated with [`Archive::deduplicate`] as it's
+    /// archived. A file's contents are only shared once another file with the same contents is in
+    /// the archive, and only files that were archived this way or checked by
+    /// [`Archive::deduplicate`] are compared. This keeps the archive from growing when the
+    /// directory tree has many copies of the same files, but the files that are deduplicated can't
+    /// be read by other sqlar tools.
+    ///
+    /// The default is `false`.
+    ///
+    /// [`Archive::deduplicate`]: crate::Archive::deduplicate
+    pub fn deduplicate(mut self, deduplicate: bool) -> Self {
+        self.deduplicate = deduplicate;
+        self
+    }
+
+    /// The largest regular file, in bytes, to write with a single insert.
+    ///
+    /// Regular files no larger than this are read into memory and inserted along with their
+    /// metadata and contents, rather than being created and then written to. Inserts are batched
+    /// according to [`ArchiveOptions::batch_size`]. This makes archiving trees with many tiny
+    /// files, like a `node_modules` directory, much faster, and it doesn't change what ends up in
+    /// the archive. Use [`Archive::inline_stats`] to check how many files were written this way.
+    ///
+    /// Files aren't written this way when [`ArchiveOptions::deduplicate`] or
+    /// [`ArchiveOptions::keep_going`] is enabled, or when their names need to be escaped with
+    /// [`ArchiveOptions::escape_names`]. Set this to `0` to disable it.
+    ///
+    /// The default is 4 KiB.
+    ///
+    /// [`Archive::inline_stats`]: crate::Archive::inline_stats
+    pub fn inline_threshold(mut self, threshold: u64) -> Self {
+        self.inline_threshold = threshold;
+        self
+    }
+
+    /// The maximum number of small files to insert with each statement.
+    ///
+    /// See [`ArchiveOptions::inline_threshold`]. This must be at least `1`.
+    ///
+    /// The default is `100`.
+    pub fn batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size;
+        self
+    }
+
+    /// Keep archiving the rest of the files when one of them fails.
+    ///
+    /// If this is `true`, a file that can't be archived, like one we don't have permission to
+    /// read, is left out of the archive instead of stopping the whole operation. Once every other
+    /// file has been archived, this returns an [`Error::Multiple`] with the error for each file
+    /// that failed, keyed by its path in the filesystem. When a directory fails, none of its
+    /// descendants are archived. Errors from the database itself still stop the operation right
+    /// away.
+    ///
+    /// Each file is archived in its own savepoint so that a file that fails partway through is
+    /// rolled back, which means small files aren't batched with
+    /// [`ArchiveOptions::inline_threshold`].
+    ///
+    /// The default is `false`.
+    ///
+    /// [`Error::Multiple`]: crate::Error::Multiple
+    pub fn keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// Skip files found while archiving a directory if this function returns `true`.
+    ///
+    /// The function is passed the path of each file in the filesystem. Like
+    /// [`ArchiveOptions::apple_metadata`], this only applies to files found inside the source
+    /// directory; the source file itself is always archived. When a directory is excluded, none
+    /// of its descendants are archived either.
+    ///
+    /// By default, no files are excluded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::ArchiveOptions;
+    /// let opts = ArchiveOptions::new().exclude(|path| {
+    ///     path.extension().is_some_and(|extension| extension == "tmp")
+    /// });
+    /// ```
+    pub fn exclude<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Path) -> bool + Send + Sync + 'static,
+    {
+        self.exclude = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Change the path each file is archived at, or leave it out of the archive.
+    ///
+    /// The function is passed the path each file would have in the archive, and returns the path
+    /// to archive it at instead, or `None` to skip it. When a directory is skipped, none of its
+    /// descendants are archived either. The children of a directory are archived under the path
+    /// the directory was mapped to, and then their own paths are passed to this function.
+    ///
+    /// Unlike [`ArchiveOptions::exclude`], this applies to the source file itself too. The parent
+    /// directory of each mapped path must already exist in the archive.
+    ///
+    /// By default, files are archived at their original paths.
+    ///
+    /// # Examples
+    ///
+    /// Archive every file with a lowercase path.
+    ///
+    /// ```
+    /// # use std::path::PathBuf;
+    /// # use sqlarfs::ArchiveOptions;
+    /// let opts = ArchiveOptions::new().map_path(|path| {
+    ///     Some(PathBuf::from(path.to_string_lossy().to_lowercase()))
+    /// });
+    /// ```
+    pub fn map_path<F>(mut self, mapper: F) -> Self
+    where
+        F: Fn(&Path) -> Option<PathBuf> + Send + Sync + 'static,
+    {
+        self.map_path = Some(Arc::new(mapper));
+        self
+    }
+
+    /// Call this function after each file is archived.
+    ///
+    /// The callback is passed a [`Progress`] with the path of the file in the archive and the
+    /// number of files and bytes archived so far. Files skipped because of
+    /// [`OverwritePolicy::Skip`] are reported too. The total number of files isn't known ahead of
+    /// time when archiving.
+    ///
+    /// By default, progress isn't reported.
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        self.on_progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Check for settings that conflict with each other.
+    ///
+    /// This returns every conflict it finds rather than stopping at the first one, which is useful
+    /// for reporting problems in options loaded from a config file. Archiving with these options
+    /// returns the first conflict as an error.
+    ///
+    /// # Errors
+    ///
+    /// Each conflict is returned as an [`InvalidArgs`] describing it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::ArchiveOptions;
+    /// let opts = ArchiveOptions::new().prefix("/backups");
+    ///
+    /// assert_eq!(opts.validate().unwrap_err().len(), 1);
+    /// ```
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    pub fn validate(&self) -> Result<(), Vec<crate::Error>> {
+        let mut conflicts = Vec::new();
+
+        if let Some(prefix) = &self.prefix {
+            if prefix.has_root() {
+                conflicts.push(crate::Error::InvalidArgs {
+                    reason: String::from("The prefix must be a relative path."),
+                });
+            }
+
+            if prefix
+                .components()
+                .any(|component| component == Component::ParentDir)
+            {
+                conflicts.push(crate::Error::InvalidArgs {
+                    reason: String::from("The prefix cannot contain `..` components."),
+                });
+            }
+        }
+
+        if self.batch_size == 0 {
+            conflicts.push(crate::Error::InvalidArgs {
+                reason: String::from("The batch size must be at least 1."),
+            });
+        }
+
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(conflicts)
+        }
+    }
+}
+
+/// What to do when extracting a file to a path that already exists in the filesystem.
+///
+/// This is returned by the callback passed to [`ExtractOptions::on_conflict`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConflictAction {
+    /// Replace the existing file.
+    ///
+    /// If the file being extracted and the existing file are both directories, the contents of
+    /// the directory are merged into the existing one. Otherwise, the existing file or directory
+    /// is deleted.
+    Overwrite,
+
+    /// Don't extract this file.
+    ///
+    /// If this file is a directory, none of its descendants are extracted either.
+    Skip,
+
+    /// Extract the file to a new path, made by appending this suffix to its file name.
+    ///
+    /// If the new path also exists, the callback is called again with the new path.
+    RenameWithSuffix(String),
+
+    /// Stop extracting and return an [`Error::FileAlreadyExists`].
+    ///
+    /// [`Error::FileAlreadyExists`]: crate::Error::FileAlreadyExists
+    Abort,
+}
+
+/// What to do when the filesystem being extracted into can't store some of the file metadata in
+/// the archive.
+///
+/// This is used with [`ExtractOptions::metadata_fallback`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum MetadataFallback {
+    /// Return an [`Error::UnsupportedMetadata`] before extracting anything.
+    ///
+    /// [`Error::UnsupportedMetadata`]: crate::Error::UnsupportedMetadata
+    Error,
+
+    /// Extract the files anyways, leaving out the metadata the filesystem can't store.
+    ///
+    /// Files are extracted with the default permissions, and symbolic links are skipped.
+    Ignore,
+}
+
+/// What to do with symbolic links when extracting on Windows.
+///
+/// Creating a symbolic link on Windows requires either administrator privileges or Developer
+/// Mode, so by default they're skipped.
+///
+/// This is used with [`ExtractOptions::windows_symlinks`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum WindowsSymlinkPolicy {
+    /// Don't extract symbolic links.
+    #[default]
+    Skip,
+
+    /// Create a real symbolic link, returning an error if we don't have the privileges to.
+    Symlink,
+
+    /// Create a junction point for symbolic links to directories.
+    ///
+    /// Junction points don't require any special privileges, but they can only point to
+    /// directories, so symbolic links to anything else are skipped.
+    Junction,
+
+    /// Extract a copy of the file or directory the symbolic link points to.
+    ///
+    /// Only targets inside the archive are copied, so symbolic links whose targets are outside of
+    /// the archive or don't exist are skipped. A symbolic link that points to one of its own
+    /// ancestors is skipped, as is one that would otherwise be copied into itself.
+    Copy,
+}
+
+/// What to do when extracting a file whose name is too long for the filesystem.
+///
+/// This is used with [`ExtractOptions::long_names`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum LongNamePolicy {
+    /// Return an [`Error::NameTooLong`].
+    ///
+    /// [`Error::NameTooLong`]: crate::Error::NameTooLong
+    #[default]
+    Error,
+
+    /// Shorten the file name so that it fits.
+    ///
+    /// The end of the file name is cut off and replaced with a short hash of the full file name,
+    /// so different long file names in the same directory are extracted to different files. The
+    /// file extension is kept.
+    Truncate,
+}
+
+/// How to convert the line endings in text files as they're extracted.
+///
+/// This is used with [`ExtractOptions::newline_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum NewlinePolicy {
+    /// Leave line endings as they are in the archive.
+    #[default]
+    Preserve,
+
+    /// Convert CRLF line endings to LF, the convention on Unix-like systems.
+    Lf,
+
+    /// Convert LF line endings to CRLF, the convention on Windows.
+    CrLf,
 }
 
+type ExcludeFilter = dyn Fn(&Path) -> bool + Send + Sync;
+
+type PathMapper = dyn Fn(&Path) -> Option<PathBuf> + Send + Sync;
+
+type ConflictResolver = dyn Fn(&Path, &FileMetadata, &fs::Metadata) -> ConflictAction + Send + Sync;
+
 /// Options for extracting files in an [`Archive`] into the filesystem.
 ///
 /// This is used with [`Archive::extract_with`].
 ///
+/// With the `serde` feature enabled, this can be loaded from a config file. Missing fields get
+/// their default values. Callbacks like [`ExtractOptions::on_conflict`] and
+/// [`ExtractOptions::on_progress`] can't be loaded this way and are left unset.
+///
 /// [`Archive`]: crate::Archive
 /// [`Archive::archive_with`]: crate::Archive::archive_with
-#[derive(Debug, Clone)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct ExtractOptions {
     children: bool,
     recursive: bool,
+    create_empty_dirs: bool,
+    strip_components: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    on_conflict: Option<Arc<ConflictResolver>>,
+    metadata_fallback: Option<MetadataFallback>,
+    template_vars: Option<HashMap<String, String>>,
+    newline_policy: NewlinePolicy,
+    long_names: LongNamePolicy,
+    skip_broken_symlinks: bool,
+    windows_symlinks: WindowsSymlinkPolicy,
+    anchor_root: bool,
+    secure: bool,
+    keep_going: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    on_progress: Option<Arc<ProgressCallback>>,
+}
+
+impl fmt::Debug for ExtractOptions {
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtractOptions")
+            .field("children", &self.children)
+            .field("recursive", &self.recursive)
+            .field("create_empty_dirs", &self.create_empty_dirs)
+            .field("strip_components", &self.strip_components)
+            .field("on_conflict", &self.on_conflict.as_ref().map(|_| ".."))
+            .field("metadata_fallback", &self.metadata_fallback)
+            .field("template_vars", &self.template_vars)
+            .field("newline_policy", &self.newline_policy)
+            .field("long_names", &self.long_names)
+            .field("skip_broken_symlinks", &self.skip_broken_symlinks)
+            .field("windows_symlinks", &self.windows_symlinks)
+            .field("anchor_root", &self.anchor_root)
+            .field("secure", &self.secure)
+            .field("keep_going", &self.keep_going)
+            .field("on_progress", &self.on_progress.as_ref().map(|_| ".."))
+            .finish()
+    }
 }
 
 impl Default for ExtractOptions {
@@ -107,6 +757,19 @@ impl ExtractOptions {
         Self {
             children: false,
             recursive: true,
+            create_empty_dirs: true,
+            strip_components: 0,
+            on_conflict: None,
+            metadata_fallback: None,
+            template_vars: None,
+            newline_policy: NewlinePolicy::Preserve,
+            long_names: LongNamePolicy::Error,
+            skip_broken_symlinks: false,
+            windows_symlinks: WindowsSymlinkPolicy::Skip,
+            anchor_root: false,
+            secure: true,
+            keep_going: false,
+            on_progress: None,
         }
     }
 
@@ -132,10 +795,336 @@ impl ExtractOptions {
         self.recursive = recursive;
         self
     }
+
+    /// Create directories that have no children in the archive.
+    ///
+    /// If this is `false`, directories in the archive that don't contain any files are skipped.
+    /// See [`ListOptions::empty_dirs`].
+    ///
+    /// The default is `true`.
+    ///
+    /// [`ListOptions::empty_dirs`]: crate::ListOptions::empty_dirs
+    pub fn create_empty_dirs(mut self, create: bool) -> Self {
+        self.create_empty_dirs = create;
+        self
+    }
+
+    /// Strip this many leading components from the path of each file before extracting it.
+    ///
+    /// Paths are stripped relative to the source directory, so extracting `a/b/file` from the root
+    /// of the archive with `strip_components(1)` puts it at `b/file` in the destination directory.
+    /// Files with no more than `n` components, including the directories being stripped, are
+    /// skipped.
+    ///
+    /// This can only be used when [`ExtractOptions::children`] is `true`.
+    ///
+    /// The default is `0`.
+    pub fn strip_components(mut self, n: usize) -> Self {
+        self.strip_components = n;
+        self
+    }
+
+    /// Decide what to do when a file being extracted already exists in the filesystem.
+    ///
+    /// The callback is passed the path the file is being extracted to, the metadata of the file in
+    /// the archive, and the metadata of the file that already exists. It returns a
+    /// [`ConflictAction`] saying how to proceed.
+    ///
+    /// If this isn't set, extracting returns an [`Error::FileAlreadyExists`] on the first conflict,
+    /// which is the same as always returning [`ConflictAction::Abort`].
+    ///
+    /// # Examples
+    ///
+    /// Keep whichever copy of each file is newer.
+    ///
+    /// ```
+    /// # use sqlarfs::{ConflictAction, ExtractOptions};
+    /// let opts = ExtractOptions::new().on_conflict(|_, archived, existing| {
+    ///     match (archived.mtime(), existing.modified().ok()) {
+    ///         (Some(archived), Some(existing)) if archived > existing => ConflictAction::Overwrite,
+    ///         _ => ConflictAction::Skip,
+    ///     }
+    /// });
+    /// ```
+    ///
+    /// [`Error::FileAlreadyExists`]: crate::Error::FileAlreadyExists
+    pub fn on_conflict<F>(mut self, resolver: F) -> Self
+    where
+        F: Fn(&Path, &FileMetadata, &fs::Metadata) -> ConflictAction + Send + Sync + 'static,
+    {
+        self.on_conflict = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Check whether the destination filesystem can store the file metadata being extracted.
+    ///
+    /// Some filesystems, like FAT32 and some network shares, can't store Unix file permissions or
+    /// symbolic links. If this is set, the destination is probed before extracting anything by
+    /// briefly creating some files in it, and the given [`MetadataFallback`] decides what happens
+    /// if it's missing support for metadata the files being extracted have.
+    ///
+    /// By default, the destination isn't probed, and any errors from setting file metadata are
+    /// returned as they happen.
+    pub fn metadata_fallback(mut self, fallback: MetadataFallback) -> Self {
+        self.metadata_fallback = Some(fallback);
+        self
+    }
+
+    /// Replace `{{name}}` tokens in the contents of text files with the values of the given
+    /// variables.
+    ///
+    /// This lets you store a project template in an archive and instantiate it directly. Spaces
+    /// around the variable name are ignored, so `{{ name }}` works too. Tokens naming variables
+    /// that aren't in `vars` are left as they are.
+    ///
+    /// Substitution happens while the files are being extracted, so the extracted files can be a
+    /// different size than the files in the archive. Files that look like binary files, because
+    /// they contain a NUL byte near the start, are extracted unchanged.
+    ///
+    /// By default, file contents are extracted unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::ExtractOptions;
+    /// let opts = ExtractOptions::new().template_vars([
+    ///     ("project_name", "my-project"),
+    ///     ("author", "Jane Doe"),
+    /// ]);
+    /// ```
+    pub fn template_vars<I, K, V>(mut self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.template_vars = Some(
+            vars.into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+        );
+        self
+    }
+
+    /// Convert the line endings in text files as they're extracted.
+    ///
+    /// This is for sharing archives between Windows and Unix-like systems. Only files that look
+    /// like text are converted, meaning the start of the file is valid UTF-8 and contains no NUL
+    /// bytes. See [`File::is_probably_text`]. Lone CR characters are left alone.
+    ///
+    /// Like [`ExtractOptions::template_vars`], this means the extracted files can be a different
+    /// size than the files in the archive.
+    ///
+    /// The default is [`NewlinePolicy::Preserve`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::{ExtractOptions, NewlinePolicy};
+    /// let opts = ExtractOptions::new().newline_policy(NewlinePolicy::CrLf);
+    /// ```
+    ///
+    /// [`File::is_probably_text`]: crate::File::is_probably_text
+    pub fn newline_policy(mut self, policy: NewlinePolicy) -> Self {
+        self.newline_policy = policy;
+        self
+    }
+
+    /// What to do when a file name is too long for the filesystem.
+    ///
+    /// Most filesystems limit file names to 255 bytes, but SQLite archives have no such limit. A
+    /// file in the archive with a longer file name is handled according to this policy. This only
+    /// applies to the files inside the source directory, not the destination path you pass.
+    ///
+    /// The default is [`LongNamePolicy::Error`].
+    pub fn long_names(mut self, policy: LongNamePolicy) -> Self {
+        self.long_names = policy;
+        self
+    }
+
+    /// Skip symbolic links whose targets don't exist.
+    ///
+    /// If this is `true`, a symbolic link whose target would be missing once it's extracted is
+    /// not extracted. Targets inside the archive are resolved against the archive, the same as
+    /// [`Archive::find_broken_symlinks`]. Targets outside of the archive, such as absolute paths,
+    /// are checked against the filesystem relative to where the link would be extracted.
+    ///
+    /// The default is `false`.
+    ///
+    /// [`Archive::find_broken_symlinks`]: crate::Archive::find_broken_symlinks
+    pub fn skip_broken_symlinks(mut self, skip: bool) -> Self {
+        self.skip_broken_symlinks = skip;
+        self
+    }
+
+    /// What to do with symbolic links when extracting on Windows.
+    ///
+    /// This has no effect on other platforms, where symbolic links are always extracted as
+    /// symbolic links.
+    ///
+    /// The default is [`WindowsSymlinkPolicy::Skip`].
+    pub fn windows_symlinks(mut self, policy: WindowsSymlinkPolicy) -> Self {
+        self.windows_symlinks = policy;
+        self
+    }
+
+    /// Create files relative to a file descriptor for the destination directory.
+    ///
+    /// If this is `true`, the directory being extracted into is opened once, and every file is
+    /// created relative to it using `openat(2)`, `mkdirat(2)`, and `symlinkat(2)`, resolving each
+    /// path one component at a time without following symbolic links. This prevents an attacker
+    /// who can write to the destination from redirecting files outside of it by swapping a
+    /// directory for a symbolic link partway through extracting.
+    ///
+    /// Because symbolic links are never followed, a file whose parent directory in the
+    /// destination is a symbolic link, including one extracted from the archive, fails with
+    /// [`PathEscapesRoot`]. This can't be combined with [`ExtractOptions::on_conflict`], and it's
+    /// only supported on Unix-like systems.
+    ///
+    /// The default is `false`.
+    ///
+    /// [`PathEscapesRoot`]: crate::Error::PathEscapesRoot
+    pub fn anchor_root(mut self, anchor: bool) -> Self {
+        self.anchor_root = anchor;
+        self
+    }
+
+    /// Refuse to extract files outside of the destination directory.
+    ///
+    /// If this is `true`, the path each file would be extracted to is checked before anything is
+    /// written to it. Archives created by other tools can contain paths with `..` components or
+    /// symbolic links followed by files underneath them, either of which could otherwise be used
+    /// to write files anywhere on the filesystem. Files whose path has a `..` component, or whose
+    /// parent directory resolves to somewhere outside of the destination directory after
+    /// following symbolic links, fail with [`PathEscapesRoot`]. This includes symbolic links that
+    /// already existed in the destination directory.
+    ///
+    /// When extracting the children of a directory, the destination directory is the directory
+    /// being extracted into. Otherwise, it's the parent of the path being extracted to.
+    ///
+    /// Only disable this if you trust the archive.
+    ///
+    /// The default is `true`.
+    ///
+    /// [`PathEscapesRoot`]: crate::Error::PathEscapesRoot
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Keep extracting the rest of the files when one of them fails.
+    ///
+    /// If this is `true`, a file that can't be extracted, like one that conflicts with a file
+    /// that's already in the destination directory, is skipped instead of stopping the whole
+    /// operation. Once every other file has been extracted, this returns an [`Error::Multiple`]
+    /// with the error for each file that failed, keyed by its path in the archive. When a
+    /// directory fails, none of its descendants are extracted. Errors from the database itself
+    /// still stop the operation right away.
+    ///
+    /// Files that were partly written when they failed are left as-is.
+    ///
+    /// The default is `false`.
+    ///
+    /// [`Error::Multiple`]: crate::Error::Multiple
+    pub fn keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// Call this function after each file is extracted.
+    ///
+    /// The callback is passed a [`Progress`] with the path of the file in the archive, the number
+    /// of files and bytes extracted so far, and the total number of files and bytes being
+    /// extracted. Files that are skipped, like files skipped by [`ExtractOptions::on_conflict`],
+    /// are reported too, so the number of files done always reaches the total.
+    ///
+    /// By default, progress isn't reported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::ExtractOptions;
+    /// let opts = ExtractOptions::new().on_progress(|progress| {
+    ///     if let Some(total) = progress.files_total() {
+    ///         println!("{}/{} files extracted", progress.files_done(), total);
+    ///     }
+    /// });
+    /// ```
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        self.on_progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Check for settings that conflict with each other.
+    ///
+    /// This returns every conflict it finds rather than stopping at the first one, which is useful
+    /// for reporting problems in options loaded from a config file. Extracting with these options
+    /// returns the first conflict as an error.
+    ///
+    /// # Errors
+    ///
+    /// Each conflict is returned as an [`InvalidArgs`] describing it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sqlarfs::ExtractOptions;
+    /// let opts = ExtractOptions::new().strip_components(1).recursive(false);
+    ///
+    /// // Stripping components requires `children`, and it would skip every file without
+    /// // `recursive`.
+    /// assert_eq!(opts.validate().unwrap_err().len(), 2);
+    /// ```
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    pub fn validate(&self) -> Result<(), Vec<crate::Error>> {
+        let mut conflicts = Vec::new();
+
+        if !self.children && self.strip_components > 0 {
+            conflicts.push(crate::Error::InvalidArgs {
+                reason: String::from("Cannot strip path components unless extracting the children of the source directory.")
+            });
+        }
+
+        if !self.recursive && self.strip_components > 0 {
+            conflicts.push(crate::Error::InvalidArgs {
+                reason: String::from("Cannot strip path components unless extracting recursively, because every file would be skipped.")
+            });
+        }
+
+        if let Some(vars) = &self.template_vars {
+            if vars.keys().any(|name| name.trim().is_empty()) {
+                conflicts.push(crate::Error::InvalidArgs {
+                    reason: String::from("Template variable names cannot be empty."),
+                });
+            }
+        }
+
+        if self.anchor_root && self.on_conflict.is_some() {
+            conflicts.push(crate::Error::InvalidArgs {
+                reason: String::from("Cannot resolve conflicts when extracting relative to the destination directory, because conflicts are resolved by path."),
+            });
+        }
+
+        if self.anchor_root && !cfg!(unix) {
+            conflicts.push(crate::Error::InvalidArgs {
+                reason: String::from("Extracting relative to the destination directory is only supported on Unix-like systems."),
+            });
+        }
+
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(conflicts)
+        }
+    }
 }
 
 fn read_metadata(path: &Path) -> crate::Result<fs::Metadata> {
-    match fs::symlink_metadata(path) {
+    match fs::symlink_metadata(long_path(path)) {
         Ok(metadata) => Ok(metadata),
         Err(err) if err.kind() == io::ErrorKind::NotFound => Err(crate::Error::FileNotFound {
             path: path.to_owned(),
@@ -144,24 +1133,528 @@ fn read_metadata(path: &Path) -> crate::Result<fs::Metadata> {
     }
 }
 
+// Create a junction point at `dest_path` pointing to the directory `target`.
+//
+// The standard library doesn't have a way to create junction points, and creating one through the
+// Windows API directly would require unsafe code, so we shell out to `mklink`.
+#[cfg(windows)]
+fn create_junction(target: &Path, dest_path: &Path) -> io::Result<()> {
+    // Junction points must point to an absolute path.
+    let target = std::env::current_dir()?.join(target);
+
+    let output = std::process::Command::new("cmd")
+        .arg("/C")
+        .arg("mklink")
+        .arg("/J")
+        .arg(dest_path)
+        .arg(&target)
+        .output()?;
+
+    if output.status.success() {
+        return Ok(());
+    }
+
+    // `mklink` doesn't tell us why it failed in a way we can parse, so check the common cases
+    // ourselves.
+    if fs::symlink_metadata(dest_path).is_ok() {
+        return Err(io::ErrorKind::AlreadyExists.into());
+    }
+
+    if dest_path
+        .parent()
+        .is_some_and(|parent| !parent.as_os_str().is_empty() && !parent.exists())
+    {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+    ))
+}
+
+fn unwrap_file_name(path: &Path) -> &OsStr {
+    path.file_name().expect(
+        "A file in the archive has no file name, but we should have already checked for this. This is a bug.",
+    )
+}
+
+fn is_apple_metadata(path: &Path) -> bool {
+    match path.file_name().and_then(OsStr::to_str) {
+        Some(name) => name == ".DS_Store" || name.starts_with("._"),
+        None => false,
+    }
+}
+
+// Get the paths of the files in `dir` that should be archived.
+fn read_children(dir: &Path, opts: &ArchiveOptions) -> crate::Result<Vec<PathBuf>> {
+    // We join the file names onto `dir` ourselves so that callers get back paths relative to the
+    // path they passed in, not the extended-length path we actually read.
+    let mut paths = fs::read_dir(long_path(dir))?
+        .map(|entry| entry.map(|entry| dir.join(entry.file_name())))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if opts.apple_metadata == AppleMetadata::Skip {
+        paths.retain(|path| !is_apple_metadata(path));
+    }
+
+    if let Some(exclude) = &opts.exclude {
+        paths.retain(|path| !exclude(path));
+    }
+
+    if opts.deterministic {
+        paths.sort();
+    }
+
+    Ok(paths)
+}
+
+// The directory that files are extracted into, used to make sure they aren't written outside of
+// it.
+#[derive(Debug)]
+struct ExtractRoot {
+    path: PathBuf,
+    canonical: PathBuf,
+}
+
+impl ExtractRoot {
+    fn new(path: &Path, dest_root: &Path) -> crate::Result<Self> {
+        let dir = if path == Path::new("") {
+            Path::new(".")
+        } else {
+            path
+        };
+
+        let canonical = fs::canonicalize(long_path(dir)).map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => crate::Error::NoParentDirectory {
+                path: dest_root.into(),
+            },
+            _ => err.into(),
+        })?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            canonical,
+        })
+    }
+
+    // Return an error if `dest_path` is outside of this directory, either lexically or once
+    // symlinks in its ancestors are resolved. The file at `dest_path` itself is never followed
+    // when extracting, so it can be a symlink.
+    fn check(&self, dest_path: &Path) -> crate::Result<()> {
+        let escapes_root = || crate::Error::PathEscapesRoot {
+            path: dest_path.into(),
+        };
+
+        let rel_path = dest_path
+            .strip_prefix(&self.path)
+            .map_err(|_| escapes_root())?;
+
+        if !rel_path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(escapes_root());
+        }
+
+        let parent = match dest_path.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        };
+
+        match fs::canonicalize(long_path(parent)) {
+            Ok(canonical_parent) if canonical_parent.starts_with(&self.canonical) => Ok(()),
+            Ok(_) => Err(escapes_root()),
+            // Nothing can be written to a parent directory that doesn't exist, so we let the
+            // error come from trying.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+// The state shared between all the files extracted by a single call to `Archive::extract_tree`.
+struct ExtractContext<'a, T> {
+    opts: &'a ExtractOptions,
+    caps: Capabilities,
+    mode_adapter: &'a T,
+    // Files are created relative to this directory when `ExtractOptions::anchor_root` is set.
+    anchor: Option<AnchoredDir>,
+    // Files are checked against this directory when `ExtractOptions::secure` is set.
+    root: Option<ExtractRoot>,
+    // This is reused between files so we only need to allocate it once.
+    copy_buf: Vec<u8>,
+    // The directories in the archive that are currently being copied in place of a symbolic link
+    // when `ExtractOptions::windows_symlinks` is `WindowsSymlinkPolicy::Copy`.
+    #[cfg(not(unix))]
+    copying_targets: Vec<PathBuf>,
+    progress: ProgressTracker,
+}
+
+// Copy the contents of a file in the archive into a file on the filesystem.
+//
+// `io::copy` only uses an 8 KiB buffer, and every read from the archive is a round trip through
+// SQLite's incremental blob I/O. Reading in bigger chunks makes extracting large files much faster.
+// The buffer is reused between files and only grows as large as the biggest file we've seen.
+//
+// If `template_vars` is passed, template variables in text files are substituted as they're copied,
+// and then line endings in text files are converted according to `newline_policy`. We only look
+// at the first chunk to decide whether a file is text.
+fn copy_to_file(
+    reader: &mut dyn Read,
+    len: u64,
+    dest: &mut fs::File,
+    buf: &mut Vec<u8>,
+    template_vars: Option<&HashMap<String, String>>,
+    newline_policy: NewlinePolicy,
+) -> io::Result<()> {
+    let wanted_len = usize::try_from(len)
+        .unwrap_or(usize::MAX)
+        .clamp(1, EXTRACT_BUF_SIZE);
+
+    if buf.len() < wanted_len {
+        buf.resize(wanted_len, 0);
+    }
+
+    let mut substituter = None;
+    let mut converter = NewlineConverter::new(NewlinePolicy::Preserve);
+    let mut is_first_chunk = true;
+
+    loop {
+        let chunk = match reader.read(buf) {
+            Ok(0) => break,
+            Ok(bytes_read) => &buf[..bytes_read],
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+
+        if is_first_chunk {
+            substituter = template_vars
+                .filter(|_| !chunk.contains(&0))
+                .map(Substituter::new);
+
+            if looks_like_text(&chunk[..chunk.len().min(TEXT_SNIFF_LEN)]) {
+                converter = NewlineConverter::new(newline_policy);
+            }
+
+            is_first_chunk = false;
+        }
+
+        let mut out = ConvertingWriter {
+            converter: &mut converter,
+            dest,
+        };
+
+        match &mut substituter {
+            Some(substituter) => substituter.write(chunk, &mut out)?,
+            None => out.write_all(chunk)?,
+        }
+    }
+
+    let mut out = ConvertingWriter {
+        converter: &mut converter,
+        dest,
+    };
+
+    if let Some(substituter) = &mut substituter {
+        substituter.finish(&mut out)?;
+    }
+
+    converter.finish(dest)?;
+
+    Ok(())
+}
+
+// Writes to `dest` through a `NewlineConverter`, so it can be the output of a `Substituter`.
+struct ConvertingWriter<'a, W> {
+    converter: &'a mut NewlineConverter,
+    dest: &'a mut W,
+}
+
+impl<'a, W: Write> Write for ConvertingWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.converter.write(buf, self.dest)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.dest.flush()
+    }
+}
+
+fn file_size(metadata: &FileMetadata) -> u64 {
+    match metadata {
+        FileMetadata::File { size, .. } => *size,
+        _ => 0,
+    }
+}
+
+// The maximum length of a file name, in bytes, on most filesystems.
+const MAX_FILE_NAME_LEN: usize = 255;
+
+// The number of hex digits of the hash we put at the end of truncated file names.
+const TRUNCATED_HASH_LEN: usize = 8;
+
+// We don't keep file extensions longer than this when truncating file names, since they're
+// probably not really extensions.
+const MAX_EXTENSION_LEN: usize = 16;
+
+// Shorten `name` to fit within `MAX_FILE_NAME_LEN`, keeping its extension and replacing the end
+// of it with a hash of the full name.
+fn truncate_file_name(name: &str) -> String {
+    let hash = Digest::from_reader(name.as_bytes())
+        .expect("Reading from a byte slice never fails.")
+        .to_string();
+    let hash = &hash[..TRUNCATED_HASH_LEN];
+
+    let (stem, extension) = match name.rfind('.') {
+        Some(index) if index > 0 && name.len() - index <= MAX_EXTENSION_LEN => name.split_at(index),
+        _ => (name, ""),
+    };
+
+    let mut stem_len = MAX_FILE_NAME_LEN - extension.len() - hash.len() - 1;
+
+    while !stem.is_char_boundary(stem_len) {
+        stem_len -= 1;
+    }
+
+    format!("{}~{hash}{extension}", &stem[..stem_len])
+}
+
+// If the file name of `dest_path` is too long for the filesystem, return the shortened path to
+// extract `src_path` to, according to `policy`.
+fn shorten_long_name(
+    src_path: &Path,
+    dest_path: &Path,
+    policy: LongNamePolicy,
+) -> crate::Result<Option<PathBuf>> {
+    let Some(file_name) = dest_path.file_name() else {
+        return Ok(None);
+    };
+
+    if file_name.len() <= MAX_FILE_NAME_LEN {
+        return Ok(None);
+    }
+
+    match policy {
+        LongNamePolicy::Error => Err(crate::Error::NameTooLong {
+            path: src_path.to_owned(),
+        }),
+        LongNamePolicy::Truncate => Ok(Some(
+            dest_path.with_file_name(truncate_file_name(&file_name.to_string_lossy())),
+        )),
+    }
+}
+
+// Apply `ArchiveOptions::map_path` to the path a file would be archived at, returning `None` if
+// the file should be skipped.
+fn map_dest_path(opts: &ArchiveOptions, dest_path: PathBuf) -> Option<PathBuf> {
+    match &opts.map_path {
+        Some(map_path) => map_path(&dest_path),
+        None => Some(dest_path),
+    }
+}
+
+// Identify a resumable archive job by its source and destination, so that running the same job
+// again picks up where it left off.
+fn job_key(src_root: &Path, dest_root: &Path) -> crate::Result<String> {
+    let src_root = fs::canonicalize(long_path(src_root))?;
+
+    Ok(format!(
+        "{}\n{}",
+        src_root.to_string_lossy(),
+        dest_root.to_string_lossy()
+    ))
+}
+
 fn rebase_path(path: &Path, new_base: &Path, old_base: &Path) -> PathBuf {
     new_base.join(path.strip_prefix(old_base).expect(
         "Could not get path relative to ancestor while walking the directory tree. This is a bug.",
     ))
 }
 
+// Probe the filesystem we're extracting into and make sure it can store the metadata of the
+// files we're extracting.
+fn check_capabilities<'a, T>(
+    dest_root: &Path,
+    children: bool,
+    fallback: MetadataFallback,
+    windows_symlinks: WindowsSymlinkPolicy,
+    mut all_metadata: impl Iterator<Item = &'a FileMetadata>,
+    mode_adapter: &T,
+) -> crate::Result<Capabilities>
+where
+    T: WriteMode,
+{
+    let probe_dir = if children {
+        dest_root
+    } else {
+        match dest_root.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        }
+    };
+
+    let caps = match probe_capabilities(&long_path(probe_dir), mode_adapter) {
+        Ok(caps) => caps,
+        Err(crate::Error::FileNotFound { .. }) if !children => {
+            return Err(crate::Error::NoParentDirectory {
+                path: dest_root.into(),
+            })
+        }
+        Err(err) => return Err(err),
+    };
+
+    if fallback == MetadataFallback::Error {
+        let is_unsupported = all_metadata.any(|metadata| match metadata {
+            FileMetadata::File { mode, .. } | FileMetadata::Dir { mode, .. } => {
+                mode.is_some() && !caps.permissions
+            }
+            // On Windows, what happens to symbolic links is up to the user.
+            FileMetadata::Symlink { .. } => {
+                !caps.symlinks && (cfg!(unix) || windows_symlinks == WindowsSymlinkPolicy::Skip)
+            }
+        });
+
+        if is_unsupported {
+            return Err(crate::Error::UnsupportedMetadata {
+                path: probe_dir.into(),
+            });
+        }
+    }
+
+    Ok(caps)
+}
+
+// The mode and mtime to give the file at `src_path` in the archive, or `None` if it should keep
+// the metadata it was created with.
+fn archived_metadata<T: ReadMode>(
+    src_path: &Path,
+    metadata: &fs::Metadata,
+    file_type: FileType,
+    opts: &ArchiveOptions,
+    mode_adapter: &T,
+) -> crate::Result<Option<(FileMode, Option<SystemTime>)>> {
+    if opts.deterministic {
+        let exec_mode = FileMode::OWNER_RWX
+            | FileMode::GROUP_R
+            | FileMode::GROUP_X
+            | FileMode::OTHER_R
+            | FileMode::OTHER_X;
+
+        let mode = match file_type {
+            FileType::File => {
+                let is_executable = mode_adapter
+                    .read_mode(src_path, metadata)?
+                    .intersects(FileMode::OWNER_X | FileMode::GROUP_X | FileMode::OTHER_X);
+
+                if is_executable {
+                    exec_mode
+                } else {
+                    FileMode::OWNER_R | FileMode::OWNER_W | FileMode::GROUP_R | FileMode::OTHER_R
+                }
+            }
+            FileType::Dir | FileType::Symlink => exec_mode,
+        };
+
+        Ok(Some((mode, Some(opts.deterministic_mtime))))
+    } else if opts.preserve_metadata {
+        let mode = mode_adapter.read_mode(src_path, metadata)?;
+        // `std::fs::Metadata::modified` returns an error when mtime isn't available on the
+        // current platform, in which case we just don't set the mtime in the archive.
+        let mtime = metadata.modified().ok();
+
+        Ok(Some((mode, mtime)))
+    } else {
+        Ok(None)
+    }
+}
+
+// A resumable archive job. See `ArchiveOptions::resumable`.
+#[derive(Debug)]
+struct ArchiveJob {
+    // Identifies this job in the `sqlar_jobs` table.
+    key: String,
+    // The number of files left to archive before pausing, or `None` to archive all of them.
+    budget: Option<u64>,
+    paused: bool,
+}
+
+// The state shared by the recursive calls to `Archive::archive_file`.
+#[derive(Debug)]
+pub(super) struct ArchiveState {
+    progress: ProgressTracker,
+    job: Option<ArchiveJob>,
+    inline: InlineBatch,
+    errors: ErrorCollector,
+}
+
 impl<'conn> Archive<'conn> {
+    // Record that the resumable job, if there is one, has started or finished archiving the file
+    // at `dest_path`. Finishing a file counts against the job's budget.
+    fn record_job_status(
+        &self,
+        state: &mut ArchiveState,
+        dest_path: &Path,
+        done: bool,
+    ) -> crate::Result<()> {
+        let Some(job) = &mut state.job else {
+            return Ok(());
+        };
+
+        self.store
+            .set_job_status(&job.key, &dest_path.to_string_lossy(), done)?;
+
+        if let Some(budget) = job.budget.as_mut().filter(|_| done) {
+            *budget = budget.saturating_sub(1);
+            job.paused = *budget == 0;
+        }
+
+        Ok(())
+    }
+
     pub(super) fn archive_file<T>(
-        &mut self,
+        &self,
         src_path: &Path,
         dest_path: &Path,
         opts: &ArchiveOptions,
         mode_adapter: &T,
         ancestor_stack: Vec<PathBuf>,
+        state: &mut ArchiveState,
     ) -> crate::Result<()>
     where
         T: ReadMode,
     {
+        let escaped_dest_path = if opts.escape_names {
+            escape_path(dest_path)
+        } else {
+            None
+        };
+
+        // We only need to record the raw file name of this file; its ancestors have already been
+        // archived.
+        let raw_name = escaped_dest_path.as_ref().and_then(|_| {
+            dest_path
+                .file_name()
+                .filter(|name| name.to_str().is_none())
+                .map(|name| name.as_encoded_bytes().to_vec())
+        });
+
+        let dest_path = escaped_dest_path.as_deref().unwrap_or(dest_path);
+
+        let job_status = match &state.job {
+            Some(job) if job.paused => return Ok(()),
+            Some(job) => self
+                .store
+                .job_status(&job.key, &dest_path.to_string_lossy())?,
+            None => None,
+        };
+
+        if job_status == Some(true) {
+            state.progress.file_done(dest_path, 0);
+            return Ok(());
+        }
+
         let metadata = read_metadata(src_path)?;
 
         let file_type = if metadata.is_file() {
@@ -171,20 +1664,116 @@ impl<'conn> Archive<'conn> {
         } else if metadata.is_symlink() {
             FileType::Symlink
         } else {
-            // We ignore special files.
-            return Ok(());
+            // We ignore special files.
+            return Ok(());
+        };
+
+        let filters = if file_type == FileType::File {
+            self.matching_filters(dest_path)
+        } else {
+            Vec::new()
+        };
+
+        let mut archive_file = self.open_unclaimed_mut(dest_path)?;
+
+        // A small file waiting to be inserted could be at this path already.
+        if state.inline.contains(archive_file.path()) {
+            self.flush_inline(&mut state.inline)?;
+        }
+
+        // When following a symlink, the file that actually gets archived is the target, so we let
+        // the recursive call handle any file that already exists at the destination.
+        let is_followed_symlink = file_type == FileType::Symlink && opts.follow_symlinks;
+
+        let existing_metadata = if is_followed_symlink {
+            None
+        } else {
+            match archive_file.metadata() {
+                Ok(metadata) => Some(metadata),
+                Err(crate::Error::FileNotFound { .. }) => None,
+                Err(err) => return Err(err),
+            }
         };
 
-        let mut archive_file = self.open(dest_path)?;
+        // A directory that a resumable job created before it was interrupted is finished rather
+        // than treated as a conflict.
+        let resume_dir = job_status == Some(false)
+            && file_type == FileType::Dir
+            && existing_metadata.as_ref().is_some_and(FileMetadata::is_dir);
+
+        // Rather than replacing or skipping a directory that already exists, we merge the
+        // contents of the source directory into it.
+        let merge_dir = resume_dir
+            || opts.overwrite != OverwritePolicy::Error
+                && file_type == FileType::Dir
+                && existing_metadata.as_ref().is_some_and(FileMetadata::is_dir);
+
+        if existing_metadata.is_some() && !merge_dir {
+            match opts.overwrite {
+                OverwritePolicy::Error => {}
+                OverwritePolicy::Replace => archive_file.delete()?,
+                OverwritePolicy::Skip => {
+                    state.progress.file_done(dest_path, 0);
+                    return Ok(());
+                }
+            }
+        }
+
+        let update_metadata = !(merge_dir && opts.overwrite == OverwritePolicy::Skip);
+
+        let is_inline = file_type == FileType::File
+            && opts.inline_threshold > 0
+            && metadata.len() <= opts.inline_threshold
+            && raw_name.is_none()
+            && !opts.deduplicate
+            && !opts.keep_going
+            // Let creating the file report that it already exists.
+            && (existing_metadata.is_none() || opts.overwrite == OverwritePolicy::Replace);
+
+        if is_inline {
+            let (mode, mtime) =
+                match archived_metadata(src_path, &metadata, file_type, opts, mode_adapter)? {
+                    Some(metadata) => metadata,
+                    None => (
+                        mode_from_umask(FileType::File, archive_file.umask()),
+                        Some(archive_file.initial_mtime()?),
+                    ),
+                };
+
+            let mtime = match mtime {
+                Some(mtime) if opts.source_date_epoch => Some(clamp_to_source_date_epoch(mtime)?),
+                mtime => mtime,
+            };
+
+            if let Some(method) = opts.compression {
+                archive_file.set_compression(method);
+            }
+
+            let contents = fs::read(long_path(src_path))?;
+            let contents = if filters.is_empty() {
+                contents
+            } else {
+                apply_on_archive(&filters, contents)?
+            };
+
+            let new_file = archive_file.prepare_new_file(mode, mtime, &contents)?;
+            self.push_inline(&mut state.inline, new_file)?;
+
+            state.progress.file_done(dest_path, metadata.len());
+            self.record_job_status(state, dest_path, true)?;
+
+            return Ok(());
+        }
 
         match file_type {
             FileType::File => archive_file.create_file()?,
+            FileType::Dir if merge_dir => {}
             FileType::Dir => archive_file.create_dir()?,
             FileType::Symlink => {
-                let target = fs::read_link(src_path)?;
+                let target = fs::read_link(long_path(src_path))?;
 
                 for ancestor in &ancestor_stack {
-                    if same_file::is_same_file(&target, ancestor)? {
+                    if same_file::is_same_file(long_path(&target), long_path(ancestor))? {
                         return Err(crate::Error::FilesystemLoop);
                     }
                 }
@@ -196,6 +1785,7 @@ impl<'conn> Archive<'conn> {
                         opts,
                         mode_adapter,
                         ancestor_stack,
+                        state,
                     );
                 } else {
                     archive_file.create_symlink(&target)?;
@@ -203,49 +1793,168 @@ impl<'conn> Archive<'conn> {
             }
         }
 
-        if opts.preserve_metadata {
-            let mode = mode_adapter.read_mode(src_path, &metadata)?;
-            // `std::fs::Metadata::modified` returns an error when mtime isn't available on the
-            // current platform, in which case we just don't set the mtime in the archive.
-            let mtime = metadata.modified().ok();
+        if let Some(raw_name) = &raw_name {
+            archive_file.set_raw_name(raw_name)?;
+        }
 
-            archive_file.set_mode(Some(mode))?;
-            archive_file.set_mtime(mtime)?;
+        if update_metadata {
+            if let Some((mode, mtime)) =
+                archived_metadata(src_path, &metadata, file_type, opts, mode_adapter)?
+            {
+                archive_file.set_mode(Some(mode))?;
+                archive_file.set_mtime(mtime)?;
+            }
         }
 
-        match file_type {
-            FileType::File => {
-                // Copy the file contents.
-                let mut fs_file = fs::File::open(src_path)?;
+        if opts.source_date_epoch && update_metadata {
+            if let Some(mtime) = archive_file.metadata()?.mtime() {
+                archive_file.set_mtime(Some(clamp_to_source_date_epoch(mtime)?))?;
+            }
+        }
+
+        if file_type == FileType::File {
+            // Copy the file contents.
+            let mut fs_file = fs::File::open(long_path(src_path))?;
+
+            if let Some(method) = opts.compression {
+                archive_file.set_compression(method);
+            }
+
+            if filters.is_empty() {
                 archive_file.write_file(&mut fs_file)?;
+            } else {
+                let mut contents = Vec::new();
+                fs_file.read_to_end(&mut contents)?;
+
+                archive_file.write_bytes(&apply_on_archive(&filters, contents)?)?;
+            }
+
+            if opts.deduplicate {
+                archive_file.deduplicate()?;
             }
+        }
+
+        let bytes = if file_type == FileType::File {
+            metadata.len()
+        } else {
+            0
+        };
+
+        state.progress.file_done(dest_path, bytes);
+
+        match file_type {
             FileType::Dir if opts.recursive => {
-                for entry in fs::read_dir(src_path)? {
-                    let entry_path = entry?.path();
-                    let dest_path = rebase_path(&entry_path, dest_path, src_path);
+                // If a resumable job is interrupted while archiving the children of a directory it
+                // created, we need to know to finish the directory when the job is resumed.
+                if !merge_dir {
+                    self.record_job_status(state, dest_path, false)?;
+                }
+
+                for entry_path in read_children(src_path, opts)? {
+                    let Some(dest_path) =
+                        map_dest_path(opts, rebase_path(&entry_path, dest_path, src_path))
+                    else {
+                        continue;
+                    };
 
                     let mut ancestor_stack = ancestor_stack.clone();
                     ancestor_stack.push(src_path.to_owned());
 
-                    self.archive_file(&entry_path, &dest_path, opts, mode_adapter, ancestor_stack)?;
+                    self.archive_entry(
+                        &entry_path,
+                        &dest_path,
+                        opts,
+                        mode_adapter,
+                        ancestor_stack,
+                        state,
+                    )?;
+                }
+
+                // The job was paused before it got through all the children, so this directory
+                // isn't finished yet.
+                if state.job.as_ref().is_some_and(|job| job.paused) {
+                    return Ok(());
+                }
+
+                if !opts.store_empty_dirs && (!merge_dir || resume_dir) {
+                    self.flush_inline(&mut state.inline)?;
+
+                    let is_empty = self
+                        .list_with(&ListOptions::new().children_of(dest_path))?
+                        .next()
+                        .is_none();
+
+                    if is_empty {
+                        self.open_unclaimed_mut(dest_path)?.delete()?;
+                    }
                 }
             }
             _ => {}
         }
 
+        self.record_job_status(state, dest_path, true)?;
+
+        Ok(())
+    }
+
+    // Archive a file found while walking the tree. With `ArchiveOptions::keep_going`, each file is
+    // archived in its own savepoint so that a file that fails can be rolled back and recorded
+    // without stopping the rest.
+    fn archive_entry<T>(
+        &self,
+        src_path: &Path,
+        dest_path: &Path,
+        opts: &ArchiveOptions,
+        mode_adapter: &T,
+        ancestor_stack: Vec<PathBuf>,
+        state: &mut ArchiveState,
+    ) -> crate::Result<()>
+    where
+        T: ReadMode,
+    {
+        if !opts.keep_going {
+            return self.archive_file(
+                src_path,
+                dest_path,
+                opts,
+                mode_adapter,
+                ancestor_stack,
+                state,
+            );
+        }
+
+        let result = self.store.exec(|_| {
+            self.archive_file(
+                src_path,
+                dest_path,
+                opts,
+                mode_adapter,
+                ancestor_stack,
+                state,
+            )
+        });
+
+        state.errors.collect(src_path, result)?;
+
         Ok(())
     }
 
+    // Return `false` if this is a resumable job and it was paused because it used up its budget
+    // before archiving every file.
     pub(super) fn archive_tree<T>(
-        &mut self,
+        &self,
         src_root: &Path,
         dest_root: &Path,
         opts: &ArchiveOptions,
         mode_adapter: &T,
-    ) -> crate::Result<()>
+        budget: Option<u64>,
+    ) -> crate::Result<bool>
     where
         T: ReadMode,
     {
+        opts.validate()
+            .map_err(|mut conflicts| conflicts.swap_remove(0))?;
+
         let dest_is_empty = dest_root == Path::new("");
 
         if dest_is_empty && !opts.children {
@@ -254,7 +1963,30 @@ impl<'conn> Archive<'conn> {
             });
         }
 
-        if opts.children && !dest_is_empty && !self.open(dest_root)?.metadata()?.is_dir() {
+        let prefixed_root;
+
+        let dest_root = if let Some(prefix) = &opts.prefix {
+            prefixed_root = prefix.join(dest_root);
+
+            let prefix_dirs = if opts.children {
+                Some(prefixed_root.as_path())
+            } else {
+                prefixed_root.parent()
+            };
+
+            if let Some(dir) = prefix_dirs.filter(|dir| *dir != Path::new("")) {
+                self.open_unclaimed_mut(dir)?.create_dir_all()?;
+            }
+
+            prefixed_root.as_path()
+        } else {
+            dest_root
+        };
+
+        let dest_is_empty = dest_root == Path::new("");
+
+        if opts.children && !dest_is_empty && !self.open_unclaimed(dest_root)?.metadata()?.is_dir()
+        {
             return Err(crate::Error::NotADirectory {
                 path: dest_root.to_owned(),
             });
@@ -270,89 +2002,249 @@ impl<'conn> Archive<'conn> {
                 path: src_root.to_owned(),
             });
         } else if opts.children {
-            fs::read_dir(src_root)?
-                .map(|entry| entry.map(|entry| entry.path()))
-                .collect::<Result<Vec<_>, _>>()?
+            read_children(src_root, opts)?
         } else {
             vec![src_root.to_path_buf()]
         };
 
-        for path in paths {
-            let dest_path = rebase_path(&path, dest_root, src_root);
-            self.archive_file(&path, &dest_path, opts, mode_adapter, Vec::new())?;
+        let job = if opts.resumable {
+            Some(ArchiveJob {
+                key: job_key(src_root, dest_root)?,
+                budget,
+                paused: false,
+            })
+        } else {
+            None
+        };
+
+        let mut state = ArchiveState {
+            progress: ProgressTracker::new(opts.on_progress.clone(), None, None),
+            job,
+            inline: InlineBatch::new(opts.batch_size),
+            errors: ErrorCollector::new(opts.keep_going),
+        };
+
+        let result = paths.into_iter().try_for_each(|path| {
+            let Some(dest_path) = map_dest_path(opts, rebase_path(&path, dest_root, src_root))
+            else {
+                return Ok(());
+            };
+
+            self.archive_entry(
+                &path,
+                &dest_path,
+                opts,
+                mode_adapter,
+                Vec::new(),
+                &mut state,
+            )
+        });
+
+        // The small files that were archived before an error are still written, the same as
+        // every other file that was archived before it.
+        let flushed = self.flush_inline(&mut state.inline);
+        result?;
+        flushed?;
+
+        // The job isn't finished if any files failed, so resuming it retries them.
+        state.errors.finish()?;
+
+        match &state.job {
+            Some(job) if job.paused => Ok(false),
+            Some(job) => {
+                self.store.delete_job(&job.key)?;
+                Ok(true)
+            }
+            None => Ok(true),
         }
+    }
 
-        Ok(())
+    // If the file at `src_path` was archived with `ArchiveOptions::escape_names`, return
+    // `dest_path` with its original file name restored.
+    fn restore_raw_name(
+        &self,
+        src_path: &Path,
+        dest_path: &Path,
+    ) -> crate::Result<Option<PathBuf>> {
+        let Some(escaped_name) = src_path.file_name().and_then(OsStr::to_str) else {
+            return Ok(None);
+        };
+
+        let Some(raw_name) = self.store.raw_name(&src_path.to_string_lossy())? else {
+            return Ok(None);
+        };
+
+        Ok(restore_file_name(escaped_name, &raw_name).map(|name| dest_path.with_file_name(name)))
     }
 
-    pub(super) fn extract_file<T>(
-        &mut self,
+    // Extract a single file, returning the path it was actually extracted to, or `None` if it was
+    // skipped.
+    fn extract_file<T>(
+        &self,
         src_path: &Path,
         dest_path: &Path,
         metadata: &FileMetadata,
-        mode_adapter: &T,
-    ) -> crate::Result<()>
+        ctx: &mut ExtractContext<'_, T>,
+    ) -> crate::Result<Option<PathBuf>>
     where
         T: WriteMode,
     {
+        let ExtractContext {
+            opts,
+            caps,
+            mode_adapter,
+            anchor,
+            root,
+            copy_buf,
+            ..
+        } = ctx;
+
+        // This needs to happen before resolving conflicts, which may delete files.
+        if let Some(root) = root {
+            root.check(dest_path)?;
+        }
+
+        let mut dest_path = dest_path.to_owned();
+        let mut merge_dir = false;
+
+        if let Some(on_conflict) = &opts.on_conflict {
+            loop {
+                let existing = match fs::symlink_metadata(long_path(&dest_path)) {
+                    Ok(existing) => existing,
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => break,
+                    Err(err) => return Err(err.into()),
+                };
+
+                match on_conflict(&dest_path, metadata, &existing) {
+                    ConflictAction::Overwrite => {
+                        if existing.is_dir() && metadata.is_dir() {
+                            merge_dir = true;
+                        } else if existing.is_dir() {
+                            fs::remove_dir_all(long_path(&dest_path))?;
+                        } else {
+                            fs::remove_file(long_path(&dest_path))?;
+                        }
+
+                        break;
+                    }
+                    ConflictAction::Skip => return Ok(None),
+                    ConflictAction::RenameWithSuffix(suffix) => {
+                        let mut renamed = OsString::from(dest_path);
+                        renamed.push(suffix);
+                        dest_path = PathBuf::from(renamed);
+                    }
+                    ConflictAction::Abort => {
+                        return Err(crate::Error::FileAlreadyExists { path: dest_path })
+                    }
+                }
+            }
+        }
+
+        let dest_path = dest_path.as_path();
+
         match metadata {
             FileMetadata::File { mtime, mode, .. } => {
-                let mut fs_file = fs::OpenOptions::new()
-                    .create_new(true)
-                    .write(true)
-                    .open(dest_path)
-                    .map_err(|err| {
-                        // Windows will throw an `io::ErrorKind::PermissionDenied` if the file
-                        // already exists and is a directory.
-                        if err.kind() == io::ErrorKind::AlreadyExists
-                            || (cfg!(windows) && err.kind() == io::ErrorKind::PermissionDenied)
-                        {
-                            crate::Error::FileAlreadyExists {
-                                path: dest_path.into(),
-                            }
-                        } else if err.kind() == io::ErrorKind::NotFound {
-                            crate::Error::NoParentDirectory {
-                                path: dest_path.into(),
+                let mut fs_file = match anchor {
+                    Some(anchor) => anchor.create_file(dest_path)?,
+                    None => fs::OpenOptions::new()
+                        .create_new(true)
+                        .write(true)
+                        .open(long_path(dest_path))
+                        .map_err(|err| {
+                            // Windows will throw an `io::ErrorKind::PermissionDenied` if the file
+                            // already exists and is a directory.
+                            if err.kind() == io::ErrorKind::AlreadyExists
+                                || (cfg!(windows) && err.kind() == io::ErrorKind::PermissionDenied)
+                            {
+                                crate::Error::FileAlreadyExists {
+                                    path: dest_path.into(),
+                                }
+                            } else if err.kind() == io::ErrorKind::NotFound {
+                                crate::Error::NoParentDirectory {
+                                    path: dest_path.into(),
+                                }
+                            } else {
+                                err.into()
                             }
-                        } else {
-                            err.into()
-                        }
-                    })?;
+                        })?,
+                };
 
-                let mut archive_file = self.open(src_path)?;
+                let filters = self.matching_filters(src_path);
+                let mut archive_file = self.open_unclaimed(src_path)?;
                 let mut reader = archive_file.reader()?;
 
-                io::copy(&mut reader, &mut fs_file)?;
+                if filters.is_empty() {
+                    let len = reader.len();
+
+                    copy_to_file(
+                        &mut reader,
+                        len,
+                        &mut fs_file,
+                        copy_buf,
+                        opts.template_vars.as_ref(),
+                        opts.newline_policy,
+                    )?;
+                } else {
+                    let mut contents = Vec::new();
+                    reader.read_to_end(&mut contents)?;
+
+                    let contents = apply_on_extract(&filters, contents)?;
+
+                    copy_to_file(
+                        &mut contents.as_slice(),
+                        u64_from_usize(contents.len()),
+                        &mut fs_file,
+                        copy_buf,
+                        opts.template_vars.as_ref(),
+                        opts.newline_policy,
+                    )?;
+                }
 
                 if let Some(mtime) = mtime {
                     fs_file.set_modified(*mtime)?;
                 }
 
-                if let Some(mode) = mode {
-                    mode_adapter.write_mode(dest_path, *mode)?;
+                if let Some(mode) = mode.filter(|_| caps.permissions) {
+                    match anchor {
+                        Some(anchor) => anchor.set_file_mode(&fs_file, mode)?,
+                        None => mode_adapter.write_mode(&long_path(dest_path), mode)?,
+                    }
                 }
             }
             FileMetadata::Dir { mode, .. } => {
-                fs::create_dir(dest_path).map_err(|err| match err.kind() {
-                    io::ErrorKind::AlreadyExists => crate::Error::FileAlreadyExists {
-                        path: dest_path.into(),
-                    },
-                    io::ErrorKind::NotFound => crate::Error::NoParentDirectory {
-                        path: dest_path.into(),
-                    },
-                    _ => err.into(),
-                })?;
-
-                if let Some(mode) = mode {
-                    mode_adapter.write_mode(dest_path, *mode)?;
+                if let Some(anchor) = anchor.as_ref().filter(|_| !merge_dir) {
+                    anchor.create_dir(dest_path)?;
+                } else if !merge_dir {
+                    fs::create_dir(long_path(dest_path)).map_err(|err| match err.kind() {
+                        io::ErrorKind::AlreadyExists => crate::Error::FileAlreadyExists {
+                            path: dest_path.into(),
+                        },
+                        io::ErrorKind::NotFound => crate::Error::NoParentDirectory {
+                            path: dest_path.into(),
+                        },
+                        _ => err.into(),
+                    })?;
+                }
+
+                if let Some(mode) = mode.filter(|_| caps.permissions) {
+                    match anchor {
+                        Some(anchor) => anchor.set_dir_mode(dest_path, mode)?,
+                        None => mode_adapter.write_mode(&long_path(dest_path), mode)?,
+                    }
                 }
             }
             // We currently do not attempt to set the mtime of symlinks, because Rust doesn't seem
             // to provide a way to do that.
+            FileMetadata::Symlink { .. } if !caps.symlinks && cfg!(unix) => return Ok(None),
+            #[cfg(not(unix))]
             FileMetadata::Symlink { target, .. } => {
-                // This is a no-op on non-Unix-like systems.
-                #[cfg(unix)]
-                {
+                return self.extract_windows_symlink(src_path, dest_path, target, ctx);
+            }
+            #[cfg(unix)]
+            FileMetadata::Symlink { target, .. } => {
+                if let Some(anchor) = anchor {
+                    anchor.create_symlink(target, dest_path)?;
+                } else {
                     std::os::unix::fs::symlink(target, dest_path).map_err(|err| {
                         match err.kind() {
                             io::ErrorKind::AlreadyExists => crate::Error::FileAlreadyExists {
@@ -368,11 +2260,163 @@ impl<'conn> Archive<'conn> {
             }
         }
 
-        Ok(())
+        Ok(Some(dest_path.to_owned()))
+    }
+
+    // Extract a symbolic link on Windows according to `ExtractOptions::windows_symlinks`.
+    #[cfg(not(unix))]
+    fn extract_windows_symlink<T>(
+        &self,
+        src_path: &Path,
+        dest_path: &Path,
+        target: &Path,
+        ctx: &mut ExtractContext<'_, T>,
+    ) -> crate::Result<Option<PathBuf>>
+    where
+        T: WriteMode,
+    {
+        let policy = ctx.opts.windows_symlinks;
+
+        if policy == WindowsSymlinkPolicy::Skip {
+            return Ok(None);
+        }
+
+        // Targets in the archive use forward slashes, which aren't valid in symbolic links or
+        // junction points on Windows.
+        let target = target.components().collect::<PathBuf>();
+
+        #[cfg(windows)]
+        let dest_target = match dest_path.parent() {
+            Some(parent) => parent.join(&target),
+            None => target.clone(),
+        };
+
+        let resolved = self.resolve_link_target(src_path, &target)?;
+
+        #[cfg(windows)]
+        let target_is_dir = match &resolved {
+            Resolved::Path(path) if path.is_empty() => true,
+            Resolved::Path(path) => self.store.read_metadata(&path.join("/"))?.is_dir(),
+            Resolved::Missing => false,
+            Resolved::Outside => fs::metadata(long_path(&dest_target)).is_ok_and(|m| m.is_dir()),
+        };
+
+        #[cfg(windows)]
+        let map_err = |err: io::Error| match err.kind() {
+            io::ErrorKind::AlreadyExists => crate::Error::FileAlreadyExists {
+                path: dest_path.into(),
+            },
+            io::ErrorKind::NotFound => crate::Error::NoParentDirectory {
+                path: dest_path.into(),
+            },
+            _ => err.into(),
+        };
+
+        let created = match policy {
+            #[cfg(windows)]
+            WindowsSymlinkPolicy::Symlink => {
+                if target_is_dir {
+                    std::os::windows::fs::symlink_dir(&target, long_path(dest_path))
+                } else {
+                    std::os::windows::fs::symlink_file(&target, long_path(dest_path))
+                }
+                .map_err(map_err)?;
+
+                true
+            }
+            #[cfg(windows)]
+            WindowsSymlinkPolicy::Junction if target_is_dir => {
+                create_junction(&dest_target, dest_path).map_err(map_err)?;
+
+                true
+            }
+            WindowsSymlinkPolicy::Copy => {
+                return match resolved {
+                    Resolved::Path(path) => {
+                        self.extract_symlink_copy(src_path, &path.join("/"), dest_path, ctx)
+                    }
+                    Resolved::Missing | Resolved::Outside => Ok(None),
+                };
+            }
+            _ => false,
+        };
+
+        Ok(created.then(|| dest_path.to_owned()))
+    }
+
+    // Extract a copy of the file or directory at `target_path` in the archive to `dest_path`, in
+    // place of the symbolic link at `src_path`.
+    #[cfg(not(unix))]
+    fn extract_symlink_copy<T>(
+        &self,
+        src_path: &Path,
+        target: &str,
+        dest_path: &Path,
+        ctx: &mut ExtractContext<'_, T>,
+    ) -> crate::Result<Option<PathBuf>>
+    where
+        T: WriteMode,
+    {
+        let target_path = Path::new(target);
+
+        // A link to one of its own ancestors, or to a directory we're already in the middle of
+        // copying, would be copied into itself forever. This also covers links to the root of the
+        // archive.
+        if src_path.starts_with(target_path) || ctx.copying_targets.iter().any(|p| p == target_path)
+        {
+            return Ok(None);
+        }
+
+        let metadata = self.store.read_metadata(target)?;
+
+        let Some(extracted_path) = self.extract_file(target_path, dest_path, &metadata, ctx)?
+        else {
+            return Ok(None);
+        };
+
+        if !metadata.is_dir() {
+            return Ok(Some(extracted_path));
+        }
+
+        let entries = self
+            .list_with(&ListOptions::new().descendants_of(target_path).by_depth())?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        ctx.copying_targets.push(target_path.to_owned());
+
+        let mut moved_dirs = HashMap::new();
+        moved_dirs.insert(target_path.to_owned(), Some(extracted_path.clone()));
+
+        let result = entries.iter().try_for_each(|entry| {
+            let dest_path = match entry
+                .path
+                .parent()
+                .and_then(|parent| moved_dirs.get(parent))
+            {
+                Some(Some(moved_parent)) => moved_parent.join(unwrap_file_name(&entry.path)),
+                _ => {
+                    moved_dirs.insert(entry.path.clone(), None);
+                    return Ok(());
+                }
+            };
+
+            let extracted_path =
+                self.extract_file(entry.path(), &dest_path, entry.metadata(), ctx)?;
+
+            if entry.metadata().is_dir() {
+                moved_dirs.insert(entry.path.clone(), extracted_path);
+            }
+
+            Ok(())
+        });
+
+        ctx.copying_targets.pop();
+
+        result.map(|()| Some(extracted_path))
     }
 
     pub(super) fn extract_tree<T>(
-        &mut self,
+        &self,
         src_root: &Path,
         dest_root: &Path,
         opts: &ExtractOptions,
@@ -383,13 +2427,19 @@ impl<'conn> Archive<'conn> {
     {
         let src_path_is_empty = src_root == Path::new("");
 
+        opts.validate()
+            .map_err(|mut conflicts| conflicts.swap_remove(0))?;
+
         if !opts.children && src_path_is_empty {
             return Err(crate::Error::InvalidArgs {
                 reason: String::from("Cannot use an empty path as the source directory unless archiving the children of the source directory.")
             });
         }
 
-        if opts.children && !src_path_is_empty && !self.open(src_root)?.metadata()?.is_dir() {
+        if opts.children
+            && !src_path_is_empty
+            && !self.open_unclaimed(src_root)?.metadata()?.is_dir()
+        {
             return Err(crate::Error::NotADirectory {
                 path: src_root.into(),
             });
@@ -418,30 +2468,227 @@ impl<'conn> Archive<'conn> {
             }
         }
 
-        if !opts.children {
-            let src_metadata = self.open(src_root)?.metadata()?;
-            self.extract_file(src_root, dest_root, &src_metadata, mode_adapter)?;
-        }
+        let empty_dirs = if opts.create_empty_dirs {
+            HashSet::new()
+        } else {
+            self.list_with(&ListOptions::new().empty_dirs())?
+                .map(|entry| entry.map(ListEntry::into_path))
+                .collect::<crate::Result<HashSet<_>>>()?
+        };
 
-        if !opts.children && !opts.recursive {
-            return Ok(());
-        }
+        let src_metadata = if opts.children {
+            None
+        } else {
+            Some(self.open_unclaimed(src_root)?.metadata()?)
+        };
+
+        let entries = if !opts.children && !opts.recursive {
+            Vec::new()
+        } else {
+            let list_opts = if opts.recursive {
+                ListOptions::new().descendants_of(src_root).by_depth()
+            } else {
+                ListOptions::new().children_of(src_root).by_depth()
+            };
+
+            // We need to collect the entries into a vector because iterating over the entries holds
+            // a statement open, and we don't want it to outlive copying the file contents.
+            self.list_with(&list_opts)?.collect::<Result<Vec<_>, _>>()?
+        };
+
+        let caps = match opts.metadata_fallback {
+            Some(fallback) => {
+                let all_metadata = src_metadata
+                    .iter()
+                    .chain(entries.iter().map(ListEntry::metadata));
 
-        let list_opts = if opts.recursive {
-            ListOptions::new().descendants_of(src_root).by_depth()
+                check_capabilities(
+                    dest_root,
+                    opts.children,
+                    fallback,
+                    opts.windows_symlinks,
+                    all_metadata,
+                    mode_adapter,
+                )?
+            }
+            None => Capabilities::ALL,
+        };
+
+        // Directories that were renamed or skipped because of a conflict, and the path they were
+        // extracted to, if any. The descendants of these directories need to follow them.
+        let mut moved_dirs = HashMap::new();
+
+        let all_metadata = || {
+            src_metadata
+                .iter()
+                .chain(entries.iter().map(ListEntry::metadata))
+        };
+
+        let files_total = u64_from_usize(all_metadata().count());
+        let bytes_total = all_metadata().map(file_size).sum();
+
+        // The directory that all the files are being extracted into.
+        let root_path = if opts.children {
+            dest_root
+        } else {
+            dest_root.parent().unwrap_or(Path::new(""))
+        };
+
+        let anchor = if opts.anchor_root {
+            Some(AnchoredDir::open(root_path)?)
+        } else {
+            None
+        };
+
+        let root = if opts.secure {
+            Some(ExtractRoot::new(root_path, dest_root)?)
         } else {
-            ListOptions::new().children_of(src_root).by_depth()
+            None
+        };
+
+        let mut ctx = ExtractContext {
+            opts,
+            caps,
+            mode_adapter,
+            anchor,
+            root,
+            copy_buf: Vec::new(),
+            #[cfg(not(unix))]
+            copying_targets: Vec::new(),
+            progress: ProgressTracker::new(
+                opts.on_progress.clone(),
+                Some(files_total),
+                Some(bytes_total),
+            ),
         };
 
-        // We need to collect the entries into a vector because iterating over the entries will
-        // borrow the `Archive`, and we need to borrow it mutably to copy the file contents.
-        let entries = self.list_with(&list_opts)?.collect::<Result<Vec<_>, _>>()?;
+        // Only archives that contain escaped file names have this table.
+        let has_raw_names = self.store.has_raw_names()?;
+
+        let mut errors = ErrorCollector::new(opts.keep_going);
+
+        if let Some(src_metadata) = &src_metadata {
+            let skip_src = match src_metadata {
+                FileMetadata::Symlink { target, .. } if opts.skip_broken_symlinks => {
+                    self.is_broken_symlink(src_root, target, dest_root)?
+                }
+                _ => false,
+            };
+
+            if !skip_src && !empty_dirs.contains(src_root) {
+                let extracted_path = errors
+                    .collect(
+                        src_root,
+                        self.extract_file(src_root, dest_root, src_metadata, &mut ctx),
+                    )?
+                    // The descendants of a directory that failed are skipped.
+                    .unwrap_or(None);
+
+                if extracted_path.as_deref() != Some(dest_root) {
+                    moved_dirs.insert(src_root.to_owned(), extracted_path);
+                }
+            }
+
+            ctx.progress.file_done(src_root, file_size(src_metadata));
+        }
 
         for entry in entries {
-            let dest_path = rebase_path(&entry.path, dest_root, src_root);
-            self.extract_file(entry.path(), &dest_path, entry.metadata(), mode_adapter)?;
+            let size = file_size(entry.metadata());
+
+            if empty_dirs.contains(entry.path()) {
+                ctx.progress.file_done(entry.path(), size);
+                continue;
+            }
+
+            let dest_path = if opts.strip_components > 0 {
+                let rel_path = entry.path.strip_prefix(src_root).expect(
+                    "Could not get path relative to ancestor while walking the directory tree. This is a bug.",
+                );
+
+                let mut components = rel_path.components();
+
+                if components.nth(opts.strip_components - 1).is_none()
+                    || components.as_path() == Path::new("")
+                {
+                    ctx.progress.file_done(entry.path(), size);
+                    continue;
+                }
+
+                dest_root.join(components.as_path())
+            } else {
+                rebase_path(&entry.path, dest_root, src_root)
+            };
+
+            let dest_path = match entry
+                .path
+                .parent()
+                .and_then(|parent| moved_dirs.get(parent))
+            {
+                Some(Some(moved_parent)) => moved_parent.join(unwrap_file_name(&entry.path)),
+                Some(None) => {
+                    moved_dirs.insert(entry.path.clone(), None);
+                    ctx.progress.file_done(entry.path(), size);
+                    continue;
+                }
+                None => dest_path,
+            };
+
+            let restored_path = if has_raw_names {
+                self.restore_raw_name(entry.path(), &dest_path)?
+            } else {
+                None
+            };
+
+            let (dest_path, restored_name) = match restored_path {
+                Some(restored_path) => (restored_path, true),
+                None => (dest_path, false),
+            };
+
+            let shortened_path = errors.collect(
+                entry.path(),
+                shorten_long_name(entry.path(), &dest_path, opts.long_names),
+            )?;
+
+            let (dest_path, restored_name) = match shortened_path {
+                Some(Some(shortened_path)) => (shortened_path, true),
+                Some(None) => (dest_path, restored_name),
+                None => {
+                    moved_dirs.insert(entry.path.clone(), None);
+                    ctx.progress.file_done(entry.path(), size);
+                    continue;
+                }
+            };
+
+            if opts.skip_broken_symlinks {
+                if let FileMetadata::Symlink { target, .. } = entry.metadata() {
+                    if self.is_broken_symlink(entry.path(), target, &dest_path)? {
+                        ctx.progress.file_done(entry.path(), size);
+                        continue;
+                    }
+                }
+            }
+
+            let Some(extracted_path) = errors.collect(
+                entry.path(),
+                self.extract_file(entry.path(), &dest_path, entry.metadata(), &mut ctx),
+            )?
+            else {
+                moved_dirs.insert(entry.path.clone(), None);
+                ctx.progress.file_done(entry.path(), size);
+                continue;
+            };
+
+            // If we restored the original name of a directory or shortened it, its children need
+            // to be extracted under that name rather than the one in the archive.
+            if entry.metadata().is_dir()
+                && (restored_name || extracted_path.as_deref() != Some(dest_path.as_path()))
+            {
+                moved_dirs.insert(entry.path.clone(), extracted_path);
+            }
+
+            ctx.progress.file_done(entry.path(), size);
         }
 
-        Ok(())
+        errors.finish()
     }
 }
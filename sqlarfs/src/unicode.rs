@@ -0,0 +1,65 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use unicode_normalization::{is_nfc, UnicodeNormalization as _};
+
+use super::archive::Archive;
+use super::list::{ListOptions, ListSort};
+
+/// How to normalize the Unicode in file paths.
+///
+/// The same file name can be encoded in Unicode in more than one way. For example, `é` can be
+/// written as a single code point (NFC), or as an `e` followed by a combining accent (NFD). macOS
+/// tends to produce NFD file names while Linux and Windows tend to produce NFC, so an archive
+/// shared between them can end up with two files whose names look the same but aren't.
+///
+/// This is used with [`Archive::set_path_normalization`].
+///
+/// [`Archive::set_path_normalization`]: crate::Archive::set_path_normalization
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum PathNormalization {
+    /// Use paths exactly as they're given.
+    #[default]
+    Preserve,
+
+    /// Normalize paths to Unicode Normalization Form C (NFC).
+    Nfc,
+}
+
+impl PathNormalization {
+    pub(super) fn apply(self, path: &Path) -> Cow<'_, Path> {
+        match (self, path.to_str()) {
+            (PathNormalization::Nfc, Some(path_str)) if !is_nfc(path_str) => {
+                Cow::Owned(PathBuf::from(path_str.nfc().collect::<String>()))
+            }
+            // If the path isn't valid Unicode, we leave it alone and let the caller return an
+            // error.
+            _ => Cow::Borrowed(path),
+        }
+    }
+}
+
+impl<'conn> Archive<'conn> {
+    pub(super) fn find_normalization_conflicts(&self) -> crate::Result<Vec<Vec<PathBuf>>> {
+        let list_opts = ListOptions {
+            sort: Some(ListSort::Name),
+            ..ListOptions::new()
+        };
+
+        let mut groups: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+
+        for entry in self.list_with(&list_opts)? {
+            let path = entry?.into_path();
+            let normalized = path.to_string_lossy().nfc().collect::<String>();
+
+            groups.entry(normalized).or_default().push(path);
+        }
+
+        Ok(groups
+            .into_values()
+            .filter(|paths| paths.len() > 1)
+            .collect())
+    }
+}
@@ -0,0 +1,171 @@
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use super::file::File;
+use super::metadata::{FileMetadata, FileMode};
+use super::store::Store;
+use super::stream::Compression;
+use super::unicode::PathNormalization;
+
+// Used to generate paths for unnamed files that are unlikely to already be taken.
+static NEXT_UNNAMED_ID: AtomicU64 = AtomicU64::new(0);
+
+// Return a path that isn't taken in the archive, for a file that's not meant to be seen under it.
+pub(super) fn unused_path(store: &Store) -> crate::Result<String> {
+    loop {
+        let path = format!(
+            ".sqlarfs-unnamed-{}-{}",
+            std::process::id(),
+            NEXT_UNNAMED_ID.fetch_add(1, Ordering::Relaxed)
+        );
+
+        match store.read_metadata(&path) {
+            Err(crate::Error::FileNotFound { .. }) => return Ok(path),
+            Ok(_) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// A regular file in an archive that doesn't have a path yet.
+///
+/// This is returned by [`Archive::create_unnamed`]. You can write to it like a [`File`], and then
+/// call [`UnnamedFile::persist`] to give it a path. If it's dropped without being persisted, it's
+/// deleted. This lets you write the complete contents of a file before it becomes visible at its
+/// path, like writing to a temporary file and then renaming it.
+///
+/// # Examples
+///
+/// ```
+/// # use sqlarfs::Connection;
+/// # let mut connection = Connection::open_in_memory()?;
+/// # let mut tx = connection.transaction()?;
+/// # let mut archive = tx.archive_mut();
+/// let mut file = archive.create_unnamed()?;
+/// file.write_str("Hello, world!")?;
+/// file.persist("file")?;
+///
+/// assert!(archive.open("file")?.exists()?);
+/// # sqlarfs::Result::Ok(())
+/// ```
+///
+/// [`Archive::create_unnamed`]: crate::Archive::create_unnamed
+#[derive(Debug)]
+pub struct UnnamedFile<'conn, 'ar> {
+    file: File<'conn, 'ar>,
+    path_normalization: PathNormalization,
+    persisted: bool,
+}
+
+impl<'conn, 'ar> UnnamedFile<'conn, 'ar> {
+    pub(super) fn new(file: File<'conn, 'ar>, path_normalization: PathNormalization) -> Self {
+        Self {
+            file,
+            path_normalization,
+            persisted: false,
+        }
+    }
+
+    /// Give this file a path in the archive.
+    ///
+    /// If this returns an error, the file is deleted.
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidArgs`]: The given path is empty or absolute.
+    /// - [`FileAlreadyExists`]: There is already a file at `path`.
+    /// - [`NoParentDirectory`]: The parent directory of `path` does not exist.
+    /// - [`FileAlreadyOpen`]: There's already a handle to the file at `path`.
+    ///
+    /// [`InvalidArgs`]: crate::Error::InvalidArgs
+    /// [`FileAlreadyExists`]: crate::Error::FileAlreadyExists
+    /// [`NoParentDirectory`]: crate::Error::NoParentDirectory
+    /// [`FileAlreadyOpen`]: crate::Error::FileAlreadyOpen
+    pub fn persist<P: AsRef<Path>>(mut self, path: P) -> crate::Result<()> {
+        let path = self.path_normalization.apply(path.as_ref()).into_owned();
+
+        self.file.link_at(&path)?;
+        self.persisted = true;
+
+        Ok(())
+    }
+
+    /// The metadata of the file.
+    ///
+    /// See [`File::metadata`].
+    pub fn metadata(&self) -> crate::Result<FileMetadata> {
+        self.file.metadata()
+    }
+
+    /// Set the file mode.
+    ///
+    /// See [`File::set_mode`].
+    pub fn set_mode(&mut self, mode: Option<FileMode>) -> crate::Result<()> {
+        self.file.set_mode(mode)
+    }
+
+    /// Set the time the file was last modified.
+    ///
+    /// See [`File::set_mtime`].
+    pub fn set_mtime(&mut self, mtime: Option<SystemTime>) -> crate::Result<()> {
+        self.file.set_mtime(mtime)
+    }
+
+    /// The current compression method used when writing to the file.
+    ///
+    /// See [`File::compression`].
+    pub fn compression(&self) -> Compression {
+        self.file.compression()
+    }
+
+    /// Set the compression method used when writing to the file.
+    ///
+    /// See [`File::set_compression`].
+    pub fn set_compression(&mut self, method: Compression) {
+        self.file.set_compression(method)
+    }
+
+    /// Overwrite the file with the contents of `reader`.
+    ///
+    /// See [`File::write_from`].
+    pub fn write_from<R>(&mut self, reader: &mut R) -> crate::Result<()>
+    where
+        R: ?Sized + Read,
+    {
+        self.file.write_from(reader)
+    }
+
+    /// Overwrite the file with the given bytes.
+    ///
+    /// See [`File::write_bytes`].
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> crate::Result<()> {
+        self.file.write_bytes(bytes)
+    }
+
+    /// Overwrite the file with the given string.
+    ///
+    /// See [`File::write_str`].
+    pub fn write_str<S: AsRef<str>>(&mut self, s: S) -> crate::Result<()> {
+        self.file.write_str(s)
+    }
+
+    /// Copy the contents of the given `file` into this file.
+    ///
+    /// See [`File::write_file`].
+    pub fn write_file(&mut self, file: &mut fs::File) -> crate::Result<()> {
+        self.file.write_file(file)
+    }
+}
+
+impl<'conn, 'ar> Drop for UnnamedFile<'conn, 'ar> {
+    fn drop(&mut self) {
+        if !self.persisted {
+            // If this fails, the file is left in the archive under its placeholder path. There's
+            // nothing more we can do about that here.
+            let _ = self.file.delete();
+        }
+    }
+}
@@ -1,8 +1,202 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::env;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 // This conversion should always succeed.
 pub fn u64_from_usize(num: usize) -> u64 {
     u64::try_from(num).expect("Failed converting a usize into a u64.")
 }
 
+// How many bytes at the start of a file to look at when guessing whether it's text.
+pub const TEXT_SNIFF_LEN: usize = 8 * 1024;
+
+// Guess whether a file is text from the bytes at the start of it. Like `grep`, we treat a file as
+// binary if it contains a NUL byte, and we also require it to be valid UTF-8.
+pub fn looks_like_text(head: &[u8]) -> bool {
+    if head.contains(&0) {
+        return false;
+    }
+
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        // The head of the file may end partway through a multi-byte character.
+        Err(err) => err.error_len().is_none(),
+    }
+}
+
+// Read the `SOURCE_DATE_EPOCH` environment variable, as defined by the reproducible builds
+// project: https://reproducible-builds.org/specs/source-date-epoch/
+pub fn source_date_epoch() -> crate::Result<Option<SystemTime>> {
+    let value = match env::var("SOURCE_DATE_EPOCH") {
+        Ok(value) => value,
+        Err(env::VarError::NotPresent) => return Ok(None),
+        Err(env::VarError::NotUnicode(_)) => {
+            return Err(crate::Error::InvalidArgs {
+                reason: String::from(
+                    "The `SOURCE_DATE_EPOCH` environment variable is not valid Unicode.",
+                ),
+            })
+        }
+    };
+
+    let secs = value
+        .trim()
+        .parse::<u64>()
+        .map_err(|err| crate::Error::InvalidArgs {
+            reason: format!(
+                "The `SOURCE_DATE_EPOCH` environment variable is not a valid Unix timestamp: {err}"
+            ),
+        })?;
+
+    Ok(Some(UNIX_EPOCH + Duration::from_secs(secs)))
+}
+
+// Clamp `mtime` so it's no later than `SOURCE_DATE_EPOCH`, if it's set.
+pub fn clamp_to_source_date_epoch(mtime: SystemTime) -> crate::Result<SystemTime> {
+    Ok(match source_date_epoch()? {
+        Some(epoch) if epoch < mtime => epoch,
+        _ => mtime,
+    })
+}
+
+// Convert `path` into an extended-length path (one starting with `\\?\`) so that filesystem
+// operations on it aren't limited to `MAX_PATH` characters. Extended-length paths are passed to
+// the Windows API without being normalized, so we make the path absolute and normalize it
+// ourselves.
+//
+// Paths that can't be converted are returned as-is.
+#[cfg(windows)]
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    use std::ffi::OsString;
+    use std::path::{Component, PathBuf, Prefix};
+
+    let absolute_path = if path.is_absolute() {
+        Cow::Borrowed(path)
+    } else {
+        match env::current_dir() {
+            Ok(current_dir) => Cow::Owned(current_dir.join(path)),
+            Err(_) => return Cow::Borrowed(path),
+        }
+    };
+
+    // This is a drive-relative path like `C:file`, which we can't resolve.
+    if !absolute_path.has_root() {
+        return Cow::Borrowed(path);
+    }
+
+    let mut components = absolute_path.components();
+
+    let mut long_path = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(_) => {
+                let mut long_path = OsString::from(r"\\?\");
+                long_path.push(prefix.as_os_str());
+                long_path
+            }
+            Prefix::UNC(server, share) => {
+                let mut long_path = OsString::from(r"\\?\UNC\");
+                long_path.push(server);
+                long_path.push(r"\");
+                long_path.push(share);
+                long_path
+            }
+            // This is either already an extended-length path or a device path.
+            _ => return Cow::Borrowed(path),
+        },
+        _ => return Cow::Borrowed(path),
+    };
+
+    let mut normal_components = Vec::new();
+
+    for component in components {
+        match component {
+            Component::Normal(name) => normal_components.push(name),
+            Component::ParentDir => {
+                normal_components.pop();
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+
+    // A path consisting of just a drive needs a trailing separator to refer to its root.
+    if normal_components.is_empty() {
+        long_path.push(r"\");
+    }
+
+    for name in normal_components {
+        long_path.push(r"\");
+        long_path.push(name);
+    }
+
+    Cow::Owned(PathBuf::from(long_path))
+}
+
+// Paths aren't limited to `MAX_PATH` characters on other platforms.
+#[cfg(not(windows))]
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+// Consume a run of ASCII digits, returning it without any leading zeros.
+fn take_number(chars: &mut Peekable<Chars<'_>>) -> String {
+    let mut digits = String::new();
+
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        if !(digits.is_empty() && c == '0') {
+            digits.push(c);
+        }
+    }
+
+    digits
+}
+
+// Compare two paths in "natural" order, ignoring case and comparing runs of digits by their
+// numeric value, so `file2` comes before `file10`. Path separators sort before every other
+// character, so the contents of a directory stay together.
+//
+// Paths that are equal under these rules (like `File` and `file`, or `01` and `1`) fall back to
+// comparing their bytes, so this is a total order.
+pub fn natural_cmp(left: &str, right: &str) -> Ordering {
+    let mut left_chars = left.chars().peekable();
+    let mut right_chars = right.chars().peekable();
+
+    loop {
+        let ordering = match (left_chars.peek().copied(), right_chars.peek().copied()) {
+            (None, None) => return left.cmp(right),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(l), Some(r)) if l.is_ascii_digit() && r.is_ascii_digit() => {
+                let left_num = take_number(&mut left_chars);
+                let right_num = take_number(&mut right_chars);
+
+                left_num
+                    .len()
+                    .cmp(&right_num.len())
+                    .then_with(|| left_num.cmp(&right_num))
+            }
+            (Some(l), Some(r)) => {
+                left_chars.next();
+                right_chars.next();
+
+                match (l, r) {
+                    ('/', '/') => Ordering::Equal,
+                    ('/', _) => Ordering::Less,
+                    (_, '/') => Ordering::Greater,
+                    _ => l.to_lowercase().cmp(r.to_lowercase()),
+                }
+            }
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -13,4 +207,52 @@ mod tests {
     fn convert_int() {
         expect!(u64_from_usize(42)).to(equal(42));
     }
+
+    #[test]
+    fn natural_cmp_compares_numbers_by_value() {
+        expect!(natural_cmp("file2", "file10")).to(equal(Ordering::Less));
+        expect!(natural_cmp("file10", "file2")).to(equal(Ordering::Greater));
+        expect!(natural_cmp("1file", "01file")).to(equal("1file".cmp("01file")));
+        expect!(natural_cmp("file002", "file10")).to(equal(Ordering::Less));
+    }
+
+    #[test]
+    fn natural_cmp_ignores_case() {
+        expect!(natural_cmp("b", "C")).to(equal(Ordering::Less));
+        expect!(natural_cmp("File", "file")).to(equal("File".cmp("file")));
+    }
+
+    #[test]
+    fn natural_cmp_sorts_path_separators_first() {
+        expect!(natural_cmp("dir/file", "dir-file")).to(equal(Ordering::Less));
+        expect!(natural_cmp("dir", "dir/file")).to(equal(Ordering::Less));
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn long_path_adds_extended_length_prefix() {
+        expect!(long_path(Path::new(r"C:\dir\..\file")).into_owned())
+            .to(equal(Path::new(r"\\?\C:\file").to_path_buf()));
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn long_path_converts_unc_paths() {
+        expect!(long_path(Path::new(r"\\server\share\file")).into_owned())
+            .to(equal(Path::new(r"\\?\UNC\server\share\file").to_path_buf()));
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn long_path_leaves_extended_length_paths_alone() {
+        expect!(long_path(Path::new(r"\\?\C:\file")).into_owned())
+            .to(equal(Path::new(r"\\?\C:\file").to_path_buf()));
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn long_path_is_a_no_op() {
+        expect!(long_path(Path::new("dir/../file")).into_owned())
+            .to(equal(Path::new("dir/../file").to_path_buf()));
+    }
 }
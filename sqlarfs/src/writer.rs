@@ -0,0 +1,181 @@
+use std::io::{self, Write};
+
+use super::file::{File, SPOOL_CHUNK_SIZE};
+#[cfg(any(feature = "deflate", feature = "zstd"))]
+use super::stream::Encoder;
+use super::util::u64_from_usize;
+
+// The compressed copy of the data written to a `FileWriter`.
+#[cfg(any(feature = "deflate", feature = "zstd"))]
+#[derive(Debug)]
+struct CompressedSpool {
+    spool: u64,
+    encoder: Encoder,
+    // The number of compressed bytes that have been spooled so far.
+    len: u64,
+}
+
+/// A writable stream of data into a [`File`].
+///
+/// This is returned by [`File::writer`]. It implements [`Write`], so you can stream data into a
+/// file from any producer without holding it all in memory.
+///
+/// Data written to this writer is staged in a temporary table in the database, and the contents of
+/// the file are only replaced once the writer is finished. Call [`FileWriter::finish`] to finish
+/// writing and handle any errors. If the writer is dropped without being finished, it's finished
+/// automatically, but errors are ignored.
+///
+/// [`File`]: crate::File
+/// [`File::writer`]: crate::File::writer
+#[derive(Debug)]
+pub struct FileWriter<'file, 'conn, 'ar> {
+    file: &'file mut File<'conn, 'ar>,
+    // The spool for the uncompressed data.
+    spool: u64,
+    // Data that's been written but not spooled yet.
+    buf: Vec<u8>,
+    // The number of uncompressed bytes written so far.
+    len: u64,
+    // We don't know whether the data is compressible until we've seen all of it, so if
+    // compression is enabled, we spool both a compressed and an uncompressed copy and keep the
+    // smaller one. The sqlar spec requires that we only store compressed data if it's smaller.
+    #[cfg(any(feature = "deflate", feature = "zstd"))]
+    compressed: Option<CompressedSpool>,
+    finished: bool,
+}
+
+impl<'file, 'conn, 'ar> FileWriter<'file, 'conn, 'ar> {
+    pub(super) fn new(file: &'file mut File<'conn, 'ar>) -> crate::Result<Self> {
+        let store = file.store();
+
+        #[cfg(any(feature = "deflate", feature = "zstd"))]
+        let compressed = match Encoder::new(file.compression())? {
+            Some(encoder) => Some(CompressedSpool {
+                spool: store.create_spool()?,
+                encoder,
+                len: 0,
+            }),
+            None => None,
+        };
+
+        Ok(Self {
+            spool: store.create_spool()?,
+            file,
+            buf: Vec::new(),
+            len: 0,
+            #[cfg(any(feature = "deflate", feature = "zstd"))]
+            compressed,
+            finished: false,
+        })
+    }
+
+    fn spool_buf(&mut self) -> crate::Result<()> {
+        if !self.buf.is_empty() {
+            self.file.store().append_spool(self.spool, &self.buf)?;
+            self.buf.clear();
+        }
+
+        Ok(())
+    }
+
+    // Spool the output of the encoder once there's a full chunk of it, or all of it if `finish`
+    // is `true`.
+    #[cfg(any(feature = "deflate", feature = "zstd"))]
+    fn spool_compressed(&mut self, finish: bool) -> crate::Result<()> {
+        if let Some(compressed) = &mut self.compressed {
+            if finish {
+                compressed.encoder.try_finish()?;
+            }
+
+            let output = compressed.encoder.output();
+
+            if !output.is_empty() && (finish || output.len() >= SPOOL_CHUNK_SIZE) {
+                self.file.store().append_spool(compressed.spool, output)?;
+                compressed.len += u64_from_usize(output.len());
+                output.clear();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finish writing and replace the contents of the file with the data written to this writer.
+    ///
+    /// # Errors
+    ///
+    /// - [`FileNotFound`]: The file was deleted while it was being written to.
+    ///
+    /// [`FileNotFound`]: crate::Error::FileNotFound
+    pub fn finish(mut self) -> crate::Result<()> {
+        self.finish_inner()
+    }
+
+    fn finish_inner(&mut self) -> crate::Result<()> {
+        self.finished = true;
+
+        let result = self.write_to_file();
+
+        let store = self.file.store();
+
+        #[cfg(any(feature = "deflate", feature = "zstd"))]
+        if let Some(compressed) = &self.compressed {
+            store.clear_spool(compressed.spool)?;
+        }
+
+        store.clear_spool(self.spool)?;
+
+        result
+    }
+
+    fn write_to_file(&mut self) -> crate::Result<()> {
+        self.spool_buf()?;
+
+        #[cfg(any(feature = "deflate", feature = "zstd"))]
+        {
+            self.spool_compressed(true)?;
+
+            if let Some(compressed) = &self.compressed {
+                if compressed.len < self.len {
+                    return self
+                        .file
+                        .write_spool(compressed.spool, compressed.len, self.len);
+                }
+            }
+        }
+
+        self.file.write_spool(self.spool, self.len, self.len)
+    }
+}
+
+impl<'file, 'conn, 'ar> Write for FileWriter<'file, 'conn, 'ar> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        #[cfg(any(feature = "deflate", feature = "zstd"))]
+        if let Some(compressed) = &mut self.compressed {
+            compressed.encoder.write_all(buf)?;
+            self.spool_compressed(false)?;
+        }
+
+        self.buf.extend_from_slice(buf);
+        self.len += u64_from_usize(buf.len());
+
+        if self.buf.len() >= SPOOL_CHUNK_SIZE {
+            self.spool_buf()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    // This moves any buffered data into the database, but the contents of the file aren't
+    // replaced until the writer is finished.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(self.spool_buf()?)
+    }
+}
+
+impl<'file, 'conn, 'ar> Drop for FileWriter<'file, 'conn, 'ar> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.finish_inner();
+        }
+    }
+}
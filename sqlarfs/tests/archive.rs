@@ -1,12 +1,22 @@
+//! Tests for `Archive`.
+
 mod common;
 
 use std::ffi::OsStr;
+use std::fs;
+use std::io::Read;
 use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use sqlarfs::{Error, FileMode};
-use xpct::{be_err, be_ok, equal, expect, match_pattern, pattern};
+use sqlarfs::{
+    ArchiveOptions, Connection, Error, FileMode, ListOptions, RenamePolicy, RetentionPolicy,
+};
+use xpct::{
+    approx_eq_time, be_err, be_false, be_gt, be_none, be_ok, be_some, be_true, equal, expect,
+    match_pattern, pattern,
+};
 
-use common::connection;
+use common::{connection, rerun_with_env};
 
 //
 // `Archive::open`
@@ -54,6 +64,70 @@ fn opening_file_with_empty_path_errors() -> sqlarfs::Result<()> {
     })
 }
 
+#[test]
+fn opening_same_file_twice_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let _file = archive.open("file")?;
+
+        expect!(archive.open("file/"))
+            .to(be_err())
+            .to(equal(Error::FileAlreadyOpen {
+                path: "file".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn opening_file_again_after_handle_is_dropped() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        expect!(archive.open("file")).to(be_ok());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copying_between_two_open_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut source = archive.open("source")?;
+        source.create_file()?;
+        source.write_str("contents")?;
+
+        let mut dest = archive.open("dest")?;
+        dest.create_file()?;
+        dest.write_from(&mut source.reader()?)?;
+
+        let mut contents = String::new();
+        dest.reader()?.read_to_string(&mut contents)?;
+
+        expect!(contents).to(equal("contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn persisting_unnamed_file_to_open_path_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let _file = archive.open("file")?;
+
+        let mut unnamed = archive.create_unnamed()?;
+        unnamed.write_str("contents")?;
+
+        expect!(unnamed.persist("file"))
+            .to(be_err())
+            .to(equal(Error::FileAlreadyOpen {
+                path: "file".into(),
+            }));
+
+        Ok(())
+    })
+}
+
 #[test]
 fn opening_file_strips_trailing_slashes() -> sqlarfs::Result<()> {
     connection()?.exec(|archive| {
@@ -66,6 +140,171 @@ fn opening_file_strips_trailing_slashes() -> sqlarfs::Result<()> {
     })
 }
 
+//
+// `Archive::open_many`
+//
+
+#[test]
+fn open_many_returns_handles_in_order() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let paths = archive
+            .open_many(["b", "a", "c/"])?
+            .iter()
+            .map(|file| file.path().to_owned())
+            .collect::<Vec<_>>();
+
+        expect!(paths).to(equal(vec![
+            Path::new("b").to_owned(),
+            Path::new("a").to_owned(),
+            Path::new("c").to_owned(),
+        ]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn open_many_handles_can_be_used_at_once() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut files = archive.open_many(["source", "dest"])?;
+        let [source, dest] = files.as_mut_slice() else {
+            unreachable!()
+        };
+
+        source.create_file()?;
+        source.write_str("contents")?;
+
+        dest.create_file()?;
+        dest.write_from(&mut source.reader()?)?;
+
+        let mut contents = String::new();
+        dest.reader()?.read_to_string(&mut contents)?;
+
+        expect!(contents).to(equal("contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn open_many_with_duplicate_paths_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(archive.open_many(["file", "other", "file/"]))
+            .to(be_err())
+            .to(equal(Error::FileAlreadyOpen {
+                path: "file".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn open_many_with_invalid_path_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(archive.open_many(["file", ""]))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn deleting_matching_open_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let mut file = archive.open("dir/file")?;
+        file.create_file()?;
+
+        expect!(archive.delete_matching(&ListOptions::new().descendants_of("dir")))
+            .to(be_err())
+            .to(equal(Error::FileInUse {
+                path: "dir/file".into(),
+            }));
+
+        expect!(file.exists()).to(be_ok()).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn renaming_ancestor_of_open_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let mut file = archive.open("dir/file")?;
+        file.create_file()?;
+
+        expect!(archive.rename_with("dir", "other", RenamePolicy::Error))
+            .to(be_err())
+            .to(equal(Error::FileInUse {
+                path: "dir/file".into(),
+            }));
+
+        expect!(file.exists()).to(be_ok()).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn renaming_over_open_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("source")?.create_file()?;
+
+        let mut file = archive.open("dest")?;
+        file.create_file()?;
+
+        expect!(archive.rename_with("source", "dest", RenamePolicy::Overwrite))
+            .to(be_err())
+            .to(equal(Error::FileInUse {
+                path: "dest".into(),
+            }));
+
+        expect!(archive.open("source")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn pruning_open_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_mtime(Some(UNIX_EPOCH + Duration::from_secs(1)))?;
+
+        let mut newer = archive.open("newer")?;
+        newer.create_file()?;
+        newer.set_mtime(Some(UNIX_EPOCH + Duration::from_secs(2)))?;
+        drop(newer);
+
+        expect!(archive.prune(&RetentionPolicy::new().keep_newest(0)))
+            .to(be_err())
+            .to(equal(Error::FileInUse {
+                path: "file".into(),
+            }));
+
+        // Nothing is deleted if any of the files are open.
+        expect!(archive.open("newer")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        drop(file);
+
+        expect!(archive.prune(&RetentionPolicy::new().keep_newest(0)))
+            .to(be_ok())
+            .to(equal(2));
+
+        Ok(())
+    })
+}
+
 //
 // `Archive::umask` / `Archive::set_umask`
 //
@@ -99,3 +338,450 @@ fn files_inherit_archive_umask() -> sqlarfs::Result<()> {
         Ok(())
     })
 }
+
+//
+// `Archive::set_source_date_epoch`
+//
+
+#[test]
+fn created_files_are_clamped_to_source_date_epoch() -> sqlarfs::Result<()> {
+    if let Some(passed) = rerun_with_env(
+        "created_files_are_clamped_to_source_date_epoch",
+        &[("SOURCE_DATE_EPOCH", "1000")],
+    )? {
+        expect!(passed).to(be_true());
+        return Ok(());
+    }
+
+    connection()?.exec(|archive| {
+        archive.set_source_date_epoch(true);
+
+        archive.open("file")?.create_file()?;
+        archive.open("path/to/dir")?.create_dir_all()?;
+
+        expect!(archive.open("file")?.metadata()?.mtime())
+            .to(equal(Some(UNIX_EPOCH + Duration::from_secs(1000))));
+
+        expect!(archive.open("path")?.metadata()?.mtime())
+            .to(equal(Some(UNIX_EPOCH + Duration::from_secs(1000))));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn source_date_epoch_is_ignored_by_default() -> sqlarfs::Result<()> {
+    if let Some(passed) = rerun_with_env(
+        "source_date_epoch_is_ignored_by_default",
+        &[("SOURCE_DATE_EPOCH", "1000")],
+    )? {
+        expect!(passed).to(be_true());
+        return Ok(());
+    }
+
+    connection()?.exec(|archive| {
+        expect!(archive.source_date_epoch()).to(be_false());
+
+        archive.open("file")?.create_file()?;
+
+        expect!(archive.open("file")?.metadata()?.mtime())
+            .to(be_some())
+            .to(approx_eq_time(SystemTime::now(), Duration::from_secs(2)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn invalid_source_date_epoch_errors() -> sqlarfs::Result<()> {
+    if let Some(passed) = rerun_with_env(
+        "invalid_source_date_epoch_errors",
+        &[("SOURCE_DATE_EPOCH", "not a timestamp")],
+    )? {
+        expect!(passed).to(be_true());
+        return Ok(());
+    }
+
+    connection()?.exec(|archive| {
+        archive.set_source_date_epoch(true);
+
+        expect!(archive.open("file")?.create_file())
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::set_clock`
+//
+
+#[test]
+fn created_files_get_mtime_from_clock() -> sqlarfs::Result<()> {
+    let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+    connection()?.exec(|archive| {
+        archive.set_clock(move || now);
+
+        archive.open("file")?.create_file()?;
+        archive.open("path/to/dir")?.create_dir_all()?;
+
+        expect!(archive.open("file")?.metadata()?.mtime()).to(equal(Some(now)));
+        expect!(archive.open("path/to/dir")?.metadata()?.mtime()).to(equal(Some(now)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn updating_mtime_on_write_uses_clock() -> sqlarfs::Result<()> {
+    let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        archive.set_clock(move || now);
+        archive.set_update_mtime(true);
+
+        archive.open("file")?.write_str("contents")?;
+
+        expect!(archive.open("file")?.metadata()?.mtime()).to(equal(Some(now)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_without_metadata_uses_clock() -> sqlarfs::Result<()> {
+    let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("small"), "contents")?;
+    fs::write(temp_dir.path().join("large"), vec![0u8; 64 * 1024])?;
+
+    connection()?.exec(|archive| {
+        archive.set_clock(move || now);
+
+        let opts = ArchiveOptions::new().preserve_metadata(false);
+        archive.archive_with(temp_dir.path(), "dir", &opts)?;
+
+        for path in ["dir", "dir/small", "dir/large"] {
+            expect!(archive.open(path)?.metadata()?.mtime()).to(equal(Some(now)));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn reset_clock_uses_system_time() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.set_clock(|| UNIX_EPOCH);
+        archive.reset_clock();
+
+        archive.open("file")?.create_file()?;
+
+        expect!(archive.open("file")?.metadata()?.mtime())
+            .to(be_some())
+            .to(approx_eq_time(SystemTime::now(), Duration::from_secs(2)));
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::set_max_name_len`
+//
+
+#[test]
+fn creating_file_with_path_longer_than_max_name_len_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.set_max_name_len(8);
+
+        expect!(archive.open("12345678")?.create_file()).to(be_ok());
+
+        expect!(archive.open("123456789")?.create_dir())
+            .to(be_err())
+            .to(equal(Error::NameTooLong {
+                path: "123456789".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn max_name_len_counts_bytes() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.set_max_name_len(4);
+
+        // This is two characters, but six bytes.
+        expect!(archive.open("日本")?.create_file())
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::NameTooLong { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn renaming_dir_so_descendant_path_is_too_long_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.set_max_name_len(12);
+
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/file")?.create_file()?;
+
+        // `long-dir` is short enough, but `long-dir/file` is not.
+        expect!(archive.rename("dir", "long-dir"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::NameTooLong { .. })));
+
+        expect!(archive.open("dir/file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::set_track_modified` / `Archive::last_modified`
+//
+
+// Wait long enough that the next modification gets a later timestamp.
+fn wait_for_next_millisecond() {
+    std::thread::sleep(Duration::from_millis(5));
+}
+
+#[test]
+fn last_modified_is_none_when_not_tracked() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        expect!(archive.last_modified()).to(be_ok()).to(be_none());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn last_modified_is_set_when_tracking_is_enabled() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.set_track_modified(true)?;
+
+        expect!(archive.last_modified())
+            .to(be_ok())
+            .to(be_some())
+            .to(approx_eq_time(SystemTime::now(), Duration::from_secs(5)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn last_modified_is_updated_by_changes() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.set_track_modified(true)?;
+
+        let mut last_modified = archive.last_modified()?;
+
+        type Change = dyn Fn(&mut sqlarfs::Archive) -> sqlarfs::Result<()>;
+
+        let changes: [&Change; 8] = [
+            &|archive| archive.open("file")?.create_file(),
+            &|archive| archive.open("file")?.write_str("contents"),
+            &|archive| archive.open("file")?.set_mode(Some(FileMode::OWNER_R)),
+            &|archive| archive.open("file")?.set_meta("key", "value"),
+            &|archive| archive.open("file")?.pin(),
+            &|archive| archive.open("file")?.unpin().map(|_| ()),
+            &|archive| archive.rename("file", "renamed"),
+            &|archive| archive.open("renamed")?.delete(),
+        ];
+
+        for change in changes {
+            wait_for_next_millisecond();
+            change(archive)?;
+
+            let new_last_modified = archive.last_modified()?;
+
+            expect!(new_last_modified).to(be_gt(last_modified));
+
+            last_modified = new_last_modified;
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn last_modified_is_not_updated_by_reads() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.set_track_modified(true)?;
+        archive.open("file")?.create_file()?;
+
+        let last_modified = archive.last_modified()?;
+
+        wait_for_next_millisecond();
+
+        archive.open("file")?.metadata()?;
+        archive.list()?.count();
+
+        expect!(archive.last_modified())
+            .to(be_ok())
+            .to(equal(last_modified));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn tracking_modified_persists_across_connections() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    Connection::create_new(&path)?.exec(|archive| archive.set_track_modified(true))?;
+
+    let last_modified = Connection::open(&path)?.exec(|archive| {
+        let last_modified = archive.last_modified()?;
+
+        wait_for_next_millisecond();
+        archive.open("file")?.create_file()?;
+
+        sqlarfs::Result::Ok(last_modified)
+    })?;
+
+    expect!(Connection::open_readonly(&path)?.exec(|archive| archive.last_modified()))
+        .to(be_ok())
+        .to(be_gt(last_modified));
+
+    Ok(())
+}
+
+//
+// `Archive::generation`
+//
+
+#[test]
+fn generation_is_none_when_not_tracked() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        expect!(archive.generation()).to(be_ok()).to(be_none());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn generation_starts_at_zero() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+        archive.set_track_modified(true)?;
+
+        expect!(archive.generation()).to(be_ok()).to(equal(Some(0)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn generation_is_incremented_by_changes() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.set_track_modified(true)?;
+
+        let mut generation = archive.generation()?;
+
+        type Change = dyn Fn(&mut sqlarfs::Archive) -> sqlarfs::Result<()>;
+
+        // Unlike `last_modified`, we don't need to wait between changes.
+        let changes: [&Change; 8] = [
+            &|archive| archive.open("file")?.create_file(),
+            &|archive| archive.open("file")?.write_str("contents"),
+            &|archive| archive.open("file")?.set_mode(Some(FileMode::OWNER_R)),
+            &|archive| archive.open("file")?.set_meta("key", "value"),
+            &|archive| archive.open("file")?.pin(),
+            &|archive| archive.open("file")?.unpin().map(|_| ()),
+            &|archive| archive.rename("file", "renamed"),
+            &|archive| archive.open("renamed")?.delete(),
+        ];
+
+        for change in changes {
+            change(archive)?;
+
+            let new_generation = archive.generation()?;
+
+            expect!(new_generation).to(be_gt(generation));
+
+            generation = new_generation;
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn generation_is_not_incremented_by_reads() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.set_track_modified(true)?;
+        archive.open("file")?.create_file()?;
+
+        let generation = archive.generation()?;
+
+        archive.open("file")?.metadata()?;
+        archive.list()?.count();
+
+        expect!(archive.generation())
+            .to(be_ok())
+            .to(equal(generation));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn generation_is_incremented_by_changes_to_side_tables() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    Connection::create_new(&path)?.exec(|archive| {
+        archive.set_track_modified(true)?;
+        archive.open("file")?.create_file()
+    })?;
+
+    let generation = || Connection::open_readonly(&path)?.exec(|archive| archive.generation());
+
+    // These are changes another program could make to the tables that hold file metadata.
+    let changes = [
+        "INSERT INTO sqlar_pins (name) VALUES ('file')",
+        "DELETE FROM sqlar_pins",
+        "INSERT INTO sqlar_external (name, archive, target) VALUES ('file', 'other', 'file')",
+        "DELETE FROM sqlar_external",
+        "INSERT INTO sqlar_raw_names (name, raw) VALUES ('file', x'ff')",
+        "DELETE FROM sqlar_raw_names",
+    ];
+
+    for change in changes {
+        let old_generation = generation()?;
+
+        rusqlite::Connection::open(&path)?.execute_batch(change)?;
+
+        expect!(generation()).to(be_ok()).to(be_gt(old_generation));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn disabling_tracking_modified() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.set_track_modified(true)?;
+        archive.set_track_modified(false)?;
+
+        archive.open("file")?.create_file()?;
+
+        expect!(archive.last_modified()).to(be_ok()).to(be_none());
+
+        Ok(())
+    })
+}
@@ -1,20 +1,34 @@
+//! Tests for copying directory trees from the filesystem into an archive.
+
 mod common;
 
 use std::ffi::OsStr;
 use std::fs;
 use std::io::prelude::*;
-use std::time::{Duration, SystemTime};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use common::{
-    connection, have_file_metadata, have_symlink_metadata, into_sqlarfs_error, truncate_mtime,
-    with_timeout,
+    connection, have_file_metadata, have_symlink_metadata, into_sqlarfs_error, rerun_with_env,
+    truncate_mtime, with_timeout,
+};
+use sqlarfs::{
+    AppleMetadata, ArchiveOptions, Compression, Error, ExtractOptions, FileMode, FileType,
+    OverwritePolicy,
 };
-use sqlarfs::{ArchiveOptions, Error, FileMode, FileType};
 use xpct::{
-    approx_eq_time, be_err, be_false, be_ok, be_some, be_true, equal, expect, match_pattern,
+    approx_eq_time, be_err, be_false, be_gt, be_ok, be_some, be_true, equal, expect, match_pattern,
     pattern,
 };
 
+// Make a directory tree deep enough that its paths are longer than `MAX_PATH` on Windows.
+fn deep_path() -> PathBuf {
+    (0..20)
+        .map(|i| format!("{i:02}-{}", "d".repeat(16)))
+        .collect()
+}
+
 //
 // `Archive::archive`
 //
@@ -52,8 +66,7 @@ fn archiving_when_dest_path_already_exists_errors() -> sqlarfs::Result<()> {
     let temp_file = tempfile::NamedTempFile::new()?;
 
     connection()?.exec(|archive| {
-        let mut target = archive.open("file")?;
-        target.create_file()?;
+        archive.open("file")?.create_file()?;
 
         expect!(archive.archive(temp_file.path(), "file"))
             .to(be_err())
@@ -232,6 +245,30 @@ fn archive_with_trailing_slash_in_dest_path() -> sqlarfs::Result<()> {
     })
 }
 
+#[test]
+fn archiving_a_deep_tree_with_long_paths() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let deep_dir = temp_dir.path().join(deep_path());
+    fs::create_dir_all(&deep_dir)?;
+    fs::write(deep_dir.join("file"), "contents")?;
+
+    expect!(deep_dir.as_os_str().len()).to(be_gt(260));
+
+    connection()?.exec(|archive| {
+        expect!(archive.archive(temp_dir.path(), "dir")).to(be_ok());
+
+        let mut contents = String::new();
+        archive
+            .open(Path::new("dir").join(deep_path()).join("file"))?
+            .reader()?
+            .read_to_string(&mut contents)?;
+
+        expect!(contents).to(equal("contents"));
+
+        Ok(())
+    })
+}
+
 //
 // `ArchiveOptions::follow_symlinks`
 //
@@ -371,8 +408,7 @@ fn archive_directory_children_to_dir() -> sqlarfs::Result<()> {
         .open(temp_dir.path().join("file"))?;
 
     connection()?.exec(|archive| {
-        let mut target = archive.open("dir")?;
-        target.create_dir()?;
+        archive.open("dir")?.create_dir()?;
 
         let opts = ArchiveOptions::new().children(true);
 
@@ -428,8 +464,7 @@ fn archiving_directory_children_when_target_is_file_errors() -> sqlarfs::Result<
         .open(temp_dir.path().join("file"))?;
 
     connection()?.exec(|archive| {
-        let mut target = archive.open("file")?;
-        target.create_file()?;
+        archive.open("file")?.create_file()?;
 
         let opts = ArchiveOptions::new().children(true);
 
@@ -630,3 +665,1132 @@ fn archiving_with_filesystem_loop_in_grandparent_errors() -> sqlarfs::Result<()>
         })
     })
 }
+
+//
+// `ArchiveOptions::deterministic`
+//
+
+#[test]
+fn archiving_deterministically_sets_fixed_mtime() -> sqlarfs::Result<()> {
+    let expected_mtime = UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+    let temp_file = tempfile::NamedTempFile::new()?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new()
+            .deterministic(true)
+            .deterministic_mtime(expected_mtime);
+
+        expect!(archive.archive_with(temp_file.path(), "file", &opts)).to(be_ok());
+
+        expect!(archive.open("file")?.metadata())
+            .to(be_ok())
+            .to(have_file_metadata())
+            .map(|metadata| metadata.mtime)
+            .to(be_some())
+            .to(equal(expected_mtime));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(unix)]
+fn archiving_deterministically_normalizes_unix_file_mode() -> sqlarfs::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = tempfile::tempdir()?;
+
+    let regular_path = temp_dir.path().join("regular");
+    fs::File::create(&regular_path)?;
+    fs::set_permissions(&regular_path, fs::Permissions::from_mode(0o600))?;
+
+    let executable_path = temp_dir.path().join("executable");
+    fs::File::create(&executable_path)?;
+    fs::set_permissions(&executable_path, fs::Permissions::from_mode(0o700))?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().deterministic(true);
+
+        expect!(archive.archive_with(temp_dir.path(), "dir", &opts)).to(be_ok());
+
+        expect!(archive.open("dir")?.metadata()?.mode())
+            .to(be_some())
+            .to(equal(FileMode::from_bits_truncate(0o755)));
+
+        expect!(archive.open("dir/regular")?.metadata()?.mode())
+            .to(be_some())
+            .to(equal(FileMode::from_bits_truncate(0o644)));
+
+        expect!(archive.open("dir/executable")?.metadata()?.mode())
+            .to(be_some())
+            .to(equal(FileMode::from_bits_truncate(0o755)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_deterministically_produces_identical_archives() -> sqlarfs::Result<()> {
+    let source_dir = tempfile::tempdir()?;
+
+    for name in ["c", "a", "b"] {
+        fs::create_dir(source_dir.path().join(name))?;
+        fs::write(source_dir.path().join(name).join("file"), name)?;
+    }
+
+    let archive_dir = tempfile::tempdir()?;
+    let opts = ArchiveOptions::new().deterministic(true);
+
+    let mut archive_bytes = Vec::new();
+
+    for archive_name in ["first.sqlar", "second.sqlar"] {
+        let archive_path = archive_dir.path().join(archive_name);
+
+        sqlarfs::Connection::create_new(&archive_path)?
+            .exec(|archive| archive.archive_with(source_dir.path(), "dir", &opts))?;
+
+        archive_bytes.push(fs::read(&archive_path)?);
+
+        // Make sure the filesystem mtimes of the source files would differ between runs.
+        fs::File::open(source_dir.path().join("a").join("file"))?
+            .set_modified(SystemTime::now() + Duration::from_secs(60))?;
+    }
+
+    expect!(&archive_bytes[0]).to(equal(&archive_bytes[1]));
+
+    Ok(())
+}
+
+//
+// `ArchiveOptions::source_date_epoch`
+//
+
+#[test]
+fn archiving_clamps_mtime_to_source_date_epoch() -> sqlarfs::Result<()> {
+    if let Some(passed) = rerun_with_env(
+        "archiving_clamps_mtime_to_source_date_epoch",
+        &[("SOURCE_DATE_EPOCH", "1000")],
+    )? {
+        expect!(passed).to(be_true());
+        return Ok(());
+    }
+
+    let epoch = UNIX_EPOCH + Duration::from_secs(1000);
+
+    let temp_dir = tempfile::tempdir()?;
+
+    let old_path = temp_dir.path().join("old");
+    fs::File::create(&old_path)?.set_modified(UNIX_EPOCH + Duration::from_secs(10))?;
+
+    let new_path = temp_dir.path().join("new");
+    fs::File::create(&new_path)?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().source_date_epoch(true);
+
+        expect!(archive.archive_with(temp_dir.path(), "dir", &opts)).to(be_ok());
+
+        expect!(archive.open("dir")?.metadata()?.mtime()).to(equal(Some(epoch)));
+        expect!(archive.open("dir/new")?.metadata()?.mtime()).to(equal(Some(epoch)));
+
+        // Older mtimes are left alone.
+        expect!(archive.open("dir/old")?.metadata()?.mtime())
+            .to(equal(Some(UNIX_EPOCH + Duration::from_secs(10))));
+
+        Ok(())
+    })
+}
+
+//
+// `ArchiveOptions::store_empty_dirs`
+//
+
+#[test]
+fn archiving_without_empty_dirs_skips_them() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::create_dir_all(temp_dir.path().join("empty/nested"))?;
+    fs::create_dir(temp_dir.path().join("full"))?;
+    fs::write(temp_dir.path().join("full/file"), "contents")?;
+
+    connection()?.exec(|archive| {
+        archive.open("dest")?.create_dir()?;
+
+        let opts = ArchiveOptions::new().children(true).store_empty_dirs(false);
+
+        expect!(archive.archive_with(temp_dir.path(), "dest", &opts)).to(be_ok());
+
+        expect!(archive.open("dest/full/file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        expect!(archive.open("dest/empty")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        expect!(archive.open("dest/empty/nested")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_empty_dirs_stores_them_by_default() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::create_dir(temp_dir.path().join("empty"))?;
+
+    connection()?.exec(|archive| {
+        expect!(archive.archive(temp_dir.path().join("empty"), "empty")).to(be_ok());
+
+        expect!(archive.open("empty")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+//
+// `ArchiveOptions::prefix`
+//
+
+#[test]
+fn archiving_with_prefix_puts_files_under_prefix() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file"), "contents")?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().prefix("backups/2024-06-01");
+
+        expect!(archive.archive_with(temp_dir.path(), "dir", &opts)).to(be_ok());
+
+        expect!(archive.open("backups/2024-06-01")?.metadata())
+            .to(be_ok())
+            .map(|metadata| metadata.is_dir())
+            .to(be_true());
+
+        expect!(archive.open("backups/2024-06-01/dir/file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        expect!(archive.open("dir")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_children_with_prefix_and_empty_dest() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file"), "contents")?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().children(true).prefix("prefix");
+
+        expect!(archive.archive_with(temp_dir.path(), "", &opts)).to(be_ok());
+
+        expect!(archive.open("prefix/file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+//
+// `ArchiveOptions::escape_names`
+//
+
+// macOS doesn't allow file names that aren't valid UTF-8.
+#[test]
+#[cfg(all(unix, not(target_os = "macos")))]
+fn archiving_file_name_that_is_not_valid_unicode_errors_by_default() -> sqlarfs::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join(OsStr::from_bytes(b"caf\xe9")), "")?;
+
+    connection()?.exec(|archive| {
+        expect!(archive.archive(temp_dir.path(), "dir"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(all(unix, not(target_os = "macos")))]
+fn archiving_with_escape_names_escapes_file_names() -> sqlarfs::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(
+        temp_dir.path().join(OsStr::from_bytes(b"100%-\xff")),
+        "contents",
+    )?;
+    fs::write(temp_dir.path().join("100%"), "")?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().escape_names(true);
+
+        expect!(archive.archive_with(temp_dir.path(), "dir", &opts)).to(be_ok());
+
+        let mut contents = String::new();
+        archive
+            .open("dir/100%25-%FF")?
+            .reader()?
+            .read_to_string(&mut contents)?;
+
+        expect!(contents).to(equal("contents"));
+
+        // File names that are valid Unicode aren't escaped.
+        expect!(archive.open("dir/100%")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(all(unix, not(target_os = "macos")))]
+fn extracting_escaped_file_names_restores_them() -> sqlarfs::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let src_dir = tempfile::tempdir()?;
+    let dest_dir = tempfile::tempdir()?;
+
+    let dir_name = OsStr::from_bytes(b"dir-\xff");
+    let file_name = OsStr::from_bytes(b"file-\xfe");
+
+    fs::create_dir(src_dir.path().join(dir_name))?;
+    fs::write(src_dir.path().join(dir_name).join(file_name), "contents")?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().children(true).escape_names(true);
+
+        archive.archive_with(src_dir.path(), "", &opts)?;
+
+        // The name of the directory is restored, and so the name of its child is too.
+        expect!(archive.extract_with("", dest_dir.path(), &ExtractOptions::new().children(true)))
+            .to(be_ok());
+
+        expect!(fs::read_to_string(
+            dest_dir.path().join(dir_name).join(file_name)
+        ))
+        .to(be_ok())
+        .to(equal("contents"));
+
+        // The name of the source file isn't restored, because we were given its destination.
+        expect!(archive.extract("dir-%FF", dest_dir.path().join("dir"))).to(be_ok());
+
+        expect!(fs::read_to_string(
+            dest_dir.path().join("dir").join(file_name)
+        ))
+        .to(be_ok())
+        .to(equal("contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(all(unix, not(target_os = "macos")))]
+fn extracting_renamed_escaped_file_uses_new_name() -> sqlarfs::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let src_dir = tempfile::tempdir()?;
+    let dest_dir = tempfile::tempdir()?;
+
+    fs::write(src_dir.path().join(OsStr::from_bytes(b"\xff")), "")?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().escape_names(true);
+
+        archive.archive_with(src_dir.path(), "dir", &opts)?;
+        archive.rename("dir/%FF", "dir/renamed")?;
+
+        expect!(archive.extract("dir", dest_dir.path().join("dir"))).to(be_ok());
+
+        expect!(dest_dir.path().join("dir/renamed").exists()).to(be_true());
+
+        Ok(())
+    })
+}
+
+//
+// `ArchiveOptions::apple_metadata`
+//
+
+#[test]
+fn archiving_with_apple_metadata_skipped_leaves_out_finder_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file"), "")?;
+    fs::write(temp_dir.path().join("._file"), "")?;
+    fs::write(temp_dir.path().join(".DS_Store"), "")?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().apple_metadata(AppleMetadata::Skip);
+
+        expect!(archive.archive_with(temp_dir.path(), "dir", &opts)).to(be_ok());
+
+        expect!(archive.open("dir/file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        expect!(archive.open("dir/._file")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        expect!(archive.open("dir/.DS_Store")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_keeps_apple_metadata_by_default() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("._file"), "")?;
+
+    connection()?.exec(|archive| {
+        expect!(archive.archive(temp_dir.path(), "dir")).to(be_ok());
+
+        expect!(archive.open("dir/._file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+//
+// `ArchiveOptions::exclude`
+//
+
+#[test]
+fn archiving_with_exclude_leaves_out_matching_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file"), "")?;
+    fs::write(temp_dir.path().join("file.tmp"), "")?;
+    fs::create_dir(temp_dir.path().join("excluded"))?;
+    fs::write(temp_dir.path().join("excluded/file"), "")?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().exclude(|path| {
+            path.extension() == Some(OsStr::new("tmp"))
+                || path.file_name() == Some(OsStr::new("excluded"))
+        });
+
+        expect!(archive.archive_with(temp_dir.path(), "dir", &opts)).to(be_ok());
+
+        expect!(archive.open("dir/file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        expect!(archive.open("dir/file.tmp")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        expect!(archive.open("dir/excluded")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_with_exclude_always_archives_source_file() -> sqlarfs::Result<()> {
+    let temp_file = tempfile::NamedTempFile::new()?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().exclude(|_| true);
+
+        expect!(archive.archive_with(temp_file.path(), "file", &opts)).to(be_ok());
+
+        expect!(archive.open("file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+//
+// `ArchiveOptions::compression`
+//
+
+#[test]
+#[cfg(feature = "deflate")]
+fn archiving_with_compression_sets_compression_method() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file"), "a".repeat(1024))?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().compression(Compression::None);
+        expect!(archive.archive_with(temp_dir.path().join("file"), "uncompressed", &opts))
+            .to(be_ok());
+
+        let opts = ArchiveOptions::new().compression(Compression::BEST);
+        expect!(archive.archive_with(temp_dir.path().join("file"), "compressed", &opts))
+            .to(be_ok());
+
+        expect!(archive.open("uncompressed")?.is_compressed())
+            .to(be_ok())
+            .to(be_false());
+
+        expect!(archive.open("compressed")?.is_compressed())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+//
+// `ArchiveOptions::overwrite`
+//
+
+#[test]
+fn archiving_with_replace_policy_overwrites_existing_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("existing"), "new contents")?;
+    fs::write(temp_dir.path().join("added"), "added contents")?;
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/existing")?.create_file()?;
+        archive.open("dir/existing")?.write_str("old contents")?;
+        archive.open("dir/untouched")?.create_file()?;
+
+        let opts = ArchiveOptions::new().overwrite(OverwritePolicy::Replace);
+
+        expect!(archive.archive_with(temp_dir.path(), "dir", &opts)).to(be_ok());
+
+        let mut contents = String::new();
+        archive
+            .open("dir/existing")?
+            .reader()?
+            .read_to_string(&mut contents)?;
+        expect!(contents).to(equal("new contents"));
+
+        expect!(archive.open("dir/added")?.exists())
+            .to(be_ok())
+            .to(be_true());
+        expect!(archive.open("dir/untouched")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_with_skip_policy_keeps_existing_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("existing"), "new contents")?;
+    fs::write(temp_dir.path().join("added"), "added contents")?;
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/existing")?.create_file()?;
+        archive.open("dir/existing")?.write_str("old contents")?;
+
+        let opts = ArchiveOptions::new().overwrite(OverwritePolicy::Skip);
+
+        expect!(archive.archive_with(temp_dir.path(), "dir", &opts)).to(be_ok());
+
+        let mut contents = String::new();
+        archive
+            .open("dir/existing")?
+            .reader()?
+            .read_to_string(&mut contents)?;
+        expect!(contents).to(equal("old contents"));
+
+        expect!(archive.open("dir/added")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_with_replace_policy_replaces_dir_with_file() -> sqlarfs::Result<()> {
+    let temp_file = tempfile::NamedTempFile::new()?;
+
+    connection()?.exec(|archive| {
+        archive.open("target")?.create_dir()?;
+        archive.open("target/child")?.create_file()?;
+
+        let opts = ArchiveOptions::new().overwrite(OverwritePolicy::Replace);
+
+        expect!(archive.archive_with(temp_file.path(), "target", &opts)).to(be_ok());
+
+        expect!(archive.open("target")?.metadata())
+            .to(be_ok())
+            .to(have_file_metadata());
+
+        expect!(archive.open("target/child")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+//
+// `ArchiveOptions::on_progress`
+//
+
+#[test]
+fn archiving_with_progress_callback_reports_each_file() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    fs::create_dir(temp_dir.path().join("dir"))?;
+    fs::write(temp_dir.path().join("dir/file1"), "12345")?;
+    fs::write(temp_dir.path().join("file2"), "123")?;
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let reports_clone = Arc::clone(&reports);
+
+    let opts = ArchiveOptions::new()
+        .children(true)
+        .on_progress(move |progress| reports_clone.lock().unwrap().push(progress.clone()));
+
+    connection()?.exec(|archive| {
+        expect!(archive.archive_with(temp_dir.path(), "", &opts)).to(be_ok());
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    let mut reports = reports.lock().unwrap().clone();
+
+    expect!(reports.len()).to(equal(3));
+    expect!(reports.last().map(|progress| progress.files_done())).to(equal(Some(3)));
+    expect!(reports.last().map(|progress| progress.bytes_done())).to(equal(Some(8)));
+    expect!(reports
+        .iter()
+        .all(|progress| progress.files_total().is_none()))
+    .to(be_true());
+
+    reports.sort_by(|left, right| left.path().cmp(right.path()));
+
+    expect!(reports
+        .iter()
+        .map(|progress| progress.path())
+        .collect::<Vec<_>>())
+    .to(equal(vec![
+        Path::new("dir"),
+        Path::new("dir/file1"),
+        Path::new("file2"),
+    ]));
+
+    Ok(())
+}
+
+//
+// `ArchiveOptions::map_path`
+//
+
+#[test]
+fn archiving_with_map_path_renames_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::create_dir(temp_dir.path().join("Dir"))?;
+    fs::write(temp_dir.path().join("Dir/File.TXT"), "contents")?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new()
+            .children(true)
+            .map_path(|path| Some(PathBuf::from(path.to_string_lossy().to_lowercase())));
+
+        expect!(archive.archive_with(temp_dir.path(), "", &opts)).to(be_ok());
+
+        let paths = archive
+            .list()?
+            .map(|entry| entry.map(|entry| entry.into_path()))
+            .collect::<sqlarfs::Result<Vec<_>>>()?;
+
+        expect!(paths).to(equal(vec![
+            PathBuf::from("dir"),
+            PathBuf::from("dir/file.txt"),
+        ]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_with_map_path_skips_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::create_dir(temp_dir.path().join("build"))?;
+    fs::write(temp_dir.path().join("build/output"), "contents")?;
+    fs::write(temp_dir.path().join("file"), "contents")?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().map_path(|path| {
+            if path.file_name() == Some(OsStr::new("build")) {
+                None
+            } else {
+                Some(path.to_owned())
+            }
+        });
+
+        expect!(archive.archive_with(temp_dir.path(), "dir", &opts)).to(be_ok());
+
+        expect!(archive.open("dir/file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        expect!(archive.open("dir/build")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        expect!(archive.open("dir/build/output")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_with_map_path_applies_to_source_file() -> sqlarfs::Result<()> {
+    let temp_file = tempfile::NamedTempFile::new()?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().map_path(|_| Some(PathBuf::from("renamed")));
+
+        expect!(archive.archive_with(temp_file.path(), "file", &opts)).to(be_ok());
+
+        expect!(archive.open("renamed")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        expect!(archive.open("file")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_with_map_path_to_missing_parent_errors() -> sqlarfs::Result<()> {
+    let temp_file = tempfile::NamedTempFile::new()?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().map_path(|_| Some(PathBuf::from("nonexistent/file")));
+
+        expect!(archive.archive_with(temp_file.path(), "file", &opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::NoParentDirectory { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `ArchiveOptions::inline_threshold` and `ArchiveOptions::batch_size`
+//
+
+#[test]
+fn archiving_small_files_inserts_them_in_batches() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    for i in 0..5 {
+        fs::write(temp_dir.path().join(format!("file{i}")), format!("{i}"))?;
+    }
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().children(true).batch_size(2);
+        archive.archive_with(temp_dir.path(), "", &opts)?;
+
+        expect!(archive.inline_stats().files()).to(equal(5));
+        expect!(archive.inline_stats().batches()).to(equal(3));
+
+        let mut contents = String::new();
+        archive
+            .open("file3")?
+            .reader()?
+            .read_to_string(&mut contents)?;
+
+        expect!(contents).to(equal("3"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_files_larger_than_inline_threshold_does_not_inline_them() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("small"), "a")?;
+    fs::write(temp_dir.path().join("large"), "a".repeat(16))?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().children(true).inline_threshold(8);
+        archive.archive_with(temp_dir.path(), "", &opts)?;
+
+        expect!(archive.inline_stats().files()).to(equal(1));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_with_zero_inline_threshold_does_not_inline_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file"), "")?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().children(true).inline_threshold(0);
+        archive.archive_with(temp_dir.path(), "", &opts)?;
+
+        expect!(archive.inline_stats().files()).to(equal(0));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn inlined_files_are_the_same_as_written_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::create_dir(temp_dir.path().join("dir"))?;
+    fs::write(temp_dir.path().join("dir/empty"), "")?;
+    fs::write(temp_dir.path().join("dir/text"), "a".repeat(1024))?;
+    fs::write(temp_dir.path().join("file"), "contents")?;
+
+    connection()?.exec(|archive| {
+        let inline_opts = ArchiveOptions::new();
+        archive.archive_with(temp_dir.path(), "inline", &inline_opts)?;
+
+        let written_opts = ArchiveOptions::new().inline_threshold(0);
+        archive.archive_with(temp_dir.path(), "written", &written_opts)?;
+
+        expect!(archive.inline_stats().files()).to(equal(3));
+
+        for path in ["dir/empty", "dir/text", "file"] {
+            let mut inline_file = archive.open(Path::new("inline").join(path))?;
+            let mut written_file = archive.open(Path::new("written").join(path))?;
+
+            expect!(inline_file.metadata()?).to(equal(written_file.metadata()?));
+            expect!(inline_file.is_compressed()?).to(equal(written_file.is_compressed()?));
+
+            let mut inline_contents = Vec::new();
+            inline_file.reader()?.read_to_end(&mut inline_contents)?;
+
+            let mut written_contents = Vec::new();
+            written_file.reader()?.read_to_end(&mut written_contents)?;
+
+            expect!(inline_contents).to(equal(written_contents));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_small_file_over_existing_file_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file"), "contents")?;
+
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        expect!(archive.archive(temp_dir.path().join("file"), "file"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileAlreadyExists { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_small_files_mapped_to_the_same_path_replaces_them() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("a"), "a")?;
+    fs::write(temp_dir.path().join("b"), "b")?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new()
+            .children(true)
+            .overwrite(OverwritePolicy::Replace)
+            .map_path(|_| Some(PathBuf::from("file")));
+
+        archive.archive_with(temp_dir.path(), "", &opts)?;
+
+        let mut contents = String::new();
+        archive
+            .open("file")?
+            .reader()?
+            .read_to_string(&mut contents)?;
+
+        expect!(["a", "b"].contains(&contents.as_str())).to(be_true());
+        expect!(archive.list()?.count()).to(equal(1));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_with_zero_batch_size_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().batch_size(0);
+
+        expect!(archive.archive_with(temp_dir.path(), "dir", &opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `ArchiveOptions::validate`
+//
+
+#[test]
+fn default_archive_options_are_valid() {
+    expect!(ArchiveOptions::new().validate()).to(be_ok());
+}
+
+#[test]
+fn validating_archive_options_returns_every_conflict() {
+    let opts = ArchiveOptions::new().prefix("/backups/../other");
+
+    expect!(opts.validate())
+        .to(be_err())
+        .map(|conflicts| conflicts.len())
+        .to(equal(2));
+}
+
+#[test]
+fn archiving_with_absolute_prefix_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file"), "contents")?;
+
+    connection()?.exec(|archive| {
+        let prefix = if cfg!(windows) {
+            r"C:\prefix"
+        } else {
+            "/prefix"
+        };
+        let opts = ArchiveOptions::new().prefix(prefix);
+
+        expect!(archive.archive_with(temp_dir.path(), "dir", &opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        expect!(archive.list()?.count()).to(equal(0));
+
+        Ok(())
+    })
+}
+
+//
+// `Connection::archive_resumable`
+//
+
+#[test]
+fn archiving_resumably_picks_up_where_it_left_off() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::create_dir(temp_dir.path().join("dir"))?;
+    fs::write(temp_dir.path().join("dir/file1"), "contents")?;
+    fs::write(temp_dir.path().join("dir/file2"), "contents")?;
+    fs::write(temp_dir.path().join("file3"), "contents")?;
+
+    let mut conn = connection()?;
+
+    // A file in the way makes the first run fail partway through.
+    conn.exec(|archive| archive.open("file3")?.create_file())?;
+
+    let opts = ArchiveOptions::new().children(true).deterministic(true);
+
+    expect!(conn.archive_resumable(temp_dir.path(), "", &opts, 1))
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::FileAlreadyExists { .. })));
+
+    conn.exec(|archive| {
+        expect!(archive.open("dir/file2")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        archive.open("file3")?.delete()
+    })?;
+
+    expect!(conn.archive_resumable(temp_dir.path(), "", &opts, 1)).to(be_ok());
+
+    conn.exec(|archive| {
+        for path in ["dir", "dir/file1", "dir/file2", "file3"] {
+            expect!(archive.open(path)?.exists())
+                .to(be_ok())
+                .to(be_true());
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_resumably_forgets_job_once_done() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file"), "contents")?;
+
+    let mut conn = connection()?;
+    let opts = ArchiveOptions::new().children(true);
+
+    expect!(conn.archive_resumable(temp_dir.path(), "", &opts, 1)).to(be_ok());
+
+    // If the job were still recorded, this would skip the file instead of failing.
+    expect!(conn.archive_resumable(temp_dir.path(), "", &opts, 1))
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::FileAlreadyExists { .. })));
+
+    Ok(())
+}
+
+#[test]
+fn archiving_resumably_with_zero_files_per_commit_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    expect!(connection()?.archive_resumable(temp_dir.path(), "dir", &ArchiveOptions::new(), 0))
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+    Ok(())
+}
+
+//
+// `ArchiveOptions::resumable`
+//
+
+#[test]
+fn archiving_resumably_in_one_transaction_skips_archived_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::create_dir(temp_dir.path().join("dir"))?;
+    fs::write(temp_dir.path().join("dir/file1"), "contents")?;
+    fs::write(temp_dir.path().join("dir/file2"), "contents")?;
+
+    connection()?.exec(|archive| {
+        archive.open("dest")?.create_dir()?;
+        archive.open("dest/file2")?.create_file()?;
+
+        let opts = ArchiveOptions::new()
+            .children(true)
+            .deterministic(true)
+            .resumable(true);
+
+        expect!(archive.archive_with(temp_dir.path().join("dir"), "dest", &opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileAlreadyExists { .. })));
+
+        archive.open("dest/file2")?.delete()?;
+
+        expect!(archive.archive_with(temp_dir.path().join("dir"), "dest", &opts)).to(be_ok());
+
+        expect!(archive.open("dest/file2")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+//
+// `ArchiveOptions::keep_going`
+//
+
+#[test]
+fn archiving_with_keep_going_archives_the_other_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file1"), "contents")?;
+    fs::write(temp_dir.path().join("file2"), "contents")?;
+    fs::write(temp_dir.path().join("file3"), "contents")?;
+
+    connection()?.exec(|archive| {
+        archive.open("dest")?.create_dir()?;
+        archive.open("dest/file2")?.create_file()?;
+
+        let opts = ArchiveOptions::new().children(true).keep_going(true);
+
+        let errors = match archive.archive_with(temp_dir.path(), "dest", &opts) {
+            Err(Error::Multiple { errors }) => errors,
+            result => panic!("expected multiple errors, got {result:?}"),
+        };
+
+        let mut errors = errors.into_iter().collect::<Vec<_>>();
+
+        expect!(errors.len()).to(equal(1));
+
+        let (path, err) = errors.remove(0);
+
+        expect!(path).to(equal(temp_dir.path().join("file2")));
+        expect!(err).to(match_pattern(pattern!(Error::FileAlreadyExists { .. })));
+
+        expect!(archive.open("dest/file1")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        expect!(archive.open("dest/file3")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(unix)]
+fn archiving_with_keep_going_skips_symlink_loops() -> sqlarfs::Result<()> {
+    use std::os::unix::fs::symlink;
+
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file"), "contents")?;
+    symlink(temp_dir.path(), temp_dir.path().join("loop"))?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().follow_symlinks(true).keep_going(true);
+
+        expect!(archive.archive_with(temp_dir.path(), "dir", &opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::Multiple { .. })));
+
+        expect!(archive.open("dir/file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        expect!(archive.open("dir/loop")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_with_keep_going_and_no_failures_succeeds() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file"), "contents")?;
+
+    connection()?.exec(|archive| {
+        let opts = ArchiveOptions::new().keep_going(true);
+
+        expect!(archive.archive_with(temp_dir.path(), "dir", &opts)).to(be_ok());
+
+        expect!(archive.open("dir/file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
@@ -0,0 +1,262 @@
+//! Tests for indexing many archives in a catalog.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use sqlarfs::catalog::Catalog;
+use sqlarfs::{Connection, Digest, Error, FileType};
+use xpct::{be_empty, be_err, be_false, be_ok, be_true, equal, expect, match_pattern, pattern};
+
+// Create an archive at `path` containing a file at each of the given paths, with the given
+// contents.
+fn create_archive(path: &Path, files: &[(&str, &str)]) -> sqlarfs::Result<()> {
+    Connection::create(path)?.exec(|archive| {
+        for (name, contents) in files {
+            let mut file = archive.open(name)?;
+            file.create_file()?;
+            file.write_str(contents)?;
+        }
+
+        Ok(())
+    })
+}
+
+//
+// `Catalog::add`
+//
+
+#[test]
+fn adding_nonexistent_archive_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let mut catalog = Catalog::open_in_memory()?;
+
+    expect!(catalog.add(temp_dir.path().join("nonexistent.sqlar")))
+        .to(be_err())
+        .to(equal(Error::CannotOpen));
+
+    expect!(catalog.archives()).to(be_ok()).to(be_empty());
+
+    Ok(())
+}
+
+#[test]
+fn adding_archive_records_its_entries() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("archive.sqlar");
+
+    Connection::create_new(&archive_path)?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let mut file = archive.open("dir/file")?;
+        file.create_file()?;
+        file.write_str("contents")
+    })?;
+
+    let mut catalog = Catalog::open_in_memory()?;
+    catalog.add(&archive_path)?;
+
+    let dirs = catalog.find_path("dir")?;
+    expect!(dirs.len()).to(equal(1));
+    expect!(dirs[0].kind()).to(equal(FileType::Dir));
+    expect!(dirs[0].size()).to(equal(None));
+    expect!(dirs[0].digest()).to(equal(None));
+
+    let files = catalog.find_path("dir/file")?;
+    expect!(files.len()).to(equal(1));
+    expect!(files[0].archive()).to(equal(archive_path.as_path()));
+    expect!(files[0].path()).to(equal(Path::new("dir/file")));
+    expect!(files[0].kind()).to(equal(FileType::File));
+    expect!(files[0].size()).to(equal(Some(8)));
+    expect!(files[0].digest()).to(equal(Some(Digest::from_reader("contents".as_bytes())?)));
+
+    Ok(())
+}
+
+#[test]
+fn adding_archive_again_replaces_its_entries() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("archive.sqlar");
+
+    create_archive(&archive_path, &[("old", "contents")])?;
+
+    let mut catalog = Catalog::open_in_memory()?;
+    catalog.add(&archive_path)?;
+
+    Connection::open(&archive_path)?.exec(|archive| {
+        archive.open("old")?.delete()?;
+        archive.open("new")?.create_file()
+    })?;
+
+    catalog.add(&archive_path)?;
+
+    expect!(catalog.find_path("old")).to(be_ok()).to(be_empty());
+    expect!(catalog.find_path("new").map(|entries| entries.len()))
+        .to(be_ok())
+        .to(equal(1));
+    expect!(catalog.archives())
+        .to(be_ok())
+        .to(equal(vec![archive_path]));
+
+    Ok(())
+}
+
+//
+// `Catalog::remove`
+//
+
+#[test]
+fn removing_archive_removes_its_entries() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("archive.sqlar");
+
+    create_archive(&archive_path, &[("file", "contents")])?;
+
+    let mut catalog = Catalog::open_in_memory()?;
+    catalog.add(&archive_path)?;
+
+    expect!(catalog.remove(&archive_path))
+        .to(be_ok())
+        .to(be_true());
+    expect!(catalog.contains(&archive_path))
+        .to(be_ok())
+        .to(be_false());
+    expect!(catalog.find_path("file"))
+        .to(be_ok())
+        .to(be_empty());
+
+    expect!(archive_path.exists()).to(be_true());
+
+    Ok(())
+}
+
+#[test]
+fn removing_archive_not_in_catalog_returns_false() -> sqlarfs::Result<()> {
+    let mut catalog = Catalog::open_in_memory()?;
+
+    expect!(catalog.remove("nonexistent.sqlar"))
+        .to(be_ok())
+        .to(be_false());
+
+    Ok(())
+}
+
+//
+// `Catalog::find_path`
+//
+
+#[test]
+fn finding_path_searches_every_archive() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let first_path = temp_dir.path().join("first.sqlar");
+    let second_path = temp_dir.path().join("second.sqlar");
+    let third_path = temp_dir.path().join("third.sqlar");
+
+    create_archive(&first_path, &[("file", "first")])?;
+    create_archive(&second_path, &[("other", "second")])?;
+    create_archive(&third_path, &[("file", "third")])?;
+
+    let mut catalog = Catalog::open_in_memory()?;
+    catalog.add(&third_path)?;
+    catalog.add(&first_path)?;
+    catalog.add(&second_path)?;
+
+    let archives = catalog
+        .find_path("file")?
+        .iter()
+        .map(|entry| entry.archive().to_owned())
+        .collect::<Vec<_>>();
+
+    expect!(archives).to(equal(vec![first_path, third_path]));
+
+    Ok(())
+}
+
+#[test]
+fn finding_path_normalizes_trailing_slashes() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("archive.sqlar");
+
+    Connection::create_new(&archive_path)?.exec(|archive| archive.open("dir")?.create_dir())?;
+
+    let mut catalog = Catalog::open_in_memory()?;
+    catalog.add(&archive_path)?;
+
+    expect!(catalog.find_path("dir/").map(|entries| entries.len()))
+        .to(be_ok())
+        .to(equal(1));
+
+    Ok(())
+}
+
+#[test]
+fn finding_empty_path_errors() -> sqlarfs::Result<()> {
+    let catalog = Catalog::open_in_memory()?;
+
+    expect!(catalog.find_path(""))
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+    Ok(())
+}
+
+//
+// `Catalog::find_digest`
+//
+
+#[test]
+fn finding_digest_returns_files_with_same_contents() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let first_path = temp_dir.path().join("first.sqlar");
+    let second_path = temp_dir.path().join("second.sqlar");
+
+    create_archive(&first_path, &[("a", "shared"), ("b", "unique")])?;
+    create_archive(&second_path, &[("c", "shared")])?;
+
+    let mut catalog = Catalog::open_in_memory()?;
+    catalog.add(&first_path)?;
+    catalog.add(&second_path)?;
+
+    let matches = catalog
+        .find_digest(&Digest::from_reader("shared".as_bytes())?)?
+        .iter()
+        .map(|entry| (entry.archive().to_owned(), entry.path().to_owned()))
+        .collect::<Vec<_>>();
+
+    expect!(matches).to(equal(vec![
+        (first_path, PathBuf::from("a")),
+        (second_path, PathBuf::from("c")),
+    ]));
+
+    expect!(catalog.find_digest(&Digest::from_reader("missing".as_bytes())?))
+        .to(be_ok())
+        .to(be_empty());
+
+    Ok(())
+}
+
+//
+// `Catalog::open`
+//
+
+#[test]
+fn catalog_persists_when_reopened() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let archive_path = temp_dir.path().join("archive.sqlar");
+    let catalog_path = temp_dir.path().join("catalog.db");
+
+    create_archive(&archive_path, &[("file", "contents")])?;
+
+    Catalog::open(&catalog_path)?.add(&archive_path)?;
+
+    let catalog = Catalog::open(&catalog_path)?;
+
+    expect!(catalog.contains(&archive_path))
+        .to(be_ok())
+        .to(be_true());
+    expect!(catalog.find_path("file").map(|entries| entries.len()))
+        .to(be_ok())
+        .to(equal(1));
+
+    Ok(())
+}
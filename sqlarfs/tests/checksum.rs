@@ -0,0 +1,235 @@
+//! Tests for storing and verifying checksums of the files in an archive.
+
+mod common;
+
+use std::path::PathBuf;
+
+use common::connection;
+use sqlarfs::{Connection, DigestAlgorithm, Error};
+use xpct::{be_empty, be_err, be_ok, equal, expect, match_pattern, pattern};
+
+fn create_file(archive: &mut sqlarfs::Archive, path: &str, contents: &str) -> sqlarfs::Result<()> {
+    let mut file = archive.open(path)?;
+    file.create_file()?;
+    file.write_str(contents)
+}
+
+//
+// `Archive::store_checksums`
+//
+
+#[test]
+fn store_checksums_returns_number_of_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        create_file(archive, "dir/a", "a")?;
+        create_file(archive, "dir/b", "b")?;
+        archive.open("symlink")?.create_symlink("dir/a")?;
+
+        expect!(archive.store_checksums(DigestAlgorithm::Sha256))
+            .to(be_ok())
+            .to(equal(2));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn store_checksums_on_empty_archive() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(archive.store_checksums(DigestAlgorithm::Sha256))
+            .to(be_ok())
+            .to(equal(0));
+
+        expect!(archive.verify_checksums())
+            .to(be_ok())
+            .to(be_empty());
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::verify_checksums`
+//
+
+#[test]
+fn verify_checksums_without_stored_checksums_finds_nothing() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "file", "contents")?;
+
+        expect!(archive.verify_checksums())
+            .to(be_ok())
+            .to(be_empty());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn verify_checksums_of_unchanged_files_finds_nothing() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "a", "a")?;
+        create_file(archive, "b", "b")?;
+
+        archive.store_checksums(DigestAlgorithm::Sha256)?;
+
+        expect!(archive.verify_checksums())
+            .to(be_ok())
+            .to(be_empty());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn verify_checksums_finds_changed_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "a", "a")?;
+        create_file(archive, "b", "b")?;
+
+        archive.store_checksums(DigestAlgorithm::Sha256)?;
+
+        archive.open("b")?.write_str("changed")?;
+
+        expect!(archive.verify_checksums())
+            .to(be_ok())
+            .to(equal(vec![PathBuf::from("b")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn verify_checksums_skips_files_added_later() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "a", "a")?;
+
+        archive.store_checksums(DigestAlgorithm::Sha256)?;
+
+        create_file(archive, "b", "b")?;
+
+        expect!(archive.verify_checksums())
+            .to(be_ok())
+            .to(be_empty());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn checksums_are_removed_with_their_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "file", "contents")?;
+
+        archive.store_checksums(DigestAlgorithm::Sha256)?;
+
+        archive.open("file")?.delete()?;
+        create_file(archive, "file", "different contents")?;
+
+        expect!(archive.verify_checksums())
+            .to(be_ok())
+            .to(be_empty());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn checksums_follow_renamed_and_copied_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "file", "contents")?;
+
+        archive.store_checksums(DigestAlgorithm::Sha256)?;
+
+        archive.rename("file", "renamed")?;
+        archive.copy("renamed", "copied")?;
+
+        archive.open("copied")?.write_str("changed")?;
+
+        expect!(archive.verify_checksums())
+            .to(be_ok())
+            .to(equal(vec![PathBuf::from("copied")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn verify_checksums_finds_corrupt_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let db_path = temp_dir.path().join("test.sqlar");
+
+    let mut conn = Connection::create_new(&db_path)?;
+
+    conn.exec(|archive| {
+        create_file(archive, "file", "contents")?;
+        archive.store_checksums(DigestAlgorithm::Sha256)?;
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    // The size is larger than the data, so it looks compressed, but it isn't valid zlib.
+    rusqlite::Connection::open(&db_path)?.execute(
+        "UPDATE sqlar SET sz = 100, data = x'00010203' WHERE name = 'file'",
+        (),
+    )?;
+
+    conn.exec(|archive| {
+        expect!(archive.verify_checksums())
+            .to(be_ok())
+            .to(equal(vec![PathBuf::from("file")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn verify_checksums_with_unsupported_algorithm_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let db_path = temp_dir.path().join("test.sqlar");
+
+    let mut conn = Connection::create_new(&db_path)?;
+
+    conn.exec(|archive| {
+        create_file(archive, "file", "contents")?;
+        archive.store_checksums(DigestAlgorithm::Sha256)?;
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    rusqlite::Connection::open(&db_path)?
+        .execute("UPDATE sqlar_checksums SET algorithm = 'md5'", ())?;
+
+    conn.exec(|archive| {
+        expect!(archive.verify_checksums())
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::DigestNotSupported { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "blake3")]
+fn verify_checksums_with_blake3() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "a", "a")?;
+        create_file(archive, "b", "b")?;
+
+        archive.store_checksums(DigestAlgorithm::Blake3)?;
+
+        expect!(archive.verify_checksums())
+            .to(be_ok())
+            .to(be_empty());
+
+        archive.open("a")?.write_str("changed")?;
+
+        expect!(archive.verify_checksums())
+            .to(be_ok())
+            .to(equal(vec![PathBuf::from("a")]));
+
+        Ok(())
+    })
+}
@@ -2,8 +2,10 @@
 
 mod matchers;
 
+use std::env;
 use std::io;
 use std::path::Path;
+use std::process::Command;
 use std::sync::mpsc;
 use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -28,7 +30,7 @@ pub fn truncate_mtime(time: SystemTime) -> SystemTime {
 }
 
 pub fn random_bytes(len: usize) -> Vec<u8> {
-    let mut buf = Vec::with_capacity(len);
+    let mut buf = vec![0u8; len];
     let mut rng = SmallRng::from_entropy();
     rng.fill_bytes(&mut buf);
     buf
@@ -71,6 +73,39 @@ where
     handle.join().unwrap()
 }
 
+// Set in the environment of the child processes started by `rerun_with_env`.
+const CHILD_ENV: &str = "SQLARFS_TEST_CHILD";
+
+// Run the test named `test` again in a child process with the environment variables in `vars`
+// set, returning whether it passed. In the child process, this returns `None` and the caller
+// should run the test.
+//
+// The test only counts as passed if the child actually ran it, so a wrong name is a failure rather
+// than a test that silently checks nothing.
+//
+// This is for tests that depend on environment variables. Setting them with `env::set_var` would
+// change the environment of every other test running in parallel in this process.
+pub fn rerun_with_env(test: &str, vars: &[(&str, &str)]) -> io::Result<Option<bool>> {
+    if env::var_os(CHILD_ENV).is_some() {
+        return Ok(None);
+    }
+
+    let output = Command::new(env::current_exe()?)
+        .args([test, "--exact", "--test-threads=1"])
+        .env(CHILD_ENV, "1")
+        .envs(vars.iter().copied())
+        .output()?;
+
+    // Show the output of the child process if this test fails.
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+
+    let ran_test = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .any(|line| line.starts_with("test result: ok. 1 passed;"));
+
+    Ok(Some(output.status.success() && ran_test))
+}
+
 // TODO: Use `eyre::Result` instead of `sqlarfs::Result` for all tests, making this unnecessary.
 pub fn into_sqlarfs_error<E>(_: E) -> sqlarfs::Error
 where
@@ -0,0 +1,150 @@
+//! Tests for reporting how well files in an archive compress.
+
+mod common;
+
+use common::{compressible_bytes, connection, incompressible_bytes};
+use sqlarfs::{Compression, Error};
+use xpct::{be_err, be_lt, be_ok, be_true, equal, expect, match_pattern, pattern};
+
+//
+// `Archive::compression_report`
+//
+
+#[test]
+fn report_on_empty_archive_is_empty() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let report = archive.compression_report("")?;
+
+        expect!(report.extensions().len()).to(equal(0));
+        expect!(report.total().files()).to(equal(0));
+        expect!(report.total().ratio() == 1.0).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn report_groups_files_by_extension() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        for (path, contents) in [
+            ("a.txt", incompressible_bytes()),
+            ("b.TXT", incompressible_bytes()),
+            ("c.bin", incompressible_bytes()),
+            ("README", vec![0u8; 10]),
+        ] {
+            let mut file = archive.open(path)?;
+            file.create_file()?;
+            file.set_compression(Compression::None);
+            file.write_bytes(&contents)?;
+        }
+
+        let report = archive.compression_report("")?;
+
+        let extensions = report
+            .extensions()
+            .iter()
+            .map(|entry| (entry.extension(), entry.stats().files()))
+            .collect::<Vec<_>>();
+
+        let file_size = incompressible_bytes().len() as u64;
+
+        expect!(extensions).to(equal(vec![(Some("txt"), 2), (Some("bin"), 1), (None, 1)]));
+
+        expect!(report.total().files()).to(equal(4));
+        expect!(report.total().original_size()).to(equal(file_size * 3 + 10));
+        expect!(report.total().stored_size()).to(equal(file_size * 3 + 10));
+        expect!(report.total().compressed_files()).to(equal(0));
+        expect!(report.total().savings()).to(equal(0));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn report_includes_compression_savings() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("compressed.txt")?;
+        file.create_file()?;
+        file.set_compression(Compression::BEST);
+        file.write_bytes(&compressible_bytes())?;
+
+        let mut file = archive.open("uncompressed.txt")?;
+        file.create_file()?;
+        file.set_compression(Compression::None);
+        file.write_bytes(&compressible_bytes())?;
+
+        let report = archive.compression_report("")?;
+        let stats = report.extensions()[0].stats();
+
+        let original_size = compressible_bytes().len() as u64 * 2;
+
+        expect!(stats.files()).to(equal(2));
+        expect!(stats.compressed_files()).to(equal(1));
+        expect!(stats.original_size()).to(equal(original_size));
+        expect!(stats.stored_size()).to(be_lt(original_size));
+        expect!(stats.savings()).to(equal(original_size - stats.stored_size()));
+        expect!(stats.ratio()).to(be_lt(1.0));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn report_only_includes_files_under_path() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/inner.txt")?.create_file()?;
+        archive.open("dir/nested")?.create_dir()?;
+        archive.open("dir/nested/inner.md")?.create_file()?;
+        archive.open("dir-sibling.txt")?.create_file()?;
+        archive.open("outer.txt")?.create_file()?;
+
+        let report = archive.compression_report("dir")?;
+
+        expect!(report.total().files()).to(equal(2));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn report_on_single_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("file.txt")?.create_file()?;
+        archive.open("other.txt")?.create_file()?;
+
+        let report = archive.compression_report("file.txt")?;
+
+        expect!(report.total().files()).to(equal(1));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn report_ignores_dirs_and_symlinks() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("symlink")?.create_symlink("target")?;
+
+        let report = archive.compression_report("")?;
+
+        expect!(report.total().files()).to(equal(0));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn report_errors_when_path_does_not_exist() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(archive.compression_report("nonexistent"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileNotFound { .. })));
+
+        expect!(archive.compression_report("")).to(be_ok());
+
+        Ok(())
+    })
+}
@@ -0,0 +1,633 @@
+//! Tests for copying files and their contents within an archive and between archives.
+
+mod common;
+
+use std::io::Read;
+use std::path::PathBuf;
+
+use common::connection;
+use sqlarfs::{Compression, Error, FileMode};
+use xpct::{
+    be_err, be_false, be_ok, be_some, be_true, consist_of, equal, expect, match_pattern, pattern,
+};
+
+fn read_contents(archive: &sqlarfs::Archive, path: &str) -> sqlarfs::Result<String> {
+    let mut contents = String::new();
+    archive
+        .open(path)?
+        .reader()?
+        .read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+//
+// `Archive::copy_data`
+//
+
+#[test]
+fn copy_data_copies_contents() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut source = archive.open("source")?;
+        source.create_file()?;
+        source.write_str("contents")?;
+        drop(source);
+
+        archive.open("dest")?.create_file()?;
+
+        expect!(archive.copy_data("source", "dest")).to(be_ok());
+
+        expect!(read_contents(archive, "dest"))
+            .to(be_ok())
+            .to(equal("contents"));
+        expect!(read_contents(archive, "source"))
+            .to(be_ok())
+            .to(equal("contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn copy_data_preserves_compression() -> sqlarfs::Result<()> {
+    let contents = "a".repeat(1024 * 1024);
+
+    connection()?.exec(|archive| {
+        let mut source = archive.open("source")?;
+        source.create_file()?;
+        source.set_compression(Compression::BEST);
+        source.write_str(&contents)?;
+        drop(source);
+
+        archive.open("dest")?.create_file()?;
+        archive.copy_data("source", "dest")?;
+
+        expect!(archive.open("dest")?.is_compressed())
+            .to(be_ok())
+            .to(be_true());
+        expect!(read_contents(archive, "dest"))
+            .to(be_ok())
+            .to(equal(contents.as_str()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copy_data_copies_empty_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("source")?.create_file()?;
+
+        let mut dest = archive.open("dest")?;
+        dest.create_file()?;
+        dest.write_str("old contents")?;
+        drop(dest);
+
+        archive.copy_data("source", "dest")?;
+
+        expect!(read_contents(archive, "dest"))
+            .to(be_ok())
+            .to(equal(""));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copy_data_leaves_dest_metadata_alone() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("source")?.create_file()?;
+
+        let mut dest = archive.open("dest")?;
+        dest.create_file()?;
+        dest.set_mode(Some(FileMode::OWNER_R))?;
+        drop(dest);
+
+        archive.copy_data("source", "dest")?;
+
+        let metadata = archive.open("dest")?.metadata()?;
+
+        expect!(metadata.mode()).to(equal(Some(FileMode::OWNER_R)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copy_data_onto_itself_leaves_contents_alone() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("contents")?;
+        drop(file);
+
+        expect!(archive.copy_data("file", "file/")).to(be_ok());
+
+        expect!(read_contents(archive, "file"))
+            .to(be_ok())
+            .to(equal("contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copy_data_when_dest_does_not_exist_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("source")?.create_file()?;
+
+        expect!(archive.copy_data("source", "dest"))
+            .to(be_err())
+            .to(equal(Error::FileNotFound {
+                path: "dest".into(),
+            }));
+
+        expect!(archive.open("dest")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copy_data_when_source_does_not_exist_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dest")?.create_file()?;
+
+        expect!(archive.copy_data("source", "dest"))
+            .to(be_err())
+            .to(equal(Error::FileNotFound {
+                path: "source".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copy_data_from_directory_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("source")?.create_dir()?;
+        archive.open("dest")?.create_file()?;
+
+        expect!(archive.copy_data("source", "dest"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::NotARegularFile { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copy_data_to_symlink_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("source")?.create_file()?;
+        archive.open("dest")?.create_symlink("source")?;
+
+        expect!(archive.copy_data("source", "dest"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::NotARegularFile { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::copy_data_with`
+//
+
+#[test]
+#[cfg(feature = "deflate")]
+fn copy_data_with_no_compression_decompresses_contents() -> sqlarfs::Result<()> {
+    let contents = "a".repeat(1024 * 1024);
+
+    connection()?.exec(|archive| {
+        let mut source = archive.open("source")?;
+        source.create_file()?;
+        source.write_str(&contents)?;
+        drop(source);
+
+        archive.open("dest")?.create_file()?;
+        archive.copy_data_with("source", "dest", Compression::None)?;
+
+        expect!(archive.open("dest")?.is_compressed())
+            .to(be_ok())
+            .to(be_false());
+        expect!(read_contents(archive, "dest"))
+            .to(be_ok())
+            .to(equal(contents.as_str()));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn copy_data_with_onto_itself_changes_compression() -> sqlarfs::Result<()> {
+    let contents = "a".repeat(1024 * 1024);
+
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_compression(Compression::None);
+        file.write_str(&contents)?;
+        drop(file);
+
+        archive.copy_data_with("file", "file", Compression::BEST)?;
+
+        expect!(archive.open("file")?.is_compressed())
+            .to(be_ok())
+            .to(be_true());
+        expect!(read_contents(archive, "file"))
+            .to(be_ok())
+            .to(equal(contents.as_str()));
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::copy`
+//
+
+#[test]
+fn copy_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut source = archive.open("source")?;
+        source.create_file()?;
+        source.set_mode(Some(FileMode::OWNER_R))?;
+        source.write_str("contents")?;
+        drop(source);
+
+        expect!(archive.copy("source", "dest")).to(be_ok());
+
+        expect!(read_contents(archive, "dest"))
+            .to(be_ok())
+            .to(equal("contents"));
+        expect!(read_contents(archive, "source"))
+            .to(be_ok())
+            .to(equal("contents"));
+        expect!(archive.open("dest")?.metadata()?.mode()).to(equal(Some(FileMode::OWNER_R)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copy_dir_copies_descendants() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("a")?.create_dir()?;
+        archive.open("a/dir")?.create_dir()?;
+        archive.open("a/dir/file")?.create_file()?;
+        archive.open("ab")?.create_file()?;
+
+        expect!(archive.copy("a", "b")).to(be_ok());
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[
+                PathBuf::from("a"),
+                PathBuf::from("a/dir"),
+                PathBuf::from("a/dir/file"),
+                PathBuf::from("ab"),
+                PathBuf::from("b"),
+                PathBuf::from("b/dir"),
+                PathBuf::from("b/dir/file"),
+            ]));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn copy_preserves_compression() -> sqlarfs::Result<()> {
+    let contents = "a".repeat(1024 * 1024);
+
+    connection()?.exec(|archive| {
+        let mut source = archive.open("source")?;
+        source.create_file()?;
+        source.set_compression(Compression::BEST);
+        source.write_str(&contents)?;
+        drop(source);
+
+        archive.copy("source", "dest")?;
+
+        expect!(archive.open("dest")?.is_compressed())
+            .to(be_ok())
+            .to(be_true());
+        expect!(read_contents(archive, "dest"))
+            .to(be_ok())
+            .to(equal(contents));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copy_copies_user_metadata() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("source")?.create_dir()?;
+
+        let mut file = archive.open("source/file")?;
+        file.create_file()?;
+        file.set_meta("key", "value")?;
+        drop(file);
+
+        archive.copy("source", "dest")?;
+
+        expect!(archive.open("dest/file")?.meta("key"))
+            .to(be_ok())
+            .to(be_some())
+            .to(equal("value"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copy_does_not_copy_pins() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("source")?;
+        file.create_file()?;
+        file.pin()?;
+        drop(file);
+
+        archive.copy("source", "dest")?;
+
+        expect!(archive.open("dest")?.is_pinned())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copy_when_source_does_not_exist_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(archive.copy("source", "dest"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileNotFound { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copy_when_dest_exists_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("source")?.create_file()?;
+        archive.open("dest")?.create_file()?;
+
+        expect!(archive.copy("source", "dest"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileAlreadyExists { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copy_when_dest_has_no_parent_dir_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("source")?.create_file()?;
+
+        expect!(archive.copy("source", "dir/dest"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::NoParentDirectory { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copy_dir_into_itself_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        expect!(archive.copy("dir", "dir/child"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::copy_to`
+//
+
+#[test]
+fn copy_to_copies_file_to_other_archive() -> sqlarfs::Result<()> {
+    let mut dest_conn = connection()?;
+
+    connection()?.exec(|src| {
+        let mut source = src.open("source")?;
+        source.create_file()?;
+        source.set_mode(Some(FileMode::OWNER_R))?;
+        source.write_str("contents")?;
+        drop(source);
+
+        dest_conn.exec(|dest| {
+            expect!(src.copy_to(dest, "source", "dest")).to(be_ok());
+
+            expect!(read_contents(dest, "dest"))
+                .to(be_ok())
+                .to(equal("contents"));
+            expect!(dest.open("dest")?.metadata()?.mode()).to(equal(Some(FileMode::OWNER_R)));
+
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn copy_to_copies_descendants() -> sqlarfs::Result<()> {
+    let mut dest_conn = connection()?;
+
+    connection()?.exec(|src| {
+        src.open("a")?.create_dir()?;
+        src.open("a/dir")?.create_dir()?;
+        src.open("a/dir/file")?.create_file()?;
+        src.open("a/symlink")?.create_symlink("dir/file")?;
+        src.open("ab")?.create_file()?;
+
+        dest_conn.exec(|dest| {
+            dest.open("parent")?.create_dir()?;
+
+            expect!(src.copy_to(dest, "a", "parent/b")).to(be_ok());
+
+            expect!(dest.list())
+                .to(be_ok())
+                .iter_try_map(|entry| Ok(entry?.into_path()))
+                .to(consist_of(&[
+                    PathBuf::from("parent"),
+                    PathBuf::from("parent/b"),
+                    PathBuf::from("parent/b/dir"),
+                    PathBuf::from("parent/b/dir/file"),
+                    PathBuf::from("parent/b/symlink"),
+                ]));
+
+            expect!(dest.open("parent/b/symlink")?.metadata())
+                .to(be_ok())
+                .to(equal(src.open("a/symlink")?.metadata()?));
+
+            Ok(())
+        })
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn copy_to_preserves_compression() -> sqlarfs::Result<()> {
+    let contents = "a".repeat(1024 * 1024);
+    let mut dest_conn = connection()?;
+
+    connection()?.exec(|src| {
+        let mut source = src.open("source")?;
+        source.create_file()?;
+        source.set_compression(Compression::BEST);
+        source.write_str(&contents)?;
+        drop(source);
+
+        dest_conn.exec(|dest| {
+            src.copy_to(dest, "source", "dest")?;
+
+            expect!(dest.open("dest")?.is_compressed())
+                .to(be_ok())
+                .to(be_true());
+            expect!(read_contents(dest, "dest"))
+                .to(be_ok())
+                .to(equal(contents));
+
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn copy_to_copies_user_metadata() -> sqlarfs::Result<()> {
+    let mut dest_conn = connection()?;
+
+    connection()?.exec(|src| {
+        let mut file = src.open("source")?;
+        file.create_file()?;
+        file.set_meta("key", "value")?;
+        drop(file);
+
+        dest_conn.exec(|dest| {
+            src.copy_to(dest, "source", "dest")?;
+
+            expect!(dest.open("dest")?.meta("key"))
+                .to(be_ok())
+                .to(be_some())
+                .to(equal("value"));
+
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn copy_to_copies_deduplicated_files() -> sqlarfs::Result<()> {
+    let mut dest_conn = connection()?;
+
+    connection()?.exec(|src| {
+        for path in ["a", "b"] {
+            let mut file = src.open(path)?;
+            file.create_file()?;
+            file.write_str("contents")?;
+        }
+
+        src.deduplicate()?;
+
+        dest_conn.exec(|dest| {
+            src.copy_to(dest, "b", "b")?;
+
+            expect!(read_contents(dest, "b"))
+                .to(be_ok())
+                .to(equal("contents"));
+
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn copy_to_when_source_does_not_exist_errors() -> sqlarfs::Result<()> {
+    let mut dest_conn = connection()?;
+
+    connection()?.exec(|src| {
+        dest_conn.exec(|dest| {
+            expect!(src.copy_to(dest, "source", "dest"))
+                .to(be_err())
+                .to(match_pattern(pattern!(Error::FileNotFound { .. })));
+
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn copy_to_when_dest_exists_errors() -> sqlarfs::Result<()> {
+    let mut dest_conn = connection()?;
+
+    connection()?.exec(|src| {
+        src.open("file")?.create_file()?;
+
+        dest_conn.exec(|dest| {
+            dest.open("file")?.create_file()?;
+
+            expect!(src.copy_to(dest, "file", "file"))
+                .to(be_err())
+                .to(match_pattern(pattern!(Error::FileAlreadyExists { .. })));
+
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn copy_to_when_dest_has_no_parent_dir_errors() -> sqlarfs::Result<()> {
+    let mut dest_conn = connection()?;
+
+    connection()?.exec(|src| {
+        src.open("source")?.create_file()?;
+
+        dest_conn.exec(|dest| {
+            expect!(src.copy_to(dest, "source", "dir/dest"))
+                .to(be_err())
+                .to(match_pattern(pattern!(Error::NoParentDirectory { .. })));
+
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn copy_to_is_atomic() -> sqlarfs::Result<()> {
+    let mut dest_conn = connection()?;
+
+    connection()?.exec(|src| {
+        src.open("dir")?.create_dir()?;
+        src.open("dir/a")?.create_file()?;
+        src.open("dir/long-name")?.create_file()?;
+
+        dest_conn.exec(|dest| {
+            dest.set_max_name_len(8);
+
+            expect!(src.copy_to(dest, "dir", "dir"))
+                .to(be_err())
+                .to(match_pattern(pattern!(Error::NameTooLong { .. })));
+
+            expect!(dest.open("dir")?.exists())
+                .to(be_ok())
+                .to(be_false());
+
+            Ok(())
+        })
+    })
+}
@@ -0,0 +1,372 @@
+//! Tests for storing the contents of identical files only once.
+
+mod common;
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use common::connection;
+use sqlarfs::{Archive, ArchiveOptions, Compression, Connection};
+#[cfg(feature = "deflate")]
+use xpct::be_true;
+use xpct::{be_false, be_ok, equal, expect};
+
+fn create_file(archive: &mut Archive, path: &str, contents: &str) -> sqlarfs::Result<()> {
+    let mut file = archive.open(path)?;
+    file.create_file()?;
+    file.set_compression(Compression::None);
+    file.write_str(contents)
+}
+
+fn read_file(archive: &mut Archive, path: &str) -> sqlarfs::Result<String> {
+    let mut contents = String::new();
+    archive
+        .open(path)?
+        .reader()?
+        .read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+// The number of distinct file contents stored in the deduplication table.
+fn num_shared_blobs(db_path: &Path) -> sqlarfs::Result<i64> {
+    Ok(rusqlite::Connection::open(db_path)?.query_row(
+        "SELECT count(*) FROM sqlar_dedup",
+        (),
+        |row| row.get(0),
+    )?)
+}
+
+// The contents of a file as they're stored in the `sqlar` table, which other tools read.
+fn stored_data(db_path: &Path, path: &str) -> sqlarfs::Result<Vec<u8>> {
+    Ok(rusqlite::Connection::open(db_path)?.query_row(
+        "SELECT data FROM sqlar WHERE name = ?1",
+        (path,),
+        |row| row.get(0),
+    )?)
+}
+
+//
+// `Archive::deduplicate`
+//
+
+#[test]
+fn deduplicate_returns_number_of_duplicate_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "a", "contents")?;
+        create_file(archive, "b", "contents")?;
+        create_file(archive, "c", "contents")?;
+        create_file(archive, "d", "different contents")?;
+
+        expect!(archive.deduplicate()).to(be_ok()).to(equal(2));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn deduplicate_twice_finds_nothing_new() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "a", "contents")?;
+        create_file(archive, "b", "contents")?;
+
+        archive.deduplicate()?;
+
+        expect!(archive.deduplicate()).to(be_ok()).to(equal(0));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn deduplicate_skips_empty_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "a", "")?;
+        create_file(archive, "b", "")?;
+
+        expect!(archive.deduplicate()).to(be_ok()).to(equal(0));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn deduplicated_files_can_be_read() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "a", "contents")?;
+        create_file(archive, "b", "contents")?;
+
+        archive.deduplicate()?;
+
+        expect!(read_file(archive, "a"))
+            .to(be_ok())
+            .to(equal(String::from("contents")));
+        expect!(read_file(archive, "b"))
+            .to(be_ok())
+            .to(equal(String::from("contents")));
+
+        expect!(archive.open("a")?.is_compressed())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn deduplicated_compressed_files_can_be_read() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        for path in ["a", "b"] {
+            let mut file = archive.open(path)?;
+            file.create_file()?;
+            file.set_compression(Compression::FAST);
+            file.write_str("a".repeat(1024))?;
+        }
+
+        expect!(archive.deduplicate()).to(be_ok()).to(equal(1));
+
+        expect!(archive.open("b")?.is_compressed())
+            .to(be_ok())
+            .to(be_true());
+        expect!(read_file(archive, "b"))
+            .to(be_ok())
+            .to(equal("a".repeat(1024)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn writing_to_deduplicated_file_does_not_change_other_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "a", "contents")?;
+        create_file(archive, "b", "contents")?;
+
+        archive.deduplicate()?;
+
+        archive.open("a")?.write_str("new contents")?;
+
+        expect!(read_file(archive, "a"))
+            .to(be_ok())
+            .to(equal(String::from("new contents")));
+        expect!(read_file(archive, "b"))
+            .to(be_ok())
+            .to(equal(String::from("contents")));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn deduplicated_files_can_be_renamed_and_copied() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "a", "contents")?;
+        create_file(archive, "b", "contents")?;
+
+        archive.deduplicate()?;
+
+        archive.rename("a", "renamed")?;
+        archive.copy("b", "copied")?;
+
+        for path in ["renamed", "b", "copied"] {
+            expect!(read_file(archive, path))
+                .to(be_ok())
+                .to(equal(String::from("contents")));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn deduplicated_contents_are_stored_once() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let db_path = temp_dir.path().join("test.sqlar");
+
+    Connection::create_new(&db_path)?.exec(|archive| {
+        create_file(archive, "a", "contents")?;
+        create_file(archive, "b", "contents")?;
+        create_file(archive, "c", "different contents")?;
+
+        archive.deduplicate()
+    })?;
+
+    expect!(num_shared_blobs(&db_path)).to(be_ok()).to(equal(1));
+
+    Ok(())
+}
+
+#[test]
+fn deduplicate_leaves_unique_files_alone() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let db_path = temp_dir.path().join("test.sqlar");
+
+    Connection::create_new(&db_path)?.exec(|archive| {
+        create_file(archive, "a", "contents")?;
+        create_file(archive, "b", "contents")?;
+        create_file(archive, "c", "different contents")?;
+
+        archive.deduplicate()
+    })?;
+
+    expect!(stored_data(&db_path, "c"))
+        .to(be_ok())
+        .to(equal(b"different contents".to_vec()));
+    expect!(stored_data(&db_path, "a"))
+        .to(be_ok())
+        .to(equal(Vec::<u8>::new()));
+
+    Ok(())
+}
+
+#[test]
+fn deduplicate_without_duplicates_changes_nothing() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let db_path = temp_dir.path().join("test.sqlar");
+
+    Connection::create_new(&db_path)?.exec(|archive| {
+        create_file(archive, "a", "contents")?;
+        create_file(archive, "b", "different contents")?;
+
+        expect!(archive.deduplicate()).to(be_ok()).to(equal(0));
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    expect!(stored_data(&db_path, "a"))
+        .to(be_ok())
+        .to(equal(b"contents".to_vec()));
+    expect!(stored_data(&db_path, "b"))
+        .to(be_ok())
+        .to(equal(b"different contents".to_vec()));
+
+    Ok(())
+}
+
+#[test]
+fn shared_contents_are_deleted_with_their_last_file() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let db_path = temp_dir.path().join("test.sqlar");
+
+    let mut conn = Connection::create_new(&db_path)?;
+
+    conn.exec(|archive| {
+        create_file(archive, "a", "contents")?;
+        create_file(archive, "b", "contents")?;
+
+        archive.deduplicate()?;
+
+        archive.open("a")?.delete()?;
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    expect!(num_shared_blobs(&db_path)).to(be_ok()).to(equal(1));
+
+    conn.exec(|archive| archive.open("b")?.write_str("new contents"))?;
+
+    expect!(num_shared_blobs(&db_path)).to(be_ok()).to(equal(0));
+
+    Ok(())
+}
+
+//
+// `ArchiveOptions::deduplicate`
+//
+
+#[test]
+fn archiving_with_deduplicate_stores_identical_files_once() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let db_path = temp_dir.path().join("test.sqlar");
+    let src_dir = temp_dir.path().join("src");
+
+    fs::create_dir(&src_dir)?;
+    fs::write(src_dir.join("a"), "contents")?;
+    fs::write(src_dir.join("b"), "contents")?;
+    fs::write(src_dir.join("c"), "different contents")?;
+
+    let mut conn = Connection::create_new(&db_path)?;
+
+    conn.exec(|archive| {
+        let opts = ArchiveOptions::new().children(true).deduplicate(true);
+        archive.archive_with(&src_dir, "", &opts)
+    })?;
+
+    expect!(num_shared_blobs(&db_path)).to(be_ok()).to(equal(1));
+    expect!(stored_data(&db_path, "c"))
+        .to(be_ok())
+        .to(equal(b"different contents".to_vec()));
+
+    conn.exec(|archive| {
+        expect!(read_file(archive, "a"))
+            .to(be_ok())
+            .to(equal(String::from("contents")));
+        expect!(read_file(archive, "b"))
+            .to(be_ok())
+            .to(equal(String::from("contents")));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_with_deduplicate_finds_files_from_earlier_runs() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let db_path = temp_dir.path().join("test.sqlar");
+    let src_file = temp_dir.path().join("file");
+
+    fs::write(&src_file, "contents")?;
+
+    let mut conn = Connection::create_new(&db_path)?;
+    let opts = ArchiveOptions::new().deduplicate(true);
+
+    conn.exec(|archive| archive.archive_with(&src_file, "a", &opts))?;
+
+    expect!(stored_data(&db_path, "a"))
+        .to(be_ok())
+        .to(equal(b"contents".to_vec()));
+
+    conn.exec(|archive| archive.archive_with(&src_file, "b", &opts))?;
+
+    expect!(num_shared_blobs(&db_path)).to(be_ok()).to(equal(1));
+
+    conn.exec(|archive| {
+        expect!(read_file(archive, "a"))
+            .to(be_ok())
+            .to(equal(String::from("contents")));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn archiving_with_deduplicate_ignores_files_changed_since() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let db_path = temp_dir.path().join("test.sqlar");
+    let src_file = temp_dir.path().join("file");
+
+    fs::write(&src_file, "contents")?;
+
+    let mut conn = Connection::create_new(&db_path)?;
+    let opts = ArchiveOptions::new().deduplicate(true);
+
+    conn.exec(|archive| {
+        archive.archive_with(&src_file, "a", &opts)?;
+        archive.open("a")?.write_str("new contents")?;
+        archive.archive_with(&src_file, "b", &opts)
+    })?;
+
+    expect!(num_shared_blobs(&db_path)).to(be_ok()).to(equal(0));
+
+    conn.exec(|archive| {
+        expect!(read_file(archive, "a"))
+            .to(be_ok())
+            .to(equal(String::from("new contents")));
+        expect!(read_file(archive, "b"))
+            .to(be_ok())
+            .to(equal(String::from("contents")));
+
+        Ok(())
+    })
+}
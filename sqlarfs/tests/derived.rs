@@ -0,0 +1,147 @@
+//! Tests for storing blobs derived from files, like thumbnails.
+
+mod common;
+
+use sqlarfs::Error;
+use xpct::{be_err, be_false, be_ok, be_true, equal, expect, match_pattern, pattern};
+
+use common::connection;
+
+//
+// `File::set_derived`
+//
+
+#[test]
+fn setting_derived_blob_on_nonexistent_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(archive.open("file")?.set_derived("thumb", b"thumbnail"))
+            .to(be_err())
+            .to(equal(Error::FileNotFound {
+                path: "file".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn setting_derived_blob_with_empty_kind_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        expect!(file.set_derived("", b"thumbnail"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn setting_derived_blob_replaces_blob_of_same_kind() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_derived("thumb", b"old")?;
+        file.set_derived("thumb", b"new")?;
+        file.set_derived("preview", b"preview")?;
+
+        expect!(file.derived("thumb"))
+            .to(be_ok())
+            .to(equal(Some(b"new".to_vec())));
+
+        expect!(file.derived("preview"))
+            .to(be_ok())
+            .to(equal(Some(b"preview".to_vec())));
+
+        Ok(())
+    })
+}
+
+//
+// `File::derived`
+//
+
+#[test]
+fn getting_derived_blob_of_nonexistent_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(archive.open("file")?.derived("thumb"))
+            .to(be_err())
+            .to(equal(Error::FileNotFound {
+                path: "file".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn getting_missing_derived_blob_returns_none() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        expect!(file.derived("thumb")).to(be_ok()).to(equal(None));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn derived_blobs_follow_renamed_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("old")?;
+        file.create_file()?;
+        file.set_derived("thumb", b"thumbnail")?;
+        drop(file);
+
+        archive.rename("old", "new")?;
+
+        expect!(archive.open("new")?.derived("thumb"))
+            .to(be_ok())
+            .to(equal(Some(b"thumbnail".to_vec())));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn derived_blobs_are_deleted_with_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_derived("thumb", b"thumbnail")?;
+        file.delete()?;
+        file.create_file()?;
+
+        expect!(file.derived("thumb")).to(be_ok()).to(equal(None));
+
+        Ok(())
+    })
+}
+
+//
+// `File::remove_derived`
+//
+
+#[test]
+fn removing_derived_blob_returns_whether_it_existed() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        expect!(file.remove_derived("thumb"))
+            .to(be_ok())
+            .to(be_false());
+
+        file.set_derived("thumb", b"thumbnail")?;
+
+        expect!(file.remove_derived("thumb"))
+            .to(be_ok())
+            .to(be_true());
+        expect!(file.derived("thumb")).to(be_ok()).to(equal(None));
+
+        Ok(())
+    })
+}
@@ -0,0 +1,208 @@
+//! Tests for compressing small files with a shared Zstandard dictionary.
+
+#![cfg(feature = "zstd")]
+
+mod common;
+
+use std::io::Read;
+
+use common::{connection, random_bytes};
+use sqlarfs::{Archive, Compression, Error};
+use xpct::{be_err, be_lt, be_ok, equal, expect, match_pattern, pattern};
+
+const ZSTD: Compression = Compression::Zstd { level: 3 };
+
+// The number of files to train dictionaries on.
+const RECORDS: usize = 200;
+
+fn record(i: usize) -> String {
+    format!(
+        r#"{{"id": {i}, "name": "user {i}", "email": "user{i}@example.com", "roles": ["reader", "writer"], "active": {}}}"#,
+        i % 2 == 0
+    )
+}
+
+// Write a small JSON record to each of `count` files.
+fn write_records(archive: &mut Archive, count: usize) -> sqlarfs::Result<()> {
+    archive.set_compression(ZSTD);
+
+    for i in 0..count {
+        let mut file = archive.open(format!("record-{i}.json"))?;
+        file.create_file()?;
+        file.write_str(record(i))?;
+    }
+
+    Ok(())
+}
+
+fn read_contents(archive: &Archive, path: &str) -> sqlarfs::Result<String> {
+    let mut contents = String::new();
+    archive
+        .open(path)?
+        .reader()?
+        .read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+//
+// `Archive::train_dictionary`
+//
+
+#[test]
+fn train_dictionary_with_no_files_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(archive.train_dictionary())
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::Io { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn train_dictionary_ignores_binary_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        for i in 0..RECORDS {
+            let mut file = archive.open(format!("file-{i}"))?;
+            file.create_file()?;
+            file.write_bytes(&random_bytes(64))?;
+        }
+
+        expect!(archive.train_dictionary())
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::Io { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn files_written_after_training_can_be_read() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        write_records(archive, RECORDS)?;
+
+        archive.train_dictionary()?;
+
+        let mut file = archive.open("new.json")?;
+        file.create_file()?;
+        file.write_str(record(RECORDS))?;
+        drop(file);
+
+        expect!(read_contents(archive, "new.json"))
+            .to(be_ok())
+            .to(equal(record(RECORDS)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn dictionary_makes_small_text_files_smaller() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        write_records(archive, RECORDS)?;
+
+        let before = archive.compression_report("")?.total().stored_size();
+
+        archive.train_dictionary()?;
+        archive.recompress(ZSTD)?;
+
+        let after = archive.compression_report("")?.total().stored_size();
+
+        expect!(after).to(be_lt(before / 2));
+
+        for i in [0, 100, 199] {
+            expect!(read_contents(archive, &format!("record-{i}.json")))
+                .to(be_ok())
+                .to(equal(record(i)));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn dictionary_is_used_when_writing_from_reader() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        write_records(archive, RECORDS)?;
+
+        archive.train_dictionary()?;
+
+        let mut file = archive.open("new.json")?;
+        file.create_file()?;
+        file.write_from(&mut record(RECORDS).as_bytes())?;
+        drop(file);
+
+        expect!(read_contents(archive, "new.json"))
+            .to(be_ok())
+            .to(equal(record(RECORDS)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn files_compressed_with_old_dictionary_can_be_read_after_retraining() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        write_records(archive, RECORDS)?;
+
+        archive.train_dictionary()?;
+
+        let mut file = archive.open("old.json")?;
+        file.create_file()?;
+        file.write_str(record(RECORDS))?;
+        drop(file);
+
+        for i in 0..RECORDS {
+            let mut file = archive.open(format!("record-{i}.json"))?;
+            file.write_str(format!("<html><body><p>Page {i}</p></body></html>"))?;
+        }
+
+        archive.train_dictionary()?;
+
+        expect!(read_contents(archive, "old.json"))
+            .to(be_ok())
+            .to(equal(record(RECORDS)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn dictionary_persists_across_transactions() -> sqlarfs::Result<()> {
+    let mut conn = connection()?;
+
+    conn.exec(|archive| {
+        write_records(archive, RECORDS)?;
+        archive.train_dictionary()?;
+        archive.recompress(ZSTD)
+    })?;
+
+    conn.exec(|archive| {
+        expect!(read_contents(archive, "record-42.json"))
+            .to(be_ok())
+            .to(equal(record(42)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn copy_to_copies_dictionary() -> sqlarfs::Result<()> {
+    let mut dest_conn = connection()?;
+
+    connection()?.exec(|src| {
+        write_records(src, RECORDS)?;
+        src.train_dictionary()?;
+        src.recompress(ZSTD)?;
+
+        dest_conn.exec(|dest| {
+            src.copy_to(dest, "record-7.json", "copy.json")?;
+
+            expect!(read_contents(dest, "copy.json"))
+                .to(be_ok())
+                .to(equal(record(7)));
+
+            Ok(())
+        })
+    })
+}
@@ -0,0 +1,279 @@
+//! Tests for computing digests of archive contents.
+
+mod common;
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use sqlarfs::{Archive, Compression, Digest, DigestAlgorithm, DigestOptions, Error, FileMode};
+use xpct::{be_err, be_ok, equal, expect};
+
+use common::connection;
+
+fn populate(archive: &mut Archive, names: &[&str]) -> sqlarfs::Result<()> {
+    for name in names {
+        let mut file = archive.open(name)?;
+        file.create_file()?;
+        file.set_mtime(Some(UNIX_EPOCH))?;
+        file.set_mode(Some(FileMode::OWNER_R))?;
+        file.write_str(name)?;
+    }
+
+    Ok(())
+}
+
+fn digest_of<F>(opts: &DigestOptions, f: F) -> sqlarfs::Result<String>
+where
+    F: FnOnce(&mut Archive) -> sqlarfs::Result<()>,
+{
+    connection()?.exec(|archive| {
+        f(archive)?;
+        Ok(archive.content_digest_with(opts)?.to_string())
+    })
+}
+
+#[test]
+fn digest_is_hex_encoded_sha256() -> sqlarfs::Result<()> {
+    let digest = digest_of(&DigestOptions::new(), |_| Ok(()))?;
+
+    // The SHA-256 of an empty input.
+    expect!(digest).to(equal(String::from(
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+    )));
+
+    Ok(())
+}
+
+#[test]
+fn digest_does_not_depend_on_insertion_order() -> sqlarfs::Result<()> {
+    let opts = DigestOptions::new();
+
+    let first = digest_of(&opts, |archive| populate(archive, &["a", "b", "c"]))?;
+    let second = digest_of(&opts, |archive| populate(archive, &["c", "a", "b"]))?;
+
+    expect!(first).to(equal(second));
+
+    Ok(())
+}
+
+#[test]
+fn digest_does_not_depend_on_compression() -> sqlarfs::Result<()> {
+    let opts = DigestOptions::new();
+
+    let compressed = digest_of(&opts, |archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_mtime(Some(UNIX_EPOCH))?;
+        file.write_bytes(&[0u8; 64])?;
+        Ok(())
+    })?;
+
+    let uncompressed = digest_of(&opts, |archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_mtime(Some(UNIX_EPOCH))?;
+        file.set_compression(Compression::None);
+        file.write_bytes(&[0u8; 64])?;
+        Ok(())
+    })?;
+
+    expect!(compressed).to(equal(uncompressed));
+
+    Ok(())
+}
+
+#[test]
+fn digest_changes_when_contents_change() -> sqlarfs::Result<()> {
+    let opts = DigestOptions::new();
+
+    let first = digest_of(&opts, |archive| populate(archive, &["a"]))?;
+    let second = digest_of(&opts, |archive| {
+        populate(archive, &["a"])?;
+        archive.open("a")?.write_str("different")
+    })?;
+
+    expect!(first).to_not(equal(second));
+
+    Ok(())
+}
+
+#[test]
+fn digest_can_ignore_mtime() -> sqlarfs::Result<()> {
+    let change_mtime = |archive: &mut Archive| {
+        populate(archive, &["a"])?;
+        archive
+            .open("a")?
+            .set_mtime(Some(UNIX_EPOCH + Duration::from_secs(60)))
+    };
+
+    let opts = DigestOptions::new();
+    expect!(digest_of(&opts, |archive| populate(archive, &["a"])))
+        .to(be_ok())
+        .to_not(equal(digest_of(&opts, change_mtime)?));
+
+    let opts = DigestOptions::new().include_mtime(false);
+    expect!(digest_of(&opts, |archive| populate(archive, &["a"])))
+        .to(be_ok())
+        .to(equal(digest_of(&opts, change_mtime)?));
+
+    Ok(())
+}
+
+#[test]
+fn digest_can_ignore_mode() -> sqlarfs::Result<()> {
+    let change_mode = |archive: &mut Archive| {
+        populate(archive, &["a"])?;
+        archive.open("a")?.set_mode(Some(FileMode::OWNER_RWX))
+    };
+
+    let opts = DigestOptions::new();
+    expect!(digest_of(&opts, |archive| populate(archive, &["a"])))
+        .to(be_ok())
+        .to_not(equal(digest_of(&opts, change_mode)?));
+
+    let opts = DigestOptions::new().include_mode(false);
+    expect!(digest_of(&opts, |archive| populate(archive, &["a"])))
+        .to(be_ok())
+        .to(equal(digest_of(&opts, change_mode)?));
+
+    Ok(())
+}
+
+//
+// `File::digest`
+//
+
+#[test]
+fn file_digest_is_sha256_of_contents() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("Hello, world!")?;
+
+        expect!(file.digest().map(|digest| digest.to_string()))
+            .to(be_ok())
+            .to(equal(String::from(
+                "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3",
+            )));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn file_digest_does_not_depend_on_compression() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut uncompressed = archive.open("uncompressed")?;
+        uncompressed.create_file()?;
+        uncompressed.set_compression(Compression::None);
+        uncompressed.write_str("a".repeat(1024))?;
+        let uncompressed_digest = uncompressed.digest()?;
+
+        let mut compressed = archive.open("compressed")?;
+        compressed.create_file()?;
+        compressed.set_compression(Compression::FAST);
+        compressed.write_str("a".repeat(1024))?;
+
+        expect!(compressed.digest())
+            .to(be_ok())
+            .to(equal(uncompressed_digest));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn file_digest_of_directory_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut dir = archive.open("dir")?;
+        dir.create_dir()?;
+
+        expect!(dir.digest())
+            .to(be_err())
+            .to(equal(Error::NotARegularFile { path: "dir".into() }));
+
+        Ok(())
+    })
+}
+
+//
+// `File::digest_with`
+//
+
+#[test]
+fn file_digest_with_sha256_matches_file_digest() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("Hello, world!")?;
+
+        let expected = file.digest()?;
+
+        expect!(file.digest_with(DigestAlgorithm::Sha256))
+            .to(be_ok())
+            .to(equal(expected));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "blake3")]
+fn file_digest_with_blake3_differs_from_sha256() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("Hello, world!")?;
+
+        let sha256 = file.digest()?;
+        let blake3 = file.digest_with(DigestAlgorithm::Blake3)?;
+
+        expect!(blake3.algorithm()).to(equal(DigestAlgorithm::Blake3));
+        expect!(blake3).to_not(equal(sha256));
+        expect!(Digest::from_reader_with(
+            "Hello, world!".as_bytes(),
+            DigestAlgorithm::Blake3
+        ))
+        .to(be_ok())
+        .to(equal(blake3));
+
+        Ok(())
+    })
+}
+
+//
+// `Digest::from_reader`
+//
+
+#[test]
+fn digest_from_reader_matches_file_digest() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("Hello, world!")?;
+
+        expect!(Digest::from_reader("Hello, world!".as_bytes()))
+            .to(be_ok())
+            .to(equal(file.digest()?));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn digest_from_reader_with_matches_file_digest_with() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("Hello, world!")?;
+
+        expect!(Digest::from_reader_with(
+            "Hello, world!".as_bytes(),
+            DigestAlgorithm::Sha256
+        ))
+        .to(be_ok())
+        .to(equal(file.digest_with(DigestAlgorithm::Sha256)?));
+
+        Ok(())
+    })
+}
@@ -0,0 +1,219 @@
+//! Tests for reading and writing serialized values to files.
+
+#![cfg(any(feature = "json", feature = "msgpack"))]
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::io;
+
+use common::connection;
+use serde::{Deserialize, Serialize};
+use sqlarfs::{Archive, Error};
+use xpct::{be_err, be_ok, equal, expect, match_pattern, pattern};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Settings {
+    name: String,
+    retries: u32,
+    tags: BTreeMap<String, bool>,
+}
+
+fn settings() -> Settings {
+    Settings {
+        name: String::from("archive"),
+        retries: 3,
+        tags: BTreeMap::from([(String::from("compressed"), true)]),
+    }
+}
+
+fn create_file(archive: &mut Archive, path: &str, contents: &str) -> sqlarfs::Result<()> {
+    let mut file = archive.open(path)?;
+    file.create_file()?;
+    file.write_str(contents)
+}
+
+//
+// `File::write_json` / `File::read_json`
+//
+
+#[test]
+#[cfg(feature = "json")]
+fn json_round_trips() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("settings.json")?;
+        file.create_file()?;
+        file.write_json(&settings())?;
+
+        expect!(file.read_json::<Settings>())
+            .to(be_ok())
+            .to(equal(settings()));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn write_json_writes_json() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("value.json")?;
+        file.create_file()?;
+        file.write_json(&vec![1, 2, 3])?;
+
+        let mut contents = String::new();
+        io::Read::read_to_string(&mut file.reader()?, &mut contents)?;
+
+        expect!(contents).to(equal(String::from("[1,2,3]")));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn write_json_replaces_contents() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(
+            archive,
+            "value.json",
+            "a much longer file than the new contents",
+        )?;
+
+        let mut file = archive.open("value.json")?;
+        file.write_json(&true)?;
+
+        expect!(file.read_json::<bool>())
+            .to(be_ok())
+            .to(equal(true));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn read_json_with_invalid_json_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "value.json", "{ not json")?;
+
+        expect!(archive.open("value.json")?.read_json::<Settings>())
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::Io {
+                kind: io::ErrorKind::InvalidData,
+                ..
+            })));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn read_json_with_wrong_type_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "value.json", "[1, 2, 3]")?;
+
+        expect!(archive.open("value.json")?.read_json::<Settings>())
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::Io {
+                kind: io::ErrorKind::InvalidData,
+                ..
+            })));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn json_on_missing_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("value.json")?;
+
+        expect!(file.write_json(&settings()))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileNotFound { .. })));
+
+        expect!(file.read_json::<Settings>())
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileNotFound { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn json_on_directory_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut dir = archive.open("dir")?;
+        dir.create_dir()?;
+
+        expect!(dir.write_json(&settings()))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::NotARegularFile { .. })));
+
+        expect!(dir.read_json::<Settings>())
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::NotARegularFile { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `File::write_msgpack` / `File::read_msgpack`
+//
+
+#[test]
+#[cfg(feature = "msgpack")]
+fn msgpack_round_trips() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("settings.msgpack")?;
+        file.create_file()?;
+        file.write_msgpack(&settings())?;
+
+        expect!(file.read_msgpack::<Settings>())
+            .to(be_ok())
+            .to(equal(settings()));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "msgpack")]
+fn read_msgpack_with_invalid_data_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file(archive, "settings.msgpack", "not msgpack")?;
+
+        expect!(archive.open("settings.msgpack")?.read_msgpack::<Settings>())
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::Io {
+                kind: io::ErrorKind::InvalidData,
+                ..
+            })));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "msgpack")]
+fn msgpack_on_directory_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut dir = archive.open("dir")?;
+        dir.create_dir()?;
+
+        expect!(dir.write_msgpack(&settings()))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::NotARegularFile { .. })));
+
+        expect!(dir.read_msgpack::<Settings>())
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::NotARegularFile { .. })));
+
+        Ok(())
+    })
+}
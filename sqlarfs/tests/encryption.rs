@@ -0,0 +1,209 @@
+//! Tests for encrypting archives with SQLCipher.
+
+#![cfg(feature = "sqlcipher")]
+
+mod common;
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use sqlarfs::{Compression, Connection, ConnectionBuilder, Error};
+use xpct::{be_err, be_false, be_ok, be_true, equal, expect, match_pattern, pattern};
+
+const CONTENTS: &str = "these are the secret contents of the file";
+
+fn create_encrypted(path: &Path, key: &str) -> sqlarfs::Result<()> {
+    ConnectionBuilder::new()
+        .encryption_key(key)
+        .create_new(path)?
+        .exec(|archive| {
+            archive.set_compression(Compression::None);
+
+            let mut file = archive.open("file")?;
+            file.create_file()?;
+            file.write_str(CONTENTS)
+        })
+}
+
+fn read_contents(conn: &mut Connection) -> sqlarfs::Result<String> {
+    conn.exec(|archive| {
+        let mut contents = String::new();
+        archive
+            .open("file")?
+            .reader()?
+            .read_to_string(&mut contents)?;
+        Ok(contents)
+    })
+}
+
+//
+// `ConnectionBuilder::encryption_key`
+//
+
+#[test]
+fn encrypted_archive_can_be_opened_with_key() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    create_encrypted(&path, "key")?;
+
+    let mut conn = ConnectionBuilder::new().encryption_key("key").open(&path)?;
+
+    expect!(read_contents(&mut conn))
+        .to(be_ok())
+        .to(equal(CONTENTS));
+
+    let mut conn = ConnectionBuilder::new()
+        .encryption_key("key")
+        .open_readonly(&path)?;
+
+    expect!(read_contents(&mut conn))
+        .to(be_ok())
+        .to(equal(CONTENTS));
+
+    Ok(())
+}
+
+#[test]
+fn encrypted_archive_does_not_contain_plaintext() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    create_encrypted(&path, "key")?;
+
+    let bytes = fs::read(&path)?;
+
+    expect!(bytes.starts_with(b"SQLite format 3")).to(be_false());
+    expect!(bytes
+        .windows(CONTENTS.len())
+        .any(|window| window == CONTENTS.as_bytes()))
+    .to(be_false());
+
+    Ok(())
+}
+
+#[test]
+fn opening_encrypted_archive_without_key_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    create_encrypted(&path, "key")?;
+
+    expect!(Connection::open(&path))
+        .to(be_err())
+        .to(equal(Error::NotADatabase));
+
+    expect!(Connection::open_readonly(&path))
+        .to(be_err())
+        .to(equal(Error::NotADatabase));
+
+    Ok(())
+}
+
+#[test]
+fn opening_encrypted_archive_with_wrong_key_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    create_encrypted(&path, "key")?;
+
+    expect!(ConnectionBuilder::new()
+        .encryption_key("wrong key")
+        .open(&path))
+    .to(be_err())
+    .to(equal(Error::NotADatabase));
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_read_of_encrypted_archive_uses_key() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    create_encrypted(&path, "key")?;
+
+    ConnectionBuilder::new()
+        .encryption_key("key")
+        .open(&path)?
+        .exec(|archive| {
+            archive.open("file")?.delete()?;
+
+            let existed_before =
+                archive.snapshot_read(|snapshot| snapshot.open("file")?.exists())?;
+
+            expect!(existed_before).to(be_true());
+
+            Ok(())
+        })
+}
+
+//
+// `Connection::rekey`
+//
+
+#[test]
+fn rekeying_changes_key() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    create_encrypted(&path, "old key")?;
+
+    ConnectionBuilder::new()
+        .encryption_key("old key")
+        .open(&path)?
+        .rekey("new key")?;
+
+    expect!(ConnectionBuilder::new()
+        .encryption_key("old key")
+        .open(&path))
+    .to(be_err())
+    .to(equal(Error::NotADatabase));
+
+    let mut conn = ConnectionBuilder::new()
+        .encryption_key("new key")
+        .open(&path)?;
+
+    expect!(read_contents(&mut conn))
+        .to(be_ok())
+        .to(equal(CONTENTS));
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_read_after_rekeying_uses_new_key() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    create_encrypted(&path, "old key")?;
+
+    let mut conn = ConnectionBuilder::new()
+        .encryption_key("old key")
+        .open(&path)?;
+
+    conn.rekey("new key")?;
+
+    conn.exec(|archive| {
+        let exists = archive.snapshot_read(|snapshot| snapshot.open("file")?.exists())?;
+
+        expect!(exists).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rekeying_unencrypted_archive_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    let mut conn = Connection::create_new(&path)?;
+
+    expect!(conn.rekey("key"))
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+    Ok(())
+}
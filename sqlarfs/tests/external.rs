@@ -0,0 +1,175 @@
+//! Tests for links to files in other archives.
+
+mod common;
+
+use std::io::Read;
+use std::path::Path;
+
+use sqlarfs::{Connection, Error, FileMetadata};
+use xpct::{be_err, be_none, be_ok, be_some, equal, expect, match_pattern, pattern};
+
+use common::connection;
+
+//
+// `File::create_external_link`
+//
+
+#[test]
+fn create_external_link_stores_link() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("link")?;
+        file.create_external_link("base.sqlar", "dir/file")?;
+
+        expect!(file.metadata())
+            .to(be_ok())
+            .to(match_pattern(pattern!(FileMetadata::File { size: 0, .. })));
+
+        let link = file.external_link()?;
+
+        expect!(link.as_ref().map(|link| link.archive()))
+            .to(be_some())
+            .to(equal(Path::new("base.sqlar")));
+
+        expect!(link.as_ref().map(|link| link.target()))
+            .to(be_some())
+            .to(equal(Path::new("dir/file")));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn create_external_link_when_file_already_exists_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("link")?;
+        file.create_file()?;
+
+        expect!(file.create_external_link("base.sqlar", "file"))
+            .to(be_err())
+            .to(equal(Error::FileAlreadyExists {
+                path: "link".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn create_external_link_with_absolute_target_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("link")?;
+
+        let target = if cfg!(windows) { r"C:\file" } else { "/file" };
+
+        expect!(file.create_external_link("base.sqlar", target))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `File::external_link`
+//
+
+#[test]
+fn regular_file_is_not_an_external_link() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        expect!(file.external_link()).to(be_ok()).to(be_none());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn external_link_of_nonexistent_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(archive.open("file")?.external_link())
+            .to(be_err())
+            .to(equal(Error::FileNotFound {
+                path: "file".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn external_link_is_deleted_with_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("link")?;
+        file.create_external_link("base.sqlar", "file")?;
+        file.delete()?;
+        file.create_file()?;
+
+        expect!(file.external_link()).to(be_ok()).to(be_none());
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::open_external`
+//
+
+#[test]
+fn open_external_resolves_relative_to_archive() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    std::fs::create_dir(temp_dir.path().join("archives"))?;
+
+    let mut base = Connection::create_new(temp_dir.path().join("archives/base.sqlar"))?;
+
+    base.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("contents")
+    })?;
+
+    let mut overlay = Connection::create_new(temp_dir.path().join("archives/overlay.sqlar"))?;
+
+    overlay.exec(|archive| {
+        archive
+            .open("link")?
+            .create_external_link("base.sqlar", "file")?;
+
+        let link = archive
+            .open("link")?
+            .external_link()?
+            .expect("file is not an external link");
+
+        archive.open_external(&link)?.exec(|base_archive| {
+            let mut contents = String::new();
+            base_archive
+                .open(link.target())?
+                .reader()?
+                .read_to_string(&mut contents)?;
+
+            expect!(contents.as_str()).to(equal("contents"));
+
+            sqlarfs::Result::Ok(())
+        })
+    })
+}
+
+#[test]
+fn open_external_when_archive_does_not_exist_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let mut conn = Connection::create_new(temp_dir.path().join("overlay.sqlar"))?;
+
+    conn.exec(|archive| {
+        let mut file = archive.open("link")?;
+        file.create_external_link("nonexistent.sqlar", "file")?;
+
+        let link = file.external_link()?.expect("file is not an external link");
+
+        expect!(archive.open_external(&link))
+            .to(be_err())
+            .to(equal(Error::CannotOpen));
+
+        Ok(())
+    })
+}
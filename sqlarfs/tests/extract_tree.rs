@@ -1,15 +1,29 @@
+//! Tests for copying directory trees from an archive into the filesystem.
+
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime};
 
-use common::{connection, truncate_mtime};
-use sqlarfs::{Error, ExtractOptions, FileMode};
+use common::{connection, random_bytes, truncate_mtime};
+use sqlarfs::{
+    ConflictAction, Connection, Error, ExtractOptions, FileMode, LongNamePolicy, MetadataFallback,
+    NewlinePolicy,
+};
 use xpct::{
-    be_directory, be_err, be_existing_file, be_false, be_ok, be_regular_file, be_true, equal,
-    expect, match_pattern, pattern,
+    be_directory, be_err, be_existing_file, be_false, be_gt, be_ok, be_regular_file, be_true,
+    equal, expect, match_pattern, pattern,
 };
 
 mod common;
 
+// Make a directory tree deep enough that its paths are longer than `MAX_PATH` on Windows.
+fn deep_path() -> PathBuf {
+    (0..20)
+        .map(|i| format!("{i:02}-{}", "d".repeat(16)))
+        .collect()
+}
+
 #[test]
 fn extracting_when_source_path_does_not_exist_errors() -> sqlarfs::Result<()> {
     let temp_dir = tempfile::tempdir()?;
@@ -329,6 +343,8 @@ fn extract_symlink() -> sqlarfs::Result<()> {
 #[test]
 #[cfg(windows)]
 fn extracting_symlinks_is_a_noop_on_windows() -> sqlarfs::Result<()> {
+    use xpct::be_false;
+
     let temp_dir = tempfile::tempdir()?;
     let symlink_target = tempfile::NamedTempFile::new()?;
     let dest_path = temp_dir.path().join("dest");
@@ -470,6 +486,65 @@ fn extracting_with_trailing_slash_in_source_path() -> sqlarfs::Result<()> {
     })
 }
 
+#[test]
+fn extracting_a_deep_tree_with_long_paths() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        let deep_dir = PathBuf::from("dir").join(deep_path());
+        archive.open(&deep_dir)?.create_dir_all()?;
+
+        let mut file = archive.open(deep_dir.join("file"))?;
+        file.create_file()?;
+        file.write_str("contents")?;
+
+        expect!(archive.extract("dir", temp_dir.path().join("dir"))).to(be_ok());
+
+        let extracted_path = temp_dir.path().join(&deep_dir).join("file");
+
+        expect!(extracted_path.as_os_str().len()).to(be_gt(260));
+        expect!(fs::read_to_string(extracted_path))
+            .to(be_ok())
+            .to(equal("contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_large_and_small_files_preserves_contents() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    // Make sure files bigger than the copy buffer are copied in full, and that smaller files
+    // extracted afterward don't pick up stale data from the buffer.
+    let large_contents = random_bytes(1024 * 1024 + 1);
+    let small_contents = random_bytes(16);
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let mut large_file = archive.open("dir/large")?;
+        large_file.create_file()?;
+        large_file.write_bytes(&large_contents)?;
+
+        let mut small_file = archive.open("dir/small")?;
+        small_file.create_file()?;
+        small_file.write_bytes(&small_contents)?;
+
+        expect!(archive.extract("dir", temp_dir.path().join("dir"))).to(be_ok());
+
+        expect!(fs::read(temp_dir.path().join("dir/large")))
+            .to(be_ok())
+            .to(equal(large_contents.clone()));
+
+        expect!(fs::read(temp_dir.path().join("dir/small")))
+            .to(be_ok())
+            .to(equal(small_contents.clone()));
+
+        Ok(())
+    })
+}
+
 //
 // `ExtractOptions::children`
 //
@@ -682,3 +757,1098 @@ fn extract_files_from_archive_root_non_recursively() -> sqlarfs::Result<()> {
         Ok(())
     })
 }
+
+//
+// `ExtractOptions::create_empty_dirs`
+//
+
+#[test]
+fn extracting_without_empty_dirs_skips_them() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        archive.open("empty")?.create_dir()?;
+        archive.open("full")?.create_dir()?;
+        archive.open("full/file")?.create_file()?;
+
+        let opts = ExtractOptions::new()
+            .children(true)
+            .create_empty_dirs(false);
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        expect!(temp_dir.path().join("full/file")).to(be_regular_file());
+        expect!(temp_dir.path().join("empty").exists()).to(be_false());
+
+        Ok(())
+    })
+}
+
+//
+// `ExtractOptions::strip_components`
+//
+
+#[test]
+fn extracting_with_strip_components_strips_leading_dirs() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        archive.open("a")?.create_dir()?;
+        archive.open("a/b")?.create_dir()?;
+        archive.open("a/b/file")?.create_file()?;
+        archive.open("top-level-file")?.create_file()?;
+
+        let opts = ExtractOptions::new().children(true).strip_components(1);
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        expect!(temp_dir.path().join("b")).to(be_directory());
+        expect!(temp_dir.path().join("b/file")).to(be_regular_file());
+        expect!(temp_dir.path().join("a").exists()).to(be_false());
+        expect!(temp_dir.path().join("top-level-file").exists()).to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_strip_components_without_children_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let opts = ExtractOptions::new().strip_components(1);
+
+        expect!(archive.extract_with("dir", temp_dir.path().join("dest"), &opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `ExtractOptions::on_conflict`
+//
+
+#[test]
+fn extracting_with_conflict_overwrite_replaces_existing_file() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file"), "old contents")?;
+
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+        archive.open("file")?.write_str("new contents")?;
+
+        let opts = ExtractOptions::new()
+            .children(true)
+            .on_conflict(|_, _, _| ConflictAction::Overwrite);
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        expect!(fs::read_to_string(temp_dir.path().join("file")))
+            .to(be_ok())
+            .to(equal("new contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_conflict_overwrite_merges_dirs() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::create_dir(temp_dir.path().join("dir"))?;
+    fs::write(temp_dir.path().join("dir/existing"), "")?;
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/new")?.create_file()?;
+
+        let opts = ExtractOptions::new()
+            .children(true)
+            .on_conflict(|_, _, _| ConflictAction::Overwrite);
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        expect!(temp_dir.path().join("dir/existing")).to(be_regular_file());
+        expect!(temp_dir.path().join("dir/new")).to(be_regular_file());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_conflict_skip_skips_dir_and_descendants() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::create_dir(temp_dir.path().join("dir"))?;
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/file")?.create_file()?;
+        archive.open("other")?.create_file()?;
+
+        let opts = ExtractOptions::new()
+            .children(true)
+            .on_conflict(|_, _, _| ConflictAction::Skip);
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        expect!(temp_dir.path().join("dir/file").exists()).to(be_false());
+        expect!(temp_dir.path().join("other")).to(be_regular_file());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_conflict_rename_extracts_to_new_path() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("dir"), "")?;
+    fs::write(temp_dir.path().join("dir.1"), "")?;
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/file")?.create_file()?;
+
+        let opts = ExtractOptions::new()
+            .children(true)
+            .on_conflict(|_, _, _| ConflictAction::RenameWithSuffix(String::from(".1")));
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        expect!(temp_dir.path().join("dir.1.1")).to(be_directory());
+        expect!(temp_dir.path().join("dir.1.1/file")).to(be_regular_file());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_conflict_abort_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file"), "")?;
+
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        let opts = ExtractOptions::new()
+            .children(true)
+            .on_conflict(|_, _, _| ConflictAction::Abort);
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts))
+            .to(be_err())
+            .to(equal(Error::FileAlreadyExists {
+                path: temp_dir.path().join("file"),
+            }));
+
+        Ok(())
+    })
+}
+
+//
+// `ExtractOptions::metadata_fallback`
+//
+
+#[test]
+#[cfg(unix)]
+fn extracting_with_metadata_fallback_on_supported_filesystem_preserves_metadata(
+) -> sqlarfs::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = tempfile::tempdir()?;
+    let mode = FileMode::OWNER_R | FileMode::OWNER_W;
+
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_mode(Some(mode))?;
+
+        archive.open("symlink")?.create_symlink("file")?;
+
+        let opts = ExtractOptions::new()
+            .children(true)
+            .metadata_fallback(MetadataFallback::Error);
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        expect!(
+            fs::metadata(temp_dir.path().join("file"))?
+                .permissions()
+                .mode()
+                & 0o777
+        )
+        .to(equal(mode.bits()));
+
+        expect!(fs::symlink_metadata(temp_dir.path().join("symlink"))?.is_symlink()).to(be_true());
+
+        // The files we created to probe the filesystem should be gone.
+        expect!(fs::read_dir(temp_dir.path())?.count()).to(equal(2));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_metadata_fallback_when_dest_has_no_parent_dir_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        let opts = ExtractOptions::new().metadata_fallback(MetadataFallback::Ignore);
+
+        expect!(archive.extract_with("file", "/nonexistent/dest", &opts))
+            .to(be_err())
+            .to(equal(Error::NoParentDirectory {
+                path: "/nonexistent/dest".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+//
+// `ExtractOptions::template_vars`
+//
+
+#[test]
+fn extracting_with_template_vars_substitutes_them_in_text_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        archive.open("template")?.create_dir()?;
+
+        let mut manifest = archive.open("template/Cargo.toml")?;
+        manifest.create_file()?;
+        manifest
+            .write_str("name = \"{{ project_name }}\"\nauthors = [\"{{author}}\"]\n{{other}}")?;
+
+        let opts = ExtractOptions::new()
+            .template_vars([("project_name", "my-project"), ("author", "Jane Doe")]);
+
+        expect!(archive.extract_with("template", temp_dir.path().join("project"), &opts))
+            .to(be_ok());
+
+        expect!(fs::read_to_string(
+            temp_dir.path().join("project/Cargo.toml")
+        ))
+        .to(be_ok())
+        .to(equal(
+            "name = \"my-project\"\nauthors = [\"Jane Doe\"]\n{{other}}",
+        ));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_template_vars_leaves_binary_files_unchanged() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let contents = b"\0{{name}}".to_vec();
+
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_bytes(&contents)?;
+
+        let opts = ExtractOptions::new().template_vars([("name", "value")]);
+
+        expect!(archive.extract_with("file", temp_dir.path().join("file"), &opts)).to(be_ok());
+
+        expect!(fs::read(temp_dir.path().join("file")))
+            .to(be_ok())
+            .to(equal(contents.clone()));
+
+        Ok(())
+    })
+}
+
+//
+// `ExtractOptions::newline_policy`
+//
+
+#[test]
+fn extracting_with_lf_newline_policy_converts_crlf() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("one\r\ntwo\rthree\nfour\r\n")?;
+
+        let opts = ExtractOptions::new().newline_policy(NewlinePolicy::Lf);
+
+        expect!(archive.extract_with("file", temp_dir.path().join("file"), &opts)).to(be_ok());
+
+        expect!(fs::read_to_string(temp_dir.path().join("file")))
+            .to(be_ok())
+            .to(equal("one\ntwo\rthree\nfour\n"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_crlf_newline_policy_converts_lf() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("one\ntwo\r\nthree")?;
+
+        let opts = ExtractOptions::new().newline_policy(NewlinePolicy::CrLf);
+
+        expect!(archive.extract_with("file", temp_dir.path().join("file"), &opts)).to(be_ok());
+
+        expect!(fs::read_to_string(temp_dir.path().join("file")))
+            .to(be_ok())
+            .to(equal("one\r\ntwo\r\nthree"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_newline_policy_and_template_vars_does_both() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("name = {{name}}\n")?;
+
+        let opts = ExtractOptions::new()
+            .template_vars([("name", "value")])
+            .newline_policy(NewlinePolicy::CrLf);
+
+        expect!(archive.extract_with("file", temp_dir.path().join("file"), &opts)).to(be_ok());
+
+        expect!(fs::read_to_string(temp_dir.path().join("file")))
+            .to(be_ok())
+            .to(equal("name = value\r\n"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_newline_policy_leaves_binary_files_unchanged() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let contents = b"\0one\ntwo\r\n".to_vec();
+
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_bytes(&contents)?;
+
+        let opts = ExtractOptions::new().newline_policy(NewlinePolicy::CrLf);
+
+        expect!(archive.extract_with("file", temp_dir.path().join("file"), &opts)).to(be_ok());
+
+        expect!(fs::read(temp_dir.path().join("file")))
+            .to(be_ok())
+            .to(equal(contents.clone()));
+
+        Ok(())
+    })
+}
+
+//
+// `ExtractOptions::long_names`
+//
+
+#[test]
+fn extracting_file_name_too_long_for_filesystem_errors_by_default() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let long_name = "a".repeat(300);
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open(format!("dir/{long_name}"))?.create_file()?;
+
+        expect!(archive.extract("dir", temp_dir.path().join("dir")))
+            .to(be_err())
+            .to(equal(Error::NameTooLong {
+                path: format!("dir/{long_name}").into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_truncated_long_names_shortens_them() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let long_dir = "d".repeat(300);
+    let long_file = format!("{}.txt", "f".repeat(300));
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open(format!("dir/{long_dir}"))?.create_dir()?;
+
+        let mut file = archive.open(format!("dir/{long_dir}/{long_file}"))?;
+        file.create_file()?;
+        file.write_str("contents")?;
+        drop(file);
+
+        let opts = ExtractOptions::new().long_names(LongNamePolicy::Truncate);
+
+        expect!(archive.extract_with("dir", temp_dir.path().join("dir"), &opts)).to(be_ok());
+
+        let dirs = fs::read_dir(temp_dir.path().join("dir"))?.collect::<Result<Vec<_>, _>>()?;
+
+        expect!(dirs.len()).to(equal(1));
+
+        let dir_name = dirs[0].file_name();
+
+        expect!(dir_name.len()).to(equal(255));
+        expect!(dir_name.to_string_lossy().starts_with("ddd")).to(be_true());
+
+        let files = fs::read_dir(dirs[0].path())?.collect::<Result<Vec<_>, _>>()?;
+
+        expect!(files.len()).to(equal(1));
+
+        let file_name = files[0].file_name();
+
+        expect!(file_name.len()).to(equal(255));
+        expect!(file_name.to_string_lossy().ends_with(".txt")).to(be_true());
+        expect!(fs::read_to_string(files[0].path()))
+            .to(be_ok())
+            .to(equal("contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_truncated_long_names_keeps_names_distinct() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        for suffix in ["1", "2"] {
+            archive
+                .open(format!("dir/{}{suffix}", "a".repeat(300)))?
+                .create_file()?;
+        }
+
+        let opts = ExtractOptions::new().long_names(LongNamePolicy::Truncate);
+
+        expect!(archive.extract_with("dir", temp_dir.path().join("dir"), &opts)).to(be_ok());
+
+        expect!(fs::read_dir(temp_dir.path().join("dir"))?.count()).to(equal(2));
+
+        Ok(())
+    })
+}
+
+//
+// `ExtractOptions::skip_broken_symlinks`
+//
+
+#[test]
+fn extracting_without_skipping_broken_symlinks_extracts_them() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        archive.open("link")?.create_symlink("missing")?;
+
+        let opts = ExtractOptions::new().children(true);
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        expect!(fs::symlink_metadata(temp_dir.path().join("link"))?.is_symlink()).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_skip_broken_symlinks_skips_links_with_missing_targets() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+        archive.open("good")?.create_symlink("file")?;
+        archive.open("bad")?.create_symlink("missing")?;
+
+        let opts = ExtractOptions::new()
+            .children(true)
+            .skip_broken_symlinks(true);
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        expect!(fs::symlink_metadata(temp_dir.path().join("good"))?.is_symlink()).to(be_true());
+        expect!(fs::symlink_metadata(temp_dir.path().join("bad"))).to(be_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_skip_broken_symlinks_checks_targets_outside_archive() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let symlink_target = tempfile::NamedTempFile::new()?;
+
+    connection()?.exec(|archive| {
+        archive
+            .open("good")?
+            .create_symlink(symlink_target.path())?;
+        archive
+            .open("bad")?
+            .create_symlink(temp_dir.path().join("nonexistent"))?;
+
+        let opts = ExtractOptions::new()
+            .children(true)
+            .skip_broken_symlinks(true);
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        expect!(fs::symlink_metadata(temp_dir.path().join("good"))?.is_symlink()).to(be_true());
+        expect!(fs::symlink_metadata(temp_dir.path().join("bad"))).to(be_err());
+
+        Ok(())
+    })
+}
+
+//
+// `ExtractOptions::windows_symlinks`
+//
+
+#[test]
+#[cfg(windows)]
+fn extracting_with_copy_windows_symlinks_copies_file_targets() -> sqlarfs::Result<()> {
+    use sqlarfs::WindowsSymlinkPolicy;
+
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("contents")?;
+
+        archive.open("link")?.create_symlink("file")?;
+
+        let opts = ExtractOptions::new()
+            .children(true)
+            .windows_symlinks(WindowsSymlinkPolicy::Copy);
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        expect!(fs::read_to_string(temp_dir.path().join("link")))
+            .to(be_ok())
+            .to(equal("contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(windows)]
+fn extracting_with_copy_windows_symlinks_copies_dir_targets() -> sqlarfs::Result<()> {
+    use sqlarfs::WindowsSymlinkPolicy;
+
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/file")?.create_file()?;
+        archive.open("link")?.create_symlink("dir")?;
+
+        let opts = ExtractOptions::new()
+            .children(true)
+            .windows_symlinks(WindowsSymlinkPolicy::Copy);
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        expect!(temp_dir.path().join("link/file")).to(be_regular_file());
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(windows)]
+fn extracting_with_copy_windows_symlinks_skips_links_to_ancestors() -> sqlarfs::Result<()> {
+    use sqlarfs::WindowsSymlinkPolicy;
+
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/link")?.create_symlink("..")?;
+        archive.open("dir/other")?.create_symlink("../dir")?;
+
+        let opts = ExtractOptions::new()
+            .children(true)
+            .windows_symlinks(WindowsSymlinkPolicy::Copy);
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        expect!(fs::read_dir(temp_dir.path().join("dir"))?.count()).to(equal(0));
+
+        Ok(())
+    })
+}
+
+//
+// `ExtractOptions::on_progress`
+//
+
+#[test]
+fn extracting_with_progress_callback_reports_each_file() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let reports_clone = Arc::clone(&reports);
+
+    let opts = ExtractOptions::new()
+        .children(true)
+        .on_progress(move |progress| reports_clone.lock().unwrap().push(progress.clone()));
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let mut file = archive.open("dir/file1")?;
+        file.create_file()?;
+        file.write_str("12345")?;
+
+        let mut file = archive.open("file2")?;
+        file.create_file()?;
+        file.write_str("123")?;
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    let reports = reports.lock().unwrap().clone();
+
+    expect!(reports
+        .iter()
+        .map(|progress| (progress.files_done(), progress.files_total()))
+        .collect::<Vec<_>>())
+    .to(equal(vec![(1, Some(3)), (2, Some(3)), (3, Some(3))]));
+
+    expect!(reports.last().map(|progress| progress.bytes_done())).to(equal(Some(8)));
+    expect!(reports.last().and_then(|progress| progress.bytes_total())).to(equal(Some(8)));
+
+    Ok(())
+}
+
+#[test]
+fn extracting_with_progress_callback_reports_skipped_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    fs::create_dir(temp_dir.path().join("dir"))?;
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let reports_clone = Arc::clone(&reports);
+
+    let opts = ExtractOptions::new()
+        .children(true)
+        .on_conflict(|_, _, _| ConflictAction::Skip)
+        .on_progress(move |progress| reports_clone.lock().unwrap().push(progress.clone()));
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/file")?.create_file()?;
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    expect!(reports
+        .lock()
+        .unwrap()
+        .last()
+        .map(|progress| (progress.files_done(), progress.files_total())))
+    .to(equal(Some((2, Some(2)))));
+
+    Ok(())
+}
+
+//
+// `ExtractOptions::anchor_root`
+//
+
+#[test]
+#[cfg(unix)]
+fn extracting_with_anchor_root_extracts_tree() -> sqlarfs::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        let mut dir = archive.open("dir")?;
+        dir.create_dir()?;
+        dir.set_mode(Some(FileMode::from_bits_truncate(0o750)))?;
+
+        let mut file = archive.open("dir/file")?;
+        file.create_file()?;
+        file.write_str("contents")?;
+        file.set_mode(Some(FileMode::from_bits_truncate(0o640)))?;
+
+        archive.open("dir/symlink")?.create_symlink("file")?;
+
+        let opts = ExtractOptions::new().anchor_root(true);
+
+        expect!(archive.extract_with("dir", temp_dir.path().join("dir"), &opts)).to(be_ok());
+
+        expect!(fs::read_to_string(temp_dir.path().join("dir/file")))
+            .to(be_ok())
+            .to(equal("contents"));
+
+        expect!(fs::read_link(temp_dir.path().join("dir/symlink")))
+            .to(be_ok())
+            .to(equal(PathBuf::from("file")));
+
+        expect!(
+            fs::metadata(temp_dir.path().join("dir/file"))?
+                .permissions()
+                .mode()
+                & 0o777
+        )
+        .to(equal(0o640));
+
+        expect!(
+            fs::metadata(temp_dir.path().join("dir"))?
+                .permissions()
+                .mode()
+                & 0o777
+        )
+        .to(equal(0o750));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(unix)]
+fn extracting_with_anchor_root_into_children_of_dest() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let mut file = archive.open("dir/file")?;
+        file.create_file()?;
+        file.write_str("contents")?;
+
+        let opts = ExtractOptions::new().children(true).anchor_root(true);
+
+        expect!(archive.extract_with("", temp_dir.path(), &opts)).to(be_ok());
+
+        expect!(fs::read_to_string(temp_dir.path().join("dir/file")))
+            .to(be_ok())
+            .to(equal("contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(unix)]
+fn extracting_with_anchor_root_when_file_already_exists_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dest_path = temp_dir.path().join("file");
+
+    fs::write(&dest_path, "existing")?;
+
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        let opts = ExtractOptions::new().anchor_root(true);
+
+        expect!(archive.extract_with("file", &dest_path, &opts))
+            .to(be_err())
+            .to(equal(Error::FileAlreadyExists { path: dest_path }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_anchor_root_and_conflict_resolver_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        let opts = ExtractOptions::new()
+            .anchor_root(true)
+            .on_conflict(|_, _, _| ConflictAction::Skip);
+
+        expect!(archive.extract_with("file", temp_dir.path().join("file"), &opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `ExtractOptions::secure`
+//
+
+// Create an archive with a directory at `dir` and add a regular file at each of `hostile_paths`,
+// bypassing the checks the API does on paths, like an archive crafted by an attacker.
+fn hostile_archive(db_path: &Path, hostile_paths: &[&str]) -> sqlarfs::Result<Connection> {
+    let mut conn = Connection::create_new(db_path)?;
+
+    conn.exec(|archive| archive.open("dir")?.create_dir())?;
+
+    let raw_conn = rusqlite::Connection::open(db_path)?;
+
+    for path in hostile_paths {
+        raw_conn.execute(
+            "INSERT INTO sqlar (name, mode, sz, data) VALUES (?1, ?2, 0, zeroblob(0))",
+            (path, 0o100644),
+        )?;
+    }
+
+    Ok(conn)
+}
+
+#[test]
+fn extracting_file_with_parent_components_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dest_dir = temp_dir.path().join("dest");
+    fs::create_dir(&dest_dir)?;
+
+    let mut conn = hostile_archive(&temp_dir.path().join("test.sqlar"), &["dir/../../evil"])?;
+
+    conn.exec(|archive| {
+        expect!(archive.extract("dir", dest_dir.join("dir")))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::PathEscapesRoot { .. })));
+
+        expect!(temp_dir.path().join("evil").exists()).to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(unix)]
+fn extracting_file_under_symlink_in_archive_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dest_dir = temp_dir.path().join("dest");
+    let outside_dir = temp_dir.path().join("outside");
+    fs::create_dir(&dest_dir)?;
+    fs::create_dir(&outside_dir)?;
+
+    let mut conn = hostile_archive(&temp_dir.path().join("test.sqlar"), &[])?;
+
+    conn.exec(|archive| archive.open("dir/link")?.create_symlink(&outside_dir))?;
+
+    let raw_conn = rusqlite::Connection::open(temp_dir.path().join("test.sqlar"))?;
+    raw_conn.execute(
+        "INSERT INTO sqlar (name, mode, sz, data) VALUES ('dir/link/file', ?1, 0, zeroblob(0))",
+        (0o100644,),
+    )?;
+
+    conn.exec(|archive| {
+        let opts = ExtractOptions::new().children(true);
+
+        expect!(archive.extract_with("dir", &dest_dir, &opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::PathEscapesRoot { .. })));
+
+        expect!(fs::read_dir(&outside_dir)?.count()).to(equal(0));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(unix)]
+fn extracting_file_under_existing_symlink_in_dest_errors() -> sqlarfs::Result<()> {
+    use std::os::unix::fs::symlink;
+
+    let temp_dir = tempfile::tempdir()?;
+    let dest_dir = temp_dir.path().join("dest");
+    let outside_dir = temp_dir.path().join("outside");
+    fs::create_dir(&dest_dir)?;
+    fs::create_dir(&outside_dir)?;
+
+    symlink(&outside_dir, dest_dir.join("link"))?;
+
+    // The archive has no entry for `link` itself, so nothing conflicts with the symlink.
+    let mut conn = hostile_archive(&temp_dir.path().join("test.sqlar"), &["link/file"])?;
+
+    conn.exec(|archive| {
+        let opts = ExtractOptions::new().children(true);
+
+        expect!(archive.extract_with("", &dest_dir, &opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::PathEscapesRoot { .. })));
+
+        expect!(fs::read_dir(&outside_dir)?.count()).to(equal(0));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(unix)]
+fn extracting_without_secure_follows_existing_symlinks() -> sqlarfs::Result<()> {
+    use std::os::unix::fs::symlink;
+
+    let temp_dir = tempfile::tempdir()?;
+    let dest_dir = temp_dir.path().join("dest");
+    let outside_dir = temp_dir.path().join("outside");
+    fs::create_dir(&dest_dir)?;
+    fs::create_dir(&outside_dir)?;
+
+    symlink(&outside_dir, dest_dir.join("link"))?;
+
+    let mut conn = hostile_archive(&temp_dir.path().join("test.sqlar"), &["link/file"])?;
+
+    conn.exec(|archive| {
+        let opts = ExtractOptions::new().children(true).secure(false);
+
+        expect!(archive.extract_with("", &dest_dir, &opts)).to(be_ok());
+
+        expect!(outside_dir.join("file")).to(be_existing_file());
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(unix)]
+fn extracting_under_symlinked_parent_of_dest_is_allowed() -> sqlarfs::Result<()> {
+    use std::os::unix::fs::symlink;
+
+    let temp_dir = tempfile::tempdir()?;
+    let real_dir = temp_dir.path().join("real");
+    let link_dir = temp_dir.path().join("link");
+    fs::create_dir(&real_dir)?;
+
+    symlink(&real_dir, &link_dir)?;
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/file")?.create_file()?;
+
+        expect!(archive.extract("dir", link_dir.join("dir"))).to(be_ok());
+
+        expect!(real_dir.join("dir/file")).to(be_existing_file());
+
+        Ok(())
+    })
+}
+
+//
+// `ExtractOptions::validate`
+//
+
+#[test]
+fn default_extract_options_are_valid() {
+    expect!(ExtractOptions::new().validate()).to(be_ok());
+}
+
+#[test]
+fn validating_extract_options_returns_every_conflict() {
+    let opts = ExtractOptions::new()
+        .strip_components(1)
+        .recursive(false)
+        .template_vars([("", "value")]);
+
+    expect!(opts.validate())
+        .to(be_err())
+        .map(|conflicts| conflicts.len())
+        .to(equal(3));
+}
+
+#[test]
+fn extracting_with_conflicting_options_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let opts = ExtractOptions::new()
+            .children(true)
+            .recursive(false)
+            .strip_components(1);
+
+        expect!(archive.extract_with("dir", temp_dir.path(), &opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `ExtractOptions::keep_going`
+//
+
+#[test]
+fn extracting_with_keep_going_extracts_the_other_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file2"), "existing")?;
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/file1")?.create_file()?;
+        archive.open("dir/file2")?.create_file()?;
+        archive.open("dir/file3")?.create_file()?;
+
+        let opts = ExtractOptions::new().children(true).keep_going(true);
+
+        let errors = match archive.extract_with("dir", temp_dir.path(), &opts) {
+            Err(Error::Multiple { errors }) => errors,
+            result => panic!("expected multiple errors, got {result:?}"),
+        };
+
+        expect!(errors.len()).to(equal(1));
+        expect!(errors.iter().next().map(|(path, _)| path.as_path()))
+            .to(equal(Some(Path::new("dir/file2"))));
+
+        expect!(temp_dir.path().join("file1")).to(be_regular_file());
+        expect!(temp_dir.path().join("file3")).to(be_regular_file());
+        expect!(fs::read_to_string(temp_dir.path().join("file2"))?).to(equal("existing"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn extracting_with_keep_going_skips_descendants_of_failed_dirs() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("subdir"), "existing")?;
+
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/subdir")?.create_dir()?;
+        archive.open("dir/subdir/file")?.create_file()?;
+        archive.open("dir/other")?.create_file()?;
+
+        let opts = ExtractOptions::new().children(true).keep_going(true);
+
+        let errors = match archive.extract_with("dir", temp_dir.path(), &opts) {
+            Err(Error::Multiple { errors }) => errors,
+            result => panic!("expected multiple errors, got {result:?}"),
+        };
+
+        expect!(errors.len()).to(equal(1));
+        expect!(errors.iter().next().map(|(_, err)| err)).to(equal(Some(
+            &Error::FileAlreadyExists {
+                path: temp_dir.path().join("subdir"),
+            },
+        )));
+
+        expect!(temp_dir.path().join("other")).to(be_regular_file());
+
+        Ok(())
+    })
+}
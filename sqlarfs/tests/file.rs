@@ -1,11 +1,13 @@
+//! Tests for `File`.
+
 mod common;
 
 use std::ffi::OsStr;
 use std::io::prelude::*;
 use std::path::Path;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use sqlarfs::{Compression, Connection, Error, FileMode, FileType};
+use sqlarfs::{Compression, ConditionalRead, Connection, Error, FileMode, FileType};
 use tempfile::NamedTempFile;
 use xpct::{
     be_empty, be_err, be_false, be_ok, be_some, be_true, be_zero, equal, expect, fields,
@@ -446,17 +448,10 @@ fn deleting_file_recursively_deletes_descendants() -> sqlarfs::Result<()> {
         let mut file_b = archive.open("dir/subdir/file_b")?;
         file_b.create_file()?;
 
-        let mut dir = archive.open("dir")?;
         expect!(dir.delete()).to(be_ok());
         expect!(dir.exists()).to(be_ok()).to(be_false());
-
-        let file_a = archive.open("dir/file_a")?;
         expect!(file_a.exists()).to(be_ok()).to(be_false());
-
-        let subdir = archive.open("dir/subdir")?;
         expect!(subdir.exists()).to(be_ok()).to(be_false());
-
-        let file_b = archive.open("dir/subdir/file_b")?;
         expect!(file_b.exists()).to(be_ok()).to(be_false());
 
         Ok(())
@@ -822,6 +817,146 @@ fn open_reader_errors_when_file_is_a_symlink() -> sqlarfs::Result<()> {
     })
 }
 
+//
+// `File::head`
+//
+
+#[test]
+fn head_returns_first_bytes_of_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_compression(Compression::None);
+        file.write_str("Hello, world!")?;
+
+        expect!(file.head(5))
+            .to(be_ok())
+            .to(equal(b"Hello".to_vec()));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn head_returns_first_bytes_of_compressed_file() -> sqlarfs::Result<()> {
+    let contents = "abcd".repeat(1024 * 1024);
+
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_compression(Compression::BEST);
+        file.write_str(&contents)?;
+
+        expect!(file.is_compressed()).to(be_ok()).to(be_true());
+        expect!(file.head(6))
+            .to(be_ok())
+            .to(equal(b"abcdab".to_vec()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn head_returns_whole_file_when_it_is_shorter() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("short")?;
+
+        expect!(file.head(1024))
+            .to(be_ok())
+            .to(equal(b"short".to_vec()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn head_errors_when_file_is_a_directory() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut dir = archive.open("dir")?;
+        dir.create_dir()?;
+
+        expect!(dir.head(1))
+            .to(be_err())
+            .to(equal(Error::NotARegularFile { path: "dir".into() }));
+
+        Ok(())
+    })
+}
+
+//
+// `File::is_probably_text`
+//
+
+#[test]
+fn utf8_file_is_probably_text() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("Hello, wörld!\n")?;
+
+        expect!(file.is_probably_text()).to(be_ok()).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn empty_file_is_probably_text() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        expect!(file.is_probably_text()).to(be_ok()).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn file_with_nul_byte_is_not_text() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_bytes(b"text\0more text")?;
+
+        expect!(file.is_probably_text()).to(be_ok()).to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn file_with_invalid_utf8_is_not_text() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_bytes(b"text\xff\xfe")?;
+
+        expect!(file.is_probably_text()).to(be_ok()).to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn file_cut_off_partway_through_character_is_text() -> sqlarfs::Result<()> {
+    // The multi-byte character straddles the end of the bytes we look at.
+    let contents = format!("{}é", "a".repeat(8 * 1024 - 1));
+
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str(&contents)?;
+
+        expect!(file.is_probably_text()).to(be_ok()).to(be_true());
+
+        Ok(())
+    })
+}
+
 //
 // `File::truncate`
 //
@@ -1053,6 +1188,185 @@ fn write_from_reader_errors_when_file_is_a_symlink() -> sqlarfs::Result<()> {
     })
 }
 
+//
+// `File::writer`
+//
+
+#[test]
+fn writer_errors_when_file_does_not_exist() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+
+        expect!(file.writer().map(drop))
+            .to(be_err())
+            .to(equal(Error::FileNotFound {
+                path: "file".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn writer_errors_when_file_is_a_directory() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut dir = archive.open("dir")?;
+        dir.create_dir()?;
+
+        expect!(dir.writer().map(drop))
+            .to(be_err())
+            .to(equal(Error::NotARegularFile { path: "dir".into() }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn writer_streams_data_into_file() -> sqlarfs::Result<()> {
+    // This is larger than the chunks the writer stages data in.
+    let chunks = (0..64).map(|_| random_bytes(1024 * 4)).collect::<Vec<_>>();
+
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("old contents")?;
+        file.set_compression(Compression::None);
+
+        let mut writer = file.writer()?;
+
+        for chunk in &chunks {
+            writer.write_all(chunk)?;
+        }
+
+        writer.finish()?;
+
+        let mut actual = Vec::new();
+        file.reader()?.read_to_end(&mut actual)?;
+
+        expect!(actual).to(equal(chunks.concat()));
+        expect!(file.is_compressed()).to(be_ok()).to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn writer_compresses_compressible_data() -> sqlarfs::Result<()> {
+    let expected = "a".repeat(1024 * 256);
+
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_compression(Compression::FAST);
+
+        let mut writer = file.writer()?;
+
+        for chunk in expected.as_bytes().chunks(1000) {
+            writer.write_all(chunk)?;
+        }
+
+        writer.finish()?;
+
+        let mut actual = String::new();
+        file.reader()?.read_to_string(&mut actual)?;
+
+        expect!(actual).to(equal(expected.clone()));
+        expect!(file.is_compressed()).to(be_ok()).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn writer_does_not_compress_incompressible_data() -> sqlarfs::Result<()> {
+    let expected = random_bytes(1024 * 256);
+
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_compression(Compression::FAST);
+
+        let mut writer = file.writer()?;
+        writer.write_all(&expected)?;
+        writer.finish()?;
+
+        let mut actual = Vec::new();
+        file.reader()?.read_to_end(&mut actual)?;
+
+        expect!(actual).to(equal(expected.clone()));
+        expect!(file.is_compressed()).to(be_ok()).to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn writer_with_no_data_empties_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("old contents")?;
+
+        file.writer()?.finish()?;
+
+        expect!(file.is_empty()).to(be_ok()).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn dropping_writer_finishes_it() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        let mut writer = file.writer()?;
+        writer.write_all(b"contents")?;
+        drop(writer);
+
+        let mut actual = String::new();
+        file.reader()?.read_to_string(&mut actual)?;
+
+        expect!(actual).to(equal("contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn writers_to_different_files_do_not_interfere() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut first = archive.open("first")?;
+        first.create_file()?;
+
+        let mut second = archive.open("second")?;
+        second.create_file()?;
+
+        let mut first_writer = first.writer()?;
+        let mut second_writer = second.writer()?;
+
+        first_writer.write_all(b"first")?;
+        second_writer.write_all(b"second")?;
+        first_writer.flush()?;
+        second_writer.flush()?;
+
+        first_writer.finish()?;
+        second_writer.finish()?;
+
+        expect!(first.head(100))
+            .to(be_ok())
+            .to(equal(b"first".to_vec()));
+        expect!(second.head(100))
+            .to(be_ok())
+            .to(equal(b"second".to_vec()));
+
+        Ok(())
+    })
+}
+
 //
 // `File::write_file`
 //
@@ -1107,3 +1421,205 @@ fn write_from_file_errors_when_file_is_a_symlink() -> sqlarfs::Result<()> {
         Ok(())
     })
 }
+
+//
+// `File::set_meta` / `File::meta` / `File::delete_meta`
+//
+
+#[test]
+fn set_and_get_file_meta() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        expect!(file.meta("key")).to(be_ok()).to(equal(None));
+
+        file.set_meta("key", "value")?;
+        expect!(file.meta("key"))
+            .to(be_ok())
+            .to(equal(Some(String::from("value"))));
+
+        file.set_meta("key", "new value")?;
+        expect!(file.meta("key"))
+            .to(be_ok())
+            .to(equal(Some(String::from("new value"))));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn set_file_meta_errors_when_file_does_not_exist() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+
+        expect!(file.set_meta("key", "value"))
+            .to(be_err())
+            .to(equal(Error::FileNotFound {
+                path: "file".into(),
+            }));
+
+        expect!(file.meta("key"))
+            .to(be_err())
+            .to(equal(Error::FileNotFound {
+                path: "file".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn delete_file_meta() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        expect!(file.delete_meta("key")).to(be_ok()).to(be_false());
+
+        file.set_meta("key", "value")?;
+
+        expect!(file.delete_meta("key")).to(be_ok()).to(be_true());
+        expect!(file.meta("key")).to(be_ok()).to(equal(None));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn file_meta_is_deleted_with_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_meta("key", "value")?;
+        file.delete()?;
+        file.create_file()?;
+
+        expect!(file.meta("key")).to(be_ok()).to(equal(None));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn file_meta_follows_renamed_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let mut file = archive.open("dir/file")?;
+        file.create_file()?;
+        file.set_meta("key", "value")?;
+        drop(file);
+
+        archive.rename("dir", "new")?;
+
+        expect!(archive.open("new/file")?.meta("key"))
+            .to(be_ok())
+            .to(equal(Some(String::from("value"))));
+
+        Ok(())
+    })
+}
+
+//
+// `File::read_if_none_match` / `File::read_if_modified_since`
+//
+
+#[test]
+fn read_if_none_match_when_etag_matches() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("contents")?;
+
+        let etag = file.etag()?;
+
+        expect!(file.read_if_none_match(&etag))
+            .to(be_ok())
+            .to(match_pattern(pattern!(ConditionalRead::NotModified)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn read_if_none_match_when_etag_does_not_match() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("contents")?;
+
+        let etag = file.etag()?;
+
+        file.write_str("new contents")?;
+
+        let mut contents = String::new();
+
+        match file.read_if_none_match(&etag)? {
+            ConditionalRead::Modified(mut reader) => reader.read_to_string(&mut contents)?,
+            ConditionalRead::NotModified => panic!("expected the file to be modified"),
+        };
+
+        expect!(contents).to(equal("new contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn read_if_none_match_when_contents_change_without_size_or_mtime() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_mtime(Some(UNIX_EPOCH + Duration::from_secs(100)))?;
+        file.write_str("old contents")?;
+
+        let etag = file.etag()?;
+        let weak_etag = file.metadata()?.etag();
+
+        file.write_str("new contents")?;
+
+        expect!(file.metadata()?.etag()).to(equal(weak_etag));
+
+        expect!(file.read_if_none_match(&etag))
+            .to(be_ok())
+            .to(match_pattern(pattern!(ConditionalRead::Modified(_))));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn read_if_modified_since() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mtime = UNIX_EPOCH + Duration::from_secs(100);
+
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_mtime(Some(mtime))?;
+
+        expect!(file.read_if_modified_since(mtime))
+            .to(be_ok())
+            .to(match_pattern(pattern!(ConditionalRead::NotModified)));
+
+        expect!(file.read_if_modified_since(mtime - Duration::from_secs(1)))
+            .to(be_ok())
+            .to(match_pattern(pattern!(ConditionalRead::Modified(_))));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn conditional_read_errors_when_file_is_a_directory() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut dir = archive.open("dir")?;
+        dir.create_dir()?;
+
+        expect!(dir.read_if_modified_since(UNIX_EPOCH))
+            .to(be_err())
+            .to(equal(Error::NotARegularFile { path: "dir".into() }));
+
+        Ok(())
+    })
+}
@@ -0,0 +1,175 @@
+//! Tests for transforming file contents as they're archived and extracted.
+
+mod common;
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use common::connection;
+use sqlarfs::{ArchiveOptions, Error, ExtractOptions, Filter};
+use xpct::{be_err, be_ok, equal, expect, match_pattern, pattern};
+
+fn is_text(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "txt")
+}
+
+fn read_contents(archive: &sqlarfs::Archive, path: &str) -> sqlarfs::Result<String> {
+    let mut contents = String::new();
+    archive
+        .open(path)?
+        .reader()?
+        .read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+//
+// `Archive::register_filter`
+//
+
+#[test]
+fn filter_transforms_matching_files_when_archiving() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file.txt"), "line\r\n")?;
+    fs::write(temp_dir.path().join("file.bin"), "line\r\n")?;
+
+    connection()?.exec(|archive| {
+        archive.register_filter(Filter::new(is_text).on_archive(|contents| {
+            Ok(String::from_utf8_lossy(contents)
+                .replace("\r\n", "\n")
+                .into_bytes())
+        }));
+
+        let opts = ArchiveOptions::new().children(true);
+        archive.archive_with(temp_dir.path(), "", &opts)?;
+
+        expect!(read_contents(archive, "file.txt"))
+            .to(be_ok())
+            .to(equal("line\n"));
+
+        expect!(read_contents(archive, "file.bin"))
+            .to(be_ok())
+            .to(equal("line\r\n"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn filter_transforms_matching_files_when_extracting() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        archive.register_filter(Filter::new(is_text).on_extract(|contents| {
+            Ok(String::from_utf8_lossy(contents)
+                .replace('\n', "\r\n")
+                .into_bytes())
+        }));
+
+        for path in ["file.txt", "file.bin"] {
+            let mut file = archive.open(path)?;
+            file.create_file()?;
+            file.write_str("line\n")?;
+        }
+
+        let opts = ExtractOptions::new().children(true);
+        archive.extract_with("", temp_dir.path(), &opts)?;
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    expect!(fs::read_to_string(temp_dir.path().join("file.txt")))
+        .to(be_ok())
+        .to(equal("line\r\n"));
+
+    expect!(fs::read_to_string(temp_dir.path().join("file.bin")))
+        .to(be_ok())
+        .to(equal("line\n"));
+
+    Ok(())
+}
+
+#[test]
+fn filters_are_applied_in_order_when_archiving_and_reverse_order_when_extracting(
+) -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let dest_dir = temp_dir.path().join("dest");
+
+    fs::create_dir(&src_dir)?;
+    fs::create_dir(&dest_dir)?;
+    fs::write(src_dir.join("file.txt"), "x")?;
+
+    connection()?.exec(|archive| {
+        archive.register_filter(
+            Filter::new(is_text)
+                .on_archive(|contents| Ok([contents, b"a"].concat()))
+                .on_extract(|contents| Ok(contents.strip_suffix(b"a").unwrap().to_vec())),
+        );
+        archive.register_filter(
+            Filter::new(is_text)
+                .on_archive(|contents| Ok([contents, b"b"].concat()))
+                .on_extract(|contents| Ok(contents.strip_suffix(b"b").unwrap().to_vec())),
+        );
+
+        let opts = ArchiveOptions::new().children(true);
+        archive.archive_with(&src_dir, "", &opts)?;
+
+        expect!(read_contents(archive, "file.txt"))
+            .to(be_ok())
+            .to(equal("xab"));
+
+        let opts = ExtractOptions::new().children(true);
+        archive.extract_with("", &dest_dir, &opts)?;
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    expect!(fs::read_to_string(dest_dir.join("file.txt")))
+        .to(be_ok())
+        .to(equal("x"));
+
+    Ok(())
+}
+
+#[test]
+fn filters_do_not_apply_to_files_written_directly() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.register_filter(Filter::new(is_text).on_archive(|_| Ok(Vec::new())));
+
+        let mut file = archive.open("file.txt")?;
+        file.create_file()?;
+        file.write_str("contents")?;
+        drop(file);
+
+        expect!(read_contents(archive, "file.txt"))
+            .to(be_ok())
+            .to(equal("contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn filter_error_stops_archiving() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file.txt"), "contents")?;
+
+    connection()?.exec(|archive| {
+        archive.register_filter(
+            Filter::new(is_text)
+                .on_archive(|_| Err(io::Error::new(io::ErrorKind::InvalidData, "bad data"))),
+        );
+
+        let opts = ArchiveOptions::new().children(true);
+
+        expect!(archive.archive_with(temp_dir.path(), "", &opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::Io {
+                kind: io::ErrorKind::InvalidData,
+                ..
+            })));
+
+        Ok(())
+    })
+}
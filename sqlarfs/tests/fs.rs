@@ -0,0 +1,302 @@
+//! Tests for the `std::fs`-like functions.
+
+mod common;
+
+use std::path::PathBuf;
+
+use sqlarfs::{fs, Error, FileMode};
+use xpct::{be_err, be_false, be_ok, be_true, consist_of, equal, expect, match_pattern, pattern};
+
+use common::connection;
+
+//
+// `fs::read`
+//
+
+#[test]
+fn read_returns_file_contents() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("contents")?;
+        drop(file);
+
+        expect!(fs::read(archive, "file"))
+            .to(be_ok())
+            .to(equal(b"contents".to_vec()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn read_nonexistent_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(fs::read(archive, "file"))
+            .to(be_err())
+            .to(equal(Error::FileNotFound {
+                path: "file".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn read_directory_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        expect!(fs::read(archive, "dir"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::NotARegularFile { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `fs::read_to_string`
+//
+
+#[test]
+fn read_to_string_returns_file_contents() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        fs::write(archive, "file", "contents")?;
+
+        expect!(fs::read_to_string(archive, "file"))
+            .to(be_ok())
+            .to(equal(String::from("contents")));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn read_to_string_with_invalid_utf8_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        fs::write(archive, "file", [0xff, 0xfe])?;
+
+        expect!(fs::read_to_string(archive, "file"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::Io { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `fs::write`
+//
+
+#[test]
+fn write_creates_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.set_umask(FileMode::OTHER_R | FileMode::OTHER_W);
+
+        fs::write(archive, "file", "contents")?;
+
+        let metadata = fs::metadata(archive, "file")?;
+
+        expect!(metadata.is_file()).to(be_true());
+        expect!(metadata.mode()).to(equal(Some(
+            FileMode::OWNER_R | FileMode::OWNER_W | FileMode::GROUP_R | FileMode::GROUP_W,
+        )));
+        expect!(fs::read(archive, "file"))
+            .to(be_ok())
+            .to(equal(b"contents".to_vec()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn write_replaces_existing_contents() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        fs::write(archive, "file", "old contents")?;
+        fs::write(archive, "file", "new")?;
+
+        expect!(fs::read(archive, "file"))
+            .to(be_ok())
+            .to(equal(b"new".to_vec()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn write_without_parent_directory_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(fs::write(archive, "dir/file", "contents"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::NoParentDirectory { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn write_to_directory_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        expect!(fs::write(archive, "dir", "contents"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::NotARegularFile { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `fs::read_dir`
+//
+
+#[test]
+fn read_dir_returns_children() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        fs::create_dir_all(archive, "dir/subdir")?;
+        fs::write(archive, "dir/file", "")?;
+        fs::write(archive, "dir/subdir/file", "")?;
+        fs::write(archive, "other", "")?;
+
+        let paths = fs::read_dir(archive, "dir")?
+            .map(|entry| entry.map(|entry| entry.into_path()))
+            .collect::<sqlarfs::Result<Vec<_>>>()?;
+
+        expect!(paths).to(consist_of([
+            PathBuf::from("dir/file"),
+            PathBuf::from("dir/subdir"),
+        ]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn read_dir_with_empty_path_returns_root() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        fs::create_dir_all(archive, "dir")?;
+        fs::write(archive, "dir/file", "")?;
+        fs::write(archive, "file", "")?;
+
+        let paths = fs::read_dir(archive, "")?
+            .map(|entry| entry.map(|entry| entry.into_path()))
+            .collect::<sqlarfs::Result<Vec<_>>>()?;
+
+        expect!(paths).to(consist_of([PathBuf::from("dir"), PathBuf::from("file")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn read_dir_of_regular_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        fs::write(archive, "file", "")?;
+
+        expect!(fs::read_dir(archive, "file"))
+            .to(be_err())
+            .to(equal(Error::NotADirectory {
+                path: "file".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn read_dir_of_nonexistent_directory_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(fs::read_dir(archive, "dir"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileNotFound { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `fs::create_dir_all`
+//
+
+#[test]
+fn create_dir_all_creates_parents() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        fs::create_dir_all(archive, "a/b/c")?;
+
+        for path in ["a", "a/b", "a/b/c"] {
+            expect!(fs::metadata(archive, path)?.is_dir()).to(be_true());
+        }
+
+        expect!(fs::create_dir_all(archive, "a/b/c")).to(be_ok());
+
+        Ok(())
+    })
+}
+
+//
+// `fs::metadata`
+//
+
+#[test]
+fn metadata_of_nonexistent_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(fs::metadata(archive, "file"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileNotFound { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `fs::remove_file`
+//
+
+#[test]
+fn remove_file_deletes_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        fs::write(archive, "file", "contents")?;
+
+        fs::remove_file(archive, "file")?;
+
+        expect!(archive.open("file")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn remove_file_deletes_symlink() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("link")?.create_symlink("target")?;
+
+        fs::remove_file(archive, "link")?;
+
+        expect!(archive.open("link")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn remove_file_on_directory_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        fs::create_dir_all(archive, "dir")?;
+
+        expect!(fs::remove_file(archive, "dir"))
+            .to(be_err())
+            .to(equal(Error::NotARegularFile { path: "dir".into() }));
+
+        expect!(archive.open("dir")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
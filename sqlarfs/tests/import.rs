@@ -0,0 +1,267 @@
+//! Tests for importing rows from another SQLite database.
+
+mod common;
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use common::connection;
+use sqlarfs::{Compression, Connection, Error, FileMode, ImportOptions};
+use xpct::{be_err, be_ok, equal, expect, match_pattern, pattern};
+
+// Create an empty SQLite database to run queries against.
+fn source_database(dir: &Path) -> sqlarfs::Result<PathBuf> {
+    let path = dir.join("source.db");
+    Connection::create_new(&path)?;
+    Ok(path)
+}
+
+fn read_contents(archive: &mut sqlarfs::Archive, path: &str) -> sqlarfs::Result<String> {
+    let mut contents = String::new();
+    archive
+        .open(path)?
+        .reader()?
+        .read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+//
+// `Archive::import_query`
+//
+
+#[test]
+fn import_rows_with_default_columns() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let source = source_database(temp_dir.path())?;
+
+    connection()?.exec(|archive| {
+        let num_imported = archive.import_query(
+            &source,
+            "SELECT 'first' AS path, 'one' AS contents UNION ALL SELECT 'second', x'74776f'",
+            &ImportOptions::new(),
+        )?;
+
+        expect!(num_imported).to(equal(2));
+        expect!(read_contents(archive, "first"))
+            .to(be_ok())
+            .to(equal("one"));
+        expect!(read_contents(archive, "second"))
+            .to(be_ok())
+            .to(equal("two"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn import_rows_with_custom_columns() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let source = source_database(temp_dir.path())?;
+
+    connection()?.exec(|archive| {
+        let opts = ImportOptions::new()
+            .path_column("name")
+            .contents_column("body")
+            .mtime_column("uploaded")
+            .mode_column("perms");
+
+        archive.import_query(
+            &source,
+            "SELECT 'file' AS name, 'contents' AS body, 60 AS uploaded, 292 AS perms",
+            &opts,
+        )?;
+
+        let metadata = archive.open("file")?.metadata()?;
+
+        expect!(metadata.mtime()).to(equal(Some(UNIX_EPOCH + Duration::from_secs(60))));
+        expect!(metadata.mode()).to(equal(Some(FileMode::from_bits_truncate(0o444))));
+        expect!(read_contents(archive, "file"))
+            .to(be_ok())
+            .to(equal("contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn import_null_mtime_and_mode() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let source = source_database(temp_dir.path())?;
+
+    connection()?.exec(|archive| {
+        let opts = ImportOptions::new()
+            .mtime_column("mtime")
+            .mode_column("mode");
+
+        archive.import_query(
+            &source,
+            "SELECT 'file' AS path, '' AS contents, NULL AS mtime, NULL AS mode",
+            &opts,
+        )?;
+
+        let metadata = archive.open("file")?.metadata()?;
+
+        expect!(metadata.mtime()).to(equal(None));
+        expect!(metadata.mode()).to(equal(None));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn import_null_contents_creates_empty_file() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let source = source_database(temp_dir.path())?;
+
+    connection()?.exec(|archive| {
+        archive.import_query(
+            &source,
+            "SELECT 'file' AS path, NULL AS contents",
+            &ImportOptions::new(),
+        )?;
+
+        expect!(read_contents(archive, "file"))
+            .to(be_ok())
+            .to(equal(""));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn import_creates_parent_directories() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let source = source_database(temp_dir.path())?;
+
+    connection()?.exec(|archive| {
+        archive.import_query(
+            &source,
+            "SELECT 'a/b/file' AS path, 'contents' AS contents",
+            &ImportOptions::new(),
+        )?;
+
+        expect!(archive.open("a/b")?.metadata())
+            .to(be_ok())
+            .map(|metadata| metadata.is_dir())
+            .to(equal(true));
+        expect!(read_contents(archive, "a/b/file"))
+            .to(be_ok())
+            .to(equal("contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn import_from_another_archive() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let source = source_database(temp_dir.path())?;
+
+    Connection::open(&source)?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_compression(Compression::None);
+        file.write_str("contents")
+    })?;
+
+    connection()?.exec(|archive| {
+        let num_imported = archive.import_query(
+            &source,
+            "SELECT name AS path, data AS contents FROM sqlar",
+            &ImportOptions::new(),
+        )?;
+
+        expect!(num_imported).to(equal(1));
+        expect!(read_contents(archive, "file"))
+            .to(be_ok())
+            .to(equal("contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn import_errors_when_column_is_missing() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let source = source_database(temp_dir.path())?;
+
+    connection()?.exec(|archive| {
+        expect!(archive.import_query(&source, "SELECT 'file' AS path", &ImportOptions::new()))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        expect!(archive.import_query(
+            &source,
+            "SELECT 'file' AS path, '' AS contents",
+            &ImportOptions::new().mtime_column("mtime"),
+        ))
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn import_errors_when_column_has_wrong_type() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let source = source_database(temp_dir.path())?;
+
+    connection()?.exec(|archive| {
+        expect!(archive.import_query(
+            &source,
+            "SELECT 1 AS path, '' AS contents",
+            &ImportOptions::new()
+        ))
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        expect!(archive.import_query(
+            &source,
+            "SELECT 'file' AS path, '' AS contents, 'yesterday' AS mtime",
+            &ImportOptions::new().mtime_column("mtime"),
+        ))
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn import_errors_when_file_already_exists() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let source = source_database(temp_dir.path())?;
+
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        expect!(archive.import_query(
+            &source,
+            "SELECT 'file' AS path, '' AS contents",
+            &ImportOptions::new()
+        ))
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::FileAlreadyExists { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn import_errors_when_database_does_not_exist() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|archive| {
+        expect!(archive.import_query(
+            temp_dir.path().join("nonexistent.db"),
+            "SELECT 'file' AS path, '' AS contents",
+            &ImportOptions::new()
+        ))
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::CannotOpen)));
+
+        Ok(())
+    })
+}
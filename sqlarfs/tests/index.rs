@@ -0,0 +1,114 @@
+//! Tests for exporting an index of the files in an archive.
+
+mod common;
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use common::connection;
+use sqlarfs::{FileMode, IndexFormat};
+use xpct::{be_ok, equal, expect};
+
+fn populate(archive: &mut sqlarfs::Archive) -> sqlarfs::Result<()> {
+    let mtime = Some(UNIX_EPOCH + Duration::from_secs(60));
+
+    let mut dir = archive.open("dir")?;
+    dir.create_dir()?;
+    dir.set_mode(Some(FileMode::from_bits_truncate(0o755)))?;
+    dir.set_mtime(mtime)?;
+
+    let mut file = archive.open("dir/file, \"quoted\"")?;
+    file.create_file()?;
+    file.write_str("contents")?;
+    file.set_mode(Some(FileMode::from_bits_truncate(0o644)))?;
+    file.set_mtime(mtime)?;
+
+    let mut symlink = archive.open("symlink")?;
+    symlink.create_symlink("dir")?;
+    symlink.set_mtime(None)?;
+
+    Ok(())
+}
+
+fn export(archive: &mut sqlarfs::Archive, format: IndexFormat) -> sqlarfs::Result<String> {
+    let mut output = Vec::new();
+    archive.export_index(&mut output, format)?;
+    Ok(String::from_utf8(output).unwrap())
+}
+
+//
+// `Archive::export_index`
+//
+
+#[test]
+fn export_empty_archive_as_csv() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(export(archive, IndexFormat::Csv))
+            .to(be_ok())
+            .to(equal("path,type,size,mode,mtime,target\n"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn export_empty_archive_as_json_lines() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(export(archive, IndexFormat::JsonLines))
+            .to(be_ok())
+            .to(equal(""));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn export_index_as_csv() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        populate(archive)?;
+
+        let expected = [
+            "path,type,size,mode,mtime,target",
+            "dir,dir,,493,60,",
+            "\"dir/file, \"\"quoted\"\"\",file,8,420,60,",
+            "symlink,symlink,,511,,dir",
+        ];
+
+        expect!(export(archive, IndexFormat::Csv))
+            .to(be_ok())
+            .to(equal(format!("{}\n", expected.join("\n"))));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn export_index_as_json_lines() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        populate(archive)?;
+
+        let expected = [
+            r#"{"path":"dir","type":"dir","size":null,"mode":493,"mtime":60,"target":null}"#,
+            r#"{"path":"dir/file, \"quoted\"","type":"file","size":8,"mode":420,"mtime":60,"target":null}"#,
+            r#"{"path":"symlink","type":"symlink","size":null,"mode":511,"mtime":null,"target":"dir"}"#,
+        ];
+
+        expect!(export(archive, IndexFormat::JsonLines))
+            .to(be_ok())
+            .to(equal(format!("{}\n", expected.join("\n"))));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn export_index_returns_number_of_entries() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        populate(archive)?;
+
+        expect!(archive.export_index(Vec::new(), IndexFormat::Csv))
+            .to(be_ok())
+            .to(equal(3));
+
+        Ok(())
+    })
+}
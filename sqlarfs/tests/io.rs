@@ -1,11 +1,16 @@
+//! Tests for reading and writing file contents.
+
 mod common;
 
 use std::io::{self, prelude::*};
+use std::time::{Duration, UNIX_EPOCH};
 
 #[cfg(feature = "deflate")]
 use flate2::write::ZlibEncoder;
-use sqlarfs::Compression;
-use xpct::{be_false, be_ge, be_lt, be_ok, be_true, eq_diff, equal, expect};
+use sqlarfs::{Compression, FileMode};
+use xpct::{
+    be_err, be_false, be_ge, be_lt, be_ok, be_true, eq_diff, equal, expect, match_pattern, pattern,
+};
 
 use common::{
     compressible_bytes, connection, have_file_metadata, incompressible_bytes, random_bytes,
@@ -192,6 +197,59 @@ fn write_from_reader_without_compression() -> sqlarfs::Result<()> {
     })
 }
 
+// A reader that returns at most a few bytes at a time.
+struct ShortReader<'a>(&'a [u8]);
+
+impl Read for ShortReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.0.len()).min(1000);
+        buf[..len].copy_from_slice(&self.0[..len]);
+        self.0 = &self.0[len..];
+        Ok(len)
+    }
+}
+
+#[test]
+fn write_large_stream_from_reader_without_compression() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        file.set_compression(Compression::None);
+
+        // Make this span several chunks, with a partial chunk at the end.
+        let expected = (0..300_001).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+
+        file.write_from(&mut ShortReader(&expected))?;
+
+        let mut reader = file.reader()?;
+        let mut actual = Vec::with_capacity(expected.len());
+
+        reader.read_to_end(&mut actual)?;
+
+        expect!(actual == expected).to(be_true());
+
+        drop(reader);
+
+        expect!(file.metadata())
+            .to(be_ok())
+            .to(have_file_metadata())
+            .map(|metadata| metadata.size)
+            .try_into::<usize>()
+            .to(equal(expected.len()));
+
+        // Writing again replaces the contents rather than appending to what's left in the spool.
+        file.write_from(&mut ShortReader(b"hello"))?;
+
+        let mut actual = Vec::new();
+        file.reader()?.read_to_end(&mut actual)?;
+
+        expect!(actual).to(equal(b"hello".to_vec()));
+
+        Ok(())
+    })
+}
+
 #[test]
 #[cfg(feature = "deflate")]
 fn write_incompressible_data_from_reader_with_compression() -> sqlarfs::Result<()> {
@@ -262,6 +320,83 @@ fn write_compressible_data_from_reader_with_compression() -> sqlarfs::Result<()>
     })
 }
 
+// Check that streaming `contents` into a compressed file round-trips, and return whether the file
+// was stored compressed.
+#[cfg(feature = "deflate")]
+fn write_stream_with_compression(contents: &[u8]) -> sqlarfs::Result<bool> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        file.set_compression(Compression::FAST);
+        file.write_from(&mut ShortReader(contents))?;
+
+        let mut actual = Vec::with_capacity(contents.len());
+        file.reader()?.read_to_end(&mut actual)?;
+
+        expect!(actual == contents).to(be_true());
+
+        expect!(file.metadata())
+            .to(be_ok())
+            .to(have_file_metadata())
+            .map(|metadata| metadata.size)
+            .try_into::<usize>()
+            .to(equal(contents.len()));
+
+        file.is_compressed()
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn write_data_that_becomes_compressible_late_from_reader_with_compression() -> sqlarfs::Result<()> {
+    let mut contents = random_bytes(100_000);
+    contents.extend(vec![0u8; 300_000]);
+
+    expect!(write_stream_with_compression(&contents))
+        .to(be_ok())
+        .to(be_true());
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn write_large_compressible_stream_from_reader_with_compression() -> sqlarfs::Result<()> {
+    // This is large enough that the compressed data has to be staged in the database rather than
+    // being kept in memory.
+    let mut contents = random_bytes(6 * 1024 * 1024);
+    contents.extend(vec![0u8; 6 * 1024 * 1024]);
+
+    expect!(write_stream_with_compression(&contents))
+        .to(be_ok())
+        .to(be_true());
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn write_large_incompressible_stream_from_reader_with_compression() -> sqlarfs::Result<()> {
+    expect!(write_stream_with_compression(&random_bytes(
+        6 * 1024 * 1024
+    )))
+    .to(be_ok())
+    .to(be_false());
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn write_empty_stream_from_reader_with_compression() -> sqlarfs::Result<()> {
+    expect!(write_stream_with_compression(&[]))
+        .to(be_ok())
+        .to(be_false());
+
+    Ok(())
+}
+
 //
 // `File::write_file`
 //
@@ -279,6 +414,7 @@ fn write_from_file_without_compression() -> sqlarfs::Result<()> {
         let expected = random_bytes(WRITE_DATA_SIZE);
 
         temp_file.write_all(&expected)?;
+        temp_file.seek(io::SeekFrom::Start(0))?;
 
         file.write_file(&mut temp_file)?;
 
@@ -408,3 +544,393 @@ fn write_string() -> sqlarfs::Result<()> {
         Ok(())
     })
 }
+
+//
+// `File::replace_contents_atomic`
+//
+
+// A reader that returns some bytes and then an error.
+struct FailingReader<'a>(&'a [u8]);
+
+impl Read for FailingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.0.is_empty() {
+            return Err(io::Error::other("read failed"));
+        }
+
+        let len = self.0.len().min(buf.len());
+        buf[..len].copy_from_slice(&self.0[..len]);
+        self.0 = &self.0[len..];
+
+        Ok(len)
+    }
+}
+
+#[test]
+fn replace_contents_atomic_replaces_contents() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("old contents")?;
+
+        let expected = random_bytes(WRITE_DATA_SIZE);
+
+        file.replace_contents_atomic(&mut expected.as_slice())?;
+
+        let mut actual = Vec::new();
+        file.reader()?.read_to_end(&mut actual)?;
+
+        expect!(&actual).to(eq_diff(&expected));
+
+        expect!(file.metadata())
+            .to(be_ok())
+            .to(have_file_metadata())
+            .map(|metadata| metadata.size)
+            .try_into::<usize>()
+            .to(equal(expected.len()));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn replace_contents_atomic_with_compression() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_compression(Compression::FAST);
+
+        let expected = compressible_bytes();
+
+        file.replace_contents_atomic(&mut expected.as_slice())?;
+
+        expect!(file.is_compressed()).to(be_ok()).to(be_true());
+
+        let mut actual = Vec::new();
+        file.reader()?.read_to_end(&mut actual)?;
+
+        expect!(&actual).to(eq_diff(&expected));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn replace_contents_atomic_keeps_old_contents_when_reader_fails() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("old contents")?;
+
+        expect!(file.replace_contents_atomic(&mut FailingReader(b"new contents"))).to(be_err());
+
+        let mut actual = String::new();
+        file.reader()?.read_to_string(&mut actual)?;
+
+        expect!(actual).to(equal("old contents"));
+
+        expect!(file.metadata())
+            .to(be_ok())
+            .to(have_file_metadata())
+            .map(|metadata| metadata.size)
+            .to(equal(12));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn replace_contents_atomic_keeps_metadata() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mtime = UNIX_EPOCH + Duration::from_secs(1000);
+
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_mode(Some(FileMode::OWNER_R))?;
+        file.set_mtime(Some(mtime))?;
+        file.set_meta("key", "value")?;
+
+        file.replace_contents_atomic(&mut b"new contents".as_slice())?;
+
+        expect!(file.metadata())
+            .to(be_ok())
+            .to(have_file_metadata())
+            .map(|metadata| (metadata.mode, metadata.mtime))
+            .to(equal((Some(FileMode::OWNER_R), Some(mtime))));
+
+        expect!(file.meta("key"))
+            .to(be_ok())
+            .to(equal(Some(String::from("value"))));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn replace_contents_atomic_updates_mtime_when_enabled() -> sqlarfs::Result<()> {
+    let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        archive.set_clock(move || now);
+        archive.set_update_mtime(true);
+
+        archive
+            .open("file")?
+            .replace_contents_atomic(&mut b"contents".as_slice())?;
+
+        expect!(archive.open("file")?.metadata()?.mtime()).to(equal(Some(now)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn replace_contents_atomic_errors_when_not_a_regular_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut dir = archive.open("dir")?;
+        dir.create_dir()?;
+
+        expect!(dir.replace_contents_atomic(&mut b"contents".as_slice()))
+            .to(be_err())
+            .to(match_pattern(pattern!(
+                sqlarfs::Error::NotARegularFile { .. }
+            )));
+
+        let mut file = archive.open("nonexistent")?;
+
+        expect!(file.replace_contents_atomic(&mut b"contents".as_slice()))
+            .to(be_err())
+            .to(match_pattern(pattern!(sqlarfs::Error::FileNotFound { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `FileReader`
+//
+
+#[test]
+fn reader_len_is_uncompressed_size() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        expect!(file.reader()?.is_empty()).to(be_true());
+
+        file.write_str("hello world")?;
+
+        let reader = file.reader()?;
+
+        expect!(reader.len()).to(equal(11));
+        expect!(reader.is_empty()).to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn reader_len_of_compressed_file_is_uncompressed_size() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        file.set_compression(Compression::FAST);
+
+        let expected = compressible_bytes();
+        file.write_bytes(&expected)?;
+
+        let mut reader = file.reader()?;
+
+        expect!(reader.len()).to(equal(expected.len() as u64));
+
+        let mut actual = Vec::new();
+        reader.read_to_end(&mut actual)?;
+
+        expect!(&actual).to(eq_diff(&expected));
+        expect!(reader.size_hint()).to(equal((0, Some(0))));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn reader_size_hint_tracks_bytes_read() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("hello world")?;
+
+        let mut reader = file.reader()?;
+
+        expect!(reader.size_hint()).to(equal((11, Some(11))));
+
+        let mut buf = [0u8; 5];
+        reader.read_exact(&mut buf)?;
+
+        expect!(reader.size_hint()).to(equal((6, Some(6))));
+
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest)?;
+
+        expect!(rest.as_str()).to(equal(" world"));
+        expect!(reader.size_hint()).to(equal((0, Some(0))));
+        expect!(reader.len()).to(equal(11));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn reading_to_end_preallocates_buffer() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        let expected = random_bytes(WRITE_DATA_SIZE);
+        file.write_bytes(&expected)?;
+
+        let mut actual = Vec::new();
+        file.reader()?.read_to_end(&mut actual)?;
+
+        expect!(actual.capacity()).to(equal(expected.len()));
+        expect!(&actual).to(eq_diff(&expected));
+
+        Ok(())
+    })
+}
+
+//
+// `Compression::Zstd`
+//
+
+#[test]
+#[cfg(feature = "zstd")]
+fn write_compressible_bytes_with_zstd_compression() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        file.set_compression(Compression::Zstd { level: 3 });
+
+        let expected = compressible_bytes();
+
+        expect!(file.write_bytes(&expected)).to(be_ok());
+
+        let mut actual = Vec::with_capacity(expected.len());
+        file.reader()?.read_to_end(&mut actual)?;
+
+        expect!(&actual).to(eq_diff(&expected));
+        expect!(file.is_compressed()).to(be_ok()).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "zstd")]
+fn write_incompressible_bytes_with_zstd_compression() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        file.set_compression(Compression::Zstd { level: 3 });
+
+        let expected = incompressible_bytes();
+
+        expect!(file.write_bytes(&expected)).to(be_ok());
+
+        let mut actual = Vec::with_capacity(expected.len());
+        file.reader()?.read_to_end(&mut actual)?;
+
+        expect!(&actual).to(eq_diff(&expected));
+        expect!(file.is_compressed()).to(be_ok()).to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "zstd")]
+fn write_compressible_data_from_reader_with_zstd_compression() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        file.set_compression(Compression::Zstd { level: 3 });
+
+        let expected = compressible_bytes();
+
+        file.write_from(&mut expected.as_slice())?;
+
+        let mut actual = Vec::with_capacity(expected.len());
+        file.reader()?.read_to_end(&mut actual)?;
+
+        expect!(&actual).to(eq_diff(&expected));
+        expect!(file.is_compressed()).to(be_ok()).to(be_true());
+
+        expect!(file.metadata())
+            .to(be_ok())
+            .to(have_file_metadata())
+            .map(|metadata| metadata.size)
+            .try_into::<usize>()
+            .to(equal(expected.len()));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "zstd")]
+fn write_compressible_data_through_writer_with_zstd_compression() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        file.set_compression(Compression::Zstd { level: 3 });
+
+        let expected = compressible_bytes();
+
+        let mut writer = file.writer()?;
+        writer.write_all(&expected)?;
+        writer.finish()?;
+
+        let mut actual = Vec::with_capacity(expected.len());
+        file.reader()?.read_to_end(&mut actual)?;
+
+        expect!(&actual).to(eq_diff(&expected));
+        expect!(file.is_compressed()).to(be_ok()).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(not(feature = "zstd"))]
+fn reading_zstd_compressed_file_without_zstd_feature_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let db_path = temp_dir.path().join("archive.sqlar");
+
+    let mut conn = sqlarfs::Connection::create_new(&db_path)?;
+
+    // This is the Zstandard magic number followed by some garbage. The size is larger than the
+    // data, so it looks compressed.
+    rusqlite::Connection::open(&db_path)?.execute(
+        "INSERT INTO sqlar (name, mode, sz, data) VALUES ('file', ?1, 100, x'28b52ffd00010203')",
+        (0o100644,),
+    )?;
+
+    conn.exec(|archive| {
+        expect!(archive.open("file")?.reader())
+            .to(be_err())
+            .to(match_pattern(pattern!(
+                sqlarfs::Error::CompressionNotSupported
+            )));
+
+        Ok(())
+    })
+}
@@ -1,3 +1,5 @@
+//! Tests for listing the files in an archive.
+
 mod common;
 
 use std::collections::HashMap;
@@ -6,8 +8,8 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use sqlarfs::{Error, FileMode, FileType, ListOptions};
 use xpct::{
-    be_empty, be_err, be_gt, be_lt, be_ok, be_some, be_zero, consist_of, contain_element, equal,
-    expect, fields, match_fields, match_pattern, pattern, why,
+    be_empty, be_err, be_gt, be_lt, be_none, be_ok, be_some, be_zero, consist_of, contain_element,
+    equal, expect, fields, match_fields, match_pattern, pattern, why,
 };
 
 use common::{connection, have_file_metadata, truncate_mtime, RegularFileMetadata};
@@ -124,6 +126,11 @@ fn specifying_mutually_exclusive_sort_options_errors() -> sqlarfs::Result<()> {
             .to(be_err())
             .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
 
+        let opts = ListOptions::new().by_depth().by_name_natural();
+        expect!(archive.list_with(&opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
         Ok(())
     })
 }
@@ -384,6 +391,44 @@ fn list_with_filter_descendants_strips_trailing_slash() -> sqlarfs::Result<()> {
     })
 }
 
+#[test]
+fn list_with_sort_by_name_natural() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("file10")?.create_file()?;
+        archive.open("File2")?.create_file()?;
+        archive.open("dir-file")?.create_file()?;
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/file1")?.create_file()?;
+        archive.open("file1")?.create_file()?;
+
+        expect!(archive.list_with(&ListOptions::new().by_name_natural().asc()))
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(equal(&[
+                PathBuf::from("dir"),
+                PathBuf::from("dir/file1"),
+                PathBuf::from("dir-file"),
+                PathBuf::from("file1"),
+                PathBuf::from("File2"),
+                PathBuf::from("file10"),
+            ]));
+
+        expect!(archive.list_with(&ListOptions::new().by_name_natural().desc()))
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(equal(&[
+                PathBuf::from("file10"),
+                PathBuf::from("File2"),
+                PathBuf::from("file1"),
+                PathBuf::from("dir-file"),
+                PathBuf::from("dir/file1"),
+                PathBuf::from("dir"),
+            ]));
+
+        Ok(())
+    })
+}
+
 #[test]
 fn list_with_sort_by_mtime() -> sqlarfs::Result<()> {
     connection()?.exec(|archive| {
@@ -741,3 +786,496 @@ fn list_with_filter_only_symlinks() -> sqlarfs::Result<()> {
         Ok(())
     })
 }
+
+#[test]
+fn list_with_filter_by_glob() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/file.tmp")?.create_file()?;
+        archive.open("file.tmp")?.create_file()?;
+        archive.open("file.txt")?.create_file()?;
+
+        let opts = ListOptions::new().glob("*.tmp");
+
+        expect!(archive.list_with(&opts))
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[
+                PathBuf::from("dir/file.tmp"),
+                PathBuf::from("file.tmp"),
+            ]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn list_with_filter_by_mtime_range() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file1 = archive.open("file1")?;
+        file1.create_file()?;
+        file1.set_mtime(Some(UNIX_EPOCH + Duration::from_secs(1)))?;
+
+        let mut file2 = archive.open("file2")?;
+        file2.create_file()?;
+        file2.set_mtime(Some(UNIX_EPOCH + Duration::from_secs(2)))?;
+
+        let mut file3 = archive.open("file3")?;
+        file3.create_file()?;
+        file3.set_mtime(Some(UNIX_EPOCH + Duration::from_secs(3)))?;
+
+        let mut file4 = archive.open("file4")?;
+        file4.create_file()?;
+        file4.set_mtime(None)?;
+
+        let opts = ListOptions::new()
+            .modified_after(UNIX_EPOCH + Duration::from_secs(2))
+            .modified_before(UNIX_EPOCH + Duration::from_secs(3));
+
+        expect!(archive.list_with(&opts))
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[PathBuf::from("file2")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn list_empty_dirs() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("empty")?.create_dir()?;
+        archive.open("parent")?.create_dir()?;
+        archive.open("parent/empty")?.create_dir()?;
+        archive.open("parent/file")?.create_file()?;
+        archive.open("file")?.create_file()?;
+
+        let opts = ListOptions::new().empty_dirs();
+
+        expect!(archive.list_with(&opts))
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[
+                PathBuf::from("empty"),
+                PathBuf::from("parent/empty"),
+            ]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn specifying_mutually_exclusive_empty_dirs_options_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let opts = ListOptions::new().empty_dirs().by_size();
+        expect!(archive.list_with(&opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        let opts = ListOptions::new().by_size().empty_dirs();
+        expect!(archive.list_with(&opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::delete_matching`
+//
+
+#[test]
+fn delete_matching_by_glob() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/file.tmp")?.create_file()?;
+        archive.open("file.tmp")?.create_file()?;
+        archive.open("file.txt")?.create_file()?;
+
+        expect!(archive.delete_matching(&ListOptions::new().glob("*.tmp")))
+            .to(be_ok())
+            .to(equal(2));
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[
+                PathBuf::from("dir"),
+                PathBuf::from("file.txt"),
+            ]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn delete_matching_by_mtime() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut old_file = archive.open("old")?;
+        old_file.create_file()?;
+        old_file.set_mtime(Some(UNIX_EPOCH + Duration::from_secs(1)))?;
+
+        let mut new_file = archive.open("new")?;
+        new_file.create_file()?;
+        new_file.set_mtime(Some(UNIX_EPOCH + Duration::from_secs(10)))?;
+
+        drop(old_file);
+
+        let opts = ListOptions::new().modified_before(UNIX_EPOCH + Duration::from_secs(5));
+
+        expect!(archive.delete_matching(&opts))
+            .to(be_ok())
+            .to(equal(1));
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[PathBuf::from("new")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn delete_matching_dir_deletes_descendants() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("cache")?.create_dir()?;
+        archive.open("cache/dir")?.create_dir()?;
+        archive.open("cache/dir/file")?.create_file()?;
+        archive.open("file")?.create_file()?;
+
+        let opts = ListOptions::new().glob("cache").file_type(FileType::Dir);
+
+        expect!(archive.delete_matching(&opts))
+            .to(be_ok())
+            .to(equal(3));
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[PathBuf::from("file")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn delete_matching_with_mutually_exclusive_options_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let opts = ListOptions::new().by_mtime().by_size();
+
+        expect!(archive.delete_matching(&opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn delete_matching_with_sort_options_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("file")?.create_file()?;
+
+        for opts in [ListOptions::new().by_size(), ListOptions::new().desc()] {
+            expect!(archive.delete_matching(&opts))
+                .to(be_err())
+                .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+        }
+
+        expect!(archive.list()?.count()).to(equal(2));
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::list_cursor`
+//
+
+fn drain_cursor_paths(
+    archive: &mut sqlarfs::Archive,
+    cursor: &mut sqlarfs::ListCursor,
+) -> sqlarfs::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+
+    while let Some(entry) = cursor.next(archive) {
+        paths.push(entry?.into_path());
+    }
+
+    Ok(paths)
+}
+
+#[test]
+fn list_cursor_returns_files_in_order() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("b")?.create_file()?;
+        archive.open("a")?.create_dir()?;
+        archive.open("a/c")?.create_file()?;
+
+        let mut cursor = archive.list_cursor(&ListOptions::new().by_name_natural())?;
+
+        expect!(cursor.remaining()).to(equal(3));
+
+        let paths = drain_cursor_paths(archive, &mut cursor)?;
+
+        expect!(paths).to(equal(vec![
+            PathBuf::from("a"),
+            PathBuf::from("a/c"),
+            PathBuf::from("b"),
+        ]));
+
+        expect!(cursor.remaining()).to(equal(0));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn list_cursor_does_not_return_files_created_while_iterating() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        for i in 0..10 {
+            archive.open(format!("file{i}"))?.create_file()?;
+        }
+
+        let mut cursor = archive.list_cursor(&ListOptions::new())?;
+        let mut paths = Vec::new();
+
+        while let Some(entry) = cursor.next(archive) {
+            let path = entry?.into_path();
+
+            // Create a new file for every file we see.
+            archive
+                .open(format!("{}-copy", path.to_string_lossy()))?
+                .create_file()?;
+
+            paths.push(path);
+        }
+
+        paths.sort();
+
+        expect!(paths).to(equal(
+            (0..10)
+                .map(|i| PathBuf::from(format!("file{i}")))
+                .collect::<Vec<_>>(),
+        ));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn list_cursor_skips_files_deleted_while_iterating() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        for i in 0..10 {
+            archive.open(format!("file{i}"))?.create_file()?;
+        }
+
+        let mut cursor = archive.list_cursor(&ListOptions::new().by_name_natural())?;
+        let mut paths = Vec::new();
+
+        while let Some(entry) = cursor.next(archive) {
+            let path = entry?.into_path();
+
+            // Delete the next file after each one we see.
+            let index: usize = path.to_string_lossy()["file".len()..].parse().unwrap();
+            archive.open(format!("file{}", index + 1))?.delete().ok();
+
+            paths.push(path);
+        }
+
+        expect!(paths).to(equal(
+            [0, 2, 4, 6, 8]
+                .iter()
+                .map(|i| PathBuf::from(format!("file{i}")))
+                .collect::<Vec<_>>(),
+        ));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn list_cursor_returns_current_metadata() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        let mut cursor = archive.list_cursor(&ListOptions::new())?;
+
+        archive.open("file")?.write_str("contents")?;
+
+        expect!(cursor.next(archive))
+            .to(be_some())
+            .to(be_ok())
+            .map(|entry| entry.metadata().clone())
+            .to(match_pattern(pattern!(sqlarfs::FileMetadata::File {
+                size: 8,
+                ..
+            })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn list_cursor_with_mutually_exclusive_options_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let opts = ListOptions::new().by_size().by_mtime();
+
+        expect!(archive.list_cursor(&opts))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `ListEntry::id`
+//
+
+#[test]
+fn entry_id_is_stable_across_renames() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        let id = archive.list()?.next().unwrap()?.id();
+
+        archive.rename("file", "renamed")?;
+
+        let renamed = archive.list()?.next().unwrap()?;
+
+        expect!(renamed.path()).to(equal(Path::new("renamed")));
+        expect!(renamed.id()).to(equal(id));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn entry_ids_are_unique() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("file1")?.create_file()?;
+        archive.open("file2")?.create_file()?;
+
+        let ids = archive
+            .list()?
+            .map(|entry| entry.map(|entry| entry.id()))
+            .collect::<sqlarfs::Result<Vec<_>>>()?;
+
+        expect!(ids[0]).to_not(equal(ids[1]));
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::open_by_id`
+//
+
+#[test]
+fn open_by_id_opens_the_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("file1")?.create_file()?;
+        archive.open("file2")?.create_file()?;
+
+        let entry = archive
+            .list()?
+            .find(|entry| {
+                entry
+                    .as_ref()
+                    .is_ok_and(|entry| entry.path() == Path::new("file2"))
+            })
+            .unwrap()?;
+
+        let file = archive.open_by_id(entry.id())?;
+
+        expect!(file)
+            .to(be_some())
+            .map(|file| file.path().to_owned())
+            .to(equal(PathBuf::from("file2")));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn open_by_id_returns_none_when_file_was_deleted() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        let id = archive.list()?.next().unwrap()?.id();
+
+        archive.open("file")?.delete()?;
+
+        expect!(archive.open_by_id(id)).to(be_ok()).to(be_none());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn open_by_id_errors_when_file_is_already_open() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        let id = archive.list()?.next().unwrap()?.id();
+
+        let _file = archive.open("file")?;
+
+        expect!(archive.open_by_id(id))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileAlreadyOpen { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `File::id`
+//
+
+#[test]
+fn file_id_matches_list_entry_id() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        let entry = archive.list()?.next().unwrap()?;
+
+        expect!(file.id()).to(be_ok()).to(equal(entry.id()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn file_id_errors_when_file_does_not_exist() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let file = archive.open("file")?;
+
+        expect!(file.id())
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileNotFound { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn file_id_survives_rename_to() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        let id = file.id()?;
+
+        file.rename_to("renamed")?;
+
+        expect!(file.id()).to(be_ok()).to(equal(id));
+
+        Ok(())
+    })
+}
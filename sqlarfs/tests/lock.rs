@@ -0,0 +1,150 @@
+//! Tests for taking out locks on files.
+
+mod common;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use sqlarfs::{Connection, Error};
+use xpct::{be_err, be_ok, equal, expect};
+
+use common::connection;
+
+#[test]
+fn try_locking_a_locked_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let file = archive.open("file")?;
+
+        let _lock = file.try_lock_exclusive()?;
+
+        expect!(file.try_lock_exclusive())
+            .to(be_err())
+            .to(equal(Error::WouldBlock {
+                path: "file".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn locking_a_file_that_was_unlocked_succeeds() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let file = archive.open("file")?;
+
+        let lock = file.try_lock_exclusive()?;
+        drop(lock);
+
+        expect!(file.try_lock_exclusive()).to(be_ok());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn locking_a_different_file_succeeds() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let _lock = archive.open("file1")?.try_lock_exclusive()?;
+
+        expect!(archive.open("file2")?.try_lock_exclusive()).to(be_ok());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn locks_are_not_shared_between_in_memory_databases() -> sqlarfs::Result<()> {
+    let mut first = connection()?;
+    let mut second = connection()?;
+
+    first.exec(|first_archive| {
+        let _lock = first_archive.open("file")?.try_lock_exclusive()?;
+
+        second.exec(|second_archive| {
+            expect!(second_archive.open("file")?.try_lock_exclusive()).to(be_ok());
+
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn locks_are_shared_between_connections_to_the_same_database() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let db_path = temp_dir.path().join("test.sqlar");
+
+    let mut first = Connection::create_new(&db_path)?;
+    let mut second = Connection::open(&db_path)?;
+
+    let mut first_tx = first.transaction()?;
+    let _lock = first_tx.archive_mut().open("file")?.try_lock_exclusive()?;
+
+    second.exec(|archive| {
+        expect!(archive.open("file")?.try_lock_exclusive())
+            .to(be_err())
+            .to(equal(Error::WouldBlock {
+                path: "file".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn locking_with_timeout_errors_when_lock_is_not_released() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let file = archive.open("file")?;
+
+        let _lock = file.try_lock_exclusive()?;
+
+        expect!(file.lock_exclusive_timeout(Duration::from_millis(10)))
+            .to(be_err())
+            .to(equal(Error::WouldBlock {
+                path: "file".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn locking_waits_until_lock_is_released() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let db_path = temp_dir.path().join("test.sqlar");
+
+    Connection::create_new(&db_path)?;
+
+    let (locked_tx, locked_rx) = mpsc::channel();
+
+    let handle = thread::spawn({
+        let db_path = db_path.clone();
+
+        move || -> sqlarfs::Result<()> {
+            let mut conn = Connection::open(&db_path)?;
+            let mut tx = conn.transaction()?;
+            let lock = tx.archive_mut().open("file")?.lock_exclusive()?;
+
+            locked_tx.send(()).unwrap();
+            thread::sleep(Duration::from_millis(50));
+            drop(lock);
+
+            Ok(())
+        }
+    });
+
+    locked_rx.recv().unwrap();
+
+    let mut conn = Connection::open(&db_path)?;
+
+    conn.exec(|archive| {
+        expect!(archive
+            .open("file")?
+            .lock_exclusive_timeout(Duration::from_secs(10)))
+        .to(be_ok());
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    handle.join().unwrap()
+}
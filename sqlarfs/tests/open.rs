@@ -1,10 +1,53 @@
+//! Tests for opening connections to an archive.
+
 mod common;
 
 use std::fs;
 use std::io::prelude::*;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-use sqlarfs::{Connection, Error};
-use xpct::{be_err, be_ok, equal, expect};
+use common::random_bytes;
+use serial_test::serial;
+use sqlarfs::{
+    AutoVacuum, Compression, Connection, ConnectionBuilder, Error, JournalMode, Synchronous,
+    TransactionBehavior,
+};
+use xpct::{
+    be_err, be_false, be_ge, be_gt, be_lt, be_ok, be_true, equal, expect, match_pattern, pattern,
+};
+
+// Read a big-endian integer from the header of the SQLite database at `path`.
+//
+// See https://www.sqlite.org/fileformat.html#the_database_header
+fn read_header_field(path: &Path, offset: usize, len: usize) -> sqlarfs::Result<u32> {
+    let header = fs::read(path)?;
+
+    Ok(header[offset..offset + len]
+        .iter()
+        .fold(0, |acc, &byte| (acc << 8) | u32::from(byte)))
+}
+
+// Create an archive with a large file in it and then delete the file, returning the size of the
+// database file before and after the deletion.
+fn write_then_delete_file(path: &Path, builder: &ConnectionBuilder) -> sqlarfs::Result<(u64, u64)> {
+    let mut conn = builder.create_new(path)?;
+
+    conn.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_compression(Compression::None);
+        file.write_bytes(&random_bytes(1024 * 1024))
+    })?;
+
+    let size_before = fs::metadata(path)?.len();
+
+    conn.exec(|archive| archive.open("file")?.delete())?;
+
+    let size_after = fs::metadata(path)?.len();
+
+    Ok((size_before, size_after))
+}
 
 //
 // `Connection::open`
@@ -100,6 +143,110 @@ fn create_new_archive_errors_when_sqlar_table_already_exists() -> sqlarfs::Resul
     Ok(())
 }
 
+//
+// `Connection::create_atomically`
+//
+
+// The names of the files in `dir`, sorted.
+fn dir_entries(dir: &Path) -> sqlarfs::Result<Vec<String>> {
+    let mut names = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<sqlarfs::Result<Vec<_>>>()?;
+
+    names.sort();
+
+    Ok(names)
+}
+
+#[test]
+fn create_atomically_moves_finished_archive_into_place() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("archive.sqlar");
+
+    let was_visible = Connection::create_atomically(&path, |archive| {
+        archive.open("file")?.create_file()?;
+
+        sqlarfs::Result::Ok(path.exists())
+    })?;
+
+    expect!(was_visible).to(be_false());
+    expect!(dir_entries(temp_dir.path()))
+        .to(be_ok())
+        .to(equal(vec![String::from("archive.sqlar")]));
+
+    Connection::open(&path)?.exec(|archive| {
+        expect!(archive.open("file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn create_atomically_replaces_existing_file() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("archive.sqlar");
+
+    Connection::create_new(&path)?.exec(|archive| archive.open("old")?.create_file())?;
+
+    Connection::create_atomically(&path, |archive| archive.open("new")?.create_file())?;
+
+    Connection::open(&path)?.exec(|archive| {
+        expect!(archive.open("old")?.exists())
+            .to(be_ok())
+            .to(be_false());
+        expect!(archive.open("new")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn create_atomically_leaves_path_alone_when_closure_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("archive.sqlar");
+
+    fs::write(&path, "original")?;
+
+    let result = Connection::create_atomically(&path, |archive| {
+        archive.open("file")?.create_file()?;
+
+        Err::<(), _>(Error::InvalidArgs {
+            reason: String::from("test"),
+        })
+    });
+
+    expect!(result)
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+    expect!(fs::read_to_string(&path))
+        .to(be_ok())
+        .to(equal("original"));
+    expect!(dir_entries(temp_dir.path()))
+        .to(be_ok())
+        .to(equal(vec![String::from("archive.sqlar")]));
+
+    Ok(())
+}
+
+#[test]
+fn create_atomically_errors_when_parent_does_not_exist() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("nonexistent").join("archive.sqlar");
+
+    expect!(Connection::create_atomically(&path, |_| {
+        sqlarfs::Result::Ok(())
+    }))
+    .to(be_err())
+    .to(equal(Error::NoParentDirectory { path }));
+
+    Ok(())
+}
+
 //
 // `Connection::open_readonly`
 //
@@ -151,3 +298,827 @@ fn open_archive_readonly_errors_when_file_is_not_a_db() -> sqlarfs::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn open_archive_readonly_errors_when_db_is_not_an_archive() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("other.db");
+
+    create_other_db(&path)?;
+
+    expect!(Connection::open_readonly(&path))
+        .to(be_err())
+        .to(equal(Error::NotAnArchive {
+            tables: vec![String::from("notes")],
+        }));
+
+    expect!(has_sqlar_table(&path)).to(be_false());
+
+    Ok(())
+}
+
+#[test]
+fn archive_opened_readonly_is_read_only() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    Connection::create_new(&path)?;
+
+    expect!(Connection::open(&path)?.exec(|archive| archive.is_read_only()))
+        .to(be_ok())
+        .to(be_false());
+
+    expect!(Connection::open_readonly(&path)?.exec(|archive| archive.is_read_only()))
+        .to(be_ok())
+        .to(be_true());
+
+    Ok(())
+}
+
+//
+// `ConnectionBuilder::create_sqlar_table`
+//
+
+// Create a SQLite database at `path` that isn't a SQLite archive.
+fn create_other_db(path: &Path) -> sqlarfs::Result<()> {
+    rusqlite::Connection::open(path)?.execute("CREATE TABLE notes (body TEXT)", ())?;
+
+    Ok(())
+}
+
+// Return whether the SQLite database at `path` has a `sqlar` table.
+fn has_sqlar_table(path: &Path) -> bool {
+    rusqlite::Connection::open(path)
+        .and_then(|conn| {
+            conn.query_row(
+                "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = 'sqlar'",
+                (),
+                |row| row.get::<_, i64>(0),
+            )
+        })
+        .is_ok_and(|count| count > 0)
+}
+
+#[test]
+fn open_creates_sqlar_table_by_default() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("other.db");
+
+    create_other_db(&path)?;
+
+    expect!(Connection::open(&path)).to(be_ok());
+    expect!(has_sqlar_table(&path)).to(be_true());
+
+    Ok(())
+}
+
+#[test]
+fn open_without_creating_sqlar_table_errors_when_it_does_not_exist() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("other.db");
+
+    create_other_db(&path)?;
+
+    let builder = ConnectionBuilder::new().create_sqlar_table(false);
+
+    let expected = Error::NotAnArchive {
+        tables: vec![String::from("notes")],
+    };
+
+    expect!(builder.open(&path))
+        .to(be_err())
+        .to(equal(expected.clone()));
+    expect!(builder.open_readonly(&path))
+        .to(be_err())
+        .to(equal(expected));
+
+    expect!(has_sqlar_table(&path)).to(be_false());
+
+    Ok(())
+}
+
+#[test]
+fn open_without_creating_sqlar_table_succeeds_when_it_exists() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    Connection::create_new(&path)?;
+
+    let builder = ConnectionBuilder::new().create_sqlar_table(false);
+
+    expect!(builder.open(&path)).to(be_ok());
+    expect!(builder.open_readonly(&path)).to(be_ok());
+
+    Ok(())
+}
+
+#[test]
+fn not_an_archive_error_lists_existing_tables() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("other.db");
+
+    create_other_db(&path)?;
+
+    let err = ConnectionBuilder::new()
+        .create_sqlar_table(false)
+        .open(&path)
+        .unwrap_err();
+
+    expect!(err.to_string()).to(equal(String::from(
+        "This SQLite database is not a SQLite archive because it has no `sqlar` table. The database has these tables: notes",
+    )));
+
+    Ok(())
+}
+
+#[test]
+fn transaction_errors_when_sqlar_table_is_dropped() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    Connection::create_new(&path)?;
+
+    let mut conn = ConnectionBuilder::new()
+        .create_sqlar_table(false)
+        .open(&path)?;
+
+    rusqlite::Connection::open(&path)?.execute("DROP TABLE sqlar", ())?;
+
+    expect!(conn.exec(|archive| archive.open("file")?.create_file()))
+        .to(be_err())
+        .to(equal(Error::NotAnArchive { tables: Vec::new() }));
+
+    Ok(())
+}
+
+#[test]
+fn create_ignores_create_sqlar_table() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    expect!(ConnectionBuilder::new()
+        .create_sqlar_table(false)
+        .create(&path))
+    .to(be_ok());
+
+    expect!(has_sqlar_table(&path)).to(be_true());
+
+    Ok(())
+}
+
+//
+// `Connection::is_archive`
+//
+
+#[test]
+fn new_archive_is_an_archive() -> sqlarfs::Result<()> {
+    expect!(Connection::open_in_memory()?.is_archive())
+        .to(be_ok())
+        .to(be_true());
+
+    Ok(())
+}
+
+#[test]
+fn database_without_sqlar_table_is_not_an_archive() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    let conn = Connection::create_new(&path)?;
+
+    rusqlite::Connection::open(&path)?.execute("DROP TABLE sqlar", ())?;
+
+    expect!(conn.is_archive()).to(be_ok()).to(be_false());
+
+    Ok(())
+}
+
+//
+// `ConnectionBuilder::page_size`
+//
+
+#[test]
+fn create_archive_with_page_size() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    ConnectionBuilder::new().page_size(8192).create_new(&path)?;
+
+    expect!(read_header_field(&path, 16, 2))
+        .to(be_ok())
+        .to(equal(8192));
+
+    Ok(())
+}
+
+#[test]
+fn page_size_is_ignored_when_archive_already_exists() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    ConnectionBuilder::new().page_size(8192).create_new(&path)?;
+    ConnectionBuilder::new().page_size(1024).open(&path)?;
+
+    expect!(read_header_field(&path, 16, 2))
+        .to(be_ok())
+        .to(equal(8192));
+
+    Ok(())
+}
+
+#[test]
+fn invalid_page_size_errors() -> sqlarfs::Result<()> {
+    for page_size in [0, 256, 1000, 131072] {
+        expect!(ConnectionBuilder::new()
+            .page_size(page_size)
+            .open_in_memory())
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+    }
+
+    Ok(())
+}
+
+//
+// `ConnectionBuilder::auto_vacuum`
+//
+
+#[test]
+fn create_archive_without_auto_vacuum() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    let (size_before, size_after) = write_then_delete_file(
+        &path,
+        &ConnectionBuilder::new().auto_vacuum(AutoVacuum::None),
+    )?;
+
+    expect!(read_header_field(&path, 52, 4))
+        .to(be_ok())
+        .to(equal(0));
+    expect!(size_after).to(equal(size_before));
+
+    Ok(())
+}
+
+#[test]
+fn create_archive_with_full_auto_vacuum() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    let (size_before, size_after) = write_then_delete_file(
+        &path,
+        &ConnectionBuilder::new().auto_vacuum(AutoVacuum::Full),
+    )?;
+
+    expect!(read_header_field(&path, 52, 4))
+        .to(be_ok())
+        .to(be_gt(0));
+    expect!(read_header_field(&path, 64, 4))
+        .to(be_ok())
+        .to(equal(0));
+    expect!(size_after).to(be_lt(size_before));
+
+    Ok(())
+}
+
+//
+// `Connection::incremental_vacuum`
+//
+
+#[test]
+fn incremental_vacuum_shrinks_archive() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    let builder = ConnectionBuilder::new().auto_vacuum(AutoVacuum::Incremental);
+    let (size_before, size_after) = write_then_delete_file(&path, &builder)?;
+
+    expect!(read_header_field(&path, 64, 4))
+        .to(be_ok())
+        .to(equal(1));
+    expect!(size_after).to(equal(size_before));
+
+    let mut conn = Connection::open(&path)?;
+
+    conn.incremental_vacuum(Some(1))?;
+
+    let size_after_one_page = fs::metadata(&path)?.len();
+
+    expect!(size_after_one_page).to(be_lt(size_before));
+
+    conn.incremental_vacuum(None)?;
+
+    expect!(fs::metadata(&path)?.len()).to(be_lt(size_after_one_page));
+
+    Ok(())
+}
+
+//
+// `ConnectionBuilder::soft_heap_limit`
+//
+
+#[test]
+#[serial]
+fn soft_heap_limit_is_applied() -> sqlarfs::Result<()> {
+    let conn = ConnectionBuilder::new()
+        .soft_heap_limit(32 * 1024 * 1024)
+        .open_in_memory()?;
+
+    expect!(conn.memory_stats())
+        .to(be_ok())
+        .map(|stats| stats.soft_heap_limit())
+        .to(equal(Some(32 * 1024 * 1024)));
+
+    // The limit is process-wide, so reset it for the other tests.
+    let conn = ConnectionBuilder::new()
+        .soft_heap_limit(0)
+        .open_in_memory()?;
+
+    expect!(conn.memory_stats())
+        .to(be_ok())
+        .map(|stats| stats.soft_heap_limit())
+        .to(equal(None));
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn soft_heap_limit_is_applied_to_readonly_connections() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    Connection::create_new(&path)?;
+
+    let conn = ConnectionBuilder::new()
+        .soft_heap_limit(32 * 1024 * 1024)
+        .open_readonly(&path)?;
+
+    expect!(conn.memory_stats())
+        .to(be_ok())
+        .map(|stats| stats.soft_heap_limit())
+        .to(equal(Some(32 * 1024 * 1024)));
+
+    ConnectionBuilder::new()
+        .soft_heap_limit(0)
+        .open_in_memory()?;
+
+    Ok(())
+}
+
+#[test]
+fn soft_heap_limit_that_is_too_large_errors() {
+    expect!(ConnectionBuilder::new()
+        .soft_heap_limit(u64::MAX)
+        .open_in_memory())
+    .to(be_err())
+    .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+}
+
+//
+// `Connection::memory_stats`
+//
+
+#[test]
+fn memory_stats_reports_cache_size() -> sqlarfs::Result<()> {
+    let conn = Connection::open_in_memory()?;
+
+    // The SQLite default is 2000 KiB.
+    expect!(conn.memory_stats())
+        .to(be_ok())
+        .map(|stats| stats.cache_size())
+        .to(equal(2000 * 1024));
+
+    Ok(())
+}
+
+//
+// `ConnectionBuilder::busy_timeout`
+//
+
+#[test]
+fn busy_timeout_waits_before_erroring() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    let mut holder = Connection::create_new(&path)?;
+    let mut waiter = ConnectionBuilder::new()
+        .busy_timeout(Duration::from_millis(200))
+        .open(&path)?;
+
+    let tx = holder.transaction_with(TransactionBehavior::Immediate)?;
+
+    let start = Instant::now();
+    let result = waiter.exec_with(TransactionBehavior::Immediate, |_| sqlarfs::Result::Ok(()));
+    let elapsed = start.elapsed();
+
+    tx.rollback()?;
+
+    expect!(result)
+        .to(be_err())
+        .map(|err| err.is_retryable())
+        .to(be_true());
+    expect!(elapsed).to(be_ge(Duration::from_millis(200)));
+
+    Ok(())
+}
+
+#[test]
+fn busy_timeout_that_is_too_large_errors() {
+    expect!(ConnectionBuilder::new()
+        .busy_timeout(Duration::MAX)
+        .open_in_memory())
+    .to(be_err())
+    .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+}
+
+//
+// `ConnectionBuilder::journal_mode`
+//
+
+// Return the journal mode of the SQLite database at `path`.
+fn journal_mode(path: &Path) -> sqlarfs::Result<String> {
+    Ok(rusqlite::Connection::open(path)?
+        .pragma_query_value(None, "journal_mode", |row| row.get(0))?)
+}
+
+#[test]
+fn create_archive_with_wal_journal_mode() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    ConnectionBuilder::new()
+        .journal_mode(JournalMode::Wal)
+        .create_new(&path)?;
+
+    expect!(journal_mode(&path)).to(be_ok()).to(equal("wal"));
+
+    Ok(())
+}
+
+#[test]
+fn journal_mode_is_ignored_by_readonly_connections() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    Connection::create_new(&path)?;
+
+    expect!(ConnectionBuilder::new()
+        .journal_mode(JournalMode::Wal)
+        .open_readonly(&path))
+    .to(be_ok());
+
+    expect!(journal_mode(&path)).to(be_ok()).to(equal("delete"));
+
+    Ok(())
+}
+
+#[test]
+fn wal_journal_mode_in_memory_is_ignored() {
+    expect!(ConnectionBuilder::new()
+        .journal_mode(JournalMode::Wal)
+        .synchronous(Synchronous::Normal)
+        .open_in_memory())
+    .to(be_ok());
+}
+
+//
+// `ConnectionBuilder::cache_size`
+//
+
+#[test]
+fn cache_size_is_applied() -> sqlarfs::Result<()> {
+    let conn = ConnectionBuilder::new()
+        .cache_size(8 * 1024 * 1024)
+        .open_in_memory()?;
+
+    expect!(conn.memory_stats())
+        .to(be_ok())
+        .map(|stats| stats.cache_size())
+        .to(equal(8 * 1024 * 1024));
+
+    Ok(())
+}
+
+//
+// `ConnectionBuilder::mmap_size`
+//
+
+#[test]
+fn mmap_size_that_is_too_large_errors() {
+    expect!(ConnectionBuilder::new()
+        .mmap_size(u64::MAX)
+        .open_in_memory())
+    .to(be_err())
+    .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+}
+
+#[test]
+fn archive_with_mmap_size_can_be_read() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    Connection::create_new(&path)?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("contents")
+    })?;
+
+    let mut conn = ConnectionBuilder::new()
+        .mmap_size(64 * 1024 * 1024)
+        .open(&path)?;
+
+    let contents = conn.exec(|archive| {
+        let mut contents = String::new();
+        archive
+            .open("file")?
+            .reader()?
+            .read_to_string(&mut contents)?;
+        sqlarfs::Result::Ok(contents)
+    })?;
+
+    expect!(contents).to(equal("contents"));
+
+    Ok(())
+}
+
+//
+// `ConnectionBuilder::table_name`
+//
+
+#[test]
+fn archives_in_different_tables_are_separate() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    ConnectionBuilder::new()
+        .table_name("photos")
+        .create_new(&path)?
+        .exec(|archive| archive.open("file")?.create_file())?;
+
+    let mut conn = ConnectionBuilder::new().table_name("videos").open(&path)?;
+
+    conn.exec(|archive| {
+        expect!(archive.open("file")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    let mut conn = ConnectionBuilder::new().table_name("photos").open(&path)?;
+
+    conn.exec(|archive| {
+        expect!(archive.open("file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    expect!(Connection::open(&path)?.is_archive())
+        .to(be_ok())
+        .to(be_true());
+
+    Ok(())
+}
+
+#[test]
+fn opening_with_invalid_table_name_errors() -> sqlarfs::Result<()> {
+    for name in ["", "1files", "my files", "sqlite_files", "files;"] {
+        expect!(ConnectionBuilder::new().table_name(name).open_in_memory())
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn opening_with_keyword_table_name_errors() -> sqlarfs::Result<()> {
+    for name in ["order", "GROUP", "Table"] {
+        expect!(ConnectionBuilder::new().table_name(name).open_in_memory())
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn table_named_after_a_column_works() -> sqlarfs::Result<()> {
+    let mut conn = ConnectionBuilder::new()
+        .table_name("name")
+        .open_in_memory()?;
+
+    conn.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        for path in ["dir/file", "dir/copy"] {
+            let mut file = archive.open(path)?;
+            file.create_file()?;
+            file.write_str("contents")?;
+            file.set_meta("name", "value")?;
+        }
+
+        archive.deduplicate()?;
+
+        let mut contents = String::new();
+        archive
+            .open("dir/copy")?
+            .reader()?
+            .read_to_string(&mut contents)?;
+
+        expect!(contents).to(equal("contents"));
+        expect!(archive.open("dir/file")?.meta("name"))
+            .to(be_ok())
+            .to(equal(Some(String::from("value"))));
+
+        archive.open("dir")?.delete()?;
+
+        expect!(archive.list()?.count()).to(equal(0));
+
+        sqlarfs::Result::Ok(())
+    })
+}
+
+#[test]
+fn opening_readonly_without_table_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    Connection::create_new(&path)?;
+
+    expect!(ConnectionBuilder::new()
+        .table_name("photos")
+        .open_readonly(&path))
+    .to(be_err())
+    .to(match_pattern(pattern!(Error::NotAnArchive { .. })));
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_read_uses_same_table() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    let mut conn = ConnectionBuilder::new()
+        .table_name("photos")
+        .create_new(&path)?;
+
+    conn.exec(|archive| archive.open("file")?.create_file())?;
+
+    conn.exec(|archive| {
+        let exists = archive.snapshot_read(|snapshot| snapshot.open("file")?.exists())?;
+
+        expect!(exists).to(be_true());
+
+        sqlarfs::Result::Ok(())
+    })
+}
+
+//
+// `Connection::rename_table`
+//
+
+#[test]
+fn renaming_table_preserves_files_and_their_data() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    let mut conn = Connection::create_new(&path)?;
+
+    conn.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.write_str("contents")?;
+        file.set_meta("key", "value")?;
+        file.pin()
+    })?;
+
+    conn.rename_table("photos")?;
+
+    expect!(conn.table_name()).to(equal("photos"));
+    expect!(Connection::open_readonly(&path)).to(be_err());
+
+    let mut conn = ConnectionBuilder::new().table_name("photos").open(&path)?;
+
+    conn.exec(|archive| {
+        let mut file = archive.open("file")?;
+
+        let mut contents = String::new();
+        file.reader()?.read_to_string(&mut contents)?;
+
+        expect!(contents).to(equal("contents"));
+        expect!(file.meta("key"))
+            .to(be_ok())
+            .to(equal(Some(String::from("value"))));
+        expect!(file.is_pinned()).to(be_ok()).to(be_true());
+
+        sqlarfs::Result::Ok(())
+    })
+}
+
+#[test]
+fn renaming_table_to_a_column_name_preserves_files() -> sqlarfs::Result<()> {
+    let mut conn = Connection::open_in_memory()?;
+
+    conn.exec(|archive| {
+        archive.set_track_modified(true)?;
+
+        for path in ["file", "copy"] {
+            let mut file = archive.open(path)?;
+            file.create_file()?;
+            file.write_str("contents")?;
+        }
+
+        archive.deduplicate()?;
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    conn.rename_table("name")?;
+
+    conn.exec(|archive| {
+        let generation = archive.generation()?;
+
+        let mut contents = String::new();
+        archive
+            .open("copy")?
+            .reader()?
+            .read_to_string(&mut contents)?;
+
+        expect!(contents).to(equal("contents"));
+
+        archive.open("file")?.write_str("new contents")?;
+
+        expect!(archive.generation()?).to_not(equal(generation));
+
+        let mut contents = String::new();
+        archive
+            .open("copy")?
+            .reader()?
+            .read_to_string(&mut contents)?;
+
+        expect!(contents).to(equal("contents"));
+
+        sqlarfs::Result::Ok(())
+    })
+}
+
+#[test]
+fn renaming_table_back_restores_sqlar_table() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    let mut conn = ConnectionBuilder::new()
+        .table_name("photos")
+        .create_new(&path)?;
+
+    conn.exec(|archive| archive.open("file")?.create_file())?;
+
+    conn.rename_table("sqlar")?;
+
+    Connection::open_readonly(&path)?.exec(|archive| {
+        expect!(archive.open("file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        sqlarfs::Result::Ok(())
+    })
+}
+
+#[test]
+fn renaming_table_to_existing_table_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("test.sqlar");
+
+    ConnectionBuilder::new()
+        .table_name("photos")
+        .create_new(&path)?;
+
+    let mut conn = Connection::open(&path)?;
+
+    expect!(conn.rename_table("photos"))
+        .to(be_err())
+        .to(equal(Error::SqlarAlreadyExists));
+
+    expect!(conn.table_name()).to(equal("sqlar"));
+
+    Ok(())
+}
+
+#[test]
+fn renaming_table_to_invalid_name_errors() -> sqlarfs::Result<()> {
+    let mut conn = Connection::open_in_memory()?;
+
+    expect!(conn.rename_table("my files"))
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+    expect!(conn.is_archive()).to(be_ok()).to(be_true());
+
+    Ok(())
+}
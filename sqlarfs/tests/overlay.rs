@@ -0,0 +1,276 @@
+//! Tests for layering archives on top of each other.
+
+mod common;
+
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+
+use sqlarfs::{Archive, Error, ListEntry, ListOptions};
+use xpct::{
+    be_directory, be_err, be_false, be_ok, be_regular_file, be_true, consist_of, equal, expect,
+    match_pattern, pattern,
+};
+
+use common::connection;
+
+fn write_file(archive: &mut Archive, path: &str, contents: &str) -> sqlarfs::Result<()> {
+    let mut file = archive.open(path)?;
+    file.create_file()?;
+    file.write_str(contents)
+}
+
+//
+// `Overlay::reader`
+//
+
+#[test]
+fn upper_layer_shadows_lower_layer() -> sqlarfs::Result<()> {
+    connection()?.exec(|lower| {
+        connection()?.exec(|upper| {
+            write_file(lower, "file", "lower")?;
+            write_file(upper, "file", "upper")?;
+
+            let overlay = upper.overlay(lower);
+
+            let mut contents = String::new();
+            overlay.reader("file")?.read_to_string(&mut contents)?;
+
+            expect!(contents.as_str()).to(equal("upper"));
+
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn files_only_in_lower_layer_are_visible() -> sqlarfs::Result<()> {
+    connection()?.exec(|lower| {
+        connection()?.exec(|upper| {
+            write_file(lower, "file", "lower")?;
+
+            let overlay = upper.overlay(lower);
+
+            let mut contents = String::new();
+            overlay.reader("file")?.read_to_string(&mut contents)?;
+
+            expect!(contents.as_str()).to(equal("lower"));
+
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn reading_a_directory_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|lower| {
+        connection()?.exec(|upper| {
+            lower.open("dir")?.create_dir()?;
+
+            let overlay = upper.overlay(lower);
+
+            expect!(overlay.reader("dir"))
+                .to(be_err())
+                .to(equal(Error::NotARegularFile { path: "dir".into() }));
+
+            Ok(())
+        })
+    })
+}
+
+//
+// `Overlay::metadata`
+//
+
+#[test]
+fn file_in_upper_layer_hides_directory_in_lower_layer() -> sqlarfs::Result<()> {
+    connection()?.exec(|lower| {
+        connection()?.exec(|upper| {
+            lower.open("dir")?.create_dir()?;
+            write_file(lower, "dir/file", "lower")?;
+            upper.open("dir")?.create_file()?;
+
+            let overlay = upper.overlay(lower);
+
+            expect!(overlay.metadata("dir"))
+                .to(be_ok())
+                .map(|metadata| metadata.is_file())
+                .to(be_true());
+
+            expect!(overlay.exists("dir/file"))
+                .to(be_ok())
+                .to(be_false());
+
+            expect!(overlay.metadata("dir/file"))
+                .to(be_err())
+                .to(equal(Error::FileNotFound {
+                    path: "dir/file".into(),
+                }));
+
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn layers_below_the_first_two_are_visible() -> sqlarfs::Result<()> {
+    connection()?.exec(|bottom| {
+        connection()?.exec(|middle| {
+            connection()?.exec(|top| {
+                bottom.open("file")?.create_file()?;
+
+                let overlay = top.overlay(middle).layer(bottom);
+
+                expect!(overlay.exists("file")).to(be_ok()).to(be_true());
+
+                Ok(())
+            })
+        })
+    })
+}
+
+//
+// `Overlay::list_with`
+//
+
+#[test]
+fn list_merges_layers() -> sqlarfs::Result<()> {
+    connection()?.exec(|lower| {
+        connection()?.exec(|upper| {
+            lower.open("dir")?.create_dir()?;
+            write_file(lower, "dir/lower", "lower")?;
+            write_file(lower, "shared", "lower")?;
+            lower.open("hidden")?.create_dir()?;
+            write_file(lower, "hidden/file", "lower")?;
+
+            upper.open("dir")?.create_dir()?;
+            write_file(upper, "dir/upper", "upper")?;
+            write_file(upper, "shared", "upper")?;
+            upper.open("hidden")?.create_file()?;
+
+            let mut overlay = upper.overlay(lower);
+
+            expect!(overlay.list())
+                .to(be_ok())
+                .map(|entries| {
+                    entries
+                        .into_iter()
+                        .map(ListEntry::into_path)
+                        .collect::<Vec<_>>()
+                })
+                .to(consist_of(&[
+                    PathBuf::from("dir"),
+                    PathBuf::from("dir/lower"),
+                    PathBuf::from("dir/upper"),
+                    PathBuf::from("shared"),
+                    PathBuf::from("hidden"),
+                ]));
+
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn list_with_sorts_across_layers() -> sqlarfs::Result<()> {
+    connection()?.exec(|lower| {
+        connection()?.exec(|upper| {
+            lower.open("file1")?.create_file()?;
+            lower.open("file10")?.create_file()?;
+            upper.open("file2")?.create_file()?;
+
+            let mut overlay = upper.overlay(lower);
+
+            expect!(overlay.list_with(&ListOptions::new().by_name_natural().desc()))
+                .to(be_ok())
+                .map(|entries| {
+                    entries
+                        .into_iter()
+                        .map(ListEntry::into_path)
+                        .collect::<Vec<_>>()
+                })
+                .to(equal(vec![
+                    PathBuf::from("file10"),
+                    PathBuf::from("file2"),
+                    PathBuf::from("file1"),
+                ]));
+
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn list_with_invalid_options_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|lower| {
+        connection()?.exec(|upper| {
+            let mut overlay = upper.overlay(lower);
+
+            expect!(overlay.list_with(&ListOptions::new().by_size().by_mtime()))
+                .to(be_err())
+                .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+            Ok(())
+        })
+    })
+}
+
+//
+// `Overlay::extract_with`
+//
+
+#[test]
+fn extract_applies_upper_layer_over_lower_layer() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dest = temp_dir.path().join("dest");
+
+    connection()?.exec(|lower| {
+        connection()?.exec(|upper| {
+            lower.open("dir")?.create_dir()?;
+            write_file(lower, "dir/shared", "lower")?;
+            write_file(lower, "dir/lower", "lower")?;
+            lower.open("dir/replaced")?.create_dir()?;
+            write_file(lower, "dir/replaced/file", "lower")?;
+
+            upper.open("dir")?.create_dir()?;
+            write_file(upper, "dir/shared", "upper")?;
+            write_file(upper, "dir/replaced", "upper")?;
+
+            let mut overlay = upper.overlay(lower);
+
+            expect!(overlay.extract("dir", &dest)).to(be_ok());
+
+            expect!(fs::read_to_string(dest.join("shared")))
+                .to(be_ok())
+                .to(equal("upper"));
+
+            expect!(fs::read_to_string(dest.join("lower")))
+                .to(be_ok())
+                .to(equal("lower"));
+
+            expect!(dest.join("replaced")).to(be_regular_file());
+            expect!(&dest).to(be_directory());
+
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn extract_when_source_is_in_no_layer_errors() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    connection()?.exec(|lower| {
+        connection()?.exec(|upper| {
+            let mut overlay = upper.overlay(lower);
+
+            expect!(overlay.extract("nonexistent", temp_dir.path().join("dest")))
+                .to(be_err())
+                .to(equal(Error::FileNotFound {
+                    path: "nonexistent".into(),
+                }));
+
+            Ok(())
+        })
+    })
+}
@@ -0,0 +1,326 @@
+//! Tests for pinning files to protect them from deletion.
+
+mod common;
+
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+use sqlarfs::{Error, ListOptions, RenamePolicy, RetentionPolicy};
+use xpct::{be_err, be_false, be_ok, be_true, consist_of, equal, expect};
+
+use common::connection;
+
+//
+// `File::pin`
+//
+
+#[test]
+fn pinning_a_nonexistent_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(archive.open("file")?.pin())
+            .to(be_err())
+            .to(equal(Error::FileNotFound {
+                path: "file".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn pinned_file_is_pinned() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        expect!(file.is_pinned()).to(be_ok()).to(be_false());
+
+        file.pin()?;
+
+        expect!(file.is_pinned()).to(be_ok()).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn pinning_a_file_twice_succeeds() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        file.pin()?;
+
+        expect!(file.pin()).to(be_ok());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn pin_follows_renamed_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("old")?;
+        file.create_file()?;
+        file.pin()?;
+        drop(file);
+
+        archive.rename("old", "new")?;
+
+        expect!(archive.open("new")?.is_pinned())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+//
+// `File::unpin`
+//
+
+#[test]
+fn unpinning_a_file_returns_whether_it_was_pinned() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        expect!(file.unpin()).to(be_ok()).to(be_false());
+
+        file.pin()?;
+
+        expect!(file.unpin()).to(be_ok()).to(be_true());
+        expect!(file.is_pinned()).to(be_ok()).to(be_false());
+
+        Ok(())
+    })
+}
+
+//
+// `File::delete`
+//
+
+#[test]
+fn deleting_a_pinned_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.pin()?;
+
+        expect!(file.delete())
+            .to(be_err())
+            .to(equal(Error::FilePinned {
+                path: "file".into(),
+            }));
+
+        expect!(file.exists()).to(be_ok()).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn deleting_a_directory_with_a_pinned_descendant_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let mut file = archive.open("dir/file")?;
+        file.create_file()?;
+        file.pin()?;
+
+        expect!(archive.open("dir")?.delete())
+            .to(be_err())
+            .to(equal(Error::FilePinned {
+                path: "dir/file".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn deleting_an_unpinned_file_succeeds() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.pin()?;
+        file.unpin()?;
+
+        expect!(file.delete()).to(be_ok());
+
+        Ok(())
+    })
+}
+
+//
+// `File::force_delete`
+//
+
+#[test]
+fn force_deleting_a_pinned_file_succeeds() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.pin()?;
+
+        expect!(file.force_delete()).to(be_ok());
+        expect!(file.exists()).to(be_ok()).to(be_false());
+
+        // The pin is deleted along with the file.
+        file.create_file()?;
+
+        expect!(file.is_pinned()).to(be_ok()).to(be_false());
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::delete_matching`
+//
+
+#[test]
+fn delete_matching_pinned_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/pinned")?.create_file()?;
+        archive.open("dir/pinned")?.pin()?;
+        archive.open("unpinned")?.create_file()?;
+
+        expect!(archive.delete_matching(&ListOptions::new()))
+            .to(be_err())
+            .to(equal(Error::FilePinned {
+                path: "dir/pinned".into(),
+            }));
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[
+                PathBuf::from("dir"),
+                PathBuf::from("dir/pinned"),
+                PathBuf::from("unpinned"),
+            ]));
+
+        expect!(archive.delete_matching(&ListOptions::new().glob("unpinned")))
+            .to(be_ok())
+            .to(equal(1));
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::prune`
+//
+
+#[test]
+fn prune_skips_pinned_files_unless_forced() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        for name in ["pinned", "unpinned"] {
+            let mut file = archive.open(name)?;
+            file.create_file()?;
+            file.set_mtime(Some(UNIX_EPOCH + Duration::from_secs(1)))?;
+        }
+
+        archive.open("pinned")?.pin()?;
+
+        let policy = RetentionPolicy::new().older_than(UNIX_EPOCH + Duration::from_secs(2));
+
+        expect!(archive.prune(&policy)).to(be_ok()).to(equal(1));
+
+        expect!(archive.open("pinned")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        expect!(archive.prune(&policy.force(true)))
+            .to(be_ok())
+            .to(equal(1));
+
+        expect!(archive.open("pinned")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::rename_with`
+//
+
+#[test]
+fn rename_overwriting_pinned_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        for policy in [RenamePolicy::Overwrite, RenamePolicy::Merge] {
+            archive.open("a")?.create_dir()?;
+            archive.open("a/conflict")?.create_file()?;
+            archive.open("b")?.create_dir()?;
+            archive.open("b/conflict")?.create_file()?;
+            archive.open("b/conflict")?.pin()?;
+
+            expect!(archive.rename_with("a", "b", policy))
+                .to(be_err())
+                .to(equal(Error::FilePinned {
+                    path: "b/conflict".into(),
+                }));
+
+            expect!(archive.list())
+                .to(be_ok())
+                .iter_try_map(|entry| Ok(entry?.into_path()))
+                .to(consist_of(&[
+                    PathBuf::from("a"),
+                    PathBuf::from("a/conflict"),
+                    PathBuf::from("b"),
+                    PathBuf::from("b/conflict"),
+                ]));
+
+            archive.open("a")?.force_delete()?;
+            archive.open("b")?.force_delete()?;
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_merging_dir_containing_pinned_file_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("a")?.create_dir()?;
+        archive.open("a/conflict")?.create_file()?;
+        archive.open("b")?.create_dir()?;
+        archive.open("b/conflict")?.create_dir()?;
+        archive.open("b/conflict/pinned")?.create_file()?;
+        archive.open("b/conflict/pinned")?.pin()?;
+
+        expect!(archive.rename_with("a", "b", RenamePolicy::Merge))
+            .to(be_err())
+            .to(equal(Error::FilePinned {
+                path: "b/conflict/pinned".into(),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_merging_around_pinned_file_succeeds() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("a")?.create_dir()?;
+        archive.open("a/new")?.create_file()?;
+        archive.open("b")?.create_dir()?;
+        archive.open("b/pinned")?.create_file()?;
+        archive.open("b/pinned")?.pin()?;
+
+        expect!(archive.rename_with("a", "b", RenamePolicy::Merge)).to(be_ok());
+
+        expect!(archive.open("b/pinned")?.is_pinned())
+            .to(be_ok())
+            .to(be_true());
+        expect!(archive.open("b/new")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
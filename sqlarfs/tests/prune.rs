@@ -0,0 +1,246 @@
+//! Tests for pruning old files from an archive.
+
+mod common;
+
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+use sqlarfs::{Archive, FileType, ListOptions, RetentionPolicy};
+use xpct::{be_ok, be_true, consist_of, equal, expect};
+
+use common::connection;
+
+fn create_file_with_mtime(archive: &Archive, path: &str, secs: u64) -> sqlarfs::Result<()> {
+    let mut file = archive.open(path)?;
+    file.create_file()?;
+    file.set_mtime(Some(UNIX_EPOCH + Duration::from_secs(secs)))?;
+    Ok(())
+}
+
+#[test]
+fn prune_keep_newest_per_directory() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("a")?.create_dir()?;
+        archive.open("b")?.create_dir()?;
+
+        create_file_with_mtime(archive, "a/1", 1)?;
+        create_file_with_mtime(archive, "a/2", 2)?;
+        create_file_with_mtime(archive, "a/3", 3)?;
+        create_file_with_mtime(archive, "b/1", 1)?;
+        create_file_with_mtime(archive, "b/2", 2)?;
+
+        expect!(archive.prune(&RetentionPolicy::new().keep_newest(1)))
+            .to(be_ok())
+            .to(equal(3));
+
+        expect!(archive.list_with(&ListOptions::new().file_type(FileType::File)))
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[PathBuf::from("a/3"), PathBuf::from("b/2")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn prune_older_than() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file_with_mtime(archive, "old", 1)?;
+        create_file_with_mtime(archive, "new", 10)?;
+
+        let policy = RetentionPolicy::new().older_than(UNIX_EPOCH + Duration::from_secs(5));
+
+        expect!(archive.prune(&policy)).to(be_ok()).to(equal(1));
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[PathBuf::from("new")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn prune_with_both_rules_only_deletes_files_matching_both() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file_with_mtime(archive, "1", 1)?;
+        create_file_with_mtime(archive, "2", 2)?;
+        create_file_with_mtime(archive, "3", 10)?;
+
+        let policy = RetentionPolicy::new()
+            .keep_newest(1)
+            .older_than(UNIX_EPOCH + Duration::from_secs(2));
+
+        expect!(archive.prune(&policy)).to(be_ok()).to(equal(1));
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[PathBuf::from("2"), PathBuf::from("3")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn prune_never_deletes_files_without_mtime() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_mtime(None)?;
+
+        expect!(archive.prune(&RetentionPolicy::new().keep_newest(0)))
+            .to(be_ok())
+            .to(equal(0));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn prune_with_no_rules_deletes_nothing() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file_with_mtime(archive, "file", 1)?;
+
+        expect!(archive.prune(&RetentionPolicy::new()))
+            .to(be_ok())
+            .to(equal(0));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn prune_only_descendants_of_dir() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("logs")?.create_dir()?;
+
+        create_file_with_mtime(archive, "logs/1", 1)?;
+        create_file_with_mtime(archive, "other", 1)?;
+
+        let policy = RetentionPolicy::new()
+            .older_than(UNIX_EPOCH + Duration::from_secs(5))
+            .descendants_of("logs");
+
+        expect!(archive.prune(&policy)).to(be_ok()).to(equal(1));
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[PathBuf::from("logs"), PathBuf::from("other")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn prune_dirs_deletes_descendants() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("snapshots")?.create_dir()?;
+
+        let mut old = archive.open("snapshots/old")?;
+        old.create_dir()?;
+        old.set_mtime(Some(UNIX_EPOCH + Duration::from_secs(1)))?;
+        drop(old);
+        create_file_with_mtime(archive, "snapshots/old/file", 20)?;
+
+        let mut new = archive.open("snapshots/new")?;
+        new.create_dir()?;
+        new.set_mtime(Some(UNIX_EPOCH + Duration::from_secs(10)))?;
+
+        let policy = RetentionPolicy::new()
+            .keep_newest(1)
+            .file_type(FileType::Dir)
+            .descendants_of("snapshots");
+
+        expect!(archive.prune(&policy)).to(be_ok()).to(equal(2));
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[
+                PathBuf::from("snapshots"),
+                PathBuf::from("snapshots/new"),
+            ]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn prune_never_deletes_dirs_by_default() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut dir = archive.open("dir")?;
+        dir.create_dir()?;
+        dir.set_mtime(Some(UNIX_EPOCH + Duration::from_secs(1)))?;
+        drop(dir);
+
+        create_file_with_mtime(archive, "dir/file", 1)?;
+
+        let policy = RetentionPolicy::new().older_than(UNIX_EPOCH + Duration::from_secs(5));
+
+        expect!(archive.prune(&policy)).to(be_ok()).to(equal(1));
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[PathBuf::from("dir")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn prune_dirs_keeps_dirs_containing_pinned_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        for name in ["pinned", "unpinned"] {
+            let mut dir = archive.open(name)?;
+            dir.create_dir()?;
+            dir.set_mtime(Some(UNIX_EPOCH + Duration::from_secs(1)))?;
+        }
+
+        create_file_with_mtime(archive, "pinned/file", 1)?;
+        archive.open("pinned/file")?.pin()?;
+
+        let policy = RetentionPolicy::new()
+            .older_than(UNIX_EPOCH + Duration::from_secs(5))
+            .file_type(FileType::Dir);
+
+        expect!(archive.prune(&policy)).to(be_ok()).to(equal(1));
+
+        expect!(archive.open("pinned/file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[
+                PathBuf::from("pinned"),
+                PathBuf::from("pinned/file"),
+            ]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn prune_counts_pinned_files_toward_keep_newest() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        create_file_with_mtime(archive, "old", 1)?;
+        create_file_with_mtime(archive, "newest", 10)?;
+        archive.open("newest")?.pin()?;
+
+        expect!(archive.prune(&RetentionPolicy::new().keep_newest(1)))
+            .to(be_ok())
+            .to(equal(1));
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[PathBuf::from("newest")]));
+
+        Ok(())
+    })
+}
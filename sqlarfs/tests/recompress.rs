@@ -0,0 +1,196 @@
+//! Tests for changing how the files in an archive are compressed.
+
+mod common;
+
+#[cfg(feature = "deflate")]
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "deflate")]
+use common::compressible_bytes;
+use common::connection;
+use sqlarfs::{Compression, Error, FileMetadata, RecompressOptions};
+use xpct::{be_err, be_ok, equal, expect, match_pattern, pattern};
+#[cfg(feature = "deflate")]
+use xpct::{be_false, be_true};
+
+//
+// `Archive::recompress`
+//
+
+#[test]
+#[cfg(feature = "deflate")]
+fn recompress_decompresses_compressed_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_compression(Compression::FAST);
+        file.write_bytes(&compressible_bytes())?;
+
+        expect!(file.is_compressed()).to(be_ok()).to(be_true());
+
+        drop(file);
+
+        archive.recompress(Compression::None)?;
+
+        let mut file = archive.open("file")?;
+
+        expect!(file.is_compressed()).to(be_ok()).to(be_false());
+
+        let mut actual = Vec::new();
+        file.reader()?.read_to_end(&mut actual)?;
+
+        expect!(actual).to(equal(compressible_bytes()));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn recompress_compresses_uncompressed_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_compression(Compression::None);
+        file.write_bytes(&compressible_bytes())?;
+
+        drop(file);
+
+        archive.recompress(Compression::BEST)?;
+
+        let mut file = archive.open("file")?;
+
+        expect!(file.is_compressed()).to(be_ok()).to(be_true());
+
+        let mut actual = Vec::new();
+        file.reader()?.read_to_end(&mut actual)?;
+
+        expect!(actual).to(equal(compressible_bytes()));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn recompress_preserves_metadata() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_compression(Compression::None);
+        file.write_bytes(&compressible_bytes())?;
+
+        let expected = file.metadata()?;
+
+        drop(file);
+
+        archive.recompress(Compression::BEST)?;
+
+        expect!(archive.open("file")?.metadata())
+            .to(be_ok())
+            .to(equal(expected));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn recompress_skips_directories_and_symlinks() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("symlink")?.create_symlink("dir")?;
+
+        archive.recompress(Compression::None)?;
+
+        expect!(archive.open("dir")?.metadata())
+            .to(be_ok())
+            .to(match_pattern(pattern!(FileMetadata::Dir { .. })));
+
+        expect!(archive.open("symlink")?.metadata())
+            .to(be_ok())
+            .to(match_pattern(pattern!(FileMetadata::Symlink { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn recompress_errors_when_file_is_open() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+
+        expect!(archive.recompress(Compression::None))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileAlreadyOpen { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn recompress_is_atomic() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        for path in ["a", "b"] {
+            let mut file = archive.open(path)?;
+            file.create_file()?;
+            file.set_compression(Compression::FAST);
+            file.write_bytes(&compressible_bytes())?;
+        }
+
+        // The files are recompressed in order, so this fails after "a" has been recompressed.
+        let file_b = archive.open("b")?;
+
+        expect!(archive.recompress(Compression::None)).to(be_err());
+
+        drop(file_b);
+
+        expect!(archive.open("a")?.is_compressed())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::recompress_with`
+//
+
+#[test]
+fn recompress_reports_progress() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        for (path, contents) in [("dir/a", "a"), ("dir/b", "bb")] {
+            let mut file = archive.open(path)?;
+            file.create_file()?;
+            file.write_str(contents)?;
+        }
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reports_ref = Arc::clone(&reports);
+
+        let opts = RecompressOptions::new().on_progress(move |progress| {
+            reports_ref.lock().unwrap().push((
+                progress.path().to_owned(),
+                progress.files_done(),
+                progress.files_total(),
+                progress.bytes_done(),
+                progress.bytes_total(),
+            ));
+        });
+
+        archive.recompress_with(Compression::None, &opts)?;
+
+        expect!(reports.lock().unwrap().clone()).to(equal(vec![
+            (PathBuf::from("dir/a"), 1, Some(2), 1, Some(3)),
+            (PathBuf::from("dir/b"), 2, Some(2), 3, Some(3)),
+        ]));
+
+        Ok(())
+    })
+}
@@ -1,3 +1,5 @@
+//! Tests for conformance with the reference sqlar implementation.
+
 #![cfg(feature = "reference-conformance-tests")]
 
 mod common;
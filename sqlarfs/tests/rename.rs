@@ -0,0 +1,295 @@
+//! Tests for renaming files in an archive.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use sqlarfs::{Error, FileMetadata, RenamePolicy};
+use xpct::{be_err, be_false, be_ok, be_true, consist_of, equal, expect, match_pattern, pattern};
+
+use common::connection;
+
+#[test]
+fn rename_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("a")?.create_file()?;
+
+        expect!(archive.rename("a", "b")).to(be_ok());
+
+        expect!(archive.open("a")?.exists())
+            .to(be_ok())
+            .to(be_false());
+        expect!(archive.open("b")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_dir_moves_descendants() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("a")?.create_dir()?;
+        archive.open("a/dir")?.create_dir()?;
+        archive.open("a/dir/file")?.create_file()?;
+        archive.open("ab")?.create_file()?;
+
+        expect!(archive.rename("a", "b")).to(be_ok());
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[
+                PathBuf::from("ab"),
+                PathBuf::from("b"),
+                PathBuf::from("b/dir"),
+                PathBuf::from("b/dir/file"),
+            ]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_when_source_does_not_exist_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(archive.rename("a", "b"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileNotFound { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_when_dest_has_no_parent_dir_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("a")?.create_file()?;
+
+        expect!(archive.rename("a", "dir/b"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::NoParentDirectory { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_dir_into_itself_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("a")?.create_dir()?;
+
+        expect!(archive.rename("a", "a/b"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_when_dest_exists_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("a")?.create_dir()?;
+        archive.open("b")?.create_dir()?;
+
+        expect!(archive.rename("a", "b"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileAlreadyExists { .. })));
+
+        expect!(archive.open("a")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_with_overwrite_replaces_dest_tree() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("a")?.create_dir()?;
+        archive.open("a/new")?.create_file()?;
+        archive.open("b")?.create_dir()?;
+        archive.open("b/old")?.create_file()?;
+
+        expect!(archive.rename_with("a", "b", RenamePolicy::Overwrite)).to(be_ok());
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[PathBuf::from("b"), PathBuf::from("b/new")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_with_merge_merges_dir_trees() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("a")?.create_dir()?;
+        archive.open("a/dir")?.create_dir()?;
+        archive.open("a/dir/new")?.create_file()?;
+        archive.open("a/conflict")?.create_file()?;
+        archive.open("a/conflict")?.write_str("new")?;
+
+        archive.open("b")?.create_dir()?;
+        archive.open("b/dir")?.create_dir()?;
+        archive.open("b/dir/old")?.create_file()?;
+        archive.open("b/conflict")?.create_file()?;
+        archive.open("b/conflict")?.write_str("older")?;
+
+        expect!(archive.rename_with("a", "b", RenamePolicy::Merge)).to(be_ok());
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[
+                PathBuf::from("b"),
+                PathBuf::from("b/conflict"),
+                PathBuf::from("b/dir"),
+                PathBuf::from("b/dir/new"),
+                PathBuf::from("b/dir/old"),
+            ]));
+
+        expect!(archive.open("b/conflict")?.metadata())
+            .to(be_ok())
+            .to(match_pattern(pattern!(FileMetadata::File { size: 3, .. })));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_with_merge_replaces_dir_with_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("a")?.create_dir()?;
+        archive.open("a/conflict")?.create_file()?;
+
+        archive.open("b")?.create_dir()?;
+        archive.open("b/conflict")?.create_dir()?;
+        archive.open("b/conflict/file")?.create_file()?;
+
+        expect!(archive.rename_with("a", "b", RenamePolicy::Merge)).to(be_ok());
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[
+                PathBuf::from("b"),
+                PathBuf::from("b/conflict"),
+            ]));
+
+        expect!(archive.open("b/conflict")?.metadata())
+            .to(be_ok())
+            .to(match_pattern(pattern!(FileMetadata::File { .. })));
+
+        Ok(())
+    })
+}
+
+//
+// `File::rename_to`
+//
+
+#[test]
+fn rename_file_handle_follows_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("a")?;
+        file.create_file()?;
+
+        expect!(file.rename_to("b")).to(be_ok());
+
+        expect!(file.path()).to(equal(Path::new("b")));
+        expect!(file.exists()).to(be_ok()).to(be_true());
+        expect!(archive.open("a")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_dir_handle_moves_descendants() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut dir = archive.open("a")?;
+        dir.create_dir()?;
+        archive.open("a/file")?.create_file()?;
+
+        expect!(dir.rename_to("b")).to(be_ok());
+
+        expect!(archive.list())
+            .to(be_ok())
+            .iter_try_map(|entry| Ok(entry?.into_path()))
+            .to(consist_of(&[PathBuf::from("b"), PathBuf::from("b/file")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_handle_releases_old_path() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("a")?;
+        file.create_file()?;
+        file.rename_to("b")?;
+
+        expect!(archive.open("a")).to(be_ok());
+        expect!(archive.open("b"))
+            .to(be_err())
+            .to(equal(Error::FileAlreadyOpen { path: "b".into() }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_handle_when_dest_is_open_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("a")?;
+        file.create_file()?;
+
+        let _other = archive.open("b")?;
+
+        expect!(file.rename_to("b"))
+            .to(be_err())
+            .to(equal(Error::FileAlreadyOpen { path: "b".into() }));
+
+        expect!(file.path()).to(equal(Path::new("a")));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_handle_when_dest_exists_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("a")?;
+        file.create_file()?;
+        archive.open("b")?.create_file()?;
+
+        expect!(file.rename_to("b"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileAlreadyExists { .. })));
+
+        expect!(file.path()).to(equal(Path::new("a")));
+        expect!(archive.open("b")).to(be_ok());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_handle_when_file_does_not_exist_errors() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.open("a")?;
+
+        expect!(file.rename_to("b"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileNotFound { .. })));
+
+        Ok(())
+    })
+}
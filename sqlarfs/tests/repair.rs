@@ -0,0 +1,455 @@
+//! Tests for finding and fixing problems in archives created by other tools.
+
+mod common;
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "deflate")]
+use sqlarfs::Compression;
+use sqlarfs::{Archive, Connection, FileType, RepairOptions};
+use xpct::{be_empty, be_false, be_ok, be_true, equal, expect};
+
+// Create an archive with a regular file at each of `paths` and a compressed file whose contents
+// are garbage at each of `corrupt_paths`, bypassing the checks the API does on paths, like an
+// archive created by a buggy tool.
+fn broken_archive(
+    db_path: &Path,
+    paths: &[&str],
+    corrupt_paths: &[&str],
+) -> sqlarfs::Result<Connection> {
+    let conn = Connection::create_new(db_path)?;
+
+    let raw_conn = rusqlite::Connection::open(db_path)?;
+
+    for path in paths {
+        raw_conn.execute(
+            "INSERT INTO sqlar (name, mode, sz, data) VALUES (?1, ?2, 0, zeroblob(0))",
+            (path, 0o100644),
+        )?;
+    }
+
+    for path in corrupt_paths {
+        // The size is larger than the data, so it looks compressed, but it isn't valid zlib.
+        raw_conn.execute(
+            "INSERT INTO sqlar (name, mode, sz, data) VALUES (?1, ?2, 100, x'00010203')",
+            (path, 0o100644),
+        )?;
+    }
+
+    Ok(conn)
+}
+
+fn paths(paths: &[&str]) -> Vec<PathBuf> {
+    paths.iter().map(PathBuf::from).collect()
+}
+
+fn read_contents(archive: &Archive, path: &str) -> sqlarfs::Result<String> {
+    let mut contents = String::new();
+    archive
+        .open(path)?
+        .reader()?
+        .read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+//
+// `Archive::check`
+//
+
+#[test]
+fn checking_healthy_archive_finds_no_problems() -> sqlarfs::Result<()> {
+    Connection::open_in_memory()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/symlink")?.create_symlink("file")?;
+
+        let mut file = archive.open("dir/file")?;
+        file.create_file()?;
+        file.write_str("contents")?;
+        drop(file);
+
+        expect!(archive.check())
+            .to(be_ok())
+            .map(|problems| problems.is_empty())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn checking_finds_parents_that_are_not_dirs() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut conn = broken_archive(
+        &temp_dir.path().join("test.sqlar"),
+        &["file", "file/child"],
+        &[],
+    )?;
+
+    conn.exec(|archive| {
+        let problems = archive.check()?;
+
+        expect!(problems.non_dir_parents()).to(equal(paths(&["file"])));
+        expect!(problems.missing_dirs()).to(be_empty());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn checking_finds_bad_and_duplicate_paths() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut conn = broken_archive(
+        &temp_dir.path().join("test.sqlar"),
+        &["file", "file/", "other", "../evil"],
+        &[],
+    )?;
+
+    conn.exec(|archive| {
+        let problems = archive.check()?;
+
+        expect!(problems.bad_paths()).to(equal(paths(&["../evil", "file/"])));
+        expect!(problems.duplicate_paths()).to(equal(paths(&["file", "file/"])));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn checking_finds_negative_sizes_on_files_that_are_not_symlinks() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let db_path = temp_dir.path().join("test.sqlar");
+
+    let mut conn = broken_archive(&db_path, &[], &[])?;
+
+    let raw_conn = rusqlite::Connection::open(&db_path)?;
+
+    for (name, mode, data) in [
+        ("dir", 0o040755, None),
+        ("file", 0o100644, Some("contents")),
+        ("symlink", 0o120777, Some("file")),
+    ] {
+        raw_conn.execute(
+            "INSERT INTO sqlar (name, mode, sz, data) VALUES (?1, ?2, -1, ?3)",
+            (name, mode, data),
+        )?;
+    }
+
+    conn.exec(|archive| {
+        let problems = archive.check()?;
+
+        expect!(problems.negative_sizes()).to(equal(paths(&["dir", "file"])));
+        expect!(problems.corrupt_files()).to(be_empty());
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn checking_finds_corrupt_files_without_changing_them() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut conn = broken_archive(&temp_dir.path().join("test.sqlar"), &[], &["corrupt"])?;
+
+    conn.exec(|archive| {
+        expect!(archive.check()?.corrupt_files()).to(equal(paths(&["corrupt"])));
+        expect!(archive.open("corrupt")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::repair`
+//
+
+#[test]
+fn repairing_healthy_archive_finds_no_problems() -> sqlarfs::Result<()> {
+    Connection::open_in_memory()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let mut file = archive.open("dir/file")?;
+        file.create_file()?;
+        file.write_str("contents")?;
+        drop(file);
+
+        let report = archive.repair(&RepairOptions::new())?;
+
+        expect!(report.before().is_empty()).to(be_true());
+        expect!(report.after().is_empty()).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn repairing_with_default_options_only_reports_problems() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut conn = broken_archive(
+        &temp_dir.path().join("test.sqlar"),
+        &["a/b/file", "dir/"],
+        &["corrupt"],
+    )?;
+
+    conn.exec(|archive| {
+        let report = archive.repair(&RepairOptions::new())?;
+
+        expect!(report.before().missing_dirs()).to(equal(paths(&["a", "a/b"])));
+        expect!(report.before().bad_paths()).to(equal(paths(&["dir/"])));
+        expect!(report.before().corrupt_files()).to(equal(paths(&["corrupt"])));
+        expect!(report.after()).to(equal(report.before()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn repairing_with_fix_dirs_creates_missing_dirs() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut conn = broken_archive(&temp_dir.path().join("test.sqlar"), &["a/b/file"], &[])?;
+
+    conn.exec(|archive| {
+        let report = archive.repair(&RepairOptions::new().fix_dirs(true))?;
+
+        expect!(report.after().missing_dirs()).to(be_empty());
+
+        for path in ["a", "a/b"] {
+            let metadata = archive.open(path)?.metadata()?;
+
+            expect!(metadata.kind()).to(equal(FileType::Dir));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn repairing_with_fix_dirs_skips_dirs_under_regular_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut conn = broken_archive(
+        &temp_dir.path().join("test.sqlar"),
+        &["file", "file/dir/child"],
+        &[],
+    )?;
+
+    conn.exec(|archive| {
+        let report = archive.repair(&RepairOptions::new().fix_dirs(true))?;
+
+        expect!(report.after().missing_dirs()).to(equal(paths(&["file/dir"])));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn repairing_with_fix_paths_renames_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut conn = broken_archive(
+        &temp_dir.path().join("test.sqlar"),
+        &["/absolute", "./dot", "trailing/", "double//slash"],
+        &[],
+    )?;
+
+    conn.exec(|archive| {
+        let opts = RepairOptions::new().fix_paths(true).fix_dirs(true);
+        let report = archive.repair(&opts)?;
+
+        expect!(report.before().bad_paths().len()).to(equal(4));
+        expect!(report.after().is_empty()).to(be_true());
+
+        for path in ["absolute", "dot", "trailing", "double", "double/slash"] {
+            expect!(archive.open(path)?.exists())
+                .to(be_ok())
+                .to(be_true());
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn repairing_with_fix_paths_skips_conflicts_and_parent_components() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut conn = broken_archive(
+        &temp_dir.path().join("test.sqlar"),
+        &["file", "file/", "../evil"],
+        &[],
+    )?;
+
+    conn.exec(|archive| {
+        let report = archive.repair(&RepairOptions::new().fix_paths(true))?;
+
+        expect!(report.after().bad_paths()).to(equal(paths(&["../evil", "file/"])));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn repairing_with_drop_corrupt_deletes_corrupt_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut conn = broken_archive(&temp_dir.path().join("test.sqlar"), &[], &["corrupt"])?;
+
+    conn.exec(|archive| {
+        let mut file = archive.open("healthy")?;
+        file.create_file()?;
+        file.write_str("a".repeat(1024))?;
+        drop(file);
+
+        let report = archive.repair(&RepairOptions::new().drop_corrupt(true))?;
+
+        expect!(report.before().corrupt_files()).to(equal(paths(&["corrupt"])));
+        expect!(report.after().corrupt_files()).to(be_empty());
+
+        expect!(archive.open("corrupt")?.exists())
+            .to(be_ok())
+            .to(be_false());
+        expect!(archive.open("healthy")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn repairing_with_remove_orphans_deletes_files_without_parent_dirs() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut conn = broken_archive(
+        &temp_dir.path().join("test.sqlar"),
+        &["file", "file/child", "a/b/file"],
+        &[],
+    )?;
+
+    conn.exec(|archive| {
+        let report = archive.repair(&RepairOptions::new().remove_orphans(true))?;
+
+        expect!(report.after().is_empty()).to(be_true());
+        expect!(archive.open("file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        for path in ["file/child", "a/b/file"] {
+            expect!(archive.open(path)?.exists())
+                .to(be_ok())
+                .to(be_false());
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn repairing_with_remove_orphans_keeps_files_whose_dirs_were_fixed() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut conn = broken_archive(
+        &temp_dir.path().join("test.sqlar"),
+        &["file", "file/dir/child", "a/b/file"],
+        &[],
+    )?;
+
+    conn.exec(|archive| {
+        let opts = RepairOptions::new().fix_dirs(true).remove_orphans(true);
+        let report = archive.repair(&opts)?;
+
+        expect!(report.after().is_empty()).to(be_true());
+        expect!(archive.open("a/b/file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+        expect!(archive.open("file/dir/child")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn repairing_with_fix_sizes_fixes_negative_sizes() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let db_path = temp_dir.path().join("test.sqlar");
+
+    let mut conn = broken_archive(&db_path, &[], &[])?;
+
+    let raw_conn = rusqlite::Connection::open(&db_path)?;
+
+    raw_conn.execute(
+        "INSERT INTO sqlar (name, mode, sz, data) VALUES ('dir', ?1, -1, NULL)",
+        (0o040755,),
+    )?;
+    raw_conn.execute(
+        "INSERT INTO sqlar (name, mode, sz, data) VALUES ('file', ?1, -1, ?2)",
+        (0o100644, b"contents".to_vec()),
+    )?;
+
+    conn.exec(|archive| {
+        let report = archive.repair(&RepairOptions::new().fix_sizes(true))?;
+
+        expect!(report.before().negative_sizes()).to(equal(paths(&["dir", "file"])));
+        expect!(report.after().is_empty()).to(be_true());
+
+        expect!(archive.open("dir")?.metadata()?.kind()).to(equal(FileType::Dir));
+        expect!(read_contents(archive, "file"))
+            .to(be_ok())
+            .to(equal(String::from("contents")));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn repairing_with_fix_sizes_fixes_sizes_of_compressed_files() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let db_path = temp_dir.path().join("test.sqlar");
+
+    let mut conn = broken_archive(&db_path, &[], &[])?;
+    let contents = "a".repeat(1000);
+
+    conn.exec(|archive| {
+        for path in ["too-big", "negative"] {
+            let mut file = archive.open(path)?;
+            file.create_file()?;
+            file.set_compression(Compression::FAST);
+            file.write_str(&contents)?;
+        }
+
+        sqlarfs::Result::Ok(())
+    })?;
+
+    let raw_conn = rusqlite::Connection::open(&db_path)?;
+    raw_conn.execute("UPDATE sqlar SET sz = 2000 WHERE name = 'too-big'", ())?;
+    raw_conn.execute("UPDATE sqlar SET sz = -1 WHERE name = 'negative'", ())?;
+
+    conn.exec(|archive| {
+        let opts = RepairOptions::new().fix_sizes(true).drop_corrupt(true);
+        let report = archive.repair(&opts)?;
+
+        expect!(report.before().corrupt_files()).to(equal(paths(&["too-big"])));
+        expect!(report.before().negative_sizes()).to(equal(paths(&["negative"])));
+        expect!(report.after().is_empty()).to(be_true());
+
+        for path in ["too-big", "negative"] {
+            expect!(read_contents(archive, path))
+                .to(be_ok())
+                .to(equal(contents.clone()));
+        }
+
+        Ok(())
+    })
+}
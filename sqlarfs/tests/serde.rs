@@ -0,0 +1,86 @@
+//! Tests for loading options from config files.
+
+#![cfg(feature = "serde")]
+
+mod common;
+
+use std::fs;
+
+use common::connection;
+use sqlarfs::{ArchiveOptions, ExtractOptions};
+use xpct::{be_err, be_ok, be_true, equal, expect};
+
+//
+// `ArchiveOptions`
+//
+
+#[test]
+fn deserialize_archive_options_with_defaults() {
+    expect!(serde_json::from_str::<ArchiveOptions>("{}"))
+        .to(be_ok())
+        .map(|opts| format!("{opts:?}"))
+        .to(equal(format!("{:?}", ArchiveOptions::new())));
+}
+
+#[test]
+fn deserialize_archive_options() -> sqlarfs::Result<()> {
+    let opts: ArchiveOptions = serde_json::from_str(
+        r#"{ "children": true, "prefix": "backups", "overwrite": "skip", "compression": "none" }"#,
+    )
+    .unwrap();
+
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("file"), "contents")?;
+
+    connection()?.exec(|archive| {
+        archive.archive_with(temp_dir.path(), "", &opts)?;
+
+        expect!(archive.open("backups/file")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn deserialize_archive_options_with_unknown_variant_errors() {
+    expect!(serde_json::from_str::<ArchiveOptions>(
+        r#"{ "overwrite": "sometimes" }"#
+    ))
+    .to(be_err());
+}
+
+#[test]
+fn serialize_archive_options_round_trips() {
+    let opts = ArchiveOptions::new().deterministic(true).prefix("prefix");
+    let json = serde_json::to_string(&opts).unwrap();
+
+    expect!(serde_json::from_str::<ArchiveOptions>(&json))
+        .to(be_ok())
+        .map(|opts| format!("{opts:?}"))
+        .to(equal(format!("{opts:?}")));
+}
+
+//
+// `ExtractOptions`
+//
+
+#[test]
+fn deserialize_extract_options_with_defaults() {
+    expect!(serde_json::from_str::<ExtractOptions>("{}"))
+        .to(be_ok())
+        .map(|opts| format!("{opts:?}"))
+        .to(equal(format!("{:?}", ExtractOptions::new())));
+}
+
+#[test]
+fn deserialized_extract_options_can_be_validated() {
+    let opts: ExtractOptions =
+        serde_json::from_str(r#"{ "recursive": false, "strip_components": 2 }"#).unwrap();
+
+    expect!(opts.validate())
+        .to(be_err())
+        .map(|conflicts| conflicts.len())
+        .to(equal(2));
+}
@@ -0,0 +1,209 @@
+//! Tests for overriding archive defaults with `Settings`.
+
+mod common;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use common::connection;
+use sqlarfs::{Compression, Error, FileMode, Settings};
+use xpct::{be_err, be_false, be_ok, be_some, be_true, equal, expect};
+
+//
+// `Archive::with_settings`
+//
+
+#[test]
+fn with_settings_overrides_umask() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let settings = Settings::new().umask(FileMode::GROUP_W | FileMode::OTHER_W);
+
+        archive.with_settings(&settings, |archive| {
+            expect!(archive.umask()).to(equal(FileMode::GROUP_W | FileMode::OTHER_W));
+
+            archive.open("inside")?.create_file()
+        })?;
+
+        expect!(archive.umask()).to(equal(FileMode::OTHER_W));
+
+        archive.open("outside")?.create_file()?;
+
+        let inside = archive.open("inside")?.metadata()?;
+        let outside = archive.open("outside")?.metadata()?;
+
+        expect!(inside.mode()).to(equal(Some(
+            FileMode::OWNER_R | FileMode::OWNER_W | FileMode::GROUP_R | FileMode::OTHER_R,
+        )));
+        expect!(outside.mode()).to(equal(Some(
+            FileMode::OWNER_R
+                | FileMode::OWNER_W
+                | FileMode::GROUP_R
+                | FileMode::GROUP_W
+                | FileMode::OTHER_R,
+        )));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn with_settings_leaves_unset_settings_alone() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.set_umask(FileMode::OTHER_R);
+        archive.set_compression(Compression::None);
+
+        archive.with_settings(&Settings::new().update_mtime(true), |archive| {
+            expect!(archive.umask()).to(equal(FileMode::OTHER_R));
+            expect!(archive.compression()).to(equal(Compression::None));
+            expect!(archive.update_mtime()).to(be_true());
+
+            sqlarfs::Result::Ok(())
+        })?;
+
+        expect!(archive.update_mtime()).to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn with_settings_overrides_compression() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let settings = Settings::new().compression(Compression::None);
+
+        archive.with_settings(&settings, |archive| {
+            let file = archive.open("file")?;
+
+            expect!(file.compression()).to(equal(Compression::None));
+
+            sqlarfs::Result::Ok(())
+        })?;
+
+        #[cfg(feature = "deflate")]
+        expect!(archive.open("file")?.compression()).to(equal(Compression::FAST));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn with_settings_compression_applies_to_writes() -> sqlarfs::Result<()> {
+    let contents = "a".repeat(1024 * 1024);
+
+    connection()?.exec(|archive| {
+        archive.set_compression(Compression::None);
+
+        archive.with_settings(&Settings::new().compression(Compression::BEST), |archive| {
+            let mut file = archive.open("file")?;
+            file.create_file()?;
+            file.write_str(&contents)
+        })?;
+
+        expect!(archive.open("file")?.is_compressed())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn with_settings_update_mtime_sets_mtime_on_write() -> sqlarfs::Result<()> {
+    let old_mtime = UNIX_EPOCH + Duration::from_secs(1);
+
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_mtime(Some(old_mtime))?;
+        drop(file);
+
+        archive.with_settings(&Settings::new().update_mtime(true), |archive| {
+            archive.open("file")?.write_str("contents")
+        })?;
+
+        let mtime = archive.open("file")?.metadata()?.mtime();
+
+        expect!(mtime).to(be_some());
+        expect!(mtime.unwrap() > old_mtime).to(be_true());
+        expect!(mtime.unwrap() <= SystemTime::now()).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn writing_without_update_mtime_leaves_mtime_alone() -> sqlarfs::Result<()> {
+    let old_mtime = UNIX_EPOCH + Duration::from_secs(1);
+
+    connection()?.exec(|archive| {
+        let mut file = archive.open("file")?;
+        file.create_file()?;
+        file.set_mtime(Some(old_mtime))?;
+        file.write_str("contents")?;
+        drop(file);
+
+        expect!(archive.open("file")?.metadata()?.mtime()).to(equal(Some(old_mtime)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn with_settings_rolls_back_changes_on_error() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("before")?.create_file()?;
+
+        let settings = Settings::new().umask(FileMode::empty());
+
+        let result = archive.with_settings(&settings, |archive| {
+            archive.open("inside")?.create_file()?;
+
+            Err::<(), _>(Error::InvalidArgs {
+                reason: String::from("failed"),
+            })
+        });
+
+        expect!(result).to(be_err());
+
+        expect!(archive.umask()).to(equal(FileMode::OTHER_W));
+        expect!(archive.open("inside")?.exists())
+            .to(be_ok())
+            .to(be_false());
+        expect!(archive.open("before")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn with_settings_can_be_nested() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.with_settings(&Settings::new().umask(FileMode::OTHER_R), |archive| {
+            let result =
+                archive.with_settings(&Settings::new().umask(FileMode::GROUP_R), |archive| {
+                    archive.open("inner")?.create_file()?;
+
+                    Err::<(), _>(Error::InvalidArgs {
+                        reason: String::from("failed"),
+                    })
+                });
+
+            expect!(result).to(be_err());
+            expect!(archive.umask()).to(equal(FileMode::OTHER_R));
+
+            archive.open("outer")?.create_file()
+        })?;
+
+        expect!(archive.umask()).to(equal(FileMode::OTHER_W));
+        expect!(archive.open("inner")?.exists())
+            .to(be_ok())
+            .to(be_false());
+        expect!(archive.open("outer")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
@@ -0,0 +1,142 @@
+//! Tests for using an archive as a container for a single document.
+
+mod common;
+
+use std::io::Read;
+
+use common::connection;
+use sqlarfs::{Error, SINGLE_ENTRY};
+use xpct::{be_err, be_ok, be_true, equal, expect, match_pattern, pattern};
+
+//
+// `Connection::store_single`
+//
+
+#[test]
+fn store_single_creates_single_entry() -> sqlarfs::Result<()> {
+    let mut conn = connection()?;
+
+    expect!(conn.store_single("contents".as_bytes())).to(be_ok());
+
+    conn.exec(|archive| {
+        let mut contents = String::new();
+        archive
+            .open(SINGLE_ENTRY)?
+            .reader()?
+            .read_to_string(&mut contents)?;
+
+        expect!(contents).to(equal("contents"));
+        expect!(archive.list()?.count()).to(equal(1));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn store_single_replaces_existing_contents() -> sqlarfs::Result<()> {
+    let mut conn = connection()?;
+
+    conn.store_single("old contents".as_bytes())?;
+    conn.store_single("new".as_bytes())?;
+
+    expect!(conn.load_single())
+        .to(be_ok())
+        .to(equal(b"new".to_vec()));
+
+    Ok(())
+}
+
+#[test]
+fn store_single_leaves_other_files_alone() -> sqlarfs::Result<()> {
+    let mut conn = connection()?;
+
+    conn.exec(|archive| archive.open("metadata.json")?.create_file())?;
+
+    conn.store_single("contents".as_bytes())?;
+
+    conn.exec(|archive| {
+        expect!(archive.open("metadata.json")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn store_single_errors_when_entry_is_a_dir() -> sqlarfs::Result<()> {
+    let mut conn = connection()?;
+
+    conn.exec(|archive| archive.open(SINGLE_ENTRY)?.create_dir())?;
+
+    expect!(conn.store_single("contents".as_bytes()))
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::NotARegularFile { .. })));
+
+    Ok(())
+}
+
+//
+// `Connection::load_single`
+//
+
+#[test]
+fn load_single_errors_when_archive_is_empty() -> sqlarfs::Result<()> {
+    expect!(connection()?.load_single())
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::FileNotFound { .. })));
+
+    Ok(())
+}
+
+#[test]
+fn load_single_prefers_single_entry() -> sqlarfs::Result<()> {
+    let mut conn = connection()?;
+
+    conn.exec(|archive| {
+        let mut file = archive.open("other")?;
+        file.create_file()?;
+        file.write_str("other contents")
+    })?;
+
+    conn.store_single("contents".as_bytes())?;
+
+    expect!(conn.load_single())
+        .to(be_ok())
+        .to(equal(b"contents".to_vec()));
+
+    Ok(())
+}
+
+#[test]
+fn load_single_reads_only_file_at_another_path() -> sqlarfs::Result<()> {
+    let mut conn = connection()?;
+
+    conn.exec(|archive| {
+        let mut file = archive.open("document.txt")?;
+        file.create_file()?;
+        file.write_str("contents")
+    })?;
+
+    expect!(conn.load_single())
+        .to(be_ok())
+        .to(equal(b"contents".to_vec()));
+
+    Ok(())
+}
+
+#[test]
+fn load_single_errors_when_there_are_several_files() -> sqlarfs::Result<()> {
+    let mut conn = connection()?;
+
+    conn.exec(|archive| {
+        archive.open("first")?.create_file()?;
+        archive.open("second")?.create_file()
+    })?;
+
+    expect!(conn.load_single())
+        .to(be_err())
+        .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+//! Tests for resolving requests to a static website backed by an archive.
+
+mod common;
+
+use std::path::PathBuf;
+
+use sqlarfs::{ContentEncoding, StaticResource};
+use xpct::{be_ok, equal, expect};
+
+use common::connection;
+
+fn file(path: &str, encoding: Option<ContentEncoding>) -> StaticResource {
+    StaticResource::File {
+        path: PathBuf::from(path),
+        encoding,
+    }
+}
+
+#[test]
+fn resolve_regular_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("style.css")?.create_file()?;
+
+        expect!(archive.resolve_static("/style.css", None))
+            .to(be_ok())
+            .to(equal(file("style.css", None)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn resolve_missing_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(archive.resolve_static("/nonexistent", None))
+            .to(be_ok())
+            .to(equal(StaticResource::NotFound));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn resolve_root_index() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("index.html")?.create_file()?;
+
+        expect!(archive.resolve_static("/", None))
+            .to(be_ok())
+            .to(equal(file("index.html", None)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn resolve_dir_without_trailing_slash_redirects() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("docs")?.create_dir()?;
+        archive.open("docs/index.html")?.create_file()?;
+
+        expect!(archive.resolve_static("/docs", None))
+            .to(be_ok())
+            .to(equal(StaticResource::Redirect {
+                location: String::from("/docs/"),
+            }));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn resolve_dir_index() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("docs")?.create_dir()?;
+        archive.open("docs/index.html")?.create_file()?;
+
+        expect!(archive.resolve_static("/docs/", None))
+            .to(be_ok())
+            .to(equal(file("docs/index.html", None)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn resolve_dir_without_index() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("docs")?.create_dir()?;
+
+        expect!(archive.resolve_static("/docs/", None))
+            .to(be_ok())
+            .to(equal(StaticResource::NotFound));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn resolve_precompressed_variant() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("app.js")?.create_file()?;
+        archive.open("app.js.gz")?.create_file()?;
+        archive.open("app.js.br")?.create_file()?;
+
+        expect!(archive.resolve_static("/app.js", Some("gzip, br")))
+            .to(be_ok())
+            .to(equal(file("app.js.br", Some(ContentEncoding::Brotli))));
+
+        expect!(archive.resolve_static("/app.js", Some("gzip")))
+            .to(be_ok())
+            .to(equal(file("app.js.gz", Some(ContentEncoding::Gzip))));
+
+        expect!(archive.resolve_static("/app.js", Some("identity")))
+            .to(be_ok())
+            .to(equal(file("app.js", None)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn resolve_path_with_parent_segments() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("secret")?.create_file()?;
+
+        expect!(archive.resolve_static("/docs/../secret", None))
+            .to(be_ok())
+            .to(equal(StaticResource::NotFound));
+
+        Ok(())
+    })
+}
@@ -0,0 +1,111 @@
+//! Tests for finding symbolic links whose targets don't exist.
+
+mod common;
+
+use std::path::PathBuf;
+
+use common::connection;
+use xpct::{be_empty, be_ok, equal, expect};
+
+//
+// `Archive::find_broken_symlinks`
+//
+
+#[test]
+fn find_broken_symlinks_when_there_are_none() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/file")?.create_file()?;
+        archive.open("file-link")?.create_symlink("dir/file")?;
+        archive.open("dir-link")?.create_symlink("./dir")?;
+        archive
+            .open("dir/parent-link")?
+            .create_symlink("../file-link")?;
+
+        expect!(archive.find_broken_symlinks())
+            .to(be_ok())
+            .to(be_empty());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn find_broken_symlinks_reports_missing_targets() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/link")?.create_symlink("missing")?;
+        archive.open("link")?.create_symlink("dir/missing")?;
+
+        expect!(archive.find_broken_symlinks())
+            .to(be_ok())
+            .to(equal(vec![
+                PathBuf::from("dir/link"),
+                PathBuf::from("link"),
+            ]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn find_broken_symlinks_follows_chains_of_links() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("dir/file")?.create_file()?;
+        archive.open("dir-link")?.create_symlink("dir")?;
+        archive.open("good")?.create_symlink("dir-link/file")?;
+        archive.open("bad")?.create_symlink("dir-link/missing")?;
+
+        expect!(archive.find_broken_symlinks())
+            .to(be_ok())
+            .to(equal(vec![PathBuf::from("bad")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn find_broken_symlinks_reports_targets_under_regular_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+        archive.open("link")?.create_symlink("file/child")?;
+
+        expect!(archive.find_broken_symlinks())
+            .to(be_ok())
+            .to(equal(vec![PathBuf::from("link")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn find_broken_symlinks_reports_cycles() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("a")?.create_symlink("b")?;
+        archive.open("b")?.create_symlink("a")?;
+
+        expect!(archive.find_broken_symlinks())
+            .to(be_ok())
+            .to(equal(vec![PathBuf::from("a"), PathBuf::from("b")]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn find_broken_symlinks_ignores_targets_outside_archive() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+        archive.open("absolute")?.create_symlink("/nonexistent")?;
+        archive
+            .open("dir/relative")?
+            .create_symlink("../../nonexistent")?;
+
+        expect!(archive.find_broken_symlinks())
+            .to(be_ok())
+            .to(be_empty());
+
+        Ok(())
+    })
+}
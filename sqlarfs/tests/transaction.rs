@@ -1,5 +1,7 @@
-use sqlarfs::{Connection, TransactionBehavior};
-use xpct::{be_false, be_ok, be_true, expect};
+//! Tests for transactions.
+
+use sqlarfs::{Connection, Error, TransactionBehavior};
+use xpct::{be_err, be_false, be_ok, be_true, expect, match_pattern, pattern};
 
 fn test_transaction_commits_successfully(
     conn: &mut Connection,
@@ -123,3 +125,71 @@ fn exec_with_exclusive_and_commit() -> sqlarfs::Result<()> {
 
     test_exec_commits_successfully(&mut conn, TransactionBehavior::Exclusive)
 }
+
+//
+// `Archive::snapshot_read`
+//
+
+#[test]
+fn snapshot_read_sees_committed_state() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let mut conn = Connection::create_new(temp_dir.path().join("test.sqlar"))?;
+
+    conn.exec(|archive| archive.open("committed")?.create_file())?;
+
+    conn.exec(|archive| {
+        archive.open("committed")?.delete()?;
+        archive.open("uncommitted")?.create_file()?;
+
+        archive.snapshot_read(|snapshot| {
+            expect!(snapshot.open("committed")?.exists())
+                .to(be_ok())
+                .to(be_true());
+
+            expect!(snapshot.open("uncommitted")?.exists())
+                .to(be_ok())
+                .to(be_false());
+
+            sqlarfs::Result::Ok(())
+        })?;
+
+        expect!(archive.open("committed")?.exists())
+            .to(be_ok())
+            .to(be_false());
+
+        expect!(archive.open("uncommitted")?.exists())
+            .to(be_ok())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn snapshot_read_does_not_persist_writes() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let mut conn = Connection::create_new(temp_dir.path().join("test.sqlar"))?;
+
+    conn.exec(|archive| {
+        archive.snapshot_read(|snapshot| {
+            expect!(snapshot.open("file")?.create_file())
+                .to(be_err())
+                .to(match_pattern(pattern!(Error::ReadOnly)));
+
+            sqlarfs::Result::Ok(())
+        })
+    })
+}
+
+#[test]
+fn snapshot_read_of_in_memory_database_errors() -> sqlarfs::Result<()> {
+    let mut conn = Connection::open_in_memory()?;
+
+    conn.exec(|archive| {
+        expect!(archive.snapshot_read(|_| sqlarfs::Result::Ok(())))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        Ok(())
+    })
+}
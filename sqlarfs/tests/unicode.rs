@@ -0,0 +1,111 @@
+//! Tests for Unicode normalization of file paths.
+
+mod common;
+
+use std::path::PathBuf;
+
+use sqlarfs::PathNormalization;
+use xpct::{be_false, be_true, equal, expect};
+
+use common::connection;
+
+const NFC_NAME: &str = "caf\u{e9}";
+const NFD_NAME: &str = "cafe\u{301}";
+
+//
+// `Archive::set_path_normalization`
+//
+
+#[test]
+fn paths_are_preserved_by_default() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        expect!(archive.path_normalization()).to(equal(PathNormalization::Preserve));
+
+        archive.open(NFD_NAME)?.create_file()?;
+
+        expect!(archive.open(NFD_NAME)?.exists()?).to(be_true());
+        expect!(archive.open(NFC_NAME)?.exists()?).to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn paths_are_normalized_to_nfc_when_creating_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.set_path_normalization(PathNormalization::Nfc);
+        archive.open(NFD_NAME)?.create_file()?;
+
+        archive.set_path_normalization(PathNormalization::Preserve);
+
+        expect!(archive.open(NFC_NAME)?.exists()?).to(be_true());
+        expect!(archive.open(NFD_NAME)?.exists()?).to(be_false());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn paths_are_normalized_to_nfc_when_renaming_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("file")?.create_file()?;
+
+        archive.set_path_normalization(PathNormalization::Nfc);
+        archive.rename("file", NFD_NAME)?;
+
+        archive.set_path_normalization(PathNormalization::Preserve);
+
+        expect!(archive.open(NFC_NAME)?.exists()?).to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn paths_are_normalized_to_nfc_when_archiving() -> sqlarfs::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    std::fs::write(temp_dir.path().join(NFD_NAME), b"")?;
+
+    connection()?.exec(|archive| {
+        archive.set_path_normalization(PathNormalization::Nfc);
+        archive.archive(temp_dir.path(), "dir")?;
+
+        archive.set_path_normalization(PathNormalization::Preserve);
+
+        expect!(archive.open(format!("dir/{NFC_NAME}"))?.exists()?).to(be_true());
+
+        Ok(())
+    })
+}
+
+//
+// `Archive::normalization_conflicts`
+//
+
+#[test]
+fn no_normalization_conflicts_when_paths_are_distinct() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open(NFC_NAME)?.create_file()?;
+        archive.open("other")?.create_file()?;
+
+        expect!(archive.normalization_conflicts()?).to(equal(Vec::<Vec<PathBuf>>::new()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn normalization_conflicts_are_detected() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open(NFC_NAME)?.create_file()?;
+        archive.open(NFD_NAME)?.create_file()?;
+        archive.open("other")?.create_file()?;
+
+        let mut expected = vec![PathBuf::from(NFC_NAME), PathBuf::from(NFD_NAME)];
+        expected.sort();
+
+        expect!(archive.normalization_conflicts()?).to(equal(vec![expected]));
+
+        Ok(())
+    })
+}
@@ -0,0 +1,169 @@
+//! Tests for creating files that don't have a path yet.
+
+mod common;
+
+use std::io::Read;
+use std::path::PathBuf;
+
+use common::connection;
+use sqlarfs::{Error, FileMode};
+use xpct::{be_err, be_ok, be_true, equal, expect, match_pattern, pattern};
+
+fn list_paths(archive: &sqlarfs::Archive) -> sqlarfs::Result<Vec<PathBuf>> {
+    archive
+        .list()?
+        .map(|entry| entry.map(|entry| entry.path().to_owned()))
+        .collect()
+}
+
+//
+// `Archive::create_unnamed`
+//
+
+#[test]
+fn unnamed_file_is_deleted_when_dropped() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.create_unnamed()?;
+        file.write_str("contents")?;
+        drop(file);
+
+        expect!(list_paths(archive))
+            .to(be_ok())
+            .to(equal(Vec::<PathBuf>::new()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn unnamed_file_is_a_regular_file() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let file = archive.create_unnamed()?;
+
+        expect!(file.metadata())
+            .to(be_ok())
+            .map(|metadata| metadata.is_file())
+            .to(be_true());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn creating_multiple_unnamed_files() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut file = archive.create_unnamed()?;
+        file.write_str("first")?;
+        file.persist("first")?;
+
+        let mut file = archive.create_unnamed()?;
+        file.write_str("second")?;
+        file.persist("second")?;
+
+        expect!(list_paths(archive))
+            .to(be_ok())
+            .to(equal(vec![PathBuf::from("first"), PathBuf::from("second")]));
+
+        Ok(())
+    })
+}
+
+//
+// `UnnamedFile::persist`
+//
+
+#[test]
+fn persisted_file_has_contents_and_metadata() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        archive.open("dir")?.create_dir()?;
+
+        let mut file = archive.create_unnamed()?;
+        file.write_str("contents")?;
+        file.set_mode(Some(FileMode::OWNER_R))?;
+        file.persist("dir/file")?;
+
+        expect!(list_paths(archive))
+            .to(be_ok())
+            .to(equal(vec![PathBuf::from("dir"), PathBuf::from("dir/file")]));
+
+        let mut file = archive.open("dir/file")?;
+
+        expect!(file.metadata())
+            .to(be_ok())
+            .map(|metadata| metadata.mode())
+            .to(equal(Some(FileMode::OWNER_R)));
+
+        let mut contents = String::new();
+        file.reader()?.read_to_string(&mut contents)?;
+
+        expect!(contents).to(equal("contents"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn persist_errors_when_file_already_exists() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let mut existing = archive.open("file")?;
+        existing.create_file()?;
+        existing.write_str("original")?;
+        drop(existing);
+
+        let mut file = archive.create_unnamed()?;
+        file.write_str("new")?;
+
+        expect!(file.persist("file"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::FileAlreadyExists { .. })));
+
+        // The unnamed file is deleted and the existing file is untouched.
+        expect!(list_paths(archive))
+            .to(be_ok())
+            .to(equal(vec![PathBuf::from("file")]));
+
+        let mut contents = String::new();
+        archive
+            .open("file")?
+            .reader()?
+            .read_to_string(&mut contents)?;
+
+        expect!(contents).to(equal("original"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn persist_errors_when_parent_does_not_exist() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let file = archive.create_unnamed()?;
+
+        expect!(file.persist("nonexistent/file"))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::NoParentDirectory { .. })));
+
+        expect!(list_paths(archive))
+            .to(be_ok())
+            .to(equal(Vec::<PathBuf>::new()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn persist_errors_when_path_is_empty() -> sqlarfs::Result<()> {
+    connection()?.exec(|archive| {
+        let file = archive.create_unnamed()?;
+
+        expect!(file.persist(""))
+            .to(be_err())
+            .to(match_pattern(pattern!(Error::InvalidArgs { .. })));
+
+        expect!(list_paths(archive))
+            .to(be_ok())
+            .to(equal(Vec::<PathBuf>::new()));
+
+        Ok(())
+    })
+}